@@ -247,7 +247,7 @@ impl Default for Config {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EditorSettings {
     pub tab_size: usize,
     pub insert_spaces: bool,
@@ -259,6 +259,12 @@ pub struct EditorSettings {
     pub show_whitespace: bool,
     pub highlight_current_line: bool,
     pub vim_mode: bool,
+    /// Editing mode, overriding `vim_mode` when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editing_mode: Option<EditingModeKind>,
+    /// List files whose names start with `.` in the file finder and explorer
+    #[serde(default)]
+    pub show_hidden_files: bool,
 }
 
 impl Default for EditorSettings {
@@ -274,6 +280,35 @@ impl Default for EditorSettings {
             show_whitespace: false,
             highlight_current_line: true,
             vim_mode: true,
+            editing_mode: None,
+            show_hidden_files: false,
+        }
+    }
+}
+
+impl EditorSettings {
+    /// Get the editing mode to use, falling back to `vim_mode`
+    pub fn editing_mode(&self) -> EditingModeKind {
+        self.editing_mode.unwrap_or(if self.vim_mode { EditingModeKind::Vim } else { EditingModeKind::Emacs })
+    }
+}
+
+/// Input model chosen with `editor.editing_mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EditingModeKind {
+    Vim,
+    Emacs,
+    Helix,
+}
+
+impl EditingModeKind {
+    /// Name of the plugin implementing the mode
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Vim => "vim",
+            Self::Emacs => "emacs",
+            Self::Helix => "helix",
         }
     }
 }
@@ -455,6 +490,20 @@ pub struct KeyBindings {
     pub insert_mode: HashMap<String, String>,
     pub visual_mode: HashMap<String, String>,
     pub command_mode: HashMap<String, String>,
+    /// Key that `<leader>` stands for in bindings, like `Space` or `,`
+    #[serde(default = "default_leader")]
+    pub leader: String,
+    /// Milliseconds a key sequence waits for the next key
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_leader() -> String {
+    "\\".to_string()
+}
+
+fn default_timeout_ms() -> u64 {
+    1000
 }
 
 impl Default for KeyBindings {
@@ -473,27 +522,81 @@ impl Default for KeyBindings {
         normal_mode.insert("gg".to_string(), "move_file_start".to_string());
         normal_mode.insert("G".to_string(), "move_file_end".to_string());
         
+        // Searches
+        normal_mode.insert("n".to_string(), "search_next".to_string());
+        normal_mode.insert("N".to_string(), "search_previous".to_string());
+        normal_mode.insert("*".to_string(), "search_word_forward".to_string());
+        normal_mode.insert("#".to_string(), "search_word_backward".to_string());
+        
+        // Jumps and changes
+        normal_mode.insert("Ctrl+o".to_string(), "jump_older".to_string());
+        normal_mode.insert("Tab".to_string(), "jump_newer".to_string());
+        normal_mode.insert("g;".to_string(), "older_change".to_string());
+        normal_mode.insert("g,".to_string(), "newer_change".to_string());
+        
         // Editing
         normal_mode.insert("i".to_string(), "enter_insert_mode".to_string());
         normal_mode.insert("a".to_string(), "append".to_string());
         normal_mode.insert("o".to_string(), "open_line_below".to_string());
         normal_mode.insert("O".to_string(), "open_line_above".to_string());
         normal_mode.insert("x".to_string(), "delete_char".to_string());
-        normal_mode.insert("dd".to_string(), "delete_line".to_string());
-        normal_mode.insert("yy".to_string(), "yank_line".to_string());
+        normal_mode.insert("d".to_string(), "operator_delete".to_string());
+        normal_mode.insert("c".to_string(), "operator_change".to_string());
+        normal_mode.insert("y".to_string(), "operator_yank".to_string());
+        normal_mode.insert("gU".to_string(), "operator_uppercase".to_string());
+        normal_mode.insert("gu".to_string(), "operator_lowercase".to_string());
+        normal_mode.insert("g~".to_string(), "operator_toggle_case".to_string());
+        normal_mode.insert(">".to_string(), "operator_indent".to_string());
+        normal_mode.insert("<".to_string(), "operator_dedent".to_string());
         normal_mode.insert("p".to_string(), "paste".to_string());
+        normal_mode.insert("P".to_string(), "paste_before".to_string());
         normal_mode.insert("u".to_string(), "undo".to_string());
         normal_mode.insert("Ctrl+r".to_string(), "redo".to_string());
         
         // Visual mode
         normal_mode.insert("v".to_string(), "enter_visual_mode".to_string());
         normal_mode.insert("V".to_string(), "enter_visual_line_mode".to_string());
+        normal_mode.insert("Ctrl+v".to_string(), "enter_visual_block_mode".to_string());
+        
+        // Folds and scrolling
+        normal_mode.insert("za".to_string(), "fold_toggle".to_string());
+        normal_mode.insert("zc".to_string(), "fold_close".to_string());
+        normal_mode.insert("zo".to_string(), "fold_open".to_string());
+        normal_mode.insert("zR".to_string(), "fold_open_all".to_string());
+        normal_mode.insert("zM".to_string(), "fold_close_all".to_string());
+        normal_mode.insert("zt".to_string(), "scroll_cursor_top".to_string());
+        normal_mode.insert("zz".to_string(), "scroll_cursor_center".to_string());
+        normal_mode.insert("zb".to_string(), "scroll_cursor_bottom".to_string());
+        
+        // Syntax nodes
+        normal_mode.insert("Alt+o".to_string(), "expand_selection".to_string());
+        normal_mode.insert("]f".to_string(), "next_function".to_string());
+        normal_mode.insert("[f".to_string(), "previous_function".to_string());
+        normal_mode.insert("]c".to_string(), "next_class".to_string());
+        normal_mode.insert("[c".to_string(), "previous_class".to_string());
+        
+        // Diagnostics
+        normal_mode.insert("]d".to_string(), "next_diagnostic".to_string());
+        normal_mode.insert("[d".to_string(), "previous_diagnostic".to_string());
+        normal_mode.insert("F8".to_string(), "toggle_diagnostics_list".to_string());
+        
+        // Language servers
+        normal_mode.insert("<leader>k".to_string(), "lsp_hover".to_string());
+        normal_mode.insert("<leader>d".to_string(), "lsp_definition".to_string());
+        normal_mode.insert("<leader>r".to_string(), "lsp_references".to_string());
+        normal_mode.insert("<leader>f".to_string(), "lsp_format".to_string());
+        normal_mode.insert("<leader>a".to_string(), "lsp_code_actions".to_string());
+        normal_mode.insert("F2".to_string(), "lsp_rename".to_string());
         
         // File operations
         normal_mode.insert(":w".to_string(), "save_file".to_string());
         normal_mode.insert(":q".to_string(), "quit".to_string());
         normal_mode.insert(":wq".to_string(), "save_and_quit".to_string());
         normal_mode.insert(":q!".to_string(), "quit_force".to_string());
+        normal_mode.insert("Ctrl+p".to_string(), "find_file".to_string());
+        normal_mode.insert("<leader>p".to_string(), "command_palette".to_string());
+        normal_mode.insert("<leader>e".to_string(), "toggle_explorer".to_string());
+        normal_mode.insert("<leader>E".to_string(), "reveal_in_explorer".to_string());
         
         let mut insert_mode = HashMap::new();
         insert_mode.insert("Escape".to_string(), "enter_normal_mode".to_string());
@@ -504,6 +607,11 @@ impl Default for KeyBindings {
         visual_mode.insert("d".to_string(), "delete_selection".to_string());
         visual_mode.insert("y".to_string(), "yank_selection".to_string());
         visual_mode.insert("c".to_string(), "change_selection".to_string());
+        visual_mode.insert("Ctrl+v".to_string(), "enter_visual_block_mode".to_string());
+        visual_mode.insert("Alt+o".to_string(), "expand_selection".to_string());
+        visual_mode.insert("Alt+i".to_string(), "shrink_selection".to_string());
+        visual_mode.insert("Alt+n".to_string(), "select_next_sibling".to_string());
+        visual_mode.insert("Alt+p".to_string(), "select_previous_sibling".to_string());
         
         let mut command_mode = HashMap::new();
         command_mode.insert("Enter".to_string(), "execute_command".to_string());
@@ -514,6 +622,8 @@ impl Default for KeyBindings {
             insert_mode,
             visual_mode,
             command_mode,
+            leader: default_leader(),
+            timeout_ms: default_timeout_ms(),
         }
     }
 }
@@ -528,10 +638,10 @@ impl Default for PluginSettings {
     fn default() -> Self {
         Self {
             enabled_plugins: vec![
-                "vim".to_string(),
                 "tui".to_string(),
-                "syntax".to_string(),
-                "file_io".to_string(),
+                "vim".to_string(),
+                "emacs".to_string(),
+                "helix".to_string(),
             ],
             plugin_configs: HashMap::new(),
         }
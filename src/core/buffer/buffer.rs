@@ -1,33 +1,73 @@
 use crate::{Result, RuskError};
 use crate::plugins::implementations::language::syntax::SupportedLanguage;
+use crate::core::cursor::{Position, TextChange, TextRange};
+use super::diagnostics::DiagnosticSet;
+use super::folds::FoldState;
+use super::history::{Edit, History, UndoStep};
+use super::marks::MarkSet;
 use std::fs;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tree_sitter::Tree;
+
+/// Identifies a buffer for as long as the editor runs, for state kept outside it like buffer-local options
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BufferId(u64);
+
+impl BufferId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
 
 /// Text buffer data structure with full editing capabilities
 #[derive(Debug, Clone)]
 pub struct Buffer {
+    id: BufferId,
     lines: Vec<String>,
     file_path: Option<String>,
     language: SupportedLanguage,
     modified: bool,
+    read_only: bool,
+    /// Whether an edit was refused because the buffer is read-only, until the interface asks
+    edit_refused: bool,
     cursor_line: usize,
     cursor_col: usize,
     selection_start: Option<(usize, usize)>,
     selection_end: Option<(usize, usize)>,
+    folds: FoldState,
+    diagnostics: DiagnosticSet,
+    marks: MarkSet,
+    version: u64,
+    syntax_tree: Option<(u64, Tree)>,
+    /// Edits made since they were last taken for the event bus
+    pending_changes: Vec<TextChange>,
+    history: History,
 }
 
 impl Buffer {
     /// Create a new empty buffer
     pub fn new() -> Self {
         Self {
+            id: BufferId::next(),
             lines: vec![String::new()],
             file_path: None,
             language: SupportedLanguage::Text,
             modified: false,
+            read_only: false,
+            edit_refused: false,
             cursor_line: 0,
             cursor_col: 0,
             selection_start: None,
             selection_end: None,
+            folds: FoldState::new(),
+            diagnostics: DiagnosticSet::new(),
+            marks: MarkSet::new(),
+            version: 0,
+            syntax_tree: None,
+            pending_changes: Vec::new(),
+            history: History::new(),
         }
     }
     
@@ -46,6 +86,8 @@ impl Buffer {
             let content = fs::read_to_string(&path)
                 .map_err(|e| RuskError::io_string(format!("Failed to read file {}: {}", path_str, e)))?;
             
+            let read_only = fs::metadata(&path).is_ok_and(|metadata| metadata.permissions().readonly());
+            
             let lines: Vec<String> = if content.is_empty() {
                 vec![String::new()]
             } else {
@@ -53,26 +95,46 @@ impl Buffer {
             };
             
             Ok(Self {
+                id: BufferId::next(),
                 lines,
                 file_path: Some(path_str),
                 language,
                 modified: false,
+                read_only,
+                edit_refused: false,
                 cursor_line: 0,
                 cursor_col: 0,
                 selection_start: None,
                 selection_end: None,
+                folds: FoldState::new(),
+                diagnostics: DiagnosticSet::new(),
+                marks: MarkSet::new(),
+                version: 0,
+                syntax_tree: None,
+                pending_changes: Vec::new(),
+                history: History::new(),
             })
         } else {
             // Create new file buffer
             Ok(Self {
+                id: BufferId::next(),
                 lines: vec![String::new()],
                 file_path: Some(path_str),
                 language,
                 modified: true,
+                read_only: false,
+                edit_refused: false,
                 cursor_line: 0,
                 cursor_col: 0,
                 selection_start: None,
                 selection_end: None,
+                folds: FoldState::new(),
+                diagnostics: DiagnosticSet::new(),
+                marks: MarkSet::new(),
+                version: 0,
+                syntax_tree: None,
+                pending_changes: Vec::new(),
+                history: History::new(),
             })
         }
     }
@@ -113,16 +175,160 @@ impl Buffer {
         self.modified
     }
     
+    /// Check if the buffer should not be edited
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    
+    /// Mark the buffer as read-only or editable
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+    
+    /// Check whether an edit was refused because the buffer is read-only since this was last called
+    pub fn take_refused_edit(&mut self) -> bool {
+        std::mem::take(&mut self.edit_refused)
+    }
+    
+    /// Refuse to change the text of a read-only buffer, remembering that an edit was tried
+    fn refuse_edit(&mut self) -> bool {
+        self.edit_refused |= self.read_only;
+        self.read_only
+    }
+    
     /// Get file path
     pub fn file_path(&self) -> Option<&str> {
         self.file_path.as_deref()
     }
     
+    /// Point the buffer at another file, as after the file was renamed or moved
+    ///
+    /// The language follows the new extension. The buffer's text and
+    /// modified state are unchanged.
+    pub fn set_file_path<P: AsRef<Path>>(&mut self, path: P) {
+        let language = path.as_ref().extension()
+            .map(|extension| SupportedLanguage::from_extension(&extension.to_string_lossy()))
+            .unwrap_or(SupportedLanguage::Text);
+        if language != self.language {
+            self.language = language;
+            self.syntax_tree = None;
+        }
+        self.file_path = Some(path.as_ref().to_string_lossy().to_string());
+    }
+    
+    /// Get the buffer's identifier
+    pub fn id(&self) -> BufferId {
+        self.id
+    }
+    
     /// Get buffer language
     pub fn language(&self) -> &SupportedLanguage {
         &self.language
     }
     
+    /// Highlight the buffer as another language, as `:set filetype` does
+    pub fn set_language(&mut self, language: SupportedLanguage) {
+        if language != self.language {
+            self.language = language;
+            self.syntax_tree = None;
+        }
+    }
+    
+    /// Get the edit version, incremented on every text change
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+    
+    /// Get the syntax tree if it is up to date with the buffer text
+    pub fn syntax_tree(&self) -> Option<&Tree> {
+        match &self.syntax_tree {
+            Some((version, tree)) if *version == self.version => Some(tree),
+            _ => None,
+        }
+    }
+    
+    /// Store the syntax tree parsed from the current buffer text
+    pub fn set_syntax_tree(&mut self, tree: Tree) {
+        self.syntax_tree = Some((self.version, tree));
+    }
+    
+    /// Get buffer text with lines joined by newlines
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+    
+    /// Record a text change
+    fn mark_changed(&mut self) {
+        self.modified = true;
+        self.version += 1;
+    }
+    
+    /// Record an edit replacing `start..old_end` with text ending at `new_end`
+    fn record_change(&mut self, start: Position, old_end: Position, new_end: Position, removed: &str) {
+        let inserted = self.text_in_range(&TextRange::new(start, new_end));
+        self.history.record(Edit { start, removed: removed.to_string(), inserted });
+        let change = TextChange::new(start, old_end, new_end);
+        self.diagnostics.apply_change(&change);
+        self.marks.apply_change(&change);
+        self.folds.apply_change(&change);
+        self.pending_changes.push(change);
+        self.mark_changed();
+    }
+    
+    /// Take the edits made since this was last called, oldest first
+    pub fn take_changes(&mut self) -> Vec<TextChange> {
+        std::mem::take(&mut self.pending_changes)
+    }
+    
+    /// End the current undo step, so the edits after it are undone separately
+    pub fn checkpoint(&mut self) {
+        let cursor = Position::new(self.cursor_line, self.cursor_col);
+        self.history.checkpoint(cursor);
+    }
+    
+    /// Undo the newest undo step, returning whether there was one
+    pub fn undo(&mut self) -> bool {
+        if self.refuse_edit() {
+            return false;
+        }
+        let Some(step) = self.history.take_undo() else {
+            return false;
+        };
+        let redo = self.replay(step);
+        self.history.push_redo(redo);
+        true
+    }
+    
+    /// Redo the newest undone step, returning whether there was one
+    pub fn redo(&mut self) -> bool {
+        if self.refuse_edit() {
+            return false;
+        }
+        let Some(step) = self.history.take_redo() else {
+            return false;
+        };
+        let undo = self.replay(step);
+        self.history.push_undo(undo);
+        true
+    }
+    
+    /// Take back the edits of a step, newest first, and return the step that puts them back
+    fn replay(&mut self, step: UndoStep) -> UndoStep {
+        let cursor = Position::new(self.cursor_line, self.cursor_col);
+        let mut inverse = Vec::with_capacity(step.edits.len());
+        
+        // The edits still go through `record_change`, so diagnostics and marks follow
+        self.history.set_replaying(true);
+        for edit in step.edits.iter().rev() {
+            self.replace_range(&TextRange::new(edit.start, edit.inserted_end()), &edit.removed);
+            inverse.push(edit.inverse());
+        }
+        self.history.set_replaying(false);
+        
+        self.set_cursor_position(step.cursor.line, step.cursor.column);
+        UndoStep { edits: inverse, cursor }
+    }
+    
     /// Get cursor position
     pub fn cursor_position(&self) -> (usize, usize) {
         (self.cursor_line, self.cursor_col)
@@ -134,6 +340,18 @@ impl Buffer {
         self.cursor_col = col.min(self.lines[self.cursor_line].len());
     }
     
+    /// Move a byte column back to the start of the character it falls inside
+    pub fn char_boundary(&self, line: usize, column: usize) -> usize {
+        let Some(text) = self.lines.get(line) else {
+            return 0;
+        };
+        let mut column = column.min(text.len());
+        while !text.is_char_boundary(column) {
+            column -= 1;
+        }
+        column
+    }
+    
     /// Get line at index
     pub fn get_line(&self, index: usize) -> Option<&str> {
         self.lines.get(index).map(|s| s.as_str())
@@ -146,62 +364,71 @@ impl Buffer {
     
     /// Insert character at cursor position
     pub fn insert_char(&mut self, ch: char) {
-        if self.cursor_line >= self.lines.len() {
+        if self.refuse_edit() || self.cursor_line >= self.lines.len() {
             return;
         }
         
+        let start = Position::new(self.cursor_line, self.cursor_col);
         self.lines[self.cursor_line].insert(self.cursor_col, ch);
         self.cursor_col += 1;
-        self.modified = true;
+        self.record_change(start, start, Position::new(start.line, start.column + ch.len_utf8()), "");
     }
     
     /// Insert string at cursor position
     pub fn insert_str(&mut self, s: &str) {
-        if self.cursor_line >= self.lines.len() {
+        if self.refuse_edit() || self.cursor_line >= self.lines.len() {
             return;
         }
         
+        let start = Position::new(self.cursor_line, self.cursor_col);
         self.lines[self.cursor_line].insert_str(self.cursor_col, s);
         self.cursor_col += s.len();
-        self.modified = true;
+        self.record_change(start, start, Position::new(start.line, self.cursor_col), "");
     }
     
     /// Delete character at cursor position
     pub fn delete_char(&mut self) {
-        if self.cursor_line >= self.lines.len() {
+        if self.refuse_edit() || self.cursor_line >= self.lines.len() {
             return;
         }
         
+        let start = Position::new(self.cursor_line, self.cursor_col);
         if self.cursor_col < self.lines[self.cursor_line].len() {
-            self.lines[self.cursor_line].remove(self.cursor_col);
-            self.modified = true;
+            let removed = self.lines[self.cursor_line].remove(self.cursor_col);
+            self.record_change(start, Position::new(start.line, start.column + removed.len_utf8()), start, &removed.to_string());
         } else if self.cursor_line + 1 < self.lines.len() {
             // Join with next line
             let next_line = self.lines.remove(self.cursor_line + 1);
             self.lines[self.cursor_line].push_str(&next_line);
-            self.modified = true;
+            self.record_change(start, Position::new(start.line + 1, 0), start, "\n");
         }
     }
     
     /// Backspace at cursor position
     pub fn backspace(&mut self) {
+        if self.refuse_edit() {
+            return;
+        }
+        let old_end = Position::new(self.cursor_line, self.cursor_col);
         if self.cursor_col > 0 {
             self.cursor_col -= 1;
-            self.lines[self.cursor_line].remove(self.cursor_col);
-            self.modified = true;
+            let removed = self.lines[self.cursor_line].remove(self.cursor_col);
+            let start = Position::new(self.cursor_line, self.cursor_col);
+            self.record_change(start, old_end, start, &removed.to_string());
         } else if self.cursor_line > 0 {
             // Join with previous line
             let current_line = self.lines.remove(self.cursor_line);
             self.cursor_line -= 1;
             self.cursor_col = self.lines[self.cursor_line].len();
             self.lines[self.cursor_line].push_str(&current_line);
-            self.modified = true;
+            let start = Position::new(self.cursor_line, self.cursor_col);
+            self.record_change(start, old_end, start, "\n");
         }
     }
     
     /// Insert new line at cursor position
     pub fn insert_newline(&mut self) {
-        if self.cursor_line >= self.lines.len() {
+        if self.refuse_edit() || self.cursor_line >= self.lines.len() {
             return;
         }
         
@@ -211,13 +438,28 @@ impl Buffer {
         self.lines[self.cursor_line] = before.to_string();
         self.lines.insert(self.cursor_line + 1, after.to_string());
         
+        let start = Position::new(self.cursor_line, self.cursor_col);
         self.cursor_line += 1;
         self.cursor_col = 0;
-        self.modified = true;
+        self.record_change(start, start, Position::new(self.cursor_line, 0), "");
     }
     
     /// Delete entire line
     pub fn delete_line(&mut self) {
+        if self.refuse_edit() {
+            return;
+        }
+        let line = self.cursor_line;
+        let (start, old_end) = if self.lines.len() == 1 {
+            (Position::new(0, 0), Position::new(0, self.lines[0].len()))
+        } else if line + 1 < self.lines.len() {
+            (Position::new(line, 0), Position::new(line + 1, 0))
+        } else {
+            // The last line takes the preceding newline with it
+            (Position::new(line - 1, self.lines[line - 1].len()), Position::new(line, self.lines[line].len()))
+        };
+        let removed = self.text_in_range(&TextRange::new(start, old_end));
+        
         if self.lines.len() > 1 {
             self.lines.remove(self.cursor_line);
             if self.cursor_line >= self.lines.len() {
@@ -227,7 +469,7 @@ impl Buffer {
             self.lines[0].clear();
         }
         self.cursor_col = 0;
-        self.modified = true;
+        self.record_change(start, old_end, start, &removed);
     }
     
     /// Get current line content
@@ -257,16 +499,16 @@ impl Buffer {
     
     /// Move cursor up
     pub fn move_cursor_up(&mut self) {
-        if self.cursor_line > 0 {
-            self.cursor_line -= 1;
+        if let Some(line) = self.folds.previous_visible_line(self.cursor_line) {
+            self.cursor_line = line;
             self.cursor_col = self.cursor_col.min(self.lines[self.cursor_line].len());
         }
     }
     
     /// Move cursor down
     pub fn move_cursor_down(&mut self) {
-        if self.cursor_line + 1 < self.lines.len() {
-            self.cursor_line += 1;
+        if let Some(line) = self.folds.next_visible_line(self.cursor_line, self.lines.len()) {
+            self.cursor_line = line;
             self.cursor_col = self.cursor_col.min(self.lines[self.cursor_line].len());
         }
     }
@@ -312,6 +554,139 @@ impl Buffer {
         self.selection_end = None;
     }
     
+    /// Get fold state
+    pub fn folds(&self) -> &FoldState {
+        &self.folds
+    }
+    
+    /// Get fold state mutably
+    pub fn folds_mut(&mut self) -> &mut FoldState {
+        &mut self.folds
+    }
+    
+    /// Get diagnostics attached to the buffer
+    pub fn diagnostics(&self) -> &DiagnosticSet {
+        &self.diagnostics
+    }
+    
+    /// Get diagnostics attached to the buffer mutably
+    pub fn diagnostics_mut(&mut self) -> &mut DiagnosticSet {
+        &mut self.diagnostics
+    }
+    
+    /// Get the marks and change list
+    pub fn marks(&self) -> &MarkSet {
+        &self.marks
+    }
+    
+    /// Get the marks and change list mutably
+    pub fn marks_mut(&mut self) -> &mut MarkSet {
+        &mut self.marks
+    }
+    
+    /// Move the cursor out of a closed fold onto the fold's summary line
+    pub fn reveal_cursor_line(&mut self) {
+        let visible_line = self.folds.visible_start(self.cursor_line);
+        if visible_line != self.cursor_line {
+            self.cursor_line = visible_line;
+            self.cursor_col = self.cursor_col.min(self.lines[self.cursor_line].len());
+        }
+    }
+    
+    /// Select a range of text, leaving the cursor at its end
+    pub fn select_range(&mut self, range: &TextRange) {
+        self.selection_start = Some((range.start.line, range.start.column));
+        self.selection_end = Some((range.end.line, range.end.column));
+        self.set_cursor_position(range.end.line, range.end.column);
+    }
+    
+    /// Get the current selection as a range
+    pub fn selection_range(&self) -> Option<TextRange> {
+        let (start_line, start_col) = self.selection_start?;
+        let (end_line, end_col) = self.selection_end?;
+        Some(TextRange::new(Position::new(start_line, start_col), Position::new(end_line, end_col)))
+    }
+    
+    /// Get the text within a range
+    pub fn text_in_range(&self, range: &TextRange) -> String {
+        let (start, end) = (self.clamp_position(range.start), self.clamp_position(range.end));
+        
+        if start.line == end.line {
+            return self.lines[start.line][start.column..end.column].to_string();
+        }
+        
+        let mut result = self.lines[start.line][start.column..].to_string();
+        for line in &self.lines[start.line + 1..end.line] {
+            result.push('\n');
+            result.push_str(line);
+        }
+        result.push('\n');
+        result.push_str(&self.lines[end.line][..end.column]);
+        result
+    }
+    
+    /// Delete the text within a range and return it, leaving the cursor at its start
+    pub fn delete_range(&mut self, range: &TextRange) -> String {
+        if self.refuse_edit() {
+            return String::new();
+        }
+        let (start, end) = (self.clamp_position(range.start), self.clamp_position(range.end));
+        let deleted = self.text_in_range(&TextRange::new(start, end));
+        
+        if start != end {
+            let tail = self.lines[end.line][end.column..].to_string();
+            self.lines.drain(start.line + 1..=end.line);
+            self.lines[start.line].truncate(start.column);
+            self.lines[start.line].push_str(&tail);
+            self.record_change(start, end, start, &deleted);
+        }
+        
+        self.cursor_line = start.line;
+        self.cursor_col = start.column;
+        deleted
+    }
+    
+    /// Insert text, which may span several lines, at a position and return the position after it
+    pub fn insert_text(&mut self, pos: Position, text: &str) -> Position {
+        if self.refuse_edit() {
+            return self.clamp_position(pos);
+        }
+        let pos = self.clamp_position(pos);
+        if text.is_empty() {
+            return pos;
+        }
+        
+        let tail = self.lines[pos.line].split_off(pos.column);
+        let mut inserted = text.split('\n');
+        
+        // The first piece joins the current line, later pieces become new lines
+        self.lines[pos.line].push_str(inserted.next().unwrap_or(""));
+        let mut end = Position::new(pos.line, self.lines[pos.line].len());
+        for piece in inserted {
+            end = Position::new(end.line + 1, piece.len());
+            self.lines.insert(end.line, piece.to_string());
+        }
+        
+        self.lines[end.line].push_str(&tail);
+        self.record_change(pos, pos, end, "");
+        end
+    }
+    
+    /// Replace the text within a range
+    pub fn replace_range(&mut self, range: &TextRange, text: &str) -> Position {
+        if self.refuse_edit() {
+            return self.clamp_position(range.start);
+        }
+        self.delete_range(range);
+        self.insert_text(range.start, text)
+    }
+    
+    /// Clamp a position to the buffer contents
+    fn clamp_position(&self, pos: Position) -> Position {
+        let line = pos.line.min(self.lines.len() - 1);
+        Position::new(line, pos.column.min(self.lines[line].len()))
+    }
+    
     /// Get selected text
     pub fn get_selection(&self) -> Option<String> {
         let start = self.selection_start?;
@@ -345,12 +720,18 @@ impl Buffer {
     
     /// Delete selected text
     pub fn delete_selection(&mut self) -> Option<String> {
+        if self.refuse_edit() {
+            return None;
+        }
         let selected = self.get_selection()?;
         let start = self.selection_start?;
         let end = self.selection_end?;
         
         let (start_line, start_col) = start;
         let (end_line, end_col) = end;
+        let change_start = Position::new(start_line, if start_line == end_line { start_col.min(end_col) } else { start_col });
+        let change_end = Position::new(end_line, if start_line == end_line { start_col.max(end_col) } else { end_col });
+        let removed = self.text_in_range(&TextRange::new(change_start, change_end));
         
         if start_line == end_line {
             let line = &mut self.lines[start_line];
@@ -375,7 +756,7 @@ impl Buffer {
         }
         
         self.clear_selection();
-        self.modified = true;
+        self.record_change(change_start, change_end, change_start, &removed);
         Some(selected)
     }
 }
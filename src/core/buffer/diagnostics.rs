@@ -0,0 +1,208 @@
+//! Diagnostics of a buffer
+//!
+//! Language servers and other sources report problems for ranges of a
+//! buffer. Each source's diagnostics replace only its own, and their
+//! ranges move with the text as it is edited, the way marks do. The
+//! gutter shows the most severe one on a line, and `]d` and `[d` go to
+//! the next and previous one.
+
+use crate::core::cursor::{Position, TextChange, TextRange};
+use std::collections::BTreeMap;
+
+/// How serious a diagnostic is, most severe first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl DiagnosticSeverity {
+    /// Gutter sign for the severity
+    pub fn sign(&self) -> &'static str {
+        match self {
+            Self::Error => "E",
+            Self::Warning => "W",
+            Self::Info => "I",
+            Self::Hint => "H",
+        }
+    }
+}
+
+/// A problem reported for a range of a buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: TextRange,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Create a new diagnostic
+    pub fn new(range: TextRange, severity: DiagnosticSeverity, message: impl Into<String>) -> Self {
+        Self {
+            range,
+            severity,
+            message: message.into(),
+        }
+    }
+
+    /// Check if the diagnostic touches a line
+    pub fn covers_line(&self, line: usize) -> bool {
+        self.range.start.line <= line
+            && (line < self.range.end.line || (line == self.range.end.line && (line == self.range.start.line || self.range.end.column > 0)))
+    }
+}
+
+/// Diagnostics of a buffer, grouped by the source that produced them
+///
+/// Each source (a language server, a linter, `:make`) replaces only its own
+/// diagnostics. Ranges follow edits made to the buffer.
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticSet {
+    sources: BTreeMap<String, Vec<Diagnostic>>,
+}
+
+impl DiagnosticSet {
+    /// Create an empty diagnostic set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the diagnostics reported by a source
+    pub fn set(&mut self, source: &str, mut diagnostics: Vec<Diagnostic>) {
+        if diagnostics.is_empty() {
+            self.sources.remove(source);
+        } else {
+            diagnostics.sort_by_key(|d| (d.range.start.line, d.range.start.column, d.severity));
+            self.sources.insert(source.to_string(), diagnostics);
+        }
+    }
+
+    /// Remove the diagnostics reported by a source
+    pub fn clear(&mut self, source: &str) {
+        self.sources.remove(source);
+    }
+
+    /// Check whether there are no diagnostics
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Get the names of the sources with diagnostics
+    pub fn source_names(&self) -> impl Iterator<Item = &str> {
+        self.sources.keys().map(String::as_str)
+    }
+
+    /// Get all diagnostics ordered by position, then severity
+    pub fn all(&self) -> Vec<&Diagnostic> {
+        let mut all: Vec<&Diagnostic> = self.sources.values().flatten().collect();
+        all.sort_by_key(|d| (d.range.start.line, d.range.start.column, d.severity));
+        all
+    }
+
+    /// Count diagnostics of a severity
+    pub fn count(&self, severity: DiagnosticSeverity) -> usize {
+        self.sources.values().flatten().filter(|d| d.severity == severity).count()
+    }
+
+    /// Get the diagnostics touching a line
+    pub fn on_line(&self, line: usize) -> Vec<&Diagnostic> {
+        self.all().into_iter().filter(|d| d.covers_line(line)).collect()
+    }
+
+    /// Get the most severe diagnostic touching a line
+    pub fn most_severe_on_line(&self, line: usize) -> Option<&Diagnostic> {
+        self.sources.values().flatten()
+            .filter(|d| d.covers_line(line))
+            .min_by_key(|d| d.severity)
+    }
+
+    /// Get the diagnostic to describe at a position: one containing it, or else the most severe on its line
+    pub fn at(&self, pos: Position) -> Option<&Diagnostic> {
+        self.sources.values().flatten()
+            .filter(|d| d.range.contains(pos) || (d.range.is_empty() && d.range.start == pos))
+            .min_by_key(|d| d.severity)
+            .or_else(|| self.most_severe_on_line(pos.line))
+    }
+
+    /// Get the first diagnostic starting after a position, wrapping around to the first one
+    pub fn next_after(&self, pos: Position) -> Option<&Diagnostic> {
+        let all = self.all();
+        let key = |p: Position| (p.line, p.column);
+        all.iter().find(|d| key(d.range.start) > key(pos)).or(all.first()).copied()
+    }
+
+    /// Get the last diagnostic starting before a position, wrapping around to the last one
+    pub fn previous_before(&self, pos: Position) -> Option<&Diagnostic> {
+        let all = self.all();
+        let key = |p: Position| (p.line, p.column);
+        all.iter().rev().find(|d| key(d.range.start) < key(pos)).or(all.last()).copied()
+    }
+
+    /// Move ranges with an edit, dropping diagnostics whose text was deleted
+    pub fn apply_change(&mut self, change: &TextChange) {
+        for diagnostics in self.sources.values_mut() {
+            diagnostics.retain_mut(|d| {
+                let mapped = change.map_range(&d.range);
+                let deleted = mapped.is_empty() && !d.range.is_empty();
+                d.range = mapped;
+                !deleted
+            });
+        }
+        self.sources.retain(|_, diagnostics| !diagnostics.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(start: (usize, usize), end: (usize, usize), severity: DiagnosticSeverity) -> Diagnostic {
+        let range = TextRange::new(Position::new(start.0, start.1), Position::new(end.0, end.1));
+        Diagnostic::new(range, severity, "problem")
+    }
+
+    #[test]
+    fn test_diagnostics_follow_edits() {
+        let mut set = DiagnosticSet::new();
+        set.set("lsp", vec![
+            diagnostic((1, 4), (1, 8), DiagnosticSeverity::Error),
+            diagnostic((3, 0), (3, 2), DiagnosticSeverity::Warning),
+        ]);
+
+        // Typing before the first diagnostic on its line shifts it right
+        set.apply_change(&TextChange::new(Position::new(1, 0), Position::new(1, 0), Position::new(1, 2)));
+        assert_eq!(set.all()[0].range.start, Position::new(1, 6));
+
+        // Inserting a line above shifts both down
+        set.apply_change(&TextChange::new(Position::new(0, 0), Position::new(0, 0), Position::new(1, 0)));
+        assert_eq!(set.all()[0].range.start.line, 2);
+        assert_eq!(set.all()[1].range.start.line, 4);
+
+        // Deleting the text of the first removes it
+        set.apply_change(&TextChange::new(Position::new(2, 0), Position::new(3, 0), Position::new(2, 0)));
+        assert_eq!(set.all().len(), 1);
+        assert_eq!(set.all()[0].range, TextRange::new(Position::new(3, 0), Position::new(3, 2)));
+    }
+
+    #[test]
+    fn test_navigation_and_sources() {
+        let mut set = DiagnosticSet::new();
+        set.set("lsp", vec![diagnostic((5, 0), (5, 3), DiagnosticSeverity::Warning)]);
+        set.set("make", vec![
+            diagnostic((2, 1), (2, 3), DiagnosticSeverity::Error),
+            diagnostic((5, 1), (5, 2), DiagnosticSeverity::Error),
+        ]);
+
+        assert_eq!(set.next_after(Position::new(2, 1)).unwrap().range.start, Position::new(5, 0));
+        assert_eq!(set.next_after(Position::new(5, 1)).unwrap().range.start, Position::new(2, 1));
+        assert_eq!(set.previous_before(Position::new(2, 1)).unwrap().range.start, Position::new(5, 1));
+        assert_eq!(set.most_severe_on_line(5).unwrap().severity, DiagnosticSeverity::Error);
+
+        set.set("make", Vec::new());
+        assert_eq!(set.count(DiagnosticSeverity::Error), 0);
+        assert_eq!(set.source_names().collect::<Vec<_>>(), vec!["lsp"]);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::core::cursor::{Position, TextChange};
+use std::collections::BTreeSet;
+
+/// A foldable region of lines
+///
+/// The start line stays visible when the fold is closed and acts as its
+/// summary line; lines `start_line + 1..=end_line` are hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FoldRange {
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl FoldRange {
+    /// Create a new fold range
+    pub fn new(start_line: usize, end_line: usize) -> Self {
+        Self { start_line, end_line }
+    }
+
+    /// Check if a line lies within this range
+    pub fn contains(&self, line: usize) -> bool {
+        line >= self.start_line && line <= self.end_line
+    }
+
+    /// Number of lines hidden when this fold is closed
+    pub fn hidden_line_count(&self) -> usize {
+        self.end_line - self.start_line
+    }
+}
+
+/// Fold regions of a buffer and which of them are closed
+#[derive(Debug, Clone, Default)]
+pub struct FoldState {
+    ranges: Vec<FoldRange>,
+    closed: BTreeSet<usize>,
+}
+
+impl FoldState {
+    /// Create an empty fold state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the fold regions, keeping closed folds whose start line still begins a region
+    pub fn set_ranges(&mut self, mut ranges: Vec<FoldRange>) {
+        ranges.retain(|range| range.end_line > range.start_line);
+        // Outer regions first so the widest region wins for a shared start line
+        ranges.sort_by(|a, b| a.start_line.cmp(&b.start_line).then(b.end_line.cmp(&a.end_line)));
+        ranges.dedup_by_key(|range| range.start_line);
+
+        self.closed.retain(|start| ranges.iter().any(|range| range.start_line == *start));
+        self.ranges = ranges;
+    }
+
+    /// Get all fold regions ordered by start line
+    pub fn ranges(&self) -> &[FoldRange] {
+        &self.ranges
+    }
+
+    /// Check whether any fold regions exist
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Check if the fold starting at a line is closed
+    pub fn is_closed(&self, start_line: usize) -> bool {
+        self.closed.contains(&start_line)
+    }
+
+    /// Get the fold region that starts at a line
+    pub fn range_starting_at(&self, line: usize) -> Option<FoldRange> {
+        self.ranges.iter().copied().find(|range| range.start_line == line)
+    }
+
+    /// Get the innermost fold region containing a line
+    pub fn innermost_range_at(&self, line: usize) -> Option<FoldRange> {
+        self.ranges
+            .iter()
+            .copied()
+            .filter(|range| range.contains(line))
+            .min_by_key(|range| range.end_line - range.start_line)
+    }
+
+    /// Get the outermost closed fold containing a line
+    pub fn closed_range_at(&self, line: usize) -> Option<FoldRange> {
+        self.ranges
+            .iter()
+            .copied()
+            .filter(|range| range.contains(line) && self.is_closed(range.start_line))
+            .max_by_key(|range| range.end_line - range.start_line)
+    }
+
+    /// Toggle the fold under a line (`za`)
+    pub fn toggle(&mut self, line: usize) -> Option<FoldRange> {
+        if let Some(range) = self.closed_range_at(line) {
+            self.closed.remove(&range.start_line);
+            return Some(range);
+        }
+
+        let range = self.innermost_range_at(line)?;
+        self.closed.insert(range.start_line);
+        Some(range)
+    }
+
+    /// Close the innermost open fold under a line (`zc`)
+    pub fn close(&mut self, line: usize) -> Option<FoldRange> {
+        let range = self.ranges
+            .iter()
+            .copied()
+            .filter(|range| range.contains(line) && !self.is_closed(range.start_line))
+            .min_by_key(|range| range.end_line - range.start_line)?;
+        self.closed.insert(range.start_line);
+        Some(range)
+    }
+
+    /// Open the closed fold under a line (`zo`)
+    pub fn open(&mut self, line: usize) -> Option<FoldRange> {
+        let range = self.closed_range_at(line)?;
+        self.closed.remove(&range.start_line);
+        Some(range)
+    }
+
+    /// Open every fold (`zR`)
+    pub fn open_all(&mut self) {
+        self.closed.clear();
+    }
+
+    /// Close every fold (`zM`)
+    pub fn close_all(&mut self) {
+        self.closed = self.ranges.iter().map(|range| range.start_line).collect();
+    }
+
+    /// Check if a line is hidden inside a closed fold
+    pub fn is_hidden(&self, line: usize) -> bool {
+        self.ranges
+            .iter()
+            .any(|range| self.is_closed(range.start_line) && line > range.start_line && line <= range.end_line)
+    }
+
+    /// Get the first visible line of the display row that contains a line
+    pub fn visible_start(&self, line: usize) -> usize {
+        self.ranges
+            .iter()
+            .filter(|range| self.is_closed(range.start_line) && line > range.start_line && line <= range.end_line)
+            .map(|range| range.start_line)
+            .min()
+            .unwrap_or(line)
+    }
+
+    /// Get the last buffer line covered by the display row starting at a line
+    pub fn visible_end(&self, line: usize) -> usize {
+        match self.closed_range_at(line) {
+            Some(range) if range.start_line == line => range.end_line,
+            _ => line,
+        }
+    }
+
+    /// Get the closed fold whose summary is displayed on a line, if any
+    pub fn folded_summary_at(&self, line: usize) -> Option<FoldRange> {
+        if self.is_hidden(line) {
+            return None;
+        }
+        self.closed_range_at(line).filter(|range| range.start_line == line)
+    }
+
+    /// Collect up to `limit` visible lines starting at a line
+    pub fn visible_lines(&self, from: usize, line_count: usize, limit: usize) -> Vec<usize> {
+        let mut lines = Vec::new();
+        let mut line = self.visible_start(from);
+
+        while line < line_count && lines.len() < limit {
+            lines.push(line);
+            line = self.visible_end(line) + 1;
+        }
+
+        lines
+    }
+
+    /// Get the next visible line after a line
+    pub fn next_visible_line(&self, line: usize, line_count: usize) -> Option<usize> {
+        let next = self.visible_end(self.visible_start(line)) + 1;
+        (next < line_count).then_some(next)
+    }
+
+    /// Get the previous visible line before a line
+    pub fn previous_visible_line(&self, line: usize) -> Option<usize> {
+        let start = self.visible_start(line);
+        (start > 0).then(|| self.visible_start(start - 1))
+    }
+
+    /// Count display rows between two visible lines (exclusive of `to`)
+    pub fn visible_distance(&self, from: usize, to: usize) -> usize {
+        let mut count = 0;
+        let mut line = self.visible_start(from);
+        let to = self.visible_start(to);
+
+        while line < to {
+            count += 1;
+            line = self.visible_end(line) + 1;
+        }
+
+        count
+    }
+
+    /// Move the fold regions and closed folds with an edit, so they stay on the same text
+    ///
+    /// A fold whose start line is deleted goes away; one whose end line is
+    /// deleted ends where the edit does.
+    pub fn apply_change(&mut self, change: &TextChange) {
+        self.closed = self.closed.iter().filter_map(|&start| Self::map_line(change, start)).collect();
+        self.ranges = self.ranges.iter()
+            .filter_map(|range| {
+                let start_line = Self::map_line(change, range.start_line)?;
+                let end_line = Self::map_line(change, range.end_line).unwrap_or(change.new_end.line);
+                (end_line > start_line).then_some(FoldRange::new(start_line, end_line))
+            })
+            .collect();
+    }
+
+    /// Map a line through an edit, or nothing when the whole line was deleted
+    fn map_line(change: &TextChange, line: usize) -> Option<usize> {
+        let line_start = Position::new(line, 0);
+        let deleted = (change.start.line, change.start.column) <= (line, 0) && line < change.old_end.line;
+        (!deleted).then(|| change.map_position(line_start).line)
+    }
+
+    /// Get the start lines of closed folds for persistence
+    pub fn closed_starts(&self) -> Vec<usize> {
+        self.closed.iter().copied().collect()
+    }
+
+    /// Restore closed folds from persisted start lines
+    pub fn restore_closed(&mut self, starts: &[usize]) {
+        self.closed = starts.iter().copied().collect();
+        if !self.ranges.is_empty() {
+            let ranges = &self.ranges;
+            self.closed.retain(|start| ranges.iter().any(|range| range.start_line == *start));
+        }
+    }
+}
+
+/// Compute fold regions from indentation, used for plain text and languages without fold queries
+pub fn indent_fold_ranges(lines: &[String], tab_size: usize) -> Vec<FoldRange> {
+    let indents: Vec<Option<usize>> = lines
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                None
+            } else {
+                Some(line
+                    .chars()
+                    .take_while(|ch| ch.is_whitespace())
+                    .map(|ch| if ch == '\t' { tab_size.max(1) } else { 1 })
+                    .sum())
+            }
+        })
+        .collect();
+
+    let mut ranges = Vec::new();
+
+    for (start, indent) in indents.iter().enumerate() {
+        let Some(indent) = *indent else { continue };
+
+        let mut end = start;
+        for (line, other) in indents.iter().enumerate().skip(start + 1) {
+            match other {
+                Some(other) if *other > indent => end = line,
+                Some(_) => break,
+                None => {}
+            }
+        }
+
+        if end > start {
+            ranges.push(FoldRange::new(start, end));
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_indent_folds() {
+        let text = lines("fn a\n    b\n\n    c\n        d\ne");
+        let ranges = indent_fold_ranges(&text, 4);
+        assert_eq!(ranges, vec![FoldRange::new(0, 4), FoldRange::new(3, 4)]);
+    }
+
+    #[test]
+    fn test_close_and_skip_hidden_lines() {
+        let mut folds = FoldState::new();
+        folds.set_ranges(vec![FoldRange::new(0, 4), FoldRange::new(3, 4)]);
+
+        folds.close(3);
+        assert!(folds.is_hidden(4));
+        assert_eq!(folds.visible_lines(0, 6, 10), vec![0, 1, 2, 3, 5]);
+
+        folds.close(3);
+        assert_eq!(folds.visible_lines(0, 6, 10), vec![0, 5]);
+        assert_eq!(folds.visible_start(2), 0);
+
+        folds.toggle(0);
+        assert_eq!(folds.visible_lines(0, 6, 10), vec![0, 1, 2, 3, 5]);
+
+        folds.open_all();
+        assert_eq!(folds.visible_lines(0, 6, 10).len(), 6);
+
+        folds.close_all();
+        assert_eq!(folds.closed_starts(), vec![0, 3]);
+        assert_eq!(folds.visible_distance(0, 5), 1);
+    }
+
+    #[test]
+    fn test_folds_follow_edits() {
+        let mut folds = FoldState::new();
+        folds.set_ranges(vec![FoldRange::new(2, 4), FoldRange::new(6, 8)]);
+        folds.close(2);
+        folds.close(6);
+
+        // A line opened above both folds pushes them down
+        let at = Position::new(0, 0);
+        folds.apply_change(&TextChange::new(at, at, Position::new(1, 0)));
+        assert_eq!(folds.closed_starts(), vec![3, 7]);
+        assert_eq!(folds.ranges(), &[FoldRange::new(3, 5), FoldRange::new(7, 9)]);
+
+        // Deleting the first fold's start line drops it, and pulls the second up
+        folds.apply_change(&TextChange::new(Position::new(3, 0), Position::new(4, 0), Position::new(3, 0)));
+        assert_eq!(folds.closed_starts(), vec![6]);
+        assert_eq!(folds.ranges(), &[FoldRange::new(6, 8)]);
+    }
+}
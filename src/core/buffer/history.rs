@@ -1,10 +1,156 @@
+//! Undo/redo history system
+//!
+//! Every buffer edit is recorded with the text it removed and inserted, so
+//! undo and redo can replay it backwards and forwards through the buffer.
+
+use crate::core::cursor::Position;
+
+/// Most undo steps kept per buffer
+const UNDO_LIMIT: usize = 1000;
+
+/// One edit: the text removed at a position and the text put there instead
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub start: Position,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Edit {
+    /// Get the position after the inserted text
+    pub fn inserted_end(&self) -> Position {
+        match self.inserted.rfind('\n') {
+            Some(newline) => Position::new(
+                self.start.line + self.inserted.matches('\n').count(),
+                self.inserted.len() - newline - 1,
+            ),
+            None => Position::new(self.start.line, self.start.column + self.inserted.len()),
+        }
+    }
+
+    /// Get the edit that takes this one back
+    pub fn inverse(&self) -> Self {
+        Self { start: self.start, removed: self.inserted.clone(), inserted: self.removed.clone() }
+    }
+}
+
+/// Edits undone together, with where the cursor was before them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoStep {
+    pub edits: Vec<Edit>,
+    pub cursor: Position,
+}
+
 /// Undo/redo history system
+#[derive(Debug, Clone, Default)]
 pub struct History {
-    // TODO: Implement history tracking
+    undo: Vec<UndoStep>,
+    redo: Vec<UndoStep>,
+    /// Step the edits since the last checkpoint go into
+    current: Option<UndoStep>,
+    /// Cursor at the last checkpoint, where undoing the next step puts it
+    checkpoint_cursor: Option<Position>,
+    /// Whether edits are being replayed by undo or redo and so are not recorded
+    replaying: bool,
 }
 
 impl History {
     pub fn new() -> Self {
-        Self {}
+        Self::default()
+    }
+
+    /// Record an edit into the current step, forgetting what could be redone
+    pub fn record(&mut self, edit: Edit) {
+        if self.replaying {
+            return;
+        }
+        self.redo.clear();
+        let cursor = self.checkpoint_cursor.take().unwrap_or(edit.start);
+        self.current.get_or_insert_with(|| UndoStep { edits: Vec::new(), cursor }).edits.push(edit);
+    }
+
+    /// End the current step, so later edits are undone separately
+    pub fn checkpoint(&mut self, cursor: Position) {
+        self.close_step();
+        self.checkpoint_cursor = Some(cursor);
+    }
+
+    fn close_step(&mut self) {
+        if let Some(step) = self.current.take() {
+            push_limited(&mut self.undo, step);
+        }
+    }
+
+    /// Take the newest step to undo
+    pub fn take_undo(&mut self) -> Option<UndoStep> {
+        self.close_step();
+        self.undo.pop()
     }
-}
\ No newline at end of file
+
+    /// Take the newest undone step to redo
+    pub fn take_redo(&mut self) -> Option<UndoStep> {
+        self.close_step();
+        self.redo.pop()
+    }
+
+    /// Keep the step that takes back an undo, for redo
+    pub fn push_redo(&mut self, step: UndoStep) {
+        push_limited(&mut self.redo, step);
+    }
+
+    /// Keep the step that takes back a redo, for undo
+    pub fn push_undo(&mut self, step: UndoStep) {
+        push_limited(&mut self.undo, step);
+    }
+
+    /// Stop or resume recording edits while a step is replayed
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replaying = replaying;
+    }
+}
+
+fn push_limited(steps: &mut Vec<UndoStep>, step: UndoStep) {
+    steps.push(step);
+    if steps.len() > UNDO_LIMIT {
+        steps.remove(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::buffer::Buffer;
+    use crate::core::cursor::{Position, TextRange};
+
+    #[test]
+    fn test_undo_and_redo_replay_through_the_buffer() {
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::new(0, 0), "one\ntwo\nthree");
+        buffer.marks_mut().set('a', Position::new(2, 1)).unwrap();
+
+        // Two edits after one checkpoint are undone together
+        buffer.set_cursor_position(1, 0);
+        buffer.checkpoint();
+        buffer.delete_line();
+        buffer.insert_char('x');
+        assert_eq!(buffer.text(), "one\nxthree");
+        assert_eq!(buffer.marks().get('a'), Some(Position::new(1, 2)));
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.text(), "one\ntwo\nthree");
+        assert_eq!(buffer.cursor_position(), (1, 0));
+        assert_eq!(buffer.marks().get('a'), Some(Position::new(2, 1)));
+        assert!(buffer.undo());
+        assert_eq!(buffer.text(), "");
+        assert!(!buffer.undo());
+
+        assert!(buffer.redo());
+        assert!(buffer.redo());
+        assert_eq!(buffer.text(), "one\nxthree");
+        assert!(!buffer.redo());
+
+        // A new edit forgets what could be redone
+        buffer.undo();
+        buffer.replace_range(&TextRange::new(Position::new(0, 0), Position::new(0, 3)), "1");
+        assert!(!buffer.redo());
+    }
+}
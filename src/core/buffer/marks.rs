@@ -0,0 +1,199 @@
+//! Marks and the change list of a buffer
+//!
+//! Marks `a`-`z` name positions in one buffer and move with the text as it
+//! is edited, the way diagnostics do. A mark on a line that is deleted
+//! goes with it. The change list keeps where recent edits happened, one
+//! entry per line edited in a row, for `g;` and `g,`; its entries move
+//! with the text too, to where the deletion was when their line goes.
+//! Marks `<` and `>` hold the start and end of the last visual selection.
+
+use crate::core::cursor::{Position, TextChange};
+use crate::{Result, RuskError};
+use std::collections::BTreeMap;
+
+/// Most changes remembered
+const MAX_CHANGES: usize = 100;
+
+/// Marks and change list of a buffer
+#[derive(Debug, Clone, Default)]
+pub struct MarkSet {
+    marks: BTreeMap<char, Position>,
+    changes: Vec<Position>,
+    /// Entry of the change list last moved to, or none when at the newest end
+    change_index: Option<usize>,
+    /// Lines followed while a command runs on each of them, none once deleted
+    tracked: Vec<Option<Position>>,
+}
+
+impl MarkSet {
+    /// Create an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a mark `a`-`z`, or `<` or `>`
+    pub fn set(&mut self, name: char, pos: Position) -> Result<()> {
+        if !name.is_ascii_lowercase() && name != '<' && name != '>' {
+            return Err(RuskError::Buffer(format!("Invalid buffer mark: '{}'", name)));
+        }
+        self.marks.insert(name, pos);
+        Ok(())
+    }
+
+    /// Get a mark, with `.` being the last change
+    pub fn get(&self, name: char) -> Option<Position> {
+        match name {
+            '.' => self.changes.last().copied(),
+            name => self.marks.get(&name).copied(),
+        }
+    }
+
+    /// Remove a mark, returning whether it was set
+    pub fn remove(&mut self, name: char) -> bool {
+        self.marks.remove(&name).is_some()
+    }
+
+    /// Iterate over the set marks in name order
+    pub fn iter(&self) -> impl Iterator<Item = (char, Position)> + '_ {
+        self.marks.iter().map(|(name, pos)| (*name, *pos))
+    }
+
+    /// Get the change list, oldest first
+    pub fn changes(&self) -> &[Position] {
+        &self.changes
+    }
+
+    /// Move `count` entries back in the change list, as `g;` does
+    pub fn older_change(&mut self, count: usize) -> Result<Position> {
+        let current = self.change_index.unwrap_or(self.changes.len());
+        if current == 0 {
+            return Err(RuskError::Buffer("At start of change list".to_string()));
+        }
+        let index = current.saturating_sub(count.max(1));
+        self.change_index = Some(index);
+        Ok(self.changes[index])
+    }
+
+    /// Move `count` entries forward in the change list, as `g,` does
+    pub fn newer_change(&mut self, count: usize) -> Result<Position> {
+        let current = self.change_index.unwrap_or(self.changes.len());
+        if current + 1 >= self.changes.len() {
+            return Err(RuskError::Buffer("At end of change list".to_string()));
+        }
+        let index = (current + count.max(1)).min(self.changes.len() - 1);
+        self.change_index = Some(index);
+        Ok(self.changes[index])
+    }
+
+    /// Follow lines through edits, as `:g` does while it runs a command on each
+    pub fn track_lines(&mut self, lines: &[usize]) {
+        self.tracked = lines.iter().map(|line| Some(Position::new(*line, 0))).collect();
+    }
+
+    /// Get where the `index`th followed line is now, or nothing once it was deleted
+    pub fn tracked_line(&self, index: usize) -> Option<usize> {
+        self.tracked.get(index).copied().flatten().map(|pos| pos.line)
+    }
+
+    /// Whether lines are being followed
+    pub fn is_tracking(&self) -> bool {
+        !self.tracked.is_empty()
+    }
+
+    /// Stop following lines
+    pub fn untrack_lines(&mut self) {
+        self.tracked.clear();
+    }
+
+    /// Move marks and changes with an edit, then add the edit to the change list
+    pub fn apply_change(&mut self, change: &TextChange) {
+        self.marks.retain(|_, pos| match Self::map(change, *pos) {
+            Some(mapped) => {
+                *pos = mapped;
+                true
+            }
+            None => false,
+        });
+        for pos in &mut self.changes {
+            *pos = change.map_position(*pos);
+        }
+        for pos in &mut self.tracked {
+            *pos = pos.and_then(|pos| Self::map(change, pos));
+        }
+
+        // Edits on the line of the last change, like typing a word, are one entry
+        match self.changes.last_mut() {
+            Some(last) if last.line == change.start.line => *last = change.start,
+            _ => self.changes.push(change.start),
+        }
+        if self.changes.len() > MAX_CHANGES {
+            self.changes.remove(0);
+        }
+        self.change_index = None;
+    }
+
+    /// Map a mark through an edit, or nothing when its whole line was deleted
+    pub fn map(change: &TextChange, pos: Position) -> Option<Position> {
+        let line_start = (pos.line, 0);
+        let deleted = (change.start.line, change.start.column) <= line_start && pos.line < change.old_end.line;
+        (!deleted).then(|| change.map_position(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(start: (usize, usize), old_end: (usize, usize), new_end: (usize, usize)) -> TextChange {
+        TextChange::new(Position::new(start.0, start.1), Position::new(old_end.0, old_end.1), Position::new(new_end.0, new_end.1))
+    }
+
+    #[test]
+    fn test_marks_follow_inserted_and_deleted_lines() {
+        let mut marks = MarkSet::new();
+        marks.set('a', Position::new(2, 3)).unwrap();
+        marks.set('b', Position::new(5, 0)).unwrap();
+        assert!(marks.set('A', Position::new(0, 0)).is_err());
+
+        // Two lines opened above both marks push them down
+        marks.apply_change(&change((1, 0), (1, 0), (3, 0)));
+        assert_eq!(marks.get('a'), Some(Position::new(4, 3)));
+        assert_eq!(marks.get('b'), Some(Position::new(7, 0)));
+
+        // Deleting the line of `a` removes it and pulls `b` up
+        marks.apply_change(&change((4, 0), (5, 0), (4, 0)));
+        assert_eq!(marks.get('a'), None);
+        assert_eq!(marks.get('b'), Some(Position::new(6, 0)));
+
+        // Joining the line of `b` onto the one above moves it to where the join happened
+        marks.apply_change(&change((5, 7), (6, 0), (5, 7)));
+        assert_eq!(marks.get('b'), Some(Position::new(5, 7)));
+
+        // Followed lines move the same way
+        marks.track_lines(&[1, 3]);
+        marks.apply_change(&change((1, 0), (2, 0), (1, 0)));
+        assert_eq!((marks.tracked_line(0), marks.tracked_line(1)), (None, Some(2)));
+    }
+
+    #[test]
+    fn test_change_list() {
+        let mut marks = MarkSet::new();
+        marks.apply_change(&change((1, 0), (1, 0), (1, 1)));
+        marks.apply_change(&change((1, 1), (1, 1), (1, 2)));
+        marks.apply_change(&change((8, 4), (8, 4), (8, 5)));
+        assert_eq!(marks.changes().len(), 2);
+        assert_eq!(marks.get('.'), Some(Position::new(8, 4)));
+
+        assert_eq!(marks.older_change(1).unwrap(), Position::new(8, 4));
+        assert_eq!(marks.older_change(1).unwrap(), Position::new(1, 1));
+        assert!(marks.older_change(1).is_err());
+        assert_eq!(marks.newer_change(1).unwrap(), Position::new(8, 4));
+        assert!(marks.newer_change(1).is_err());
+
+        // Entries move with lines opened above them, and to the deletion when their line goes
+        marks.apply_change(&change((0, 0), (0, 0), (2, 0)));
+        assert_eq!(marks.changes()[..2], [Position::new(3, 1), Position::new(10, 4)]);
+        marks.apply_change(&change((9, 0), (11, 0), (9, 0)));
+        assert_eq!(marks.changes()[..2], [Position::new(3, 1), Position::new(9, 0)]);
+    }
+}
@@ -1,10 +1,16 @@
 //! Buffer management domain
 //!
 //! This module handles text buffers, operations on them,
-//! and the undo/redo history system.
+//! the undo/redo history system, code folding, diagnostics and marks.
 
 pub mod buffer;
 pub mod operations;
 pub mod history;
+pub mod folds;
+pub mod diagnostics;
+pub mod marks;
 
-pub use buffer::Buffer;
\ No newline at end of file
+pub use buffer::{Buffer, BufferId};
+pub use folds::{FoldRange, FoldState};
+pub use diagnostics::{Diagnostic, DiagnosticSet, DiagnosticSeverity};
+pub use marks::MarkSet;
\ No newline at end of file
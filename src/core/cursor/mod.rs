@@ -3,4 +3,10 @@
 //! This module handles cursor positioning and movement operations.
 
 pub mod position;
-pub mod movement;
\ No newline at end of file
+pub mod movement;
+pub mod range;
+pub mod textobject;
+
+pub use position::Position;
+pub use range::{TextChange, TextRange};
+pub use textobject::{ObjectRange, TextObject};
\ No newline at end of file
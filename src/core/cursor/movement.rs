@@ -1,26 +1,399 @@
 use super::position::Position;
 
 /// Cursor movement operations
+///
+/// Motions over text take the buffer's lines and work in byte columns. The
+/// end of a line that has another after it is a position of its own, the
+/// line break, which word and sentence motions step over like a space.
 pub struct Movement;
 
+/// Kind of character, words being runs of one kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Punctuation,
+    Word,
+}
+
+fn class(ch: char, big: bool) -> CharClass {
+    if ch.is_whitespace() {
+        CharClass::Blank
+    } else if big || ch.is_alphanumeric() || ch == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
 impl Movement {
     /// Move cursor up by one line
     pub fn up(pos: Position) -> Position {
         Position::new(pos.line.saturating_sub(1), pos.column)
     }
-    
+
     /// Move cursor down by one line
     pub fn down(pos: Position) -> Position {
         Position::new(pos.line + 1, pos.column)
     }
-    
+
     /// Move cursor left by one column
     pub fn left(pos: Position) -> Position {
         Position::new(pos.line, pos.column.saturating_sub(1))
     }
-    
+
     /// Move cursor right by one column
     pub fn right(pos: Position) -> Position {
         Position::new(pos.line, pos.column + 1)
     }
-}
\ No newline at end of file
+
+    /// Character at a position, `\n` at the end of a line followed by another, `None` at the end of the text
+    pub fn char_at(lines: &[String], pos: Position) -> Option<char> {
+        let line = lines.get(pos.line)?;
+        match line[pos.column.min(line.len())..].chars().next() {
+            Some(ch) => Some(ch),
+            None => (pos.line + 1 < lines.len()).then_some('\n'),
+        }
+    }
+
+    /// Position of the next character, crossing line breaks
+    pub fn next_char(lines: &[String], pos: Position) -> Option<Position> {
+        let ch = Self::char_at(lines, pos)?;
+        if ch == '\n' {
+            Some(Position::new(pos.line + 1, 0))
+        } else {
+            Some(Position::new(pos.line, pos.column + ch.len_utf8()))
+        }
+    }
+
+    /// Position of the previous character, crossing line breaks
+    pub fn prev_char(lines: &[String], pos: Position) -> Option<Position> {
+        if pos.column == 0 {
+            let line = pos.line.checked_sub(1)?;
+            return Some(Position::new(line, lines[line].len()));
+        }
+        let before = lines[pos.line][..pos.column].chars().next_back()?;
+        Some(Position::new(pos.line, pos.column - before.len_utf8()))
+    }
+
+    fn is_empty_line(lines: &[String], pos: Position) -> bool {
+        pos.column == 0 && lines.get(pos.line).is_some_and(|line| line.is_empty())
+    }
+
+    fn is_blank(lines: &[String], pos: Position) -> bool {
+        Self::char_at(lines, pos).is_none_or(char::is_whitespace)
+    }
+
+    fn class_at(lines: &[String], pos: Position, big: bool) -> Option<CharClass> {
+        Self::char_at(lines, pos).map(|ch| class(ch, big))
+    }
+
+    /// Column of the first non-blank character of a line
+    pub fn first_non_blank(lines: &[String], line: usize) -> usize {
+        let text = &lines[line];
+        text.len() - text.trim_start().len()
+    }
+
+    /// Column of the last character of a line, where normal mode leaves the cursor at its end
+    pub fn last_char(lines: &[String], line: usize) -> usize {
+        let text = &lines[line];
+        text.char_indices().next_back().map_or(0, |(column, _)| column)
+    }
+
+    /// Start of the next word, or of the next WORD of non-blank characters when `big`
+    ///
+    /// Empty lines count as words. Past the last word this is the end of the text.
+    pub fn word_forward(lines: &[String], pos: Position, big: bool) -> Position {
+        let mut p = pos;
+        if let Some(start) = Self::class_at(lines, p, big).filter(|class| *class != CharClass::Blank) {
+            while Self::class_at(lines, p, big) == Some(start) {
+                p = Self::next_char(lines, p).unwrap_or(p);
+                if Self::char_at(lines, p).is_none() {
+                    return p;
+                }
+            }
+        }
+        while Self::is_blank(lines, p) {
+            if p != pos && Self::is_empty_line(lines, p) {
+                return p;
+            }
+            match Self::next_char(lines, p) {
+                Some(next) => p = next,
+                None => return p,
+            }
+        }
+        p
+    }
+
+    /// End of the word under the cursor, or of the next one when already at its end
+    pub fn word_end(lines: &[String], pos: Position, big: bool) -> Position {
+        let Some(mut p) = Self::next_char(lines, pos) else {
+            return pos;
+        };
+        while Self::is_blank(lines, p) {
+            match Self::next_char(lines, p) {
+                Some(next) if Self::char_at(lines, next).is_some() => p = next,
+                _ => return pos,
+            }
+        }
+        let start = Self::class_at(lines, p, big);
+        while let Some(next) = Self::next_char(lines, p) {
+            if Self::class_at(lines, next, big) != start {
+                break;
+            }
+            p = next;
+        }
+        p
+    }
+
+    /// Last character of the word the cursor is on
+    pub fn end_of_word(lines: &[String], pos: Position, big: bool) -> Position {
+        let start = Self::class_at(lines, pos, big);
+        let mut p = pos;
+        while let Some(next) = Self::next_char(lines, p)
+            && Self::char_at(lines, next) != Some('\n')
+            && Self::class_at(lines, next, big) == start
+        {
+            p = next;
+        }
+        p
+    }
+
+    /// Start of the word under the cursor, or of the previous one when already at its start
+    pub fn word_backward(lines: &[String], pos: Position, big: bool) -> Position {
+        let Some(mut p) = Self::prev_char(lines, pos) else {
+            return pos;
+        };
+        while Self::is_blank(lines, p) && !Self::is_empty_line(lines, p) {
+            match Self::prev_char(lines, p) {
+                Some(prev) => p = prev,
+                None => return p,
+            }
+        }
+        if Self::is_empty_line(lines, p) {
+            return p;
+        }
+        let start = Self::class_at(lines, p, big);
+        while let Some(prev) = Self::prev_char(lines, p) {
+            if Self::class_at(lines, prev, big) != start {
+                break;
+            }
+            p = prev;
+        }
+        p
+    }
+
+    /// Whether a sentence starts at a position: the first character after
+    /// `.`, `!` or `?` and white space, after an empty line, or an empty line
+    pub fn is_sentence_start(lines: &[String], pos: Position) -> bool {
+        if Self::is_empty_line(lines, pos) {
+            return true;
+        }
+        if Self::is_blank(lines, pos) {
+            return false;
+        }
+        let mut p = pos;
+        let mut crossed_blank = false;
+        loop {
+            let Some(prev) = Self::prev_char(lines, p) else {
+                return true;
+            };
+            if Self::is_empty_line(lines, prev) {
+                return true;
+            }
+            if !Self::is_blank(lines, prev) {
+                p = prev;
+                break;
+            }
+            crossed_blank = true;
+            p = prev;
+        }
+        if !crossed_blank {
+            return false;
+        }
+        // Closing quotes and brackets may follow the punctuation
+        while matches!(Self::char_at(lines, p), Some(')' | ']' | '"' | '\'')) {
+            match Self::prev_char(lines, p) {
+                Some(prev) => p = prev,
+                None => return false,
+            }
+        }
+        matches!(Self::char_at(lines, p), Some('.' | '!' | '?'))
+    }
+
+    /// Start of the next sentence, or the end of the text
+    pub fn sentence_forward(lines: &[String], pos: Position) -> Position {
+        let mut p = pos;
+        while let Some(next) = Self::next_char(lines, p) {
+            p = next;
+            if Self::is_sentence_start(lines, p) {
+                return p;
+            }
+        }
+        p
+    }
+
+    /// Start of the sentence under the cursor, or of the previous one when already at its start
+    pub fn sentence_backward(lines: &[String], pos: Position) -> Position {
+        let mut p = pos;
+        while let Some(prev) = Self::prev_char(lines, p) {
+            p = prev;
+            if Self::is_sentence_start(lines, p) {
+                return p;
+            }
+        }
+        p
+    }
+
+    /// Next empty line after the paragraph, or the end of the text
+    pub fn paragraph_forward(lines: &[String], pos: Position) -> Position {
+        let mut line = pos.line;
+        while line < lines.len() && lines[line].is_empty() {
+            line += 1;
+        }
+        while line < lines.len() && !lines[line].is_empty() {
+            line += 1;
+        }
+        if line < lines.len() {
+            Position::new(line, 0)
+        } else {
+            let last = lines.len() - 1;
+            Position::new(last, lines[last].len())
+        }
+    }
+
+    /// Previous empty line before the paragraph, or the start of the text
+    pub fn paragraph_backward(lines: &[String], pos: Position) -> Position {
+        let mut line = pos.line;
+        while line > 0 && lines[line].is_empty() {
+            line -= 1;
+        }
+        while line > 0 && !lines[line].is_empty() {
+            line -= 1;
+        }
+        Position::new(line, 0)
+    }
+
+    /// Find the `count`th `ch` on the line, as `f` and `F` do, or the character before it for `t` and `T`
+    pub fn find_char(lines: &[String], pos: Position, ch: char, forward: bool, till: bool, count: usize) -> Option<Position> {
+        let line = &lines[pos.line];
+        let found = if forward {
+            // `t` next to its character would not move, so it looks past it
+            let from = line[pos.column..].chars().next().map_or(pos.column, |at| pos.column + at.len_utf8());
+            let skip = if till { line[from..].chars().next().filter(|next| *next == ch).map_or(0, char::len_utf8) } else { 0 };
+            line[from + skip..].match_indices(ch).nth(count.max(1) - 1).map(|(i, _)| from + skip + i)?
+        } else {
+            let mut before = &line[..pos.column];
+            if till && before.ends_with(ch) {
+                before = &before[..before.len() - ch.len_utf8()];
+            }
+            before.rmatch_indices(ch).nth(count.max(1) - 1).map(|(i, _)| i)?
+        };
+        let column = match (till, forward) {
+            (false, _) => found,
+            (true, true) => line[..found].char_indices().next_back().map_or(found, |(i, _)| i),
+            (true, false) => found + ch.len_utf8(),
+        };
+        Some(Position::new(pos.line, column))
+    }
+
+    /// Bracket matching the first bracket at or after the cursor on its line, as `%` does
+    pub fn matching_bracket(lines: &[String], pos: Position) -> Option<Position> {
+        const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+        let line = &lines[pos.line];
+        let (offset, bracket) = line[pos.column..].char_indices()
+            .find(|(_, ch)| PAIRS.iter().any(|(open, close)| ch == open || ch == close))?;
+        let start = Position::new(pos.line, pos.column + offset);
+
+        let (open, close, forward) = PAIRS.iter()
+            .find_map(|&(open, close)| if bracket == open { Some((open, close, true)) } else if bracket == close { Some((open, close, false)) } else { None })?;
+        let mut depth = 0usize;
+        let mut p = start;
+        loop {
+            match Self::char_at(lines, p) {
+                Some(ch) if ch == open => depth = if forward { depth + 1 } else { depth - 1 },
+                Some(ch) if ch == close => depth = if forward { depth - 1 } else { depth + 1 },
+                _ => {}
+            }
+            if depth == 0 {
+                return Some(p);
+            }
+            p = if forward { Self::next_char(lines, p)? } else { Self::prev_char(lines, p)? };
+        }
+    }
+
+    /// Line `H`, `M` or `L` moves to in the visible lines `top..top + height`
+    ///
+    /// `offset` counts lines down from the top for `H` and up from the bottom for `L`.
+    pub fn screen_line(top: usize, height: usize, line_count: usize, place: ScreenPlace, offset: usize) -> usize {
+        let bottom = (top + height.max(1)).min(line_count) - 1;
+        match place {
+            ScreenPlace::Top => (top + offset).min(bottom),
+            ScreenPlace::Middle => top + (bottom - top) / 2,
+            ScreenPlace::Bottom => bottom.saturating_sub(offset).max(top),
+        }
+    }
+}
+
+/// Visible line moved to by `H`, `M` and `L`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenPlace {
+    Top,
+    Middle,
+    Bottom,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_word_motions() {
+        let text = lines("foo.bar baz\n\n  qux");
+        assert_eq!(Movement::word_forward(&text, Position::new(0, 0), false), Position::new(0, 3));
+        assert_eq!(Movement::word_forward(&text, Position::new(0, 0), true), Position::new(0, 8));
+        assert_eq!(Movement::word_forward(&text, Position::new(0, 8), false), Position::new(1, 0));
+        assert_eq!(Movement::word_forward(&text, Position::new(1, 0), false), Position::new(2, 2));
+        assert_eq!(Movement::word_forward(&text, Position::new(2, 2), false), Position::new(2, 5));
+        assert_eq!(Movement::word_end(&text, Position::new(0, 0), false), Position::new(0, 2));
+        assert_eq!(Movement::word_end(&text, Position::new(0, 0), true), Position::new(0, 6));
+        assert_eq!(Movement::word_end(&text, Position::new(0, 10), false), Position::new(2, 4));
+        assert_eq!(Movement::word_backward(&text, Position::new(2, 2), false), Position::new(1, 0));
+        assert_eq!(Movement::word_backward(&text, Position::new(0, 8), false), Position::new(0, 4));
+        assert_eq!(Movement::word_backward(&text, Position::new(0, 8), true), Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_sentence_and_paragraph_motions() {
+        let text = lines("One. Two (three).  Four\nfive.\n\nSix");
+        assert_eq!(Movement::sentence_forward(&text, Position::new(0, 0)), Position::new(0, 5));
+        assert_eq!(Movement::sentence_forward(&text, Position::new(0, 5)), Position::new(0, 19));
+        assert_eq!(Movement::sentence_forward(&text, Position::new(0, 19)), Position::new(2, 0));
+        assert_eq!(Movement::sentence_backward(&text, Position::new(0, 21)), Position::new(0, 19));
+        assert_eq!(Movement::paragraph_forward(&text, Position::new(0, 3)), Position::new(2, 0));
+        assert_eq!(Movement::paragraph_forward(&text, Position::new(2, 0)), Position::new(3, 3));
+        assert_eq!(Movement::paragraph_backward(&text, Position::new(3, 1)), Position::new(2, 0));
+    }
+
+    #[test]
+    fn test_find_bracket_and_screen_motions() {
+        let text = lines("call(a, (b), c) x");
+        let at = |column| Position::new(0, column);
+        assert_eq!(Movement::find_char(&text, at(0), ',', true, false, 2), Some(at(11)));
+        assert_eq!(Movement::find_char(&text, at(0), ',', true, true, 1), Some(at(5)));
+        assert_eq!(Movement::find_char(&text, at(5), ',', true, true, 1), Some(at(10)));
+        assert_eq!(Movement::find_char(&text, at(14), '(', false, false, 1), Some(at(8)));
+        assert_eq!(Movement::find_char(&text, at(14), '(', false, true, 1), Some(at(9)));
+        assert_eq!(Movement::find_char(&text, at(0), 'z', true, false, 1), None);
+        assert_eq!(Movement::matching_bracket(&text, at(0)), Some(at(14)));
+        assert_eq!(Movement::matching_bracket(&text, at(14)), Some(at(4)));
+        assert_eq!(Movement::matching_bracket(&text, at(15)), None);
+
+        assert_eq!(Movement::screen_line(10, 20, 100, ScreenPlace::Top, 2), 12);
+        assert_eq!(Movement::screen_line(10, 20, 100, ScreenPlace::Middle, 0), 19);
+        assert_eq!(Movement::screen_line(10, 20, 25, ScreenPlace::Bottom, 0), 24);
+    }
+}
@@ -0,0 +1,114 @@
+use super::position::Position;
+
+/// A range of text in a buffer, with an exclusive end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextRange {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl TextRange {
+    /// Create a new range, ordering the endpoints
+    pub fn new(start: Position, end: Position) -> Self {
+        if (end.line, end.column) < (start.line, start.column) {
+            Self { start: end, end: start }
+        } else {
+            Self { start, end }
+        }
+    }
+    
+    /// Check if the range is empty
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+    
+    /// Check if a position lies within the range
+    pub fn contains(&self, pos: Position) -> bool {
+        (self.start.line, self.start.column) <= (pos.line, pos.column)
+            && (pos.line, pos.column) < (self.end.line, self.end.column)
+    }
+    
+    /// Check if another range lies entirely within this one
+    pub fn encloses(&self, other: &TextRange) -> bool {
+        (self.start.line, self.start.column) <= (other.start.line, other.start.column)
+            && (other.end.line, other.end.column) <= (self.end.line, self.end.column)
+    }
+}
+
+/// An edit to a buffer: the text between `start` and `old_end` was replaced
+/// by text ending at `new_end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextChange {
+    pub start: Position,
+    pub old_end: Position,
+    pub new_end: Position,
+}
+
+impl TextChange {
+    /// Create a change replacing `start..old_end` with text ending at `new_end`
+    pub fn new(start: Position, old_end: Position, new_end: Position) -> Self {
+        Self { start, old_end, new_end }
+    }
+    
+    /// The single edit that turns one version of a buffer's lines into another
+    ///
+    /// The change spans the text between the longest common prefix and
+    /// suffix, kept on character boundaries; `None` when nothing differs.
+    pub fn between(old: &[String], new: &[String]) -> Option<Self> {
+        let (old_text, new_text) = (old.join("\n"), new.join("\n"));
+        if old_text == new_text {
+            return None;
+        }
+        
+        let mut prefix = old_text.bytes().zip(new_text.bytes()).take_while(|(a, b)| a == b).count();
+        while !old_text.is_char_boundary(prefix) {
+            prefix -= 1;
+        }
+        let max_suffix = old_text.len().min(new_text.len()) - prefix;
+        let mut suffix = old_text.bytes().rev().zip(new_text.bytes().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old_text.is_char_boundary(old_text.len() - suffix) || !new_text.is_char_boundary(new_text.len() - suffix) {
+            suffix -= 1;
+        }
+        
+        Some(Self::new(
+            offset_to_position(&old_text, prefix),
+            offset_to_position(&old_text, old_text.len() - suffix),
+            offset_to_position(&new_text, new_text.len() - suffix),
+        ))
+    }
+    
+    /// Map a position from before the change to after it
+    ///
+    /// Positions before the change stay put, positions after it shift with
+    /// the text, and positions inside replaced text collapse to its start.
+    pub fn map_position(&self, pos: Position) -> Position {
+        let key = |p: Position| (p.line, p.column);
+        
+        if key(pos) < key(self.start) {
+            pos
+        } else if key(pos) >= key(self.old_end) {
+            if pos.line == self.old_end.line {
+                Position::new(self.new_end.line, self.new_end.column + (pos.column - self.old_end.column))
+            } else {
+                Position::new(pos.line - self.old_end.line + self.new_end.line, pos.column)
+            }
+        } else {
+            self.start
+        }
+    }
+    
+    /// Map a range from before the change to after it
+    pub fn map_range(&self, range: &TextRange) -> TextRange {
+        TextRange::new(self.map_position(range.start), self.map_position(range.end))
+    }
+}
+
+/// Position of a byte offset in newline-joined lines
+fn offset_to_position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(before.matches('\n').count(), offset - line_start)
+}
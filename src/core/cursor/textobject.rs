@@ -0,0 +1,295 @@
+//! Text objects
+//!
+//! Text objects select text around the cursor rather than moving it, as
+//! `iw` or `a(` do after an operator. The inner object leaves out the
+//! surrounding white space or delimiters, and `a` takes them too.
+
+use super::movement::Movement;
+use super::position::Position;
+use super::range::TextRange;
+
+/// Kind of text around the cursor an object selects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextObject {
+    Word { big: bool },
+    Sentence,
+    Paragraph,
+    Bracket { open: char, close: char },
+    Quote(char),
+}
+
+impl TextObject {
+    /// Get the object typed after `i` or `a`
+    pub fn from_char(ch: char) -> Option<Self> {
+        Some(match ch {
+            'w' => Self::Word { big: false },
+            'W' => Self::Word { big: true },
+            's' => Self::Sentence,
+            'p' => Self::Paragraph,
+            '(' | ')' | 'b' => Self::Bracket { open: '(', close: ')' },
+            '[' | ']' => Self::Bracket { open: '[', close: ']' },
+            '{' | '}' | 'B' => Self::Bracket { open: '{', close: '}' },
+            '<' | '>' => Self::Bracket { open: '<', close: '>' },
+            '"' | '\'' | '`' => Self::Quote(ch),
+            _ => return None,
+        })
+    }
+}
+
+/// Text selected by an object, covering whole lines when `linewise`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectRange {
+    pub range: TextRange,
+    pub linewise: bool,
+}
+
+impl ObjectRange {
+    fn chars(start: Position, end: Position) -> Self {
+        Self { range: TextRange::new(start, end), linewise: false }
+    }
+}
+
+/// Select the object around a position, `count` words or paragraphs at once
+pub fn select(lines: &[String], pos: Position, object: TextObject, around: bool, count: usize) -> Option<ObjectRange> {
+    match object {
+        TextObject::Word { big } => select_word(lines, pos, big, around, count),
+        TextObject::Sentence => select_sentence(lines, pos, around),
+        TextObject::Paragraph => select_paragraph(lines, pos, around, count),
+        TextObject::Bracket { open, close } => select_bracket(lines, pos, open, close, around),
+        TextObject::Quote(quote) => select_quote(lines, pos, quote, around),
+    }
+}
+
+fn is_word_char(ch: char, big: bool) -> bool {
+    big || ch.is_alphanumeric() || ch == '_'
+}
+
+/// Whether two characters belong to the same word, or are both blank
+fn same_run(a: char, b: char, big: bool) -> bool {
+    match (a.is_whitespace(), b.is_whitespace()) {
+        (true, true) => true,
+        (false, false) => is_word_char(a, big) == is_word_char(b, big),
+        _ => false,
+    }
+}
+
+/// End of the run of characters like the one at `column`
+fn run_end(line: &str, column: usize, big: bool) -> usize {
+    let Some(first) = line[column..].chars().next() else {
+        return column;
+    };
+    line[column..].char_indices()
+        .find(|(_, ch)| !same_run(first, *ch, big))
+        .map_or(line.len(), |(i, _)| column + i)
+}
+
+/// Start of the run of characters like the one at `column`
+fn run_start(line: &str, column: usize, big: bool) -> usize {
+    let Some(first) = line[column..].chars().next() else {
+        return column;
+    };
+    line[..column].char_indices().rev()
+        .take_while(|(_, ch)| same_run(first, *ch, big))
+        .last()
+        .map_or(column, |(i, _)| i)
+}
+
+fn select_word(lines: &[String], pos: Position, big: bool, around: bool, count: usize) -> Option<ObjectRange> {
+    let line = lines.get(pos.line)?;
+    if line.is_empty() {
+        return None;
+    }
+    let column = pos.column.min(Movement::last_char(lines, pos.line));
+    let on_blank = line[column..].starts_with(char::is_whitespace);
+    let mut start = run_start(line, column, big);
+    let mut end = run_end(line, column, big);
+    if around {
+        // A word takes the blanks after it, a run of blanks the word after it
+        let trailing = run_end(line, end, big);
+        if on_blank || trailing > end {
+            end = trailing;
+        } else {
+            start = line[..start].char_indices().rev()
+                .take_while(|(_, ch)| ch.is_whitespace())
+                .last()
+                .map_or(start, |(i, _)| i);
+        }
+    }
+    for _ in 1..count.max(1) {
+        let next = run_end(line, end, big);
+        end = if around { run_end(line, next, big) } else { next };
+    }
+    Some(ObjectRange::chars(Position::new(pos.line, start), Position::new(pos.line, end)))
+}
+
+fn select_sentence(lines: &[String], pos: Position, around: bool) -> Option<ObjectRange> {
+    let start = if Movement::is_sentence_start(lines, pos) { pos } else { Movement::sentence_backward(lines, pos) };
+    let mut end = Movement::sentence_forward(lines, start);
+    if !around {
+        while let Some(prev) = Movement::prev_char(lines, end) {
+            if (prev.line, prev.column) <= (start.line, start.column) || !Movement::char_at(lines, prev).is_some_and(char::is_whitespace) {
+                break;
+            }
+            end = prev;
+        }
+    }
+    Some(ObjectRange::chars(start, end))
+}
+
+fn select_paragraph(lines: &[String], pos: Position, around: bool, count: usize) -> Option<ObjectRange> {
+    let empty = |line: usize| lines[line].is_empty();
+    // Lines like `line` from it onwards, returning the last
+    let block_end = |line: usize| {
+        let mut last = line;
+        while last + 1 < lines.len() && empty(last + 1) == empty(line) {
+            last += 1;
+        }
+        last
+    };
+
+    let mut first = pos.line;
+    while first > 0 && empty(first - 1) == empty(pos.line) {
+        first -= 1;
+    }
+    let mut last = block_end(pos.line);
+    for _ in 1..count.max(1) {
+        if last + 1 >= lines.len() {
+            return None;
+        }
+        last = block_end(last + 1);
+    }
+    if around {
+        if last + 1 < lines.len() {
+            last = block_end(last + 1);
+        } else if !empty(pos.line) {
+            // At the end of the text the blank lines before are taken instead
+            while first > 0 && empty(first - 1) {
+                first -= 1;
+            }
+        }
+    }
+    Some(ObjectRange {
+        range: TextRange::new(Position::new(first, 0), Position::new(last, lines[last].len())),
+        linewise: true,
+    })
+}
+
+fn select_bracket(lines: &[String], pos: Position, open: char, close: char, around: bool) -> Option<ObjectRange> {
+    // Walk back to the unmatched opening bracket, the cursor itself being on one counting
+    let mut depth = 0usize;
+    let mut p = pos;
+    let open_at = loop {
+        match Movement::char_at(lines, p) {
+            Some(ch) if ch == open => {
+                if depth == 0 {
+                    break p;
+                }
+                depth -= 1;
+            }
+            Some(ch) if ch == close && p != pos => depth += 1,
+            _ => {}
+        }
+        p = Movement::prev_char(lines, p)?;
+    };
+
+    let mut depth = 0usize;
+    let mut p = open_at;
+    let close_at = loop {
+        match Movement::char_at(lines, p) {
+            Some(ch) if ch == open => depth += 1,
+            Some(ch) if ch == close => {
+                depth -= 1;
+                if depth == 0 {
+                    break p;
+                }
+            }
+            _ => {}
+        }
+        p = Movement::next_char(lines, p)?;
+    };
+
+    if around {
+        let end = Movement::next_char(lines, close_at)?;
+        return Some(ObjectRange::chars(open_at, end));
+    }
+    let start = Movement::next_char(lines, open_at)?;
+    // A block with its brackets on lines of their own is its lines in between
+    let opens_line = Movement::char_at(lines, start) == Some('\n');
+    let closes_line = lines[close_at.line][..close_at.column].trim().is_empty();
+    if opens_line && closes_line && close_at.line > start.line + 1 {
+        let last = close_at.line - 1;
+        return Some(ObjectRange {
+            range: TextRange::new(Position::new(start.line + 1, 0), Position::new(last, lines[last].len())),
+            linewise: true,
+        });
+    }
+    Some(ObjectRange::chars(start, close_at))
+}
+
+fn select_quote(lines: &[String], pos: Position, quote: char, around: bool) -> Option<ObjectRange> {
+    let line = lines.get(pos.line)?;
+    let quotes: Vec<usize> = line.match_indices(quote).map(|(i, _)| i).collect();
+    let before = quotes.iter().filter(|i| **i < pos.column).count();
+    // Quotes pair up from the start of the line
+    let pair = if quotes.get(before) == Some(&pos.column) || before % 2 == 0 {
+        let first = before - before % 2;
+        (first, first + 1)
+    } else {
+        (before - 1, before)
+    };
+    let (open, close) = (*quotes.get(pair.0)?, *quotes.get(pair.1)?);
+
+    let (mut start, mut end) = (open + quote.len_utf8(), close);
+    if around {
+        start = open;
+        end = close + quote.len_utf8();
+        let trailing = line[end..].len() - line[end..].trim_start().len();
+        if trailing > 0 {
+            end += trailing;
+        } else {
+            start -= line[..start].len() - line[..start].trim_end().len();
+        }
+    }
+    Some(ObjectRange::chars(Position::new(pos.line, start), Position::new(pos.line, end)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_of(lines: &[String], object: Option<ObjectRange>) -> String {
+        let range = object.unwrap().range;
+        let joined = lines.join("\n");
+        let offset = |p: Position| lines[..p.line].iter().map(|line| line.len() + 1).sum::<usize>() + p.column;
+        joined[offset(range.start)..offset(range.end)].to_string()
+    }
+
+    #[test]
+    fn test_word_bracket_and_quote_objects() {
+        let lines: Vec<String> = ["let x = call(a, \"b c\", (d));".to_string()].to_vec();
+        let at = |column| Position::new(0, column);
+        let word = TextObject::Word { big: false };
+        assert_eq!(text_of(&lines, select(&lines, at(4), word, false, 1)), "x");
+        assert_eq!(text_of(&lines, select(&lines, at(4), word, true, 1)), "x ");
+        assert_eq!(text_of(&lines, select(&lines, at(4), word, false, 3)), "x =");
+        assert_eq!(text_of(&lines, select(&lines, at(18), TextObject::Quote('"'), false, 1)), "b c");
+        assert_eq!(text_of(&lines, select(&lines, at(18), TextObject::Quote('"'), true, 1)), " \"b c\"");
+        let paren = TextObject::from_char('b').unwrap();
+        assert_eq!(text_of(&lines, select(&lines, at(14), paren, false, 1)), "a, \"b c\", (d)");
+        assert_eq!(text_of(&lines, select(&lines, at(25), paren, true, 1)), "(d)");
+        assert_eq!(select(&lines, at(2), paren, false, 1), None);
+    }
+
+    #[test]
+    fn test_paragraph_and_block_objects() {
+        let lines: Vec<String> = "fn f() {\n    body\n}\n\nnext".split('\n').map(str::to_string).collect();
+        let block = select(&lines, Position::new(1, 4), TextObject::from_char('{').unwrap(), false, 1).unwrap();
+        assert!(block.linewise);
+        assert_eq!(text_of(&lines, Some(block)), "    body");
+
+        let paragraph = select(&lines, Position::new(0, 0), TextObject::Paragraph, true, 1).unwrap();
+        assert_eq!((paragraph.range.start.line, paragraph.range.end.line), (0, 3));
+        let last = select(&lines, Position::new(4, 0), TextObject::Paragraph, true, 1).unwrap();
+        assert_eq!((last.range.start.line, last.range.end.line), (3, 4));
+    }
+}
@@ -1,16 +1,21 @@
 use crate::{Result, RuskError, Config, ConfigLoader};
 use crate::core::buffer::Buffer;
-use crate::plugins::implementations::editing::vim::VimPlugin;
-use crate::plugins::implementations::ui::tui::TuiPlugin;
-use crate::plugins::Plugin;
-use super::state::EditorState;
+use crate::plugins::implementations::ui::tui::EventLoopExit;
+use crate::plugins::implementations::language::lsp::LspManager;
+use crate::plugins::PluginRegistry;
+use crate::services::event_bus::{EditorEvent, EventBus};
+use super::state::{options, EditorState};
+use std::path::{Path, PathBuf};
 
 /// Main editor orchestrator that coordinates all components
 pub struct Editor {
     state: EditorState,
     config: Config,
-    vim_plugin: VimPlugin,
-    tui_plugin: TuiPlugin,
+    /// The user interface, the editing mode and every other enabled plugin
+    plugins: PluginRegistry,
+    events: EventBus,
+    // Declared last so language servers shut down before the runtime goes away
+    runtime: tokio::runtime::Runtime,
 }
 
 impl Editor {
@@ -20,20 +25,41 @@ impl Editor {
         let config = ConfigLoader::load()?;
         
         // Create state
-        let state = EditorState::new()?;
+        let mut state = EditorState::new()?;
+        options::register_editor_options(state.properties_mut(), &config.editor)?;
         
-        // Create plugins
-        let mut vim_plugin = VimPlugin::new();
-        vim_plugin.set_config(config.clone());
+        // Create the enabled plugins
+        let mut plugins = PluginRegistry::new();
+        let mut notices = plugins.load(&config)?;
         
-        let mut tui_plugin = TuiPlugin::new();
-        tui_plugin.set_config(config.clone());
+        // Folds closed in earlier sessions come back when their files open
+        if let Some(storage) = EditorState::default_fold_storage()
+            && let Err(e) = state.load_folds(storage)
+        {
+            notices.push(e.to_string());
+        }
+        for spec in plugins.properties() {
+            state.properties_mut().register(spec)?;
+        }
+        
+        let events = EventBus::new();
+        let ui = plugins.ui_mut()?;
+        ui.set_events(events.clone());
+        if !notices.is_empty() {
+            ui.show_message(notices.join("; "));
+        }
+        
+        // Language servers run on their own runtime; the editor loop blocks on it
+        let runtime = tokio::runtime::Runtime::new()
+            .map_err(|e| RuskError::Internal(format!("Failed to start async runtime: {}", e)))?;
+        ui.set_lsp(LspManager::from_config(runtime.handle().clone(), &config)?);
         
         Ok(Self {
             state,
             config,
-            vim_plugin,
-            tui_plugin,
+            plugins,
+            events,
+            runtime,
         })
     }
     
@@ -54,6 +80,21 @@ impl Editor {
         &mut self.state
     }
     
+    /// Get the async runtime used by language servers
+    pub fn runtime(&self) -> &tokio::runtime::Runtime {
+        &self.runtime
+    }
+    
+    /// Get the event bus plugins subscribe to
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+    
+    /// Get the plugin registry
+    pub fn plugins(&self) -> &PluginRegistry {
+        &self.plugins
+    }
+    
     /// Get configuration
     pub fn config(&self) -> &Config {
         &self.config
@@ -62,21 +103,34 @@ impl Editor {
     /// Open a file in the editor
     pub fn open_file(&mut self, file_path: &str) -> Result<()> {
         let buffer = Buffer::from_file(file_path)?;
-        self.state.add_buffer(buffer);
-        Ok(())
+        self.add_buffer(buffer)
+    }
+    
+    /// Switch to the buffer of a file, opening it if it is not open yet
+    pub fn show_file(&mut self, file_path: &str) -> Result<()> {
+        match self.state.find_buffer_by_path(file_path) {
+            Some(index) => self.state.switch_to_buffer(index),
+            None => self.open_file(file_path),
+        }
     }
     
     /// Create a new empty buffer
     pub fn new_buffer(&mut self) -> Result<()> {
         let buffer = Buffer::new();
+        self.add_buffer(buffer)
+    }
+    
+    /// Add a buffer and tell subscribers it was opened
+    fn add_buffer(&mut self, buffer: Buffer) -> Result<()> {
+        let event = EditorEvent::BufferOpened { buffer: buffer.id(), path: buffer.file_path().map(PathBuf::from) };
         self.state.add_buffer(buffer);
-        Ok(())
+        self.events.emit(event)
     }
     
     /// Save current buffer
     pub fn save_current_buffer(&mut self) -> Result<()> {
         if let Some(buffer) = self.state.current_buffer_mut() {
-            buffer.save()?;
+            self.events.save_buffer(buffer, None)?;
         }
         Ok(())
     }
@@ -84,7 +138,7 @@ impl Editor {
     /// Save current buffer as
     pub fn save_current_buffer_as(&mut self, file_path: &str) -> Result<()> {
         if let Some(buffer) = self.state.current_buffer_mut() {
-            buffer.save_as(file_path)?;
+            self.events.save_buffer(buffer, Some(Path::new(file_path)))?;
         }
         Ok(())
     }
@@ -98,15 +152,25 @@ impl Editor {
             }
         }
         
-        self.state.close_current_buffer();
+        self.discard_current_buffer()?;
         Ok(self.state.buffer_count() == 0) // Return true if no more buffers
     }
     
+    /// Close the current buffer, unsaved changes and all, and tell subscribers
+    fn discard_current_buffer(&mut self) -> Result<()> {
+        match self.state.close_current_buffer() {
+            Some(buffer) => self.events.emit(closed_event(&buffer)),
+            None => Ok(()),
+        }
+    }
+    
     /// Run the main editor loop
     pub fn run(&mut self) -> Result<()> {
-        // Initialize plugins
-        self.vim_plugin.initialize()?;
-        self.tui_plugin.initialize()?;
+        // Initialize plugins, shutting down the ones started if one fails
+        if let Err(e) = self.plugins.initialize_all() {
+            let _ = self.plugins.shutdown_all();
+            return Err(e);
+        }
         
         // Ensure we have at least one buffer
         if self.state.buffer_count() == 0 {
@@ -115,24 +179,55 @@ impl Editor {
         
         // Run the main event loop
         let result = self.run_main_loop();
+        let saved = self.state.save_folds();
+        for buffer in self.state.buffers() {
+            let _ = self.events.emit(closed_event(buffer));
+        }
+        let _ = self.events.emit(EditorEvent::Shutdown);
         
         // Shutdown plugins
-        let _ = self.tui_plugin.shutdown();
-        let _ = self.vim_plugin.shutdown();
+        let _ = self.plugins.shutdown_all();
         
-        result
+        result.and(saved)
     }
     
     /// Run the main event loop
     fn run_main_loop(&mut self) -> Result<()> {
         loop {
-            // Get current buffer
-            let current_buffer = self.state.current_buffer_mut()
+            // Let completion see the words of every open buffer
+            self.plugins.ui_mut()?.index_open_buffers(self.state.buffers());
+            
+            // Get current buffer, with the options `:set` changes
+            let (current_buffer, properties) = self.state.current_buffer_and_properties_mut()
                 .ok_or_else(|| RuskError::Internal("No active buffer".to_string()))?;
             
-            // Run TUI event loop which handles vim input
-            match self.tui_plugin.run_event_loop(current_buffer, &mut self.vim_plugin) {
-                Ok(()) => break, // Normal exit
+            // Run TUI event loop which hands keys to the editing mode
+            let (ui, editing) = self.plugins.ui_and_editing_mode_mut()?;
+            match ui.run_event_loop(current_buffer, properties, editing) {
+                Ok(EventLoopExit::Quit) => {
+                    // Subscribers can keep the editor open, say to finish a save
+                    match self.events.emit(EditorEvent::BeforeShutdown) {
+                        Ok(()) => break,
+                        Err(e) => self.plugins.ui_mut()?.show_message(e.to_string()),
+                    }
+                }
+                Ok(EventLoopExit::Open(path)) => {
+                    if let Err(e) = self.show_file(&path.to_string_lossy()) {
+                        self.plugins.ui_mut()?.show_message(e.to_string());
+                    }
+                }
+                Ok(EventLoopExit::Renamed { from, to }) => {
+                    self.state.rename_path(&from, &to);
+                }
+                Ok(EventLoopExit::Close) => {
+                    if let Err(e) = self.discard_current_buffer() {
+                        self.plugins.ui_mut()?.show_message(e.to_string());
+                    }
+                    // Like vim, closing the last buffer leaves an empty one
+                    if self.state.buffer_count() == 0 {
+                        self.new_buffer()?;
+                    }
+                }
                 Err(e) => {
                     // Handle errors gracefully
                     eprintln!("Error in event loop: {}", e);
@@ -172,9 +267,14 @@ impl Editor {
     /// Reload configuration
     pub fn reload_config(&mut self) -> Result<()> {
         self.config = ConfigLoader::reload()?;
-        self.vim_plugin.set_config(self.config.clone());
-        self.tui_plugin.set_config(self.config.clone());
-        Ok(())
+        options::register_editor_options(self.state.properties_mut(), &self.config.editor)?;
+        if self.plugins.reload_editing_mode(&self.config)? {
+            for spec in self.plugins.editing_mode_mut()?.properties() {
+                self.state.properties_mut().register(spec)?;
+            }
+        }
+        self.plugins.ui_mut()?.set_config(self.config.clone());
+        self.events.emit(EditorEvent::ConfigReloaded)
     }
     
     /// Check if any buffers are modified
@@ -194,8 +294,7 @@ impl Editor {
     /// Force quit without saving
     pub fn force_quit(&mut self) -> Result<()> {
         // Just shutdown plugins and exit
-        let _ = self.tui_plugin.shutdown();
-        let _ = self.vim_plugin.shutdown();
+        let _ = self.plugins.shutdown_all();
         Ok(())
     }
     
@@ -209,4 +308,9 @@ impl Editor {
             Ok(true)
         }
     }
-}
\ No newline at end of file
+}
+
+/// Get the event telling subscribers a buffer closed
+fn closed_event(buffer: &Buffer) -> EditorEvent {
+    EditorEvent::BufferClosed { buffer: buffer.id(), path: buffer.file_path().map(PathBuf::from) }
+}
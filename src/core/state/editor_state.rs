@@ -1,6 +1,42 @@
 use crate::{Result, RuskError};
 use crate::core::buffer::Buffer;
 use super::properties::PropertySystem;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Closed folds kept between sessions, by absolute file path
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SavedFolds {
+    #[serde(default)]
+    folds: BTreeMap<String, Vec<ClosedFold>>,
+}
+
+/// A closed fold, found again by the text of its first line when the file changed elsewhere
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ClosedFold {
+    line: usize,
+    text: String,
+}
+
+impl ClosedFold {
+    /// Find the fold's first line in the buffer: the line with its text nearest to where it was
+    fn locate(&self, lines: &[String]) -> Option<usize> {
+        lines.iter()
+            .enumerate()
+            .filter(|(_, text)| **text == self.text)
+            .map(|(line, _)| line)
+            .min_by_key(|line| line.abs_diff(self.line))
+    }
+}
+
+/// Make a path absolute so a file keeps its folds however it was opened
+fn fold_key(path: &str) -> String {
+    fs::canonicalize(path)
+        .or_else(|_| std::env::current_dir().map(|cwd| cwd.join(path)))
+        .map_or_else(|_| path.to_string(), |path| path.to_string_lossy().into_owned())
+}
 
 /// Editor state management
 pub struct EditorState {
@@ -8,6 +44,9 @@ pub struct EditorState {
     current_buffer_index: usize,
     modified: bool,
     properties: PropertySystem,
+    closed_folds: BTreeMap<String, Vec<ClosedFold>>,
+    /// File the closed folds are saved to between sessions
+    fold_storage: Option<PathBuf>,
 }
 
 impl EditorState {
@@ -18,11 +57,19 @@ impl EditorState {
             current_buffer_index: 0,
             modified: false,
             properties: PropertySystem::new(),
+            closed_folds: BTreeMap::new(),
+            fold_storage: None,
         })
     }
     
     /// Add a new buffer
-    pub fn add_buffer(&mut self, buffer: Buffer) {
+    pub fn add_buffer(&mut self, mut buffer: Buffer) {
+        // Restore folds closed earlier in this session or the last one
+        if let Some(closed) = buffer.file_path().and_then(|path| self.closed_folds.get(&fold_key(path))) {
+            let starts: Vec<usize> = closed.iter().filter_map(|fold| fold.locate(buffer.lines())).collect();
+            buffer.folds_mut().restore_closed(&starts);
+        }
+        
         self.buffers.push(buffer);
         self.current_buffer_index = self.buffers.len() - 1;
         self.modified = true;
@@ -81,10 +128,12 @@ impl EditorState {
         }
     }
     
-    /// Close current buffer
-    pub fn close_current_buffer(&mut self) {
+    /// Close current buffer, returning it
+    pub fn close_current_buffer(&mut self) -> Option<Buffer> {
         if !self.buffers.is_empty() {
-            self.buffers.remove(self.current_buffer_index);
+            self.remember_folds(self.current_buffer_index);
+            let buffer = self.buffers.remove(self.current_buffer_index);
+            self.properties.remove_buffer(buffer.id());
             
             // Adjust current buffer index
             if self.current_buffer_index >= self.buffers.len() && !self.buffers.is_empty() {
@@ -92,13 +141,18 @@ impl EditorState {
             }
             
             self.modified = true;
+            Some(buffer)
+        } else {
+            None
         }
     }
     
-    /// Close buffer by index
-    pub fn close_buffer(&mut self, index: usize) -> Result<()> {
+    /// Close buffer by index, returning it
+    pub fn close_buffer(&mut self, index: usize) -> Result<Buffer> {
         if index < self.buffers.len() {
-            self.buffers.remove(index);
+            self.remember_folds(index);
+            let buffer = self.buffers.remove(index);
+            self.properties.remove_buffer(buffer.id());
             
             // Adjust current buffer index if necessary
             if index <= self.current_buffer_index && self.current_buffer_index > 0 {
@@ -111,7 +165,7 @@ impl EditorState {
             }
             
             self.modified = true;
-            Ok(())
+            Ok(buffer)
         } else {
             Err(RuskError::Buffer(format!("Buffer index {} out of range", index)))
         }
@@ -133,10 +187,48 @@ impl EditorState {
     }
     
     /// Find buffer by file path
+    ///
+    /// Paths naming the same file match even when written differently,
+    /// like `src/main.rs` and `./src/main.rs`.
     pub fn find_buffer_by_path(&self, path: &str) -> Option<usize> {
+        let canonical = std::fs::canonicalize(path).ok();
         self.buffers
             .iter()
-            .position(|buffer| buffer.file_path() == Some(path))
+            .position(|buffer| buffer.file_path().is_some_and(|buffer_path| {
+                buffer_path == path || (canonical.is_some() && std::fs::canonicalize(buffer_path).ok() == canonical)
+            }))
+    }
+    
+    /// Point buffers at a renamed or moved file, or at the files of a moved directory
+    ///
+    /// Returns the number of buffers updated. Relative buffer paths stay
+    /// relative to the working directory when possible.
+    pub fn rename_path(&mut self, from: &Path, to: &Path) -> usize {
+        let (Ok(from), Ok(to)) = (std::path::absolute(from), std::path::absolute(to)) else {
+            return 0;
+        };
+        let cwd = std::env::current_dir().ok();
+        
+        let mut renamed = 0;
+        for buffer in &mut self.buffers {
+            let Some(path) = buffer.file_path().map(PathBuf::from) else {
+                continue;
+            };
+            let Some(rest) = std::path::absolute(&path).ok()
+                .and_then(|absolute| absolute.strip_prefix(&from).ok().map(Path::to_path_buf))
+            else {
+                continue;
+            };
+            
+            let new_path = if rest.as_os_str().is_empty() { to.clone() } else { to.join(rest) };
+            let new_path = match &cwd {
+                Some(cwd) if path.is_relative() => new_path.strip_prefix(cwd).map(Path::to_path_buf).unwrap_or(new_path),
+                _ => new_path,
+            };
+            buffer.set_file_path(new_path);
+            renamed += 1;
+        }
+        renamed
     }
     
     /// Get buffer names for display
@@ -179,6 +271,70 @@ impl EditorState {
             .collect()
     }
     
+    /// Record the closed folds of a buffer so they survive closing and reopening it
+    pub fn remember_folds(&mut self, index: usize) {
+        if let Some(buffer) = self.buffers.get(index)
+            && let Some(path) = buffer.file_path()
+        {
+            let closed = buffer.folds().closed_starts().into_iter()
+                .map(|line| ClosedFold { line, text: buffer.lines()[line].clone() })
+                .collect();
+            self.closed_folds.insert(fold_key(path), closed);
+        }
+    }
+    
+    /// Record the closed folds of every open buffer
+    pub fn remember_all_folds(&mut self) {
+        for index in 0..self.buffers.len() {
+            self.remember_folds(index);
+        }
+    }
+    
+    /// Get the first lines of the closed folds recorded for a file
+    pub fn closed_folds(&self, path: &str) -> Option<Vec<usize>> {
+        self.closed_folds.get(&fold_key(path)).map(|closed| closed.iter().map(|fold| fold.line).collect())
+    }
+    
+    /// Get where closed folds are kept between sessions
+    pub fn default_fold_storage() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("rusk").join("folds.toml"))
+    }
+    
+    /// Load the closed folds saved to a file, saving them there again with `save_folds`
+    pub fn load_folds(&mut self, storage: impl Into<PathBuf>) -> Result<()> {
+        let storage = storage.into();
+        let saved = match fs::read_to_string(&storage) {
+            Ok(content) => toml::from_str::<SavedFolds>(&content)
+                .map_err(|e| RuskError::Config(format!("Failed to parse folds file '{}': {}", storage.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SavedFolds::default(),
+            Err(e) => return Err(RuskError::io_string(format!("Failed to read folds file '{}': {}", storage.display(), e))),
+        };
+        self.closed_folds.extend(saved.folds);
+        self.fold_storage = Some(storage);
+        Ok(())
+    }
+    
+    /// Record the closed folds of every open buffer and write them all to the fold storage
+    pub fn save_folds(&mut self) -> Result<()> {
+        self.remember_all_folds();
+        let Some(storage) = &self.fold_storage else {
+            return Ok(());
+        };
+        let saved = SavedFolds {
+            folds: self.closed_folds.iter()
+                .filter(|(_, starts)| !starts.is_empty())
+                .map(|(path, starts)| (path.clone(), starts.clone()))
+                .collect(),
+        };
+        let content = toml::to_string_pretty(&saved)
+            .map_err(|e| RuskError::Config(format!("Failed to serialize folds: {}", e)))?;
+        if let Some(dir) = storage.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(storage, content)
+            .map_err(|e| RuskError::io_string(format!("Failed to write folds file '{}': {}", storage.display(), e)))
+    }
+    
     /// Get access to property system
     pub fn properties(&self) -> &PropertySystem {
         &self.properties
@@ -188,4 +344,45 @@ impl EditorState {
     pub fn properties_mut(&mut self) -> &mut PropertySystem {
         &mut self.properties
     }
-}
\ No newline at end of file
+    
+    /// Get the current buffer and the property system together, for setting its options
+    pub fn current_buffer_and_properties_mut(&mut self) -> Option<(&mut Buffer, &mut PropertySystem)> {
+        let buffer = self.buffers.get_mut(self.current_buffer_index)?;
+        Some((buffer, &mut self.properties))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::buffer::FoldRange;
+    
+    #[test]
+    fn test_closed_folds_survive_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let (file, storage) = (dir.path().join("notes.txt"), dir.path().join("folds.toml"));
+        fs::write(&file, "a\n  b\n  c\nd\n").unwrap();
+        let path = file.to_string_lossy().to_string();
+        
+        let mut state = EditorState::new().unwrap();
+        state.load_folds(&storage).unwrap();
+        state.add_buffer(Buffer::from_file(&file).unwrap());
+        let folds = state.current_buffer_mut().unwrap().folds_mut();
+        folds.set_ranges(vec![FoldRange::new(0, 2)]);
+        folds.close(0);
+        state.save_folds().unwrap();
+        
+        let mut state = EditorState::new().unwrap();
+        state.load_folds(&storage).unwrap();
+        assert_eq!(state.closed_folds(&path), Some(vec![0]));
+        state.add_buffer(Buffer::from_file(&file).unwrap());
+        assert!(state.current_buffer().unwrap().folds().is_closed(0));
+        
+        // Lines added above the fold elsewhere move it down with its text
+        fs::write(&file, "new\na\n  b\n  c\nd\n").unwrap();
+        let mut state = EditorState::new().unwrap();
+        state.load_folds(&storage).unwrap();
+        state.add_buffer(Buffer::from_file(&file).unwrap());
+        let folds = state.current_buffer().unwrap().folds();
+        assert!(folds.is_closed(1) && !folds.is_closed(0));
+    }
+}
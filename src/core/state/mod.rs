@@ -5,5 +5,7 @@
 
 mod editor_state;
 mod properties;
+pub mod options;
 
-pub use editor_state::EditorState;
\ No newline at end of file
+pub use editor_state::EditorState;
+pub use properties::{PropertyChange, PropertyScope, PropertySpec, PropertySystem, PropertyTarget, PropertyType, WatchCallback, WatchId};
\ No newline at end of file
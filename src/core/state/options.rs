@@ -0,0 +1,248 @@
+//! Editor options changed at runtime with `:set` and `:setlocal`
+//!
+//! The editor settings that can change while the editor runs are registered
+//! as properties under their config names, with vim's names as aliases
+//! (`tabstop`, `ts`). Buffer and window options set with `:setlocal`
+//! override the configured settings for one buffer or for the window.
+
+use crate::{Result, RuskError};
+use crate::config::settings::EditorSettings;
+use crate::core::buffer::BufferId;
+use crate::plugins::implementations::language::syntax::SupportedLanguage;
+use super::properties::{PropertyScope, PropertySpec, PropertySystem, PropertyTarget, PropertyType};
+use serde_json::Value;
+
+/// Option holding a buffer's language, by its LSP language identifier
+pub const FILETYPE: &str = "filetype";
+
+/// The editor options, defaulting to the configured settings
+fn editor_options(settings: &EditorSettings) -> Vec<PropertySpec> {
+    use PropertyScope::{Buffer, Global, Window};
+    use PropertyType::{Bool, Integer};
+    let configured = serde_json::to_value(settings).unwrap_or_default();
+    let option = |name: &str, kind, scope| PropertySpec::new(name, kind, scope, configured.get(name).cloned().unwrap_or_default());
+    vec![
+        option("tab_size", Integer, Buffer).alias("tabstop").alias("ts").range(1, 32)
+            .describe("Columns a tab takes, and the width of one indentation level"),
+        option("insert_spaces", Bool, Buffer).alias("expandtab").alias("et")
+            .describe("Indent with spaces rather than tabs"),
+        option("line_numbers", Bool, Window).alias("number").alias("nu")
+            .describe("Show line numbers"),
+        option("wrap_lines", Bool, Window).alias("wrap")
+            .describe("Wrap lines longer than the window"),
+        option("show_whitespace", Bool, Window).alias("list")
+            .describe("Show tabs and trailing spaces"),
+        option("highlight_current_line", Bool, Window).alias("cursorline").alias("cul")
+            .describe("Highlight the line the cursor is on"),
+        option("auto_save", Bool, Global).alias("autowrite").alias("aw")
+            .describe("Save the buffer being edited once it has been modified for auto_save_interval seconds"),
+        option("auto_save_interval", Integer, Global).range(1, 3600)
+            .describe("Seconds between automatic saves"),
+        option("backup_files", Bool, Global).alias("backup").alias("bk")
+            .describe("Keep a copy of a file before overwriting it"),
+        option("show_hidden_files", Bool, Global)
+            .describe("List files starting with . in the file finder and explorer"),
+        PropertySpec::new(FILETYPE, PropertyType::String, Buffer, SupportedLanguage::Text.language_id()).alias("ft")
+            .choices(SupportedLanguage::ALL.iter().map(SupportedLanguage::language_id))
+            .describe("Language the buffer is highlighted and checked as"),
+    ]
+}
+
+/// Register the editor options, defaulting to the configured settings
+///
+/// Registering them again after the config is reloaded changes the
+/// defaults while keeping the values set with `:set`. Fails if a
+/// configured setting is out of range.
+pub fn register_editor_options(properties: &mut PropertySystem, settings: &EditorSettings) -> Result<()> {
+    for spec in editor_options(settings) {
+        properties.register(spec)?;
+    }
+    Ok(())
+}
+
+/// List the registered options with their type, scope, default and allowed values, for `:help option`
+///
+/// Each option takes two lines, the second its description.
+pub fn help_lines(properties: &PropertySystem) -> Vec<String> {
+    properties.specs().into_iter().flat_map(help_entry).collect()
+}
+
+/// Describe one option for `:help`, or `None` if no option has that name
+pub fn help_option(properties: &PropertySystem, name: &str) -> Option<Vec<String>> {
+    properties.spec(name).map(help_entry)
+}
+
+fn help_entry(spec: &PropertySpec) -> Vec<String> {
+    let names = std::iter::once(&spec.name).chain(&spec.aliases).cloned().collect::<Vec<_>>().join(", ");
+    let default = match &spec.default {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    };
+    let mut header = format!("{}  {}, {}, default {}", names, spec.kind.name(), spec.scope.name(), default);
+    match spec.allowed() {
+        Some(allowed) if spec.range.is_some() => header.push_str(&format!(", {}", allowed)),
+        Some(allowed) => header.push_str(&format!(", one of {}", allowed)),
+        None => {}
+    }
+    vec![header, format!("    {}", spec.description)]
+}
+
+/// Get the settings that apply to a buffer: the configured ones, overridden by the options set
+pub fn editor_settings(properties: &PropertySystem, configured: &EditorSettings, buffer: BufferId) -> EditorSettings {
+    let Ok(Value::Object(mut fields)) = serde_json::to_value(configured) else {
+        return configured.clone();
+    };
+    for (name, field) in fields.iter_mut() {
+        if let Some(value) = properties.value(name, buffer) {
+            *field = value.clone();
+        }
+    }
+    serde_json::from_value(Value::Object(fields)).unwrap_or_else(|_| configured.clone())
+}
+
+/// Get the names `:set` completes, config names and aliases alike
+pub fn option_names(properties: &PropertySystem) -> Vec<String> {
+    let mut names: Vec<String> = properties.specs().into_iter()
+        .flat_map(|spec| std::iter::once(&spec.name).chain(&spec.aliases))
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
+
+/// Run the arguments of `:set`, or of `:setlocal` when `local`, returning the lines to show
+///
+/// Accepts `name=value` (or `name:value`), `name+=n`, `name-=n`, `name`,
+/// `noname`, `invname` and `name!` for booleans, `name?` to show a value,
+/// `name&` to reset it and `all`. Without arguments, the options differing
+/// from their defaults are shown.
+pub fn run_set(properties: &mut PropertySystem, args: &str, local: bool, buffer: BufferId) -> Result<Vec<String>> {
+    let mut shown = Vec::new();
+    if args.trim().is_empty() {
+        for spec in properties.specs() {
+            if properties.value(&spec.name, buffer) != Some(&spec.default) {
+                shown.push(describe(properties, &spec.name, buffer));
+            }
+        }
+        return Ok(shown);
+    }
+    for item in args.split_whitespace() {
+        set_item(properties, item, local, buffer, &mut shown)?;
+    }
+    Ok(shown)
+}
+
+/// Run one `:set` argument
+fn set_item(properties: &mut PropertySystem, item: &str, local: bool, buffer: BufferId, shown: &mut Vec<String>) -> Result<()> {
+    if item == "all" {
+        let names: Vec<String> = properties.specs().iter().map(|spec| spec.name.clone()).collect();
+        shown.extend(names.iter().map(|name| describe(properties, name, buffer)));
+        return Ok(());
+    }
+    let end = item.find(|ch: char| !ch.is_alphanumeric() && ch != '_').unwrap_or(item.len());
+    let (name, operation) = item.split_at(end);
+    let invalid = || RuskError::Config(format!("Invalid argument: {}", item));
+    
+    // `noname` and `invname` switch booleans off and over
+    let boolean = |name: &str| operation.is_empty() && properties.spec(name).is_some_and(|spec| spec.kind == PropertyType::Bool);
+    let (name, operation) = if properties.spec(name).is_some() {
+        (name, operation)
+    } else if let Some(name) = name.strip_prefix("no").filter(|name| boolean(name)) {
+        (name, "=false")
+    } else if let Some(name) = name.strip_prefix("inv").filter(|name| boolean(name)) {
+        (name, "!")
+    } else {
+        (name, operation)
+    };
+    let spec = properties.spec(name)
+        .ok_or_else(|| RuskError::Config(format!("Unknown option: {}", name)))?;
+    let (name, kind, default) = (spec.name.clone(), spec.kind, spec.default.clone());
+    let current = properties.value(&name, buffer).cloned().unwrap_or(Value::Null);
+    
+    let value = match operation {
+        "?" => {
+            shown.push(describe(properties, &name, buffer));
+            return Ok(());
+        }
+        "&" => default,
+        "" if kind == PropertyType::Bool => Value::Bool(true),
+        "!" if kind == PropertyType::Bool => Value::Bool(!current.as_bool().unwrap_or(false)),
+        "" => {
+            // Other options are shown when named alone
+            shown.push(describe(properties, &name, buffer));
+            return Ok(());
+        }
+        _ => {
+            let (operator, text) = ["+=", "-=", "=", ":"].iter()
+                .find_map(|operator| operation.strip_prefix(operator).map(|text| (*operator, text)))
+                .ok_or_else(invalid)?;
+            let value = kind.parse(text)?;
+            match (operator, kind) {
+                ("=" | ":", _) => value,
+                (_, PropertyType::Integer) => {
+                    let (current, change) = (current.as_i64().unwrap_or(0), value.as_i64().unwrap_or(0));
+                    Value::from(if operator == "+=" { current + change } else { current - change })
+                }
+                ("+=", PropertyType::String) => Value::String(format!("{}{}", current.as_str().unwrap_or(""), text)),
+                _ => return Err(invalid()),
+            }
+        }
+    };
+    assign(properties, &name, value, local, buffer)
+}
+
+/// Set an option's value: `:set` sets the global value and the one the
+/// buffer or window sees, `:setlocal` only the latter
+fn assign(properties: &mut PropertySystem, name: &str, value: Value, local: bool, buffer: BufferId) -> Result<()> {
+    let target = match properties.spec(name).map(|spec| spec.scope) {
+        Some(PropertyScope::Buffer) => PropertyTarget::Buffer(buffer),
+        Some(PropertyScope::Window) => PropertyTarget::Window,
+        _ => PropertyTarget::Global,
+    };
+    if !local && target != PropertyTarget::Global {
+        properties.set_property(name, value.clone())?;
+    }
+    properties.set_local(name, target, value)
+}
+
+/// Show an option as `name=value`
+fn describe(properties: &PropertySystem, name: &str, buffer: BufferId) -> String {
+    match properties.value(name, buffer) {
+        Some(Value::String(text)) => format!("{}={}", name, text),
+        Some(value) => format!("{}={}", name, value),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::buffer::Buffer;
+    
+    #[test]
+    fn test_set_and_setlocal() {
+        let mut properties = PropertySystem::new();
+        register_editor_options(&mut properties, &EditorSettings::default()).unwrap();
+        let (first, second) = (Buffer::new().id(), Buffer::new().id());
+        
+        run_set(&mut properties, "ts=2 nowrap", false, first).unwrap();
+        run_set(&mut properties, "tabstop+=6 et!", true, second).unwrap();
+        assert_eq!(editor_settings(&properties, &EditorSettings::default(), first).tab_size, 2);
+        let settings = editor_settings(&properties, &EditorSettings::default(), second);
+        assert_eq!((settings.tab_size, settings.insert_spaces, settings.wrap_lines), (8, false, false));
+        assert_eq!(run_set(&mut properties, "ts?", false, second).unwrap(), vec!["tab_size=8"]);
+        
+        run_set(&mut properties, "ts&", true, second).unwrap();
+        assert_eq!(editor_settings(&properties, &EditorSettings::default(), second).tab_size, 4);
+        assert!(run_set(&mut properties, "ts=wide", false, first).is_err());
+        assert!(run_set(&mut properties, "nots", false, first).is_err());
+        assert!(run_set(&mut properties, "colors=on", false, first).is_err());
+        assert!(run_set(&mut properties, "ts=0", false, first).is_err());
+        assert!(run_set(&mut properties, "ft=cobol", false, first).is_err());
+        assert_eq!(help_option(&properties, "ts").unwrap()[0], "tab_size, tabstop, ts  integer, buffer, default 4, 1 to 32");
+        
+        let changes = properties.take_changes();
+        assert_eq!(changes.first().map(|change| change.key.as_str()), Some("tab_size"));
+        assert!(changes.iter().all(|change| change.old != change.new));
+    }
+}
@@ -1,83 +1,636 @@
+use crate::{Result, RuskError};
+use crate::core::buffer::BufferId;
 use std::collections::HashMap;
 use serde_json::Value;
 
+/// Type the values of a property must have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyType {
+    Bool,
+    Integer,
+    String,
+}
+
+impl PropertyType {
+    /// Get the name shown in messages
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bool => "bool",
+            Self::Integer => "integer",
+            Self::String => "string",
+        }
+    }
+    
+    /// Check whether a value has this type
+    pub fn accepts(self, value: &Value) -> bool {
+        match self {
+            Self::Bool => value.is_boolean(),
+            Self::Integer => value.is_i64() || value.is_u64(),
+            Self::String => value.is_string(),
+        }
+    }
+    
+    /// Read a value of this type from text, as typed after `:set name=`
+    pub fn parse(self, text: &str) -> Result<Value> {
+        let value = match self {
+            Self::Bool => match text {
+                "true" | "on" | "1" => Some(Value::Bool(true)),
+                "false" | "off" | "0" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            Self::Integer => text.parse::<i64>().ok().map(Value::from),
+            Self::String => Some(Value::String(text.to_string())),
+        };
+        value.ok_or_else(|| RuskError::Config(format!("Expected {} value, got '{}'", self.name(), text)))
+    }
+}
+
+/// Where the value of a property applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyScope {
+    /// One value for the whole editor
+    Global,
+    /// A value per buffer, like the tab size
+    Buffer,
+    /// A value per window, like line numbers
+    Window,
+}
+
+impl PropertyScope {
+    /// Get the name shown in messages
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Buffer => "buffer",
+            Self::Window => "window",
+        }
+    }
+}
+
+/// Declared name, type, scope, default and allowed values of a property
+#[derive(Debug, Clone)]
+pub struct PropertySpec {
+    pub name: String,
+    /// Other names accepted for it, like `ts` for `tab_size`
+    pub aliases: Vec<String>,
+    pub kind: PropertyType,
+    pub scope: PropertyScope,
+    pub default: Value,
+    /// Smallest and largest value of an integer property
+    pub range: Option<(i64, i64)>,
+    /// Values a string property is limited to, if any
+    pub choices: Vec<String>,
+    pub description: String,
+}
+
+impl PropertySpec {
+    /// Declare a property with the value it has until set
+    pub fn new(name: impl Into<String>, kind: PropertyType, scope: PropertyScope, default: impl Into<Value>) -> Self {
+        Self {
+            name: name.into(),
+            aliases: Vec::new(),
+            kind,
+            scope,
+            default: default.into(),
+            range: None,
+            choices: Vec::new(),
+            description: String::new(),
+        }
+    }
+    
+    /// Accept another name for the property
+    pub fn alias(mut self, alias: impl Into<String>) -> Self {
+        self.aliases.push(alias.into());
+        self
+    }
+    
+    /// Limit an integer property to a range
+    pub fn range(mut self, min: i64, max: i64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+    
+    /// Limit a string property to some values
+    pub fn choices<S: Into<String>>(mut self, choices: impl IntoIterator<Item = S>) -> Self {
+        self.choices = choices.into_iter().map(Into::into).collect();
+        self
+    }
+    
+    /// Say what the property does, for `:help option`
+    pub fn describe(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+    
+    /// Describe the values allowed besides the type, like `1 to 32`
+    pub fn allowed(&self) -> Option<String> {
+        match (self.range, self.choices.is_empty()) {
+            (Some((min, max)), _) => Some(format!("{} to {}", min, max)),
+            (None, false) => Some(self.choices.join(", ")),
+            (None, true) => None,
+        }
+    }
+    
+    /// Check that a value has the property's type and is allowed
+    pub fn check(&self, value: &Value) -> Result<()> {
+        let allowed = match value {
+            _ if !self.kind.accepts(value) => false,
+            Value::Number(number) => self.range.is_none_or(|(min, max)| number.as_i64().is_some_and(|n| (min..=max).contains(&n))),
+            Value::String(text) => self.choices.is_empty() || self.choices.contains(text),
+            _ => true,
+        };
+        if allowed {
+            return Ok(());
+        }
+        let expected = match self.allowed() {
+            Some(allowed) => format!("a {} value ({})", self.kind.name(), allowed),
+            None => format!("a {} value", self.kind.name()),
+        };
+        Err(RuskError::Config(format!("{} takes {}, not {}", self.name, expected, value)))
+    }
+}
+
+/// Where a value is set: for the whole editor, or only for one buffer or the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyTarget {
+    Global,
+    Buffer(BufferId),
+    Window,
+}
+
+/// A property whose value changed, with the values seen where it was set
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyChange {
+    pub key: String,
+    pub target: PropertyTarget,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// Identifies a watcher, for removing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchId(u64);
+
+/// Called with the changes to the properties a watcher watches, and the properties to read or set others
+pub type WatchCallback = Box<dyn FnMut(&[PropertyChange], &mut PropertySystem)>;
+
+/// What a watcher watches
+enum WatchPattern {
+    Key(String),
+    Prefix(String),
+}
+
+struct Watcher {
+    id: WatchId,
+    pattern: WatchPattern,
+    /// Taken while the watcher runs, so it is not run again from inside itself
+    callback: Option<WatchCallback>,
+}
+
+impl Watcher {
+    fn matches(&self, key: &str) -> bool {
+        match &self.pattern {
+            WatchPattern::Key(watched) => key == watched,
+            WatchPattern::Prefix(prefix) => key.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// Values saved when a batch starts, restored if it fails
+type Snapshot = (HashMap<String, Value>, HashMap<BufferId, HashMap<String, Value>>, HashMap<String, Value>);
+
 /// Property system for dynamic configuration
 /// This allows plugins to register and manage properties dynamically
+///
+/// Properties are registered with their type and allowed values before
+/// they can be set, and buffer and window properties can have local values
+/// overriding the global one. Changes are recorded until taken with
+/// `take_changes`, and delivered to the watchers of the properties as
+/// they happen, or once at the end of a batch.
 pub struct PropertySystem {
     properties: HashMap<String, Value>,
-    property_types: HashMap<String, String>,
+    specs: HashMap<String, PropertySpec>,
+    aliases: HashMap<String, String>,
+    buffer_values: HashMap<BufferId, HashMap<String, Value>>,
+    window_values: HashMap<String, Value>,
+    changes: Vec<PropertyChange>,
+    watchers: Vec<Watcher>,
+    next_watch: u64,
+    /// Changes made in the running batch, delivered when it ends
+    batched: Option<Vec<PropertyChange>>,
+    /// Properties whose changes are being delivered, which watchers may not set again
+    notifying: Vec<String>,
 }
 
 impl PropertySystem {
     pub fn new() -> Self {
         Self {
             properties: HashMap::new(),
-            property_types: HashMap::new(),
+            specs: HashMap::new(),
+            aliases: HashMap::new(),
+            buffer_values: HashMap::new(),
+            window_values: HashMap::new(),
+            changes: Vec::new(),
+            watchers: Vec::new(),
+            next_watch: 0,
+            batched: None,
+            notifying: Vec::new(),
+        }
+    }
+    
+    /// Register a property, replacing an earlier registration of it
+    ///
+    /// Fails if the default is not allowed or a name is taken by another
+    /// property. Values set earlier that are no longer allowed are dropped.
+    pub fn register(&mut self, spec: PropertySpec) -> Result<()> {
+        spec.check(&spec.default)?;
+        let name = spec.name.clone();
+        for taken in std::iter::once(&name).chain(&spec.aliases) {
+            let owner = self.aliases.get(taken).or(self.specs.contains_key(taken).then_some(taken));
+            if owner.is_some_and(|owner| *owner != name) {
+                return Err(RuskError::Config(format!("Property name {} is already taken", taken)));
+            }
         }
+        
+        self.aliases.retain(|_, owner| *owner != name);
+        for alias in &spec.aliases {
+            self.aliases.insert(alias.clone(), name.clone());
+        }
+        if self.properties.get(&name).is_some_and(|value| spec.check(value).is_err()) {
+            self.properties.remove(&name);
+        }
+        for values in self.buffer_values.values_mut().chain(std::iter::once(&mut self.window_values)) {
+            if values.get(&name).is_some_and(|value| spec.check(value).is_err()) {
+                values.remove(&name);
+            }
+        }
+        self.specs.insert(name, spec);
+        Ok(())
+    }
+    
+    /// Get the registration of a property, by its name or an alias
+    pub fn spec(&self, key: &str) -> Option<&PropertySpec> {
+        self.specs.get(self.aliases.get(key).map_or(key, String::as_str))
+    }
+    
+    /// Get every registered property, by name
+    pub fn specs(&self) -> Vec<&PropertySpec> {
+        let mut specs: Vec<&PropertySpec> = self.specs.values().collect();
+        specs.sort_by(|a, b| a.name.cmp(&b.name));
+        specs
     }
     
     /// Set a property value
-    pub fn set_property(&mut self, key: &str, value: Value) {
-        let type_name = match &value {
-            Value::Bool(_) => "bool",
-            Value::Number(_) => "number", 
-            Value::String(_) => "string",
-            Value::Array(_) => "array",
-            Value::Object(_) => "object",
-            Value::Null => "null",
+    ///
+    /// The property, which can be named by an alias, must be registered and
+    /// allow the value. This sets the global value, used where no local
+    /// value is set.
+    pub fn set_property(&mut self, key: &str, value: Value) -> Result<()> {
+        let spec = self.spec(key)
+            .ok_or_else(|| RuskError::Config(format!("Unknown option: {}", key)))?;
+        spec.check(&value)?;
+        let key = spec.name.clone();
+        self.check_cycle(&key)?;
+        
+        let old = self.get_property(&key).cloned();
+        self.properties.insert(key.clone(), value);
+        self.record(key, PropertyTarget::Global, old);
+        Ok(())
+    }
+    
+    /// Set the value a buffer or window property has in one buffer or in the window
+    pub fn set_local(&mut self, key: &str, target: PropertyTarget, value: Value) -> Result<()> {
+        let spec = self.spec(key)
+            .ok_or_else(|| RuskError::Config(format!("Unknown option: {}", key)))?;
+        spec.check(&value)?;
+        let key = spec.name.clone();
+        self.check_cycle(&key)?;
+        let values = match (target, spec.scope) {
+            // Global properties have no local values, as with vim's `:setlocal`
+            (PropertyTarget::Global, _) | (_, PropertyScope::Global) => return self.set_property(&key, value),
+            (PropertyTarget::Buffer(id), PropertyScope::Buffer) => self.buffer_values.entry(id).or_default(),
+            (PropertyTarget::Window, PropertyScope::Window) => &mut self.window_values,
+            (_, scope) => return Err(RuskError::Config(format!("{} is a {} option", key, scope.name()))),
         };
         
-        self.property_types.insert(key.to_string(), type_name.to_string());
-        self.properties.insert(key.to_string(), value);
+        let old = values.insert(key.clone(), value);
+        let old = old.or_else(|| self.get_property(&key).cloned());
+        self.record(key, target, old);
+        Ok(())
+    }
+    
+    /// Give a buffer the value of a property found when opening it, like its
+    /// file type, unless it has one; this is not reported as a change
+    pub fn init_local(&mut self, key: &str, buffer: BufferId, value: Value) -> Result<()> {
+        let spec = self.spec(key)
+            .ok_or_else(|| RuskError::Config(format!("Unknown option: {}", key)))?;
+        spec.check(&value)?;
+        let key = spec.name.clone();
+        self.buffer_values.entry(buffer).or_default().entry(key).or_insert(value);
+        Ok(())
+    }
+    
+    /// Get the value set for a property in one buffer or in the window
+    pub fn local(&self, key: &str, target: PropertyTarget) -> Option<&Value> {
+        let key = self.aliases.get(key).map_or(key, String::as_str);
+        match target {
+            PropertyTarget::Global => self.properties.get(key),
+            PropertyTarget::Buffer(id) => self.buffer_values.get(&id)?.get(key),
+            PropertyTarget::Window => self.window_values.get(key),
+        }
+    }
+    
+    /// Remove the value set for a property in one buffer or in the window, so the global one applies again
+    ///
+    /// Like `remove_property`, this does nothing to a property whose changes are being delivered.
+    pub fn remove_local(&mut self, key: &str, target: PropertyTarget) -> Option<Value> {
+        if target == PropertyTarget::Global {
+            return self.remove_property(key);
+        }
+        let key = self.canonical_key(key);
+        if self.check_cycle(&key).is_err() {
+            return None;
+        }
+        let values = match target {
+            PropertyTarget::Buffer(id) => self.buffer_values.get_mut(&id)?,
+            _ => &mut self.window_values,
+        };
+        let old = values.remove(&key)?;
+        self.record(key, target, Some(old.clone()));
+        Some(old)
+    }
+    
+    /// Forget the local values of a closed buffer
+    pub fn remove_buffer(&mut self, buffer: BufferId) {
+        self.buffer_values.remove(&buffer);
+    }
+    
+    /// Get the value of a property in a buffer shown in the window: its
+    /// local value, or else the global value or default
+    pub fn value(&self, key: &str, buffer: BufferId) -> Option<&Value> {
+        let local = match self.spec(key)?.scope {
+            PropertyScope::Global => None,
+            PropertyScope::Buffer => self.local(key, PropertyTarget::Buffer(buffer)),
+            PropertyScope::Window => self.local(key, PropertyTarget::Window),
+        };
+        local.or_else(|| self.get_property(key))
     }
     
     /// Get a property value
+    ///
+    /// This is the global value, or its default if it was never set.
     pub fn get_property(&self, key: &str) -> Option<&Value> {
-        self.properties.get(key)
+        let key = self.aliases.get(key).map_or(key, String::as_str);
+        self.properties.get(key).or_else(|| self.specs.get(key).map(|spec| &spec.default))
     }
     
     /// Get a property as a specific type
     pub fn get_bool(&self, key: &str) -> Option<bool> {
-        self.properties.get(key)?.as_bool()
+        self.get_property(key)?.as_bool()
     }
     
     pub fn get_string(&self, key: &str) -> Option<&str> {
-        self.properties.get(key)?.as_str()
+        self.get_property(key)?.as_str()
     }
     
     pub fn get_number(&self, key: &str) -> Option<f64> {
-        self.properties.get(key)?.as_f64()
+        self.get_property(key)?.as_f64()
     }
     
-    /// Remove a property
+    /// Remove a property's global value, so it goes back to its default
+    ///
+    /// Watchers of the property cannot remove it while its change is being
+    /// delivered to them, as that could go round in a cycle; this then
+    /// returns `None`.
     pub fn remove_property(&mut self, key: &str) -> Option<Value> {
-        self.property_types.remove(key);
-        self.properties.remove(key)
+        let key = self.canonical_key(key);
+        if self.check_cycle(&key).is_err() {
+            return None;
+        }
+        let old = self.properties.remove(&key)?;
+        self.record(key, PropertyTarget::Global, Some(old.clone()));
+        Some(old)
     }
     
     /// Check if property exists
     pub fn has_property(&self, key: &str) -> bool {
-        self.properties.contains_key(key)
+        self.get_property(key).is_some()
     }
     
     /// Get all property keys
     pub fn property_keys(&self) -> Vec<&String> {
-        self.properties.keys().collect()
+        self.specs.keys().collect()
     }
     
     /// Get property type
-    pub fn property_type(&self, key: &str) -> Option<&String> {
-        self.property_types.get(key)
+    pub fn property_type(&self, key: &str) -> Option<PropertyType> {
+        self.spec(key).map(|spec| spec.kind)
     }
     
     /// Clear all properties
+    ///
+    /// Registrations are kept, so properties go back to their defaults.
     pub fn clear(&mut self) {
         self.properties.clear();
-        self.property_types.clear();
+        self.buffer_values.clear();
+        self.window_values.clear();
     }
     
     /// Get property count
     pub fn property_count(&self) -> usize {
-        self.properties.len()
+        self.property_keys().len()
     }
-}
\ No newline at end of file
+    
+    /// Take the changes made since they were last taken, oldest first
+    pub fn take_changes(&mut self) -> Vec<PropertyChange> {
+        std::mem::take(&mut self.changes)
+    }
+    
+    /// Call a function with the changes to a property, named by its name or an alias
+    pub fn watch(&mut self, key: &str, callback: impl FnMut(&[PropertyChange], &mut PropertySystem) + 'static) -> WatchId {
+        let key = self.canonical_key(key);
+        self.add_watcher(WatchPattern::Key(key), Box::new(callback))
+    }
+    
+    /// Call a function with the changes to every property whose name starts with a prefix, like `lsp.`
+    pub fn watch_prefix(&mut self, prefix: &str, callback: impl FnMut(&[PropertyChange], &mut PropertySystem) + 'static) -> WatchId {
+        self.add_watcher(WatchPattern::Prefix(prefix.to_string()), Box::new(callback))
+    }
+    
+    /// Stop calling a watcher, returning whether it was watching
+    pub fn unwatch(&mut self, id: WatchId) -> bool {
+        let count = self.watchers.len();
+        self.watchers.retain(|watcher| watcher.id != id);
+        self.watchers.len() != count
+    }
+    
+    /// Make several changes that watchers see together, once the function returns
+    ///
+    /// Each watcher is called once with the changes it watches, each
+    /// property's change going from its value before the batch to its value
+    /// after. If the function fails, the values are restored and nothing is
+    /// delivered. Batches inside batches are part of the outer one.
+    pub fn batch<T>(&mut self, changes: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.batched.is_some() {
+            return changes(self);
+        }
+        let snapshot: Snapshot = (self.properties.clone(), self.buffer_values.clone(), self.window_values.clone());
+        self.batched = Some(Vec::new());
+        let result = changes(self);
+        let batched = self.batched.take().unwrap_or_default();
+        
+        if result.is_err() {
+            (self.properties, self.buffer_values, self.window_values) = snapshot;
+            return result;
+        }
+        // One change per property and target, from the first old value to the last new one
+        let mut merged: Vec<PropertyChange> = Vec::new();
+        for change in batched {
+            match merged.iter_mut().find(|merged| merged.key == change.key && merged.target == change.target) {
+                Some(merged) => merged.new = change.new,
+                None => merged.push(change),
+            }
+        }
+        merged.retain(|change| change.old != change.new);
+        self.publish(merged);
+        result
+    }
+    
+    fn add_watcher(&mut self, pattern: WatchPattern, callback: WatchCallback) -> WatchId {
+        let id = WatchId(self.next_watch);
+        self.next_watch += 1;
+        self.watchers.push(Watcher { id, pattern, callback: Some(callback) });
+        id
+    }
+    
+    /// Refuse to change a property while its own change is being delivered
+    fn check_cycle(&self, key: &str) -> Result<()> {
+        if !self.notifying.iter().any(|notifying| notifying == key) {
+            return Ok(());
+        }
+        Err(RuskError::Config(format!("Property watchers go round in a cycle: {} -> {}", self.notifying.join(" -> "), key)))
+    }
+    
+    /// Record changes for `take_changes` and deliver them to the watchers, each called once
+    fn publish(&mut self, changes: Vec<PropertyChange>) {
+        if changes.is_empty() {
+            return;
+        }
+        let notifying = self.notifying.len();
+        for change in &changes {
+            if !self.notifying.contains(&change.key) {
+                self.notifying.push(change.key.clone());
+            }
+        }
+        self.changes.extend(changes.iter().cloned());
+        
+        let calls: Vec<(WatchId, Vec<PropertyChange>)> = self.watchers.iter()
+            .filter_map(|watcher| {
+                let watched: Vec<PropertyChange> = changes.iter().filter(|change| watcher.matches(&change.key)).cloned().collect();
+                (!watched.is_empty()).then_some((watcher.id, watched))
+            })
+            .collect();
+        for (id, watched) in calls {
+            let Some(mut callback) = self.watchers.iter_mut().find(|watcher| watcher.id == id).and_then(|watcher| watcher.callback.take()) else {
+                continue;
+            };
+            callback(&watched, self);
+            // The watcher may have been removed while it ran
+            if let Some(watcher) = self.watchers.iter_mut().find(|watcher| watcher.id == id) {
+                watcher.callback = Some(callback);
+            }
+        }
+        self.notifying.truncate(notifying);
+    }
+    
+    fn canonical_key(&self, key: &str) -> String {
+        self.aliases.get(key).map_or(key, String::as_str).to_string()
+    }
+    
+    /// Record a change if the value seen where it was made differs from before
+    fn record(&mut self, key: String, target: PropertyTarget, old: Option<Value>) {
+        let new = match target {
+            PropertyTarget::Global => self.get_property(&key),
+            target => self.local(&key, target).or_else(|| self.get_property(&key)),
+        }.cloned();
+        if old == new {
+            return;
+        }
+        let change = PropertyChange { key, target, old, new };
+        match &mut self.batched {
+            Some(batched) => batched.push(change),
+            None => self.publish(vec![change]),
+        }
+    }
+}
+
+impl Default for PropertySystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registered_properties_reject_invalid_values() {
+        let mut properties = PropertySystem::new();
+        assert!(properties.set_property("plugin.width", Value::from(3)).is_err());
+        properties.register(PropertySpec::new("plugin.width", PropertyType::Integer, PropertyScope::Global, 80).alias("pw").range(20, 200)).unwrap();
+        properties.register(PropertySpec::new("plugin.side", PropertyType::String, PropertyScope::Window, "left").choices(["left", "right"])).unwrap();
+        assert!(properties.register(PropertySpec::new("plugin.height", PropertyType::Integer, PropertyScope::Global, 10).alias("pw")).is_err());
+        assert!(properties.register(PropertySpec::new("plugin.depth", PropertyType::Integer, PropertyScope::Global, 0).range(1, 9)).is_err());
+
+        properties.set_property("pw", Value::from(120)).unwrap();
+        assert_eq!(properties.get_number("plugin.width"), Some(120.0));
+        for (key, value) in [("plugin.width", Value::from(10)), ("plugin.width", Value::from("wide")), ("plugin.side", Value::from("top"))] {
+            assert!(matches!(properties.set_property(key, value), Err(RuskError::Config(_))));
+        }
+        properties.set_local("plugin.side", PropertyTarget::Window, Value::from("right")).unwrap();
+        assert_eq!(properties.get_string("plugin.side"), Some("left"));
+        assert_eq!(properties.local("plugin.side", PropertyTarget::Window), Some(&Value::from("right")));
+        assert_eq!(properties.take_changes().len(), 2);
+    }
+    
+    #[test]
+    fn test_watchers_batches_and_cycles() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        
+        let mut properties = PropertySystem::new();
+        for name in ["ui.width", "ui.height", "ui.area"] {
+            properties.register(PropertySpec::new(name, PropertyType::Integer, PropertyScope::Global, 1)).unwrap();
+        }
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let seen = calls.clone();
+        properties.watch_prefix("ui.", move |changes, _| seen.borrow_mut().push(changes.len()));
+        // The area follows the width and height
+        properties.watch("ui.width", |changes, properties| {
+            let width = changes[0].new.as_ref().and_then(Value::as_i64).unwrap_or(0);
+            let height = properties.get_number("ui.height").unwrap_or(0.0) as i64;
+            properties.set_property("ui.area", Value::from(width * height)).unwrap();
+        });
+        
+        properties.batch(|properties| {
+            properties.set_property("ui.height", Value::from(3))?;
+            properties.set_property("ui.width", Value::from(2))?;
+            properties.set_property("ui.width", Value::from(4))
+        }).unwrap();
+        assert_eq!(properties.get_number("ui.area"), Some(12.0));
+        assert_eq!(*calls.borrow(), vec![2, 1]);
+        
+        // A failed batch changes nothing
+        assert!(properties.batch(|properties| properties.set_property("ui.width", Value::from(9)).and_then(|_| properties.set_property("ui.gone", Value::from(1)))).is_err());
+        assert_eq!(properties.get_number("ui.width"), Some(4.0));
+        
+        // The area setting the width again would go round forever
+        let cycle = Rc::new(RefCell::new(None));
+        let error = cycle.clone();
+        properties.watch("ui.area", move |_, properties| *error.borrow_mut() = properties.set_property("ui.width", Value::from(1)).err());
+        properties.set_property("ui.width", Value::from(5)).unwrap();
+        assert!(cycle.borrow().as_ref().is_some_and(|e| e.to_string().contains("ui.width -> ui.area -> ui.width")));
+        assert_eq!(properties.get_number("ui.width"), Some(5.0));
+    }
+}
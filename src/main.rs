@@ -1,6 +1,10 @@
 use clap::{Arg, Command, ArgAction};
-use rusk::{Editor, Result, Config, ConfigLoader};
+use rusk::{Editor, Result, RuskError, Config, ConfigLoader};
 use rusk::config::ThemeManager;
+use rusk::core::state::{options, PropertySystem};
+use rusk::plugins::PluginRegistry;
+use rusk::services::command_system::CommandSystem;
+use rusk::utils::keybindings::Keybindings;
 use std::path::Path;
 
 fn main() -> Result<()> {
@@ -43,7 +47,7 @@ fn main() -> Result<()> {
                         .about("Show current configuration")
                         .arg(
                             Arg::new("section")
-                                .help("Configuration section to show (editor, ui, keybindings, plugins)")
+                                .help("Configuration section to show (editor, ui, keybindings, plugins, options)")
                                 .value_name("SECTION")
                         )
                 )
@@ -259,6 +263,9 @@ fn handle_edit_command(matches: &clap::ArgMatches) -> Result<()> {
         editor.open_file(file_path)?;
         
         if readonly {
+            if let Some(buffer) = editor.current_buffer_mut() {
+                buffer.set_read_only(true);
+            }
             println!("Opened '{}' in read-only mode", file_path);
         } else {
             println!("Opened '{}' for editing", file_path);
@@ -289,14 +296,17 @@ fn handle_config_command(matches: &clap::ArgMatches) -> Result<()> {
                     "ui" => println!("{:#?}", config.ui),
                     "keybindings" => println!("{:#?}", config.keybindings),
                     "plugins" => println!("{:#?}", config.plugins),
+                    "options" => print_options(&config)?,
                     _ => {
                         eprintln!("Unknown section: {}", section);
-                        eprintln!("Available sections: editor, ui, keybindings, plugins");
+                        eprintln!("Available sections: editor, ui, keybindings, plugins, options");
                         std::process::exit(1);
                     }
                 }
             } else {
                 println!("{:#?}", config);
+                println!();
+                print_options(&config)?;
             }
         }
         Some(("edit", _)) => {
@@ -326,7 +336,7 @@ fn handle_config_command(matches: &clap::ArgMatches) -> Result<()> {
             match config_file {
                 Some(file) => {
                     if Path::new(file).exists() {
-                        match ConfigLoader::load_from_file(file) {
+                        match ConfigLoader::load_from_file(file).and_then(validate_keybindings) {
                             Ok(_) => println!("Configuration file '{}' is valid", file),
                             Err(e) => {
                                 eprintln!("Configuration file '{}' is invalid: {}", file, e);
@@ -339,7 +349,7 @@ fn handle_config_command(matches: &clap::ArgMatches) -> Result<()> {
                     }
                 }
                 None => {
-                    match ConfigLoader::load() {
+                    match ConfigLoader::load().and_then(validate_keybindings) {
                         Ok(_) => println!("Current configuration is valid"),
                         Err(e) => {
                             eprintln!("Current configuration is invalid: {}", e);
@@ -356,6 +366,31 @@ fn handle_config_command(matches: &clap::ArgMatches) -> Result<()> {
     Ok(())
 }
 
+/// List the options `:set` changes, with their type, scope, default and allowed values
+fn print_options(config: &Config) -> Result<()> {
+    let mut properties = PropertySystem::new();
+    options::register_editor_options(&mut properties, &config.editor)?;
+    
+    // Plugins add options of their own, as they do when the editor starts
+    let mut plugins = PluginRegistry::new();
+    plugins.load(config)?;
+    for spec in plugins.properties() {
+        properties.register(spec)?;
+    }
+    println!("Options:");
+    for line in options::help_lines(&properties) {
+        println!("  {}", line);
+    }
+    Ok(())
+}
+
+/// Check that every keybinding of a configuration parses and names a known command
+fn validate_keybindings(config: Config) -> Result<Config> {
+    CommandSystem::with_builtins().validate_keybindings(&config.keybindings)?;
+    Keybindings::from_config(&config.keybindings)?;
+    Ok(config)
+}
+
 fn handle_session_command(matches: &clap::ArgMatches) -> Result<()> {
     match matches.subcommand() {
         Some(("save", sub_matches)) => {
@@ -389,6 +424,7 @@ fn handle_session_command(matches: &clap::ArgMatches) -> Result<()> {
 }
 
 fn handle_plugin_command(matches: &clap::ArgMatches) -> Result<()> {
+    let registry = PluginRegistry::new();
     match matches.subcommand() {
         Some(("list", sub_matches)) => {
             let enabled_only = sub_matches.get_flag("enabled");
@@ -397,32 +433,63 @@ fn handle_plugin_command(matches: &clap::ArgMatches) -> Result<()> {
             if enabled_only {
                 println!("Enabled plugins:");
                 for plugin in &config.plugins.enabled_plugins {
-                    println!("  ✓ {}", plugin);
+                    match registry.factory(plugin) {
+                        Some(factory) => println!("  ✓ {:<8} {}", plugin, factory.description),
+                        None => println!("  ? {:<8} unknown plugin", plugin),
+                    }
                 }
             } else {
                 println!("Available plugins:");
-                let all_plugins = vec!["vim", "tui", "syntax", "file_io", "config"];
-                
-                for plugin in all_plugins {
-                    let status = if config.plugins.enabled_plugins.contains(&plugin.to_string()) {
+                for factory in registry.factories() {
+                    let status = if config.plugins.enabled_plugins.iter().any(|name| name == factory.name) {
                         "✓"
                     } else {
                         "✗"
                     };
-                    println!("  {} {}", status, plugin);
+                    println!("  {} {:<8} {}", status, factory.name, factory.description);
                 }
             }
+            
+            // Say what actually runs, and why not when the config cannot start
+            match registry.plan(&config) {
+                Ok(plan) => {
+                    println!();
+                    println!("Loaded at startup: {}", plan.plugins.join(", "));
+                    for notice in plan.notices {
+                        println!("  {}", notice);
+                    }
+                }
+                Err(e) => eprintln!("Warning: {}", e),
+            }
         }
         Some(("enable", sub_matches)) => {
             let plugin = sub_matches.get_one::<String>("plugin").unwrap();
-            println!("Enabling plugin '{}'...", plugin);
-            // TODO: Implement plugin enable functionality
-            println!("Plugin '{}' enabled", plugin);
+            if registry.factory(plugin).is_none() {
+                let known: Vec<&str> = registry.factories().iter().map(|factory| factory.name).collect();
+                return Err(RuskError::Plugin(format!("Unknown plugin '{}' (available: {})", plugin, known.join(", "))));
+            }
+            let mut config = ConfigLoader::load()?;
+            if config.plugins.enabled_plugins.contains(plugin) {
+                println!("Plugin '{}' is already enabled", plugin);
+            } else {
+                config.plugins.enabled_plugins.push(plugin.clone());
+                ConfigLoader::save(&config)?;
+                println!("Plugin '{}' enabled", plugin);
+            }
         }
         Some(("disable", sub_matches)) => {
             let plugin = sub_matches.get_one::<String>("plugin").unwrap();
-            println!("Disabling plugin '{}'...", plugin);
-            // TODO: Implement plugin disable functionality
+            let mut config = ConfigLoader::load()?;
+            if !config.plugins.enabled_plugins.contains(plugin) {
+                println!("Plugin '{}' is not enabled", plugin);
+                return Ok(());
+            }
+            config.plugins.enabled_plugins.retain(|name| name != plugin);
+            
+            // Refuse to leave the editor without a user interface or editing mode
+            registry.plan(&config)
+                .map_err(|e| RuskError::Plugin(format!("Cannot disable '{}': {}", plugin, e)))?;
+            ConfigLoader::save(&config)?;
             println!("Plugin '{}' disabled", plugin);
         }
         _ => {
@@ -468,14 +535,47 @@ fn handle_check_command(matches: &clap::ArgMatches) -> Result<()> {
     // Check plugins
     print!("Plugins: ");
     let config = ConfigLoader::load().unwrap_or_default();
-    println!("✓ {} enabled", config.plugins.enabled_plugins.len());
-    
-    if verbose {
-        for plugin in &config.plugins.enabled_plugins {
-            println!("  - {}", plugin);
+    match PluginRegistry::new().plan(&config) {
+        Ok(plan) => {
+            println!("✓ {} loaded", plan.plugins.len());
+            for notice in &plan.notices {
+                println!("  Warning: {}", notice);
+            }
+            if verbose {
+                for plugin in &plan.plugins {
+                    println!("  - {}", plugin);
+                }
+            }
+        }
+        Err(e) => {
+            println!("✗ Cannot start");
+            println!("  Error: {}", e);
         }
     }
-    
+
+    // Check keybindings
+    print!("Keybindings: ");
+    match Keybindings::from_config(&config.keybindings) {
+        Ok(keybindings) => {
+            let problems: Vec<String> = keybindings.unknown_commands(&CommandSystem::with_builtins())
+                .into_iter()
+                .chain(keybindings.conflicts().iter().cloned())
+                .collect();
+            if problems.is_empty() {
+                println!("✓ No conflicts");
+            } else {
+                println!("✗ {} problem(s)", problems.len());
+                for problem in &problems {
+                    println!("  - {}", problem);
+                }
+            }
+        }
+        Err(e) => {
+            println!("✗ Invalid");
+            println!("  Error: {}", e);
+        }
+    }
+
     println!();
     println!("Health check complete");
     
@@ -1,10 +0,0 @@
-/// Event system for plugin communication
-pub struct EventBus {
-    // TODO: Implement event bus for plugin communication
-}
-
-impl EventBus {
-    pub fn new() -> Self {
-        Self {}
-    }
-}
\ No newline at end of file
@@ -0,0 +1,587 @@
+/// Completion engine: gathers candidates from sources, ranks them and drives the popup menu
+use crate::core::buffer::Buffer;
+use crate::core::cursor::{Position, TextRange};
+use crate::plugins::implementations::editing::mode::EditingMode;
+use crate::services::command_system::EditorMode;
+use crate::plugins::implementations::language::lsp::LspManager;
+use crate::plugins::implementations::language::syntax::SupportedLanguage;
+use crate::plugins::implementations::snippets::Snippet;
+use crate::utils::fuzzy::fuzzy_match;
+use crate::{Config, Result, RuskError};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use super::sources::{BufferWordsSource, LspSource, PathSource};
+
+/// What a completion candidate is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Word,
+    Path,
+    Function,
+    Variable,
+    Field,
+    Type,
+    Module,
+    Keyword,
+    Snippet,
+    Other,
+}
+
+impl CompletionKind {
+    /// Short marker shown in the popup
+    pub fn marker(&self) -> &'static str {
+        match self {
+            Self::Word => "w",
+            Self::Path => "p",
+            Self::Function => "f",
+            Self::Variable => "v",
+            Self::Field => ".",
+            Self::Type => "t",
+            Self::Module => "m",
+            Self::Keyword => "k",
+            Self::Snippet => "s",
+            Self::Other => " ",
+        }
+    }
+
+    /// Map an LSP `CompletionItemKind`
+    pub fn from_lsp(kind: u64) -> Self {
+        match kind {
+            2..=4 => Self::Function,
+            5 | 10 => Self::Field,
+            6 | 12 | 21 => Self::Variable,
+            7 | 8 | 13 | 22 | 25 => Self::Type,
+            9 => Self::Module,
+            14 => Self::Keyword,
+            15 => Self::Snippet,
+            17 | 19 => Self::Path,
+            1 => Self::Word,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A completion candidate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionItem {
+    pub label: String,
+    pub insert_text: String,
+    pub kind: CompletionKind,
+    pub detail: Option<String>,
+    pub documentation: Option<String>,
+    /// Name of the source that produced the item
+    pub source: String,
+    /// Column on the cursor line where the replaced text starts
+    pub start_column: usize,
+    /// Whether `insert_text` uses snippet syntax
+    pub is_snippet: bool,
+}
+
+impl CompletionItem {
+    /// Create a plain item that inserts its label
+    pub fn new(label: impl Into<String>, kind: CompletionKind, source: &str, start_column: usize) -> Self {
+        let label = label.into();
+        Self {
+            insert_text: label.clone(),
+            label,
+            kind,
+            detail: None,
+            documentation: None,
+            source: source.to_string(),
+            start_column,
+            is_snippet: false,
+        }
+    }
+}
+
+/// What sources see when asked for candidates
+pub struct CompletionContext<'a> {
+    pub buffer: &'a Buffer,
+    pub cursor: Position,
+    /// Trigger character that opened completion, if any
+    pub trigger: Option<char>,
+    pub lsp: Option<&'a mut LspManager>,
+}
+
+impl CompletionContext<'_> {
+    /// Text of the cursor line before the cursor
+    pub fn line_before_cursor(&self) -> &str {
+        let line = self.buffer.get_line(self.cursor.line).unwrap_or("");
+        &line[..self.cursor.column.min(line.len())]
+    }
+
+    /// Column where the identifier ending at the cursor starts
+    pub fn word_start(&self) -> usize {
+        word_start(self.line_before_cursor())
+    }
+}
+
+/// Column where the identifier ending a piece of text starts
+pub fn word_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .find(|(_, ch)| !(ch.is_alphanumeric() || *ch == '_'))
+        .map_or(0, |(i, ch)| i + ch.len_utf8())
+}
+
+/// A provider of completion candidates
+pub trait CompletionSource {
+    /// Name shown next to candidates
+    fn name(&self) -> &str;
+
+    /// Bonus added to the fuzzy score of this source's candidates
+    fn priority(&self) -> i64 {
+        0
+    }
+
+    /// Produce candidates for the cursor position
+    fn complete(&mut self, ctx: &mut CompletionContext) -> Vec<CompletionItem>;
+
+    /// Take candidates that arrived after `complete` returned, such as a language server's answer
+    fn poll(&mut self, _buffer: &Buffer) -> Option<Vec<CompletionItem>> {
+        None
+    }
+
+    /// Learn from the open buffers, forgetting closed ones; sources that don't index buffers ignore this
+    fn index_buffers(&mut self, _buffers: &[Buffer]) {}
+}
+
+/// When completion opens by itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionSettings {
+    /// Open the popup while typing, not only on Ctrl-n/Ctrl-p
+    pub auto: bool,
+    /// Identifier length that opens the popup while typing
+    pub min_prefix: usize,
+    /// Character sequences that open the popup, per language
+    pub triggers: HashMap<SupportedLanguage, Vec<String>>,
+    /// Most candidates kept in the popup
+    pub max_items: usize,
+}
+
+impl Default for CompletionSettings {
+    fn default() -> Self {
+        Self {
+            auto: true,
+            min_prefix: 2,
+            triggers: HashMap::from([
+                (SupportedLanguage::Rust, vec![".".to_string(), "::".to_string()]),
+                (SupportedLanguage::Python, vec![".".to_string()]),
+            ]),
+            max_items: 50,
+        }
+    }
+}
+
+impl CompletionSettings {
+    /// Read settings from `[plugins.plugin_configs.completion]`, on top of the defaults
+    ///
+    /// ```toml
+    /// [plugins.plugin_configs.completion]
+    /// auto = true
+    /// min_prefix = 2
+    ///
+    /// [plugins.plugin_configs.completion.triggers]
+    /// rust = [".", "::"]
+    /// ```
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut settings = Self::default();
+        let Some(table) = config.plugins.plugin_configs.get("completion").and_then(|value| value.as_table()) else {
+            return Ok(settings);
+        };
+
+        if let Some(auto) = table.get("auto").and_then(|value| value.as_bool()) {
+            settings.auto = auto;
+        }
+        if let Some(min_prefix) = table.get("min_prefix").and_then(|value| value.as_integer()) {
+            settings.min_prefix = min_prefix.max(1) as usize;
+        }
+        if let Some(max_items) = table.get("max_items").and_then(|value| value.as_integer()) {
+            settings.max_items = max_items.max(1) as usize;
+        }
+
+        if let Some(triggers) = table.get("triggers").and_then(|value| value.as_table()) {
+            for (language_id, sequences) in triggers {
+                let language = SupportedLanguage::from_language_id(language_id)
+                    .ok_or_else(|| RuskError::Config(format!("Unknown language '{}' in completion triggers", language_id)))?;
+                let sequences = sequences.as_array()
+                    .ok_or_else(|| RuskError::Config(format!("Completion triggers for '{}' must be a list", language_id)))?
+                    .iter()
+                    .filter_map(|sequence| sequence.as_str().map(str::to_string))
+                    .collect();
+                settings.triggers.insert(language, sequences);
+            }
+        }
+
+        Ok(settings)
+    }
+
+    /// Get the trigger sequence ending a line prefix, if any
+    fn trigger_at(&self, language: &SupportedLanguage, before_cursor: &str) -> Option<&str> {
+        self.triggers.get(language)?
+            .iter()
+            .find(|sequence| !sequence.is_empty() && before_cursor.ends_with(sequence.as_str()))
+            .map(String::as_str)
+    }
+}
+
+/// A ranked candidate in the popup
+#[derive(Debug, Clone)]
+pub struct ScoredCompletion {
+    pub item: CompletionItem,
+    pub score: i64,
+    /// Char indices of the label that matched the typed text
+    pub matched: Vec<usize>,
+}
+
+/// The open completion popup
+#[derive(Debug, Clone)]
+pub struct CompletionMenu {
+    candidates: Vec<(CompletionItem, i64)>,
+    items: Vec<ScoredCompletion>,
+    selected: usize,
+    line: usize,
+    anchor: usize,
+}
+
+impl CompletionMenu {
+    /// Get the ranked candidates matching the typed text
+    pub fn items(&self) -> &[ScoredCompletion] {
+        &self.items
+    }
+
+    /// Get the index of the selected candidate
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Get the selected candidate
+    pub fn selected_item(&self) -> Option<&CompletionItem> {
+        self.items.get(self.selected).map(|scored| &scored.item)
+    }
+
+    /// Get the line the popup belongs to
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Get the leftmost column replaced by any candidate, where the popup is drawn
+    pub fn anchor_column(&self) -> usize {
+        self.anchor
+    }
+
+    /// Rank candidates against the text typed since each one's start column
+    fn filter(&mut self, buffer: &Buffer, cursor: Position, max_items: usize) {
+        let line = buffer.get_line(cursor.line).unwrap_or("");
+        let selected_label = self.selected_item().map(|item| item.label.clone());
+
+        let mut items: Vec<ScoredCompletion> = self.candidates.iter()
+            .filter_map(|(item, priority)| {
+                let typed = line.get(item.start_column..cursor.column.min(line.len()))?;
+                // A word that is already typed out in full is no suggestion
+                if item.kind == CompletionKind::Word && item.label == typed {
+                    return None;
+                }
+                let found = fuzzy_match(typed, &item.label)?;
+                Some(ScoredCompletion { item: item.clone(), score: found.score + priority, matched: found.indices })
+            })
+            .collect();
+
+        items.sort_by(|a, b| {
+            b.score.cmp(&a.score)
+                .then(a.item.label.len().cmp(&b.item.label.len()))
+                .then(a.item.label.cmp(&b.item.label))
+        });
+        items.truncate(max_items);
+
+        // Keep the selection on the same candidate while it still matches
+        self.selected = selected_label
+            .and_then(|label| items.iter().position(|scored| scored.item.label == label))
+            .unwrap_or(0);
+        self.anchor = items.iter().map(|scored| scored.item.start_column).min().unwrap_or(cursor.column);
+        self.items = items;
+    }
+}
+
+/// Insert-mode completion with pluggable sources
+pub struct CompletionEngine {
+    sources: Vec<Box<dyn CompletionSource>>,
+    settings: CompletionSettings,
+    menu: Option<CompletionMenu>,
+    last_version: u64,
+    /// Whether late candidates may still open the popup, until it is closed
+    waiting: bool,
+}
+
+impl CompletionEngine {
+    /// Create an engine without sources
+    pub fn new(settings: CompletionSettings) -> Self {
+        Self {
+            sources: Vec::new(),
+            settings,
+            menu: None,
+            last_version: 0,
+            waiting: false,
+        }
+    }
+
+    /// Create an engine with the buffer word, path and LSP sources
+    pub fn with_default_sources(settings: CompletionSettings) -> Self {
+        let mut engine = Self::new(settings);
+        engine.add_source(Box::new(LspSource::new()));
+        engine.add_source(Box::new(PathSource::new()));
+        engine.add_source(Box::new(BufferWordsSource::new()));
+        engine
+    }
+
+    /// Add a completion source, replacing any source with the same name
+    pub fn add_source(&mut self, source: Box<dyn CompletionSource>) {
+        self.sources.retain(|existing| existing.name() != source.name());
+        self.sources.push(source);
+    }
+
+    /// Get the names of the registered sources
+    pub fn source_names(&self) -> Vec<&str> {
+        self.sources.iter().map(|source| source.name()).collect()
+    }
+
+    /// Get the completion settings
+    pub fn settings(&self) -> &CompletionSettings {
+        &self.settings
+    }
+
+    /// Replace the completion settings
+    pub fn set_settings(&mut self, settings: CompletionSettings) {
+        self.settings = settings;
+    }
+
+    /// Let sources index every open buffer
+    pub fn index_buffers(&mut self, buffers: &[Buffer]) {
+        for source in &mut self.sources {
+            source.index_buffers(buffers);
+        }
+    }
+
+    /// Get the open popup
+    pub fn menu(&self) -> Option<&CompletionMenu> {
+        self.menu.as_ref()
+    }
+
+    /// Check if the popup is open
+    pub fn is_active(&self) -> bool {
+        self.menu.is_some()
+    }
+
+    /// Ask every source for candidates and open the popup, returning whether anything matched
+    ///
+    /// Sources that answer later, like language servers, add their
+    /// candidates when `poll` finds them.
+    pub fn trigger(&mut self, buffer: &Buffer, lsp: Option<&mut LspManager>, trigger: Option<char>) -> bool {
+        let (line, column) = buffer.cursor_position();
+        let cursor = Position::new(line, column);
+        let mut ctx = CompletionContext { buffer, cursor, trigger, lsp };
+
+        let mut candidates = Vec::new();
+        for source in &mut self.sources {
+            let priority = source.priority();
+            candidates.extend(source.complete(&mut ctx).into_iter().map(|item| (item, priority)));
+        }
+
+        let mut menu = CompletionMenu { candidates, items: Vec::new(), selected: 0, line, anchor: column };
+        menu.filter(buffer, cursor, self.settings.max_items);
+        self.menu = (!menu.items.is_empty()).then_some(menu);
+        self.last_version = buffer.version();
+        self.waiting = true;
+        self.menu.is_some()
+    }
+
+    /// Add the candidates sources produced late, returning whether the popup changed
+    ///
+    /// They open the popup if it closed for lack of matches, but not once
+    /// it was dismissed or insert mode ended, both of which cancel it.
+    pub fn poll(&mut self, buffer: &Buffer) -> bool {
+        let mut late = Vec::new();
+        for source in &mut self.sources {
+            let priority = source.priority();
+            if let Some(items) = source.poll(buffer) {
+                late.extend(items.into_iter().map(|item| (item, priority)));
+            }
+        }
+        if late.is_empty() || !self.waiting {
+            return false;
+        }
+
+        let (line, column) = buffer.cursor_position();
+        if self.menu.as_ref().is_none_or(|menu| menu.line != line) {
+            self.menu = Some(CompletionMenu { candidates: Vec::new(), items: Vec::new(), selected: 0, line, anchor: column });
+        }
+        let Some(menu) = &mut self.menu else {
+            return false;
+        };
+        menu.candidates.extend(late);
+        menu.filter(buffer, Position::new(line, column), self.settings.max_items);
+        if menu.items.is_empty() {
+            self.menu = None;
+        }
+        true
+    }
+
+    /// Open the popup with fixed options replacing the text from a column to the cursor, such as a snippet choice
+    pub fn show_choices(&mut self, buffer: &Buffer, start_column: usize, choices: &[String]) {
+        let (line, column) = buffer.cursor_position();
+        let candidates: Vec<(CompletionItem, i64)> = choices.iter()
+            .map(|choice| (CompletionItem::new(choice.clone(), CompletionKind::Other, "choice", start_column), 0))
+            .collect();
+        // Every option is listed, whatever text the placeholder shows
+        let items = candidates.iter()
+            .map(|(item, _)| ScoredCompletion { item: item.clone(), score: 0, matched: Vec::new() })
+            .collect();
+
+        self.menu = Some(CompletionMenu { candidates, items, selected: 0, line, anchor: start_column.min(column) });
+        self.last_version = buffer.version();
+        self.waiting = false;
+    }
+
+    /// Re-rank the open popup after the cursor moved or text was typed
+    pub fn refresh(&mut self, buffer: &Buffer) {
+        let Some(menu) = &mut self.menu else { return };
+        let (line, column) = buffer.cursor_position();
+
+        let left_word = line != menu.line
+            || column < menu.candidates.iter().map(|(item, _)| item.start_column).min().unwrap_or(column);
+        if left_word {
+            self.cancel();
+            return;
+        }
+
+        menu.filter(buffer, Position::new(line, column), self.settings.max_items);
+        if menu.items.is_empty() {
+            self.menu = None;
+        }
+    }
+
+    /// React to an edit: refresh the popup, or open it on a trigger sequence or a long enough word
+    pub fn after_edit(&mut self, buffer: &Buffer, lsp: Option<&mut LspManager>) {
+        let edited = buffer.version() != self.last_version;
+        self.last_version = buffer.version();
+
+        let (line, column) = buffer.cursor_position();
+        let text = buffer.get_line(line).unwrap_or("");
+        let before_cursor = &text[..column.min(text.len())];
+
+        // A trigger sequence asks the sources again, since the context changed
+        if edited && let Some(sequence) = self.settings.trigger_at(buffer.language(), before_cursor) {
+            let trigger = sequence.chars().last();
+            if !self.trigger(buffer, lsp, trigger) {
+                self.menu = None;
+            }
+            return;
+        }
+
+        if self.menu.is_some() {
+            self.refresh(buffer);
+            return;
+        }
+
+        let typed = before_cursor.len() - word_start(before_cursor);
+        if edited && self.settings.auto && typed >= self.settings.min_prefix {
+            self.trigger(buffer, lsp, None);
+        }
+    }
+
+    /// Move the selection down, wrapping around
+    pub fn select_next(&mut self) {
+        if let Some(menu) = &mut self.menu {
+            menu.selected = (menu.selected + 1) % menu.items.len().max(1);
+        }
+    }
+
+    /// Move the selection up, wrapping around
+    pub fn select_previous(&mut self) {
+        if let Some(menu) = &mut self.menu {
+            menu.selected = menu.selected.checked_sub(1).unwrap_or(menu.items.len().saturating_sub(1));
+        }
+    }
+
+    /// Close the popup, dropping candidates still on their way
+    pub fn cancel(&mut self) {
+        self.menu = None;
+        self.waiting = false;
+    }
+
+    /// Take the selected candidate and close the popup, leaving the buffer untouched
+    pub fn take_selected(&mut self) -> Option<CompletionItem> {
+        self.waiting = false;
+        self.menu.take()?.selected_item().cloned()
+    }
+
+    /// Replace the typed text with the selected candidate, returning whether one was inserted
+    pub fn accept(&mut self, buffer: &mut Buffer) -> bool {
+        let Some(item) = self.take_selected() else {
+            return false;
+        };
+
+        let text = if item.is_snippet { Snippet::parse(&item.insert_text).plain_text() } else { item.insert_text.clone() };
+        let (line, column) = buffer.cursor_position();
+        let range = TextRange::new(Position::new(line, item.start_column), Position::new(line, column));
+        let end = buffer.replace_range(&range, &text);
+        buffer.set_cursor_position(end.line, end.column);
+        self.last_version = buffer.version();
+        true
+    }
+
+    /// Handle an insert-mode key, returning whether it was consumed
+    ///
+    /// Ctrl-n/Ctrl-p open the popup or move the selection, Tab accepts,
+    /// and Esc or Enter close it before the editing mode sees them. Modes
+    /// with their own key maps, like emacs, keep all of these keys.
+    pub fn handle_key(&mut self, key: KeyEvent, buffer: &mut Buffer, editing: &dyn EditingMode, lsp: Option<&mut LspManager>) -> bool {
+        if editing.editor_mode() != EditorMode::Insert || !editing.uses_keybindings() {
+            self.cancel();
+            return false;
+        }
+
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match (key.code, self.is_active()) {
+            (KeyCode::Char('n'), _) | (KeyCode::Char(' '), false) if ctrl => {
+                if self.is_active() {
+                    self.select_next();
+                } else {
+                    self.trigger(buffer, lsp, None);
+                }
+                true
+            }
+            (KeyCode::Char('p'), _) if ctrl => {
+                // Opening with Ctrl-p starts from the last candidate
+                if self.is_active() || self.trigger(buffer, lsp, None) {
+                    self.select_previous();
+                }
+                true
+            }
+            (KeyCode::Down, true) => {
+                self.select_next();
+                true
+            }
+            (KeyCode::Up, true) => {
+                self.select_previous();
+                true
+            }
+            (KeyCode::Tab, true) => self.accept(buffer),
+            (KeyCode::Esc | KeyCode::Enter, true) => {
+                self.cancel();
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Follow up a key that the editing mode handled
+    pub fn after_key(&mut self, buffer: &Buffer, editing: &dyn EditingMode, lsp: Option<&mut LspManager>) {
+        if editing.editor_mode() == EditorMode::Insert && editing.uses_keybindings() {
+            self.after_edit(buffer, lsp);
+        } else {
+            self.cancel();
+            self.last_version = buffer.version();
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! Insert-mode completion
+//!
+//! Candidates come from pluggable `CompletionSource`s (words of open
+//! buffers, file paths, snippets, the language server), are ranked with fuzzy
+//! matching and shown in a popup. Ctrl-n/Ctrl-p open the popup and move
+//! the selection and Tab accepts; typing a configured trigger sequence or a
+//! long enough word opens it automatically.
+
+pub mod engine;
+pub mod sources;
+
+pub use engine::{
+    CompletionContext, CompletionEngine, CompletionItem, CompletionKind, CompletionMenu, CompletionSettings,
+    CompletionSource, ScoredCompletion,
+};
+pub use sources::{BufferWordsSource, LspSource, PathSource, SnippetSource};
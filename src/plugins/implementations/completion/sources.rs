@@ -0,0 +1,302 @@
+/// Built-in completion sources: buffer words, file paths, snippets and language servers
+use crate::core::buffer::Buffer;
+use crate::core::cursor::Position;
+use crate::plugins::implementations::language::lsp::protocol::{from_lsp_position, LspCompletionItem};
+use crate::plugins::implementations::language::lsp::LspRequest;
+use crate::plugins::implementations::snippets::{Snippet, SnippetLibrary};
+use super::engine::{CompletionContext, CompletionItem, CompletionKind, CompletionSource};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Shortest word offered by the buffer words source
+const MIN_WORD_LENGTH: usize = 3;
+/// Most directory entries listed by the path source
+const MAX_PATH_ENTRIES: usize = 200;
+
+/// Name under which a buffer is indexed
+fn buffer_key(buffer: &Buffer) -> String {
+    buffer.file_path().unwrap_or("[No Name]").to_string()
+}
+
+/// Collect the identifiers of some lines
+fn collect_words<'a>(lines: impl IntoIterator<Item = &'a String>, words: &mut BTreeSet<String>) {
+    for line in lines {
+        for word in line.split(|ch: char| !(ch.is_alphanumeric() || ch == '_')) {
+            if word.chars().count() >= MIN_WORD_LENGTH && !word.starts_with(|ch: char| ch.is_ascii_digit()) {
+                words.insert(word.to_string());
+            }
+        }
+    }
+}
+
+/// Words from the current buffer and every other open buffer
+#[derive(Default)]
+pub struct BufferWordsSource {
+    index: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl BufferWordsSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CompletionSource for BufferWordsSource {
+    fn name(&self) -> &str {
+        "buffer"
+    }
+
+    fn complete(&mut self, ctx: &mut CompletionContext) -> Vec<CompletionItem> {
+        let start = ctx.word_start();
+        let current = buffer_key(ctx.buffer);
+
+        // The current buffer is read fresh, other buffers come from the index
+        let mut words = BTreeSet::new();
+        collect_words(ctx.buffer.lines(), &mut words);
+        self.index.insert(current.clone(), words.clone());
+
+        let mut seen: HashSet<&str> = words.iter().map(String::as_str).collect();
+        let mut items: Vec<CompletionItem> = words.iter()
+            .map(|word| {
+                let mut item = CompletionItem::new(word.clone(), CompletionKind::Word, self.name(), start);
+                item.detail = Some("this buffer".to_string());
+                item
+            })
+            .collect();
+
+        for (key, words) in &self.index {
+            if *key == current {
+                continue;
+            }
+            let file_name = Path::new(key).file_name().map_or(key.clone(), |name| name.to_string_lossy().to_string());
+            for word in words {
+                if !seen.insert(word) {
+                    continue;
+                }
+                let mut item = CompletionItem::new(word.clone(), CompletionKind::Word, self.name(), start);
+                item.detail = Some(file_name.clone());
+                items.push(item);
+            }
+        }
+
+        items
+    }
+
+    fn index_buffers(&mut self, buffers: &[Buffer]) {
+        // Rebuilt from scratch, so closed buffers stop offering their words
+        self.index = buffers.iter()
+            .map(|buffer| {
+                let mut words = BTreeSet::new();
+                collect_words(buffer.lines(), &mut words);
+                (buffer_key(buffer), words)
+            })
+            .collect();
+    }
+}
+
+/// File and directory names, for text that looks like a path
+#[derive(Default)]
+pub struct PathSource;
+
+impl PathSource {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Get the path being typed before the cursor, if the text looks like one
+    fn typed_path(before_cursor: &str) -> Option<&str> {
+        let start = before_cursor
+            .rfind(|ch: char| ch.is_whitespace() || matches!(ch, '"' | '\'' | '(' | '<' | '=' | ','))
+            .map_or(0, |i| i + 1);
+        let typed = &before_cursor[start..];
+        typed.contains('/').then_some(typed)
+    }
+
+    /// Resolve the directory part of a typed path
+    fn resolve_dir(buffer: &Buffer, dir: &str) -> PathBuf {
+        if let Some(rest) = dir.strip_prefix("~/")
+            && let Some(home) = dirs::home_dir()
+        {
+            return home.join(rest);
+        }
+
+        let dir = Path::new(if dir.is_empty() { "." } else { dir });
+        if dir.is_absolute() {
+            return dir.to_path_buf();
+        }
+
+        // Relative paths are relative to the buffer's file, like most editors do
+        let base = buffer.file_path()
+            .and_then(|path| Path::new(path).parent().map(Path::to_path_buf))
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("."));
+        base.join(dir)
+    }
+}
+
+impl CompletionSource for PathSource {
+    fn name(&self) -> &str {
+        "path"
+    }
+
+    fn priority(&self) -> i64 {
+        2
+    }
+
+    fn complete(&mut self, ctx: &mut CompletionContext) -> Vec<CompletionItem> {
+        let Some(typed) = Self::typed_path(ctx.line_before_cursor()) else {
+            return Vec::new();
+        };
+
+        let split = typed.rfind('/').map_or(0, |i| i + 1);
+        let (dir, file_part) = typed.split_at(split);
+        let start = ctx.cursor.column - file_part.len();
+
+        let Ok(entries) = std::fs::read_dir(Self::resolve_dir(ctx.buffer, dir)) else {
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                // Hidden entries only when asked for
+                if name.starts_with('.') && !file_part.starts_with('.') {
+                    return None;
+                }
+                let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+                let label = if is_dir { format!("{}/", name) } else { name };
+                let mut item = CompletionItem::new(label, CompletionKind::Path, self.name(), start);
+                item.detail = Some(if is_dir { "directory" } else { "file" }.to_string());
+                Some(item)
+            })
+            .take(MAX_PATH_ENTRIES)
+            .collect()
+    }
+}
+
+/// Snippets of the buffer's language, listed by prefix
+#[derive(Default)]
+pub struct SnippetSource {
+    library: SnippetLibrary,
+}
+
+impl SnippetSource {
+    pub fn new(library: SnippetLibrary) -> Self {
+        Self { library }
+    }
+}
+
+impl CompletionSource for SnippetSource {
+    fn name(&self) -> &str {
+        "snippet"
+    }
+
+    fn priority(&self) -> i64 {
+        3
+    }
+
+    fn complete(&mut self, ctx: &mut CompletionContext) -> Vec<CompletionItem> {
+        let start = ctx.word_start();
+        self.library.for_language(ctx.buffer.language())
+            .flat_map(|definition| definition.prefixes.iter().map(move |prefix| (prefix, definition)))
+            .map(|(prefix, definition)| {
+                let mut item = CompletionItem::new(prefix.clone(), CompletionKind::Snippet, self.name(), start);
+                item.insert_text = definition.body.clone();
+                item.is_snippet = true;
+                item.detail = Some(definition.description.clone().unwrap_or_else(|| definition.name.clone()));
+                item.documentation = Some(Snippet::parse(&definition.body).plain_text());
+                item
+            })
+            .collect()
+    }
+}
+
+/// A completion request waiting for the language server
+struct PendingCompletion {
+    request: LspRequest<Vec<LspCompletionItem>>,
+    path: String,
+    line: usize,
+    word_start: usize,
+}
+
+/// Candidates from the buffer's language server
+///
+/// The server answers while the user keeps typing: `complete` only sends
+/// the request, and the items come in through `poll`.
+#[derive(Default)]
+pub struct LspSource {
+    pending: Option<PendingCompletion>,
+}
+
+impl LspSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CompletionSource for LspSource {
+    fn name(&self) -> &str {
+        "lsp"
+    }
+
+    fn priority(&self) -> i64 {
+        4
+    }
+
+    fn complete(&mut self, ctx: &mut CompletionContext) -> Vec<CompletionItem> {
+        let (buffer, cursor, trigger) = (ctx.buffer, ctx.cursor, ctx.trigger);
+        let word_start = ctx.word_start();
+        let Some(manager) = ctx.lsp.as_deref_mut() else {
+            return Vec::new();
+        };
+        let Some(path) = buffer.file_path().filter(|_| manager.has_server(buffer.language())) else {
+            return Vec::new();
+        };
+
+        // A request already out for this word also answers for the longer text typed since
+        let asked = self.pending.as_ref()
+            .is_some_and(|pending| pending.path == path && pending.line == cursor.line && pending.word_start == word_start);
+        if trigger.is_some() || !asked {
+            self.pending = manager.completion(buffer, trigger).ok().map(|request| PendingCompletion {
+                request,
+                path: path.to_string(),
+                line: cursor.line,
+                word_start,
+            });
+        }
+        Vec::new()
+    }
+
+    fn poll(&mut self, buffer: &Buffer) -> Option<Vec<CompletionItem>> {
+        let result = self.pending.as_mut()?.request.poll()?;
+        let pending = self.pending.take()?;
+        let (line, column) = buffer.cursor_position();
+        let cursor = Position::new(line, column);
+        // The answer is for a line the cursor has left
+        if buffer.file_path() != Some(pending.path.as_str()) || line != pending.line {
+            return Some(Vec::new());
+        }
+
+        let items = result.unwrap_or_default().into_iter()
+            .map(|lsp_item| {
+                let (text, range) = lsp_item.edit();
+                // Use the server's replacement range when it lies on the cursor line
+                let start = range
+                    .map(|range| from_lsp_position(buffer.lines(), range.start))
+                    .filter(|start| start.line == cursor.line && start.column <= cursor.column)
+                    .map_or(pending.word_start, |start| start.column);
+
+                let kind = lsp_item.kind.map_or(CompletionKind::Other, CompletionKind::from_lsp);
+                let mut item = CompletionItem::new(lsp_item.label.clone(), kind, self.name(), start);
+                item.insert_text = text;
+                item.is_snippet = lsp_item.is_snippet();
+                item.detail = lsp_item.detail.clone();
+                item.documentation = lsp_item.documentation_text();
+                item
+            })
+            .collect();
+        Some(items)
+    }
+}
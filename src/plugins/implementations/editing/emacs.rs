@@ -0,0 +1,1262 @@
+//! Emacs editing mode
+//!
+//! Modeless editing with emacs keys: a kill ring where consecutive kills
+//! join and `M-y` cycles yanks, the mark and region, the `C-x` and `M-g`
+//! prefix maps, incremental search, `M-x` running any command of the
+//! command system and the universal argument `C-u`.
+
+use crate::core::buffer::Buffer;
+use crate::core::cursor::{Position, TextRange};
+use crate::plugins::implementations::editing::mode::{self, CursorShape, EditingMode};
+use crate::plugins::Plugin;
+use crate::services::command_system::EditorMode;
+use crate::utils::keybindings::KeyPress;
+use crate::{Config, Result, RuskError};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::style::Color;
+use std::collections::VecDeque;
+
+/// Number of kills remembered unless `kill_ring_max` is configured
+const KILL_RING_LIMIT: usize = 60;
+/// Lines moved by `C-v` and `M-v`
+const PAGE_LINES: i64 = 20;
+
+/// Killed text, most recent first
+#[derive(Debug, Clone)]
+pub struct KillRing {
+    entries: VecDeque<String>,
+    /// Entry that `yank` inserts, moved by `M-y`
+    index: usize,
+    limit: usize,
+}
+
+impl Default for KillRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KillRing {
+    /// Create an empty kill ring
+    pub fn new() -> Self {
+        Self { entries: VecDeque::new(), index: 0, limit: KILL_RING_LIMIT }
+    }
+
+    /// Change how many kills are remembered, forgetting the oldest ones beyond it
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.max(1);
+        self.entries.truncate(self.limit);
+        self.index = self.index.min(self.entries.len().saturating_sub(1));
+    }
+
+    /// Add a new kill
+    pub fn push(&mut self, text: String) {
+        self.entries.push_front(text);
+        self.entries.truncate(self.limit);
+        self.index = 0;
+    }
+
+    /// Join text to the most recent kill, after it for forward kills and before it for backward ones
+    pub fn join(&mut self, text: &str, backward: bool) {
+        match self.entries.front_mut() {
+            Some(last) if backward => last.insert_str(0, text),
+            Some(last) => last.push_str(text),
+            None => self.push(text.to_string()),
+        }
+        self.index = 0;
+    }
+
+    /// Get the entry `yank` inserts
+    pub fn current(&self) -> Option<&str> {
+        self.entries.get(self.index).map(String::as_str)
+    }
+
+    /// Move to an older entry, wrapping around, and return it
+    pub fn rotate(&mut self, by: i64) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.index = (self.index as i64 + by).rem_euclid(self.entries.len() as i64) as usize;
+        self.current()
+    }
+
+    /// Get every kill, most recent first
+    pub fn entries(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+}
+
+/// Commands implemented by the emacs mode itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    ForwardChar,
+    BackwardChar,
+    NextLine,
+    PreviousLine,
+    BeginningOfLine,
+    EndOfLine,
+    ForwardWord,
+    BackwardWord,
+    BeginningOfBuffer,
+    EndOfBuffer,
+    ScrollUp,
+    ScrollDown,
+    DeleteChar,
+    DeleteBackwardChar,
+    KillLine,
+    KillWholeLine,
+    KillWord,
+    BackwardKillWord,
+    KillRegion,
+    CopyRegion,
+    Yank,
+    YankPop,
+    SetMark,
+    ExchangePointAndMark,
+    MarkWholeBuffer,
+    IsearchForward,
+    IsearchBackward,
+    ExecuteExtended,
+    WriteFile,
+    GotoLine,
+    Undo,
+    Newline,
+    OpenLine,
+    Indent,
+    KeyboardQuit,
+    UniversalArgument,
+}
+
+/// Emacs command names, with the command system names the same behaviour has in other modes
+const COMMANDS: [(Command, &str, Option<&str>); 36] = [
+    (Command::ForwardChar, "forward-char", Some("move_right")),
+    (Command::BackwardChar, "backward-char", Some("move_left")),
+    (Command::NextLine, "next-line", Some("move_down")),
+    (Command::PreviousLine, "previous-line", Some("move_up")),
+    (Command::BeginningOfLine, "move-beginning-of-line", Some("move_line_start")),
+    (Command::EndOfLine, "move-end-of-line", Some("move_line_end")),
+    (Command::ForwardWord, "forward-word", Some("move_word_forward")),
+    (Command::BackwardWord, "backward-word", Some("move_word_backward")),
+    (Command::BeginningOfBuffer, "beginning-of-buffer", Some("move_file_start")),
+    (Command::EndOfBuffer, "end-of-buffer", Some("move_file_end")),
+    (Command::ScrollUp, "scroll-up-command", None),
+    (Command::ScrollDown, "scroll-down-command", None),
+    (Command::DeleteChar, "delete-char", Some("delete_char")),
+    (Command::DeleteBackwardChar, "delete-backward-char", None),
+    (Command::KillLine, "kill-line", None),
+    (Command::KillWholeLine, "kill-whole-line", Some("delete_line")),
+    (Command::KillWord, "kill-word", None),
+    (Command::BackwardKillWord, "backward-kill-word", None),
+    (Command::KillRegion, "kill-region", Some("delete_selection")),
+    (Command::CopyRegion, "kill-ring-save", Some("yank_selection")),
+    (Command::Yank, "yank", Some("paste")),
+    (Command::YankPop, "yank-pop", None),
+    (Command::SetMark, "set-mark-command", None),
+    (Command::ExchangePointAndMark, "exchange-point-and-mark", None),
+    (Command::MarkWholeBuffer, "mark-whole-buffer", None),
+    (Command::IsearchForward, "isearch-forward", None),
+    (Command::IsearchBackward, "isearch-backward", None),
+    (Command::ExecuteExtended, "execute-extended-command", None),
+    (Command::WriteFile, "write-file", None),
+    (Command::GotoLine, "goto-line", None),
+    (Command::Undo, "undo", Some("undo")),
+    (Command::Newline, "newline", None),
+    (Command::OpenLine, "open-line", None),
+    (Command::Indent, "indent-for-tab-command", None),
+    (Command::KeyboardQuit, "keyboard-quit", Some("enter_normal_mode")),
+    (Command::UniversalArgument, "universal-argument", None),
+];
+
+impl Command {
+    /// Look a command up by its emacs or command system name
+    fn from_name(name: &str) -> Option<Self> {
+        COMMANDS.iter()
+            .find(|(_, emacs, native)| *emacs == name || *native == Some(name))
+            .map(|(command, _, _)| *command)
+    }
+
+    /// The command moving the other way, used for negative arguments
+    fn reversed(self) -> Self {
+        match self {
+            Self::ForwardChar => Self::BackwardChar,
+            Self::BackwardChar => Self::ForwardChar,
+            Self::NextLine => Self::PreviousLine,
+            Self::PreviousLine => Self::NextLine,
+            Self::ForwardWord => Self::BackwardWord,
+            Self::BackwardWord => Self::ForwardWord,
+            Self::ScrollUp => Self::ScrollDown,
+            Self::ScrollDown => Self::ScrollUp,
+            Self::DeleteChar => Self::DeleteBackwardChar,
+            Self::DeleteBackwardChar => Self::DeleteChar,
+            Self::KillWord => Self::BackwardKillWord,
+            Self::BackwardKillWord => Self::KillWord,
+            command => command,
+        }
+    }
+
+    /// Whether the command changes the buffer, so an undo step is recorded first
+    fn edits(self) -> bool {
+        matches!(
+            self,
+            Self::DeleteChar | Self::DeleteBackwardChar | Self::KillLine | Self::KillWholeLine | Self::KillWord
+                | Self::BackwardKillWord | Self::KillRegion | Self::Yank | Self::YankPop | Self::Newline
+                | Self::OpenLine | Self::Indent
+        )
+    }
+}
+
+/// What a key does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Binding {
+    Native(Command),
+    /// A command of the command system
+    Run(&'static str),
+    Prefix(Prefix),
+}
+
+/// Prefix key waiting for the rest of its sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prefix {
+    CtrlX,
+    MetaG,
+    /// `ESC`, which makes the next key a meta key
+    Escape,
+}
+
+/// Bindings outside prefix maps
+fn global_binding(key: KeyEvent) -> Option<Binding> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let meta = key.modifiers.contains(KeyModifiers::ALT);
+    let command = match (key.code, ctrl, meta) {
+        (KeyCode::Char('f'), true, false) | (KeyCode::Right, false, false) => Command::ForwardChar,
+        (KeyCode::Char('b'), true, false) | (KeyCode::Left, false, false) => Command::BackwardChar,
+        (KeyCode::Char('n'), true, false) | (KeyCode::Down, false, false) => Command::NextLine,
+        (KeyCode::Char('p'), true, false) | (KeyCode::Up, false, false) => Command::PreviousLine,
+        (KeyCode::Char('a'), true, false) | (KeyCode::Home, false, false) => Command::BeginningOfLine,
+        (KeyCode::Char('e'), true, false) | (KeyCode::End, false, false) => Command::EndOfLine,
+        (KeyCode::Char('f'), false, true) | (KeyCode::Right, true, false) => Command::ForwardWord,
+        (KeyCode::Char('b'), false, true) | (KeyCode::Left, true, false) => Command::BackwardWord,
+        (KeyCode::Char('<'), false, true) => Command::BeginningOfBuffer,
+        (KeyCode::Char('>'), false, true) => Command::EndOfBuffer,
+        (KeyCode::Char('v'), true, false) | (KeyCode::PageDown, false, false) => Command::ScrollUp,
+        (KeyCode::Char('v'), false, true) | (KeyCode::PageUp, false, false) => Command::ScrollDown,
+        (KeyCode::Char('d'), true, false) | (KeyCode::Delete, false, false) => Command::DeleteChar,
+        (KeyCode::Backspace, false, false) => Command::DeleteBackwardChar,
+        (KeyCode::Char('k'), true, false) => Command::KillLine,
+        (KeyCode::Char('d'), false, true) => Command::KillWord,
+        (KeyCode::Backspace, _, true) => Command::BackwardKillWord,
+        (KeyCode::Char('w'), true, false) => Command::KillRegion,
+        (KeyCode::Char('w'), false, true) => Command::CopyRegion,
+        (KeyCode::Char('y'), true, false) => Command::Yank,
+        (KeyCode::Char('y'), false, true) => Command::YankPop,
+        (KeyCode::Char(' ' | '@'), true, false) => Command::SetMark,
+        (KeyCode::Char('s'), true, false) => Command::IsearchForward,
+        (KeyCode::Char('r'), true, false) => Command::IsearchBackward,
+        (KeyCode::Char('x'), false, true) => Command::ExecuteExtended,
+        (KeyCode::Char('/' | '_'), true, false) => Command::Undo,
+        (KeyCode::Char('m'), true, false) | (KeyCode::Enter, false, false) => Command::Newline,
+        (KeyCode::Char('o'), true, false) => Command::OpenLine,
+        (KeyCode::Char('i'), true, false) | (KeyCode::Tab, false, false) => Command::Indent,
+        (KeyCode::Char('g'), true, false) => Command::KeyboardQuit,
+        (KeyCode::Char('u'), true, false) => Command::UniversalArgument,
+        (KeyCode::Char('x'), true, false) => return Some(Binding::Prefix(Prefix::CtrlX)),
+        (KeyCode::Char('g'), false, true) => return Some(Binding::Prefix(Prefix::MetaG)),
+        (KeyCode::Esc, false, false) => return Some(Binding::Prefix(Prefix::Escape)),
+        _ => return None,
+    };
+    Some(Binding::Native(command))
+}
+
+/// Bindings after `C-x`
+fn ctrl_x_binding(key: KeyEvent) -> Option<Binding> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    Some(match (key.code, ctrl) {
+        (KeyCode::Char('s'), true) => Binding::Run("save_file"),
+        (KeyCode::Char('w'), true) => Binding::Native(Command::WriteFile),
+        (KeyCode::Char('f'), true) => Binding::Run("find_file"),
+        (KeyCode::Char('c'), true) => Binding::Run("quit"),
+        (KeyCode::Char('x'), true) => Binding::Native(Command::ExchangePointAndMark),
+        (KeyCode::Char('u'), false) => Binding::Native(Command::Undo),
+        (KeyCode::Char('h'), false) => Binding::Native(Command::MarkWholeBuffer),
+        (KeyCode::Char('d'), false) => Binding::Run("toggle_explorer"),
+        (KeyCode::Char('g'), true) => Binding::Native(Command::KeyboardQuit),
+        _ => return None,
+    })
+}
+
+/// Bindings after `M-g`
+fn meta_g_binding(key: KeyEvent) -> Option<Binding> {
+    match (key.code, key.modifiers.contains(KeyModifiers::CONTROL)) {
+        (KeyCode::Char('g'), false) => Some(Binding::Native(Command::GotoLine)),
+        (KeyCode::Char('g'), true) => Some(Binding::Native(Command::KeyboardQuit)),
+        _ => None,
+    }
+}
+
+/// Write a key in emacs notation, like `C-x` or `M-DEL`
+fn describe_key(key: KeyEvent) -> String {
+    let name = match key.code {
+        KeyCode::Char(' ') => "SPC".to_string(),
+        KeyCode::Char(ch) => ch.to_string(),
+        KeyCode::Enter => "RET".to_string(),
+        KeyCode::Tab => "TAB".to_string(),
+        KeyCode::Backspace => "DEL".to_string(),
+        KeyCode::Esc => "ESC".to_string(),
+        code => format!("<{}>", KeyPress::new(code, KeyModifiers::NONE).to_string().to_lowercase()),
+    };
+    let mut described = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        described.push_str("C-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        described.push_str("M-");
+    }
+    described + &name
+}
+
+/// Numeric argument typed with `C-u`, `M-<digit>` or `M--`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Argument {
+    /// Typed digits, if any
+    digits: Option<i64>,
+    /// Value without digits: 4 for each `C-u`
+    power: i64,
+    negative: bool,
+    /// Whether plain digits still extend the argument
+    collecting: bool,
+}
+
+impl Argument {
+    fn value(&self) -> i64 {
+        let value = self.digits.unwrap_or(if self.negative && self.power == 1 { 1 } else { self.power });
+        if self.negative { -value } else { value }
+    }
+}
+
+/// What the minibuffer is reading
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reading {
+    /// A command line for `M-x`, with the argument typed before it
+    Command(Option<i64>),
+    /// A file name for `C-x C-w`
+    FileName,
+    /// A line number for `M-g g`
+    Line,
+    /// Incremental search text
+    Search {
+        forward: bool,
+        origin: Position,
+        found: Option<TextRange>,
+    },
+}
+
+/// Input being typed at the bottom of the screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Minibuffer {
+    reading: Reading,
+    input: String,
+}
+
+/// What the previous command was, for joining kills and `M-y`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LastCommand {
+    Other,
+    SelfInsert,
+    Kill,
+    Yank(TextRange),
+    /// Line motion, keeping the column it started from
+    LineMotion(usize),
+}
+
+/// Emacs-style editing mode
+pub struct EmacsPlugin {
+    name: String,
+    version: String,
+    kill_ring: KillRing,
+    mark: Option<Position>,
+    mark_active: bool,
+    /// Whether the region is shown as the buffer selection
+    region_shown: bool,
+    prefix: Option<Prefix>,
+    argument: Option<Argument>,
+    /// Keys of the sequence being typed, echoed in the status line
+    echo: Vec<String>,
+    minibuffer: Option<Minibuffer>,
+    last_search: String,
+    last_command: LastCommand,
+    commands: Vec<String>,
+    message: Option<String>,
+    tab_size: usize,
+    insert_spaces: bool,
+}
+
+impl EmacsPlugin {
+    pub fn new() -> Self {
+        Self {
+            name: "emacs".to_string(),
+            version: "0.1.0".to_string(),
+            kill_ring: KillRing::new(),
+            mark: None,
+            mark_active: false,
+            region_shown: false,
+            prefix: None,
+            argument: None,
+            echo: Vec::new(),
+            minibuffer: None,
+            last_search: String::new(),
+            last_command: LastCommand::Other,
+            commands: Vec::new(),
+            message: None,
+            tab_size: 4,
+            insert_spaces: true,
+        }
+    }
+
+    /// Get the kill ring
+    pub fn kill_ring(&self) -> &KillRing {
+        &self.kill_ring
+    }
+
+    /// Get the mark, if it was set
+    pub fn mark(&self) -> Option<Position> {
+        self.mark
+    }
+
+    /// Handle a key, leaving errors as messages
+    fn dispatch(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<()> {
+        if self.minibuffer.is_some() {
+            return self.handle_minibuffer_key(key, buffer);
+        }
+
+        let mut key = key;
+        let prefix = self.prefix.take();
+        let binding = match prefix {
+            Some(Prefix::CtrlX) => ctrl_x_binding(key),
+            Some(Prefix::MetaG) => meta_g_binding(key),
+            Some(Prefix::Escape) if key.code == KeyCode::Esc => Some(Binding::Native(Command::KeyboardQuit)),
+            escape => {
+                if escape.is_some() {
+                    // ESC followed by a key is the meta version of the key
+                    self.echo.pop();
+                    key.modifiers |= KeyModifiers::ALT;
+                }
+                if self.take_argument_key(key) {
+                    return Ok(());
+                }
+                global_binding(key)
+            }
+        };
+
+        match binding {
+            Some(Binding::Prefix(prefix)) => {
+                self.prefix = Some(prefix);
+                self.echo.push(describe_key(key));
+                Ok(())
+            }
+            Some(Binding::Native(Command::UniversalArgument)) => {
+                self.echo.push("C-u".to_string());
+                match &mut self.argument {
+                    Some(argument) if argument.digits.is_none() => argument.power *= 4,
+                    Some(argument) => argument.collecting = false,
+                    None => self.argument = Some(Argument { digits: None, power: 4, negative: false, collecting: true }),
+                }
+                Ok(())
+            }
+            Some(Binding::Native(command)) => {
+                self.echo.clear();
+                let argument = self.argument.take().map(|argument| argument.value());
+                self.run(command, argument, buffer)
+            }
+            Some(Binding::Run(name)) => {
+                self.echo.clear();
+                self.argument = None;
+                self.commands.push(name.to_string());
+                self.last_command = LastCommand::Other;
+                Ok(())
+            }
+            None => {
+                let sequence = std::mem::take(&mut self.echo);
+                let count = self.argument.take().map_or(1, |argument| argument.value());
+                match key.code {
+                    KeyCode::Char(ch) if prefix.is_none() && !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                        self.self_insert(ch, count, buffer)
+                    }
+                    _ => {
+                        let described = sequence.into_iter().chain([describe_key(key)]).collect::<Vec<_>>().join(" ");
+                        Err(RuskError::Command(format!("{} is undefined", described)))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Extend the argument with a digit or minus sign, returning whether the key was taken
+    fn take_argument_key(&mut self, key: KeyEvent) -> bool {
+        let meta = key.modifiers.contains(KeyModifiers::ALT);
+        let collecting = self.argument.is_some_and(|argument| argument.collecting);
+        if key.modifiers.contains(KeyModifiers::CONTROL) || !(meta || collecting) {
+            return false;
+        }
+        let argument = self.argument.unwrap_or(Argument { digits: None, power: 1, negative: false, collecting: true });
+        let argument = match key.code {
+            KeyCode::Char(digit @ '0'..='9') => {
+                let digit = i64::from(digit.to_digit(10).unwrap_or(0));
+                let digits = argument.digits.unwrap_or(0).saturating_mul(10).saturating_add(digit);
+                Argument { digits: Some(digits), collecting: true, ..argument }
+            }
+            KeyCode::Char('-') if argument.digits.is_none() && !argument.negative => Argument { negative: true, collecting: true, ..argument },
+            _ => {
+                if let Some(argument) = &mut self.argument {
+                    argument.collecting = false;
+                }
+                return false;
+            }
+        };
+        self.argument = Some(argument);
+        self.echo.push(describe_key(key));
+        true
+    }
+
+    /// Run a command `count` times, or with its special meaning for an explicit argument
+    fn run(&mut self, command: Command, argument: Option<i64>, buffer: &mut Buffer) -> Result<()> {
+        let count = argument.unwrap_or(1);
+        let (command, count) = if count < 0 { (command.reversed(), -count) } else { (command, count) };
+        let count = count as usize;
+
+        if command.edits() {
+            if buffer.is_read_only() {
+                return Err(RuskError::Command("Buffer is read-only".to_string()));
+            }
+            buffer.checkpoint();
+        }
+
+        let last = self.last_command;
+        self.last_command = LastCommand::Other;
+        let point = Self::point(buffer);
+
+        match command {
+            Command::ForwardChar => self.move_to(buffer, (0..count).fold(point, |pos, _| Self::next_char(buffer, pos))),
+            Command::BackwardChar => self.move_to(buffer, (0..count).fold(point, |pos, _| Self::previous_char(buffer, pos))),
+            Command::NextLine | Command::PreviousLine | Command::ScrollUp | Command::ScrollDown => {
+                let lines = if matches!(command, Command::ScrollUp | Command::ScrollDown) { count as i64 * PAGE_LINES } else { count as i64 };
+                let lines = if matches!(command, Command::PreviousLine | Command::ScrollDown) { -lines } else { lines };
+                let goal = match last {
+                    LastCommand::LineMotion(goal) => goal,
+                    _ => point.column,
+                };
+                let line = (point.line as i64 + lines).clamp(0, buffer.line_count() as i64 - 1) as usize;
+                let column = Self::floor_char_boundary(&buffer.lines()[line], goal);
+                self.move_to(buffer, Position::new(line, column));
+                self.last_command = LastCommand::LineMotion(goal);
+            }
+            Command::BeginningOfLine => self.move_to(buffer, Position::new(point.line, 0)),
+            Command::EndOfLine => self.move_to(buffer, Position::new(point.line, buffer.lines()[point.line].len())),
+            Command::ForwardWord => self.move_to(buffer, (0..count).fold(point, |pos, _| Self::forward_word(buffer, pos))),
+            Command::BackwardWord => self.move_to(buffer, (0..count).fold(point, |pos, _| Self::backward_word(buffer, pos))),
+            Command::BeginningOfBuffer | Command::EndOfBuffer => {
+                self.push_mark(point, "Mark set");
+                let target = if command == Command::BeginningOfBuffer { Position::origin() } else { Self::buffer_end(buffer) };
+                self.move_to(buffer, target);
+            }
+            Command::DeleteChar | Command::DeleteBackwardChar => {
+                let end = (0..count).fold(point, |pos, _| {
+                    if command == Command::DeleteChar { Self::next_char(buffer, pos) } else { Self::previous_char(buffer, pos) }
+                });
+                buffer.delete_range(&TextRange::new(point, end));
+            }
+            Command::KillLine => {
+                let backward = argument.is_some_and(|lines| lines < 0);
+                let end = match argument {
+                    Some(_) if backward => Position::new(point.line.saturating_sub(count), 0),
+                    Some(_) => Self::line_start(buffer, point.line + count),
+                    None => {
+                        // Kill the rest of the line, or the line break when only blanks remain
+                        let line = &buffer.lines()[point.line];
+                        if line[point.column..].trim().is_empty() {
+                            Self::next_char(buffer, Position::new(point.line, line.len()))
+                        } else {
+                            Position::new(point.line, line.len())
+                        }
+                    }
+                };
+                self.kill(buffer, TextRange::new(point, end), backward, last);
+            }
+            Command::KillWholeLine => {
+                let start = if point.line + count >= buffer.line_count() && point.line > 0 {
+                    // The last line takes the line break before it
+                    Position::new(point.line - 1, buffer.lines()[point.line - 1].len())
+                } else {
+                    Position::new(point.line, 0)
+                };
+                let end = Self::line_start(buffer, point.line + count);
+                self.kill(buffer, TextRange::new(start, end), false, last);
+            }
+            Command::KillWord => {
+                let end = (0..count).fold(point, |pos, _| Self::forward_word(buffer, pos));
+                self.kill(buffer, TextRange::new(point, end), false, last);
+            }
+            Command::BackwardKillWord => {
+                let start = (0..count).fold(point, |pos, _| Self::backward_word(buffer, pos));
+                self.kill(buffer, TextRange::new(start, point), true, last);
+            }
+            Command::KillRegion => {
+                let region = self.region(buffer)?;
+                self.kill(buffer, region, false, last);
+                self.mark_active = false;
+            }
+            Command::CopyRegion => {
+                let region = self.region(buffer)?;
+                let text = buffer.text_in_range(&region);
+                if last == LastCommand::Kill {
+                    self.kill_ring.join(&text, false);
+                } else {
+                    self.kill_ring.push(text);
+                }
+                self.mark_active = false;
+                self.last_command = LastCommand::Kill;
+            }
+            Command::Yank => {
+                if argument.is_some() {
+                    self.kill_ring.rotate(count as i64 - 1);
+                }
+                let text = self.kill_ring.current().ok_or_else(|| RuskError::Command("Kill ring is empty".to_string()))?.to_string();
+                self.mark = Some(point);
+                self.mark_active = false;
+                let end = buffer.insert_text(point, &text);
+                self.move_to(buffer, end);
+                self.last_command = LastCommand::Yank(TextRange::new(point, end));
+            }
+            Command::YankPop => {
+                let LastCommand::Yank(range) = last else {
+                    return Err(RuskError::Command("Previous command was not a yank".to_string()));
+                };
+                let text = self.kill_ring.rotate(count as i64).unwrap_or_default().to_string();
+                buffer.delete_range(&range);
+                let end = buffer.insert_text(range.start, &text);
+                self.move_to(buffer, end);
+                self.last_command = LastCommand::Yank(TextRange::new(range.start, end));
+            }
+            Command::SetMark => match (argument, self.mark) {
+                // With an argument, jump back to the mark
+                (Some(_), Some(mark)) => self.move_to(buffer, Self::clamp(buffer, mark)),
+                (Some(_), None) => return Err(RuskError::Command("No mark set in this buffer".to_string())),
+                (None, _) => {
+                    self.mark = Some(point);
+                    self.mark_active = true;
+                    self.message = Some("Mark set".to_string());
+                }
+            },
+            Command::ExchangePointAndMark => {
+                let mark = self.mark.ok_or_else(|| RuskError::Command("No mark set in this buffer".to_string()))?;
+                self.mark = Some(point);
+                self.mark_active = true;
+                self.move_to(buffer, Self::clamp(buffer, mark));
+            }
+            Command::MarkWholeBuffer => {
+                self.mark = Some(Self::buffer_end(buffer));
+                self.mark_active = true;
+                self.move_to(buffer, Position::origin());
+            }
+            Command::IsearchForward | Command::IsearchBackward => {
+                self.mark_active = false;
+                self.minibuffer = Some(Minibuffer {
+                    reading: Reading::Search { forward: command == Command::IsearchForward, origin: point, found: None },
+                    input: String::new(),
+                });
+            }
+            Command::ExecuteExtended => self.open_minibuffer(Reading::Command(argument)),
+            Command::WriteFile => self.open_minibuffer(Reading::FileName),
+            Command::GotoLine => match argument {
+                Some(line) => self.goto_line(buffer, line),
+                None => self.open_minibuffer(Reading::Line),
+            },
+            Command::Undo => {
+                for _ in 0..count {
+                    if !buffer.undo() {
+                        return Err(RuskError::Command("No further undo information".to_string()));
+                    }
+                }
+                self.mark_active = false;
+            }
+            Command::Newline | Command::OpenLine => {
+                let end = buffer.insert_text(point, &"\n".repeat(count));
+                self.move_to(buffer, if command == Command::Newline { end } else { point });
+            }
+            Command::Indent => {
+                let text = if self.insert_spaces {
+                    let column = buffer.lines()[point.line][..point.column].chars().count();
+                    " ".repeat(self.tab_size * count - column % self.tab_size)
+                } else {
+                    "\t".repeat(count)
+                };
+                let end = buffer.insert_text(point, &text);
+                self.move_to(buffer, end);
+            }
+            Command::KeyboardQuit => {
+                self.mark_active = false;
+                self.message = Some("Quit".to_string());
+            }
+            Command::UniversalArgument => {}
+        }
+        Ok(())
+    }
+
+    /// Insert a typed character `count` times
+    fn self_insert(&mut self, ch: char, count: i64, buffer: &mut Buffer) -> Result<()> {
+        if buffer.is_read_only() {
+            return Err(RuskError::Command("Buffer is read-only".to_string()));
+        }
+        // Typing runs are undone together
+        if self.last_command != LastCommand::SelfInsert {
+            buffer.checkpoint();
+        }
+        let text: String = std::iter::repeat_n(ch, count.max(0) as usize).collect();
+        let end = buffer.insert_text(Self::point(buffer), &text);
+        self.move_to(buffer, end);
+        self.last_command = LastCommand::SelfInsert;
+        Ok(())
+    }
+
+    /// Remove a range and put its text on the kill ring, joining it to the previous kill
+    fn kill(&mut self, buffer: &mut Buffer, range: TextRange, backward: bool, last: LastCommand) {
+        let text = buffer.delete_range(&range);
+        if last == LastCommand::Kill {
+            self.kill_ring.join(&text, backward);
+        } else if !text.is_empty() {
+            self.kill_ring.push(text);
+        }
+        self.last_command = LastCommand::Kill;
+    }
+
+    /// Get the text between the mark and the point
+    fn region(&self, buffer: &Buffer) -> Result<TextRange> {
+        let mark = self.mark.ok_or_else(|| RuskError::Command("The mark is not set now, so there is no region".to_string()))?;
+        Ok(TextRange::new(Self::clamp(buffer, mark), Self::point(buffer)))
+    }
+
+    fn push_mark(&mut self, position: Position, message: &str) {
+        if !self.mark_active {
+            self.mark = Some(position);
+            self.message = Some(message.to_string());
+        }
+    }
+
+    fn open_minibuffer(&mut self, reading: Reading) {
+        self.minibuffer = Some(Minibuffer { reading, input: String::new() });
+    }
+
+    fn goto_line(&mut self, buffer: &mut Buffer, line: i64) {
+        self.push_mark(Self::point(buffer), "Mark set");
+        let line = (line.max(1) as usize - 1).min(buffer.line_count() - 1);
+        self.move_to(buffer, Position::new(line, 0));
+    }
+
+    /// Handle a key typed into the minibuffer
+    fn handle_minibuffer_key(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<()> {
+        let Some(minibuffer) = &mut self.minibuffer else {
+            return Ok(());
+        };
+        if let Reading::Search { .. } = minibuffer.reading {
+            return self.handle_search_key(key, buffer);
+        }
+
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('g') if ctrl => {
+                self.minibuffer = None;
+                self.message = Some("Quit".to_string());
+            }
+            KeyCode::Esc => self.minibuffer = None,
+            KeyCode::Backspace => {
+                minibuffer.input.pop();
+            }
+            KeyCode::Char(ch) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => minibuffer.input.push(ch),
+            KeyCode::Enter => {
+                let Some(Minibuffer { reading, input }) = self.minibuffer.take() else {
+                    return Ok(());
+                };
+                let input = input.trim();
+                match reading {
+                    Reading::Command(argument) => {
+                        if input.is_empty() {
+                            return Ok(());
+                        }
+                        // The mode's own commands run directly, everything else goes to the command system
+                        match Command::from_name(input) {
+                            Some(command) => return self.run(command, argument, buffer),
+                            None => self.commands.push(input.to_string()),
+                        }
+                    }
+                    Reading::FileName if !input.is_empty() => {
+                        self.commands.push(format!("save_as \"{}\"", input.replace('\\', "\\\\").replace('"', "\\\"")));
+                    }
+                    Reading::Line => {
+                        let line = input.parse::<i64>()
+                            .map_err(|_| RuskError::Command(format!("Invalid line number '{}'", input)))?;
+                        self.goto_line(buffer, line);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Handle a key during incremental search; keys that are not search keys end it and run normally
+    fn handle_search_key(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<()> {
+        let Some(Minibuffer { reading: Reading::Search { forward, origin, found }, input }) = &mut self.minibuffer else {
+            return Ok(());
+        };
+        let (origin, found) = (*origin, *found);
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key.code {
+            KeyCode::Char(ch) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                // A longer search text keeps matching where the shorter one did if it can
+                input.push(ch);
+                let start = found.map_or(origin, |range| range.start);
+                let result = Self::search(buffer, input, start, *forward, found.is_some() || *forward);
+                self.show_match(buffer, result);
+            }
+            KeyCode::Backspace => {
+                input.pop();
+                let result = if input.is_empty() { None } else { Self::search(buffer, input, origin, *forward, *forward) };
+                self.show_match(buffer, result);
+                if result.is_none() {
+                    buffer.clear_selection();
+                    buffer.set_cursor_position(origin.line, origin.column);
+                }
+            }
+            KeyCode::Char(direction @ ('s' | 'r')) if ctrl => {
+                *forward = direction == 's';
+                let reused = input.is_empty();
+                if reused {
+                    input.clone_from(&self.last_search);
+                }
+                let (start, inclusive) = match found {
+                    Some(range) => (range.start, false),
+                    None if reused => (origin, *forward),
+                    // A failed search wraps around
+                    None if *forward => (Position::origin(), true),
+                    None => (Self::buffer_end(buffer), true),
+                };
+                let forward = *forward;
+                let needle = input.clone();
+                let result = if needle.is_empty() { None } else { Self::search(buffer, &needle, start, forward, inclusive) };
+                self.show_match(buffer, result);
+            }
+            KeyCode::Char('g') if ctrl => {
+                self.minibuffer = None;
+                buffer.clear_selection();
+                buffer.set_cursor_position(origin.line, origin.column);
+                self.message = Some("Quit".to_string());
+            }
+            KeyCode::Enter => self.finish_search(buffer),
+            _ => {
+                self.finish_search(buffer);
+                return self.dispatch(key, buffer);
+            }
+        }
+        Ok(())
+    }
+
+    /// Move to a search match and highlight it, or report the failure
+    fn show_match(&mut self, buffer: &mut Buffer, result: Option<TextRange>) {
+        let Some(Minibuffer { reading: Reading::Search { forward, found, .. }, .. }) = &mut self.minibuffer else {
+            return;
+        };
+        *found = result;
+        if let Some(range) = result {
+            buffer.select_range(&range);
+            let point = if *forward { range.end } else { range.start };
+            buffer.set_cursor_position(point.line, point.column);
+        }
+    }
+
+    /// End incremental search at the match, leaving the mark where it started
+    fn finish_search(&mut self, buffer: &mut Buffer) {
+        let Some(Minibuffer { reading: Reading::Search { origin, .. }, input }) = self.minibuffer.take() else {
+            return;
+        };
+        buffer.clear_selection();
+        if !input.is_empty() {
+            self.last_search = input;
+        }
+        if Self::point(buffer) != origin {
+            self.mark = Some(origin);
+            self.mark_active = false;
+            self.message = Some("Mark saved where search started".to_string());
+        }
+    }
+
+    /// Find text from a position, case-insensitively unless it has capitals
+    ///
+    /// Matches start after the position when searching forward and before
+    /// it when searching backward, or at it too with `inclusive`.
+    fn search(buffer: &Buffer, needle: &str, from: Position, forward: bool, inclusive: bool) -> Option<TextRange> {
+        let fold = !needle.chars().any(char::is_uppercase);
+        let needle = if fold { needle.to_lowercase() } else { needle.to_string() };
+        let haystack = |line: &str| if fold && line.to_lowercase().len() == line.len() { line.to_lowercase() } else { line.to_string() };
+        let found = |line: usize, column: usize| TextRange::new(Position::new(line, column), Position::new(line, column + needle.len()));
+
+        let lines = buffer.lines();
+        if forward {
+            let skip = usize::from(!inclusive);
+            for (index, line) in lines.iter().enumerate().skip(from.line) {
+                let text = haystack(line);
+                let start = if index == from.line { (from.column + skip).min(text.len()) } else { 0 };
+                let start = Self::floor_char_boundary(&text, start);
+                if let Some(column) = text[start..].find(&needle) {
+                    return Some(found(index, start + column));
+                }
+            }
+        } else {
+            for (index, line) in lines.iter().enumerate().take(from.line + 1).rev() {
+                let text = haystack(line);
+                let before = |column: &usize| index < from.line || *column < from.column || (inclusive && *column == from.column);
+                if let Some(column) = text.match_indices(&needle).map(|(column, _)| column).filter(before).last() {
+                    return Some(found(index, column));
+                }
+            }
+        }
+        None
+    }
+
+    /// Move the point
+    fn move_to(&mut self, buffer: &mut Buffer, position: Position) {
+        buffer.set_cursor_position(position.line, position.column);
+    }
+
+    /// Show the region as the buffer selection while the mark is active
+    fn show_region(&mut self, buffer: &mut Buffer) {
+        if matches!(self.minibuffer, Some(Minibuffer { reading: Reading::Search { .. }, .. })) {
+            return;
+        }
+        match self.mark {
+            Some(mark) if self.mark_active => {
+                let point = Self::point(buffer);
+                buffer.select_range(&TextRange::new(Self::clamp(buffer, mark), point));
+                buffer.set_cursor_position(point.line, point.column);
+                self.region_shown = true;
+            }
+            _ if self.region_shown => {
+                buffer.clear_selection();
+                self.region_shown = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn point(buffer: &Buffer) -> Position {
+        let (line, column) = buffer.cursor_position();
+        Position::new(line, column)
+    }
+
+    fn buffer_end(buffer: &Buffer) -> Position {
+        let last = buffer.line_count() - 1;
+        Position::new(last, buffer.lines()[last].len())
+    }
+
+    /// Start of a line, or the end of the buffer past the last line
+    fn line_start(buffer: &Buffer, line: usize) -> Position {
+        if line < buffer.line_count() { Position::new(line, 0) } else { Self::buffer_end(buffer) }
+    }
+
+    fn clamp(buffer: &Buffer, position: Position) -> Position {
+        let line = position.line.min(buffer.line_count() - 1);
+        let text = &buffer.lines()[line];
+        Position::new(line, Self::floor_char_boundary(text, position.column))
+    }
+
+    fn floor_char_boundary(text: &str, column: usize) -> usize {
+        let mut column = column.min(text.len());
+        while !text.is_char_boundary(column) {
+            column -= 1;
+        }
+        column
+    }
+
+    /// Position after the character at a position, moving to the next line at its end
+    fn next_char(buffer: &Buffer, position: Position) -> Position {
+        let text = &buffer.lines()[position.line];
+        match text[position.column..].chars().next() {
+            Some(ch) => Position::new(position.line, position.column + ch.len_utf8()),
+            None if position.line + 1 < buffer.line_count() => Position::new(position.line + 1, 0),
+            None => position,
+        }
+    }
+
+    /// Position of the character before a position, moving to the previous line at its start
+    fn previous_char(buffer: &Buffer, position: Position) -> Position {
+        let text = &buffer.lines()[position.line];
+        match text[..position.column].chars().next_back() {
+            Some(ch) => Position::new(position.line, position.column - ch.len_utf8()),
+            None if position.line > 0 => Position::new(position.line - 1, buffer.lines()[position.line - 1].len()),
+            None => position,
+        }
+    }
+
+    fn is_word_char(ch: char) -> bool {
+        ch.is_alphanumeric() || ch == '_'
+    }
+
+    /// Position after the end of the next word
+    fn forward_word(buffer: &Buffer, position: Position) -> Position {
+        let lines = buffer.lines();
+        let (mut line, mut column) = (position.line, position.column);
+        loop {
+            let text = &lines[line];
+            if let Some((offset, _)) = text[column..].char_indices().find(|(_, ch)| Self::is_word_char(*ch)) {
+                column += offset;
+                break;
+            }
+            if line + 1 >= lines.len() {
+                return Position::new(line, text.len());
+            }
+            line += 1;
+            column = 0;
+        }
+        let text = &lines[line];
+        let length = text[column..].char_indices().find(|(_, ch)| !Self::is_word_char(*ch)).map_or(text.len() - column, |(offset, _)| offset);
+        Position::new(line, column + length)
+    }
+
+    /// Position of the start of the previous word
+    fn backward_word(buffer: &Buffer, position: Position) -> Position {
+        let lines = buffer.lines();
+        let (mut line, mut column) = (position.line, position.column);
+        loop {
+            if let Some((offset, _)) = lines[line][..column].char_indices().rev().find(|(_, ch)| Self::is_word_char(*ch)) {
+                column = offset;
+                break;
+            }
+            if line == 0 {
+                return Position::origin();
+            }
+            line -= 1;
+            column = lines[line].len();
+        }
+        let start = lines[line][..column].char_indices().rev()
+            .take_while(|(_, ch)| Self::is_word_char(*ch))
+            .last()
+            .map_or(column, |(offset, _)| offset);
+        Position::new(line, start)
+    }
+}
+
+impl Default for EmacsPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for EmacsPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn configure(&mut self, settings: &toml::Value) -> Result<()> {
+        for (key, value) in settings.as_table().into_iter().flatten() {
+            match key.as_str() {
+                "kill_ring_max" => {
+                    let limit = value.as_integer().filter(|limit| *limit > 0).ok_or_else(|| {
+                        RuskError::Config("plugins.plugin_configs.emacs.kill_ring_max must be a positive integer".to_string())
+                    })?;
+                    self.kill_ring.set_limit(limit as usize);
+                }
+                _ => return Err(RuskError::Config(format!("Unknown emacs setting: {}", key))),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EditingMode for EmacsPlugin {
+    fn set_config(&mut self, config: &Config) {
+        self.tab_size = config.editor.tab_size.max(1);
+        self.insert_spaces = config.editor.insert_spaces;
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<bool> {
+        if let Err(e) = self.dispatch(key, buffer) {
+            self.message = Some(e.to_string());
+            self.echo.clear();
+            self.prefix = None;
+            self.argument = None;
+            self.last_command = LastCommand::Other;
+        }
+        self.show_region(buffer);
+        Ok(false)
+    }
+
+    fn editor_mode(&self) -> EditorMode {
+        if self.minibuffer.is_some() { EditorMode::Command } else { EditorMode::Insert }
+    }
+
+    fn mode_name(&self) -> String {
+        match &self.minibuffer {
+            Some(Minibuffer { reading: Reading::Search { .. }, .. }) => "I-SEARCH",
+            Some(_) => "MINIBUF",
+            None if self.mark_active => "REGION",
+            None => "EMACS",
+        }.to_string()
+    }
+
+    fn command_line(&self) -> Option<String> {
+        if let Some(Minibuffer { reading, input }) = &self.minibuffer {
+            let prompt = match reading {
+                Reading::Command(Some(argument)) => format!("{} M-x ", argument),
+                Reading::Command(None) => "M-x ".to_string(),
+                Reading::FileName => "Write file: ".to_string(),
+                Reading::Line => "Goto line: ".to_string(),
+                Reading::Search { forward, found, .. } => {
+                    let failing = if found.is_none() && !input.is_empty() { "Failing " } else { "" };
+                    let direction = if *forward { "I-search" } else { "I-search backward" };
+                    format!("{}{}: ", failing, direction)
+                }
+            };
+            return Some(prompt + input);
+        }
+        None
+    }
+
+    fn mode_color(&self) -> Option<Color> {
+        match &self.minibuffer {
+            Some(_) => mode::mode_color(EditorMode::Command),
+            None if self.mark_active => mode::mode_color(EditorMode::Visual),
+            None => None,
+        }
+    }
+
+    fn cursor_shape(&self) -> CursorShape {
+        CursorShape::Block
+    }
+
+    fn pending_keys(&self) -> Option<String> {
+        (!self.echo.is_empty()).then(|| format!("{}-", self.echo.join(" ")))
+    }
+
+    fn uses_keybindings(&self) -> bool {
+        false
+    }
+
+    fn execute(&mut self, command: &str, buffer: &mut Buffer) -> Option<Result<bool>> {
+        let command = Command::from_name(command)?;
+        let result = self.run(command, None, buffer);
+        self.show_region(buffer);
+        Some(result.map(|_| false))
+    }
+
+    fn take_commands(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.commands)
+    }
+
+    fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(emacs: &mut EmacsPlugin, buffer: &mut Buffer, keys: &[KeyEvent]) {
+        for key in keys {
+            EditingMode::handle_key(emacs, *key, buffer).unwrap();
+        }
+    }
+
+    fn ctrl(ch: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL)
+    }
+
+    fn meta(ch: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(ch), KeyModifiers::ALT)
+    }
+
+    fn text(text: &str) -> Vec<KeyEvent> {
+        text.chars().map(|ch| match ch {
+            '\n' => KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+            ch => KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE),
+        }).collect()
+    }
+
+    #[test]
+    fn test_kill_ring_and_region() {
+        let mut emacs = EmacsPlugin::new();
+        let mut buffer = Buffer::new();
+        press(&mut emacs, &mut buffer, &text("one two\nthree"));
+        assert_eq!(buffer.text(), "one two\nthree");
+
+        // Consecutive kills join into one entry
+        press(&mut emacs, &mut buffer, &[meta('<'), ctrl('k'), ctrl('k')]);
+        assert_eq!(buffer.text(), "three");
+        assert_eq!(emacs.kill_ring().current(), Some("one two\n"));
+
+        // Yank, then replace the yank with an older kill
+        press(&mut emacs, &mut buffer, &[ctrl('e'), KeyEvent::new(KeyCode::Backspace, KeyModifiers::ALT)]);
+        assert_eq!(buffer.text(), "");
+        press(&mut emacs, &mut buffer, &[ctrl('y')]);
+        assert_eq!(buffer.text(), "three");
+        press(&mut emacs, &mut buffer, &[meta('y')]);
+        assert_eq!(buffer.text(), "one two\n");
+
+        // Copy the region between the mark and the point
+        press(&mut emacs, &mut buffer, &[meta('<'), ctrl(' '), meta('f'), meta('w'), ctrl('e'), ctrl('y')]);
+        assert_eq!(buffer.text(), "one twoone\n");
+
+        // Undo takes back the yank
+        press(&mut emacs, &mut buffer, &[ctrl('/')]);
+        assert_eq!(buffer.text(), "one two\n");
+    }
+
+    #[test]
+    fn test_arguments_search_and_commands() {
+        let mut emacs = EmacsPlugin::new();
+        let mut buffer = Buffer::new();
+        press(&mut emacs, &mut buffer, &text("alpha beta gamma"));
+        press(&mut emacs, &mut buffer, &[ctrl('a')]);
+
+        // C-u multiplies by four, digits give the count
+        press(&mut emacs, &mut buffer, &[ctrl('u'), ctrl('f')]);
+        assert_eq!(buffer.cursor_position(), (0, 4));
+        press(&mut emacs, &mut buffer, &[ctrl('u'), KeyEvent::new(KeyCode::Char('2'), KeyModifiers::NONE), ctrl('b')]);
+        assert_eq!(buffer.cursor_position(), (0, 2));
+        press(&mut emacs, &mut buffer, &[ctrl('u'), KeyEvent::new(KeyCode::Char('3'), KeyModifiers::NONE)]);
+        press(&mut emacs, &mut buffer, &text("x"));
+        assert_eq!(buffer.text(), "alxxxpha beta gamma");
+
+        // Incremental search moves to the end of the match and sets the mark where it started
+        press(&mut emacs, &mut buffer, &[ctrl('s')]);
+        press(&mut emacs, &mut buffer, &text("GAM"));
+        assert_eq!(emacs.command_line().as_deref(), Some("Failing I-search: GAM"));
+        press(&mut emacs, &mut buffer, &[KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE); 3]);
+        press(&mut emacs, &mut buffer, &text("gam\n"));
+        assert_eq!(buffer.cursor_position(), (0, 17));
+        assert_eq!(emacs.mark(), Some(Position::new(0, 5)));
+
+        // Prefix maps and M-x hand commands to the command system
+        press(&mut emacs, &mut buffer, &[ctrl('x')]);
+        assert_eq!(emacs.pending_keys().as_deref(), Some("C-x-"));
+        press(&mut emacs, &mut buffer, &[ctrl('s'), meta('x')]);
+        press(&mut emacs, &mut buffer, &text("goto_line 1\n"));
+        assert_eq!(emacs.take_commands(), vec!["save_file", "goto_line 1"]);
+        press(&mut emacs, &mut buffer, &[meta('x')]);
+        press(&mut emacs, &mut buffer, &text("beginning-of-buffer\n"));
+        assert_eq!(buffer.cursor_position(), (0, 0));
+        assert!(emacs.take_commands().is_empty());
+    }
+}
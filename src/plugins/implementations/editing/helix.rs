@@ -0,0 +1,914 @@
+//! Helix editing mode
+//!
+//! Selection-first modal editing in the style of helix: motions select the
+//! text they move over and actions such as `d`, `c` and `y` then work on
+//! that selection. Select mode (`v`) extends the selection instead of
+//! replacing it, `x` selects whole lines, `/` searches with a regex and `:`
+//! runs a line of the command system.
+
+use crate::core::buffer::Buffer;
+use crate::core::cursor::{Position, TextRange};
+use crate::plugins::implementations::editing::mode::EditingMode;
+use crate::plugins::Plugin;
+use crate::services::command_system::EditorMode;
+use crate::{Config, Result, RuskError};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use regex::Regex;
+
+/// Helix modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HelixMode {
+    Normal,
+    Select,
+    Insert,
+}
+
+/// Prefix key waiting for the key that completes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    Goto,
+    Space,
+    Replace,
+    Find { forward: bool, till: bool },
+}
+
+/// What the prompt reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PromptKind {
+    Command,
+    Search,
+}
+
+#[derive(Debug, Clone)]
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
+/// Kind of character, words being runs of one kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharKind {
+    Space,
+    Word,
+    Punctuation,
+}
+
+/// Helix style editing mode
+///
+/// The selection runs from the anchor to the head and includes the
+/// characters under both, so there always is at least one character
+/// selected. The head is where the cursor is shown.
+pub struct HelixPlugin {
+    name: String,
+    version: String,
+    mode: HelixMode,
+    anchor: Position,
+    head: Position,
+    /// Selection last shown in the buffer, to notice when something else changed it
+    shown: Option<TextRange>,
+    count: Option<usize>,
+    pending: Option<Pending>,
+    prompt: Option<Prompt>,
+    register: Option<String>,
+    last_search: Option<Regex>,
+    /// Whether searches go on from the other end of the buffer
+    wrap_around: bool,
+    commands: Vec<String>,
+    message: Option<String>,
+    tab_size: usize,
+    insert_spaces: bool,
+}
+
+impl HelixPlugin {
+    pub fn new() -> Self {
+        Self {
+            name: "helix".to_string(),
+            version: "0.1.0".to_string(),
+            mode: HelixMode::Normal,
+            anchor: Position::origin(),
+            head: Position::origin(),
+            shown: None,
+            count: None,
+            pending: None,
+            prompt: None,
+            register: None,
+            last_search: None,
+            wrap_around: true,
+            commands: Vec::new(),
+            message: None,
+            tab_size: 4,
+            insert_spaces: true,
+        }
+    }
+
+    /// Get the current mode
+    pub fn mode(&self) -> HelixMode {
+        self.mode
+    }
+
+    /// Get the selection as a range with an exclusive end
+    pub fn selection(&self, buffer: &Buffer) -> TextRange {
+        let (start, end) = Self::ordered(self.anchor, self.head);
+        TextRange::new(start, Self::next_char(buffer, end))
+    }
+
+    /// Handle a key, leaving errors as messages
+    fn dispatch(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<()> {
+        if self.prompt.is_some() {
+            return self.handle_prompt_key(key, buffer);
+        }
+        match self.mode {
+            HelixMode::Insert => self.handle_insert_key(key, buffer),
+            HelixMode::Normal | HelixMode::Select => match self.pending.take() {
+                Some(pending) => {
+                    let count = self.count.take().unwrap_or(1);
+                    self.handle_pending_key(pending, key, count, buffer)
+                }
+                None => self.handle_normal_key(key, buffer),
+            },
+        }
+    }
+
+    fn handle_normal_key(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<()> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+
+        if let KeyCode::Char(digit @ '0'..='9') = key.code
+            && !ctrl && !alt && (digit != '0' || self.count.is_some())
+        {
+            let digit = digit.to_digit(10).unwrap_or(0) as usize;
+            self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+            return Ok(());
+        }
+        let count = self.count.take().unwrap_or(1);
+
+        match key.code {
+            KeyCode::Esc => {
+                self.mode = HelixMode::Normal;
+            }
+            KeyCode::Char('h') | KeyCode::Left => self.repeat_move(buffer, count, Self::previous_char),
+            KeyCode::Char('l') | KeyCode::Right => self.repeat_move(buffer, count, Self::next_char),
+            KeyCode::Char('j') | KeyCode::Down => self.move_vertically(buffer, count as isize),
+            KeyCode::Char('k') | KeyCode::Up => self.move_vertically(buffer, -(count as isize)),
+            KeyCode::Home => self.move_head(buffer, Position::new(self.head.line, 0)),
+            KeyCode::End => self.move_head(buffer, Self::line_end(buffer, self.head.line)),
+            KeyCode::Char('w' | 'W') => {
+                let long = key.code == KeyCode::Char('W');
+                self.repeat_select(buffer, count, |buffer, from| {
+                    let start = Self::word_motion_start(buffer, from, long);
+                    let end = Self::next_word_start(buffer, start, long);
+                    (start, if end == start { end } else { Self::previous_char(buffer, end) })
+                });
+            }
+            KeyCode::Char('e' | 'E') => {
+                let long = key.code == KeyCode::Char('E');
+                self.repeat_select(buffer, count, |buffer, from| {
+                    let start = Self::word_motion_start(buffer, from, long);
+                    (start, Self::word_end(buffer, start, long))
+                });
+            }
+            KeyCode::Char('b' | 'B') => {
+                let long = key.code == KeyCode::Char('B');
+                self.repeat_select(buffer, count, |buffer, from| (from, Self::previous_word_start(buffer, from, long)));
+            }
+            KeyCode::Char(ch @ ('f' | 't' | 'F' | 'T')) if !ctrl && !alt => {
+                self.count = (count > 1).then_some(count);
+                self.pending = Some(Pending::Find { forward: ch.is_lowercase(), till: ch.eq_ignore_ascii_case(&'t') });
+            }
+            KeyCode::Char('g') => {
+                self.count = (count > 1).then_some(count);
+                self.pending = Some(Pending::Goto);
+            }
+            KeyCode::Char(' ') => self.pending = Some(Pending::Space),
+            KeyCode::Char('r') => self.pending = Some(Pending::Replace),
+            KeyCode::Char('x') => self.select_lines(buffer, count),
+            KeyCode::Char('%') => {
+                self.anchor = Position::origin();
+                self.head = Self::buffer_end(buffer);
+            }
+            KeyCode::Char(';') if alt => std::mem::swap(&mut self.anchor, &mut self.head),
+            KeyCode::Char(';') => self.anchor = self.head,
+            KeyCode::Char('v') => {
+                self.mode = if self.mode == HelixMode::Select { HelixMode::Normal } else { HelixMode::Select };
+            }
+            KeyCode::Char('d') => {
+                let deleted = self.delete_selection(buffer)?;
+                if !alt {
+                    self.register = Some(deleted);
+                }
+            }
+            KeyCode::Char('c') => {
+                let deleted = self.delete_selection(buffer)?;
+                if !alt {
+                    self.register = Some(deleted);
+                }
+                self.mode = HelixMode::Insert;
+            }
+            KeyCode::Char('y') => {
+                let text = buffer.text_in_range(&self.selection(buffer));
+                self.message = Some(format!("Yanked {} characters", text.chars().count()));
+                self.register = Some(text);
+            }
+            KeyCode::Char('p') => self.paste(buffer, true)?,
+            KeyCode::Char('P') => self.paste(buffer, false)?,
+            KeyCode::Char('R') => {
+                let text = self.register.clone().ok_or_else(|| RuskError::Command("Nothing yanked".to_string()))?;
+                self.replace_selection(buffer, &text)?;
+            }
+            KeyCode::Char('~') => self.map_selection(buffer, |ch| {
+                if ch.is_uppercase() { ch.to_lowercase().collect() } else { ch.to_uppercase().collect() }
+            })?,
+            KeyCode::Char('`') if alt => self.map_selection(buffer, |ch| ch.to_uppercase().collect())?,
+            KeyCode::Char('`') => self.map_selection(buffer, |ch| ch.to_lowercase().collect())?,
+            KeyCode::Char('J') => self.join_lines(buffer)?,
+            KeyCode::Char('i') => {
+                let (start, _) = Self::ordered(self.anchor, self.head);
+                self.enter_insert(buffer, start)?;
+            }
+            KeyCode::Char('a') => {
+                let end = self.selection(buffer).end;
+                self.enter_insert(buffer, end)?;
+            }
+            KeyCode::Char('I') => {
+                let start = Self::first_non_blank(buffer, self.head.line);
+                self.enter_insert(buffer, start)?;
+            }
+            KeyCode::Char('A') => {
+                let end = Self::line_end(buffer, self.head.line);
+                self.enter_insert(buffer, end)?;
+            }
+            KeyCode::Char(ch @ ('o' | 'O')) => {
+                let line = self.head.line;
+                let indentation: String = buffer.lines()[line].chars().take_while(|ch| *ch == ' ' || *ch == '\t').collect();
+                self.enter_insert(buffer, Self::point(buffer))?;
+                let cursor = if ch == 'o' {
+                    buffer.insert_text(Self::line_end(buffer, line), &format!("\n{}", indentation))
+                } else {
+                    buffer.insert_text(Position::new(line, 0), &format!("{}\n", indentation));
+                    Position::new(line, indentation.len())
+                };
+                buffer.set_cursor_position(cursor.line, cursor.column);
+            }
+            KeyCode::Char('u') => self.undo(buffer, count)?,
+            KeyCode::Char('U') => self.redo(buffer, count)?,
+            KeyCode::Char(':') => self.prompt = Some(Prompt { kind: PromptKind::Command, input: String::new() }),
+            KeyCode::Char('/') => self.prompt = Some(Prompt { kind: PromptKind::Search, input: String::new() }),
+            KeyCode::Char(ch @ ('n' | 'N')) => {
+                let regex = self.last_search.clone().ok_or_else(|| RuskError::Command("No previous search".to_string()))?;
+                for _ in 0..count {
+                    self.search_next(buffer, &regex, ch == 'n')?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_pending_key(&mut self, pending: Pending, key: KeyEvent, count: usize, buffer: &mut Buffer) -> Result<()> {
+        let KeyCode::Char(ch) = key.code else {
+            return Ok(());
+        };
+        match pending {
+            Pending::Goto => {
+                let target = match ch {
+                    'g' => Self::first_non_blank(buffer, (count.max(1) - 1).min(buffer.line_count() - 1)),
+                    'e' => Position::new(buffer.line_count() - 1, 0),
+                    'h' => Position::new(self.head.line, 0),
+                    'l' => Self::line_end(buffer, self.head.line),
+                    's' => Self::first_non_blank(buffer, self.head.line),
+                    _ => return Err(RuskError::Command(format!("g{} is not a goto command", ch))),
+                };
+                self.move_head(buffer, target);
+            }
+            Pending::Space => match ch {
+                'f' => self.commands.push("find_file".to_string()),
+                'e' => self.commands.push("toggle_explorer".to_string()),
+                'E' => self.commands.push("reveal_in_explorer".to_string()),
+                _ => return Err(RuskError::Command(format!("Space {} is not bound", ch))),
+            },
+            Pending::Replace => self.map_selection(buffer, |_| ch.to_string())?,
+            Pending::Find { forward, till } => {
+                self.repeat_select(buffer, count, |buffer, from| {
+                    let text = &buffer.lines()[from.line];
+                    let found = if forward {
+                        let after = from.column + Self::char_at(buffer, from).map_or(0, char::len_utf8);
+                        text[after..].find(ch).map(|offset| after + offset)
+                    } else {
+                        text[..from.column].rfind(ch)
+                    };
+                    let target = found.map_or(from, |column| {
+                        let target = Position::new(from.line, column);
+                        match (till, forward) {
+                            (true, true) => Self::previous_char(buffer, target),
+                            (true, false) => Self::next_char(buffer, target),
+                            _ => target,
+                        }
+                    });
+                    (from, target)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_insert_key(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<()> {
+        let point = Self::point(buffer);
+        match key.code {
+            KeyCode::Esc => {
+                self.mode = HelixMode::Normal;
+                self.anchor = point;
+                self.head = point;
+            }
+            KeyCode::Char(ch) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                let end = buffer.insert_text(point, &ch.to_string());
+                buffer.set_cursor_position(end.line, end.column);
+            }
+            KeyCode::Enter => {
+                let end = buffer.insert_text(point, "\n");
+                buffer.set_cursor_position(end.line, end.column);
+            }
+            KeyCode::Tab => {
+                let text = if self.insert_spaces {
+                    let column = buffer.lines()[point.line][..point.column].chars().count();
+                    " ".repeat(self.tab_size - column % self.tab_size)
+                } else {
+                    "\t".to_string()
+                };
+                let end = buffer.insert_text(point, &text);
+                buffer.set_cursor_position(end.line, end.column);
+            }
+            KeyCode::Backspace => {
+                buffer.delete_range(&TextRange::new(Self::previous_char(buffer, point), point));
+            }
+            KeyCode::Delete => {
+                buffer.delete_range(&TextRange::new(point, Self::next_char(buffer, point)));
+            }
+            KeyCode::Left => buffer.move_cursor_left(),
+            KeyCode::Right => buffer.move_cursor_right(),
+            KeyCode::Up => buffer.move_cursor_up(),
+            KeyCode::Down => buffer.move_cursor_down(),
+            KeyCode::Home => buffer.move_cursor_line_start(),
+            KeyCode::End => buffer.move_cursor_line_end(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_prompt_key(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<()> {
+        let Some(prompt) = &mut self.prompt else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Esc => self.prompt = None,
+            KeyCode::Backspace if prompt.input.is_empty() => self.prompt = None,
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => prompt.input.push(ch),
+            KeyCode::Enter => {
+                let Prompt { kind, input } = self.prompt.take().unwrap_or(Prompt { kind: PromptKind::Command, input: String::new() });
+                if input.trim().is_empty() {
+                    return Ok(());
+                }
+                match kind {
+                    PromptKind::Command => self.commands.push(input),
+                    PromptKind::Search => {
+                        let regex = Regex::new(&input).map_err(|e| RuskError::Command(format!("Invalid regex: {}", e)))?;
+                        self.last_search = Some(regex.clone());
+                        self.search_next(buffer, &regex, true)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Move the head, taking the anchor along unless in select mode
+    fn move_head(&mut self, buffer: &Buffer, target: Position) {
+        self.head = Self::clamp(buffer, target);
+        if self.mode != HelixMode::Select {
+            self.anchor = self.head;
+        }
+    }
+
+    fn repeat_move(&mut self, buffer: &Buffer, count: usize, step: fn(&Buffer, Position) -> Position) {
+        let target = (0..count).fold(self.head, |position, _| step(buffer, position));
+        self.move_head(buffer, target);
+    }
+
+    fn move_vertically(&mut self, buffer: &Buffer, lines: isize) {
+        let line = self.head.line.saturating_add_signed(lines).min(buffer.line_count() - 1);
+        let column = buffer.lines()[self.head.line][..self.head.column].chars().count();
+        let text = &buffer.lines()[line];
+        let column = text.char_indices().nth(column).map_or(text.len(), |(offset, _)| offset);
+        self.move_head(buffer, Position::new(line, column));
+    }
+
+    /// Select what a motion covers `count` times, keeping only the last selection
+    ///
+    /// The motion gets the position to start from and returns the anchor
+    /// and head of the selection; select mode keeps the anchor instead.
+    fn repeat_select(&mut self, buffer: &Buffer, count: usize, motion: impl Fn(&Buffer, Position) -> (Position, Position)) {
+        for _ in 0..count {
+            let (anchor, head) = motion(buffer, self.head);
+            if self.mode != HelixMode::Select {
+                self.anchor = anchor;
+            }
+            self.head = head;
+        }
+    }
+
+    /// Select the lines of the selection, or extend a selection of whole lines by `count` lines
+    fn select_lines(&mut self, buffer: &Buffer, count: usize) {
+        let (start, end) = Self::ordered(self.anchor, self.head);
+        let whole = start.column == 0 && end == Self::line_end(buffer, end.line);
+        let last = if whole { end.line + count } else { end.line + count - 1 };
+        let last = last.min(buffer.line_count() - 1);
+        self.anchor = Position::new(start.line, 0);
+        self.head = Self::line_end(buffer, last);
+    }
+
+    fn ensure_writable(buffer: &Buffer) -> Result<()> {
+        if buffer.is_read_only() {
+            return Err(RuskError::Command("Buffer is read-only".to_string()));
+        }
+        Ok(())
+    }
+
+    fn enter_insert(&mut self, buffer: &mut Buffer, position: Position) -> Result<()> {
+        Self::ensure_writable(buffer)?;
+        buffer.checkpoint();
+        self.mode = HelixMode::Insert;
+        buffer.set_cursor_position(position.line, position.column);
+        Ok(())
+    }
+
+    fn delete_selection(&mut self, buffer: &mut Buffer) -> Result<String> {
+        Self::ensure_writable(buffer)?;
+        buffer.checkpoint();
+        let range = self.selection(buffer);
+        let deleted = buffer.delete_range(&range);
+        self.anchor = Self::clamp(buffer, range.start);
+        self.head = self.anchor;
+        Ok(deleted)
+    }
+
+    /// Replace the selection with text, selecting the new text
+    fn replace_selection(&mut self, buffer: &mut Buffer, text: &str) -> Result<()> {
+        Self::ensure_writable(buffer)?;
+        buffer.checkpoint();
+        let range = self.selection(buffer);
+        let end = buffer.replace_range(&range, text);
+        self.select_inserted(buffer, range.start, end);
+        Ok(())
+    }
+
+    fn map_selection(&mut self, buffer: &mut Buffer, map: impl Fn(char) -> String) -> Result<()> {
+        let text = buffer.text_in_range(&self.selection(buffer));
+        let mapped: String = text.chars().map(|ch| if ch == '\n' { ch.to_string() } else { map(ch) }).collect();
+        self.replace_selection(buffer, &mapped)
+    }
+
+    fn paste(&mut self, buffer: &mut Buffer, after: bool) -> Result<()> {
+        let text = self.register.clone().ok_or_else(|| RuskError::Command("Nothing yanked".to_string()))?;
+        Self::ensure_writable(buffer)?;
+        buffer.checkpoint();
+        let range = self.selection(buffer);
+        let at = if after { range.end } else { range.start };
+        let end = buffer.insert_text(at, &text);
+        self.select_inserted(buffer, at, end);
+        Ok(())
+    }
+
+    fn select_inserted(&mut self, buffer: &Buffer, start: Position, end: Position) {
+        self.anchor = Self::clamp(buffer, start);
+        self.head = if end == start { self.anchor } else { Self::previous_char(buffer, end) };
+    }
+
+    fn join_lines(&mut self, buffer: &mut Buffer) -> Result<()> {
+        Self::ensure_writable(buffer)?;
+        let (start, end) = Self::ordered(self.anchor, self.head);
+        let last = if end.line == start.line { start.line + 1 } else { end.line };
+        if last >= buffer.line_count() {
+            return Ok(());
+        }
+        buffer.checkpoint();
+        for _ in start.line..last {
+            let line_end = Self::line_end(buffer, start.line);
+            let next = Self::first_non_blank(buffer, start.line + 1);
+            buffer.replace_range(&TextRange::new(line_end, next), " ");
+        }
+        let end = Self::line_end(buffer, start.line);
+        self.anchor = Position::new(start.line, 0);
+        self.head = Self::previous_char(buffer, end);
+        Ok(())
+    }
+
+    /// Select the next match of a regex after the selection, wrapping around the buffer unless turned off
+    fn search_next(&mut self, buffer: &Buffer, regex: &Regex, forward: bool) -> Result<()> {
+        let lines = buffer.lines();
+        let (start, end) = Self::ordered(self.anchor, self.head);
+        let count = lines.len();
+        let found = if forward {
+            let from = Self::next_char(buffer, end);
+            (0..=count).take_while(|step| self.wrap_around || from.line + step < count).find_map(|step| {
+                let line = (from.line + step) % count;
+                let column = if step == 0 { from.column.min(lines[line].len()) } else { 0 };
+                regex.find_at(&lines[line], column).filter(|m| !m.is_empty()).map(|m| (line, m.start(), m.end()))
+            })
+        } else {
+            (0..=count).take_while(|step| self.wrap_around || *step <= start.line).find_map(|step| {
+                let line = (start.line + count - step % count) % count;
+                regex.find_iter(&lines[line])
+                    .filter(|m| !m.is_empty() && (step > 0 || m.start() < start.column))
+                    .last()
+                    .map(|m| (line, m.start(), m.end()))
+            })
+        };
+        let (line, from, to) = found.ok_or_else(|| RuskError::Command(format!("Pattern not found: {}", regex.as_str())))?;
+        self.anchor = Position::new(line, from);
+        self.head = Self::previous_char(buffer, Position::new(line, to));
+        Ok(())
+    }
+
+    fn undo(&mut self, buffer: &mut Buffer, count: usize) -> Result<()> {
+        for _ in 0..count {
+            if !buffer.undo() {
+                return Err(RuskError::Command("Already at oldest change".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    fn redo(&mut self, buffer: &mut Buffer, count: usize) -> Result<()> {
+        for _ in 0..count {
+            if !buffer.redo() {
+                return Err(RuskError::Command("Already at newest change".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Take over selections and cursor moves made outside the mode
+    fn sync(&mut self, buffer: &Buffer) {
+        let selection = buffer.selection_range();
+        if self.mode == HelixMode::Insert {
+            self.head = Self::point(buffer);
+            self.anchor = self.head;
+        } else if selection != self.shown {
+            match selection.filter(|range| !range.is_empty()) {
+                Some(range) => {
+                    self.anchor = range.start;
+                    self.head = Self::previous_char(buffer, range.end);
+                }
+                None => {
+                    self.head = Self::point(buffer);
+                    self.anchor = self.head;
+                }
+            }
+        } else if Self::point(buffer) != self.head {
+            self.head = Self::point(buffer);
+            self.anchor = self.head;
+        }
+        self.anchor = Self::clamp(buffer, self.anchor);
+        self.head = Self::clamp(buffer, self.head);
+    }
+
+    /// Show the selection in the buffer, or no selection while inserting
+    fn show(&mut self, buffer: &mut Buffer) {
+        if self.mode == HelixMode::Insert {
+            if self.shown.take().is_some() {
+                buffer.clear_selection();
+            }
+            return;
+        }
+        self.anchor = Self::clamp(buffer, self.anchor);
+        self.head = Self::clamp(buffer, self.head);
+        let selection = self.selection(buffer);
+        buffer.select_range(&selection);
+        buffer.set_cursor_position(self.head.line, self.head.column);
+        self.shown = Some(selection);
+    }
+
+    fn ordered(a: Position, b: Position) -> (Position, Position) {
+        if (b.line, b.column) < (a.line, a.column) { (b, a) } else { (a, b) }
+    }
+
+    fn point(buffer: &Buffer) -> Position {
+        let (line, column) = buffer.cursor_position();
+        Position::new(line, column)
+    }
+
+    fn buffer_end(buffer: &Buffer) -> Position {
+        Self::line_end(buffer, buffer.line_count() - 1)
+    }
+
+    fn line_end(buffer: &Buffer, line: usize) -> Position {
+        Position::new(line, buffer.lines()[line].len())
+    }
+
+    fn first_non_blank(buffer: &Buffer, line: usize) -> Position {
+        let text = &buffer.lines()[line];
+        Position::new(line, text.len() - text.trim_start().len())
+    }
+
+    fn clamp(buffer: &Buffer, position: Position) -> Position {
+        let line = position.line.min(buffer.line_count() - 1);
+        let text = &buffer.lines()[line];
+        let mut column = position.column.min(text.len());
+        while !text.is_char_boundary(column) {
+            column -= 1;
+        }
+        Position::new(line, column)
+    }
+
+    /// Character at a position, `None` at the end of a line
+    fn char_at(buffer: &Buffer, position: Position) -> Option<char> {
+        buffer.lines()[position.line][position.column..].chars().next()
+    }
+
+    fn char_kind(ch: Option<char>, long: bool) -> CharKind {
+        match ch {
+            None => CharKind::Space,
+            Some(ch) if ch.is_whitespace() => CharKind::Space,
+            Some(ch) if long || ch.is_alphanumeric() || ch == '_' => CharKind::Word,
+            Some(_) => CharKind::Punctuation,
+        }
+    }
+
+    fn kind_at(buffer: &Buffer, position: Position, long: bool) -> CharKind {
+        Self::char_kind(Self::char_at(buffer, position), long)
+    }
+
+    /// Position after the character at a position, the line end counting as a character
+    fn next_char(buffer: &Buffer, position: Position) -> Position {
+        match Self::char_at(buffer, position) {
+            Some(ch) => Position::new(position.line, position.column + ch.len_utf8()),
+            None if position.line + 1 < buffer.line_count() => Position::new(position.line + 1, 0),
+            None => position,
+        }
+    }
+
+    /// Position of the character before a position, the line end counting as a character
+    fn previous_char(buffer: &Buffer, position: Position) -> Position {
+        match buffer.lines()[position.line][..position.column].chars().next_back() {
+            Some(ch) => Position::new(position.line, position.column - ch.len_utf8()),
+            None if position.line > 0 => Self::line_end(buffer, position.line - 1),
+            None => position,
+        }
+    }
+
+    /// Where a word motion from a position starts: the next character when the position ends a word
+    fn word_motion_start(buffer: &Buffer, position: Position, long: bool) -> Position {
+        let next = Self::next_char(buffer, position);
+        if next != position && Self::kind_at(buffer, next, long) != Self::kind_at(buffer, position, long) { next } else { position }
+    }
+
+    /// Start of the word after the one at a position, skipping whitespace
+    fn next_word_start(buffer: &Buffer, position: Position, long: bool) -> Position {
+        let mut position = position;
+        let kind = Self::kind_at(buffer, position, long);
+        if kind != CharKind::Space {
+            while Self::kind_at(buffer, position, long) == kind && Self::next_char(buffer, position) != position {
+                position = Self::next_char(buffer, position);
+            }
+        }
+        while Self::kind_at(buffer, position, long) == CharKind::Space && Self::next_char(buffer, position) != position {
+            position = Self::next_char(buffer, position);
+        }
+        position
+    }
+
+    /// Last character of the word at or after a position
+    fn word_end(buffer: &Buffer, position: Position, long: bool) -> Position {
+        let mut position = position;
+        while Self::kind_at(buffer, position, long) == CharKind::Space && Self::next_char(buffer, position) != position {
+            position = Self::next_char(buffer, position);
+        }
+        let kind = Self::kind_at(buffer, position, long);
+        loop {
+            let next = Self::next_char(buffer, position);
+            if next == position || Self::kind_at(buffer, next, long) != kind {
+                return position;
+            }
+            position = next;
+        }
+    }
+
+    /// Start of the word before a position, skipping whitespace
+    fn previous_word_start(buffer: &Buffer, position: Position, long: bool) -> Position {
+        let mut position = Self::previous_char(buffer, position);
+        while Self::kind_at(buffer, position, long) == CharKind::Space && position != Position::origin() {
+            position = Self::previous_char(buffer, position);
+        }
+        let kind = Self::kind_at(buffer, position, long);
+        loop {
+            let previous = Self::previous_char(buffer, position);
+            if previous == position || Self::kind_at(buffer, previous, long) != kind {
+                return position;
+            }
+            position = previous;
+        }
+    }
+}
+
+impl Default for HelixPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for HelixPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn configure(&mut self, settings: &toml::Value) -> Result<()> {
+        for (key, value) in settings.as_table().into_iter().flatten() {
+            match key.as_str() {
+                "wrap_around" => {
+                    self.wrap_around = value.as_bool().ok_or_else(|| {
+                        RuskError::Config("plugins.plugin_configs.helix.wrap_around must be true or false".to_string())
+                    })?;
+                }
+                _ => return Err(RuskError::Config(format!("Unknown helix setting: {}", key))),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EditingMode for HelixPlugin {
+    fn set_config(&mut self, config: &Config) {
+        self.tab_size = config.editor.tab_size.max(1);
+        self.insert_spaces = config.editor.insert_spaces;
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<bool> {
+        self.sync(buffer);
+        if let Err(e) = self.dispatch(key, buffer) {
+            self.message = Some(e.to_string());
+            self.count = None;
+            self.pending = None;
+        }
+        self.show(buffer);
+        Ok(false)
+    }
+
+    fn editor_mode(&self) -> EditorMode {
+        if self.prompt.is_some() {
+            return EditorMode::Command;
+        }
+        match self.mode {
+            HelixMode::Normal => EditorMode::Normal,
+            HelixMode::Select => EditorMode::Visual,
+            HelixMode::Insert => EditorMode::Insert,
+        }
+    }
+
+    fn mode_name(&self) -> String {
+        match self.mode {
+            HelixMode::Normal => "NOR",
+            HelixMode::Select => "SEL",
+            HelixMode::Insert => "INS",
+        }.to_string()
+    }
+
+    fn pending_keys(&self) -> Option<String> {
+        let count = self.count.map(|count| count.to_string()).unwrap_or_default();
+        let key = match self.pending {
+            Some(Pending::Goto) => "g",
+            Some(Pending::Space) => "space",
+            Some(Pending::Replace) => "r",
+            Some(Pending::Find { forward: true, till: false }) => "f",
+            Some(Pending::Find { forward: true, till: true }) => "t",
+            Some(Pending::Find { forward: false, till: false }) => "F",
+            Some(Pending::Find { forward: false, till: true }) => "T",
+            None => "",
+        };
+        let pending = count + key;
+        (!pending.is_empty()).then_some(pending)
+    }
+
+    fn command_line(&self) -> Option<String> {
+        let prompt = self.prompt.as_ref()?;
+        let leader = match prompt.kind {
+            PromptKind::Command => ":",
+            PromptKind::Search => "/",
+        };
+        Some(format!("{}{}", leader, prompt.input))
+    }
+
+    fn uses_keybindings(&self) -> bool {
+        false
+    }
+
+    fn execute(&mut self, command: &str, buffer: &mut Buffer) -> Option<Result<bool>> {
+        self.sync(buffer);
+        let result = match command {
+            "undo" => self.undo(buffer, 1),
+            "redo" => self.redo(buffer, 1),
+            _ => return None,
+        };
+        self.show(buffer);
+        Some(result.map(|_| false))
+    }
+
+    fn take_commands(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.commands)
+    }
+
+    fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(helix: &mut HelixPlugin, buffer: &mut Buffer, keys: &str) {
+        for ch in keys.chars() {
+            let key = match ch {
+                '\u{1b}' => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+                '\n' => KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+                ch => KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE),
+            };
+            helix.handle_key(key, buffer).unwrap();
+        }
+    }
+
+    fn buffer(text: &str) -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::origin(), text);
+        buffer.set_cursor_position(0, 0);
+        buffer
+    }
+
+    #[test]
+    fn test_motions_select_and_actions_use_selection() {
+        let mut helix = HelixPlugin::new();
+        let mut buffer = buffer("alpha beta gamma\nsecond line");
+
+        press(&mut helix, &mut buffer, "w");
+        assert_eq!(buffer.get_selection().as_deref(), Some("alpha "));
+        press(&mut helix, &mut buffer, "e");
+        assert_eq!(buffer.get_selection().as_deref(), Some("beta"));
+        press(&mut helix, &mut buffer, "d");
+        assert_eq!(buffer.lines()[0], "alpha  gamma");
+
+        // Select mode extends, and paste inserts before the selection
+        press(&mut helix, &mut buffer, "ggvllly");
+        assert_eq!(helix.mode_name(), "SEL");
+        assert_eq!(buffer.get_selection().as_deref(), Some("alph"));
+        press(&mut helix, &mut buffer, "\u{1b}ggP");
+        assert_eq!(buffer.lines()[0], "alphalpha  gamma");
+
+        press(&mut helix, &mut buffer, "jxc");
+        assert_eq!(helix.editor_mode(), EditorMode::Insert);
+        press(&mut helix, &mut buffer, "new\u{1b}");
+        assert_eq!(buffer.lines(), ["alphalpha  gamma", "new"]);
+
+        press(&mut helix, &mut buffer, "u");
+        assert_eq!(buffer.lines(), ["alphalpha  gamma", "second line"]);
+        press(&mut helix, &mut buffer, "U");
+        assert_eq!(buffer.lines()[1], "new");
+    }
+
+    #[test]
+    fn test_counts_search_and_commands() {
+        let mut helix = HelixPlugin::new();
+        let mut buffer = buffer("one two three four\nfive six");
+
+        press(&mut helix, &mut buffer, "2");
+        assert_eq!(helix.pending_keys().as_deref(), Some("2"));
+        press(&mut helix, &mut buffer, "w");
+        assert_eq!(buffer.get_selection().as_deref(), Some("two "));
+
+        press(&mut helix, &mut buffer, "/f[a-z]+\n");
+        assert_eq!(buffer.get_selection().as_deref(), Some("four"));
+        press(&mut helix, &mut buffer, "n");
+        assert_eq!(buffer.get_selection().as_deref(), Some("five"));
+
+        press(&mut helix, &mut buffer, "x~");
+        assert_eq!(buffer.lines()[1], "FIVE SIX");
+
+        press(&mut helix, &mut buffer, ":w");
+        assert_eq!(helix.command_line().as_deref(), Some(":w"));
+        press(&mut helix, &mut buffer, "\n f");
+        assert_eq!(helix.take_commands(), ["w", "find_file"]);
+    }
+}
@@ -0,0 +1,386 @@
+//! Language server commands
+//!
+//! Hover, go to definition, references, formatting, code actions and
+//! rename run as commands, bound to `<leader>` keys and `F2` by default.
+//! Rename and code action choice prompt in the command line, which takes
+//! every key until the prompt is answered or dismissed. Requests run in the
+//! background; `poll` applies a response once it arrives, so editing goes on
+//! while a server works.
+
+use crate::core::buffer::Buffer;
+use crate::plugins::implementations::language::lsp::manager::{apply_text_edits, location_position};
+use crate::plugins::implementations::language::lsp::{CodeAction, Location, LspManager, LspRequest, TextEdit, WorkspaceEdit};
+use crossterm::event::{KeyCode, KeyEvent};
+use std::path::Path;
+
+/// Language server features that commands ask for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LspAction {
+    Hover,
+    Definition,
+    References,
+    Format,
+    CodeActions,
+    /// Rename to a name, or prompt for one
+    Rename(Option<String>),
+}
+
+/// Input being collected in the command line
+enum LspPrompt {
+    Rename(String),
+    CodeAction(Vec<CodeAction>),
+}
+
+/// Request waiting for its response
+enum InFlight {
+    Hover(LspRequest<Option<String>>),
+    Definition(LspRequest<Vec<Location>>),
+    References(LspRequest<Vec<Location>>),
+    Format(LspRequest<Vec<TextEdit>>),
+    CodeActions(LspRequest<Vec<CodeAction>>),
+    Rename(LspRequest<WorkspaceEdit>, String),
+    Command(LspRequest<()>, String),
+}
+
+/// An in-flight request with the buffer state it was sent for
+struct Sent {
+    request: InFlight,
+    path: Option<String>,
+    version: u64,
+}
+
+/// Key handler for language server features
+pub struct LspCommands {
+    manager: Option<LspManager>,
+    prompt: Option<LspPrompt>,
+    in_flight: Option<Sent>,
+    message: Option<String>,
+    saved_version: Option<u64>,
+    tab_size: usize,
+    insert_spaces: bool,
+}
+
+impl LspCommands {
+    pub fn new() -> Self {
+        Self {
+            manager: None,
+            prompt: None,
+            in_flight: None,
+            message: None,
+            saved_version: None,
+            tab_size: 4,
+            insert_spaces: true,
+        }
+    }
+
+    /// Attach the language server manager
+    pub fn set_manager(&mut self, manager: LspManager) {
+        self.manager = Some(manager);
+    }
+
+    /// Get the language server manager
+    pub fn manager(&self) -> Option<&LspManager> {
+        self.manager.as_ref()
+    }
+
+    /// Get the language server manager mutably
+    pub fn manager_mut(&mut self) -> Option<&mut LspManager> {
+        self.manager.as_mut()
+    }
+
+    /// Set the indentation sent with formatting requests
+    pub fn set_indentation(&mut self, tab_size: usize, insert_spaces: bool) {
+        self.tab_size = tab_size;
+        self.insert_spaces = insert_spaces;
+    }
+
+    /// Take the message produced by the last command
+    pub fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+
+    /// Text to show in the command line while prompting
+    pub fn prompt_text(&self) -> Option<String> {
+        match self.prompt.as_ref()? {
+            LspPrompt::Rename(name) => Some(format!("Rename to: {}", name)),
+            LspPrompt::CodeAction(actions) => {
+                let choices: Vec<String> = actions.iter()
+                    .enumerate()
+                    .map(|(i, action)| format!("{}:{}", i + 1, action.title))
+                    .collect();
+                Some(format!("Code action: {}", choices.join("  ")))
+            }
+        }
+    }
+
+    /// Send buffer changes and saves to the server, returning whether new diagnostics were attached
+    pub fn sync(&mut self, buffer: &mut Buffer) -> bool {
+        let Some(manager) = &mut self.manager else {
+            return false;
+        };
+
+        if let Err(e) = manager.sync_buffer(buffer) {
+            self.message.get_or_insert_with(|| e.to_string());
+        }
+
+        // A save shows up as the buffer going from modified to unmodified
+        if buffer.is_modified() {
+            self.saved_version = None;
+        } else if self.saved_version != Some(buffer.version()) {
+            self.saved_version = Some(buffer.version());
+            let _ = manager.save_buffer(buffer);
+        }
+
+        if !manager.poll() {
+            return false;
+        }
+        let diagnostics = manager.buffer_diagnostics(buffer);
+        buffer.diagnostics_mut().set("lsp", diagnostics);
+        true
+    }
+
+    /// Shut down all language servers
+    pub fn shutdown(&mut self) {
+        if let Some(manager) = &mut self.manager {
+            manager.shutdown_all();
+        }
+    }
+
+    /// Whether a rename or code action prompt takes the keys
+    pub fn is_prompting(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    /// Run a language server feature at the cursor
+    pub fn run(&mut self, action: LspAction, buffer: &mut Buffer) {
+        let Some(manager) = &self.manager else {
+            self.message = Some("No language servers configured".to_string());
+            return;
+        };
+        if buffer.file_path().is_none() || !manager.has_server(buffer.language()) {
+            self.message = Some(format!("No language server for {} files", buffer.language().language_id()));
+            return;
+        }
+
+        match action {
+            LspAction::Hover => self.send(buffer, |manager, buffer| manager.hover(buffer).map(InFlight::Hover)),
+            LspAction::Definition => self.send(buffer, |manager, buffer| manager.definition(buffer).map(InFlight::Definition)),
+            LspAction::References => self.send(buffer, |manager, buffer| manager.references(buffer).map(InFlight::References)),
+            LspAction::Format => {
+                let (tab_size, insert_spaces) = (self.tab_size, self.insert_spaces);
+                self.send(buffer, |manager, buffer| manager.formatting(buffer, tab_size, insert_spaces).map(InFlight::Format));
+            }
+            LspAction::CodeActions => self.send(buffer, |manager, buffer| manager.code_actions(buffer).map(InFlight::CodeActions)),
+            LspAction::Rename(Some(name)) => self.rename(buffer, name),
+            LspAction::Rename(None) => self.prompt = Some(LspPrompt::Rename(Self::word_at_cursor(buffer))),
+        }
+    }
+
+    /// Apply the response to the request in flight once it has arrived, returning whether it did
+    ///
+    /// Edits are dropped when the buffer changed since the request was sent,
+    /// and jumps when another buffer is shown.
+    pub fn poll(&mut self, buffer: &mut Buffer) -> bool {
+        let Some(mut sent) = self.in_flight.take() else {
+            return false;
+        };
+        let same_buffer = sent.path.as_deref() == buffer.file_path();
+        let unchanged = same_buffer && sent.version == buffer.version();
+
+        let message = match &mut sent.request {
+            InFlight::Hover(request) => match request.poll() {
+                None => return self.keep(sent),
+                Some(Ok(Some(text))) => text.lines().find(|line| !line.trim().is_empty()).unwrap_or_default().to_string(),
+                Some(Ok(None)) => "No hover information".to_string(),
+                Some(Err(e)) => e.to_string(),
+            },
+            InFlight::Definition(request) => match request.poll() {
+                None => return self.keep(sent),
+                Some(Ok(locations)) => match locations.first() {
+                    Some(location) if same_buffer => Self::jump_to(buffer, location).unwrap_or_default(),
+                    Some(_) => String::new(),
+                    None => "No definition found".to_string(),
+                },
+                Some(Err(e)) => e.to_string(),
+            },
+            InFlight::References(request) => match request.poll() {
+                None => return self.keep(sent),
+                Some(Ok(locations)) if same_buffer => Self::next_reference(buffer, &locations),
+                Some(Ok(_)) => String::new(),
+                Some(Err(e)) => e.to_string(),
+            },
+            InFlight::Format(request) => match request.poll() {
+                None => return self.keep(sent),
+                Some(Ok(edits)) if edits.is_empty() => "Already formatted".to_string(),
+                Some(Ok(_)) if !unchanged => "Buffer changed; formatting skipped".to_string(),
+                Some(Ok(edits)) => {
+                    apply_text_edits(buffer, &edits);
+                    format!("Applied {} formatting edits", edits.len())
+                }
+                Some(Err(e)) => e.to_string(),
+            },
+            InFlight::CodeActions(request) => match request.poll() {
+                None => return self.keep(sent),
+                Some(Ok(actions)) if actions.is_empty() => "No code actions".to_string(),
+                Some(Ok(_)) if !unchanged => "Buffer changed; code actions skipped".to_string(),
+                Some(Ok(actions)) => {
+                    self.prompt = Some(LspPrompt::CodeAction(actions.into_iter().take(9).collect()));
+                    String::new()
+                }
+                Some(Err(e)) => e.to_string(),
+            },
+            InFlight::Rename(request, new_name) => match request.poll() {
+                None => return self.keep(sent),
+                Some(Ok(_)) if !unchanged => "Buffer changed; rename skipped".to_string(),
+                Some(Ok(edit)) => {
+                    let files = edit.edits_by_uri().len();
+                    let applied = self.manager.as_mut()
+                        .map_or(Ok(()), |manager| manager.apply_workspace_edit(std::slice::from_mut(buffer), &edit));
+                    match (applied, files) {
+                        (Err(e), _) => e.to_string(),
+                        (Ok(()), 0) => "Nothing to rename".to_string(),
+                        (Ok(()), files) => format!("Renamed to '{}' in {} files", new_name, files),
+                    }
+                }
+                Some(Err(e)) => e.to_string(),
+            },
+            InFlight::Command(request, title) => match request.poll() {
+                None => return self.keep(sent),
+                Some(Ok(())) => format!("Applied '{}'", title),
+                Some(Err(e)) => e.to_string(),
+            },
+        };
+
+        if !message.is_empty() {
+            self.message = Some(message);
+        }
+        true
+    }
+
+    /// Put back a request whose response has not arrived
+    fn keep(&mut self, sent: Sent) -> bool {
+        self.in_flight = Some(sent);
+        false
+    }
+
+    /// Send a request for the buffer, replacing any still in flight
+    fn send(&mut self, buffer: &Buffer, request: impl FnOnce(&mut LspManager, &Buffer) -> crate::Result<InFlight>) {
+        let Some(manager) = &mut self.manager else { return };
+        match request(manager, buffer) {
+            Ok(request) => {
+                self.in_flight = Some(Sent {
+                    request,
+                    path: buffer.file_path().map(str::to_string),
+                    version: buffer.version(),
+                });
+            }
+            Err(e) => self.message = Some(e.to_string()),
+        }
+    }
+
+    /// Pass a key to the open prompt
+    pub fn handle_prompt_key(&mut self, key: KeyEvent, buffer: &mut Buffer) {
+        let Some(prompt) = &mut self.prompt else { return };
+
+        match (prompt, key.code) {
+            (_, KeyCode::Esc) => self.prompt = None,
+            (LspPrompt::Rename(name), KeyCode::Char(ch)) => name.push(ch),
+            (LspPrompt::Rename(name), KeyCode::Backspace) => {
+                name.pop();
+            }
+            (LspPrompt::Rename(name), KeyCode::Enter) => {
+                let name = std::mem::take(name);
+                self.prompt = None;
+                if !name.is_empty() {
+                    self.rename(buffer, name);
+                }
+            }
+            (LspPrompt::CodeAction(actions), KeyCode::Char(ch)) => {
+                let choice = ch.to_digit(10).and_then(|n| (n as usize).checked_sub(1));
+                if let Some(action) = choice.and_then(|i| actions.get(i)).cloned() {
+                    self.prompt = None;
+                    self.run_code_action(buffer, &action);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Jump to the next reference in this file, wrapping around, and describe them all
+    fn next_reference(buffer: &mut Buffer, locations: &[Location]) -> String {
+        let (line, col) = buffer.cursor_position();
+        let local: Vec<&Location> = locations.iter().filter(|l| Self::is_buffer_location(buffer, l)).collect();
+        let next = local.iter()
+            .find(|l| {
+                let pos = location_position(buffer, l);
+                (pos.line, pos.column) > (line, col)
+            })
+            .or(local.first())
+            .copied()
+            .cloned();
+
+        if let Some(location) = next {
+            Self::jump_to(buffer, &location);
+        }
+        format!("{} references ({} in other files)", locations.len(), locations.len() - local.len())
+    }
+
+    fn run_code_action(&mut self, buffer: &mut Buffer, action: &CodeAction) {
+        let Some(manager) = &mut self.manager else { return };
+
+        if let Some(edit) = &action.edit
+            && let Err(e) = manager.apply_workspace_edit(std::slice::from_mut(buffer), edit)
+        {
+            self.message = Some(e.to_string());
+            return;
+        }
+        match &action.command {
+            Some(command) => {
+                let title = action.title.clone();
+                let language = buffer.language().clone();
+                self.send(buffer, |manager, _| manager.execute_command(&language, command).map(|request| InFlight::Command(request, title)));
+            }
+            None => self.message = Some(format!("Applied '{}'", action.title)),
+        }
+    }
+
+    fn rename(&mut self, buffer: &Buffer, new_name: String) {
+        self.send(buffer, |manager, buffer| manager.rename(buffer, &new_name).map(|request| InFlight::Rename(request, new_name)));
+    }
+
+    /// Move the cursor to a location in this buffer, or describe where it is
+    fn jump_to(buffer: &mut Buffer, location: &Location) -> Option<String> {
+        if Self::is_buffer_location(buffer, location) {
+            let pos = location_position(buffer, location);
+            buffer.set_cursor_position(pos.line, pos.column);
+            buffer.reveal_cursor_line();
+            None
+        } else {
+            let path = location.path().map(|p| p.display().to_string()).unwrap_or_else(|| location.uri.clone());
+            Some(format!("{}:{}", path, location.range.start.line + 1))
+        }
+    }
+
+    fn is_buffer_location(buffer: &Buffer, location: &Location) -> bool {
+        let (Some(path), Some(target)) = (buffer.file_path(), location.path()) else {
+            return false;
+        };
+        Path::new(path).canonicalize().ok() == target.canonicalize().ok()
+    }
+
+    fn word_at_cursor(buffer: &Buffer) -> String {
+        let (_, col) = buffer.cursor_position();
+        let line = buffer.current_line();
+        let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+
+        let start = line[..col.min(line.len())].char_indices().rev().find(|(_, ch)| !is_word(*ch)).map_or(0, |(i, ch)| i + ch.len_utf8());
+        let end = line[start..].find(|ch: char| !is_word(ch)).map_or(line.len(), |i| start + i);
+        line[start..end].to_string()
+    }
+}
+
+impl Default for LspCommands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -3,4 +3,10 @@
 //! This module contains different editing mode implementations
 //! like vim, emacs, etc.
 
-pub mod vim;
\ No newline at end of file
+pub mod mode;
+pub mod vim;
+pub mod vim_grammar;
+pub mod emacs;
+pub mod helix;
+pub mod structural;
+pub mod lsp_commands;
\ No newline at end of file
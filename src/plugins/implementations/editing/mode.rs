@@ -0,0 +1,318 @@
+//! Editing mode abstraction
+//!
+//! An editing mode turns keys into edits of the current buffer. The user
+//! interface talks to the mode chosen in the configuration through the
+//! `EditingMode` trait, so vim, emacs and helix style input are
+//! interchangeable.
+
+use crate::core::buffer::Buffer;
+use crate::core::cursor::{Position, TextRange};
+use crate::core::state::PropertySpec;
+use crate::config::settings::ColorScheme;
+use crate::plugins::implementations::editing::vim::{VimMode, VimPlugin};
+use crate::plugins::implementations::editing::vim_grammar::VimGrammar;
+use crate::plugins::implementations::language::textobjects::TextObjectProvider;
+use crate::plugins::Plugin;
+use crate::services::command_system::{CommandArgs, EditorMode};
+use crate::services::operators::{MotionEnv, Operator};
+use crate::services::registers::Registers;
+use crate::{Config, Result};
+use crossterm::event::KeyEvent;
+use ratatui::style::Color;
+
+/// Shape of the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    Block,
+    Bar,
+    Underline,
+}
+
+/// What the interface lends a mode's grammar while it handles a key
+pub struct GrammarContext<'a> {
+    pub registers: &'a mut Registers,
+    /// Visible lines and indentation motions and operators use
+    pub env: MotionEnv,
+    /// Whether the keybindings are partway through a key sequence
+    pub keys_pending: bool,
+    /// Whether the key starts a binding in the current mode
+    pub bound: bool,
+    /// Whether a macro is being recorded
+    pub recording: bool,
+    /// Finds syntax text objects like `af`, when syntax trees are available
+    pub text_objects: Option<&'a mut TextObjectProvider>,
+}
+
+/// What the interface does once a mode's grammar took a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrammarAction {
+    /// Nothing more
+    Done,
+    /// Quit the editor
+    Quit,
+    /// The key failed, stopping a playing macro, with a message to show
+    Failed(Option<String>),
+    /// The cursor jumped from a position, which the jump list remembers
+    Jumped((usize, usize)),
+    /// Run a command, as setting or going to a mark does
+    Command(String, CommandArgs),
+    /// Open the `:` command line with a range already typed
+    OpenCommandLine(String),
+    /// Record the keys typed into a register
+    StartRecording(char),
+    StopRecording,
+    /// Play the macro in a register a number of times
+    PlayMacro { register: char, count: usize },
+}
+
+/// Input model driving the editor
+pub trait EditingMode: Plugin {
+    /// Apply settings such as the tab size
+    fn set_config(&mut self, config: &Config);
+
+    /// Handle a key, returning whether the editor should quit
+    fn handle_key(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<bool>;
+
+    /// Mode that keybindings and command conditions are checked against
+    fn editor_mode(&self) -> EditorMode;
+
+    /// Name shown in the status line
+    fn mode_name(&self) -> String;
+
+    /// Colour of the mode name in the status line, `None` for the status bar colour
+    fn mode_color(&self) -> Option<Color> {
+        mode_color(self.editor_mode())
+    }
+
+    /// Shape of the cursor
+    fn cursor_shape(&self) -> CursorShape {
+        match self.editor_mode() {
+            EditorMode::Insert => CursorShape::Bar,
+            _ => CursorShape::Block,
+        }
+    }
+
+    /// Keys of a command still being typed, like a count or a prefix key
+    fn pending_keys(&self) -> Option<String> {
+        None
+    }
+
+    /// Text for the command line, such as a command being typed
+    fn command_line(&self) -> Option<String>;
+
+    /// Whether keys go through the configured keybindings first
+    ///
+    /// Commands implemented natively by handing vim keys to the mode only
+    /// apply to modes that use the keybindings; other modes have their own
+    /// key maps and get every key directly.
+    fn uses_keybindings(&self) -> bool {
+        true
+    }
+
+    /// Run a command the mode implements itself, returning `None` when the command system should run it
+    fn execute(&mut self, _command: &str, _buffer: &mut Buffer) -> Option<Result<bool>> {
+        None
+    }
+
+    /// Take the command lines the mode asks the editor to run, like input to `M-x`
+    fn take_commands(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Take a message for the status line
+    fn take_message(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Handle a key of a grammar longer than the keybindings, like vim's
+    /// operators, registers, marks and macros; `None` leaves the key to the keybindings
+    fn handle_grammar_key(&mut self, _key: KeyEvent, _buffer: &mut Buffer, _context: GrammarContext) -> Result<Option<GrammarAction>> {
+        Ok(None)
+    }
+
+    /// Follow a key the keybindings handled, given the mode before it, such as to remember what an insert typed
+    fn key_handled(&mut self, _key: KeyEvent, _before: EditorMode, _buffer: &mut Buffer, _context: GrammarContext) {}
+
+    /// Take the count typed before a key the grammar left to the keybindings
+    fn take_count(&mut self) -> Option<usize> {
+        None
+    }
+
+    /// Wait for the motion an operator started by a command applies to, returning whether the mode has operators
+    fn start_operator(&mut self, _operator: Operator, _count: Option<usize>) -> bool {
+        false
+    }
+
+    /// Start or end selecting a block of columns, returning whether the mode selects blocks
+    fn toggle_block(&mut self, _buffer: &mut Buffer) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Byte ranges of the selected block on each of its rows
+    fn block_ranges(&self, _buffer: &Buffer, _tab_size: usize) -> Vec<TextRange> {
+        Vec::new()
+    }
+
+    /// Where an insert started on a block begins on each of its rows, the typed row first; empty outside one
+    fn block_insert_cursors(&self, _buffer: &Buffer) -> Vec<Position> {
+        Vec::new()
+    }
+
+    /// Leave the other rows of a block insert alone when it ends, because something else edited them
+    fn end_block_insert(&mut self) {}
+}
+
+impl EditingMode for VimPlugin {
+    fn set_config(&mut self, config: &Config) {
+        VimPlugin::set_config(self, config.clone());
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<bool> {
+        VimPlugin::handle_key(self, key, buffer)
+    }
+
+    fn editor_mode(&self) -> EditorMode {
+        match self.mode() {
+            VimMode::Normal => EditorMode::Normal,
+            VimMode::Insert => EditorMode::Insert,
+            VimMode::Visual | VimMode::VisualLine => EditorMode::Visual,
+            VimMode::Command => EditorMode::Command,
+        }
+    }
+
+    fn mode_name(&self) -> String {
+        match self.mode() {
+            VimMode::Normal => "NORMAL",
+            VimMode::Insert => "INSERT",
+            VimMode::Visual => "VISUAL",
+            VimMode::VisualLine => "V-LINE",
+            VimMode::Command => "COMMAND",
+        }.to_string()
+    }
+
+    fn command_line(&self) -> Option<String> {
+        (self.mode() == &VimMode::Command).then(|| self.command_buffer().to_string())
+    }
+}
+
+/// Vim editing: the vim plugin's modes with the normal mode grammar typed on top of them
+///
+/// A selected block is a visual mode of its own, `V-BLOCK`, while vim
+/// itself stays in normal mode and the grammar takes the keys.
+pub struct VimEditing {
+    vim: VimPlugin,
+    grammar: VimGrammar,
+}
+
+impl VimEditing {
+    pub fn new() -> Self {
+        Self { vim: VimPlugin::new(), grammar: VimGrammar::new() }
+    }
+}
+
+impl Default for VimEditing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for VimEditing {
+    fn name(&self) -> &str {
+        self.vim.name()
+    }
+
+    fn version(&self) -> &str {
+        self.vim.version()
+    }
+
+    fn initialize(&mut self) -> Result<()> {
+        self.vim.initialize()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.vim.shutdown()
+    }
+
+    fn properties(&self) -> Vec<PropertySpec> {
+        self.vim.properties()
+    }
+
+    fn dependencies(&self) -> Vec<String> {
+        self.vim.dependencies()
+    }
+
+    fn configure(&mut self, settings: &toml::Value) -> Result<()> {
+        self.vim.configure(settings)
+    }
+}
+
+impl EditingMode for VimEditing {
+    fn set_config(&mut self, config: &Config) {
+        EditingMode::set_config(&mut self.vim, config);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, buffer: &mut Buffer) -> Result<bool> {
+        EditingMode::handle_key(&mut self.vim, key, buffer)
+    }
+
+    fn editor_mode(&self) -> EditorMode {
+        if self.grammar.has_block() { EditorMode::Visual } else { self.vim.editor_mode() }
+    }
+
+    fn mode_name(&self) -> String {
+        if self.grammar.has_block() { "V-BLOCK".to_string() } else { self.vim.mode_name() }
+    }
+
+    fn pending_keys(&self) -> Option<String> {
+        self.grammar.pending_keys()
+    }
+
+    fn command_line(&self) -> Option<String> {
+        self.vim.command_line()
+    }
+
+    fn handle_grammar_key(&mut self, key: KeyEvent, buffer: &mut Buffer, context: GrammarContext) -> Result<Option<GrammarAction>> {
+        self.grammar.handle_key(key, &mut self.vim, buffer, context)
+    }
+
+    fn key_handled(&mut self, key: KeyEvent, before: EditorMode, buffer: &mut Buffer, mut context: GrammarContext) {
+        let after = self.vim.editor_mode();
+        self.grammar.track_insert(key, before, after, buffer, &mut context);
+    }
+
+    fn take_count(&mut self) -> Option<usize> {
+        self.grammar.take_count()
+    }
+
+    fn start_operator(&mut self, operator: Operator, count: Option<usize>) -> bool {
+        self.grammar.start_operator(operator, count);
+        true
+    }
+
+    fn toggle_block(&mut self, buffer: &mut Buffer) -> Result<bool> {
+        self.grammar.toggle_block(&mut self.vim, buffer)?;
+        Ok(true)
+    }
+
+    fn block_ranges(&self, buffer: &Buffer, tab_size: usize) -> Vec<TextRange> {
+        self.grammar.block_ranges(buffer, tab_size)
+    }
+
+    fn block_insert_cursors(&self, buffer: &Buffer) -> Vec<Position> {
+        self.grammar.block_insert_cursors(buffer)
+    }
+
+    fn end_block_insert(&mut self) {
+        self.grammar.end_block_insert();
+    }
+}
+
+/// Status line colour of an editor mode, `None` for the status bar colour
+pub fn mode_color(mode: EditorMode) -> Option<Color> {
+    match mode {
+        EditorMode::Normal => None,
+        EditorMode::Insert => Some(ColorScheme::parse_color("#16a085")),
+        EditorMode::Visual => Some(ColorScheme::parse_color("#f39c12")),
+        EditorMode::Command => Some(ColorScheme::parse_color("#9b59b6")),
+    }
+}
@@ -0,0 +1,93 @@
+//! Structural selection and navigation
+//!
+//! Grows the selection to the parent syntax node and back, moves it to
+//! sibling nodes, and goes to the next or previous function or class
+//! (`]f`, `[c`). These run as commands bound like any other key; syntax
+//! text objects (`af`, `ic`, `aa`, `i,`) are part of the vim grammar,
+//! which borrows the text object provider from here.
+
+use crate::core::buffer::Buffer;
+use crate::core::cursor::{Position, TextRange};
+use crate::plugins::implementations::editing::mode::EditingMode;
+use crate::services::command_system::EditorMode;
+use crate::plugins::implementations::language::textobjects::{SyntaxSelection, TextObjectKind, TextObjectProvider};
+use crate::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Syntax-aware selection over the text object provider
+pub struct StructuralEditing {
+    provider: Option<TextObjectProvider>,
+    /// Selections grown from, most recent last, which shrinking goes back to
+    selection_history: Vec<TextRange>,
+}
+
+impl StructuralEditing {
+    pub fn new() -> Self {
+        Self {
+            provider: TextObjectProvider::new().ok(),
+            selection_history: Vec::new(),
+        }
+    }
+
+    /// Get the text object provider, if the text object queries loaded
+    pub fn provider_mut(&mut self) -> Option<&mut TextObjectProvider> {
+        self.provider.as_mut()
+    }
+
+    /// Grow, shrink or move the selection, starting visual mode from normal mode
+    pub fn select(&mut self, step: SyntaxSelection, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<()> {
+        let Some(provider) = self.provider.as_mut() else {
+            return Ok(());
+        };
+        let (line, col) = buffer.cursor_position();
+        let cursor = TextRange::new(Position::new(line, col), Position::new(line, col));
+        let current = match editing.editor_mode() {
+            EditorMode::Visual => buffer.selection_range().unwrap_or(cursor),
+            _ => {
+                self.selection_history.clear();
+                cursor
+            }
+        };
+        // A selection changed by other keys has nothing to shrink back to
+        if self.selection_history.last().is_some_and(|last| !current.encloses(last)) {
+            self.selection_history.clear();
+        }
+
+        let next = match step {
+            SyntaxSelection::Expand => {
+                let expanded = provider.expand_selection(buffer, current);
+                if expanded.is_some() {
+                    self.selection_history.push(current);
+                }
+                expanded
+            }
+            SyntaxSelection::Shrink => self.selection_history.pop(),
+            SyntaxSelection::NextSibling => provider.sibling_selection(buffer, current, true),
+            SyntaxSelection::PreviousSibling => provider.sibling_selection(buffer, current, false),
+        };
+
+        if let Some(range) = next {
+            if editing.editor_mode() != EditorMode::Visual {
+                editing.handle_key(KeyEvent::new(KeyCode::Char('v'), KeyModifiers::NONE), buffer)?;
+            }
+            buffer.select_range(&range);
+        }
+        Ok(())
+    }
+
+    /// Move to the start of the next or previous text object of a kind, returning whether there was one
+    pub fn goto_object(&mut self, kind: TextObjectKind, forward: bool, buffer: &mut Buffer) -> bool {
+        let Some(pos) = self.provider.as_mut().and_then(|p| p.adjacent_object_start(buffer, kind, forward)) else {
+            return false;
+        };
+        buffer.set_cursor_position(pos.line, pos.column);
+        buffer.folds_mut().open(pos.line);
+        true
+    }
+}
+
+impl Default for StructuralEditing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,681 @@
+//! Vim's normal mode grammar
+//!
+//! Some vim keys start sequences longer than a keybinding: `"a` picks the
+//! register the next yank or put uses, `ma` sets a mark and `'a` goes to
+//! it, `qa` records a macro and `3@a` plays it, operators wait for a
+//! motion (`d2w`) or a text object (`daf` with the syntax tree's
+//! functions) and `Ctrl-v` selects a block of columns. The grammar
+//! keeps what has been typed of these, and what they leave behind such as
+//! the change `.` repeats, and tells the interface what to do with the
+//! services it owns, like playing a macro.
+
+use crate::core::buffer::Buffer;
+use crate::core::cursor::{Position, TextRange};
+use crate::plugins::implementations::editing::mode::{EditingMode, GrammarAction, GrammarContext};
+use crate::plugins::implementations::language::textobjects::{TextObjectKind, TextObjectScope};
+use crate::services::command_system::{ArgValue, CommandArgs, EditorMode};
+use crate::services::operators::{self, Motion, Operation, Operator, OperatorParser, Parsed};
+use crate::services::registers::{RegisterContent, WriteKind};
+use crate::services::visual_block::{self, VisualBlock};
+use crate::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::ops::RangeInclusive;
+
+/// What the register typed after a macro key is for
+#[derive(Debug, Clone, Copy)]
+enum MacroKey {
+    Record,
+    Play(usize),
+}
+
+/// What the key typed after a mark key is for
+#[derive(Debug, Clone, Copy)]
+enum MarkKey {
+    Set,
+    Goto,
+    GotoLine,
+}
+
+impl MarkKey {
+    /// Get the key that started it
+    fn key(self) -> char {
+        match self {
+            Self::Set => 'm',
+            Self::Goto => '`',
+            Self::GotoLine => '\'',
+        }
+    }
+
+    /// Get the command run with the mark's name
+    fn command(self) -> &'static str {
+        match self {
+            Self::Set => "set_mark",
+            Self::Goto => "goto_mark",
+            Self::GotoLine => "goto_mark_line",
+        }
+    }
+}
+
+/// Rows and column an insert on a block's first row is copied to when it ends
+#[derive(Debug, Clone)]
+struct BlockInsert {
+    start: Position,
+    rows: RangeInclusive<usize>,
+    column: Option<usize>,
+    pad: bool,
+    tab_size: usize,
+}
+
+/// Registers, marks, macros, operators and blocks typed in vim's normal mode
+#[derive(Debug, Clone, Default)]
+pub struct VimGrammar {
+    /// Waiting for a register name after `"`
+    pending_register: bool,
+    /// Waiting for the register after `q` or `@`
+    pending_macro: Option<MacroKey>,
+    /// Waiting for the mark after `m`, `'` or `` ` ``
+    pending_mark: Option<MarkKey>,
+    /// Waiting for the object after `a` (true) or `i` in visual mode
+    pending_object: Option<bool>,
+    /// Counts, operators and motions
+    operators: OperatorParser,
+    /// Last change and the text its insert typed, repeated by `.`
+    last_change: Option<(Operation, String)>,
+    /// Change whose insert is being typed, becoming the last change when it ends
+    pending_change: Option<Operation>,
+    /// Block selected after `Ctrl-v`, vim itself staying in normal mode meanwhile
+    block: Option<VisualBlock>,
+    /// Waiting for the character `r` replaces a block with
+    pending_block_replace: bool,
+    /// Rows that get the text of an insert started by `I`, `A` or `c` on a block
+    block_insert: Option<BlockInsert>,
+    /// Text typed in the current insert, kept for `".`
+    inserted: Option<String>,
+}
+
+impl VimGrammar {
+    /// Create a grammar with nothing typed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a block of columns is selected
+    pub fn has_block(&self) -> bool {
+        self.block.is_some()
+    }
+
+    /// Get the keys typed of a sequence that is not finished
+    pub fn pending_keys(&self) -> Option<String> {
+        let mut keys = String::new();
+        if self.pending_register {
+            keys.push('"');
+        }
+        if let Some(mark) = self.pending_mark {
+            keys.push(mark.key());
+        }
+        if let Some(around) = self.pending_object {
+            keys.push(if around { 'a' } else { 'i' });
+        }
+        match self.pending_macro {
+            Some(MacroKey::Record) => keys.push('q'),
+            Some(MacroKey::Play(_)) => keys.push('@'),
+            None => {}
+        }
+        if self.pending_block_replace {
+            keys.push('r');
+        }
+        keys.push_str(self.operators.typed());
+        (!keys.is_empty()).then_some(keys)
+    }
+
+    /// Take a count typed before a key the grammar leaves to the keybindings
+    pub fn take_count(&mut self) -> Option<usize> {
+        if self.operators.has_count() { self.operators.take_count() } else { None }
+    }
+
+    /// Wait for the motion an operator applies to
+    pub fn start_operator(&mut self, operator: Operator, count: Option<usize>) {
+        self.operators.start(operator, count);
+    }
+
+    /// Start a block at the cursor, or at the far end of a visual selection
+    /// turning into one; a block already selected ends instead
+    pub fn toggle_block(&mut self, vim: &mut dyn EditingMode, buffer: &mut Buffer) -> Result<()> {
+        if self.block.take().is_some() {
+            return Ok(());
+        }
+        let visual = vim.editor_mode() == EditorMode::Visual;
+        let (line, column) = buffer.cursor_position();
+        let anchor = match buffer.selection_range() {
+            Some(range) if visual => {
+                if (range.start.line, range.start.column) == (line, column) { range.end } else { range.start }
+            }
+            _ => Position::new(line, column),
+        };
+        if visual {
+            vim.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE), buffer)?;
+            buffer.clear_selection();
+            buffer.set_cursor_position(line, column);
+        }
+        self.block = Some(VisualBlock::new(anchor));
+        Ok(())
+    }
+
+    /// Byte ranges of the block on each of its rows, for drawing
+    pub fn block_ranges(&self, buffer: &Buffer, tab_size: usize) -> Vec<TextRange> {
+        let Some(block) = self.block.filter(|block| block.anchor().line < buffer.line_count()) else {
+            return Vec::new();
+        };
+        let (line, column) = buffer.cursor_position();
+        block.columns(buffer.lines(), Position::new(line, column), tab_size).ranges(buffer.lines(), tab_size)
+    }
+
+    /// Where an insert on a block's rows starts on each row it will be copied to, the typed row first
+    ///
+    /// Rows too short to reach the block are left out, as padding them
+    /// would be an edit of its own.
+    pub fn block_insert_cursors(&self, buffer: &Buffer) -> Vec<Position> {
+        let Some(insert) = &self.block_insert else {
+            return Vec::new();
+        };
+        let rows = insert.rows.clone().filter(|&row| row < buffer.line_count()).filter_map(|row| {
+            let line = &buffer.lines()[row];
+            let Some(column) = insert.column else {
+                return Some(Position::new(row, line.len()));
+            };
+            let width = visual_block::display_column(line, usize::MAX, insert.tab_size);
+            let reaches = if insert.pad { width >= column } else { width > column };
+            reaches.then(|| Position::new(row, visual_block::byte_column(line, column, insert.tab_size)))
+        });
+        std::iter::once(insert.start).chain(rows).collect()
+    }
+
+    /// Stop copying the text of a block insert to its other rows when the insert ends
+    pub fn end_block_insert(&mut self) {
+        self.block_insert = None;
+    }
+
+    /// Handle a key of the grammar, returning `None` for keys left to the keybindings
+    ///
+    /// `vim` is the mode the keys edit with, switched to insert mode by a
+    /// change and out of visual mode when a block or the command line
+    /// takes the selection.
+    pub fn handle_key(&mut self, key: KeyEvent, vim: &mut dyn EditingMode, buffer: &mut Buffer, mut context: GrammarContext) -> Result<Option<GrammarAction>> {
+        let before = vim.editor_mode();
+        let action = self.parse_key(key, before, vim, buffer, &mut context)?;
+        if action.is_some() {
+            self.track_insert(key, before, vim.editor_mode(), buffer, &mut context);
+        }
+        Ok(action)
+    }
+
+    fn parse_key(&mut self, key: KeyEvent, mode: EditorMode, vim: &mut dyn EditingMode, buffer: &mut Buffer, context: &mut GrammarContext) -> Result<Option<GrammarAction>> {
+        // `"x` chooses the register the next yank, delete or put uses
+        if self.pending_register {
+            self.pending_register = false;
+            if let KeyCode::Char(name) = key.code
+                && let Err(e) = context.registers.select(name)
+            {
+                return Ok(Some(GrammarAction::Failed(Some(e.to_string()))));
+            }
+            return Ok(Some(GrammarAction::Done));
+        }
+        // Keys typed after an operator or `f` belong to the operator parser
+        let idle = !context.keys_pending && !self.operators.is_active();
+        let command_mode = matches!(mode, EditorMode::Normal | EditorMode::Visual);
+        if command_mode && key.code == KeyCode::Char('"') && idle {
+            self.pending_register = true;
+            return Ok(Some(GrammarAction::Done));
+        }
+
+        // `:` opens the command line, given the selected lines or counted lines as its range
+        if command_mode
+            && key.code == KeyCode::Char(':')
+            && key.modifiers.difference(KeyModifiers::SHIFT).is_empty()
+            && idle
+        {
+            return self.open_command_line(vim, buffer, context).map(Some);
+        }
+
+        // A selected block takes every other key
+        if self.block.is_some() && mode == EditorMode::Normal {
+            return self.handle_block_key(key, vim, buffer, context).map(Some);
+        }
+
+        // `qa` records a macro until the next `q`, `[count]@a` plays it
+        if let Some(pending) = self.pending_macro.take() {
+            let KeyCode::Char(name) = key.code else {
+                return Ok(Some(GrammarAction::Done));
+            };
+            return Ok(Some(match pending {
+                MacroKey::Record => GrammarAction::StartRecording(name),
+                MacroKey::Play(count) => GrammarAction::PlayMacro { register: name, count },
+            }));
+        }
+        if mode == EditorMode::Normal && idle && !key.modifiers.contains(KeyModifiers::CONTROL) {
+            match key.code {
+                KeyCode::Char('q') if context.recording => return Ok(Some(GrammarAction::StopRecording)),
+                KeyCode::Char('q') => {
+                    self.pending_macro = Some(MacroKey::Record);
+                    return Ok(Some(GrammarAction::Done));
+                }
+                KeyCode::Char('@') => {
+                    let count = self.operators.take_count();
+                    self.pending_macro = Some(MacroKey::Play(count.unwrap_or(1)));
+                    return Ok(Some(GrammarAction::Done));
+                }
+                _ => {}
+            }
+        }
+
+        // `ma` sets a mark, `'a` and `` `a `` go to its line or position
+        if let Some(pending) = self.pending_mark.take() {
+            let KeyCode::Char(name) = key.code else {
+                return Ok(Some(GrammarAction::Done));
+            };
+            let args = CommandArgs::new().with("name", ArgValue::String(name.to_string()));
+            return Ok(Some(GrammarAction::Command(pending.command().to_string(), args)));
+        }
+        if command_mode && idle && key.modifiers.difference(KeyModifiers::SHIFT).is_empty() {
+            let pending = match key.code {
+                KeyCode::Char('m') => Some(MarkKey::Set),
+                KeyCode::Char('`') => Some(MarkKey::Goto),
+                KeyCode::Char('\'') => Some(MarkKey::GotoLine),
+                _ => None,
+            };
+            if pending.is_some() {
+                self.pending_mark = pending;
+                return Ok(Some(GrammarAction::Done));
+            }
+        }
+
+        // `af`, `ic` and the other syntax text objects select in visual mode; vim's own objects stay vim's
+        if let Some(around) = self.pending_object.take() {
+            return self.select_object(around, key, vim, buffer, context).map(Some);
+        }
+        if mode == EditorMode::Visual
+            && idle
+            && !context.bound
+            && context.text_objects.is_some()
+            && key.modifiers.difference(KeyModifiers::SHIFT).is_empty()
+            && matches!(key.code, KeyCode::Char('a' | 'i'))
+        {
+            self.pending_object = Some(key.code == KeyCode::Char('a'));
+            return Ok(Some(GrammarAction::Done));
+        }
+
+        // Counts, operators and the motions the keybindings leave unbound go through the operator parser
+        if command_mode && !context.keys_pending {
+            return self.handle_operator_key(key, mode, vim, buffer, context);
+        }
+        Ok(None)
+    }
+
+    /// Feed a key to the operator parser if it is waiting for one, or the key
+    /// is a count or a motion with no binding
+    fn handle_operator_key(&mut self, key: KeyEvent, mode: EditorMode, vim: &mut dyn EditingMode, buffer: &mut Buffer, context: &mut GrammarContext) -> Result<Option<GrammarAction>> {
+        let plain = key.modifiers.difference(KeyModifiers::SHIFT).is_empty();
+        let takes_key = self.operators.is_active()
+            || (plain && matches!(key.code, KeyCode::Char('1'..='9')))
+            || (self.operators.has_count() && (key.code == KeyCode::Char('0') || key.code == KeyCode::Esc))
+            || (OperatorParser::is_motion_key(key) && !context.bound);
+        if !takes_key {
+            return Ok(None);
+        }
+
+        let action = match self.operators.feed(key) {
+            Parsed::Pending | Parsed::Cancelled => GrammarAction::Done,
+            Parsed::Invalid => GrammarAction::Failed(None),
+            Parsed::Motion(motion, count) => {
+                let cursor = buffer.cursor_position();
+                if !operators::move_cursor(motion, count, buffer, &context.env) {
+                    return Ok(Some(GrammarAction::Failed(None)));
+                }
+                let jump = matches!(motion, Motion::MatchBracket | Motion::SentenceForward | Motion::SentenceBackward
+                    | Motion::ParagraphForward | Motion::ParagraphBackward | Motion::Screen(_) | Motion::FileStart | Motion::FileEnd);
+                if jump { GrammarAction::Jumped(cursor) } else { GrammarAction::Done }
+            }
+            Parsed::Operation(mut operation) => {
+                operation.register = context.registers.take_selected();
+                self.run_operation(operation, None, vim, buffer, context)?
+            }
+            Parsed::Repeat(count) => {
+                let Some((mut operation, text)) = self.last_change.clone().filter(|_| mode == EditorMode::Normal) else {
+                    return Ok(Some(GrammarAction::Failed(None)));
+                };
+                if count.is_some() {
+                    operation.count = count;
+                }
+                self.run_operation(operation, Some(text), vim, buffer, context)?
+            }
+        };
+        Ok(Some(action))
+    }
+
+    /// Select the syntax text object typed after `a` or `i` in visual mode,
+    /// or hand both keys to vim when the key is not one
+    fn select_object(&mut self, around: bool, key: KeyEvent, vim: &mut dyn EditingMode, buffer: &mut Buffer, context: &mut GrammarContext) -> Result<GrammarAction> {
+        let kind = match key.code {
+            KeyCode::Char(ch) if key.modifiers.difference(KeyModifiers::SHIFT).is_empty() => TextObjectKind::from_char(ch),
+            _ => None,
+        };
+        let Some(kind) = kind else {
+            let scope_key = KeyEvent::new(KeyCode::Char(if around { 'a' } else { 'i' }), KeyModifiers::NONE);
+            let quit = vim.handle_key(scope_key, buffer)? || vim.handle_key(key, buffer)?;
+            return Ok(if quit { GrammarAction::Quit } else { GrammarAction::Done });
+        };
+
+        let scope = if around { TextObjectScope::Around } else { TextObjectScope::Inside };
+        match context.text_objects.as_deref_mut().and_then(|provider| provider.select(buffer, kind, scope)) {
+            Some(range) => {
+                buffer.select_range(&range);
+                Ok(GrammarAction::Done)
+            }
+            None => Ok(GrammarAction::Failed(None)),
+        }
+    }
+
+    /// Apply an operation, entering insert mode after a change or, when
+    /// repeating one, typing the text its insert typed
+    fn run_operation(&mut self, operation: Operation, repeated_text: Option<String>, vim: &mut dyn EditingMode, buffer: &mut Buffer, context: &mut GrammarContext) -> Result<GrammarAction> {
+        let applied = match operation.motion {
+            Motion::Syntax { kind, around } => {
+                let scope = if around { TextObjectScope::Around } else { TextObjectScope::Inside };
+                match context.text_objects.as_deref_mut().and_then(|provider| provider.select(buffer, kind, scope)) {
+                    Some(range) => operators::apply_to(&operation, range, false, buffer, context.registers, &context.env).map(|()| true),
+                    None => Ok(false),
+                }
+            }
+            _ => operators::apply(&operation, buffer, context.registers, &context.env),
+        };
+        match applied {
+            Ok(true) => {}
+            Ok(false) => return Ok(GrammarAction::Failed(None)),
+            Err(e) => return Ok(GrammarAction::Failed(Some(e.to_string()))),
+        }
+
+        if operation.operator != Operator::Change {
+            if operation.operator.is_change() {
+                self.last_change = Some((operation, String::new()));
+            }
+            return Ok(GrammarAction::Done);
+        }
+        match repeated_text {
+            Some(text) => {
+                let (line, column) = buffer.cursor_position();
+                let end = buffer.insert_text(Position::new(line, column), &text);
+                // The cursor ends on the last character typed, as when leaving insert mode
+                let last = buffer.lines()[end.line][..end.column].chars().next_back().map_or(0, char::len_utf8);
+                buffer.set_cursor_position(end.line, end.column - last);
+                self.last_change = Some((operation, text));
+                Ok(GrammarAction::Done)
+            }
+            None => {
+                self.pending_change = Some(operation);
+                Self::insert(vim, buffer)
+            }
+        }
+    }
+
+    /// Switch vim to insert mode
+    fn insert(vim: &mut dyn EditingMode, buffer: &mut Buffer) -> Result<GrammarAction> {
+        let quit = vim.handle_key(KeyEvent::new(KeyCode::Char('i'), KeyModifiers::NONE), buffer)?;
+        Ok(if quit { GrammarAction::Quit } else { GrammarAction::Done })
+    }
+
+    /// Handle a key while a block is selected: motions reshape it, `o`/`O`
+    /// move to other corners, and `y`, `d`, `c`, `I`, `A` and `r` act on it
+    fn handle_block_key(&mut self, key: KeyEvent, vim: &mut dyn EditingMode, buffer: &mut Buffer, context: &mut GrammarContext) -> Result<GrammarAction> {
+        let Some(mut block) = self.block.filter(|block| block.anchor().line < buffer.line_count()) else {
+            self.block = None;
+            return Ok(GrammarAction::Done);
+        };
+        let (line, column) = buffer.cursor_position();
+        let cursor = Position::new(line, column);
+        let tab_size = context.env.tab_size;
+        let columns = block.columns(buffer.lines(), cursor, tab_size);
+
+        if self.pending_block_replace {
+            self.pending_block_replace = false;
+            if let KeyCode::Char(ch) = key.code {
+                visual_block::replace(buffer, &columns, ch, tab_size);
+                self.block = None;
+            }
+            return Ok(GrammarAction::Done);
+        }
+
+        let command_key = !key.modifiers.contains(KeyModifiers::CONTROL) && !self.operators.is_active();
+        match key.code {
+            KeyCode::Esc => self.block = None,
+            KeyCode::Char('c' | 'v') if key.modifiers.contains(KeyModifiers::CONTROL) => self.block = None,
+            KeyCode::Char('o') if command_key => {
+                let to = block.swap_corners(cursor);
+                buffer.set_cursor_position(to.line, to.column);
+                self.block = Some(block);
+            }
+            KeyCode::Char('O') if command_key => {
+                let to = block.swap_columns(buffer.lines(), cursor, tab_size);
+                buffer.set_cursor_position(to.line, to.column);
+                self.block = Some(block);
+            }
+            KeyCode::Char('r') if command_key => self.pending_block_replace = true,
+            KeyCode::Char(ch @ ('y' | 'd' | 'x' | 'c' | 'I' | 'A')) if command_key => {
+                self.block = None;
+                if matches!(ch, 'y' | 'd' | 'x' | 'c') {
+                    let register = context.registers.take_selected();
+                    let content = RegisterContent::block(columns.text(buffer.lines(), tab_size));
+                    let kind = if ch == 'y' { WriteKind::Yank } else { WriteKind::Delete };
+                    if let Err(e) = context.registers.write(register, content, kind) {
+                        return Ok(GrammarAction::Failed(Some(e.to_string())));
+                    }
+                }
+                match ch {
+                    'y' => {
+                        let start = visual_block::byte_column(&buffer.lines()[columns.first], columns.left, tab_size);
+                        buffer.set_cursor_position(columns.first, start);
+                    }
+                    'd' | 'x' => visual_block::delete(buffer, &columns, tab_size),
+                    _ => {
+                        if ch == 'c' {
+                            visual_block::delete(buffer, &columns, tab_size);
+                        }
+                        // `A` goes after the block, padding short lines out to it, or after every line past `$`
+                        let (column, pad) = match (ch, columns.right) {
+                            ('A', Some(right)) => (Some(right + 1), true),
+                            ('A', None) => (None, true),
+                            _ => (Some(columns.left), false),
+                        };
+                        visual_block::insert_at_column(buffer, columns.first..=columns.first, column, "", pad, tab_size);
+                        let first = &buffer.lines()[columns.first];
+                        let start = column.map_or(first.len(), |column| visual_block::byte_column(first, column, tab_size));
+                        buffer.set_cursor_position(columns.first, start);
+                        self.block_insert = Some(BlockInsert {
+                            start: Position::new(columns.first, start),
+                            rows: columns.first + 1..=columns.last,
+                            column,
+                            pad,
+                            tab_size,
+                        });
+                        return Self::insert(vim, buffer);
+                    }
+                }
+            }
+            _ => {
+                // Counts and motions move the cursor, the block following it
+                let plain = key.modifiers.difference(KeyModifiers::SHIFT).is_empty();
+                let takes_key = self.operators.is_active()
+                    || (plain && matches!(key.code, KeyCode::Char('1'..='9')))
+                    || OperatorParser::is_motion_key(key);
+                if !takes_key {
+                    return Ok(GrammarAction::Done);
+                }
+                match self.operators.feed(key) {
+                    Parsed::Motion(motion, count) => {
+                        let moved = operators::move_cursor(motion, count, buffer, &context.env);
+                        block.set_to_end(motion == Motion::LineEnd);
+                        self.block = Some(block);
+                        if !moved {
+                            return Ok(GrammarAction::Failed(None));
+                        }
+                    }
+                    Parsed::Invalid => return Ok(GrammarAction::Failed(None)),
+                    _ => {}
+                }
+            }
+        }
+        Ok(GrammarAction::Done)
+    }
+
+    /// Open the `:` command line, starting it with `'<,'>` when lines are
+    /// selected or `.,.+N` after a count
+    fn open_command_line(&mut self, vim: &mut dyn EditingMode, buffer: &mut Buffer, context: &GrammarContext) -> Result<GrammarAction> {
+        let visual = vim.editor_mode() == EditorMode::Visual;
+        let selected = match self.block_ranges(buffer, context.env.tab_size).as_slice() {
+            [first, .., last] | [first @ last] => Some((first.start, last.end)),
+            [] if visual => buffer.selection_range().map(|range| (range.start, range.end)),
+            [] => None,
+        };
+        let count = self.operators.take_count();
+        let range = if let Some((start, end)) = selected {
+            let marks = buffer.marks_mut();
+            marks.set('<', start)?;
+            marks.set('>', end)?;
+            self.block = None;
+            if visual {
+                vim.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE), buffer)?;
+            }
+            buffer.clear_selection();
+            "'<,'>".to_string()
+        } else {
+            match count {
+                Some(count) if count > 1 => format!(".,.+{}", count - 1),
+                _ => String::new(),
+            }
+        };
+        Ok(GrammarAction::OpenCommandLine(range))
+    }
+
+    /// Collect the text typed during an insert and store it in `".` when the
+    /// insert ends, also typing it on the other rows of a block it started on
+    pub fn track_insert(&mut self, key: KeyEvent, before: EditorMode, after: EditorMode, buffer: &mut Buffer, context: &mut GrammarContext) {
+        if before != EditorMode::Insert {
+            if after == EditorMode::Insert {
+                self.inserted = Some(String::new());
+            }
+            return;
+        }
+        if let Some(inserted) = &mut self.inserted {
+            match key.code {
+                KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => inserted.push(ch),
+                KeyCode::Enter => inserted.push('\n'),
+                KeyCode::Tab => inserted.push('\t'),
+                KeyCode::Backspace => {
+                    inserted.pop();
+                }
+                _ => {}
+            }
+        }
+        if after != EditorMode::Insert
+            && let Some(inserted) = self.inserted.take()
+        {
+            if let Some(operation) = self.pending_change.take() {
+                self.last_change = Some((operation, inserted.clone()));
+            }
+            // Text typed on a block's first row goes on the others too, unless it broke the line
+            if let Some(insert) = self.block_insert.take()
+                && !inserted.is_empty()
+                && !inserted.contains('\n')
+            {
+                let cursor = buffer.cursor_position();
+                visual_block::insert_at_column(buffer, insert.rows, insert.column, &inserted, insert.pad, context.env.tab_size);
+                buffer.set_cursor_position(cursor.0, cursor.1);
+            }
+            context.registers.set_last_inserted(inserted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::implementations::editing::mode::VimEditing;
+    use crate::plugins::implementations::language::syntax::SupportedLanguage;
+    use crate::plugins::implementations::language::textobjects::TextObjectProvider;
+    use crate::services::operators::MotionEnv;
+    use crate::services::registers::Registers;
+
+    const ENV: MotionEnv = MotionEnv { top: 0, height: 20, tab_size: 4, insert_spaces: true };
+
+    fn feed(vim: &mut VimEditing, keys: &str, buffer: &mut Buffer, registers: &mut Registers) -> Vec<Option<GrammarAction>> {
+        feed_with(vim, keys, buffer, registers, None)
+    }
+
+    fn feed_with(vim: &mut VimEditing, keys: &str, buffer: &mut Buffer, registers: &mut Registers, mut text_objects: Option<&mut TextObjectProvider>) -> Vec<Option<GrammarAction>> {
+        keys.chars()
+            .map(|ch| {
+                let text_objects = text_objects.as_deref_mut();
+                let context = GrammarContext { registers: &mut *registers, env: ENV, keys_pending: false, bound: false, recording: false, text_objects };
+                vim.handle_grammar_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE), buffer, context).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_block_is_a_visual_mode_of_its_own() {
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::new(0, 0), "abcd\nefgh\nijkl");
+        buffer.set_cursor_position(0, 1);
+        let mut registers = Registers::new();
+        let mut vim = VimEditing::new();
+
+        assert!(vim.toggle_block(&mut buffer).unwrap());
+        assert_eq!(vim.mode_name(), "V-BLOCK");
+        assert_eq!(vim.editor_mode(), EditorMode::Visual);
+        feed(&mut vim, "jl", &mut buffer, &mut registers);
+        assert_eq!(vim.block_ranges(&buffer, 4).len(), 2);
+
+        feed(&mut vim, "d", &mut buffer, &mut registers);
+        assert_eq!(buffer.text(), "ad\neh\nijkl");
+        assert_eq!(registers.get(None, &buffer).unwrap(), Some(RegisterContent::block("bc\nfg")));
+        assert!(vim.block_ranges(&buffer, 4).is_empty());
+        assert_ne!(vim.mode_name(), "V-BLOCK");
+    }
+
+    #[test]
+    fn test_macro_and_mark_keys_ask_the_interface() {
+        let mut buffer = Buffer::new();
+        let mut registers = Registers::new();
+        let mut vim = VimEditing::new();
+
+        let actions = feed(&mut vim, "qa3@", &mut buffer, &mut registers);
+        assert_eq!(actions[1], Some(GrammarAction::StartRecording('a')));
+        assert_eq!(vim.pending_keys().as_deref(), Some("@"));
+        let actions = feed(&mut vim, "b", &mut buffer, &mut registers);
+        assert_eq!(actions[0], Some(GrammarAction::PlayMacro { register: 'b', count: 3 }));
+
+        let actions = feed(&mut vim, "mx", &mut buffer, &mut registers);
+        let args = CommandArgs::new().with("name", ArgValue::String("x".to_string()));
+        assert_eq!(actions[1], Some(GrammarAction::Command("set_mark".to_string(), args)));
+        assert_eq!(vim.pending_keys(), None);
+    }
+
+    #[test]
+    fn test_syntax_objects_go_through_the_registers() {
+        let mut buffer = Buffer::new();
+        buffer.set_language(SupportedLanguage::Rust);
+        buffer.insert_text(Position::new(0, 0), "fn a() {\n    1\n}\nfn b() {}\n");
+        buffer.set_cursor_position(1, 4);
+        let mut registers = Registers::new();
+        let mut provider = TextObjectProvider::new().unwrap();
+        let mut vim = VimEditing::new();
+
+        vim.start_operator(Operator::Yank, None);
+        feed_with(&mut vim, "if", &mut buffer, &mut registers, Some(&mut provider));
+        assert_eq!(registers.get(Some('0'), &buffer).unwrap(), Some(RegisterContent::chars("    1\n")));
+
+        vim.start_operator(Operator::Delete, None);
+        feed_with(&mut vim, "af", &mut buffer, &mut registers, Some(&mut provider));
+        assert_eq!(buffer.text(), "\nfn b() {}\n");
+        assert_eq!(registers.get(None, &buffer).unwrap(), Some(RegisterContent::chars("fn a() {\n    1\n}")));
+    }
+}
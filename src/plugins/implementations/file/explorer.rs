@@ -0,0 +1,570 @@
+//! File explorer tree
+//!
+//! A tree of the working directory with expandable directories, file
+//! operations and git status decorations. Ignored files are listed but
+//! marked, hidden files follow the same setting as the file finder.
+
+use crate::plugins::implementations::file::finder::WalkOptions;
+use crate::plugins::implementations::file::ignore::IgnoreRules;
+use crate::{Result, RuskError};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Git status of a file, or the most notable status below a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GitStatus {
+    Untracked,
+    Deleted,
+    Renamed,
+    Added,
+    Modified,
+    Conflicted,
+}
+
+impl GitStatus {
+    /// Letter shown beside the file
+    pub fn marker(&self) -> char {
+        match self {
+            Self::Untracked => '?',
+            Self::Deleted => 'D',
+            Self::Renamed => 'R',
+            Self::Added => 'A',
+            Self::Modified => 'M',
+            Self::Conflicted => 'U',
+        }
+    }
+
+    /// Read the two status letters of `git status --porcelain`
+    fn from_porcelain(index: char, worktree: char) -> Option<Self> {
+        match (index, worktree) {
+            ('?', '?') => Some(Self::Untracked),
+            ('!', '!') => None,
+            ('U', _) | (_, 'U') | ('A', 'A') | ('D', 'D') => Some(Self::Conflicted),
+            (_, 'M') | ('M', _) | (_, 'T') | ('T', _) => Some(Self::Modified),
+            ('A', _) => Some(Self::Added),
+            ('R', _) | ('C', _) => Some(Self::Renamed),
+            ('D', _) | (_, 'D') => Some(Self::Deleted),
+            _ => None,
+        }
+    }
+}
+
+/// Parse `git status --porcelain -z` output into paths relative to the repository
+fn parse_porcelain(output: &str) -> Vec<(String, GitStatus)> {
+    let mut statuses = Vec::new();
+    let mut records = output.split('\0');
+    while let Some(record) = records.next() {
+        let mut chars = record.chars();
+        let (Some(index), Some(worktree), Some(' ')) = (chars.next(), chars.next(), chars.next()) else {
+            continue;
+        };
+        // Renames and copies are followed by the original path
+        if matches!(index, 'R' | 'C') {
+            records.next();
+        }
+        if let Some(status) = GitStatus::from_porcelain(index, worktree) {
+            statuses.push((chars.as_str().trim_end_matches('/').to_string(), status));
+        }
+    }
+    statuses
+}
+
+/// Get the git status of changed files under a directory, keyed by absolute path
+///
+/// Returns nothing when the directory is not in a repository or git is not installed.
+pub fn git_status(root: &Path) -> HashMap<PathBuf, GitStatus> {
+    let run = |args: &[&str]| {
+        Command::new("git").arg("-C").arg(root).args(args).output().ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let Some(top) = run(&["rev-parse", "--show-toplevel"]) else {
+        return HashMap::new();
+    };
+    let top = PathBuf::from(top.trim());
+    let Some(output) = run(&["status", "--porcelain=v1", "-z", "--untracked-files=all"]) else {
+        return HashMap::new();
+    };
+    parse_porcelain(&output).into_iter().map(|(path, status)| (top.join(path), status)).collect()
+}
+
+/// A row of the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplorerEntry {
+    pub path: PathBuf,
+    pub name: String,
+    pub depth: usize,
+    pub is_dir: bool,
+    pub expanded: bool,
+    /// Whether `.gitignore` excludes the entry
+    pub ignored: bool,
+    pub git: Option<GitStatus>,
+}
+
+/// File operation waiting for input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplorerPrompt {
+    /// New file name, a trailing `/` creating a directory
+    Create,
+    /// New name in the same directory
+    Rename,
+    /// New path relative to the root, or an existing directory to move into
+    Move,
+    /// Confirmation of a delete
+    Delete,
+}
+
+/// What happened in the explorer that the editor needs to know about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExplorerEvent {
+    /// A file was chosen for editing
+    Open(PathBuf),
+    /// A file or directory was renamed or moved, so buffers of it need new paths
+    Renamed { from: PathBuf, to: PathBuf },
+    /// Status line message
+    Message(String),
+}
+
+/// State of the file explorer panel
+#[derive(Debug, Clone, Default)]
+pub struct FileExplorer {
+    root: PathBuf,
+    options: WalkOptions,
+    expanded: HashSet<PathBuf>,
+    entries: Vec<ExplorerEntry>,
+    selected: usize,
+    git: HashMap<PathBuf, GitStatus>,
+    prompt: Option<(ExplorerPrompt, String)>,
+    open: bool,
+    focused: bool,
+}
+
+impl FileExplorer {
+    /// Create a closed explorer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set which files are listed
+    pub fn set_options(&mut self, options: WalkOptions) {
+        self.options = options;
+    }
+
+    /// Show the tree of a directory and focus it
+    pub fn open(&mut self, root: &Path) {
+        if self.root != root {
+            self.root = root.to_path_buf();
+            self.expanded.clear();
+            self.selected = 0;
+        }
+        self.open = true;
+        self.focused = true;
+        self.refresh();
+    }
+
+    /// Hide the panel
+    pub fn close(&mut self) {
+        self.open = false;
+        self.focused = false;
+        self.prompt = None;
+    }
+
+    /// Check whether the panel is shown
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Check whether keys go to the panel
+    pub fn is_focused(&self) -> bool {
+        self.open && self.focused
+    }
+
+    /// Give keys to the panel or back to the editor
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused && self.open;
+    }
+
+    /// Get the directory shown
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the visible rows
+    pub fn entries(&self) -> &[ExplorerEntry] {
+        &self.entries
+    }
+
+    /// Get the index of the highlighted row
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Get the highlighted row
+    pub fn selected_entry(&self) -> Option<&ExplorerEntry> {
+        self.entries.get(self.selected)
+    }
+
+    /// Get the pending operation's question and the text typed so far
+    pub fn prompt_text(&self) -> Option<String> {
+        let (prompt, input) = self.prompt.as_ref()?;
+        let question = match prompt {
+            ExplorerPrompt::Create => "New (end with / for a directory): ",
+            ExplorerPrompt::Rename => "Rename to: ",
+            ExplorerPrompt::Move => "Move to: ",
+            ExplorerPrompt::Delete => "Delete? (y/n) ",
+        };
+        Some(format!("{}{}", question, input))
+    }
+
+    /// Reread the tree and the git status
+    pub fn refresh(&mut self) {
+        self.git = git_status(&self.root);
+        let selected = self.selected_entry().map(|entry| entry.path.clone());
+
+        let mut entries = Vec::new();
+        let rules = if self.options.respect_gitignore { IgnoreRules::new(&self.root) } else { IgnoreRules::default() };
+        self.list_dir(&self.root, "", 0, &rules, &mut entries);
+        self.entries = entries;
+
+        self.selected = selected
+            .and_then(|path| self.entries.iter().position(|entry| entry.path == path))
+            .unwrap_or(self.selected)
+            .min(self.entries.len().saturating_sub(1));
+    }
+
+    /// Add the entries of a directory, and of its expanded subdirectories, directories first
+    fn list_dir(&self, dir: &Path, relative: &str, depth: usize, rules: &IgnoreRules, entries: &mut Vec<ExplorerEntry>) {
+        let Ok(read) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut children: Vec<(String, PathBuf, bool)> = read
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+                (entry.file_name().to_string_lossy().into_owned(), entry.path(), is_dir)
+            })
+            .filter(|(name, _, _)| name != ".git" && (self.options.show_hidden || !name.starts_with('.')))
+            .collect();
+        children.sort_by(|(a, _, a_dir), (b, _, b_dir)| b_dir.cmp(a_dir).then_with(|| a.to_lowercase().cmp(&b.to_lowercase())));
+
+        for (name, path, is_dir) in children {
+            let child_relative = if relative.is_empty() { name.clone() } else { format!("{}/{}", relative, name) };
+            let expanded = is_dir && self.expanded.contains(&path);
+            let git = if is_dir {
+                self.git.iter().filter(|(changed, _)| changed.starts_with(&path)).map(|(_, status)| *status).max()
+            } else {
+                self.git.get(&path).copied()
+            };
+            entries.push(ExplorerEntry {
+                path: path.clone(),
+                name,
+                depth,
+                is_dir,
+                expanded,
+                ignored: self.options.respect_gitignore && rules.is_ignored(&child_relative, is_dir),
+                git,
+            });
+            if expanded {
+                let child_rules = if self.options.respect_gitignore { rules.child(&path, &child_relative) } else { rules.clone() };
+                self.list_dir(&path, &child_relative, depth + 1, &child_rules, entries);
+            }
+        }
+    }
+
+    /// Expand the directories leading to a file and highlight it, returning whether it is in the tree
+    pub fn reveal(&mut self, path: &Path) -> bool {
+        let Ok(path) = std::path::absolute(path) else {
+            return false;
+        };
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let mut dir = self.root.clone();
+        for component in relative.parent().into_iter().flat_map(Path::components) {
+            dir.push(component);
+            self.expanded.insert(dir.clone());
+        }
+        self.refresh();
+        match self.entries.iter().position(|entry| entry.path == path) {
+            Some(index) => {
+                self.selected = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Directory new files go in: the highlighted directory, or the one holding the highlighted file
+    fn target_dir(&self) -> PathBuf {
+        match self.selected_entry() {
+            Some(entry) if entry.is_dir => entry.path.clone(),
+            Some(entry) => entry.path.parent().map_or_else(|| self.root.clone(), Path::to_path_buf),
+            None => self.root.clone(),
+        }
+    }
+
+    /// Create a file, or a directory when the name ends with `/`, beside or inside the highlighted entry
+    pub fn create(&mut self, name: &str) -> Result<PathBuf> {
+        let name = name.trim();
+        if name.is_empty() || name == "/" {
+            return Err(RuskError::Command("Empty file name".to_string()));
+        }
+        let dir = self.target_dir();
+        let path = dir.join(name.trim_end_matches('/'));
+        if path.exists() {
+            return Err(RuskError::Command(format!("{} already exists", path.display())));
+        }
+
+        if name.ends_with('/') {
+            fs::create_dir_all(&path)
+                .map_err(|e| RuskError::io_string(format!("Failed to create directory {}: {}", path.display(), e)))?;
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| RuskError::io_string(format!("Failed to create directory {}: {}", parent.display(), e)))?;
+            }
+            fs::File::create_new(&path)
+                .map_err(|e| RuskError::io_string(format!("Failed to create file {}: {}", path.display(), e)))?;
+        }
+        self.expanded.insert(dir);
+        self.reveal(&path);
+        Ok(path)
+    }
+
+    /// Give the highlighted entry a new name in the same directory
+    pub fn rename(&mut self, name: &str) -> Result<(PathBuf, PathBuf)> {
+        let name = name.trim();
+        if name.is_empty() || name.contains('/') {
+            return Err(RuskError::Command(format!("Invalid name '{}'; use move to change directory", name)));
+        }
+        let from = self.selected_path()?;
+        let to = from.with_file_name(name);
+        self.move_path(from, to)
+    }
+
+    /// Move the highlighted entry to a path relative to the root, or into an existing directory
+    pub fn move_to(&mut self, destination: &str) -> Result<(PathBuf, PathBuf)> {
+        let destination = destination.trim();
+        if destination.is_empty() {
+            return Err(RuskError::Command("Empty destination".to_string()));
+        }
+        let from = self.selected_path()?;
+        let mut to = self.root.join(destination.trim_start_matches('/'));
+        if to.is_dir() && let Some(name) = from.file_name() {
+            to.push(name);
+        }
+        self.move_path(from, to)
+    }
+
+    fn move_path(&mut self, from: PathBuf, to: PathBuf) -> Result<(PathBuf, PathBuf)> {
+        if to.exists() {
+            return Err(RuskError::Command(format!("{} already exists", to.display())));
+        }
+        if to.starts_with(&from) {
+            return Err(RuskError::Command(format!("Can't move {} into itself", from.display())));
+        }
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| RuskError::io_string(format!("Failed to create directory {}: {}", parent.display(), e)))?;
+        }
+        fs::rename(&from, &to)
+            .map_err(|e| RuskError::io_string(format!("Failed to move {} to {}: {}", from.display(), to.display(), e)))?;
+
+        // Keep moved directories expanded
+        let moved: Vec<PathBuf> = self.expanded.iter().filter(|dir| dir.starts_with(&from)).cloned().collect();
+        for dir in moved {
+            self.expanded.remove(&dir);
+            if let Ok(rest) = dir.strip_prefix(&from) {
+                self.expanded.insert(to.join(rest));
+            }
+        }
+        self.reveal(&to);
+        Ok((from, to))
+    }
+
+    /// Delete the highlighted file, or directory with everything in it
+    pub fn delete(&mut self) -> Result<PathBuf> {
+        let path = self.selected_path()?;
+        let result = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+        result.map_err(|e| RuskError::io_string(format!("Failed to delete {}: {}", path.display(), e)))?;
+        self.expanded.retain(|dir| !dir.starts_with(&path));
+        self.refresh();
+        Ok(path)
+    }
+
+    fn selected_path(&self) -> Result<PathBuf> {
+        self.selected_entry()
+            .map(|entry| entry.path.clone())
+            .ok_or_else(|| RuskError::Command("Nothing selected".to_string()))
+    }
+
+    /// Handle a key while the panel is focused
+    pub fn handle_key(&mut self, key: KeyEvent) -> Result<Option<ExplorerEvent>> {
+        if self.prompt.is_some() {
+            return self.handle_prompt_key(key);
+        }
+        if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return Ok(None);
+        }
+
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => self.move_selection(1),
+            KeyCode::Char('k') | KeyCode::Up => self.move_selection(-1),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => self.selected = self.entries.len().saturating_sub(1),
+            KeyCode::Enter | KeyCode::Char('o' | 'l') | KeyCode::Right => {
+                let Some(entry) = self.selected_entry().cloned() else {
+                    return Ok(None);
+                };
+                if !entry.is_dir {
+                    self.focused = false;
+                    return Ok(Some(ExplorerEvent::Open(entry.path)));
+                }
+                if entry.expanded && key.code != KeyCode::Char('l') && key.code != KeyCode::Right {
+                    self.expanded.remove(&entry.path);
+                } else {
+                    self.expanded.insert(entry.path);
+                }
+                self.refresh();
+            }
+            KeyCode::Char('h') | KeyCode::Left => {
+                let Some(entry) = self.selected_entry().cloned() else {
+                    return Ok(None);
+                };
+                if entry.expanded {
+                    self.expanded.remove(&entry.path);
+                    self.refresh();
+                } else if let Some(parent) = entry.path.parent()
+                    && let Some(index) = self.entries.iter().position(|candidate| candidate.path == parent)
+                {
+                    self.selected = index;
+                }
+            }
+            KeyCode::Char('a') => self.prompt = Some((ExplorerPrompt::Create, String::new())),
+            KeyCode::Char('r') => {
+                let name = self.selected_entry().map(|entry| entry.name.clone()).unwrap_or_default();
+                self.prompt = Some((ExplorerPrompt::Rename, name));
+            }
+            KeyCode::Char('m') => {
+                let relative = self.selected_entry()
+                    .and_then(|entry| entry.path.strip_prefix(&self.root).ok())
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                self.prompt = Some((ExplorerPrompt::Move, relative));
+            }
+            KeyCode::Char('d') if self.selected_entry().is_some() => self.prompt = Some((ExplorerPrompt::Delete, String::new())),
+            KeyCode::Char('R') => self.refresh(),
+            KeyCode::Char('q') => self.close(),
+            KeyCode::Esc | KeyCode::Tab => self.focused = false,
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn handle_prompt_key(&mut self, key: KeyEvent) -> Result<Option<ExplorerEvent>> {
+        let Some((prompt, input)) = &mut self.prompt else {
+            return Ok(None);
+        };
+        let prompt = *prompt;
+
+        if prompt == ExplorerPrompt::Delete {
+            self.prompt = None;
+            if key.code != KeyCode::Char('y') {
+                return Ok(None);
+            }
+            let path = self.delete()?;
+            return Ok(Some(ExplorerEvent::Message(format!("Deleted {}", path.display()))));
+        }
+
+        match key.code {
+            KeyCode::Esc => self.prompt = None,
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => input.clear(),
+            KeyCode::Char(ch) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => input.push(ch),
+            KeyCode::Enter => {
+                let input = std::mem::take(input);
+                self.prompt = None;
+                return Ok(Some(match prompt {
+                    ExplorerPrompt::Create => {
+                        let path = self.create(&input)?;
+                        ExplorerEvent::Message(format!("Created {}", path.display()))
+                    }
+                    ExplorerPrompt::Rename => {
+                        let (from, to) = self.rename(&input)?;
+                        ExplorerEvent::Renamed { from, to }
+                    }
+                    ExplorerPrompt::Move | ExplorerPrompt::Delete => {
+                        let (from, to) = self.move_to(&input)?;
+                        ExplorerEvent::Renamed { from, to }
+                    }
+                }));
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let last = self.entries.len() as isize - 1;
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_porcelain_parsing() {
+        let output = " M src/main.rs\0?? notes.txt\0R  new.rs\0old.rs\0A  added.rs\0UU conflict.rs\0";
+        assert_eq!(parse_porcelain(output), vec![
+            ("src/main.rs".to_string(), GitStatus::Modified),
+            ("notes.txt".to_string(), GitStatus::Untracked),
+            ("new.rs".to_string(), GitStatus::Renamed),
+            ("added.rs".to_string(), GitStatus::Added),
+            ("conflict.rs".to_string(), GitStatus::Conflicted),
+        ]);
+    }
+
+    #[test]
+    fn test_explorer_file_operations() {
+        let root = std::env::temp_dir().join(format!("rusk-explorer-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        fs::write(root.join("README.md"), "").unwrap();
+
+        let mut explorer = FileExplorer::new();
+        explorer.open(&root);
+        let names: Vec<&str> = explorer.entries().iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["src", "README.md"]);
+
+        assert!(explorer.reveal(&root.join("src/main.rs")));
+        assert_eq!(explorer.selected_entry().map(|entry| entry.depth), Some(1));
+
+        let created = explorer.create("lib.rs").unwrap();
+        assert_eq!(created, root.join("src/lib.rs"));
+        let (from, to) = explorer.rename("util.rs").unwrap();
+        assert_eq!((from, to.clone()), (root.join("src/lib.rs"), root.join("src/util.rs")));
+
+        // New entries go beside the highlighted file
+        assert_eq!(explorer.create("docs/").unwrap(), root.join("src/docs"));
+        assert!(explorer.reveal(&to));
+        let (_, moved) = explorer.move_to("src/docs").unwrap();
+        assert_eq!(moved, root.join("src/docs/util.rs"));
+        assert!(moved.exists());
+
+        explorer.delete().unwrap();
+        assert!(!moved.exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
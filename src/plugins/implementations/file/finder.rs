@@ -0,0 +1,456 @@
+//! Fuzzy file finder
+//!
+//! Files under the working directory are indexed on a background thread
+//! and matched incrementally as the query grows, so the finder stays
+//! responsive on large trees.
+
+use crate::plugins::implementations::file::ignore::IgnoreRules;
+use crate::plugins::implementations::language::syntax::SupportedLanguage;
+use crate::utils::fuzzy::fuzzy_match;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+/// Files sent from the indexing thread at a time
+const INDEX_BATCH: usize = 512;
+/// Bytes of a file read for its preview
+const PREVIEW_BYTES: u64 = 64 * 1024;
+/// Lines kept for a preview
+const PREVIEW_LINES: usize = 200;
+
+/// Which files a walk visits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkOptions {
+    /// Include files and directories whose names start with `.`
+    pub show_hidden: bool,
+    /// Skip what `.gitignore` files exclude
+    pub respect_gitignore: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self { show_hidden: false, respect_gitignore: true }
+    }
+}
+
+/// Visit every file under a root as a `/`-separated relative path, until the visitor returns `false`
+///
+/// `.git` directories are always skipped, and symlinked directories are
+/// not followed so cycles can't occur.
+pub fn walk_files(root: &Path, options: WalkOptions, mut visit: impl FnMut(String) -> bool) {
+    let rules = if options.respect_gitignore { IgnoreRules::new(root) } else { IgnoreRules::default() };
+    let mut stack = vec![(root.to_path_buf(), String::new(), rules)];
+
+    while let Some((dir, relative, rules)) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut entries: Vec<_> = entries.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let mut subdirs = Vec::new();
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == ".git" || (!options.show_hidden && name.starts_with('.')) {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = if relative.is_empty() { name } else { format!("{}/{}", relative, name) };
+            let is_dir = file_type.is_dir();
+            if options.respect_gitignore && rules.is_ignored(&path, is_dir) {
+                continue;
+            }
+
+            if is_dir {
+                subdirs.push((entry.path(), path));
+            } else if !visit(path) {
+                return;
+            }
+        }
+
+        // Reversed so directories come off the stack in name order
+        for (path, subdir) in subdirs.into_iter().rev() {
+            let child_rules = if options.respect_gitignore { rules.child(&path, &subdir) } else { rules.clone() };
+            stack.push((path, subdir, child_rules));
+        }
+    }
+}
+
+/// The files under a directory, collected on a background thread
+pub struct FileIndex {
+    root: PathBuf,
+    files: Vec<String>,
+    receiver: Option<Receiver<Vec<String>>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl FileIndex {
+    /// Start indexing a directory
+    pub fn build(root: &Path, options: WalkOptions) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let thread_root = root.to_path_buf();
+        let thread_cancel = Arc::clone(&cancel);
+        thread::spawn(move || {
+            let mut batch = Vec::with_capacity(INDEX_BATCH);
+            walk_files(&thread_root, options, |path| {
+                batch.push(path);
+                if batch.len() == INDEX_BATCH && sender.send(std::mem::take(&mut batch)).is_err() {
+                    return false;
+                }
+                !thread_cancel.load(Ordering::Relaxed)
+            });
+            if !batch.is_empty() {
+                let _ = sender.send(batch);
+            }
+        });
+
+        Self { root: root.to_path_buf(), files: Vec::new(), receiver: Some(receiver), cancel }
+    }
+
+    /// Take in the files found since the last poll, returning whether there were any
+    pub fn poll(&mut self) -> bool {
+        let Some(receiver) = &self.receiver else {
+            return false;
+        };
+        let before = self.files.len();
+        loop {
+            match receiver.try_recv() {
+                Ok(batch) => self.files.extend(batch),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                    break;
+                }
+            }
+        }
+        self.files.len() > before
+    }
+
+    /// Check whether the whole tree has been indexed
+    pub fn is_complete(&self) -> bool {
+        self.receiver.is_none()
+    }
+
+    /// Get the directory being indexed
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the files found so far, relative to the root
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+}
+
+impl Drop for FileIndex {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A file matching the query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinderMatch {
+    pub path: String,
+    /// Char indices of the path that matched the query
+    pub indices: Vec<usize>,
+}
+
+/// The start of a file, shown beside the results
+#[derive(Debug, Clone)]
+pub struct FilePreview {
+    pub path: String,
+    pub language: SupportedLanguage,
+    pub lines: Vec<String>,
+}
+
+/// Score a path: the whole path must match, and matching within the file name counts extra
+fn score_path(query: &str, path: &str) -> Option<i64> {
+    let score = fuzzy_match(query, path)?.score;
+    let name = path.rsplit('/').next().unwrap_or(path);
+    Some(score + fuzzy_match(query, name).map_or(0, |found| found.score / 2))
+}
+
+/// State of the file finder overlay
+pub struct FileFinder {
+    open: bool,
+    options: WalkOptions,
+    index: Option<FileIndex>,
+    query: String,
+    /// (file index, score) of matching files, best first
+    matches: Vec<(usize, i64)>,
+    /// Query the matches are for
+    matched_query: String,
+    /// Number of indexed files checked against the query
+    checked: usize,
+    selected: usize,
+    preview: Option<FilePreview>,
+}
+
+impl Default for FileFinder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileFinder {
+    /// Create a closed finder
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            options: WalkOptions::default(),
+            index: None,
+            query: String::new(),
+            matches: Vec::new(),
+            matched_query: String::new(),
+            checked: 0,
+            selected: 0,
+            preview: None,
+        }
+    }
+
+    /// Set which files are listed; takes effect the next time the finder opens
+    pub fn set_options(&mut self, options: WalkOptions) {
+        self.options = options;
+    }
+
+    /// Show the finder, indexing a directory afresh
+    pub fn open(&mut self, root: &Path) {
+        self.index = Some(FileIndex::build(root, self.options));
+        self.open = true;
+        self.query.clear();
+        self.matches.clear();
+        self.matched_query.clear();
+        self.checked = 0;
+        self.selected = 0;
+        self.preview = None;
+    }
+
+    /// Hide the finder and stop indexing
+    pub fn close(&mut self) {
+        self.open = false;
+        self.index = None;
+        self.preview = None;
+    }
+
+    /// Check whether the finder is shown
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Get the typed query
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Get the index of the highlighted result
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Get the number of matching files
+    pub fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Get the number of files indexed so far, and whether indexing is done
+    pub fn indexed(&self) -> (usize, bool) {
+        self.index.as_ref().map_or((0, true), |index| (index.files().len(), index.is_complete()))
+    }
+
+    /// Take in newly indexed files, returning whether the results changed
+    pub fn poll(&mut self) -> bool {
+        let Some(index) = &mut self.index else {
+            return false;
+        };
+        if !index.poll() && self.checked == index.files().len() {
+            return false;
+        }
+        self.update_matches();
+        true
+    }
+
+    /// Get a window of results around the selection, at most `rows` long, with the position of the first
+    pub fn visible(&self, rows: usize) -> (usize, Vec<FinderMatch>) {
+        let Some(index) = &self.index else {
+            return (0, Vec::new());
+        };
+        let first = self.selected.saturating_sub(rows.saturating_sub(1));
+        let visible = self.matches.iter()
+            .skip(first)
+            .take(rows)
+            .map(|&(file, _)| {
+                let path = index.files()[file].clone();
+                let indices = fuzzy_match(&self.query, &path).map(|found| found.indices).unwrap_or_default();
+                FinderMatch { path, indices }
+            })
+            .collect();
+        (first, visible)
+    }
+
+    /// Get the path of the highlighted file
+    pub fn selected_path(&self) -> Option<PathBuf> {
+        let index = self.index.as_ref()?;
+        let &(file, _) = self.matches.get(self.selected)?;
+        Some(index.root().join(&index.files()[file]))
+    }
+
+    /// Get the preview of the highlighted file, reading it if it changed
+    pub fn preview(&mut self) -> Option<&FilePreview> {
+        let index = self.index.as_ref()?;
+        let &(file, _) = self.matches.get(self.selected)?;
+        let path = &index.files()[file];
+        if self.preview.as_ref().is_none_or(|preview| &preview.path != path) {
+            self.preview = Some(read_preview(index.root(), path));
+        }
+        self.preview.as_ref()
+    }
+
+    /// Handle a key while the finder is open, returning the file to open when one is chosen
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<PathBuf> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => self.close(),
+            KeyCode::Char('c') if ctrl => self.close(),
+            KeyCode::Enter => {
+                let path = self.selected_path();
+                if path.is_some() {
+                    self.close();
+                }
+                return path;
+            }
+            KeyCode::Up | KeyCode::BackTab => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Tab => self.move_selection(1),
+            KeyCode::Char('p' | 'k') if ctrl => self.move_selection(-1),
+            KeyCode::Char('n' | 'j') if ctrl => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-10),
+            KeyCode::PageDown => self.move_selection(10),
+            KeyCode::Char('u') if ctrl => {
+                self.query.clear();
+                self.update_matches();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.update_matches();
+            }
+            KeyCode::Char(ch) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                self.query.push(ch);
+                self.update_matches();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.matches.is_empty() {
+            return;
+        }
+        let last = self.matches.len() as isize - 1;
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize;
+    }
+
+    /// Bring the matches up to date with the query and the index
+    ///
+    /// A query that extends the previous one only narrows the previous
+    /// matches; files indexed since the last update are checked on their own.
+    fn update_matches(&mut self) {
+        let Some(index) = &self.index else {
+            return;
+        };
+        let files = index.files();
+
+        if self.query != self.matched_query {
+            if self.query.starts_with(&self.matched_query) {
+                let query = &self.query;
+                self.matches = self.matches.iter()
+                    .filter_map(|&(file, _)| Some((file, score_path(query, &files[file])?)))
+                    .collect();
+            } else {
+                self.matches.clear();
+                self.checked = 0;
+            }
+            self.matched_query = self.query.clone();
+            self.selected = 0;
+        }
+
+        let query = &self.query;
+        self.matches.extend((self.checked..files.len()).filter_map(|file| Some((file, score_path(query, &files[file])?))));
+        self.checked = files.len();
+
+        // Best score first, then shorter paths, then in walk order
+        self.matches.sort_by(|(a, a_score), (b, b_score)| {
+            b_score.cmp(a_score).then(files[*a].len().cmp(&files[*b].len())).then(a.cmp(b))
+        });
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+    }
+}
+
+/// Read the start of a file for its preview
+fn read_preview(root: &Path, path: &str) -> FilePreview {
+    let language = Path::new(path).extension()
+        .and_then(|ext| ext.to_str())
+        .map(SupportedLanguage::from_extension)
+        .unwrap_or(SupportedLanguage::Text);
+
+    let mut bytes = Vec::new();
+    let read = fs::File::open(root.join(path)).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut bytes));
+    let lines = match read {
+        Err(e) => vec![format!("<{}>", e)],
+        Ok(_) if bytes.contains(&0) => vec!["<binary file>".to_string()],
+        Ok(_) => String::from_utf8_lossy(&bytes)
+            .lines()
+            .take(PREVIEW_LINES)
+            .map(|line| line.replace('\t', "    "))
+            .collect(),
+    };
+    FilePreview { path: path.to_string(), language, lines }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_finder_indexes_and_matches() {
+        let root = std::env::temp_dir().join(format!("rusk-finder-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join(".hidden"), "").unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("src/nested/mod.rs"), "").unwrap();
+        fs::write(root.join("target/out.rs"), "").unwrap();
+
+        let mut finder = FileFinder::new();
+        finder.open(&root);
+        let started = Instant::now();
+        while !finder.indexed().1 && started.elapsed() < Duration::from_secs(5) {
+            finder.poll();
+            thread::sleep(Duration::from_millis(5));
+        }
+        finder.poll();
+        assert_eq!(finder.indexed(), (2, true));
+
+        for ch in "mai".chars() {
+            finder.handle_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE));
+        }
+        assert_eq!(finder.match_count(), 1);
+        assert_eq!(finder.preview().map(|preview| preview.lines.clone()), Some(vec!["fn main() {}".to_string()]));
+        assert_eq!(finder.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)), Some(root.join("src/main.rs")));
+        assert!(!finder.is_open());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,202 @@
+//! `.gitignore` matching
+//!
+//! Supports the usual gitignore syntax: `*`, `?`, `[...]` and `**` globs,
+//! `!` negation, a trailing `/` for directories only, and patterns with a
+//! `/` being anchored to the directory of their `.gitignore`. Deeper files
+//! and later lines take precedence.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A single pattern line
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    glob: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    /// Matches the path relative to the `.gitignore` rather than just the file name
+    anchored: bool,
+}
+
+impl IgnorePattern {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        // Trailing spaces are ignored unless escaped
+        let trimmed = line.trim_end_matches(' ');
+        let line = if trimmed.ends_with('\\') && trimmed.len() < line.len() { &line[..trimmed.len() + 1] } else { trimmed };
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').filter(|rest| rest.starts_with(['!', '#'])).unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Self { glob: line.chars().collect(), negated, dir_only, anchored })
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let target = if self.anchored { relative } else { relative.rsplit('/').next().unwrap_or(relative) };
+        glob_match(&self.glob, &target.chars().collect::<Vec<_>>())
+    }
+}
+
+/// Match a gitignore glob against a `/`-separated path
+pub fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            match rest.split_first() {
+                // `**/` matches zero or more whole directories
+                Some(('/', after)) => (0..=text.len())
+                    .filter(|&i| i == 0 || text[i - 1] == '/')
+                    .any(|i| glob_match(after, &text[i..])),
+                _ => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+            }
+        }
+        Some('*') => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match(&pattern[1..], &text[i..])),
+        Some('?') => text.first().is_some_and(|&ch| ch != '/') && glob_match(&pattern[1..], &text[1..]),
+        Some('[') => match match_class(&pattern[1..], text.first().copied()) {
+            Some((matched, len)) => matched && glob_match(&pattern[1 + len..], &text[1..]),
+            None => text.first() == Some(&'[') && glob_match(&pattern[1..], &text[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => text.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &text[1..]),
+        Some(&ch) => text.first() == Some(&ch) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Match a `[...]` class, given the pattern after `[`; returns whether it matched and the class length including `]`
+fn match_class(class: &[char], ch: Option<char>) -> Option<(bool, usize)> {
+    let negated = matches!(class.first(), Some('!' | '^'));
+    let start = usize::from(negated);
+    // A `]` right after the opening bracket is part of the class
+    let end = start + 1 + class.get(start + 1..)?.iter().position(|&c| c == ']')?;
+    let members = &class[start..end];
+
+    let ch = ch.filter(|&ch| ch != '/');
+    let found = ch.is_some_and(|ch| {
+        let mut i = 0;
+        while i < members.len() {
+            if i + 2 < members.len() && members[i + 1] == '-' {
+                if members[i] <= ch && ch <= members[i + 2] {
+                    return true;
+                }
+                i += 3;
+            } else {
+                if members[i] == ch {
+                    return true;
+                }
+                i += 1;
+            }
+        }
+        false
+    });
+    Some((ch.is_some() && found != negated, end + 1))
+}
+
+/// The patterns of one `.gitignore`, with the directory it applies to
+#[derive(Debug)]
+struct IgnoreFile {
+    /// Directory relative to the root, `/`-separated, empty for the root
+    base: String,
+    patterns: Vec<IgnorePattern>,
+}
+
+/// The ignore files that apply to a directory of a walk
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    files: Vec<Arc<IgnoreFile>>,
+}
+
+impl IgnoreRules {
+    /// Load the root's `.gitignore` and `.git/info/exclude`
+    pub fn new(root: &Path) -> Self {
+        let mut rules = Self::default();
+        rules.add_file(&root.join(".git").join("info").join("exclude"), "");
+        rules.add_file(&root.join(".gitignore"), "");
+        rules
+    }
+
+    /// Rules for a subdirectory, adding its own `.gitignore`
+    pub fn child(&self, dir: &Path, relative: &str) -> Self {
+        let mut rules = self.clone();
+        rules.add_file(&dir.join(".gitignore"), relative);
+        rules
+    }
+
+    fn add_file(&mut self, path: &Path, base: &str) {
+        let Ok(content) = fs::read_to_string(path) else {
+            return;
+        };
+        let patterns: Vec<IgnorePattern> = content.lines().filter_map(IgnorePattern::parse).collect();
+        if !patterns.is_empty() {
+            self.files.push(Arc::new(IgnoreFile { base: base.to_string(), patterns }));
+        }
+    }
+
+    /// Check whether a path relative to the root is ignored
+    pub fn is_ignored(&self, relative: &str, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for file in &self.files {
+            let inner = if file.base.is_empty() {
+                relative
+            } else {
+                match relative.strip_prefix(file.base.as_str()).and_then(|rest| rest.strip_prefix('/')) {
+                    Some(inner) => inner,
+                    None => continue,
+                }
+            };
+            for pattern in &file.patterns {
+                if pattern.matches(inner, is_dir) {
+                    ignored = !pattern.negated;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(lines: &str) -> IgnoreRules {
+        let patterns = lines.lines().filter_map(IgnorePattern::parse).collect();
+        IgnoreRules { files: vec![Arc::new(IgnoreFile { base: String::new(), patterns })] }
+    }
+
+    #[test]
+    fn test_gitignore_patterns() {
+        let rules = rules("# build output\ntarget/\n*.log\n!keep.log\n/root.txt\ndocs/**/*.tmp\n[Tt]emp?");
+
+        assert!(rules.is_ignored("target", true));
+        assert!(!rules.is_ignored("target", false));
+        assert!(rules.is_ignored("src/debug.log", false));
+        assert!(!rules.is_ignored("src/keep.log", false));
+        assert!(rules.is_ignored("root.txt", false));
+        assert!(!rules.is_ignored("src/root.txt", false));
+        assert!(rules.is_ignored("docs/a.tmp", false));
+        assert!(rules.is_ignored("docs/a/b/c.tmp", false));
+        assert!(!rules.is_ignored("src/a.tmp", false));
+        assert!(rules.is_ignored("Temp1", false));
+        assert!(!rules.is_ignored("temp", false));
+        assert!(!rules.is_ignored("src/main.rs", false));
+    }
+}
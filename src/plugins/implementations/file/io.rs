@@ -1,3 +1,7 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
 /// File I/O operations
 pub struct FileIo;
 
@@ -5,4 +9,41 @@ impl FileIo {
     pub fn new() -> Self {
         Self
     }
-}
\ No newline at end of file
+
+    /// Get where a file's backup goes: next to it, with `~` after its name
+    pub fn backup_path(path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push("~");
+        path.with_file_name(name)
+    }
+
+    /// Copy a file to its backup before it is overwritten, returning the backup's path
+    ///
+    /// A file that does not exist yet has nothing to back up.
+    pub fn write_backup(path: &Path) -> io::Result<Option<PathBuf>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let backup = Self::backup_path(path);
+        fs::copy(path, &backup)?;
+        Ok(Some(backup))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_keeps_the_old_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.txt");
+        assert_eq!(FileIo::write_backup(&file).unwrap(), None);
+
+        fs::write(&file, "old").unwrap();
+        let backup = FileIo::write_backup(&file).unwrap().unwrap();
+        assert_eq!(backup, dir.path().join("notes.txt~"));
+        fs::write(&file, "new").unwrap();
+        assert_eq!(fs::read_to_string(backup).unwrap(), "old");
+    }
+}
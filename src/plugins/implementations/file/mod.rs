@@ -1,3 +1,6 @@
 //! File operation plugins
 
-pub mod io;
\ No newline at end of file
+pub mod io;
+pub mod ignore;
+pub mod finder;
+pub mod explorer;
@@ -0,0 +1,110 @@
+/// Fold region computation using tree-sitter fold queries
+use crate::core::buffer::{Buffer, BufferId, FoldRange};
+use crate::core::buffer::folds::indent_fold_ranges;
+use crate::{Result, RuskError};
+use super::syntax::SupportedLanguage;
+use super::tree::SyntaxTreeProvider;
+use std::collections::HashMap;
+use tree_sitter::{Query, QueryCursor, StreamingIterator, Tree};
+
+/// Computes fold regions for buffers
+///
+/// Languages with a `folds.scm` query get syntax-based folds, everything
+/// else falls back to indentation.
+pub struct FoldingProvider {
+    trees: SyntaxTreeProvider,
+    queries: HashMap<SupportedLanguage, Query>,
+    tab_size: usize,
+    /// Buffer, edit version, language and tab size the current fold regions were computed for
+    computed: Option<(BufferId, u64, SupportedLanguage, usize)>,
+}
+
+impl FoldingProvider {
+    pub fn new() -> Result<Self> {
+        let mut provider = Self {
+            trees: SyntaxTreeProvider::new()?,
+            queries: HashMap::new(),
+            tab_size: 4,
+            computed: None,
+        };
+
+        provider.initialize_languages()?;
+
+        Ok(provider)
+    }
+
+    /// Initialize fold queries for supported languages
+    fn initialize_languages(&mut self) -> Result<()> {
+        for language in [SupportedLanguage::Rust, SupportedLanguage::Python] {
+            let (Some(ts_language), Some(query_source)) = (language.get_language(), language.get_fold_query()) else {
+                continue;
+            };
+
+            let query = Query::new(&ts_language, query_source)
+                .map_err(|e| RuskError::Syntax(format!("Failed to create {:?} fold query: {}", language, e)))?;
+
+            self.queries.insert(language, query);
+        }
+
+        Ok(())
+    }
+
+    /// Set the tab width used by indentation folding
+    pub fn set_tab_size(&mut self, tab_size: usize) {
+        self.tab_size = tab_size;
+    }
+
+    /// Compute fold regions for a buffer
+    pub fn compute_ranges(&mut self, buffer: &mut Buffer) -> Vec<FoldRange> {
+        if self.queries.contains_key(buffer.language())
+            && self.trees.ensure_tree(buffer)
+            && let Some(ranges) = buffer.syntax_tree().and_then(|tree| self.syntax_ranges(tree, buffer))
+        {
+            return ranges;
+        }
+
+        indent_fold_ranges(buffer.lines(), self.tab_size)
+    }
+
+    /// Recompute the fold regions of a buffer, unless it has not changed since they were last computed
+    ///
+    /// Between recomputes edits move the regions along with the text.
+    pub fn refresh(&mut self, buffer: &mut Buffer) {
+        let key = (buffer.id(), buffer.version(), buffer.language().clone(), self.tab_size);
+        if self.computed.as_ref() != Some(&key) {
+            let ranges = self.compute_ranges(buffer);
+            buffer.folds_mut().set_ranges(ranges);
+            self.computed = Some(key);
+        }
+        buffer.reveal_cursor_line();
+    }
+
+    /// Compute fold regions from the language's fold query
+    fn syntax_ranges(&self, tree: &Tree, buffer: &Buffer) -> Option<Vec<FoldRange>> {
+        let query = self.queries.get(buffer.language())?;
+        let source = buffer.text();
+        let fold_capture = query.capture_index_for_name("fold")?;
+
+        let mut ranges = Vec::new();
+        let mut cursor = QueryCursor::new();
+        let mut captures = cursor.captures(query, tree.root_node(), source.as_bytes());
+
+        while let Some((query_match, capture_index)) = captures.next() {
+            let capture = query_match.captures[*capture_index];
+            if capture.index != fold_capture {
+                continue;
+            }
+
+            let start = capture.node.start_position();
+            let end = capture.node.end_position();
+
+            // A node ending at column 0 does not include any text on its last row
+            let end_line = if end.column == 0 { end.row.saturating_sub(1) } else { end.row };
+            if end_line > start.row {
+                ranges.push(FoldRange::new(start.row, end_line));
+            }
+        }
+
+        Some(ranges)
+    }
+}
@@ -0,0 +1,252 @@
+/// Asynchronous JSON-RPC client for a single language server process
+use crate::{Result, RuskError};
+use super::protocol::{encode_message, read_message};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, oneshot};
+
+/// Notification sent by a language server
+#[derive(Debug, Clone)]
+pub struct ServerNotification {
+    pub method: String,
+    pub params: Value,
+}
+
+type PendingRequests = Arc<Mutex<HashMap<i64, oneshot::Sender<Result<Value>>>>>;
+
+/// Connection to a running language server
+pub struct LspClient {
+    name: String,
+    child: Child,
+    outgoing: mpsc::UnboundedSender<Value>,
+    pending: PendingRequests,
+    next_id: AtomicI64,
+    capabilities: Arc<OnceLock<Value>>,
+    timeout: Duration,
+}
+
+impl LspClient {
+    /// Spawn a server and wire its stdio to reader and writer tasks on the current runtime
+    ///
+    /// The `initialize` handshake runs on the writer task, which holds every
+    /// other message back until the server has answered, so starting a
+    /// server never waits on it.
+    pub fn spawn(
+        name: &str,
+        command: &str,
+        args: &[String],
+        root: &Path,
+        timeout: Duration,
+        notifications: mpsc::UnboundedSender<ServerNotification>,
+    ) -> Result<Self> {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| RuskError::Language(format!("Failed to start language server '{}': {}", command, e)))?;
+
+        let mut stdin = child.stdin.take()
+            .ok_or_else(|| RuskError::Language(format!("Language server '{}' has no stdin", command)))?;
+        let stdout = child.stdout.take()
+            .ok_or_else(|| RuskError::Language(format!("Language server '{}' has no stdout", command)))?;
+
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<Value>();
+        let (initialized, initialize_response) = oneshot::channel();
+        let pending: PendingRequests = Arc::new(Mutex::new(HashMap::from([(0, initialized)])));
+        let capabilities = Arc::new(OnceLock::new());
+
+        // Writer task: initialize, then serialize outgoing messages in order
+        let initialize = json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": Self::initialize_params(root) });
+        let server_capabilities = capabilities.clone();
+        tokio::spawn(async move {
+            if stdin.write_all(&encode_message(&initialize)).await.is_err() || stdin.flush().await.is_err() {
+                return;
+            }
+            // Dropping the queue when the handshake fails makes later messages report the server as not running
+            let Ok(Ok(Ok(result))) = tokio::time::timeout(timeout, initialize_response).await else {
+                return;
+            };
+            let _ = server_capabilities.set(result.get("capabilities").cloned().unwrap_or(Value::Null));
+
+            let initialized = json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} });
+            if stdin.write_all(&encode_message(&initialized)).await.is_err() {
+                return;
+            }
+            while let Some(message) = outgoing_rx.recv().await {
+                if stdin.write_all(&encode_message(&message)).await.is_err() || stdin.flush().await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        // Reader task: route responses to waiting requests and everything else to the notification channel
+        let reader_pending = pending.clone();
+        let reader_outgoing = outgoing.clone();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(message)) = read_message(&mut reader).await {
+                Self::dispatch(message, &reader_pending, &reader_outgoing, &notifications);
+            }
+
+            // The server went away; fail everything still waiting
+            for (_, sender) in Self::lock(&reader_pending).drain() {
+                let _ = sender.send(Err(RuskError::Language("Language server exited".to_string())));
+            }
+        });
+
+        Ok(Self {
+            name: name.to_string(),
+            child,
+            outgoing,
+            pending,
+            next_id: AtomicI64::new(1),
+            capabilities,
+            timeout,
+        })
+    }
+
+    /// Lock the requests waiting for a response
+    fn lock(pending: &PendingRequests) -> MutexGuard<'_, HashMap<i64, oneshot::Sender<Result<Value>>>> {
+        pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Route one incoming message
+    fn dispatch(
+        message: Value,
+        pending: &PendingRequests,
+        outgoing: &mpsc::UnboundedSender<Value>,
+        notifications: &mpsc::UnboundedSender<ServerNotification>,
+    ) {
+        let method = message.get("method").and_then(Value::as_str).map(str::to_string);
+        let id = message.get("id").cloned();
+
+        match (method, id) {
+            // Response to one of our requests
+            (None, Some(id)) => {
+                let Some(id) = id.as_i64() else { return };
+                if let Some(sender) = Self::lock(pending).remove(&id) {
+                    let result = match message.get("error") {
+                        Some(error) => Err(RuskError::Language(format!(
+                            "Language server error: {}",
+                            error.get("message").and_then(Value::as_str).unwrap_or("unknown error")
+                        ))),
+                        None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+                    };
+                    let _ = sender.send(result);
+                }
+            }
+            // Server-to-client request; we support none, so answer with an empty result
+            (Some(method), Some(id)) => {
+                let result = match method.as_str() {
+                    "workspace/configuration" => json!([]),
+                    _ => Value::Null,
+                };
+                let _ = outgoing.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+            }
+            (Some(method), None) => {
+                let params = message.get("params").cloned().unwrap_or(Value::Null);
+                let _ = notifications.send(ServerNotification { method, params });
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Get the server name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the capabilities the server announced during initialization, or null until it has answered
+    pub fn capabilities(&self) -> &Value {
+        self.capabilities.get().unwrap_or(&Value::Null)
+    }
+
+    /// Check if the server announced a capability such as `hoverProvider`
+    pub fn supports(&self, capability: &str) -> bool {
+        match self.capabilities().get(capability) {
+            None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+            Some(_) => true,
+        }
+    }
+
+    /// Send a request, returning a future for its result that does not borrow the client
+    ///
+    /// The request is queued before this returns, so it reaches the server
+    /// ahead of any later notification whether or not the future is polled.
+    pub fn request(&self, method: &str, params: Value) -> impl Future<Output = Result<Value>> + Send + 'static {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = oneshot::channel();
+        Self::lock(&self.pending).insert(id, sender);
+
+        let sent = self.outgoing.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        let (name, method, pending, timeout) = (self.name.clone(), method.to_string(), self.pending.clone(), self.timeout);
+
+        async move {
+            if sent.is_err() {
+                Self::lock(&pending).remove(&id);
+                return Err(RuskError::Language(format!("Language server '{}' is not running", name)));
+            }
+            match tokio::time::timeout(timeout, receiver).await {
+                Ok(Ok(result)) => result,
+                Ok(Err(_)) => Err(RuskError::Language(format!("Language server '{}' dropped request '{}'", name, method))),
+                Err(_) => {
+                    Self::lock(&pending).remove(&id);
+                    Err(RuskError::Language(format!("Request '{}' to '{}' timed out", method, name)))
+                }
+            }
+        }
+    }
+
+    /// Send a notification
+    pub fn notify(&self, method: &str, params: Value) -> Result<()> {
+        self.outgoing
+            .send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+            .map_err(|_| RuskError::Language(format!("Language server '{}' is not running", self.name)))
+    }
+
+    /// Parameters of the `initialize` request
+    fn initialize_params(root: &Path) -> Value {
+        let root_uri = super::protocol::path_to_uri(root);
+        json!({
+            "processId": std::process::id(),
+            "rootUri": root_uri,
+            "workspaceFolders": [{ "uri": root_uri, "name": root.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default() }],
+            "capabilities": {
+                "textDocument": {
+                    "synchronization": { "didSave": true },
+                    "hover": { "contentFormat": ["plaintext", "markdown"] },
+                    "completion": { "completionItem": { "snippetSupport": true, "documentationFormat": ["plaintext", "markdown"] } },
+                    "definition": {},
+                    "references": {},
+                    "rename": { "prepareSupport": false },
+                    "codeAction": {},
+                    "formatting": {},
+                    "publishDiagnostics": { "relatedInformation": false },
+                },
+                "workspace": { "workspaceEdit": { "documentChanges": true } },
+            },
+        })
+    }
+
+    /// Shut the server down politely, killing it if it does not exit
+    pub async fn shutdown(&mut self) -> Result<()> {
+        let _ = self.request("shutdown", Value::Null).await;
+        let _ = self.notify("exit", Value::Null);
+
+        if tokio::time::timeout(Duration::from_secs(2), self.child.wait()).await.is_err() {
+            self.child.kill().await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,571 @@
+/// Language server management for open buffers
+use crate::core::buffer::{Buffer, Diagnostic};
+use crate::core::cursor::{Position, TextRange};
+use crate::plugins::implementations::language::syntax::SupportedLanguage;
+use crate::{Config, Result, RuskError};
+use super::client::{LspClient, ServerNotification};
+use super::protocol::{
+    compute_change, from_lsp_position, path_to_uri, to_lsp_position, uri_to_path, CodeAction, LspCompletionItem,
+    LspDiagnostic, Location, TextEdit, WorkspaceEdit,
+};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
+
+/// How to start the language server for a language
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LspServerConfig {
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+impl LspServerConfig {
+    pub fn new(command: impl Into<String>, args: Vec<String>) -> Self {
+        Self {
+            command: command.into(),
+            args,
+        }
+    }
+
+    /// Default servers for the supported languages
+    pub fn defaults() -> HashMap<SupportedLanguage, Self> {
+        HashMap::from([
+            (SupportedLanguage::Rust, Self::new("rust-analyzer", Vec::new())),
+            (SupportedLanguage::Python, Self::new("pylsp", Vec::new())),
+        ])
+    }
+
+    /// Read server settings from `[plugins.plugin_configs.lsp]`, on top of the defaults
+    ///
+    /// ```toml
+    /// [plugins.plugin_configs.lsp.rust]
+    /// command = "rust-analyzer"
+    /// args = []
+    /// ```
+    pub fn from_config(config: &Config) -> Result<HashMap<SupportedLanguage, Self>> {
+        let mut servers = Self::defaults();
+
+        let Some(table) = config.plugins.plugin_configs.get("lsp").and_then(|value| value.as_table()) else {
+            return Ok(servers);
+        };
+
+        for (language_id, settings) in table {
+            if language_id == "timeout_ms" {
+                continue;
+            }
+
+            let language = SupportedLanguage::from_language_id(language_id)
+                .ok_or_else(|| RuskError::Config(format!("Unknown language '{}' in LSP configuration", language_id)))?;
+
+            // `enabled = false` turns a language server off
+            if settings.get("enabled").and_then(|value| value.as_bool()) == Some(false) {
+                servers.remove(&language);
+                continue;
+            }
+
+            let command = settings.get("command").and_then(|value| value.as_str())
+                .ok_or_else(|| RuskError::Config(format!("LSP server for '{}' needs a command", language_id)))?;
+            let args = settings.get("args")
+                .and_then(|value| value.as_array())
+                .map(|args| args.iter().filter_map(|arg| arg.as_str().map(str::to_string)).collect())
+                .unwrap_or_default();
+
+            servers.insert(language, Self::new(command, args));
+        }
+
+        Ok(servers)
+    }
+}
+
+/// Last text sent to the server for a document
+struct SyncedDocument {
+    language: SupportedLanguage,
+    version: u64,
+    lines: Vec<String>,
+}
+
+/// A request sent to a language server, whose response is taken by polling
+pub struct LspRequest<T> {
+    response: oneshot::Receiver<Result<Value>>,
+    parse: fn(Value) -> Result<T>,
+}
+
+impl<T> LspRequest<T> {
+    /// Take the response if it has arrived
+    pub fn poll(&mut self) -> Option<Result<T>> {
+        match self.response.try_recv() {
+            Ok(result) => Some(result.and_then(self.parse)),
+            Err(oneshot::error::TryRecvError::Empty) => None,
+            Err(oneshot::error::TryRecvError::Closed) => Some(Err(Self::dropped())),
+        }
+    }
+
+    /// Block until the response arrives; must not be called from the runtime
+    pub fn wait(self) -> Result<T> {
+        self.response.blocking_recv().map_err(|_| Self::dropped())?.and_then(self.parse)
+    }
+
+    fn dropped() -> RuskError {
+        RuskError::Language("Language server request was dropped".to_string())
+    }
+}
+
+/// Runs language servers per `SupportedLanguage` and keeps them in sync with buffers
+///
+/// Server I/O happens on tokio tasks. Notifications are sent straight away
+/// and requests return an `LspRequest` to poll, so the synchronous editor
+/// loop never waits on a server.
+pub struct LspManager {
+    runtime: Handle,
+    root: PathBuf,
+    servers: HashMap<SupportedLanguage, LspServerConfig>,
+    clients: HashMap<SupportedLanguage, LspClient>,
+    failed: HashSet<SupportedLanguage>,
+    documents: HashMap<String, SyncedDocument>,
+    diagnostics: HashMap<String, Vec<LspDiagnostic>>,
+    notifications: mpsc::UnboundedReceiver<ServerNotification>,
+    notification_sender: mpsc::UnboundedSender<ServerNotification>,
+    timeout: Duration,
+}
+
+impl LspManager {
+    /// Create a manager that runs servers on the given runtime
+    pub fn new(runtime: Handle, servers: HashMap<SupportedLanguage, LspServerConfig>) -> Self {
+        let (notification_sender, notifications) = mpsc::unbounded_channel();
+
+        Self {
+            runtime,
+            root: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            servers,
+            clients: HashMap::new(),
+            failed: HashSet::new(),
+            documents: HashMap::new(),
+            diagnostics: HashMap::new(),
+            notifications,
+            notification_sender,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Create a manager from the editor configuration
+    pub fn from_config(runtime: Handle, config: &Config) -> Result<Self> {
+        let mut manager = Self::new(runtime, LspServerConfig::from_config(config)?);
+
+        if let Some(timeout) = config.plugins.plugin_configs.get("lsp")
+            .and_then(|lsp| lsp.get("timeout_ms"))
+            .and_then(|value| value.as_integer())
+        {
+            manager.timeout = Duration::from_millis(timeout.max(0) as u64);
+        }
+
+        Ok(manager)
+    }
+
+    /// Set the workspace root sent to servers
+    pub fn set_root(&mut self, root: impl Into<PathBuf>) {
+        self.root = root.into();
+    }
+
+    /// Check if a server is configured for a language
+    pub fn has_server(&self, language: &SupportedLanguage) -> bool {
+        self.servers.contains_key(language) && !self.failed.contains(language)
+    }
+
+    /// Get the running client for a language, starting it on first use
+    fn client(&mut self, language: &SupportedLanguage) -> Result<&LspClient> {
+        if !self.clients.contains_key(language) {
+            if self.failed.contains(language) {
+                return Err(RuskError::Language(format!("Language server for {} failed to start", language.language_id())));
+            }
+
+            let config = self.servers.get(language)
+                .ok_or_else(|| RuskError::Language(format!("No language server configured for {}", language.language_id())))?
+                .clone();
+
+            let started = {
+                let _guard = self.runtime.enter();
+                LspClient::spawn(
+                    language.language_id(),
+                    &config.command,
+                    &config.args,
+                    &self.root,
+                    self.timeout,
+                    self.notification_sender.clone(),
+                )
+            };
+
+            match started {
+                Ok(client) => {
+                    self.clients.insert(language.clone(), client);
+                }
+                Err(e) => {
+                    self.failed.insert(language.clone());
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(&self.clients[language])
+    }
+
+    /// Get the document URI of a buffer
+    fn buffer_uri(buffer: &Buffer) -> Result<String> {
+        buffer.file_path()
+            .map(|path| path_to_uri(Path::new(path)))
+            .ok_or_else(|| RuskError::Language("Buffer has no file path".to_string()))
+    }
+
+    /// Send `textDocument/didOpen` for a buffer
+    pub fn open_buffer(&mut self, buffer: &Buffer) -> Result<()> {
+        let uri = Self::buffer_uri(buffer)?;
+        let language = buffer.language().clone();
+
+        self.client(&language)?.notify("textDocument/didOpen", json!({
+            "textDocument": {
+                "uri": uri,
+                "languageId": language.language_id(),
+                "version": buffer.version(),
+                "text": buffer.text(),
+            }
+        }))?;
+
+        self.documents.insert(uri, SyncedDocument {
+            language,
+            version: buffer.version(),
+            lines: buffer.lines().to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Send the buffer's changes since the last sync as an incremental `didChange`
+    pub fn sync_buffer(&mut self, buffer: &Buffer) -> Result<()> {
+        if buffer.file_path().is_none() || !self.has_server(buffer.language()) {
+            return Ok(());
+        }
+
+        let uri = Self::buffer_uri(buffer)?;
+        let Some(document) = self.documents.get(&uri) else {
+            return self.open_buffer(buffer);
+        };
+        if document.version == buffer.version() {
+            return Ok(());
+        }
+
+        let change = compute_change(&document.lines, buffer.lines());
+        let language = document.language.clone();
+
+        if let Some(change) = change {
+            self.client(&language)?.notify("textDocument/didChange", json!({
+                "textDocument": { "uri": uri, "version": buffer.version() },
+                "contentChanges": [change],
+            }))?;
+        }
+
+        if let Some(document) = self.documents.get_mut(&uri) {
+            document.version = buffer.version();
+            document.lines = buffer.lines().to_vec();
+        }
+        Ok(())
+    }
+
+    /// Send `textDocument/didSave` for a buffer
+    pub fn save_buffer(&mut self, buffer: &Buffer) -> Result<()> {
+        self.sync_buffer(buffer)?;
+        let uri = Self::buffer_uri(buffer)?;
+        if let Some(document) = self.documents.get(&uri) {
+            let language = document.language.clone();
+            self.client(&language)?.notify("textDocument/didSave", json!({ "textDocument": { "uri": uri } }))?;
+        }
+        Ok(())
+    }
+
+    /// Send `textDocument/didClose` for a buffer
+    pub fn close_buffer(&mut self, buffer: &Buffer) -> Result<()> {
+        let uri = Self::buffer_uri(buffer)?;
+        if let Some(document) = self.documents.remove(&uri) {
+            self.diagnostics.remove(&uri);
+            self.client(&document.language)?.notify("textDocument/didClose", json!({ "textDocument": { "uri": uri } }))?;
+        }
+        Ok(())
+    }
+
+    /// Process server notifications, returning whether diagnostics changed
+    pub fn poll(&mut self) -> bool {
+        let mut diagnostics_changed = false;
+
+        while let Ok(notification) = self.notifications.try_recv() {
+            if notification.method == "textDocument/publishDiagnostics" {
+                let Some(uri) = notification.params.get("uri").and_then(Value::as_str) else { continue };
+                let diagnostics = notification.params.get("diagnostics")
+                    .cloned()
+                    .and_then(|value| serde_json::from_value::<Vec<LspDiagnostic>>(value).ok())
+                    .unwrap_or_default();
+
+                self.diagnostics.insert(uri.to_string(), diagnostics);
+                diagnostics_changed = true;
+            }
+        }
+
+        diagnostics_changed
+    }
+
+    /// Wait until diagnostics arrive or the timeout passes, returning whether any arrived
+    pub fn wait_for_diagnostics(&mut self, timeout: Duration) -> bool {
+        let deadline = std::time::Instant::now() + timeout;
+        while std::time::Instant::now() < deadline {
+            if self.poll() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    /// Get the latest diagnostics for a file
+    pub fn diagnostics(&self, path: &str) -> &[LspDiagnostic] {
+        self.diagnostics.get(&path_to_uri(Path::new(path))).map(|d| d.as_slice()).unwrap_or(&[])
+    }
+
+    /// Get the latest diagnostics for a buffer, converted to buffer positions
+    pub fn buffer_diagnostics(&self, buffer: &Buffer) -> Vec<Diagnostic> {
+        let Some(path) = buffer.file_path() else {
+            return Vec::new();
+        };
+        self.diagnostics(path).iter().map(|d| d.to_diagnostic(buffer.lines())).collect()
+    }
+
+    /// Send a request on the runtime, answering through the returned handle
+    fn send<T>(&mut self, language: &SupportedLanguage, method: &str, params: Value, parse: fn(Value) -> Result<T>) -> Result<LspRequest<T>> {
+        let request = self.client(language)?.request(method, params);
+        let (sender, response) = oneshot::channel();
+        self.runtime.spawn(async move {
+            let _ = sender.send(request.await);
+        });
+        Ok(LspRequest { response, parse })
+    }
+
+    /// Sync the buffer and send a position request at the cursor
+    fn position_request<T>(&mut self, buffer: &Buffer, method: &str, extra: Value, parse: fn(Value) -> Result<T>) -> Result<LspRequest<T>> {
+        self.sync_buffer(buffer)?;
+
+        let uri = Self::buffer_uri(buffer)?;
+        let (line, col) = buffer.cursor_position();
+        let mut params = json!({
+            "textDocument": { "uri": uri },
+            "position": to_lsp_position(buffer.lines(), Position::new(line, col)),
+        });
+        if let (Some(params), Some(extra)) = (params.as_object_mut(), extra.as_object()) {
+            params.extend(extra.clone());
+        }
+
+        self.send(buffer.language(), method, params, parse)
+    }
+
+    /// Get hover text at the cursor
+    pub fn hover(&mut self, buffer: &Buffer) -> Result<LspRequest<Option<String>>> {
+        self.position_request(buffer, "textDocument/hover", Value::Null, |result| {
+            Ok(result.get("contents").map(hover_text).filter(|text| !text.is_empty()))
+        })
+    }
+
+    /// Get completion candidates at the cursor
+    pub fn completion(&mut self, buffer: &Buffer, trigger: Option<char>) -> Result<LspRequest<Vec<LspCompletionItem>>> {
+        let context = match trigger {
+            Some(ch) => json!({ "context": { "triggerKind": 2, "triggerCharacter": ch.to_string() } }),
+            None => json!({ "context": { "triggerKind": 1 } }),
+        };
+        self.position_request(buffer, "textDocument/completion", context, |result| {
+            // Either `CompletionItem[]` or a `CompletionList` with `items`
+            let items = match result {
+                Value::Array(items) => items,
+                Value::Object(mut list) => match list.remove("items") {
+                    Some(Value::Array(items)) => items,
+                    _ => Vec::new(),
+                },
+                _ => Vec::new(),
+            };
+            Ok(items.into_iter().filter_map(|item| serde_json::from_value(item).ok()).collect())
+        })
+    }
+
+    /// Get definition locations of the symbol at the cursor
+    pub fn definition(&mut self, buffer: &Buffer) -> Result<LspRequest<Vec<Location>>> {
+        self.position_request(buffer, "textDocument/definition", Value::Null, |result| Ok(parse_locations(result)))
+    }
+
+    /// Get references to the symbol at the cursor, including its declaration
+    pub fn references(&mut self, buffer: &Buffer) -> Result<LspRequest<Vec<Location>>> {
+        let context = json!({ "context": { "includeDeclaration": true } });
+        self.position_request(buffer, "textDocument/references", context, |result| Ok(parse_locations(result)))
+    }
+
+    /// Ask the server how to rename the symbol at the cursor
+    pub fn rename(&mut self, buffer: &Buffer, new_name: &str) -> Result<LspRequest<WorkspaceEdit>> {
+        self.position_request(buffer, "textDocument/rename", json!({ "newName": new_name }), |result| {
+            if result.is_null() {
+                return Ok(WorkspaceEdit::default());
+            }
+            serde_json::from_value(result)
+                .map_err(|e| RuskError::Language(format!("Invalid rename result: {}", e)))
+        })
+    }
+
+    /// Get code actions for the cursor line
+    pub fn code_actions(&mut self, buffer: &Buffer) -> Result<LspRequest<Vec<CodeAction>>> {
+        self.sync_buffer(buffer)?;
+
+        let uri = Self::buffer_uri(buffer)?;
+        let (line, _) = buffer.cursor_position();
+        let line_end = buffer.get_line(line).map_or(0, |text| text.len());
+        let start = to_lsp_position(buffer.lines(), Position::new(line, 0));
+        let end = to_lsp_position(buffer.lines(), Position::new(line, line_end));
+
+        let diagnostics: Vec<&LspDiagnostic> = self.diagnostics.get(&uri)
+            .map(|all| all.iter().filter(|d| d.range.start.line as usize <= line && line <= d.range.end.line as usize).collect())
+            .unwrap_or_default();
+
+        let params = json!({
+            "textDocument": { "uri": uri },
+            "range": { "start": start, "end": end },
+            "context": { "diagnostics": diagnostics },
+        });
+
+        self.send(buffer.language(), "textDocument/codeAction", params, |result| {
+            Ok(result.as_array()
+                .map(|items| items.iter().filter_map(parse_code_action).collect())
+                .unwrap_or_default())
+        })
+    }
+
+    /// Run the server command attached to a code action
+    pub fn execute_command(&mut self, language: &SupportedLanguage, command: &Value) -> Result<LspRequest<()>> {
+        let params = json!({
+            "command": command.get("command").cloned().unwrap_or(Value::Null),
+            "arguments": command.get("arguments").cloned().unwrap_or(json!([])),
+        });
+        self.send(language, "workspace/executeCommand", params, |_| Ok(()))
+    }
+
+    /// Get formatting edits for the whole buffer
+    pub fn formatting(&mut self, buffer: &Buffer, tab_size: usize, insert_spaces: bool) -> Result<LspRequest<Vec<TextEdit>>> {
+        self.sync_buffer(buffer)?;
+
+        let params = json!({
+            "textDocument": { "uri": Self::buffer_uri(buffer)? },
+            "options": { "tabSize": tab_size, "insertSpaces": insert_spaces },
+        });
+
+        self.send(buffer.language(), "textDocument/formatting", params, |result| {
+            if result.is_null() {
+                return Ok(Vec::new());
+            }
+            serde_json::from_value(result)
+                .map_err(|e| RuskError::Language(format!("Invalid formatting result: {}", e)))
+        })
+    }
+
+    /// Apply a workspace edit to open buffers, writing files that are not open
+    pub fn apply_workspace_edit(&mut self, buffers: &mut [Buffer], edit: &WorkspaceEdit) -> Result<()> {
+        for (uri, edits) in edit.edits_by_uri() {
+            let open = buffers.iter_mut()
+                .find(|buffer| buffer.file_path().map(|path| path_to_uri(Path::new(path))) == Some(uri.clone()));
+
+            match open {
+                Some(buffer) => {
+                    apply_text_edits(buffer, &edits);
+                    self.sync_buffer(buffer)?;
+                }
+                None => {
+                    let path = uri_to_path(&uri)
+                        .ok_or_else(|| RuskError::Language(format!("Cannot edit non-file URI {}", uri)))?;
+                    let mut buffer = Buffer::from_file(&path)?;
+                    apply_text_edits(&mut buffer, &edits);
+                    buffer.save()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Shut down every running server
+    pub fn shutdown_all(&mut self) {
+        for (_, mut client) in self.clients.drain() {
+            let _ = self.runtime.block_on(client.shutdown());
+        }
+        self.documents.clear();
+    }
+}
+
+impl Drop for LspManager {
+    fn drop(&mut self) {
+        self.shutdown_all();
+    }
+}
+
+/// Apply text edits to a buffer, later edits first so earlier ranges stay valid
+pub fn apply_text_edits(buffer: &mut Buffer, edits: &[TextEdit]) {
+    let mut ranges: Vec<(TextRange, &str)> = edits.iter()
+        .map(|edit| {
+            let start = from_lsp_position(buffer.lines(), edit.range.start);
+            let end = from_lsp_position(buffer.lines(), edit.range.end);
+            (TextRange::new(start, end), edit.new_text.as_str())
+        })
+        .collect();
+    ranges.sort_by_key(|(range, _)| std::cmp::Reverse((range.start.line, range.start.column)));
+
+    let cursor = buffer.cursor_position();
+    for (range, text) in ranges {
+        buffer.replace_range(&range, text);
+    }
+    buffer.set_cursor_position(cursor.0, cursor.1);
+}
+
+/// Convert a location into a buffer position
+pub fn location_position(buffer: &Buffer, location: &Location) -> Position {
+    from_lsp_position(buffer.lines(), location.range.start)
+}
+
+/// Flatten hover contents (`MarkupContent`, `MarkedString` or an array of them) into text
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(hover_text).filter(|t| !t.is_empty()).collect::<Vec<_>>().join("\n"),
+        Value::Object(object) => object.get("value").and_then(Value::as_str).unwrap_or_default().to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Parse a `Location`, `Location[]` or `LocationLink[]` result
+fn parse_locations(result: Value) -> Vec<Location> {
+    let items = match result {
+        Value::Array(items) => items,
+        Value::Null => Vec::new(),
+        single => vec![single],
+    };
+
+    items.into_iter()
+        .filter_map(|item| {
+            if let Some(uri) = item.get("targetUri").and_then(Value::as_str) {
+                let range = item.get("targetSelectionRange").or_else(|| item.get("targetRange"))?;
+                return Some(Location { uri: uri.to_string(), range: serde_json::from_value(range.clone()).ok()? });
+            }
+            serde_json::from_value(item).ok()
+        })
+        .collect()
+}
+
+/// Parse a `Command` or `CodeAction` from a code action result
+fn parse_code_action(item: &Value) -> Option<CodeAction> {
+    if item.get("command").is_some_and(Value::is_string) {
+        return Some(CodeAction {
+            title: item.get("title")?.as_str()?.to_string(),
+            kind: None,
+            edit: None,
+            command: Some(item.clone()),
+        });
+    }
+    serde_json::from_value(item.clone()).ok()
+}
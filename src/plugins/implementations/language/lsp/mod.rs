@@ -0,0 +1,13 @@
+//! Language Server Protocol client
+//!
+//! Servers are configured per `SupportedLanguage`, started on demand on the
+//! tokio runtime, and kept in sync with buffers through incremental
+//! `didChange` notifications.
+
+pub mod protocol;
+pub mod client;
+pub mod manager;
+
+pub use client::{LspClient, ServerNotification};
+pub use manager::{LspManager, LspRequest, LspServerConfig};
+pub use protocol::{CodeAction, Location, LspCompletionItem, LspDiagnostic, LspSeverity, TextEdit, WorkspaceEdit};
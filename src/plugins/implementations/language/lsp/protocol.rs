@@ -0,0 +1,393 @@
+/// Language Server Protocol message framing and data types
+use crate::core::buffer::{Diagnostic, DiagnosticSeverity};
+use crate::core::cursor::{Position, TextChange, TextRange};
+use crate::{Result, RuskError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+/// Encode a JSON-RPC message with its `Content-Length` header
+pub fn encode_message(message: &Value) -> Vec<u8> {
+    let body = message.to_string();
+    let mut bytes = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+    bytes.extend_from_slice(body.as_bytes());
+    bytes
+}
+
+/// Read one JSON-RPC message, returning `None` at end of stream
+pub async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Value>> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            content_length = Some(value.trim().parse::<usize>()
+                .map_err(|e| RuskError::Language(format!("Invalid Content-Length header '{}': {}", header, e)))?);
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap_or(0)];
+    reader.read_exact(&mut body).await?;
+
+    let message = serde_json::from_slice(&body)
+        .map_err(|e| RuskError::Language(format!("Invalid JSON-RPC message: {}", e)))?;
+    Ok(Some(message))
+}
+
+/// Position in a text document, with UTF-16 character offsets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LspPosition {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// Range in a text document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LspRange {
+    pub start: LspPosition,
+    pub end: LspPosition,
+}
+
+/// Location of a range in a document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub uri: String,
+    pub range: LspRange,
+}
+
+impl Location {
+    /// Get the file path of the location
+    pub fn path(&self) -> Option<PathBuf> {
+        uri_to_path(&self.uri)
+    }
+}
+
+/// Text replacement in a document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextEdit {
+    pub range: LspRange,
+    pub new_text: String,
+}
+
+/// Edits across several documents
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceEdit {
+    #[serde(default)]
+    pub changes: HashMap<String, Vec<TextEdit>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub document_changes: Vec<TextDocumentEdit>,
+}
+
+impl WorkspaceEdit {
+    /// Get all edits grouped by document URI
+    pub fn edits_by_uri(&self) -> HashMap<String, Vec<TextEdit>> {
+        let mut edits = self.changes.clone();
+        for change in &self.document_changes {
+            edits.entry(change.text_document.uri.clone())
+                .or_default()
+                .extend(change.edits.iter().cloned());
+        }
+        edits
+    }
+}
+
+/// Edits to a single versioned document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentEdit {
+    pub text_document: DocumentIdentifier,
+    pub edits: Vec<TextEdit>,
+}
+
+/// Reference to a document by URI
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentIdentifier {
+    pub uri: String,
+}
+
+/// Code action offered by the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodeAction {
+    pub title: String,
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub edit: Option<WorkspaceEdit>,
+    #[serde(default)]
+    pub command: Option<Value>,
+}
+
+/// Completion candidate offered by the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LspCompletionItem {
+    pub label: String,
+    #[serde(default)]
+    pub kind: Option<u64>,
+    #[serde(default)]
+    pub detail: Option<String>,
+    #[serde(default)]
+    pub documentation: Option<Value>,
+    #[serde(default)]
+    pub insert_text: Option<String>,
+    #[serde(default)]
+    pub insert_text_format: Option<u64>,
+    #[serde(default)]
+    pub text_edit: Option<Value>,
+    #[serde(default)]
+    pub filter_text: Option<String>,
+}
+
+impl LspCompletionItem {
+    /// Check if the insert text uses snippet syntax
+    pub fn is_snippet(&self) -> bool {
+        self.insert_text_format == Some(2)
+    }
+
+    /// Get the text to insert and the range it replaces, if the server gave one
+    pub fn edit(&self) -> (String, Option<LspRange>) {
+        if let Some(edit) = &self.text_edit {
+            let text = edit.get("newText").and_then(Value::as_str).unwrap_or(&self.label).to_string();
+            // Plain `TextEdit` has `range`; `InsertReplaceEdit` has `insert` and `replace`
+            let range = edit.get("range").or_else(|| edit.get("insert"))
+                .and_then(|range| serde_json::from_value(range.clone()).ok());
+            return (text, range);
+        }
+        (self.insert_text.clone().unwrap_or_else(|| self.label.clone()), None)
+    }
+
+    /// Get the documentation as plain text
+    pub fn documentation_text(&self) -> Option<String> {
+        match self.documentation.as_ref()? {
+            Value::String(text) => Some(text.clone()),
+            Value::Object(markup) => markup.get("value").and_then(Value::as_str).map(str::to_string),
+            _ => None,
+        }
+    }
+}
+
+/// Diagnostic severity levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LspSeverity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl LspSeverity {
+    fn from_code(code: u64) -> Self {
+        match code {
+            1 => Self::Error,
+            2 => Self::Warning,
+            3 => Self::Information,
+            _ => Self::Hint,
+        }
+    }
+}
+
+/// Diagnostic published by the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LspDiagnostic {
+    pub range: LspRange,
+    #[serde(default)]
+    pub severity: Option<u64>,
+    pub message: String,
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+impl LspDiagnostic {
+    /// Get the diagnostic severity, defaulting to error as the protocol suggests
+    pub fn severity(&self) -> LspSeverity {
+        self.severity.map(LspSeverity::from_code).unwrap_or(LspSeverity::Error)
+    }
+
+    /// Convert to a buffer diagnostic against the given lines
+    pub fn to_diagnostic(&self, lines: &[String]) -> Diagnostic {
+        let range = TextRange::new(from_lsp_position(lines, self.range.start), from_lsp_position(lines, self.range.end));
+        let severity = match self.severity() {
+            LspSeverity::Error => DiagnosticSeverity::Error,
+            LspSeverity::Warning => DiagnosticSeverity::Warning,
+            LspSeverity::Information => DiagnosticSeverity::Info,
+            LspSeverity::Hint => DiagnosticSeverity::Hint,
+        };
+        let message = match &self.source {
+            Some(source) => format!("{}: {}", source, self.message),
+            None => self.message.clone(),
+        };
+        Diagnostic::new(range, severity, message)
+    }
+}
+
+/// Incremental document change sent with `textDocument/didChange`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentChange {
+    pub range: LspRange,
+    pub text: String,
+}
+
+/// Compute a single range change turning `old` lines into `new` lines
+///
+/// Only the span between the common prefix and suffix is sent, so typing
+/// a character sends just that character.
+pub fn compute_change(old: &[String], new: &[String]) -> Option<ContentChange> {
+    let change = TextChange::between(old, new)?;
+    let range = LspRange {
+        start: to_lsp_position(old, change.start),
+        end: to_lsp_position(old, change.old_end),
+    };
+    let new_text = new.join("\n");
+    let text = new_text[byte_offset(new, change.start)..byte_offset(new, change.new_end)].to_string();
+
+    Some(ContentChange { range, text })
+}
+
+/// Byte offset of a position in newline-joined lines
+fn byte_offset(lines: &[String], pos: Position) -> usize {
+    lines[..pos.line].iter().map(|line| line.len() + 1).sum::<usize>() + pos.column
+}
+
+/// Number of UTF-16 code units in a string
+pub fn utf16_len(text: &str) -> u32 {
+    text.encode_utf16().count() as u32
+}
+
+/// Convert a buffer position (byte column) to an LSP position (UTF-16 column)
+pub fn to_lsp_position(lines: &[String], pos: Position) -> LspPosition {
+    let character = lines.get(pos.line)
+        .map(|line| utf16_len(&line[..pos.column.min(line.len())]))
+        .unwrap_or(0);
+    LspPosition { line: pos.line as u32, character }
+}
+
+/// Convert an LSP position (UTF-16 column) to a buffer position (byte column)
+pub fn from_lsp_position(lines: &[String], pos: LspPosition) -> Position {
+    let line = pos.line as usize;
+    let Some(text) = lines.get(line) else {
+        let last = lines.len().saturating_sub(1);
+        return Position::new(last, lines.get(last).map_or(0, |l| l.len()));
+    };
+
+    let mut units = 0;
+    for (byte, ch) in text.char_indices() {
+        if units >= pos.character {
+            return Position::new(line, byte);
+        }
+        units += ch.len_utf16() as u32;
+    }
+    Position::new(line, text.len())
+}
+
+/// Convert a file path into a `file://` URI
+pub fn path_to_uri(path: &Path) -> String {
+    let absolute = path.canonicalize().unwrap_or_else(|_| {
+        std::env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf())
+    });
+
+    let mut uri = String::from("file://");
+    for byte in absolute.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Convert a `file://` URI into a file path
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let Ok(byte) = u8::from_str_radix(&encoded[i + 1..i + 3], 16)
+        {
+            decoded.push(byte);
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    Some(PathBuf::from(String::from_utf8_lossy(&decoded).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(|s| s.to_string()).collect()
+    }
+
+    /// Apply a change the way a server would, on the joined text
+    fn apply(old: &[String], change: &ContentChange) -> Vec<String> {
+        let offset = |pos: LspPosition| {
+            old[..pos.line as usize].iter().map(|l| l.len() + 1).sum::<usize>() + pos.character as usize
+        };
+        let mut text = old.join("\n");
+        text.replace_range(offset(change.range.start)..offset(change.range.end), &change.text);
+        lines(&text)
+    }
+
+    #[test]
+    fn test_incremental_changes_round_trip() {
+        let cases = [
+            ("a\nb\nc", "a\nB\nc"),
+            ("a\nb\nc", "a\nb\nc\nd"),
+            ("a\nb\nc", "a\nb"),
+            ("a\nb\nc", "x\na\nb\nc"),
+            ("a\nb\nc", "b\nc"),
+            ("a\nb\nc", "a\nc"),
+            ("a", "a\nb"),
+            ("a\nb", "a"),
+            ("a", ""),
+        ];
+
+        for (old, new) in cases {
+            let (old, new) = (lines(old), lines(new));
+            let change = compute_change(&old, &new).unwrap();
+            assert_eq!(apply(&old, &change), new, "change {:?}", change);
+        }
+    }
+
+    #[test]
+    fn test_utf16_positions() {
+        let text = lines("aé😀b");
+        let pos = to_lsp_position(&text, Position::new(0, 7));
+        assert_eq!(pos.character, 4);
+        assert_eq!(from_lsp_position(&text, pos), Position::new(0, 7));
+    }
+
+    #[test]
+    fn test_uri_round_trip() {
+        let path = Path::new("/tmp/some dir/file.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/some%20dir/file.rs");
+        assert_eq!(uri_to_path(&uri), Some(path.to_path_buf()));
+    }
+}
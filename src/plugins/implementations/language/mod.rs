@@ -1,6 +1,10 @@
 //! Language support plugins
 //!
 //! This module contains language-specific functionality
-//! like syntax highlighting, LSP integration, etc.
+//! like syntax highlighting, code folding, text objects, LSP integration, etc.
 
-pub mod syntax;
\ No newline at end of file
+pub mod syntax;
+pub mod folding;
+pub mod tree;
+pub mod textobjects;
+pub mod lsp;
\ No newline at end of file
@@ -34,6 +34,28 @@ impl SupportedLanguage {
         }
     }
 
+    /// Get the LSP language identifier
+    pub fn language_id(&self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::Python => "python",
+            Self::Markdown => "markdown",
+            Self::Json => "json",
+            Self::Toml => "toml",
+            Self::Yaml => "yaml",
+            Self::Fish => "fish",
+            Self::Text => "plaintext",
+        }
+    }
+
+    /// Every language, plain text last
+    pub const ALL: [Self; 8] = [Self::Rust, Self::Python, Self::Markdown, Self::Json, Self::Toml, Self::Yaml, Self::Fish, Self::Text];
+
+    /// Look up a language by its LSP language identifier
+    pub fn from_language_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|language| language.language_id() == id)
+    }
+
     /// Get tree-sitter language
     pub fn get_language(&self) -> Option<Language> {
         match self {
@@ -56,6 +78,24 @@ impl SupportedLanguage {
             _ => None,
         }
     }
+
+    /// Get fold queries for the language
+    pub fn get_fold_query(&self) -> Option<&'static str> {
+        match self {
+            Self::Rust => Some(include_str!("../../../syntax/queries/rust/folds.scm")),
+            Self::Python => Some(include_str!("../../../syntax/queries/python/folds.scm")),
+            _ => None,
+        }
+    }
+
+    /// Get text object queries for the language
+    pub fn get_textobjects_query(&self) -> Option<&'static str> {
+        match self {
+            Self::Rust => Some(include_str!("../../../syntax/queries/rust/textobjects.scm")),
+            Self::Python => Some(include_str!("../../../syntax/queries/python/textobjects.scm")),
+            _ => None,
+        }
+    }
 }
 
 /// Syntax highlight information for a text span
@@ -0,0 +1,369 @@
+/// Syntax-aware text objects and structural selection using tree-sitter
+use crate::core::buffer::Buffer;
+use crate::core::cursor::{Position, TextRange};
+use crate::{Result, RuskError};
+use super::syntax::SupportedLanguage;
+use super::tree::SyntaxTreeProvider;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use tree_sitter::{Node, Point, Query, QueryCursor, StreamingIterator};
+
+/// Kinds of syntax text objects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextObjectKind {
+    Function,
+    Class,
+    Parameter,
+    ListItem,
+}
+
+impl TextObjectKind {
+    /// Get the text object for the key following `a` or `i`
+    pub fn from_char(ch: char) -> Option<Self> {
+        match ch {
+            'f' => Some(Self::Function),
+            'c' => Some(Self::Class),
+            'a' => Some(Self::Parameter),
+            ',' => Some(Self::ListItem),
+            _ => None,
+        }
+    }
+
+    /// Capture name prefix used in `textobjects.scm`
+    fn capture_prefix(&self) -> &'static str {
+        match self {
+            Self::Function => "function",
+            Self::Class => "class",
+            Self::Parameter | Self::ListItem => "parameter",
+        }
+    }
+}
+
+/// Whether a text object includes its surroundings (`a`) or only its contents (`i`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextObjectScope {
+    Around,
+    Inside,
+}
+
+/// Ways a selection moves over the syntax tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxSelection {
+    /// Grow to the enclosing node
+    Expand,
+    /// Go back to the node selected before growing
+    Shrink,
+    NextSibling,
+    PreviousSibling,
+}
+
+/// A text object found in a buffer
+#[derive(Debug, Clone, Copy)]
+struct TextObjectMatch {
+    outer: Option<TextRange>,
+    inner: Option<TextRange>,
+}
+
+impl TextObjectMatch {
+    fn range(&self) -> Option<TextRange> {
+        self.outer.or(self.inner)
+    }
+}
+
+/// Resolves text objects and structural selections from per-buffer syntax trees
+pub struct TextObjectProvider {
+    trees: SyntaxTreeProvider,
+    queries: HashMap<SupportedLanguage, Query>,
+}
+
+impl TextObjectProvider {
+    pub fn new() -> Result<Self> {
+        let mut provider = Self {
+            trees: SyntaxTreeProvider::new()?,
+            queries: HashMap::new(),
+        };
+
+        for language in [SupportedLanguage::Rust, SupportedLanguage::Python] {
+            let (Some(ts_language), Some(query_source)) = (language.get_language(), language.get_textobjects_query()) else {
+                continue;
+            };
+
+            let query = Query::new(&ts_language, query_source)
+                .map_err(|e| RuskError::Syntax(format!("Failed to create {:?} text object query: {}", language, e)))?;
+            provider.queries.insert(language, query);
+        }
+
+        Ok(provider)
+    }
+
+    /// Select the innermost text object around the cursor
+    pub fn select(&mut self, buffer: &mut Buffer, kind: TextObjectKind, scope: TextObjectScope) -> Option<TextRange> {
+        if !self.trees.ensure_tree(buffer) {
+            return None;
+        }
+
+        let (line, col) = buffer.cursor_position();
+        let cursor = Position::new(line, col);
+
+        if kind == TextObjectKind::ListItem {
+            return Self::select_list_item(buffer, cursor, scope);
+        }
+
+        let found = self.find_objects(buffer, kind)
+            .into_iter()
+            .filter(|object| object.range().is_some_and(|range| range.contains(cursor)))
+            .max_by_key(|object| Self::nesting_key(&object.range().unwrap()))?;
+
+        match (kind, scope) {
+            (TextObjectKind::Parameter, TextObjectScope::Around) => {
+                let inner = found.inner?;
+                let tree = buffer.syntax_tree()?;
+                let node = tree.root_node().descendant_for_point_range(Self::to_point(inner.start), Self::to_point(inner.end))?;
+                Some(Self::with_separator(buffer, node))
+            }
+            (_, TextObjectScope::Around) => found.outer.or(found.inner),
+            (_, TextObjectScope::Inside) => found.inner.or(found.outer),
+        }
+    }
+
+    /// Get the smallest syntax node strictly enclosing a range (expand selection)
+    pub fn expand_selection(&mut self, buffer: &mut Buffer, range: TextRange) -> Option<TextRange> {
+        if !self.trees.ensure_tree(buffer) {
+            return None;
+        }
+
+        let tree = buffer.syntax_tree()?;
+        let mut node = tree.root_node().descendant_for_point_range(Self::to_point(range.start), Self::to_point(range.end))?;
+
+        loop {
+            let node_range = Self::node_range(&node);
+            if node_range.encloses(&range) && node_range != range {
+                return Some(node_range);
+            }
+            node = node.parent()?;
+        }
+    }
+
+    /// Get the named sibling of the node spanning a range
+    pub fn sibling_selection(&mut self, buffer: &mut Buffer, range: TextRange, forward: bool) -> Option<TextRange> {
+        if !self.trees.ensure_tree(buffer) {
+            return None;
+        }
+
+        let tree = buffer.syntax_tree()?;
+        let mut node = tree.root_node().named_descendant_for_point_range(Self::to_point(range.start), Self::to_point(range.end))?;
+
+        loop {
+            let mut sibling = if forward { node.next_sibling() } else { node.prev_sibling() };
+            while let Some(candidate) = sibling {
+                if candidate.is_named() {
+                    return Some(Self::node_range(&candidate));
+                }
+                sibling = if forward { candidate.next_sibling() } else { candidate.prev_sibling() };
+            }
+            node = node.parent()?;
+        }
+    }
+
+    /// Get the start of the next or previous text object of a kind (`]f`, `[f`)
+    pub fn adjacent_object_start(&mut self, buffer: &mut Buffer, kind: TextObjectKind, forward: bool) -> Option<Position> {
+        if !self.trees.ensure_tree(buffer) {
+            return None;
+        }
+
+        let (line, col) = buffer.cursor_position();
+        let cursor = (line, col);
+        let starts = self.find_objects(buffer, kind)
+            .into_iter()
+            .filter_map(|object| object.range())
+            .map(|range| range.start);
+
+        if forward {
+            starts.filter(|start| (start.line, start.column) > cursor).min_by_key(|start| (start.line, start.column))
+        } else {
+            starts.filter(|start| (start.line, start.column) < cursor).max_by_key(|start| (start.line, start.column))
+        }
+    }
+
+    /// Collect all text objects of a kind from the buffer's syntax tree
+    fn find_objects(&self, buffer: &Buffer, kind: TextObjectKind) -> Vec<TextObjectMatch> {
+        let (Some(query), Some(tree)) = (self.queries.get(buffer.language()), buffer.syntax_tree()) else {
+            return Vec::new();
+        };
+
+        let outer_name = format!("{}.outer", kind.capture_prefix());
+        let inner_name = format!("{}.inner", kind.capture_prefix());
+        let outer_index = query.capture_index_for_name(&outer_name);
+        let inner_index = query.capture_index_for_name(&inner_name);
+
+        let source = buffer.text();
+        let mut objects = Vec::new();
+        let mut cursor = QueryCursor::new();
+        let mut matches = cursor.matches(query, tree.root_node(), source.as_bytes());
+
+        while let Some(query_match) = matches.next() {
+            let mut object = TextObjectMatch { outer: None, inner: None };
+
+            for capture in query_match.captures {
+                if Some(capture.index) == outer_index {
+                    object.outer = Some(Self::node_range(&capture.node));
+                } else if Some(capture.index) == inner_index {
+                    object.inner = Some(Self::inner_range(buffer, &capture.node));
+                }
+            }
+
+            if object.range().is_some() {
+                objects.push(object);
+            }
+        }
+
+        objects
+    }
+
+    /// Select the comma-separated item around the cursor, in any bracketed list
+    fn select_list_item(buffer: &Buffer, cursor: Position, scope: TextObjectScope) -> Option<TextRange> {
+        let tree = buffer.syntax_tree()?;
+        let point = Self::to_point(cursor);
+        let mut node = tree.root_node().named_descendant_for_point_range(point, point)?;
+
+        loop {
+            let parent = node.parent()?;
+            let separated = (0..parent.child_count())
+                .filter_map(|i| parent.child(i))
+                .any(|child| child.kind() == ",");
+
+            if separated && node.is_named() {
+                return Some(match scope {
+                    TextObjectScope::Inside => Self::node_range(&node),
+                    TextObjectScope::Around => Self::with_separator(buffer, node),
+                });
+            }
+            node = parent;
+        }
+    }
+
+    /// Extend a list item's range over its trailing separator, or the leading one for the last item
+    fn with_separator(buffer: &Buffer, node: Node) -> TextRange {
+        let range = Self::node_range(&node);
+
+        if let Some(next) = node.next_sibling().filter(|next| next.kind() == ",") {
+            let mut end = Self::to_position(next.end_position());
+            if let Some(line) = buffer.get_line(end.line) {
+                end.column += line[end.column..].len() - line[end.column..].trim_start().len();
+            }
+            return TextRange::new(range.start, end);
+        }
+
+        match node.prev_sibling().filter(|prev| prev.kind() == ",") {
+            Some(prev) => TextRange::new(Self::to_position(prev.start_position()), range.end),
+            None => range,
+        }
+    }
+
+    /// Get a node's contents, excluding surrounding brackets and the lines they sit on
+    fn inner_range(buffer: &Buffer, node: &Node) -> TextRange {
+        let count = node.child_count();
+        let (Some(first), Some(last)) = (node.child(0), count.checked_sub(1).and_then(|i| node.child(i))) else {
+            return Self::node_range(node);
+        };
+
+        let bracketed = count >= 2
+            && !first.is_named()
+            && !last.is_named()
+            && matches!((first.kind(), last.kind()), ("{", "}") | ("(", ")") | ("[", "]"));
+        if !bracketed {
+            return Self::node_range(node);
+        }
+
+        let mut start = Self::to_position(first.end_position());
+        let mut end = Self::to_position(last.start_position());
+
+        // Keep the brackets' own lines intact when the contents span whole lines
+        if start.line < end.line {
+            let rest_blank = buffer.get_line(start.line).is_some_and(|line| line[start.column.min(line.len())..].trim().is_empty());
+            let before_blank = buffer.get_line(end.line).is_some_and(|line| line[..end.column.min(line.len())].trim().is_empty());
+            if rest_blank && before_blank {
+                start = Position::new(start.line + 1, 0);
+                end = Position::new(end.line, 0);
+            }
+        }
+
+        TextRange::new(start, end)
+    }
+
+    fn node_range(node: &Node) -> TextRange {
+        TextRange::new(Self::to_position(node.start_position()), Self::to_position(node.end_position()))
+    }
+
+    /// Ordering key under which the innermost of several nested ranges is the greatest
+    fn nesting_key(range: &TextRange) -> (usize, usize, Reverse<(usize, usize)>) {
+        (range.start.line, range.start.column, Reverse((range.end.line, range.end.column)))
+    }
+
+    fn to_point(pos: Position) -> Point {
+        Point::new(pos.line, pos.column)
+    }
+
+    fn to_position(point: Point) -> Position {
+        Position::new(point.row, point.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "struct S {\n    a: u8,\n}\n\nfn one(x: u8, y: u8) {\n    call(1, 2);\n}\n\nfn two() {}\n";
+
+    fn rust_buffer(cursor: (usize, usize)) -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.set_language(SupportedLanguage::Rust);
+        buffer.insert_text(Position::new(0, 0), SOURCE);
+        buffer.set_cursor_position(cursor.0, cursor.1);
+        buffer
+    }
+
+    fn range(start: (usize, usize), end: (usize, usize)) -> TextRange {
+        TextRange::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    #[test]
+    fn test_select_objects_around_the_cursor() {
+        let mut provider = TextObjectProvider::new().unwrap();
+        let mut buffer = rust_buffer((5, 4));
+
+        let function = provider.select(&mut buffer, TextObjectKind::Function, TextObjectScope::Around);
+        assert_eq!(function, Some(range((4, 0), (6, 1))));
+        // The body's lines without the braces' own lines
+        let body = provider.select(&mut buffer, TextObjectKind::Function, TextObjectScope::Inside);
+        assert_eq!(body, Some(range((5, 0), (6, 0))));
+
+        // List items take the separator after them, or before them when last
+        buffer.set_cursor_position(5, 9);
+        assert_eq!(provider.select(&mut buffer, TextObjectKind::ListItem, TextObjectScope::Around), Some(range((5, 9), (5, 12))));
+        buffer.set_cursor_position(4, 14);
+        assert_eq!(provider.select(&mut buffer, TextObjectKind::Parameter, TextObjectScope::Inside), Some(range((4, 14), (4, 19))));
+        assert_eq!(provider.select(&mut buffer, TextObjectKind::Parameter, TextObjectScope::Around), Some(range((4, 12), (4, 19))));
+
+        buffer.set_cursor_position(1, 4);
+        assert_eq!(provider.select(&mut buffer, TextObjectKind::Class, TextObjectScope::Around), Some(range((0, 0), (2, 1))));
+        assert_eq!(provider.select(&mut buffer, TextObjectKind::Function, TextObjectScope::Around), None);
+    }
+
+    #[test]
+    fn test_structural_selection_and_navigation() {
+        let mut provider = TextObjectProvider::new().unwrap();
+        let mut buffer = rust_buffer((4, 7));
+
+        // `x` grows to its parameter, then to the parameter list
+        let parameter = provider.expand_selection(&mut buffer, range((4, 7), (4, 8))).unwrap();
+        assert_eq!(parameter, range((4, 7), (4, 12)));
+        assert_eq!(provider.expand_selection(&mut buffer, parameter), Some(range((4, 6), (4, 20))));
+        assert_eq!(provider.sibling_selection(&mut buffer, parameter, true), Some(range((4, 14), (4, 19))));
+
+        assert_eq!(provider.adjacent_object_start(&mut buffer, TextObjectKind::Function, true), Some(Position::new(8, 0)));
+        assert_eq!(provider.adjacent_object_start(&mut buffer, TextObjectKind::Class, false), Some(Position::new(0, 0)));
+        buffer.set_cursor_position(8, 0);
+        assert_eq!(provider.adjacent_object_start(&mut buffer, TextObjectKind::Function, true), None);
+    }
+}
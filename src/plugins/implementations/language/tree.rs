@@ -0,0 +1,57 @@
+/// Per-buffer syntax trees using tree-sitter
+use crate::core::buffer::Buffer;
+use crate::{Result, RuskError};
+use super::syntax::SupportedLanguage;
+use std::collections::HashMap;
+use tree_sitter::Parser;
+
+/// Parses buffers and caches the resulting syntax tree on the buffer
+///
+/// Trees are keyed on the buffer's edit version, so several providers can
+/// share one parse of the same text.
+pub struct SyntaxTreeProvider {
+    parsers: HashMap<SupportedLanguage, Parser>,
+}
+
+impl SyntaxTreeProvider {
+    pub fn new() -> Result<Self> {
+        let mut provider = Self {
+            parsers: HashMap::new(),
+        };
+
+        for language in [SupportedLanguage::Rust, SupportedLanguage::Python] {
+            if let Some(ts_language) = language.get_language() {
+                let mut parser = Parser::new();
+                parser.set_language(&ts_language)
+                    .map_err(|e| RuskError::Syntax(format!("Failed to set {:?} language: {}", language, e)))?;
+                provider.parsers.insert(language, parser);
+            }
+        }
+
+        Ok(provider)
+    }
+
+    /// Check if a language can be parsed
+    pub fn supports(&self, language: &SupportedLanguage) -> bool {
+        self.parsers.contains_key(language)
+    }
+
+    /// Make sure the buffer has an up to date syntax tree, returning whether one is available
+    pub fn ensure_tree(&mut self, buffer: &mut Buffer) -> bool {
+        if buffer.syntax_tree().is_some() {
+            return true;
+        }
+
+        let Some(parser) = self.parsers.get_mut(buffer.language()) else {
+            return false;
+        };
+
+        match parser.parse(buffer.text(), None) {
+            Some(tree) => {
+                buffer.set_syntax_tree(tree);
+                true
+            }
+            None => false,
+        }
+    }
+}
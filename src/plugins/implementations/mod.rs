@@ -7,4 +7,6 @@ pub mod editing;
 pub mod ui;
 pub mod language;
 pub mod file;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod completion;
+pub mod snippets;
\ No newline at end of file
@@ -0,0 +1,212 @@
+/// Snippet definitions loaded from `~/.config/rusk/snippets/`
+use crate::plugins::implementations::language::syntax::SupportedLanguage;
+use crate::{Result, RuskError};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File stem of snippets offered in every language
+const GLOBAL_SCOPE: &str = "global";
+
+/// A named snippet with the prefixes that expand it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnippetDefinition {
+    pub name: String,
+    pub prefixes: Vec<String>,
+    pub body: String,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::One(value) => vec![value],
+            Self::Many(values) => values,
+        }
+    }
+}
+
+/// A snippet as written in a file; VS Code and the TOML files share this shape
+#[derive(Deserialize)]
+struct RawSnippet {
+    prefix: OneOrMany,
+    body: OneOrMany,
+    description: Option<String>,
+}
+
+/// Snippets per language, keyed by LSP language id
+///
+/// Each language has a file named after its id, as TOML or as VS Code
+/// JSON, and `global.toml`/`global.json` apply everywhere:
+///
+/// ```toml
+/// [function]
+/// prefix = "fn"
+/// body = ["fn ${1:name}(${2}) {", "\t$0", "}"]
+/// description = "Function definition"
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SnippetLibrary {
+    snippets: HashMap<String, Vec<SnippetDefinition>>,
+}
+
+impl SnippetLibrary {
+    /// Create an empty library
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the directory snippets are loaded from
+    pub fn default_dir() -> Result<PathBuf> {
+        let home_dir = dirs::home_dir()
+            .ok_or_else(|| RuskError::Config("Could not determine home directory".to_string()))?;
+        Ok(home_dir.join(".config").join("rusk").join("snippets"))
+    }
+
+    /// Load snippets from the default directory, which may not exist
+    pub fn load_default() -> Result<Self> {
+        let dir = Self::default_dir()?;
+        if dir.exists() { Self::load_dir(&dir) } else { Ok(Self::new()) }
+    }
+
+    /// Load every `<language>.toml` and `<language>.json` file of a directory
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let mut library = Self::new();
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let Some(scope) = path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string) else {
+                continue;
+            };
+            let content = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("toml" | "json") => fs::read_to_string(&path)
+                    .map_err(|e| RuskError::io_string(format!("Failed to read snippet file {}: {}", path.display(), e)))?,
+                _ => continue,
+            };
+            if scope != GLOBAL_SCOPE && SupportedLanguage::from_language_id(&scope).is_none() {
+                return Err(RuskError::Config(format!("Unknown language '{}' for snippet file {}", scope, path.display())));
+            }
+
+            let raw: BTreeMap<String, RawSnippet> = if path.extension().is_some_and(|ext| ext == "toml") {
+                toml::from_str(&content)
+                    .map_err(|e| RuskError::Config(format!("Failed to parse snippet file {}: {}", path.display(), e)))?
+            } else {
+                serde_json::from_str(&strip_trailing_commas(&strip_json_comments(&content)))
+                    .map_err(|e| RuskError::Config(format!("Failed to parse snippet file {}: {}", path.display(), e)))?
+            };
+
+            library.snippets.entry(scope).or_default().extend(raw.into_iter().map(|(name, raw)| SnippetDefinition {
+                name,
+                prefixes: raw.prefix.into_vec(),
+                body: raw.body.into_vec().join("\n"),
+                description: raw.description,
+            }));
+        }
+
+        Ok(library)
+    }
+
+    /// Add a snippet for a language, or for every language with `global`
+    pub fn add(&mut self, scope: &str, definition: SnippetDefinition) {
+        self.snippets.entry(scope.to_string()).or_default().push(definition);
+    }
+
+    /// Get the snippets available in a language, its own first
+    pub fn for_language(&self, language: &SupportedLanguage) -> impl Iterator<Item = &SnippetDefinition> {
+        let own = self.snippets.get(language.language_id()).into_iter().flatten();
+        own.chain(self.snippets.get(GLOBAL_SCOPE).into_iter().flatten())
+    }
+
+    /// Find the snippet whose prefix is exactly a typed word
+    pub fn find_by_prefix(&self, language: &SupportedLanguage, prefix: &str) -> Option<&SnippetDefinition> {
+        self.for_language(language).find(|definition| definition.prefixes.iter().any(|p| p == prefix))
+    }
+
+    /// Check whether the library has no snippets
+    pub fn is_empty(&self) -> bool {
+        self.snippets.values().all(Vec::is_empty)
+    }
+}
+
+/// Remove `//` and `/* */` comments from VS Code's JSON-with-comments files
+fn strip_json_comments(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(ch) = chars.next() {
+        if in_string {
+            result.push(ch);
+            match ch {
+                '\\' => result.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (ch, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                result.push(ch);
+            }
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|&next| next != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for inner in chars.by_ref() {
+                    if previous == '*' && inner == '/' {
+                        break;
+                    }
+                    previous = inner;
+                }
+            }
+            _ => result.push(ch),
+        }
+    }
+
+    result
+}
+
+/// Remove commas before a closing brace or bracket, which VS Code accepts and JSON does not
+fn strip_trailing_commas(content: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let mut result = String::with_capacity(content.len());
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, &ch) in chars.iter().enumerate() {
+        if in_string {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if ch == '"' {
+            in_string = true;
+        } else if ch == ',' && chars[i + 1..].iter().find(|next| !next.is_whitespace()).is_some_and(|next| matches!(next, '}' | ']')) {
+            continue;
+        }
+        result.push(ch);
+    }
+
+    result
+}
@@ -0,0 +1,232 @@
+/// Insert-mode snippet expansion and tabstop navigation
+use crate::core::buffer::Buffer;
+use crate::core::cursor::{Position, TextRange};
+use crate::plugins::implementations::completion::CompletionEngine;
+use crate::plugins::implementations::editing::mode::EditingMode;
+use crate::services::clipboard::Clipboard;
+use crate::services::command_system::EditorMode;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use super::library::{SnippetDefinition, SnippetLibrary};
+use super::parser::Snippet;
+use super::session::SnippetSession;
+use super::variables::SnippetVariables;
+
+/// Expands snippets and drives the active snippet session
+pub struct SnippetManager {
+    library: SnippetLibrary,
+    session: Option<SnippetSession>,
+    tab: String,
+}
+
+impl Default for SnippetManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SnippetManager {
+    pub fn new() -> Self {
+        Self {
+            library: SnippetLibrary::new(),
+            session: None,
+            tab: "    ".to_string(),
+        }
+    }
+
+    /// Replace the snippet library
+    pub fn set_library(&mut self, library: SnippetLibrary) {
+        self.library = library;
+    }
+
+    /// Get the snippet library
+    pub fn library(&self) -> &SnippetLibrary {
+        &self.library
+    }
+
+    /// Set the indentation that tabs in snippet bodies expand to
+    pub fn set_indentation(&mut self, tab_size: usize, insert_spaces: bool) {
+        self.tab = if insert_spaces { " ".repeat(tab_size) } else { "\t".to_string() };
+    }
+
+    /// Get the active snippet session
+    pub fn session(&self) -> Option<&SnippetSession> {
+        self.session.as_ref()
+    }
+
+    /// Check if a snippet is being filled in
+    pub fn is_active(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// End the active snippet session, leaving the text as it is
+    pub fn cancel(&mut self) {
+        self.session = None;
+    }
+
+    /// Replace each target range with a copy of a snippet body, returning whether a session started
+    ///
+    /// `$CLIPBOARD` is read from the clipboard the `"+` register uses.
+    pub fn expand(&mut self, buffer: &mut Buffer, body: &str, targets: &[TextRange], clipboard: &Clipboard) -> bool {
+        let snippet = Snippet::parse(body);
+        // Only ask the clipboard, which may run a paste command, when the snippet needs it
+        let clipboard = body.contains("CLIPBOARD").then(|| clipboard.paste().ok().flatten()).flatten();
+        let variables = SnippetVariables::from_buffer(buffer, buffer.get_selection().unwrap_or_default(), clipboard);
+
+        self.session = SnippetSession::start(buffer, &snippet, targets, &self.tab, &variables);
+        self.session.is_some()
+    }
+
+    /// Find the snippet whose prefix ends the text before the cursor, with the range of that prefix
+    pub fn prefix_at_cursor(&self, buffer: &Buffer) -> Option<(&SnippetDefinition, TextRange)> {
+        let (line, column) = buffer.cursor_position();
+        let text = buffer.get_line(line).unwrap_or("");
+        let before = &text[..column.min(text.len())];
+
+        // Try the whole whitespace-separated token, then the identifier at its end
+        let token_start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word_start = before.rfind(|ch: char| !(ch.is_alphanumeric() || ch == '_')).map_or(0, |i| i + 1);
+        [token_start, word_start].into_iter()
+            .filter(|&start| start < before.len())
+            .find_map(|start| {
+                let definition = self.library.find_by_prefix(buffer.language(), &before[start..])?;
+                Some((definition, TextRange::new(Position::new(line, start), Position::new(line, column))))
+            })
+    }
+
+    /// Expand the snippet whose prefix is before the cursor, returning whether there was one
+    ///
+    /// In an insert on a block the snippet goes on every row of it.
+    pub fn expand_prefix(&mut self, buffer: &mut Buffer, editing: &mut dyn EditingMode, clipboard: &Clipboard) -> bool {
+        let Some((definition, range)) = self.prefix_at_cursor(buffer) else {
+            return false;
+        };
+        let body = definition.body.clone();
+        let targets = Self::targets(range, buffer, editing);
+        self.expand(buffer, &body, &targets, clipboard);
+        true
+    }
+
+    /// The ranges a snippet replacing `range` expands at: the rows of a block insert typed from its start, or just `range`
+    fn targets(range: TextRange, buffer: &Buffer, editing: &mut dyn EditingMode) -> Vec<TextRange> {
+        let cursors = editing.block_insert_cursors(buffer);
+        if cursors.first() != Some(&range.start) {
+            return vec![range];
+        }
+        // The session fills in the other rows from now on, instead of the block insert copying keys
+        editing.end_block_insert();
+        std::iter::once(range).chain(cursors[1..].iter().map(|&pos| TextRange::new(pos, pos))).collect()
+    }
+
+    /// Jump to the next tabstop, ending the session at the final one; returns whether a session was active
+    pub fn next_stop(&mut self, buffer: &mut Buffer) -> bool {
+        let Some(session) = &mut self.session else {
+            return false;
+        };
+        if !session.next(buffer) {
+            self.session = None;
+        }
+        true
+    }
+
+    /// Jump to the previous tabstop, returning whether a session was active
+    pub fn previous_stop(&mut self, buffer: &mut Buffer) -> bool {
+        let Some(session) = &mut self.session else {
+            return false;
+        };
+        session.previous(buffer);
+        true
+    }
+
+    /// Handle an insert-mode key, returning whether it was consumed
+    ///
+    /// Tab expands the selected snippet completion or the snippet whose
+    /// prefix was typed, or jumps to the next tabstop; Shift-Tab jumps back.
+    /// Typing over an untouched placeholder replaces it. Modes with their
+    /// own key maps, like emacs, keep Tab.
+    pub fn handle_key(&mut self, key: KeyEvent, buffer: &mut Buffer, editing: &mut dyn EditingMode, completion: &mut CompletionEngine, clipboard: &Clipboard) -> bool {
+        if editing.editor_mode() != EditorMode::Insert
+            || !editing.uses_keybindings()
+            || key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+        {
+            return false;
+        }
+
+        match key.code {
+            KeyCode::Tab => {
+                let selected_snippet = completion.menu()
+                    .and_then(|menu| menu.selected_item())
+                    .is_some_and(|item| item.is_snippet);
+                if selected_snippet && let Some(item) = completion.take_selected() {
+                    let (line, column) = buffer.cursor_position();
+                    let target = TextRange::new(Position::new(line, item.start_column), Position::new(line, column));
+                    let targets = Self::targets(target, buffer, editing);
+                    self.expand(buffer, &item.insert_text, &targets, clipboard);
+                    self.show_choices(buffer, completion);
+                    return true;
+                }
+                if completion.is_active() {
+                    return false;
+                }
+
+                if !self.next_stop(buffer) && !self.expand_prefix(buffer, editing, clipboard) {
+                    return false;
+                }
+                self.show_choices(buffer, completion);
+                true
+            }
+            KeyCode::BackTab => {
+                if !self.previous_stop(buffer) {
+                    return false;
+                }
+                completion.cancel();
+                self.show_choices(buffer, completion);
+                true
+            }
+            KeyCode::Char(_) => {
+                if let Some(session) = &mut self.session {
+                    session.clear_placeholder(buffer);
+                }
+                false
+            }
+            KeyCode::Backspace | KeyCode::Delete => {
+                let Some(session) = &mut self.session else {
+                    return false;
+                };
+                if !session.is_pristine() {
+                    return false;
+                }
+                session.clear_placeholder(buffer);
+                completion.cancel();
+                self.after_key(buffer);
+                true
+            }
+            _ => {
+                if let Some(session) = &mut self.session {
+                    session.touch();
+                }
+                false
+            }
+        }
+    }
+
+    /// Follow up a key that the editing mode handled: update mirrors, or end the session if the cursor left the snippet
+    pub fn after_key(&mut self, buffer: &mut Buffer) {
+        let Some(session) = &mut self.session else {
+            return;
+        };
+        let (line, column) = buffer.cursor_position();
+        if !session.after_edit(buffer) || !session.covers(Position::new(line, column)) {
+            self.session = None;
+        }
+    }
+
+    /// Offer the options of a choice tabstop in the completion popup
+    fn show_choices(&self, buffer: &Buffer, completion: &mut CompletionEngine) {
+        if let Some(session) = &self.session {
+            let stop = session.current_stop();
+            if !stop.choices.is_empty() {
+                completion.show_choices(buffer, stop.ranges[0].start.column, &stop.choices);
+            }
+        }
+    }
+}
@@ -0,0 +1,21 @@
+//! Snippets
+//!
+//! Snippet bodies use the VS Code/LSP syntax: numbered tabstops (`$1`,
+//! `${1:placeholder}`, repeated numbers mirror each other), choices
+//! (`${1|one,two|}`), the final stop `$0` and variables such as
+//! `$TM_FILENAME`, `$CLIPBOARD` or `$CURRENT_YEAR`. Definitions are loaded
+//! per language from `~/.config/rusk/snippets/`. In insert mode Tab expands
+//! the snippet whose prefix was typed and then jumps between tabstops,
+//! Shift-Tab jumps back.
+
+pub mod parser;
+pub mod library;
+pub mod variables;
+pub mod session;
+pub mod manager;
+
+pub use parser::{ExpandOptions, ExpandedStop, Expansion, Snippet, SnippetNode};
+pub use library::{SnippetDefinition, SnippetLibrary};
+pub use variables::SnippetVariables;
+pub use session::{ActiveStop, SnippetSession};
+pub use manager::SnippetManager;
@@ -0,0 +1,382 @@
+/// Parser and expander for VS Code/LSP snippet syntax
+use std::collections::{BTreeSet, HashMap};
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A piece of a parsed snippet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnippetNode {
+    Text(String),
+    /// `$1`, `${1}` or `${1:placeholder}`; the placeholder may nest other nodes
+    Tabstop { index: usize, placeholder: Vec<SnippetNode> },
+    /// `${1|one,two,three|}`
+    Choice { index: usize, options: Vec<String> },
+    /// `$NAME`, `${NAME}` or `${NAME:default}`
+    Variable { name: String, default: Vec<SnippetNode> },
+}
+
+/// A parsed snippet body
+///
+/// Parsing is lenient like in other editors: a `$` that starts nothing
+/// valid is kept as text, so any string is a valid snippet.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Snippet {
+    nodes: Vec<SnippetNode>,
+}
+
+/// A tabstop of an expanded snippet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandedStop {
+    pub index: usize,
+    /// Byte ranges in the expanded text; more than one means mirrors
+    pub ranges: Vec<(usize, usize)>,
+    /// Options of a choice tabstop
+    pub choices: Vec<String>,
+}
+
+/// Text of an expanded snippet with its tabstops in visiting order, `$0` last
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub text: String,
+    pub stops: Vec<ExpandedStop>,
+}
+
+/// How an expansion is laid out in the buffer
+pub struct ExpandOptions<'a> {
+    /// Indentation of the line the snippet is inserted on, repeated after every newline
+    pub line_indent: &'a str,
+    /// Text that replaces tab characters of the body
+    pub tab: &'a str,
+    /// Value of a variable, `None` when it is unknown or unset
+    pub resolve: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl Snippet {
+    /// Parse a snippet body
+    pub fn parse(body: &str) -> Self {
+        let mut chars = body.chars().peekable();
+        Self { nodes: parse_nodes(&mut chars, false) }
+    }
+
+    /// Get the parsed nodes
+    pub fn nodes(&self) -> &[SnippetNode] {
+        &self.nodes
+    }
+
+    /// Check whether the snippet has tabstops besides the final one
+    pub fn has_tabstops(&self) -> bool {
+        fn any_stop(nodes: &[SnippetNode]) -> bool {
+            nodes.iter().any(|node| match node {
+                SnippetNode::Tabstop { index, placeholder } => *index > 0 || any_stop(placeholder),
+                SnippetNode::Choice { index, .. } => *index > 0,
+                SnippetNode::Variable { default, .. } => any_stop(default),
+                SnippetNode::Text(_) => false,
+            })
+        }
+        any_stop(&self.nodes)
+    }
+
+    /// Expand the snippet into text and tabstop ranges
+    ///
+    /// Every occurrence of a tabstop shows the same text: the first
+    /// placeholder given for its index. Without an explicit `$0` the final
+    /// stop is the end of the text.
+    pub fn expand(&self, options: &ExpandOptions) -> Expansion {
+        let mut defaults = HashMap::new();
+        collect_defaults(&self.nodes, &mut defaults);
+
+        let mut expander = Expander { options, defaults: &defaults, text: String::new(), stops: Vec::new() };
+        expander.render(&self.nodes, &mut BTreeSet::new());
+        let Expander { text, mut stops, .. } = expander;
+
+        if !stops.iter().any(|stop| stop.index == 0) {
+            stops.push(ExpandedStop { index: 0, ranges: vec![(text.len(), text.len())], choices: Vec::new() });
+        }
+        stops.sort_by_key(|stop| if stop.index == 0 { usize::MAX } else { stop.index });
+        Expansion { text, stops }
+    }
+
+    /// Expand with placeholders as text, no variables and no indentation
+    pub fn plain_text(&self) -> String {
+        let options = ExpandOptions { line_indent: "", tab: "\t", resolve: &|_| None };
+        self.expand(&options).text
+    }
+}
+
+/// Remember the first placeholder of each tabstop index, used by its mirrors
+fn collect_defaults<'a>(nodes: &'a [SnippetNode], defaults: &mut HashMap<usize, &'a SnippetNode>) {
+    for node in nodes {
+        match node {
+            SnippetNode::Tabstop { index, placeholder } => {
+                if !placeholder.is_empty() {
+                    defaults.entry(*index).or_insert(node);
+                }
+                collect_defaults(placeholder, defaults);
+            }
+            SnippetNode::Choice { index, .. } => {
+                defaults.entry(*index).or_insert(node);
+            }
+            SnippetNode::Variable { default, .. } => collect_defaults(default, defaults),
+            SnippetNode::Text(_) => {}
+        }
+    }
+}
+
+struct Expander<'a> {
+    options: &'a ExpandOptions<'a>,
+    defaults: &'a HashMap<usize, &'a SnippetNode>,
+    text: String,
+    stops: Vec<ExpandedStop>,
+}
+
+impl Expander<'_> {
+    /// Append text, indenting continuation lines and expanding tabs
+    fn push_text(&mut self, text: &str) {
+        for ch in text.chars() {
+            match ch {
+                '\n' => {
+                    self.text.push('\n');
+                    self.text.push_str(self.options.line_indent);
+                }
+                '\t' => self.text.push_str(self.options.tab),
+                _ => self.text.push(ch),
+            }
+        }
+    }
+
+    /// Render nodes; `open` holds the tabstops being rendered, which cannot contain themselves
+    fn render(&mut self, nodes: &[SnippetNode], open: &mut BTreeSet<usize>) {
+        for node in nodes {
+            match node {
+                SnippetNode::Text(text) => self.push_text(text),
+                SnippetNode::Tabstop { index, .. } | SnippetNode::Choice { index, .. } => {
+                    if open.contains(index) {
+                        continue;
+                    }
+                    let start = self.text.len();
+                    let mut choices = Vec::new();
+                    match self.defaults.get(index).copied().unwrap_or(node) {
+                        SnippetNode::Tabstop { placeholder, .. } => {
+                            open.insert(*index);
+                            self.render(placeholder, open);
+                            open.remove(index);
+                        }
+                        SnippetNode::Choice { options, .. } => {
+                            self.push_text(options.first().map_or("", String::as_str));
+                            choices = options.clone();
+                        }
+                        _ => {}
+                    }
+                    self.add_stop(*index, (start, self.text.len()), choices);
+                }
+                SnippetNode::Variable { name, default } => match (self.options.resolve)(name) {
+                    Some(value) => self.push_text(&value),
+                    None if default.is_empty() => self.push_text(name),
+                    None => self.render(default, open),
+                },
+            }
+        }
+    }
+
+    fn add_stop(&mut self, index: usize, range: (usize, usize), choices: Vec<String>) {
+        match self.stops.iter_mut().find(|stop| stop.index == index) {
+            Some(stop) => {
+                stop.ranges.push(range);
+                if stop.choices.is_empty() {
+                    stop.choices = choices;
+                }
+            }
+            None => self.stops.push(ExpandedStop { index, ranges: vec![range], choices }),
+        }
+    }
+}
+
+/// Parse nodes up to the end, or up to an unescaped `}` when `nested`
+fn parse_nodes(chars: &mut Peekable<Chars>, nested: bool) -> Vec<SnippetNode> {
+    let mut nodes = Vec::new();
+    let mut text = String::new();
+
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            '}' if nested => break,
+            '\\' => {
+                chars.next();
+                match chars.peek() {
+                    Some(&escaped @ ('$' | '}' | '\\')) => {
+                        chars.next();
+                        text.push(escaped);
+                    }
+                    _ => text.push('\\'),
+                }
+            }
+            '$' => {
+                chars.next();
+                match parse_dollar(chars) {
+                    Some(node) => {
+                        if !text.is_empty() {
+                            nodes.push(SnippetNode::Text(std::mem::take(&mut text)));
+                        }
+                        nodes.push(node);
+                    }
+                    None => text.push('$'),
+                }
+            }
+            _ => {
+                chars.next();
+                text.push(ch);
+            }
+        }
+    }
+
+    if !text.is_empty() {
+        nodes.push(SnippetNode::Text(text));
+    }
+    nodes
+}
+
+/// Parse what follows a `$`, leaving the input untouched if it is not a tabstop or variable
+fn parse_dollar(chars: &mut Peekable<Chars>) -> Option<SnippetNode> {
+    match chars.peek()? {
+        ch if ch.is_ascii_digit() => Some(SnippetNode::Tabstop { index: take_number(chars), placeholder: Vec::new() }),
+        ch if is_variable_start(*ch) => Some(SnippetNode::Variable { name: take_name(chars), default: Vec::new() }),
+        '{' => {
+            // Look ahead on a copy so a malformed `${` stays text
+            let mut ahead = chars.clone();
+            ahead.next();
+            let node = parse_braced(&mut ahead)?;
+            *chars = ahead;
+            Some(node)
+        }
+        _ => None,
+    }
+}
+
+/// Parse the inside of `${...}` including the closing brace
+fn parse_braced(chars: &mut Peekable<Chars>) -> Option<SnippetNode> {
+    let first = *chars.peek()?;
+
+    if first.is_ascii_digit() {
+        let index = take_number(chars);
+        return match chars.next()? {
+            '}' => Some(SnippetNode::Tabstop { index, placeholder: Vec::new() }),
+            ':' => {
+                let placeholder = parse_nodes(chars, true);
+                (chars.next()? == '}').then_some(SnippetNode::Tabstop { index, placeholder })
+            }
+            '|' => {
+                let options = parse_choices(chars)?;
+                (chars.next()? == '}').then_some(SnippetNode::Choice { index, options })
+            }
+            _ => None,
+        };
+    }
+
+    if is_variable_start(first) {
+        let name = take_name(chars);
+        return match chars.next()? {
+            '}' => Some(SnippetNode::Variable { name, default: Vec::new() }),
+            ':' => {
+                let default = parse_nodes(chars, true);
+                (chars.next()? == '}').then_some(SnippetNode::Variable { name, default })
+            }
+            // Transforms (`${NAME/regex/format/}`) are not supported: the plain value is used
+            '/' => {
+                let mut depth = 0;
+                for ch in chars.by_ref() {
+                    match ch {
+                        '{' => depth += 1,
+                        '}' if depth == 0 => return Some(SnippetNode::Variable { name, default: Vec::new() }),
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                }
+                None
+            }
+            _ => None,
+        };
+    }
+
+    None
+}
+
+/// Parse `one,two|` of a choice, consuming the closing `|`
+fn parse_choices(chars: &mut Peekable<Chars>) -> Option<Vec<String>> {
+    let mut options = Vec::new();
+    let mut current = String::new();
+
+    loop {
+        match chars.next()? {
+            '\\' => match chars.next()? {
+                escaped @ (',' | '|' | '$' | '}' | '\\') => current.push(escaped),
+                other => {
+                    current.push('\\');
+                    current.push(other);
+                }
+            },
+            ',' => options.push(std::mem::take(&mut current)),
+            '|' => {
+                options.push(current);
+                return Some(options);
+            }
+            ch => current.push(ch),
+        }
+    }
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> usize {
+    let mut digits = String::new();
+    while let Some(&ch) = chars.peek().filter(|ch| ch.is_ascii_digit()) {
+        digits.push(ch);
+        chars.next();
+    }
+    digits.parse().unwrap_or(usize::MAX)
+}
+
+fn is_variable_start(ch: char) -> bool {
+    ch.is_ascii_alphabetic() || ch == '_'
+}
+
+fn take_name(chars: &mut Peekable<Chars>) -> String {
+    let mut name = String::new();
+    while let Some(&ch) = chars.peek().filter(|ch| ch.is_ascii_alphanumeric() || **ch == '_') {
+        name.push(ch);
+        chars.next();
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(body: &str) -> Expansion {
+        let resolve = |name: &str| (name == "TM_FILENAME").then(|| "main.rs".to_string());
+        Snippet::parse(body).expand(&ExpandOptions { line_indent: "    ", tab: "    ", resolve: &resolve })
+    }
+
+    #[test]
+    fn test_tabstops_mirrors_and_choices() {
+        let expansion = expand("fn ${1:name}(${2|a,b|}) -> $1 {\n\t$0\n}");
+        assert_eq!(expansion.text, "fn name(a) -> name {\n        \n    }");
+
+        let indices: Vec<usize> = expansion.stops.iter().map(|stop| stop.index).collect();
+        assert_eq!(indices, vec![1, 2, 0]);
+        assert_eq!(expansion.stops[0].ranges, vec![(3, 7), (14, 18)]);
+        assert_eq!(expansion.stops[1].choices, vec!["a", "b"]);
+        assert_eq!(expansion.stops[2].ranges, vec![(29, 29)]);
+    }
+
+    #[test]
+    fn test_variables_escapes_and_leniency() {
+        assert_eq!(expand("// $TM_FILENAME ${UNKNOWN:none} $OTHER").text, "// main.rs none OTHER");
+        assert_eq!(expand(r"cost \$5 ${ and $").text, "cost $5 ${ and $");
+        assert_eq!(Snippet::parse("println!(\"${1:text}\")$0").plain_text(), "println!(\"text\")");
+
+        // Nested placeholders become stops of their own and the final stop defaults to the end
+        let expansion = expand("${1:a ${2:b}}");
+        assert_eq!(expansion.text, "a b");
+        assert_eq!(expansion.stops[1].ranges, vec![(2, 3)]);
+        assert_eq!(expansion.stops[2].ranges, vec![(3, 3)]);
+        assert!(!Snippet::parse("done$0").has_tabstops());
+    }
+}
@@ -0,0 +1,233 @@
+/// An expanded snippet whose tabstops are being visited
+use crate::core::buffer::Buffer;
+use crate::core::cursor::{Position, TextChange, TextRange};
+use super::parser::{ExpandOptions, Snippet};
+use super::variables::SnippetVariables;
+
+fn key(pos: Position) -> (usize, usize) {
+    (pos.line, pos.column)
+}
+
+/// Position reached after writing some text at a position
+fn advance(pos: Position, text: &str) -> Position {
+    match text.rfind('\n') {
+        Some(last) => Position::new(pos.line + text.matches('\n').count(), text.len() - last - 1),
+        None => Position::new(pos.line, pos.column + text.len()),
+    }
+}
+
+/// Map a stop range through an edit
+///
+/// The range being typed in grows with insertions at either edge; other
+/// ranges keep their text and let insertions at their edges stay outside.
+fn map_stop_range(change: &TextChange, range: &TextRange, growing: bool) -> TextRange {
+    if growing {
+        let start = if change.start == range.start { range.start } else { change.map_position(range.start) };
+        return TextRange::new(start, change.map_position(range.end));
+    }
+    if range.is_empty() || change.start != range.end {
+        return change.map_range(range);
+    }
+    TextRange::new(change.map_position(range.start), range.end)
+}
+
+/// A tabstop of an active snippet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveStop {
+    pub index: usize,
+    /// Where the stop's text is; the first range is the one being typed in, the others mirror it
+    pub ranges: Vec<TextRange>,
+    pub choices: Vec<String>,
+}
+
+/// A snippet being filled in
+///
+/// Expanding at several cursors inserts one copy per cursor; tabstops with
+/// the same number in all copies are visited together, so typing at the
+/// first cursor fills in the others like mirrors.
+#[derive(Debug, Clone)]
+pub struct SnippetSession {
+    stops: Vec<ActiveStop>,
+    current: usize,
+    lines: Vec<String>,
+    version: u64,
+    /// Whether the current placeholder is untouched, so typing replaces it
+    pristine: bool,
+}
+
+impl SnippetSession {
+    /// Replace each target range with a copy of the snippet and select its first tabstop
+    ///
+    /// The copies are written with a single edit spanning all targets, so
+    /// undo removes the whole expansion at once. Returns `None` when the
+    /// snippet has no tabstops to visit; the cursor is then at its end.
+    pub fn start(buffer: &mut Buffer, snippet: &Snippet, targets: &[TextRange], tab: &str, variables: &SnippetVariables) -> Option<Self> {
+        let mut targets = targets.to_vec();
+        targets.sort_by_key(|target| (key(target.start), key(target.end)));
+        // Overlapping targets would be replaced twice
+        targets.dedup_by(|later, earlier| key(later.start) < key(earlier.end));
+        let (Some(first), Some(last)) = (targets.first().copied(), targets.last().copied()) else {
+            return None;
+        };
+
+        let mut text = String::new();
+        let mut pos = first.start;
+        let mut stops: Vec<ActiveStop> = Vec::new();
+
+        for (n, target) in targets.iter().enumerate() {
+            if n > 0 {
+                let between = buffer.text_in_range(&TextRange::new(targets[n - 1].end, target.start));
+                pos = advance(pos, &between);
+                text.push_str(&between);
+            }
+
+            let line = buffer.get_line(target.start.line).unwrap_or("");
+            let line_indent = &line[..line.len() - line.trim_start().len()];
+            let resolve = |name: &str| variables.resolve(name);
+            let expansion = snippet.expand(&ExpandOptions { line_indent, tab, resolve: &resolve });
+
+            for stop in expansion.stops {
+                let ranges = stop.ranges.iter().map(|&(start, end)| {
+                    TextRange::new(advance(pos, &expansion.text[..start]), advance(pos, &expansion.text[..end]))
+                });
+                match stops.iter_mut().find(|active| active.index == stop.index) {
+                    Some(active) => active.ranges.extend(ranges),
+                    None => stops.push(ActiveStop { index: stop.index, ranges: ranges.collect(), choices: stop.choices }),
+                }
+            }
+
+            pos = advance(pos, &expansion.text);
+            text.push_str(&expansion.text);
+        }
+
+        buffer.clear_selection();
+        buffer.replace_range(&TextRange::new(first.start, last.end), &text);
+
+        let mut session = Self { stops, current: 0, lines: Vec::new(), version: 0, pristine: false };
+        session.snapshot(buffer);
+        session.select_current(buffer);
+        (session.stops.len() > 1).then_some(session)
+    }
+
+    /// Get the tabstops in visiting order
+    pub fn stops(&self) -> &[ActiveStop] {
+        &self.stops
+    }
+
+    /// Get the position of the current tabstop in visiting order
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Get the current tabstop
+    pub fn current_stop(&self) -> &ActiveStop {
+        &self.stops[self.current]
+    }
+
+    /// Check whether typing would replace the current placeholder
+    pub fn is_pristine(&self) -> bool {
+        self.pristine
+    }
+
+    /// Check whether a position lies within the lines of the snippet
+    pub fn covers(&self, pos: Position) -> bool {
+        let lines = self.stops.iter().flat_map(|stop| &stop.ranges).flat_map(|range| [range.start.line, range.end.line]);
+        let (first, last) = lines.fold((usize::MAX, 0), |(first, last), line| (first.min(line), last.max(line)));
+        first <= pos.line && pos.line <= last
+    }
+
+    /// Move to the next tabstop, returning `false` once the final stop is reached and the session is over
+    pub fn next(&mut self, buffer: &mut Buffer) -> bool {
+        self.current = (self.current + 1).min(self.stops.len() - 1);
+        self.select_current(buffer);
+        self.current + 1 < self.stops.len()
+    }
+
+    /// Move to the previous tabstop
+    pub fn previous(&mut self, buffer: &mut Buffer) {
+        self.current = self.current.saturating_sub(1);
+        self.select_current(buffer);
+    }
+
+    /// Delete the untouched placeholder of the current tabstop, before typing replaces it
+    pub fn clear_placeholder(&mut self, buffer: &mut Buffer) {
+        if self.pristine {
+            let range = self.current_stop().ranges[0];
+            buffer.clear_selection();
+            buffer.delete_range(&range);
+            self.pristine = false;
+        }
+    }
+
+    /// Forget that the current placeholder is untouched
+    pub fn touch(&mut self) {
+        self.pristine = false;
+    }
+
+    /// Follow an edit of the buffer, copying the current tabstop's text to its mirrors
+    ///
+    /// Returns `false` when the edit was outside the current tabstop, which ends the session.
+    pub fn after_edit(&mut self, buffer: &mut Buffer) -> bool {
+        if buffer.version() == self.version {
+            return true;
+        }
+        let change = TextChange::between(&self.lines, buffer.lines());
+        self.snapshot(buffer);
+        let Some(change) = change else {
+            return true;
+        };
+
+        let primary = self.current_stop().ranges[0];
+        let inside = key(change.start) >= key(primary.start) && key(change.old_end) <= key(primary.end);
+        self.map_ranges(&change, Some((self.current, 0)));
+        if !inside {
+            return false;
+        }
+        self.pristine = false;
+
+        // Copy the typed text to the mirrors, keeping the cursor where it was
+        let (line, column) = buffer.cursor_position();
+        let mut cursor = Position::new(line, column);
+        let text = buffer.text_in_range(&self.current_stop().ranges[0]);
+        for mirror in 1..self.current_stop().ranges.len() {
+            let range = self.stops[self.current].ranges[mirror];
+            if buffer.text_in_range(&range) == text {
+                continue;
+            }
+            let end = buffer.replace_range(&range, &text);
+            let change = TextChange::new(range.start, range.end, end);
+            self.map_ranges(&change, None);
+            self.stops[self.current].ranges[mirror] = TextRange::new(range.start, end);
+            cursor = change.map_position(cursor);
+        }
+        buffer.set_cursor_position(cursor.line, cursor.column);
+        self.snapshot(buffer);
+        true
+    }
+
+    /// Map every range through an edit, `growing` naming the one being typed in
+    fn map_ranges(&mut self, change: &TextChange, growing: Option<(usize, usize)>) {
+        for (s, stop) in self.stops.iter_mut().enumerate() {
+            for (r, range) in stop.ranges.iter_mut().enumerate() {
+                *range = map_stop_range(change, range, growing == Some((s, r)));
+            }
+        }
+    }
+
+    /// Put the cursor on the current tabstop, selecting its placeholder
+    fn select_current(&mut self, buffer: &mut Buffer) {
+        let range = self.current_stop().ranges[0];
+        if range.is_empty() {
+            buffer.clear_selection();
+            buffer.set_cursor_position(range.start.line, range.start.column);
+        } else {
+            buffer.select_range(&range);
+        }
+        self.pristine = !range.is_empty();
+    }
+
+    fn snapshot(&mut self, buffer: &Buffer) {
+        self.lines = buffer.lines().to_vec();
+        self.version = buffer.version();
+    }
+}
@@ -0,0 +1,100 @@
+/// Values of the snippet variables (`$TM_FILENAME`, `$CLIPBOARD`, `$CURRENT_YEAR`, ...)
+use crate::core::buffer::Buffer;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MONTH_NAMES: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+const DAY_NAMES: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+/// What snippet variables are resolved against
+#[derive(Debug, Clone, Default)]
+pub struct SnippetVariables {
+    pub file_path: Option<String>,
+    pub line_index: usize,
+    pub current_line: String,
+    pub current_word: String,
+    pub selected_text: String,
+    pub clipboard: Option<String>,
+    /// Seconds since the Unix epoch
+    pub now: u64,
+}
+
+impl SnippetVariables {
+    /// Capture the variables for an expansion at the cursor of a buffer
+    pub fn from_buffer(buffer: &Buffer, selected_text: String, clipboard: Option<String>) -> Self {
+        let (line, column) = buffer.cursor_position();
+        let current_line = buffer.get_line(line).unwrap_or("").to_string();
+        let before = &current_line[..column.min(current_line.len())];
+        let word_start = before.rfind(|ch: char| !(ch.is_alphanumeric() || ch == '_')).map_or(0, |i| i + 1);
+
+        Self {
+            file_path: buffer.file_path().map(str::to_string),
+            line_index: line,
+            current_word: before[word_start..].to_string(),
+            current_line,
+            selected_text,
+            clipboard,
+            now: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs()),
+        }
+    }
+
+    /// Get the value of a variable, `None` when it is unknown
+    ///
+    /// Dates and times are in UTC.
+    pub fn resolve(&self, name: &str) -> Option<String> {
+        let path = self.file_path.as_deref().map(Path::new);
+        let (year, month, day) = civil_date(self.now / 86_400);
+        let seconds_of_day = self.now % 86_400;
+
+        let value = match name {
+            "TM_FILENAME" => path?.file_name()?.to_string_lossy().to_string(),
+            "TM_FILENAME_BASE" => path?.file_stem()?.to_string_lossy().to_string(),
+            "TM_FILEPATH" => self.file_path.clone()?,
+            "TM_DIRECTORY" => path?.parent()?.to_string_lossy().to_string(),
+            "TM_LINE_INDEX" => self.line_index.to_string(),
+            "TM_LINE_NUMBER" => (self.line_index + 1).to_string(),
+            "TM_CURRENT_LINE" => self.current_line.clone(),
+            "TM_CURRENT_WORD" => self.current_word.clone(),
+            "TM_SELECTED_TEXT" => self.selected_text.clone(),
+            "CLIPBOARD" => self.clipboard.clone().unwrap_or_default(),
+            "CURRENT_YEAR" => year.to_string(),
+            "CURRENT_YEAR_SHORT" => format!("{:02}", year % 100),
+            "CURRENT_MONTH" => format!("{:02}", month),
+            "CURRENT_MONTH_NAME" => MONTH_NAMES[month as usize - 1].to_string(),
+            "CURRENT_MONTH_NAME_SHORT" => MONTH_NAMES[month as usize - 1][..3].to_string(),
+            "CURRENT_DATE" => format!("{:02}", day),
+            "CURRENT_DAY_NAME" => DAY_NAMES[weekday(self.now / 86_400)].to_string(),
+            "CURRENT_DAY_NAME_SHORT" => DAY_NAMES[weekday(self.now / 86_400)][..3].to_string(),
+            "CURRENT_HOUR" => format!("{:02}", seconds_of_day / 3600),
+            "CURRENT_MINUTE" => format!("{:02}", seconds_of_day / 60 % 60),
+            "CURRENT_SECOND" => format!("{:02}", seconds_of_day % 60),
+            "CURRENT_SECONDS_UNIX" => self.now.to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) date
+fn civil_date(days: u64) -> (i64, u32, u32) {
+    // Howard Hinnant's days-to-civil algorithm
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Day of the week, Monday being 0
+fn weekday(days: u64) -> usize {
+    // 1970-01-01 was a Thursday
+    ((days + 3) % 7) as usize
+}
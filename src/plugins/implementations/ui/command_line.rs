@@ -0,0 +1,457 @@
+//! Ex command line
+//!
+//! The line typed after `:`. Lines run are kept in a history saved between
+//! sessions, which Up and Down walk through showing only the lines that
+//! start with what was typed. Tab completes the command name, then the
+//! argument being typed from paths, settings or buffer names as the command
+//! declares; more Tabs cycle through the matches.
+
+use crate::services::command_system::{ArgCompletion, CommandSystem};
+use crate::services::ex;
+use crate::{Result, RuskError};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Most command lines remembered
+const HISTORY_LIMIT: usize = 200;
+
+/// Command lines run, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct CommandHistory {
+    entries: Vec<String>,
+    /// File the history is saved to
+    storage: Option<PathBuf>,
+}
+
+impl CommandHistory {
+    /// Create a history that is not saved
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get where the history is kept between sessions
+    pub fn default_storage() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("rusk").join("command_history"))
+    }
+
+    /// Load a history saved to a file, one line per entry, saving later lines there too
+    pub fn load(storage: impl Into<PathBuf>) -> Result<Self> {
+        let storage = storage.into();
+        let entries = match fs::read_to_string(&storage) {
+            Ok(content) => content.lines().filter(|line| !line.is_empty()).map(str::to_string).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(RuskError::io_string(format!("Failed to read command history '{}': {}", storage.display(), e))),
+        };
+        Ok(Self { entries, storage: Some(storage) })
+    }
+
+    /// Get the lines, oldest first
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Remember a line run, moving it to the newest end if it was run before
+    pub fn add(&mut self, line: &str) -> Result<()> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        self.entries.retain(|entry| entry != line);
+        self.entries.push(line.to_string());
+        if self.entries.len() > HISTORY_LIMIT {
+            self.entries.remove(0);
+        }
+        self.save()
+    }
+
+    /// Find the newest entry before `index` starting with `prefix`
+    pub fn older(&self, index: usize, prefix: &str) -> Option<usize> {
+        self.entries[..index.min(self.entries.len())].iter().rposition(|entry| entry.starts_with(prefix))
+    }
+
+    /// Find the oldest entry after `index` starting with `prefix`
+    pub fn newer(&self, index: usize, prefix: &str) -> Option<usize> {
+        let start = index + 1;
+        self.entries.get(start..)?.iter().position(|entry| entry.starts_with(prefix)).map(|i| start + i)
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        if let Some(dir) = storage.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| RuskError::io_string(format!("Failed to create {}: {}", dir.display(), e)))?;
+        }
+        let mut content = self.entries.join("\n");
+        content.push('\n');
+        fs::write(storage, content)
+            .map_err(|e| RuskError::io_string(format!("Failed to write command history '{}': {}", storage.display(), e)))
+    }
+}
+
+/// What Tab completes arguments from, besides the registered commands
+#[derive(Clone, Copy)]
+pub struct CompletionSources<'a> {
+    pub commands: &'a CommandSystem,
+    pub settings: &'a [String],
+    pub buffers: &'a [String],
+}
+
+/// Find what the word before the cursor could become, returning where the word starts and the candidates
+pub fn complete(line: &str, sources: &CompletionSources) -> (usize, Vec<String>) {
+    let Ok((_, rest)) = ex::parse_range(line) else {
+        return (line.len(), Vec::new());
+    };
+    let rest = rest.trim_start();
+    let offset = line.len() - rest.len();
+    if rest.starts_with('!') {
+        return (line.len(), Vec::new());
+    }
+
+    let (name, after) = ex::split_command(rest);
+    if after.is_empty() {
+        let mut names: Vec<String> = sources.commands.commands()
+            .flat_map(|spec| std::iter::once(&spec.name).chain(&spec.aliases))
+            .filter(|candidate| candidate.starts_with(name) && !candidate.ends_with('!'))
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+        return (offset, names);
+    }
+
+    let Some(spec) = sources.commands.get(name).filter(|spec| !spec.args.is_empty()) else {
+        return (line.len(), Vec::new());
+    };
+    let word = &after[after.rfind(char::is_whitespace).map_or(0, |i| i + 1)..];
+    let start = line.len() - word.len();
+    // Arguments before the word, the last one taking the rest of the line
+    let index = after[..after.len() - word.len()].split_whitespace().count().min(spec.args.len() - 1);
+    let candidates = match spec.args[index].completion {
+        ArgCompletion::None => Vec::new(),
+        ArgCompletion::Commands => sources.commands.commands()
+            .map(|spec| spec.name.clone())
+            .filter(|candidate| candidate.starts_with(word))
+            .collect(),
+        ArgCompletion::Paths if word.starts_with('!') => Vec::new(),
+        ArgCompletion::Paths => complete_path(word),
+        ArgCompletion::Settings => sources.settings.iter().filter(|setting| setting.starts_with(word)).cloned().collect(),
+        ArgCompletion::Buffers => sources.buffers.iter().filter(|name| name.contains(word)).cloned().collect(),
+    };
+    (start, candidates)
+}
+
+/// Complete a path from the entries of its directory, directories ending in `/`
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+    let listed = match dir.strip_prefix("~/").zip(dirs::home_dir()) {
+        Some((rest, home)) => home.join(rest),
+        None if dir.is_empty() => PathBuf::from("."),
+        None => PathBuf::from(dir),
+    };
+    let Ok(entries) = fs::read_dir(&listed) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<String> = entries.flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Hidden files only complete once a dot is typed
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if is_dir(&entry.path()) { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+fn is_dir(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_dir())
+}
+
+/// What a key did on the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandLineEvent {
+    /// The line was entered and should run
+    Run(String),
+    /// The line was closed without running
+    Cancelled,
+    /// The line is still being typed
+    Editing,
+}
+
+/// Matches Tab is cycling through
+#[derive(Debug, Clone)]
+struct Completion {
+    /// Byte offset the candidates replace from
+    start: usize,
+    candidates: Vec<String>,
+    /// Candidate shown, or none for the word as typed
+    selected: Option<usize>,
+    typed: String,
+}
+
+/// State of the `:` command line
+#[derive(Debug, Clone, Default)]
+pub struct CommandLine {
+    open: bool,
+    text: String,
+    /// Byte offset of the cursor in the text
+    cursor: usize,
+    history: CommandHistory,
+    /// History entry shown, and the text typed before walking the history
+    browsing: Option<(usize, String)>,
+    completion: Option<Completion>,
+}
+
+impl CommandLine {
+    /// Create a closed command line
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the history, as when loading the saved one
+    pub fn set_history(&mut self, history: CommandHistory) {
+        self.history = history;
+    }
+
+    /// Get the history
+    pub fn history(&self) -> &CommandHistory {
+        &self.history
+    }
+
+    /// Remember a line run in the history
+    pub fn remember(&mut self, line: &str) -> Result<()> {
+        self.history.add(line)
+    }
+
+    /// Check whether the command line is shown
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Show the command line with some text already typed, like `'<,'>` after a selection
+    pub fn open(&mut self, text: &str) {
+        self.open = true;
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+        self.browsing = None;
+        self.completion = None;
+    }
+
+    /// Get the typed text
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the text typed before the cursor
+    pub fn before_cursor(&self) -> &str {
+        &self.text[..self.cursor]
+    }
+
+    /// Get the matches Tab is cycling through and the one shown
+    pub fn candidates(&self) -> Option<(&[String], Option<usize>)> {
+        self.completion.as_ref()
+            .filter(|completion| completion.candidates.len() > 1)
+            .map(|completion| (completion.candidates.as_slice(), completion.selected))
+    }
+
+    /// Handle a key while the command line is open
+    pub fn handle_key(&mut self, key: KeyEvent, sources: &CompletionSources) -> CommandLineEvent {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        if !matches!(key.code, KeyCode::Tab | KeyCode::BackTab) {
+            self.completion = None;
+        }
+        if !matches!(key.code, KeyCode::Up | KeyCode::Down) {
+            self.browsing = None;
+        }
+
+        match key.code {
+            KeyCode::Esc => return self.close(),
+            KeyCode::Char('c') if control => return self.close(),
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut self.text);
+                self.close();
+                return CommandLineEvent::Run(line);
+            }
+            KeyCode::Backspace if self.text.is_empty() => return self.close(),
+            KeyCode::Backspace => {
+                if let Some(ch) = self.before_cursor().chars().next_back() {
+                    self.cursor -= ch.len_utf8();
+                    self.text.remove(self.cursor);
+                }
+            }
+            KeyCode::Delete if self.cursor < self.text.len() => {
+                self.text.remove(self.cursor);
+            }
+            KeyCode::Left => self.cursor -= self.before_cursor().chars().next_back().map_or(0, char::len_utf8),
+            KeyCode::Right => self.cursor += self.text[self.cursor..].chars().next().map_or(0, char::len_utf8),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::Char('b') if control => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            KeyCode::Char('e') if control => self.cursor = self.text.len(),
+            KeyCode::Char('u') if control => {
+                self.text.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            KeyCode::Char('w') if control => {
+                // Delete the word before the cursor and the blanks after it, or a single other character
+                let before = self.before_cursor().trim_end();
+                let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+                let start = match before.char_indices().next_back() {
+                    Some((i, ch)) if !is_word(ch) => i,
+                    _ => before.char_indices().rev().take_while(|(_, ch)| is_word(*ch)).last().map_or(before.len(), |(i, _)| i),
+                };
+                self.text.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            KeyCode::Up => self.walk_history(true),
+            KeyCode::Down => self.walk_history(false),
+            KeyCode::Tab => self.cycle_completion(sources, true),
+            KeyCode::BackTab => self.cycle_completion(sources, false),
+            KeyCode::Char(ch) if !control => {
+                self.text.insert(self.cursor, ch);
+                self.cursor += ch.len_utf8();
+            }
+            _ => {}
+        }
+        CommandLineEvent::Editing
+    }
+
+    fn close(&mut self) -> CommandLineEvent {
+        self.open = false;
+        self.text.clear();
+        self.cursor = 0;
+        CommandLineEvent::Cancelled
+    }
+
+    /// Show the previous or next history entry starting with what was typed before walking
+    fn walk_history(&mut self, older: bool) {
+        let (index, typed) = self.browsing.clone()
+            .unwrap_or_else(|| (self.history.entries().len(), self.text.clone()));
+        let found = if older { self.history.older(index, &typed) } else { self.history.newer(index, &typed) };
+        let (index, text) = match found {
+            Some(found) => (found, self.history.entries()[found].clone()),
+            // Past the newest entry is the text as typed
+            None if !older => (self.history.entries().len(), typed.clone()),
+            None => return,
+        };
+        self.text = text;
+        self.cursor = self.text.len();
+        self.browsing = Some((index, typed));
+    }
+
+    /// Complete the word before the cursor, or show the next or previous match
+    fn cycle_completion(&mut self, sources: &CompletionSources, forward: bool) {
+        let mut completion = match self.completion.take() {
+            Some(completion) => completion,
+            None => {
+                let (start, candidates) = complete(self.before_cursor(), sources);
+                if candidates.is_empty() {
+                    return;
+                }
+                let typed = self.text[start..self.cursor].to_string();
+                Completion { start, candidates, selected: None, typed }
+            }
+        };
+
+        // Cycling passes through the word as typed between the last match and the first
+        let count = completion.candidates.len();
+        completion.selected = match (completion.selected, forward) {
+            (None, true) => Some(0),
+            (None, false) => Some(count - 1),
+            (Some(i), true) => (i + 1 < count).then_some(i + 1),
+            (Some(i), false) => i.checked_sub(1),
+        };
+        let replacement = completion.selected.map_or(completion.typed.clone(), |i| completion.candidates[i].clone());
+        self.text.replace_range(completion.start..self.cursor, &replacement);
+        self.cursor = completion.start + replacement.len();
+        if count > 1 {
+            self.completion = Some(completion);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn type_text(line: &mut CommandLine, text: &str, sources: &CompletionSources) {
+        for ch in text.chars() {
+            line.handle_key(key(KeyCode::Char(ch)), sources);
+        }
+    }
+
+    #[test]
+    fn test_history_is_saved_and_filtered_by_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = dir.path().join("history");
+        let mut history = CommandHistory::load(&storage).unwrap();
+        for line in ["sort", "w", "set number", "sort u"] {
+            history.add(line).unwrap();
+        }
+        history.add("w").unwrap();
+        assert_eq!(CommandHistory::load(&storage).unwrap().entries(), ["sort", "set number", "sort u", "w"]);
+
+        let commands = CommandSystem::with_builtins();
+        let sources = CompletionSources { commands: &commands, settings: &[], buffers: &[] };
+        let mut line = CommandLine::new();
+        line.set_history(history);
+        line.open("");
+        type_text(&mut line, "so", &sources);
+        line.handle_key(key(KeyCode::Up), &sources);
+        assert_eq!(line.text(), "sort u");
+        line.handle_key(key(KeyCode::Up), &sources);
+        assert_eq!(line.text(), "sort");
+        line.handle_key(key(KeyCode::Up), &sources);
+        assert_eq!(line.text(), "sort");
+        line.handle_key(key(KeyCode::Down), &sources);
+        line.handle_key(key(KeyCode::Down), &sources);
+        assert_eq!(line.text(), "so");
+        assert_eq!(line.handle_key(key(KeyCode::Enter), &sources), CommandLineEvent::Run("so".to_string()));
+        assert!(!line.is_open());
+    }
+
+    #[test]
+    fn test_tab_completes_commands_paths_and_buffers() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("setup.rs"), "").unwrap();
+        fs::write(dir.path().join(".secret"), "").unwrap();
+        let root = format!("{}/", dir.path().display());
+
+        let commands = CommandSystem::with_builtins();
+        let buffers = ["src/main.rs".to_string(), "README.md".to_string()];
+        let sources = CompletionSources { commands: &commands, settings: &[], buffers: &buffers };
+
+        let (start, names) = complete("'<,'>so", &sources);
+        assert_eq!((start, names), (5, vec!["sort".to_string()]));
+        assert_eq!(complete(&format!("e {}s", root), &sources).1, [format!("{}setup.rs", root), format!("{}src/", root)]);
+        assert_eq!(complete(&format!("r {}.", root), &sources).1, [format!("{}.secret", root)]);
+        assert_eq!(complete("b main", &sources), (2, vec!["src/main.rs".to_string()]));
+
+        let mut line = CommandLine::new();
+        line.open("");
+        type_text(&mut line, &format!("e {}s", root), &sources);
+        line.handle_key(key(KeyCode::Tab), &sources);
+        assert_eq!(line.text(), format!("e {}setup.rs", root));
+        line.handle_key(key(KeyCode::Tab), &sources);
+        assert_eq!(line.text(), format!("e {}src/", root));
+        line.handle_key(key(KeyCode::Tab), &sources);
+        assert_eq!(line.text(), format!("e {}s", root));
+        line.handle_key(key(KeyCode::BackTab), &sources);
+        assert_eq!(line.text(), format!("e {}src/", root));
+    }
+}
@@ -3,4 +3,6 @@
 //! This module contains different UI implementations
 //! like TUI, GUI, etc.
 
-pub mod tui;
\ No newline at end of file
+pub mod tui;
+pub mod palette;
+pub mod command_line;
@@ -0,0 +1,303 @@
+//! Command palette
+//!
+//! Lists every registered command with its keybinding and description,
+//! ranked by fuzzy matching against the typed query. Recently run commands
+//! come first, and commands taking arguments prompt for them inline.
+
+use crate::core::buffer::Buffer;
+use crate::services::command_system::{ArgSpec, CommandArgs, CommandSystem, EditorMode};
+use crate::utils::fuzzy::fuzzy_match;
+use crate::utils::keybindings::Keybindings;
+use crate::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
+
+/// Number of recently run commands remembered
+const RECENT_LIMIT: usize = 20;
+
+/// A command listed in the palette
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteEntry {
+    pub name: String,
+    pub description: String,
+    /// Shortest keybinding of the command, if it has one
+    pub keys: Option<String>,
+    /// Whether the command can run in the current mode and buffer
+    pub available: bool,
+    /// Char indices of the name that matched the query
+    pub matched: Vec<usize>,
+}
+
+/// Arguments being typed for the chosen command
+#[derive(Debug, Clone)]
+struct ArgumentPrompt {
+    command: String,
+    args: Vec<ArgSpec>,
+    values: Vec<String>,
+    input: String,
+}
+
+/// State of the command palette overlay
+#[derive(Debug, Clone, Default)]
+pub struct CommandPalette {
+    open: bool,
+    query: String,
+    commands: Vec<PaletteEntry>,
+    entries: Vec<PaletteEntry>,
+    selected: usize,
+    recent: VecDeque<String>,
+    prompt: Option<ArgumentPrompt>,
+}
+
+impl CommandPalette {
+    /// Create a closed palette
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check whether a key opens the palette: Ctrl-Shift-p, from any mode, in terminals that report Shift with Ctrl
+    ///
+    /// The `command_palette` command opens it too, bound to `<leader>p`.
+    pub fn is_open_key(key: KeyEvent) -> bool {
+        key.modifiers.contains(KeyModifiers::CONTROL)
+            && (key.code == KeyCode::Char('P') || (key.code == KeyCode::Char('p') && key.modifiers.contains(KeyModifiers::SHIFT)))
+    }
+
+    /// Check whether the palette is shown
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Show the palette listing every command, marking those unavailable in the current mode
+    pub fn open(&mut self, commands: &CommandSystem, keybindings: &Keybindings, buffer: &Buffer, mode: EditorMode) {
+        self.commands = commands.commands()
+            .map(|spec| PaletteEntry {
+                name: spec.name.clone(),
+                description: spec.description.clone(),
+                keys: keybindings.keys_for(&spec.name),
+                available: spec.condition.check(buffer, mode).is_ok(),
+                matched: Vec::new(),
+            })
+            .collect();
+        self.open = true;
+        self.query.clear();
+        self.prompt = None;
+        self.rank();
+    }
+
+    /// Hide the palette
+    pub fn close(&mut self) {
+        self.open = false;
+        self.prompt = None;
+    }
+
+    /// Get the typed query
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Get the matching commands in ranked order
+    pub fn entries(&self) -> &[PaletteEntry] {
+        &self.entries
+    }
+
+    /// Get the index of the highlighted entry
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Get recently run commands, most recent first
+    pub fn recent(&self) -> impl Iterator<Item = &str> {
+        self.recent.iter().map(String::as_str)
+    }
+
+    /// Remember that a command ran
+    pub fn record(&mut self, name: &str) {
+        self.recent.retain(|recent| recent != name);
+        self.recent.push_front(name.to_string());
+        self.recent.truncate(RECENT_LIMIT);
+    }
+
+    /// Get the argument prompt, like `save_as <path>: `, and the text typed for it
+    pub fn argument_prompt(&self) -> Option<(String, &str)> {
+        let prompt = self.prompt.as_ref()?;
+        let spec = &prompt.args[prompt.values.len()];
+        let label = if spec.required { format!("<{}>", spec.name) } else { format!("[{}]", spec.name) };
+        Some((format!("{} {} ({}): ", prompt.command, label, spec.description), &prompt.input))
+    }
+
+    /// Handle a key while the palette is open, returning a command to run with its arguments
+    pub fn handle_key(&mut self, key: KeyEvent, commands: &CommandSystem) -> Result<Option<(String, CommandArgs)>> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => self.close(),
+            KeyCode::Char('c') if ctrl => self.close(),
+            KeyCode::Enter => return self.confirm(commands),
+            KeyCode::Up | KeyCode::BackTab => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Tab => self.move_selection(1),
+            KeyCode::Char('p' | 'k') if ctrl => self.move_selection(-1),
+            KeyCode::Char('n' | 'j') if ctrl => self.move_selection(1),
+            KeyCode::Char('u') if ctrl => {
+                self.input_mut().clear();
+                self.rank();
+            }
+            KeyCode::Backspace => {
+                self.input_mut().pop();
+                self.rank();
+            }
+            KeyCode::Char(ch) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                self.input_mut().push(ch);
+                self.rank();
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Run the highlighted command, or take the typed argument
+    fn confirm(&mut self, commands: &CommandSystem) -> Result<Option<(String, CommandArgs)>> {
+        if let Some(prompt) = &mut self.prompt {
+            let spec = &prompt.args[prompt.values.len()];
+            let input = std::mem::take(&mut prompt.input);
+            if input.is_empty() && spec.required {
+                return Ok(None);
+            }
+            // Arguments are positional, so leaving an optional one empty skips the rest
+            let done = input.is_empty() || prompt.values.len() + 1 == prompt.args.len();
+            if !input.is_empty() {
+                prompt.values.push(input);
+            }
+            if !done {
+                return Ok(None);
+            }
+
+            let name = prompt.command.clone();
+            let line = prompt.values.iter().map(|value| quote(value)).collect::<Vec<_>>().join(" ");
+            self.close();
+            let Some(spec) = commands.get(&name) else {
+                return Ok(None);
+            };
+            let args = spec.parse_args(&line)?;
+            self.record(&name);
+            return Ok(Some((name, args)));
+        }
+
+        let Some(entry) = self.entries.get(self.selected) else {
+            return Ok(None);
+        };
+        let Some(spec) = commands.get(&entry.name) else {
+            return Ok(None);
+        };
+        if spec.args.is_empty() {
+            let name = spec.name.clone();
+            self.close();
+            self.record(&name);
+            return Ok(Some((name, CommandArgs::new())));
+        }
+
+        self.prompt = Some(ArgumentPrompt {
+            command: spec.name.clone(),
+            args: spec.args.clone(),
+            values: Vec::new(),
+            input: String::new(),
+        });
+        Ok(None)
+    }
+
+    /// The text being typed: the query, or the current argument
+    fn input_mut(&mut self) -> &mut String {
+        match &mut self.prompt {
+            Some(prompt) => &mut prompt.input,
+            None => &mut self.query,
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.prompt.is_some() || self.entries.is_empty() {
+            return;
+        }
+        let len = self.entries.len() as isize;
+        self.selected = (self.selected as isize + delta).rem_euclid(len) as usize;
+    }
+
+    /// Filter and order the commands for the query
+    ///
+    /// Names match better than descriptions; ties go to recently run
+    /// commands, then available ones, then alphabetical order.
+    fn rank(&mut self) {
+        if self.prompt.is_some() {
+            return;
+        }
+        let recency = |name: &str| self.recent.iter().position(|recent| recent == name).unwrap_or(RECENT_LIMIT);
+
+        let mut ranked: Vec<(i64, usize, PaletteEntry)> = self.commands.iter()
+            .filter_map(|entry| {
+                let by_name = fuzzy_match(&self.query, &entry.name);
+                let by_description = fuzzy_match(&self.query, &entry.description).map(|found| found.score / 2);
+                let score = match (&by_name, by_description) {
+                    (Some(found), Some(description)) => found.score.max(description),
+                    (Some(found), None) => found.score,
+                    (None, Some(description)) => description,
+                    (None, None) => return None,
+                };
+                let matched = by_name.map(|found| found.indices).unwrap_or_default();
+                Some((score, recency(&entry.name), PaletteEntry { matched, ..entry.clone() }))
+            })
+            .collect();
+        ranked.sort_by(|(a_score, a_recent, a), (b_score, b_recent, b)| {
+            b_score.cmp(a_score)
+                .then(a_recent.cmp(b_recent))
+                .then(b.available.cmp(&a.available))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        self.entries = ranked.into_iter().map(|(_, _, entry)| entry).collect();
+        self.selected = 0;
+    }
+}
+
+/// Quote an argument so `CommandSpec::parse_args` reads it back as one token
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::KeyBindings;
+
+    fn type_text(palette: &mut CommandPalette, commands: &CommandSystem, text: &str) {
+        for ch in text.chars() {
+            palette.handle_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE), commands).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_palette_ranking_and_arguments() {
+        let commands = CommandSystem::with_builtins();
+        let keybindings = Keybindings::from_config(&KeyBindings::default()).unwrap();
+        let buffer = Buffer::new();
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        let mut palette = CommandPalette::new();
+
+        palette.open(&commands, &keybindings, &buffer, EditorMode::Normal);
+        assert_eq!(palette.entries().len(), commands.commands().count());
+        type_text(&mut palette, &commands, "svas");
+        assert_eq!(palette.entries()[0].name, "save_as");
+
+        // Arguments are asked for inline, and may contain spaces and quotes
+        assert_eq!(palette.handle_key(enter, &commands).unwrap(), None);
+        assert!(palette.argument_prompt().is_some_and(|(label, _)| label.starts_with("save_as <path>")));
+        type_text(&mut palette, &commands, "my \"file\".txt");
+        let (name, args) = palette.handle_key(enter, &commands).unwrap().unwrap();
+        assert_eq!(name, "save_as");
+        assert_eq!(args.path("path").map(|path| path.to_string_lossy().into_owned()), Some("my \"file\".txt".to_string()));
+        assert!(!palette.is_open());
+
+        // Recently run commands come first
+        palette.open(&commands, &keybindings, &buffer, EditorMode::Normal);
+        assert_eq!(palette.entries()[0].name, "save_as");
+        let quit = palette.entries().iter().find(|entry| entry.name == "save_and_quit").unwrap();
+        assert_eq!(quit.keys.as_deref(), Some(":wq"));
+    }
+}
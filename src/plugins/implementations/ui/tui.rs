@@ -3,25 +3,110 @@
 //! This module implements terminal user interface functionality using ratatui
 
 use crate::plugins::Plugin;
-use crate::core::buffer::Buffer;
-use crate::plugins::implementations::editing::vim::{VimPlugin, VimMode};
-use crate::plugins::implementations::language::syntax::SyntaxHighlighter;
+use crate::core::buffer::{Buffer, DiagnosticSeverity};
+use crate::core::state::{options, PropertySystem, PropertyTarget};
+use crate::core::cursor::movement::ScreenPlace;
+use crate::core::cursor::{Position, TextRange};
+use crate::plugins::implementations::editing::mode::{self, CursorShape, EditingMode, GrammarAction, GrammarContext};
+use crate::plugins::implementations::editing::structural::StructuralEditing;
+use crate::plugins::implementations::editing::lsp_commands::LspCommands;
+use crate::plugins::implementations::completion::{CompletionEngine, CompletionMenu, CompletionSettings, SnippetSource};
+use crate::plugins::implementations::snippets::{SnippetLibrary, SnippetManager};
+use crate::services::command_system::{ArgValue, CommandArgs, CommandOutcome, CommandSystem, EditorMode, EditorRequest};
+use crate::services::clipboard::Clipboard;
+use crate::services::event_bus::{EditorEvent, EventBus, EventKind, Verdict};
+use crate::services::macros::MacroRecorder;
+use crate::services::marks::{Location, Marks};
+use crate::services::operators::{MotionEnv, Operator};
+use crate::plugins::implementations::ui::palette::CommandPalette;
+use crate::plugins::implementations::ui::command_line::{CommandHistory, CommandLine, CommandLineEvent, CompletionSources};
+use crate::plugins::implementations::file::finder::{FileFinder, WalkOptions};
+use crate::plugins::implementations::file::io::FileIo;
+use crate::plugins::implementations::file::explorer::{ExplorerEvent, FileExplorer, GitStatus};
+use crate::utils::keybindings::{format_key_sequence, KeyAction, KeyMatch, KeyPress, Keybindings};
+use crate::plugins::implementations::language::syntax::{SupportedLanguage, SyntaxHighlighter};
+use crate::plugins::implementations::language::folding::FoldingProvider;
+use crate::plugins::implementations::language::lsp::LspManager;
 use crate::{Result, RuskError, Config};
 use crate::config::ConfigLoader;
+use crate::config::settings::EditorSettings;
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout, Rect},
-    style::Style,
+    style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
     Frame, Terminal,
 };
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event},
+    cursor::SetCursorStyle,
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use std::collections::VecDeque;
 use std::io::{self, Stdout};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Rows shown by the diagnostics list
+const DIAGNOSTICS_LIST_ROWS: usize = 8;
+/// Rows shown by the completion popup
+const COMPLETION_POPUP_ROWS: usize = 10;
+/// Width of the completion documentation preview
+const COMPLETION_DOC_WIDTH: u16 = 40;
+/// How long a pending key sequence waits before the which-key popup lists its continuations
+const WHICH_KEY_DELAY: Duration = Duration::from_millis(400);
+/// Rows shown by the which-key popup
+const WHICH_KEY_ROWS: usize = 10;
+/// Commands shown by the command palette
+const PALETTE_ROWS: usize = 12;
+/// Widest the command palette gets
+const PALETTE_WIDTH: u16 = 90;
+/// Width of the file explorer panel
+const EXPLORER_WIDTH: u16 = 30;
+
+/// Why the event loop returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventLoopExit {
+    /// The user quit
+    Quit,
+    /// A file should be opened, or its buffer shown, and the loop run again
+    Open(PathBuf),
+    /// The buffer should be closed and the loop run again on another one
+    Close,
+    /// A file or directory was renamed, so buffers of it need the new path
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+/// Settings deciding how the buffer text is drawn
+#[derive(Debug, Clone, Copy)]
+struct ViewOptions {
+    line_numbers: bool,
+    /// Wrap long lines rather than cutting them off at the window's edge
+    wrap: bool,
+    /// Show tabs as `→` and trailing spaces as `·`
+    whitespace: bool,
+    cursor_line: bool,
+}
+
+/// What a command asked of the options, which the event loop is given
+#[derive(Debug, Clone)]
+enum OptionRequest {
+    /// Arguments of `:set`, or of `:setlocal` when `local`
+    Set { args: String, local: bool },
+    /// Describe an option, or every option
+    Help(Option<String>),
+}
+
+/// Commands that go far enough to be remembered in the jump list
+const JUMP_COMMANDS: [&str; 7] = ["move_file_start", "move_file_end", "goto_line", "search_next", "search_previous", "search_word_forward", "search_word_backward"];
+/// Motions that fail, stopping a playing macro, when the cursor cannot move
+const FAILABLE_MOTIONS: [&str; 6] = ["move_left", "move_down", "move_up", "move_right", "move_word_forward", "move_word_backward"];
+/// Keys a macro plays before it is stopped, ending recursive macros that never fail
+const MACRO_KEY_LIMIT: usize = 1_000_000;
 
 /// TUI backend plugin with full terminal interface
 pub struct TuiPlugin {
@@ -30,9 +115,52 @@ pub struct TuiPlugin {
     terminal: Option<Terminal<CrosstermBackend<Stdout>>>,
     config: Option<Config>,
     syntax_highlighter: Option<SyntaxHighlighter>,
+    folding: Option<FoldingProvider>,
+    show_diagnostics_list: bool,
+    structural: StructuralEditing,
+    lsp: LspCommands,
+    completion: CompletionEngine,
+    snippets: SnippetManager,
+    commands: CommandSystem,
+    /// Where edits, cursor moves and mode changes are announced
+    events: EventBus,
+    /// Editing mode the keys are handed to, which has to start first
+    editing_mode: Option<String>,
+    keybindings: Keybindings,
+    palette: CommandPalette,
+    command_line: CommandLine,
+    /// Names of the editor options, completed after `:set`
+    setting_names: Vec<String>,
+    /// `:set` and `:help option` requests waiting for the options
+    option_requests: Vec<OptionRequest>,
+    /// Files of the open buffers, completed after `:b`
+    buffer_names: Vec<String>,
+    finder: FileFinder,
+    open_request: Option<PathBuf>,
+    close_request: bool,
+    explorer: FileExplorer,
+    rename_request: Option<(PathBuf, PathBuf)>,
+    command_message: Option<String>,
+    /// Title and lines of an info popup, closed by any key
+    info: Option<(String, Vec<String>)>,
+    macros: MacroRecorder,
+    /// Count typed before the key sequence being matched to a binding
+    count: Option<usize>,
+    /// Keys left to play from macros, played without redrawing
+    replay: VecDeque<KeyEvent>,
+    replaying: bool,
+    /// Whether the last key failed, which stops a playing macro
+    key_failed: bool,
+    /// Where the cursor goes in the next buffer shown, after jumping to another file
+    pending_cursor: Option<Position>,
+    cursor_shape: Option<CursorShape>,
     scroll_offset: usize,
     status_message: String,
-    show_line_numbers: bool,
+    view: ViewOptions,
+    /// Whether files are copied to a backup before being overwritten, shared with the `BeforeSave` subscriber
+    backup_files: Arc<AtomicBool>,
+    /// When the buffer was first seen modified since its last save, for `auto_save`
+    modified_since: Option<Instant>,
 }
 
 impl TuiPlugin {
@@ -43,16 +171,45 @@ impl TuiPlugin {
             terminal: None,
             config: None,
             syntax_highlighter: None,
+            folding: None,
+            show_diagnostics_list: false,
+            structural: StructuralEditing::new(),
+            lsp: LspCommands::new(),
+            completion: CompletionEngine::with_default_sources(CompletionSettings::default()),
+            snippets: SnippetManager::new(),
+            commands: CommandSystem::with_builtins(),
+            events: EventBus::new(),
+            editing_mode: None,
+            keybindings: Keybindings::new(),
+            palette: CommandPalette::new(),
+            command_line: CommandLine::new(),
+            setting_names: Vec::new(),
+            option_requests: Vec::new(),
+            buffer_names: Vec::new(),
+            finder: FileFinder::new(),
+            open_request: None,
+            close_request: false,
+            explorer: FileExplorer::new(),
+            rename_request: None,
+            command_message: None,
+            info: None,
+            macros: MacroRecorder::new(),
+            count: None,
+            replay: VecDeque::new(),
+            replaying: false,
+            key_failed: false,
+            pending_cursor: None,
+            cursor_shape: None,
             scroll_offset: 0,
             status_message: String::new(),
-            show_line_numbers: true,
+            view: ViewOptions { line_numbers: true, wrap: false, whitespace: false, cursor_line: true },
+            backup_files: Arc::new(AtomicBool::new(false)),
+            modified_since: None,
         }
     }
     
     /// Set configuration
     pub fn set_config(&mut self, config: Config) {
-        self.show_line_numbers = config.editor.line_numbers;
-        
         // Initialize syntax highlighter with theme colors
         if let Ok(mut highlighter) = SyntaxHighlighter::new() {
             if let Ok(color_scheme) = ConfigLoader::load_color_scheme(&config) {
@@ -61,9 +218,155 @@ impl TuiPlugin {
             self.syntax_highlighter = Some(highlighter);
         }
         
+        if let Ok(folding) = FoldingProvider::new() {
+            self.folding = Some(folding);
+        }
+        self.apply_editor_settings(&config.editor);
+        
+        match CompletionSettings::from_config(&config) {
+            Ok(settings) => self.completion.set_settings(settings),
+            Err(e) => self.status_message = e.to_string(),
+        }
+        
+        if let Some(storage) = Marks::default_storage() {
+            match Marks::load(storage) {
+                Ok(marks) => *self.commands.marks_mut() = marks,
+                Err(e) => self.status_message = e.to_string(),
+            }
+        }
+        
+        if let Some(storage) = CommandHistory::default_storage() {
+            match CommandHistory::load(storage) {
+                Ok(history) => self.command_line.set_history(history),
+                Err(e) => self.status_message = e.to_string(),
+            }
+        }
+        
+        match Clipboard::from_config(&config) {
+            Ok(clipboard) => self.commands.registers_mut().set_clipboard(clipboard),
+            Err(e) => self.status_message = e.to_string(),
+        }
+        
+        if let Err(e) = self.commands.validate_keybindings(&config.keybindings)
+            .and_then(|_| self.keybindings.load(&config.keybindings))
+        {
+            self.status_message = e.to_string();
+        }
+        
+        match SnippetLibrary::load_default() {
+            Ok(library) => {
+                self.completion.add_source(Box::new(SnippetSource::new(library.clone())));
+                self.snippets.set_library(library);
+            }
+            Err(e) => self.status_message = e.to_string(),
+        }
         self.config = Some(config);
     }
     
+    /// Apply the editor settings in effect for the buffer shown
+    fn apply_editor_settings(&mut self, settings: &EditorSettings) {
+        self.view = ViewOptions {
+            line_numbers: settings.line_numbers,
+            wrap: settings.wrap_lines,
+            whitespace: settings.show_whitespace,
+            cursor_line: settings.highlight_current_line,
+        };
+        self.backup_files.store(settings.backup_files, Ordering::Relaxed);
+        if let Some(folding) = &mut self.folding {
+            folding.set_tab_size(settings.tab_size);
+        }
+        self.lsp.set_indentation(settings.tab_size, settings.insert_spaces);
+        self.commands.set_tab_size(settings.tab_size);
+        self.snippets.set_indentation(settings.tab_size, settings.insert_spaces);
+        self.finder.set_options(WalkOptions { show_hidden: settings.show_hidden_files, respect_gitignore: true });
+        self.explorer.set_options(WalkOptions { show_hidden: settings.show_hidden_files, respect_gitignore: true });
+    }
+    
+    /// Run waiting `:set` and `:help option` requests, then react to the options that changed
+    ///
+    /// A changed file type highlights the buffer as that language, and the
+    /// settings the buffer sees go to the interface and the editing mode.
+    fn apply_options(&mut self, buffer: &mut Buffer, properties: &mut PropertySystem, editing: &mut dyn EditingMode) {
+        for request in std::mem::take(&mut self.option_requests) {
+            match request {
+                OptionRequest::Set { args, local } => match options::run_set(properties, &args, local, buffer.id()) {
+                    Ok(shown) if shown.len() > 1 => {
+                        let title = if local { ":setlocal" } else { ":set" };
+                        self.info = Some((format!("{} {}", title, args).trim_end().to_string(), shown));
+                    }
+                    Ok(shown) => self.command_message = shown.into_iter().next(),
+                    Err(e) => self.command_message = Some(e.to_string()),
+                },
+                OptionRequest::Help(None) => self.info = Some((":help option".to_string(), options::help_lines(properties))),
+                OptionRequest::Help(Some(name)) => match options::help_option(properties, &name) {
+                    Some(lines) => self.info = Some((format!(":help {}", name), lines)),
+                    None => self.command_message = Some(format!("No help for {}", name)),
+                },
+            }
+        }
+        
+        for change in properties.take_changes() {
+            if change.key == options::FILETYPE
+                && change.target == PropertyTarget::Buffer(buffer.id())
+                && let Some(id) = change.new.as_ref().and_then(|value| value.as_str())
+            {
+                match SupportedLanguage::from_language_id(id) {
+                    Some(language) => buffer.set_language(language),
+                    None => self.command_message = Some(format!("Unknown filetype: {}", id)),
+                }
+            }
+        }
+        
+        let Some(config) = &self.config else {
+            return;
+        };
+        let settings = options::editor_settings(properties, &config.editor, buffer.id());
+        if settings != config.editor {
+            let mut config = config.clone();
+            config.editor = settings;
+            self.apply_editor_settings(&config.editor);
+            editing.set_config(&config);
+            self.config = Some(config);
+        }
+    }
+    
+    /// Announce edits, cursor moves, mode changes and saves on the editor's event bus
+    pub fn set_events(&mut self, events: EventBus) {
+        self.commands.set_events(events.clone());
+        
+        // Keep the old contents of a file before it is overwritten, when `backup_files` is on
+        let backup_files = self.backup_files.clone();
+        events.subscribe("backup", &[EventKind::BeforeSave], 0, move |event| {
+            let EditorEvent::BeforeSave { path, .. } = event else {
+                return Verdict::Continue;
+            };
+            if !backup_files.load(Ordering::Relaxed) {
+                return Verdict::Continue;
+            }
+            match FileIo::write_backup(path) {
+                Ok(_) => Verdict::Continue,
+                Err(e) => Verdict::Veto(format!("Cannot write backup file: {}", e)),
+            }
+        });
+        self.events = events;
+    }
+    
+    /// Name the editing mode the keys are handed to, so it starts before the user interface
+    pub fn set_editing_mode(&mut self, name: &str) {
+        self.editing_mode = Some(name.to_string());
+    }
+    
+    /// Attach a language server manager for LSP commands
+    pub fn set_lsp(&mut self, manager: LspManager) {
+        self.lsp.set_manager(manager);
+    }
+    
+    /// Let completion sources index every open buffer
+    pub fn index_open_buffers(&mut self, buffers: &[Buffer]) {
+        self.completion.index_buffers(buffers);
+        self.buffer_names = buffers.iter().filter_map(|buffer| buffer.file_path().map(str::to_string)).collect();
+    }
+    
     /// Setup terminal for TUI mode
     pub fn setup_terminal(&mut self) -> Result<()> {
         enable_raw_mode()
@@ -90,7 +393,8 @@ impl TuiPlugin {
             execute!(
                 terminal.backend_mut(),
                 LeaveAlternateScreen,
-                DisableMouseCapture
+                DisableMouseCapture,
+                SetCursorStyle::DefaultUserShape
             )
             .map_err(|e| RuskError::Ui(format!("Failed to restore terminal: {}", e)))?;
             
@@ -101,17 +405,100 @@ impl TuiPlugin {
     }
     
     /// Main event loop for the TUI
-    pub fn run_event_loop(&mut self, buffer: &mut Buffer, vim: &mut VimPlugin) -> Result<()> {
+    ///
+    /// Returns when the user quits or asks for another file, which the
+    /// editor opens before running the loop again with its buffer.
+    /// `:set` changes the options in `properties`, which hold the buffer's
+    /// local options.
+    pub fn run_event_loop(&mut self, buffer: &mut Buffer, properties: &mut PropertySystem, editing: &mut dyn EditingMode) -> Result<EventLoopExit> {
         if self.terminal.is_none() {
             return Err(RuskError::Ui("Terminal not initialized".to_string()));
         }
         
+        // The buffer's file type starts as the language of its file
+        let _ = properties.init_local(options::FILETYPE, buffer.id(), buffer.language().language_id().into());
+        self.setting_names = options::option_names(properties);
+        self.apply_options(buffer, properties, editing);
+        
+        if let Some(position) = self.pending_cursor.take() {
+            buffer.set_cursor_position(position.line, position.column);
+            buffer.reveal_cursor_line();
+        }
+        self.refresh_folds(buffer);
+        self.scroll_offset = 0;
+        self.modified_since = None;
+        self.update_scroll(buffer);
+        let mut seen = (Self::cursor(buffer), editing.editor_mode());
+        self.publish_changes(buffer, editing, &mut seen);
+        
         loop {
+            if let Some(path) = self.open_request.take() {
+                // Editing state belongs to this buffer
+                self.snippets.cancel();
+                self.completion.cancel();
+                // Switching files is a jump, unless it came from the jump list or a mark, which remembered it already
+                if self.pending_cursor.is_none() {
+                    self.commands.marks_mut().push_jump(Location::cursor(buffer));
+                }
+                return Ok(EventLoopExit::Open(path));
+            }
+            if let Some((from, to)) = self.rename_request.take() {
+                return Ok(EventLoopExit::Renamed { from, to });
+            }
+            if std::mem::take(&mut self.close_request) {
+                self.snippets.cancel();
+                self.completion.cancel();
+                return Ok(EventLoopExit::Close);
+            }
+            if !self.option_requests.is_empty() {
+                self.apply_options(buffer, properties, editing);
+            }
+            
+            if self.finder.is_open() {
+                self.finder.poll();
+            }
+            
+            if self.lsp.sync(buffer) {
+                self.update_status_message(buffer, editing);
+            }
+            let cursor = buffer.cursor_position();
+            if self.lsp.poll(buffer) {
+                self.record_jump(cursor, buffer);
+                self.publish_changes(buffer, editing, &mut seen);
+                self.update_scroll(buffer);
+                self.update_status_message(buffer, editing);
+                if let Some(message) = self.lsp.take_message() {
+                    self.status_message = message;
+                }
+            }
+            // Language server candidates arrive after the key that asked for them
+            self.completion.poll(buffer);
+            self.auto_save(buffer);
+            
+            let list_height = self.diagnostics_list_height(buffer);
+            let block = editing.block_ranges(buffer, self.motion_env(buffer).tab_size);
+            
             // Draw the interface
             if let Some(terminal) = &mut self.terminal {
+                let cursor_shape = editing.cursor_shape();
+                if self.cursor_shape != Some(cursor_shape) {
+                    let style = match cursor_shape {
+                        CursorShape::Block => SetCursorStyle::SteadyBlock,
+                        CursorShape::Bar => SetCursorStyle::SteadyBar,
+                        CursorShape::Underline => SetCursorStyle::SteadyUnderScore,
+                    };
+                    execute!(terminal.backend_mut(), style)
+                        .map_err(|e| RuskError::Ui(format!("Failed to set cursor style: {}", e)))?;
+                    self.cursor_shape = Some(cursor_shape);
+                }
+                
                 let scroll_offset = self.scroll_offset;
-                let show_line_numbers = self.show_line_numbers;
+                let view = self.view;
                 let status_message = self.status_message.clone();
+                let prompt = self.lsp.prompt_text()
+                    .or_else(|| self.command_line.is_open().then(|| format!(":{}", self.command_line.text())));
+                let mode_override = self.command_line.is_open().then_some(("COMMAND", EditorMode::Command));
+                let which_key = Self::which_key_entries(&self.keybindings, &self.commands);
                 
                 terminal.draw(|f| {
                     let size = f.area();
@@ -120,9 +507,10 @@ impl TuiPlugin {
                     let chunks = Layout::default()
                         .direction(Direction::Vertical)
                         .constraints([
-                            Constraint::Min(1),      // Editor area
-                            Constraint::Length(1),   // Status line
-                            Constraint::Length(1),   // Command line
+                            Constraint::Min(1),                // Editor area
+                            Constraint::Length(list_height),   // Diagnostics list
+                            Constraint::Length(1),             // Status line
+                            Constraint::Length(1),             // Command line
                         ])
                         .split(size);
                     
@@ -135,71 +523,224 @@ impl TuiPlugin {
                         crate::config::settings::ColorScheme::default()
                     };
                     
+                    // Draw the file explorer to the left of the editor
+                    let editor_area = if self.explorer.is_open() {
+                        let columns = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .constraints([Constraint::Length(EXPLORER_WIDTH.min(chunks[0].width / 2)), Constraint::Min(1)])
+                            .split(chunks[0]);
+                        Self::draw_explorer_static(f, columns[0], &self.explorer, buffer, &color_scheme);
+                        columns[1]
+                    } else {
+                        chunks[0]
+                    };
+                    
                     // Draw editor area
-                    Self::draw_editor_static(f, chunks[0], buffer, vim, scroll_offset, show_line_numbers, &color_scheme, &mut self.syntax_highlighter);
+                    let cursor = Self::draw_editor_static(f, editor_area, buffer, editing, scroll_offset, view, &block, &color_scheme, &mut self.syntax_highlighter);
                     
-                    // Draw status line
-                    Self::draw_status_line_static(f, chunks[1], buffer, vim, &status_message, &color_scheme);
+                    // Draw diagnostics list
+                    if list_height > 0 {
+                        Self::draw_diagnostics_list_static(f, chunks[1], buffer, &color_scheme);
+                    }
+                    
+                    // Draw status line, or the command line's completions over it
+                    match self.command_line.candidates() {
+                        Some((candidates, selected)) => Self::draw_wildmenu_static(f, chunks[2], candidates, selected, &color_scheme),
+                        None => Self::draw_status_line_static(f, chunks[2], buffer, editing, mode_override, &status_message, &color_scheme),
+                    }
                     
                     // Draw command line
-                    Self::draw_command_line_static(f, chunks[2], vim, &color_scheme);
+                    Self::draw_command_line_static(f, chunks[3], editing, prompt.as_deref(), &color_scheme);
+                    
+                    // Draw the completion popup over the editor area
+                    if let Some(menu) = self.completion.menu() {
+                        let gutter = Self::gutter_width(buffer, view.line_numbers);
+                        let text_width = Self::area_text_width(editor_area.width, buffer, view);
+                        let (row, column) = Self::screen_position(buffer, scroll_offset, Position::new(menu.line(), menu.anchor_column()), text_width, view);
+                        let anchor = (editor_area.x + 1 + gutter + column as u16, editor_area.y + 1 + row as u16);
+                        Self::draw_completion_popup_static(f, editor_area, anchor, menu, &color_scheme);
+                    }
+                    
+                    // Draw the continuations of a pending key sequence
+                    if let Some((title, entries)) = &which_key {
+                        Self::draw_which_key_static(f, editor_area, title, entries, &color_scheme);
+                    }
+                    
+                    // Draw command output such as :registers
+                    if let Some((title, lines)) = &self.info {
+                        Self::draw_info_static(f, editor_area, title, lines, &color_scheme);
+                    }
+                    
+                    // Draw the file finder and command palette over everything else
+                    if self.finder.is_open() {
+                        Self::draw_finder_static(f, size, &mut self.finder, &mut self.syntax_highlighter, &color_scheme);
+                    }
+                    if self.palette.is_open() {
+                        Self::draw_palette_static(f, size, &self.palette, &color_scheme);
+                    }
+                    
+                    // Show the terminal cursor on the command line while typing there
+                    let cursor = if self.command_line.is_open() {
+                        let column = Span::raw(format!(":{}", self.command_line.before_cursor())).width() as u16;
+                        Some((chunks[3].x + column.min(chunks[3].width.saturating_sub(1)), chunks[3].y))
+                    } else {
+                        cursor
+                    };
+                    
+                    // Show the terminal cursor when the mode wants a shape other than the block drawn in the text
+                    if let Some(cursor) = cursor
+                        && !self.finder.is_open() && !self.palette.is_open() && !self.explorer.is_focused()
+                    {
+                        f.set_cursor_position(cursor);
+                    }
                 })
                     .map_err(|e| RuskError::Ui(format!("Failed to draw: {}", e)))?;
             }
             
             // Handle events
-            if event::poll(std::time::Duration::from_millis(100))
+            if !event::poll(std::time::Duration::from_millis(100))
                 .map_err(|e| RuskError::Ui(format!("Failed to poll events: {}", e)))?
             {
-                if let Event::Key(key) = event::read()
-                    .map_err(|e| RuskError::Ui(format!("Failed to read event: {}", e)))?
-                {
-                    // Handle vim key input
-                    let should_quit = vim.handle_key(key, buffer)?;
-                    if should_quit {
+                // A binding that is also a prefix runs once the timeout passes; plain prefixes wait for a key, with the which-key popup listing the choices
+                let actions = match self.keybindings.pending_match() {
+                    KeyMatch::Prefix => Vec::new(),
+                    _ => self.keybindings.check_timeout(Instant::now()),
+                };
+                if !actions.is_empty() {
+                    let quit = self.run_key_actions(actions, buffer, editing)?;
+                    self.publish_changes(buffer, editing, &mut seen);
+                    if quit {
                         break;
                     }
-                    
-                    // Update scroll if needed
                     self.update_scroll(buffer);
-                    
-                    // Update status message
-                    self.update_status_message(buffer, vim);
+                    self.update_status_message(buffer, editing);
+                    if let Some(message) = self.command_message.take() {
+                        self.status_message = message;
+                    }
+                }
+            } else if let Event::Key(key) = event::read()
+                .map_err(|e| RuskError::Ui(format!("Failed to read event: {}", e)))?
+            {
+                // Keys typed while recording go into the macro, except the `q` that stops it
+                let recording = self.macros.recording().is_some();
+                let quit = self.dispatch_key(key, buffer, editing)?;
+                self.publish_changes(buffer, editing, &mut seen);
+                if quit {
+                    break;
+                }
+                if recording {
+                    self.macros.record(key);
+                }
+                
+                // Update scroll if needed
+                self.update_scroll(buffer);
+                
+                // Update status message
+                self.update_status_message(buffer, editing);
+                if let Some(message) = self.lsp.take_message().or_else(|| self.command_message.take()) {
+                    self.status_message = message;
                 }
             }
         }
         
-        Ok(())
+        Ok(EventLoopExit::Quit)
     }
     
-
+    /// Save the buffer once it has been modified for `auto_save_interval` seconds, when `auto_save` is on
+    fn auto_save(&mut self, buffer: &mut Buffer) {
+        let interval = self.config.as_ref()
+            .filter(|config| config.editor.auto_save)
+            .map(|config| Duration::from_secs(config.editor.auto_save_interval));
+        let Some(interval) = interval.filter(|_| buffer.is_modified() && buffer.file_path().is_some() && !buffer.is_read_only()) else {
+            self.modified_since = None;
+            return;
+        };
+        if self.modified_since.get_or_insert_with(Instant::now).elapsed() >= interval {
+            self.modified_since = None;
+            if let Err(e) = self.events.save_buffer(buffer, None) {
+                self.status_message = e.to_string();
+            }
+        }
+    }
+    
+    fn cursor(buffer: &Buffer) -> Position {
+        let (line, column) = buffer.cursor_position();
+        Position::new(line, column)
+    }
+    
+    /// Announce the edits made by a key, then where the cursor and mode went since `seen`
+    fn publish_changes(&mut self, buffer: &mut Buffer, editing: &dyn EditingMode, seen: &mut (Position, EditorMode)) {
+        let changes = buffer.take_changes();
+        // File marks and jumps in this file follow its text, like its buffer marks
+        if let Err(e) = self.commands.marks_mut().apply_changes(buffer, &changes) {
+            self.command_message = Some(e.to_string());
+        }
+        if !changes.is_empty() {
+            let _ = self.events.emit(EditorEvent::TextChanged { buffer: buffer.id(), changes });
+        }
+        let (cursor, mode) = (Self::cursor(buffer), editing.editor_mode());
+        if cursor != seen.0 {
+            let _ = self.events.emit(EditorEvent::CursorMoved { buffer: buffer.id(), from: seen.0, to: cursor });
+        }
+        if mode != seen.1 {
+            let _ = self.events.emit(EditorEvent::ModeChanged { from: seen.1, to: mode });
+        }
+        *seen = (cursor, mode);
+    }
+    
+    
     
     /// Draw the main editor area (static version)
+    ///
+    /// Returns where the terminal cursor goes when the editing mode wants a
+    /// bar or underline cursor, which cannot be drawn as part of the text.
     fn draw_editor_static(
         f: &mut Frame, 
         area: Rect, 
         buffer: &Buffer, 
-        _vim: &VimPlugin, 
+        editing: &dyn EditingMode, 
         scroll_offset: usize, 
-        show_line_numbers: bool, 
+        view: ViewOptions, 
+        block: &[TextRange],
         color_scheme: &crate::config::settings::ColorScheme,
         syntax_highlighter: &mut Option<SyntaxHighlighter>
-    ) {
+    ) -> Option<(u16, u16)> {
         let (cursor_line, cursor_col) = buffer.cursor_position();
+        let block_cursor = editing.cursor_shape() == CursorShape::Block;
+        let selection = buffer.selection_range().filter(|range| !range.is_empty());
         
         // Calculate visible area
         let editor_height = area.height as usize;
         let start_line = scroll_offset;
-        let _end_line = (start_line + editor_height).min(buffer.line_count());
+        let gutter_width = Self::gutter_width(buffer, view.line_numbers);
+        let text_width = Self::area_text_width(area.width, buffer, view);
         
-        // Prepare lines for display
+        // Prepare lines for display, skipping lines hidden inside closed folds
         let mut lines = Vec::new();
+        let folds = buffer.folds();
+        
+        let diagnostics = buffer.diagnostics();
         
-        for (line_idx, line_content) in buffer.lines().iter().enumerate().skip(start_line).take(editor_height) {
+        for line_idx in folds.visible_lines(start_line, buffer.line_count(), editor_height) {
+            if lines.len() >= editor_height {
+                break;
+            }
+            let line_content = &buffer.lines()[line_idx];
             let mut spans = Vec::new();
             
+            // Add diagnostic sign column
+            if !diagnostics.is_empty() {
+                match diagnostics.most_severe_on_line(line_idx) {
+                    Some(diagnostic) => spans.push(Span::styled(
+                        format!("{} ", diagnostic.severity.sign()),
+                        Style::default().fg(Self::severity_color(diagnostic.severity, color_scheme)),
+                    )),
+                    None => spans.push(Span::raw("  ")),
+                }
+            }
+            
             // Add line number if enabled
-            if show_line_numbers {
+            if view.line_numbers {
                 let line_num = format!("{:4} ", line_idx + 1);
                 spans.push(Span::styled(
                     line_num,
@@ -207,6 +748,19 @@ impl TuiPlugin {
                 ));
             }
             
+            // Add fold column
+            if !folds.is_empty() {
+                let marker = match folds.range_starting_at(line_idx) {
+                    Some(_) if folds.is_closed(line_idx) => "▸ ",
+                    Some(_) => "▾ ",
+                    None => "  ",
+                };
+                spans.push(Span::styled(
+                    marker,
+                    Style::default().fg(color_scheme.line_number_color()),
+                ));
+            }
+            
             // Add line content with syntax highlighting
             let content_spans = if let Some(highlighter) = syntax_highlighter {
                 Self::apply_syntax_highlighting(line_content, buffer.language(), highlighter, color_scheme)
@@ -215,25 +769,69 @@ impl TuiPlugin {
                 vec![Span::styled(line_content.clone(), Style::default().fg(color_scheme.foreground_color()))]
             };
             
+            // Underline diagnostic ranges, least severe first so the most severe colour wins
+            let mut content_spans = content_spans;
+            let mut line_diagnostics = diagnostics.on_line(line_idx);
+            line_diagnostics.sort_by_key(|d| std::cmp::Reverse(d.severity));
+            for diagnostic in line_diagnostics {
+                let start = if diagnostic.range.start.line == line_idx { diagnostic.range.start.column } else { 0 };
+                let mut end = if diagnostic.range.end.line == line_idx { diagnostic.range.end.column } else { line_content.len() };
+                if end <= start {
+                    // Empty ranges underline the character they point at
+                    end = line_content[start.min(line_content.len())..].chars().next().map_or(start, |ch| start + ch.len_utf8());
+                }
+                let color = Self::severity_color(diagnostic.severity, color_scheme);
+                content_spans = Self::style_columns(content_spans, start, end, |style| {
+                    style.add_modifier(Modifier::UNDERLINED).underline_color(color)
+                });
+            }
+            
+            // Highlight the selected text
+            if let Some(selection) = selection.filter(|range| range.start.line <= line_idx && line_idx <= range.end.line) {
+                let start = if selection.start.line == line_idx { selection.start.column } else { 0 };
+                let end = if selection.end.line == line_idx { selection.end.column } else { line_content.len() };
+                content_spans = Self::style_columns(content_spans, start, end, |style| style.bg(color_scheme.selection_color()));
+            }
+            if let Some(range) = block.iter().find(|range| range.start.line == line_idx) {
+                content_spans = Self::style_columns(content_spans, range.start.column, range.end.column, |style| style.bg(color_scheme.selection_color()));
+            }
+            
             // Add line content with cursor highlighting
             if line_idx == cursor_line {
                 // Highlight current line
-                let bg_style = Style::default().bg(color_scheme.current_line_color());
+                let line_color = if view.cursor_line { color_scheme.current_line_color() } else { color_scheme.background_color() };
+                let bg_style = Style::default().bg(line_color);
                 
                 // Apply cursor highlighting to syntax-highlighted spans
-                let cursor_highlighted_spans = Self::apply_cursor_highlighting(
+                content_spans = Self::apply_cursor_highlighting(
                     content_spans, 
-                    cursor_col, 
+                    block_cursor.then_some(cursor_col), 
                     bg_style,
                     color_scheme
                 );
-                spans.extend(cursor_highlighted_spans);
+            }
+            if view.whitespace {
+                content_spans = Self::show_whitespace(content_spans, line_content);
+            }
+            
+            // Summarize closed folds after their first line
+            if let Some(summary) = Self::fold_summary(buffer, line_idx) {
+                content_spans.push(Span::styled(summary, Style::default().fg(color_scheme.comment_color())));
+            }
+            
+            // Wrapped lines continue on rows of their own, under a blank gutter
+            if view.wrap && text_width > 0 {
+                for (row, row_spans) in Self::wrap_spans(content_spans, text_width).into_iter().enumerate() {
+                    if row > 0 {
+                        spans = vec![Span::raw(" ".repeat(gutter_width as usize))];
+                    }
+                    spans.extend(row_spans);
+                    lines.push(Line::from(std::mem::take(&mut spans)));
+                }
             } else {
-                // Regular line with syntax highlighting
                 spans.extend(content_spans);
+                lines.push(Line::from(spans));
             }
-            
-            lines.push(Line::from(spans));
         }
         
         // Create editor widget
@@ -243,10 +841,90 @@ impl TuiPlugin {
                 .title("Rusk Editor")
                 .border_style(Style::default().fg(color_scheme.border_color()))
             )
-            .style(Style::default().bg(color_scheme.background_color()))
-            .wrap(Wrap { trim: false });
+            .style(Style::default().bg(color_scheme.background_color()));
         
         f.render_widget(editor, area);
+        
+        if block_cursor || cursor_line < scroll_offset {
+            return None;
+        }
+        let (row, column) = Self::screen_position(buffer, scroll_offset, Position::new(cursor_line, cursor_col), text_width, view);
+        let position = (area.x + 1 + gutter_width + column as u16, area.y + 1 + row as u16);
+        (position.0 + 1 < area.right() && position.1 + 1 < area.bottom()).then_some(position)
+    }
+    
+    /// Text shown after the first line of a closed fold
+    fn fold_summary(buffer: &Buffer, line: usize) -> Option<String> {
+        buffer.folds().folded_summary_at(line).map(|fold| format!(" ··· {} lines", fold.hidden_line_count()))
+    }
+    
+    /// Width a piece of line text is drawn at
+    fn text_display_width(text: &str, view: ViewOptions) -> usize {
+        if view.whitespace { Span::raw(text.replace('\t', "→")).width() } else { Span::raw(text).width() }
+    }
+    
+    /// Columns left for line text in an editor area of a given width, right of the borders and gutter
+    fn area_text_width(area_width: u16, buffer: &Buffer, view: ViewOptions) -> usize {
+        (area_width as usize).saturating_sub(2 + Self::gutter_width(buffer, view.line_numbers) as usize)
+    }
+    
+    /// Columns left for line text in the editor area as it is laid out now
+    fn text_width(&self, buffer: &Buffer) -> usize {
+        let width = self.terminal_size().map_or(0, |(width, _)| width);
+        let explorer = if self.explorer.is_open() { EXPLORER_WIDTH.min(width / 2) } else { 0 };
+        Self::area_text_width(width - explorer, buffer, self.view)
+    }
+    
+    /// Screen rows a visible line takes: one, or as many as its text needs when long lines wrap
+    fn line_rows(buffer: &Buffer, line: usize, text_width: usize, view: ViewOptions) -> usize {
+        if !view.wrap || text_width == 0 {
+            return 1;
+        }
+        let summary = Self::fold_summary(buffer, line).map_or(0, |summary| Span::raw(summary).width());
+        (Self::text_display_width(&buffer.lines()[line], view) + summary).div_ceil(text_width).max(1)
+    }
+    
+    /// Screen row and column, within the text area, of a position on a visible line at or below the top line
+    fn screen_position(buffer: &Buffer, top: usize, position: Position, text_width: usize, view: ViewOptions) -> (usize, usize) {
+        let folds = buffer.folds();
+        let rows_above: usize = folds.visible_lines(top, folds.visible_start(position.line), usize::MAX).into_iter()
+            .map(|line| Self::line_rows(buffer, line, text_width, view))
+            .sum();
+        
+        let text = buffer.lines().get(position.line).map_or("", |line| line.get(..position.column).unwrap_or_default());
+        let column = Self::text_display_width(text, view);
+        if view.wrap && text_width > 0 {
+            (rows_above + column / text_width, column % text_width)
+        } else {
+            (rows_above, column)
+        }
+    }
+    
+    /// Split a line's spans into rows of at most `width` columns
+    fn wrap_spans(spans: Vec<Span<'static>>, width: usize) -> Vec<Vec<Span<'static>>> {
+        let mut rows = vec![Vec::new()];
+        let mut used = 0;
+        
+        for span in spans {
+            let mut piece = String::new();
+            for ch in span.content.chars() {
+                let ch_width = Span::raw(&*ch.encode_utf8(&mut [0; 4])).width();
+                if used > 0 && used + ch_width > width {
+                    if !piece.is_empty() {
+                        rows.last_mut().unwrap().push(Span::styled(std::mem::take(&mut piece), span.style));
+                    }
+                    rows.push(Vec::new());
+                    used = 0;
+                }
+                piece.push(ch);
+                used += ch_width;
+            }
+            if !piece.is_empty() {
+                rows.last_mut().unwrap().push(Span::styled(piece, span.style));
+            }
+        }
+        
+        rows
     }
     
     /// Apply syntax highlighting to a line of text
@@ -302,22 +980,526 @@ impl TuiPlugin {
         }
     }
     
+    /// Restyle the part of a line's spans between two byte columns
+    fn style_columns(spans: Vec<Span<'static>>, start: usize, end: usize, restyle: impl Fn(Style) -> Style) -> Vec<Span<'static>> {
+        let mut result = Vec::new();
+        let mut current_pos = 0;
+        
+        for span in spans {
+            let span_text = span.content.as_ref();
+            let span_end = current_pos + span_text.len();
+            let from = start.clamp(current_pos, span_end) - current_pos;
+            let to = end.clamp(current_pos, span_end) - current_pos;
+            
+            if from >= to || !span_text.is_char_boundary(from) || !span_text.is_char_boundary(to) {
+                result.push(span);
+            } else {
+                for (piece, restyled) in [(&span_text[..from], false), (&span_text[from..to], true), (&span_text[to..], false)] {
+                    if !piece.is_empty() {
+                        let style = if restyled { restyle(span.style) } else { span.style };
+                        result.push(Span::styled(piece.to_string(), style));
+                    }
+                }
+            }
+            
+            current_pos = span_end;
+        }
+        
+        result
+    }
+    
+    /// Show the tabs of a line's spans as `→` and its trailing spaces as `·`, once no more styling needs byte columns
+    fn show_whitespace(spans: Vec<Span<'static>>, line_content: &str) -> Vec<Span<'static>> {
+        let trailing = line_content.trim_end_matches([' ', '\t']).len();
+        let mut current_pos = 0;
+        spans.into_iter().map(|span| {
+            let start = current_pos;
+            current_pos += span.content.len();
+            let shown: String = span.content.char_indices().map(|(offset, ch)| match ch {
+                '\t' => '→',
+                // The block cursor past the end of the line is a space too
+                ' ' if (trailing..line_content.len()).contains(&(start + offset)) => '·',
+                ch => ch,
+            }).collect();
+            Span::styled(shown, span.style)
+        }).collect()
+    }
+    
+    /// Width of the sign, line number and fold columns before the text
+    fn gutter_width(buffer: &Buffer, show_line_numbers: bool) -> u16 {
+        let signs = if buffer.diagnostics().is_empty() { 0 } else { 2 };
+        let numbers = if show_line_numbers { 5 } else { 0 };
+        let folds = if buffer.folds().is_empty() { 0 } else { 2 };
+        signs + numbers + folds
+    }
+    
+    /// Get the title and (keys, description) entries of the which-key popup, once a pending sequence has waited long enough
+    fn which_key_entries(keybindings: &Keybindings, commands: &CommandSystem) -> Option<(String, Vec<(String, String)>)> {
+        let delay = WHICH_KEY_DELAY.min(keybindings.timeout() / 2);
+        if keybindings.pending_since().is_none_or(|since| since.elapsed() < delay) {
+            return None;
+        }
+        
+        let entries: Vec<(String, String)> = keybindings.pending_continuations().into_iter()
+            .map(|continuation| {
+                let description = continuation.command.as_deref()
+                    .map(|name| commands.get(name).map_or(name, |spec| spec.description.as_str()).to_string());
+                let description = match (description, continuation.more) {
+                    (Some(description), 0) => description,
+                    (Some(description), more) => format!("{} (+{} more)", description, more),
+                    (None, more) => format!("+{} binding{}", more, if more == 1 { "" } else { "s" }),
+                };
+                (continuation.key.to_string(), description)
+            })
+            .collect();
+        (!entries.is_empty()).then(|| (format_key_sequence(&keybindings.pending()), entries))
+    }
+    
+    /// Draw the which-key popup in columns along the bottom of the editor area
+    fn draw_which_key_static(
+        f: &mut Frame,
+        area: Rect,
+        title: &str,
+        entries: &[(String, String)],
+        color_scheme: &crate::config::settings::ColorScheme,
+    ) {
+        let key_width = entries.iter().map(|(key, _)| key.chars().count()).max().unwrap_or(0);
+        let column_width = entries.iter()
+            .map(|(_, description)| key_width + description.chars().count() + 5)
+            .max()
+            .unwrap_or(1)
+            .min(area.width.saturating_sub(2).max(1) as usize);
+        let columns = (area.width.saturating_sub(2) as usize / column_width).max(1);
+        let rows = entries.len().div_ceil(columns).min(WHICH_KEY_ROWS);
+        let height = (rows as u16 + 2).min(area.height);
+        let popup = Rect::new(area.x, area.y + area.height - height, area.width, height);
+        
+        let base = Style::default().fg(color_scheme.foreground_color()).bg(color_scheme.current_line_color());
+        let lines: Vec<Line> = (0..rows)
+            .map(|row| {
+                let spans = (0..columns)
+                    .filter_map(|column| entries.get(column * rows + row))
+                    .flat_map(|(key, description)| {
+                        let description: String = description.chars().take(column_width.saturating_sub(key_width + 5)).collect();
+                        [
+                            Span::styled(format!("{:>width$}", key, width = key_width), base.fg(color_scheme.keyword_color()).add_modifier(Modifier::BOLD)),
+                            Span::styled(" → ", base.fg(color_scheme.comment_color())),
+                            Span::styled(format!("{:<width$}", description, width = column_width.saturating_sub(key_width + 3)), base),
+                        ]
+                    })
+                    .collect::<Vec<_>>();
+                Line::from(spans)
+            })
+            .collect();
+        
+        f.render_widget(Clear, popup);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" {} ", title))
+                .border_style(Style::default().fg(color_scheme.border_color()))
+                .style(Style::default().bg(color_scheme.current_line_color()))),
+            popup,
+        );
+    }
+    
+    /// Draw an info popup of plain lines along the bottom of the editor area
+    fn draw_info_static(
+        f: &mut Frame,
+        area: Rect,
+        title: &str,
+        lines: &[String],
+        color_scheme: &crate::config::settings::ColorScheme,
+    ) {
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let popup = Rect::new(area.x, area.y + area.height - height, area.width, height);
+        let base = Style::default().fg(color_scheme.foreground_color()).bg(color_scheme.current_line_color());
+        let lines: Vec<Line> = lines.iter().map(|line| Line::from(Span::styled(line.clone(), base))).collect();
+        
+        f.render_widget(Clear, popup);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" {} ", title))
+                .title_bottom(" Press any key ")
+                .border_style(Style::default().fg(color_scheme.border_color()))
+                .style(Style::default().bg(color_scheme.current_line_color()))),
+            popup,
+        );
+    }
+    
+    /// Draw the file finder: the query and results on the left, a preview of the highlighted file on the right
+    fn draw_finder_static(
+        f: &mut Frame,
+        area: Rect,
+        finder: &mut FileFinder,
+        syntax_highlighter: &mut Option<SyntaxHighlighter>,
+        color_scheme: &crate::config::settings::ColorScheme,
+    ) {
+        let width = (area.width * 9 / 10).max(area.width.min(30));
+        let height = (area.height * 8 / 10).max(area.height.min(8));
+        let popup = Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height);
+        let halves = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+            .split(popup);
+        
+        let base = Style::default().fg(color_scheme.foreground_color()).bg(color_scheme.current_line_color());
+        let block = |title: String| Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(color_scheme.border_color()))
+            .style(Style::default().bg(color_scheme.current_line_color()));
+        
+        // Results
+        let rows = halves[0].height.saturating_sub(3) as usize;
+        let (first, visible) = finder.visible(rows);
+        let mut lines = vec![Line::from(vec![
+            Span::styled("> ", base.fg(color_scheme.keyword_color())),
+            Span::styled(format!("{}█", finder.query()), base),
+        ])];
+        for (i, found) in visible.iter().enumerate() {
+            let style = if first + i == finder.selected() { base.bg(color_scheme.selection_color()) } else { base };
+            let spans: Vec<Span> = found.path.chars().enumerate()
+                .map(|(n, ch)| {
+                    let matched = if found.indices.contains(&n) { style.fg(color_scheme.keyword_color()).add_modifier(Modifier::BOLD) } else { style };
+                    Span::styled(ch.to_string(), matched)
+                })
+                .collect();
+            lines.push(Line::from(spans));
+        }
+        let (indexed, complete) = finder.indexed();
+        let title = if complete {
+            format!(" Files {}/{} ", finder.match_count(), indexed)
+        } else {
+            format!(" Files {}/{} (indexing…) ", finder.match_count(), indexed)
+        };
+        
+        f.render_widget(Clear, popup);
+        f.render_widget(Paragraph::new(lines).block(block(title)), halves[0]);
+        
+        // Preview, highlighted like the editor
+        let preview_rows = halves[1].height.saturating_sub(2) as usize;
+        let (title, lines) = match finder.preview() {
+            Some(preview) => {
+                let lines: Vec<Line> = preview.lines.iter()
+                    .take(preview_rows)
+                    .map(|line| match syntax_highlighter {
+                        Some(highlighter) => Line::from(Self::apply_syntax_highlighting(line, &preview.language, highlighter, color_scheme)),
+                        None => Line::from(Span::styled(line.clone(), base)),
+                    })
+                    .collect();
+                (format!(" {} ", preview.path), lines)
+            }
+            None => (" Preview ".to_string(), Vec::new()),
+        };
+        f.render_widget(Paragraph::new(lines).block(block(title)), halves[1]);
+    }
+    
+    /// Draw the file explorer tree, marking the current buffer's file, git status and ignored files
+    fn draw_explorer_static(
+        f: &mut Frame,
+        area: Rect,
+        explorer: &FileExplorer,
+        buffer: &Buffer,
+        color_scheme: &crate::config::settings::ColorScheme,
+    ) {
+        let base = Style::default().fg(color_scheme.foreground_color());
+        let current = buffer.file_path().and_then(|path| std::path::absolute(path).ok());
+        let prompt = explorer.prompt_text();
+        let rows = area.height.saturating_sub(2 + u16::from(prompt.is_some())) as usize;
+        let first = explorer.selected().saturating_sub(rows.saturating_sub(1));
+        
+        let mut lines: Vec<Line> = explorer.entries().iter().enumerate().skip(first).take(rows)
+            .map(|(i, entry)| {
+                let mut style = if entry.ignored { base.fg(color_scheme.comment_color()) } else { base };
+                if let Some(status) = entry.git {
+                    style = style.fg(Self::git_status_color(status, color_scheme));
+                }
+                if entry.is_dir {
+                    style = style.add_modifier(Modifier::BOLD);
+                }
+                if current.as_ref() == Some(&entry.path) {
+                    style = style.add_modifier(Modifier::UNDERLINED);
+                }
+                if i == explorer.selected() {
+                    style = style.bg(if explorer.is_focused() { color_scheme.selection_color() } else { color_scheme.current_line_color() });
+                }
+                let icon = match (entry.is_dir, entry.expanded) {
+                    (true, true) => "▾ ",
+                    (true, false) => "▸ ",
+                    (false, _) => "  ",
+                };
+                let marker = entry.git.map_or(' ', |status| status.marker());
+                Line::from(vec![
+                    Span::styled(format!("{}{}{}", "  ".repeat(entry.depth), icon, entry.name), style),
+                    Span::styled(format!(" {}", marker), style.remove_modifier(Modifier::UNDERLINED)),
+                ])
+            })
+            .collect();
+        if let Some(prompt) = prompt {
+            lines.resize(rows, Line::from(""));
+            lines.push(Line::from(Span::styled(format!("{}█", prompt), base.fg(color_scheme.keyword_color()))));
+        }
+        
+        let title = explorer.root().file_name()
+            .map(|name| format!(" {} ", name.to_string_lossy()))
+            .unwrap_or_else(|| " Files ".to_string());
+        let border = if explorer.is_focused() { color_scheme.keyword_color() } else { color_scheme.border_color() };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(border));
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+    
+    /// Color of a git status decoration
+    fn git_status_color(status: GitStatus, color_scheme: &crate::config::settings::ColorScheme) -> Color {
+        match status {
+            GitStatus::Conflicted | GitStatus::Deleted => color_scheme.error_color(),
+            GitStatus::Modified | GitStatus::Renamed => color_scheme.warning_color(),
+            GitStatus::Added | GitStatus::Untracked => color_scheme.string_color(),
+        }
+    }
+    
+    /// Draw the command palette centered near the top of the screen
+    fn draw_palette_static(
+        f: &mut Frame,
+        area: Rect,
+        palette: &CommandPalette,
+        color_scheme: &crate::config::settings::ColorScheme,
+    ) {
+        let entries = palette.entries();
+        let rows = entries.len().clamp(1, PALETTE_ROWS);
+        let width = PALETTE_WIDTH.min(area.width.saturating_sub(4)).max(area.width.min(20));
+        let height = (rows as u16 + 3).min(area.height);
+        let popup = Rect::new(area.x + (area.width - width) / 2, area.y + area.height.min(2), width, height);
+        let inner_width = width.saturating_sub(2) as usize;
+        
+        let base = Style::default().fg(color_scheme.foreground_color()).bg(color_scheme.current_line_color());
+        let input = match palette.argument_prompt() {
+            Some((label, text)) => Line::from(vec![
+                Span::styled(label, base.fg(color_scheme.comment_color())),
+                Span::styled(format!("{}█", text), base),
+            ]),
+            None => Line::from(vec![
+                Span::styled("> ", base.fg(color_scheme.keyword_color())),
+                Span::styled(format!("{}█", palette.query()), base),
+            ]),
+        };
+        let mut lines = vec![input];
+        
+        if entries.is_empty() {
+            lines.push(Line::from(Span::styled("No matching commands", base.fg(color_scheme.comment_color()))));
+        }
+        let first = palette.selected().saturating_sub(rows - 1);
+        let name_width = entries.iter().map(|entry| entry.name.chars().count()).max().unwrap_or(0);
+        for (i, entry) in entries.iter().enumerate().skip(first).take(rows) {
+            let mut style = base;
+            if i == palette.selected() {
+                style = style.bg(color_scheme.selection_color());
+            }
+            if !entry.available {
+                style = style.fg(color_scheme.comment_color());
+            }
+            
+            let mut spans: Vec<Span> = entry.name.chars().enumerate()
+                .map(|(n, ch)| {
+                    let matched = if entry.matched.contains(&n) { style.fg(color_scheme.keyword_color()).add_modifier(Modifier::BOLD) } else { style };
+                    Span::styled(ch.to_string(), matched)
+                })
+                .collect();
+            let keys = entry.keys.as_deref().unwrap_or("");
+            let description_width = inner_width.saturating_sub(name_width + keys.chars().count() + 4);
+            let description: String = entry.description.chars().take(description_width).collect();
+            spans.push(Span::styled(" ".repeat(name_width - entry.name.chars().count() + 2), style));
+            spans.push(Span::styled(format!("{:<width$}", description, width = description_width), style.fg(color_scheme.comment_color())));
+            spans.push(Span::styled(format!("  {}", keys), style.fg(color_scheme.type_name_color())));
+            lines.push(Line::from(spans));
+        }
+        
+        f.render_widget(Clear, popup);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default()
+                .borders(Borders::ALL)
+                .title(" Commands ")
+                .border_style(Style::default().fg(color_scheme.border_color()))
+                .style(Style::default().bg(color_scheme.current_line_color()))),
+            popup,
+        );
+    }
+    
+    /// Draw the completion popup below (or above) the anchor cell, with a documentation preview beside it
+    fn draw_completion_popup_static(
+        f: &mut Frame,
+        area: Rect,
+        anchor: (u16, u16),
+        menu: &CompletionMenu,
+        color_scheme: &crate::config::settings::ColorScheme,
+    ) {
+        let items = menu.items();
+        let rows = items.len().min(COMPLETION_POPUP_ROWS);
+        let first = menu.selected().saturating_sub(rows.saturating_sub(1));
+        
+        let label_width = items.iter().map(|scored| scored.item.label.chars().count()).max().unwrap_or(0);
+        let detail_width = items.iter()
+            .filter_map(|scored| scored.item.detail.as_ref().map(|detail| detail.chars().count()))
+            .max()
+            .unwrap_or(0)
+            .min(24);
+        let width = ((label_width + detail_width + 5) as u16 + 2).min(area.width);
+        let height = (rows as u16 + 2).min(area.height);
+        
+        // Below the cursor line if it fits, otherwise above it
+        let (x, cursor_y) = anchor;
+        let y = if cursor_y + 1 + height <= area.y + area.height {
+            cursor_y + 1
+        } else {
+            cursor_y.saturating_sub(height).max(area.y)
+        };
+        let x = x.saturating_sub(3).min(area.x + area.width - width).max(area.x);
+        let popup = Rect::new(x, y, width, height);
+        
+        let lines: Vec<Line> = items.iter()
+            .enumerate()
+            .skip(first)
+            .take(rows)
+            .map(|(i, scored)| {
+                let mut base = Style::default().fg(color_scheme.foreground_color()).bg(color_scheme.current_line_color());
+                if i == menu.selected() {
+                    base = base.bg(color_scheme.selection_color());
+                }
+                
+                let mut spans = vec![Span::styled(format!("{} ", scored.item.kind.marker()), base.fg(color_scheme.type_name_color()))];
+                // Highlight the characters that matched the typed text
+                for (n, ch) in scored.item.label.chars().enumerate() {
+                    let style = if scored.matched.contains(&n) { base.fg(color_scheme.keyword_color()).add_modifier(Modifier::BOLD) } else { base };
+                    spans.push(Span::styled(ch.to_string(), style));
+                }
+                let padding = label_width - scored.item.label.chars().count() + 2;
+                let detail: String = scored.item.detail.as_deref().unwrap_or("").chars().take(detail_width).collect();
+                spans.push(Span::styled(format!("{}{:<width$}", " ".repeat(padding), detail, width = detail_width), base.fg(color_scheme.comment_color())));
+                Line::from(spans)
+            })
+            .collect();
+        
+        f.render_widget(Clear, popup);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(color_scheme.border_color()))
+                .style(Style::default().bg(color_scheme.current_line_color()))),
+            popup,
+        );
+        
+        // Documentation of the selected candidate, to the right if there is room, else to the left
+        let Some(documentation) = menu.selected_item().and_then(|item| item.documentation.as_ref()) else {
+            return;
+        };
+        let doc_width = COMPLETION_DOC_WIDTH.min(area.width / 2);
+        let doc_x = if popup.x + popup.width + doc_width <= area.x + area.width {
+            popup.x + popup.width
+        } else if popup.x >= area.x + doc_width {
+            popup.x - doc_width
+        } else {
+            return;
+        };
+        let doc_area = Rect::new(doc_x, popup.y, doc_width, popup.height.max(5).min(area.y + area.height - popup.y));
+        
+        f.render_widget(Clear, doc_area);
+        f.render_widget(
+            Paragraph::new(documentation.as_str())
+                .wrap(Wrap { trim: true })
+                .style(Style::default().fg(color_scheme.foreground_color()))
+                .block(Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(color_scheme.border_color()))
+                    .style(Style::default().bg(color_scheme.current_line_color()))),
+            doc_area,
+        );
+    }
+    
+    /// Get the colour used for a diagnostic severity
+    fn severity_color(severity: DiagnosticSeverity, color_scheme: &crate::config::settings::ColorScheme) -> Color {
+        match severity {
+            DiagnosticSeverity::Error => color_scheme.error_color(),
+            DiagnosticSeverity::Warning => color_scheme.warning_color(),
+            DiagnosticSeverity::Info => color_scheme.function_color(),
+            DiagnosticSeverity::Hint => color_scheme.comment_color(),
+        }
+    }
+    
+    /// Draw the diagnostics list, scrolled to keep the diagnostic at the cursor visible
+    fn draw_diagnostics_list_static(f: &mut Frame, area: Rect, buffer: &Buffer, color_scheme: &crate::config::settings::ColorScheme) {
+        let diagnostics = buffer.diagnostics();
+        let all = diagnostics.all();
+        let (line, col) = buffer.cursor_position();
+        let current = diagnostics.at(Position::new(line, col));
+        
+        let selected = current.and_then(|c| all.iter().position(|d| std::ptr::eq(*d, c)));
+        let first = selected.map_or(0, |i| i.saturating_sub(DIAGNOSTICS_LIST_ROWS - 1));
+        
+        let mut lines: Vec<Line> = all.iter()
+            .enumerate()
+            .skip(first)
+            .take(DIAGNOSTICS_LIST_ROWS)
+            .map(|(i, diagnostic)| {
+                let mut style = Style::default().fg(color_scheme.foreground_color());
+                if Some(i) == selected {
+                    style = style.bg(color_scheme.current_line_color());
+                }
+                Line::from(vec![
+                    Span::styled(
+                        format!("{} ", diagnostic.severity.sign()),
+                        style.fg(Self::severity_color(diagnostic.severity, color_scheme)),
+                    ),
+                    Span::styled(
+                        format!("{}:{} {}", diagnostic.range.start.line + 1, diagnostic.range.start.column + 1, diagnostic.message),
+                        style,
+                    ),
+                ])
+            })
+            .collect();
+        if lines.is_empty() {
+            lines.push(Line::from(Span::styled("No diagnostics", Style::default().fg(color_scheme.comment_color()))));
+        }
+        
+        let title = format!(
+            "Diagnostics ({} errors, {} warnings)",
+            diagnostics.count(DiagnosticSeverity::Error),
+            diagnostics.count(DiagnosticSeverity::Warning),
+        );
+        let list = Paragraph::new(lines)
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(color_scheme.border_color()))
+            )
+            .style(Style::default().bg(color_scheme.background_color()));
+        
+        f.render_widget(list, area);
+    }
+    
     /// Apply cursor highlighting to syntax-highlighted spans
+    ///
+    /// Without a cursor column only the line background is applied, leaving
+    /// spans that already have a background, like the selection, as they are.
     fn apply_cursor_highlighting(
         spans: Vec<Span<'static>>,
-        cursor_col: usize,
+        cursor_col: Option<usize>,
         bg_style: Style,
         color_scheme: &crate::config::settings::ColorScheme,
     ) -> Vec<Span<'static>> {
         let mut result = Vec::new();
         let mut current_pos = 0;
         let mut cursor_applied = false;
+        let line_bg = |style: Style| if style.bg.is_some() { style } else { style.bg(bg_style.bg.unwrap_or(ratatui::style::Color::Reset)) };
         
         for span in spans {
             let span_text = span.content.as_ref();
             let span_end = current_pos + span_text.len();
             
-            if cursor_col >= current_pos && cursor_col < span_end && !cursor_applied {
+            if let Some(cursor_col) = cursor_col.filter(|col| *col >= current_pos && *col < span_end && !cursor_applied) {
                 // Cursor is within this span
                 let cursor_offset = cursor_col - current_pos;
                 
@@ -325,7 +1507,7 @@ impl TuiPlugin {
                 if cursor_offset > 0 {
                     result.push(Span::styled(
                         span_text[..cursor_offset].to_string(),
-                        span.style.bg(bg_style.bg.unwrap_or(ratatui::style::Color::Reset)),
+                        line_bg(span.style),
                     ));
                 }
                 
@@ -340,7 +1522,7 @@ impl TuiPlugin {
                 if cursor_offset + 1 < span_text.len() {
                     result.push(Span::styled(
                         span_text[cursor_offset + 1..].to_string(),
-                        span.style.bg(bg_style.bg.unwrap_or(ratatui::style::Color::Reset)),
+                        line_bg(span.style),
                     ));
                 }
                 
@@ -349,7 +1531,7 @@ impl TuiPlugin {
                 // Apply background style to entire span
                 result.push(Span::styled(
                     span_text.to_string(),
-                    span.style.bg(bg_style.bg.unwrap_or(ratatui::style::Color::Reset)),
+                    line_bg(span.style),
                 ));
             }
             
@@ -357,7 +1539,7 @@ impl TuiPlugin {
         }
         
         // If cursor is at the end of the line, add a space with cursor style
-        if cursor_col >= current_pos && !cursor_applied {
+        if cursor_col.is_some_and(|col| col >= current_pos) && !cursor_applied {
             result.push(Span::styled(
                 " ",
                 Style::default().bg(color_scheme.cursor_color()).fg(color_scheme.background_color()),
@@ -368,14 +1550,15 @@ impl TuiPlugin {
     }
     
     /// Draw the status line (static version)
-    fn draw_status_line_static(f: &mut Frame, area: Rect, buffer: &Buffer, vim: &VimPlugin, status_message: &str, color_scheme: &crate::config::settings::ColorScheme) {
+    ///
+    /// `mode_override` names a mode the interface itself is in, like
+    /// typing on its `:` command line, with the mode its colour is taken from.
+    fn draw_status_line_static(f: &mut Frame, area: Rect, buffer: &Buffer, editing: &dyn EditingMode, mode_override: Option<(&str, EditorMode)>, status_message: &str, color_scheme: &crate::config::settings::ColorScheme) {
         let (line, col) = buffer.cursor_position();
-        let mode_str = match vim.mode() {
-            VimMode::Normal => "NORMAL",
-            VimMode::Insert => "INSERT",
-            VimMode::Visual => "VISUAL",
-            VimMode::VisualLine => "V-LINE",
-            VimMode::Command => "COMMAND",
+        let mode_name = mode_override.map_or_else(|| editing.mode_name(), |(name, _)| name.to_string());
+        let mode_str = match editing.pending_keys() {
+            Some(keys) => format!("{} {}", mode_name, keys),
+            None => mode_name,
         };
         
         let file_name = buffer.file_path().unwrap_or("[No Name]");
@@ -394,11 +1577,10 @@ impl TuiPlugin {
             position, total_lines
         );
         
-        let status_style = match vim.mode() {
-            VimMode::Normal => Style::default().bg(color_scheme.status_bar_color()).fg(color_scheme.foreground_color()),
-            VimMode::Insert => Style::default().bg(crate::config::settings::ColorScheme::parse_color("#16a085")).fg(color_scheme.background_color()),
-            VimMode::Visual | VimMode::VisualLine => Style::default().bg(crate::config::settings::ColorScheme::parse_color("#f39c12")).fg(color_scheme.background_color()),
-            VimMode::Command => Style::default().bg(crate::config::settings::ColorScheme::parse_color("#9b59b6")).fg(color_scheme.foreground_color()),
+        let color = mode_override.map_or_else(|| editing.mode_color(), |(_, mode)| mode::mode_color(mode));
+        let status_style = match color {
+            Some(color) => Style::default().bg(color).fg(color_scheme.background_color()),
+            None => Style::default().bg(color_scheme.status_bar_color()).fg(color_scheme.foreground_color()),
         };
         
         let status = Paragraph::new(status_text)
@@ -407,12 +1589,31 @@ impl TuiPlugin {
         f.render_widget(status, area);
     }
     
+    /// Draw the matches Tab cycles through on the command line, the one shown highlighted
+    fn draw_wildmenu_static(f: &mut Frame, area: Rect, candidates: &[String], selected: Option<usize>, color_scheme: &crate::config::settings::ColorScheme) {
+        let base = Style::default().bg(color_scheme.status_bar_color()).fg(color_scheme.foreground_color());
+        // Start the row late enough for the shown match to fit
+        let widths: Vec<usize> = candidates.iter().map(|candidate| Span::raw(candidate.as_str()).width() + 2).collect();
+        let mut first = 0;
+        if let Some(selected) = selected {
+            while first < selected && widths[first..=selected].iter().sum::<usize>() > area.width as usize {
+                first += 1;
+            }
+        }
+        let spans: Vec<Span> = candidates.iter().enumerate().skip(first)
+            .map(|(i, candidate)| {
+                let style = if Some(i) == selected { base.bg(color_scheme.selection_color()) } else { base };
+                Span::styled(format!(" {} ", candidate), style)
+            })
+            .collect();
+        f.render_widget(Paragraph::new(Line::from(spans)).style(base), area);
+    }
+    
     /// Draw the command line (static version)
-    fn draw_command_line_static(f: &mut Frame, area: Rect, vim: &VimPlugin, color_scheme: &crate::config::settings::ColorScheme) {
-        let command_text = if vim.mode() == &VimMode::Command {
-            vim.command_buffer()
-        } else {
-            ""
+    fn draw_command_line_static(f: &mut Frame, area: Rect, editing: &dyn EditingMode, prompt: Option<&str>, color_scheme: &crate::config::settings::ColorScheme) {
+        let command_text = match prompt {
+            Some(prompt) => prompt.to_string(),
+            None => editing.command_line().unwrap_or_default(),
         };
         
         let command = Paragraph::new(command_text)
@@ -423,33 +1624,661 @@ impl TuiPlugin {
     
     /// Update scroll offset based on cursor position
     fn update_scroll(&mut self, buffer: &Buffer) {
-        let (cursor_line, _) = buffer.cursor_position();
+        let (cursor_line, cursor_col) = buffer.cursor_position();
         
         if let Some(terminal) = &self.terminal {
             let terminal_height = terminal.size().unwrap_or_default().height as usize;
-            let editor_height = terminal_height.saturating_sub(3); // Account for status and command lines
+            let editor_height = terminal_height
+                .saturating_sub(3) // Account for status and command lines
+                .saturating_sub(self.diagnostics_list_height(buffer) as usize);
             
-            // Scroll down if cursor is below visible area
-            if cursor_line >= self.scroll_offset + editor_height {
-                self.scroll_offset = cursor_line - editor_height + 1;
-            }
+            let folds = buffer.folds();
+            self.scroll_offset = folds.visible_start(self.scroll_offset);
             
             // Scroll up if cursor is above visible area
             if cursor_line < self.scroll_offset {
                 self.scroll_offset = cursor_line;
             }
+            
+            // Scroll down if cursor is below visible area, counting folded lines as one row and wrapped lines as all of theirs
+            let (text_width, view) = (self.text_width(buffer), self.view);
+            let cursor = Position::new(cursor_line, cursor_col);
+            let below = editor_height > 0
+                && (folds.visible_distance(self.scroll_offset, cursor_line) >= editor_height
+                    || Self::screen_position(buffer, self.scroll_offset, cursor, text_width, view).0 >= editor_height);
+            if below {
+                // Fill the rows above the cursor's row, from the cursor line up
+                let mut top = folds.visible_start(cursor_line);
+                let mut rows = Self::screen_position(buffer, top, cursor, text_width, view).0 + 1;
+                while let Some(line) = folds.previous_visible_line(top) {
+                    rows += Self::line_rows(buffer, line, text_width, view);
+                    if rows > editor_height {
+                        break;
+                    }
+                    top = line;
+                }
+                self.scroll_offset = top;
+            }
         }
     }
     
+    /// Scroll so the cursor line is at the top, middle or bottom of the editor area, as `zt`, `zz` and `zb` do
+    fn scroll_cursor(&mut self, place: ScreenPlace, buffer: &Buffer) {
+        let height = self.motion_env(buffer).height;
+        let rows_above = match place {
+            ScreenPlace::Top => 0,
+            ScreenPlace::Middle => height.saturating_sub(1) / 2,
+            ScreenPlace::Bottom => height.saturating_sub(1),
+        };
+        
+        let (line, column) = buffer.cursor_position();
+        let (text_width, view) = (self.text_width(buffer), self.view);
+        let folds = buffer.folds();
+        let mut top = folds.visible_start(line);
+        let mut rows = Self::screen_position(buffer, top, Position::new(line, column), text_width, view).0;
+        while let Some(line) = folds.previous_visible_line(top) {
+            rows += Self::line_rows(buffer, line, text_width, view);
+            if rows > rows_above {
+                break;
+            }
+            top = line;
+        }
+        self.scroll_offset = top;
+    }
+    
+    /// Recompute fold regions for the buffer
+    fn refresh_folds(&mut self, buffer: &mut Buffer) {
+        if let Some(folding) = &mut self.folding {
+            folding.refresh(buffer);
+        }
+    }
+    
+    /// Height of the diagnostics list including its border, or 0 when hidden
+    fn diagnostics_list_height(&self, buffer: &Buffer) -> u16 {
+        if self.show_diagnostics_list {
+            buffer.diagnostics().all().len().clamp(1, DIAGNOSTICS_LIST_ROWS) as u16 + 2
+        } else {
+            0
+        }
+    }
+    
+    /// Get the command system
+    pub fn commands(&self) -> &CommandSystem {
+        &self.commands
+    }
+    
+    /// Get the command system for registering commands
+    pub fn commands_mut(&mut self) -> &mut CommandSystem {
+        &mut self.commands
+    }
+    
+    /// Feed a key to the keybindings, running completed bindings and handing unbound keys to the editing mode
+    ///
+    /// A typed command line that is bound (`:wq`) or names a registered
+    /// command runs through the command system. Modes with their own key
+    /// maps get the key directly. Returns whether to quit.
+    fn handle_command_key(&mut self, key: KeyEvent, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        if !editing.uses_keybindings() {
+            let quit = editing.handle_key(key, buffer)?;
+            return Ok(self.run_mode_requests(buffer, editing)? || quit);
+        }
+        let mode = editing.editor_mode();
+        
+        if mode == EditorMode::Command && key.code == KeyCode::Enter {
+            let line = editing.command_line().unwrap_or_default().trim().trim_start_matches(':').to_string();
+            let bound = self.keybindings.command_line(&line).map(str::to_string);
+            let name = line.split_whitespace().next().unwrap_or("");
+            if bound.is_some() || self.commands.contains(name) {
+                // Leave the command line before running, as vim would
+                editing.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE), buffer)?;
+                self.commands.registers_mut().set_last_command(line.clone());
+                let result = match bound {
+                    Some(name) => self.commands.execute(&name, &CommandArgs::new(), buffer, EditorMode::Normal),
+                    None => self.commands.execute_line(&line, buffer, EditorMode::Normal),
+                };
+                return self.apply_command_outcome(result, buffer, editing);
+            }
+        }
+        
+        // Registers, marks, macros, operators and blocks are the editing mode's own grammar
+        let context = self.grammar_context(key, mode, buffer);
+        if let Some(action) = editing.handle_grammar_key(key, buffer, context)? {
+            return self.run_grammar_action(action, buffer, editing);
+        }
+        if let Some(count) = editing.take_count() {
+            self.count = Some(count);
+        }
+        
+        // A search typed on the mode's command line is a jump too, remembered once it moves the cursor
+        let search = mode == EditorMode::Command
+            && key.code == KeyCode::Enter
+            && editing.command_line().is_some_and(|line| line.starts_with(['/', '?']));
+        let cursor = buffer.cursor_position();
+        
+        let actions = self.keybindings.feed(mode, key, Instant::now());
+        let quit = self.run_key_actions(actions, buffer, editing)?;
+        if !self.keybindings.is_pending() {
+            self.count = None;
+        }
+        let context = self.grammar_context(key, mode, buffer);
+        editing.key_handled(key, mode, buffer, context);
+        if search {
+            self.record_jump(cursor, buffer);
+        }
+        Ok(quit)
+    }
+    
+    /// Lend the editing mode's grammar the registers and what it needs to know of the interface
+    fn grammar_context(&mut self, key: KeyEvent, mode: EditorMode, buffer: &Buffer) -> GrammarContext<'_> {
+        let env = self.motion_env(buffer);
+        let bound = self.keybindings.trie(mode).is_some_and(|trie| trie.lookup(&[KeyPress::from_event(key)]) != KeyMatch::None);
+        GrammarContext {
+            env,
+            keys_pending: self.keybindings.is_pending(),
+            bound,
+            recording: self.macros.recording().is_some(),
+            registers: self.commands.registers_mut(),
+            text_objects: self.structural.provider_mut(),
+        }
+    }
+    
+    /// Do what the editing mode's grammar asked for after taking a key; returns whether to quit
+    fn run_grammar_action(&mut self, action: GrammarAction, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        match action {
+            GrammarAction::Done => {}
+            GrammarAction::Quit => return Ok(true),
+            GrammarAction::Failed(message) => {
+                if message.is_some() {
+                    self.command_message = message;
+                }
+                self.key_failed = true;
+            }
+            GrammarAction::Jumped(before) => self.record_jump(before, buffer),
+            GrammarAction::Command(name, args) => return self.execute_command(&name, &args, buffer, editing),
+            GrammarAction::OpenCommandLine(range) => {
+                self.completion.cancel();
+                self.command_line.open(&range);
+            }
+            GrammarAction::StartRecording(name) => {
+                if let Err(e) = self.macros.start(name) {
+                    self.command_message = Some(e.to_string());
+                }
+            }
+            GrammarAction::StopRecording => {
+                if let Err(e) = self.macros.stop(self.commands.registers_mut()) {
+                    self.command_message = Some(e.to_string());
+                }
+            }
+            GrammarAction::PlayMacro { register, count } => return self.play_macro(register, count, buffer, editing),
+        }
+        Ok(false)
+    }
+    
+    /// Get the visible lines and indentation motions and operators use
+    fn motion_env(&self, buffer: &Buffer) -> MotionEnv {
+        let height = self.terminal_size()
+            .map_or(buffer.line_count(), |(_, height)| height as usize)
+            .saturating_sub(3)
+            .saturating_sub(self.diagnostics_list_height(buffer) as usize);
+        let (tab_size, insert_spaces) = self.config.as_ref()
+            .map_or((4, true), |config| (config.editor.tab_size, config.editor.insert_spaces));
+        MotionEnv { top: self.scroll_offset, height, tab_size, insert_spaces }
+    }
+    
+    /// Remember where the cursor was before a jump, if it moved
+    fn record_jump(&mut self, before: (usize, usize), buffer: &Buffer) {
+        if buffer.cursor_position() != before {
+            let location = Location { position: Position::new(before.0, before.1), ..Location::cursor(buffer) };
+            self.commands.marks_mut().push_jump(location);
+        }
+    }
+    
+    /// Play the macro in a register `count` times without redrawing, stopping at the first failing key
+    ///
+    /// A macro played from inside another, including itself, has its keys
+    /// played next in the same run.
+    fn play_macro(&mut self, name: char, count: usize, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        if name == ':' {
+            // `@:` repeats the last command line
+            let line = self.commands.registers().get(Some(':'), buffer)?.map(|content| content.text).unwrap_or_default();
+            for _ in 0..count {
+                let result = self.commands.execute_line(&line, buffer, EditorMode::Normal);
+                if self.apply_command_outcome(result, buffer, editing)? {
+                    return Ok(true);
+                }
+            }
+            return Ok(false);
+        }
+        
+        let keys = match self.macros.keys(name, self.commands.registers(), buffer) {
+            Ok(keys) => keys.repeat(count),
+            Err(e) => {
+                self.command_message = Some(e.to_string());
+                self.key_failed = true;
+                return Ok(false);
+            }
+        };
+        if self.replaying {
+            for key in keys.into_iter().rev() {
+                self.replay.push_front(key);
+            }
+            return Ok(false);
+        }
+        self.play_keys(keys, buffer, editing)
+    }
+    
+    /// Play keys without redrawing, stopping at the first failing key; returns whether to quit
+    ///
+    /// Keys still to play from a macro playing these wait until they are done.
+    fn play_keys(&mut self, keys: Vec<KeyEvent>, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        let outer = std::mem::replace(&mut self.replay, keys.into());
+        let replaying = std::mem::replace(&mut self.replaying, true);
+        let mut played = 0;
+        let result = loop {
+            let Some(key) = self.replay.pop_front() else {
+                break Ok(false);
+            };
+            played += 1;
+            if played > MACRO_KEY_LIMIT {
+                self.command_message = Some(format!("Macro stopped after {} keys", MACRO_KEY_LIMIT));
+                break Ok(false);
+            }
+            self.key_failed = false;
+            match self.dispatch_key(key, buffer, editing) {
+                Ok(false) => {}
+                quit_or_error => break quit_or_error,
+            }
+            // Opening another file ends the macro, as its keys were meant for this buffer
+            if self.key_failed || self.open_request.is_some() || self.rename_request.is_some() || self.close_request {
+                break Ok(false);
+            }
+        };
+        self.replay = outer;
+        self.replaying = replaying;
+        result
+    }
+    
+    /// Type keys in normal mode at the start of each line, or once where the cursor is,
+    /// ending with `Esc` whatever the keys left unfinished; returns whether to quit
+    ///
+    /// The lines are followed through the edits the keys make, so a line
+    /// deleted while typing on an earlier one is skipped.
+    fn run_normal(&mut self, lines: Option<Vec<usize>>, keys: Vec<KeyEvent>, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        let escape = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        let play = |tui: &mut Self, buffer: &mut Buffer, editing: &mut dyn EditingMode| -> Result<bool> {
+            let quit = tui.play_keys(keys.clone(), buffer, editing)?;
+            if !quit && (editing.editor_mode() != EditorMode::Normal || editing.pending_keys().is_some() || tui.command_line.is_open()) {
+                return tui.dispatch_key(escape, buffer, editing);
+            }
+            Ok(quit)
+        };
+        let Some(lines) = lines else {
+            return play(self, buffer, editing);
+        };
+        
+        let following = !buffer.marks().is_tracking();
+        if following {
+            buffer.marks_mut().track_lines(&lines);
+        }
+        let mut quit = Ok(false);
+        for (index, line) in lines.iter().enumerate() {
+            let line = if following { buffer.marks().tracked_line(index) } else { Some(*line) };
+            let Some(line) = line.filter(|line| *line < buffer.line_count()) else {
+                continue;
+            };
+            buffer.set_cursor_position(line, 0);
+            quit = play(self, buffer, editing);
+            if !matches!(quit, Ok(false)) {
+                break;
+            }
+        }
+        if following {
+            buffer.marks_mut().untrack_lines();
+        }
+        quit
+    }
+    
+    /// Pass a key to the open command line, running the line on Enter; returns whether to quit
+    fn handle_command_line_key(&mut self, key: KeyEvent, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        let sources = CompletionSources {
+            commands: &self.commands,
+            settings: &self.setting_names,
+            buffers: &self.buffer_names,
+        };
+        match self.command_line.handle_key(key, &sources) {
+            CommandLineEvent::Run(line) => self.run_command_line(&line, buffer, editing),
+            CommandLineEvent::Cancelled | CommandLineEvent::Editing => Ok(false),
+        }
+    }
+    
+    /// Run a line typed on the command line: bound lines (`:wq`) and
+    /// registered commands go through the command system, anything else to
+    /// the editing mode's own command line. Returns whether to quit.
+    fn run_command_line(&mut self, line: &str, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(false);
+        }
+        if let Err(e) = self.command_line.remember(line) {
+            self.command_message = Some(e.to_string());
+        }
+        self.commands.registers_mut().set_last_command(line);
+        
+        if let Some(name) = self.keybindings.command_line(line).map(str::to_string) {
+            return self.execute_command(&name, &CommandArgs::new(), buffer, editing);
+        }
+        if self.commands.is_command_line(line) {
+            let result = self.commands.execute_line(line, buffer, EditorMode::Normal);
+            return self.apply_command_outcome(result, buffer, editing);
+        }
+        if !editing.uses_keybindings() {
+            self.command_message = Some(format!("Not an editor command: {}", line));
+            return Ok(false);
+        }
+        let keys = std::iter::once(':').chain(line.chars())
+            .map(|c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+            .chain(std::iter::once(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)));
+        let mut quit = false;
+        for key in keys {
+            quit |= editing.handle_key(key, buffer)?;
+        }
+        Ok(self.run_mode_requests(buffer, editing)? || quit)
+    }
+    
+    /// Send a typed or replayed key to whatever has focus, returning whether to quit
+    fn dispatch_key(&mut self, key: KeyEvent, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        if self.info.is_some() {
+            // Any key closes the info popup
+            self.info = None;
+        } else if self.command_line.is_open() {
+            // The command line takes every key while it is open
+            return self.handle_command_line_key(key, buffer, editing);
+        } else if self.finder.is_open() {
+            // The file finder takes every key while it is open; chosen files open by their relative path
+            self.open_request = self.finder.handle_key(key).map(Self::relative_to_cwd);
+        } else if self.palette.is_open() || CommandPalette::is_open_key(key) {
+            // The command palette takes every key while it is open
+            return self.handle_palette_key(key, buffer, editing);
+        } else if self.explorer.is_focused() {
+            // The file explorer takes every key while it has focus
+            self.handle_explorer_key(key);
+        } else if self.lsp.is_prompting() {
+            // A rename or code action prompt takes every key until it is answered
+            self.lsp.handle_prompt_key(key, buffer);
+        } else {
+            // Snippet and completion keys are handled here, everything else goes to the editing mode;
+            // keys finishing a sequence the grammar or a binding holds, like the `w` of `dw`, are never theirs
+            let holding = editing.pending_keys().is_some() || self.keybindings.is_pending();
+            let consumed = !holding
+                && (self.snippets.handle_key(key, buffer, editing, &mut self.completion, self.commands.registers().clipboard())
+                    || self.completion.handle_key(key, buffer, editing, self.lsp.manager_mut()));
+            // Bound keys and registered commands run through the command system
+            if !consumed && self.handle_command_key(key, buffer, editing)? {
+                return Ok(true);
+            }
+            self.snippets.after_key(buffer);
+            if !consumed {
+                self.completion.after_key(buffer, editing, self.lsp.manager_mut());
+            }
+            
+            // The buffer refuses edits while it is read-only; say why nothing changed, and stop a playing macro
+            if buffer.take_refused_edit() {
+                self.command_message = Some("Buffer is read-only".to_string());
+                self.key_failed = true;
+            }
+            
+            self.refresh_folds(buffer);
+        }
+        Ok(false)
+    }
+    
+    /// Open the command palette, or pass it a key and run the command it picks; returns whether to quit
+    fn handle_palette_key(&mut self, key: KeyEvent, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        if !self.palette.is_open() {
+            return self.open_palette(buffer, editing);
+        }
+        
+        let (name, args) = match self.palette.handle_key(key, &self.commands) {
+            Ok(Some(chosen)) => chosen,
+            Ok(None) => return Ok(false),
+            Err(e) => {
+                self.command_message = Some(e.to_string());
+                return Ok(false);
+            }
+        };
+        self.execute_command(&name, &args, buffer, editing)
+    }
+    
+    /// Show the command palette, returning whether a key held for a longer binding quit
+    fn open_palette(&mut self, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        // Keys held for a longer binding are done with
+        let actions = self.keybindings.flush();
+        if self.run_key_actions(actions, buffer, editing)? {
+            return Ok(true);
+        }
+        self.completion.cancel();
+        self.palette.open(&self.commands, &self.keybindings, buffer, editing.editor_mode());
+        Ok(false)
+    }
+    
+    /// Run a command, letting the editing mode run it if it implements it itself; returns whether to quit
+    fn execute_command(&mut self, name: &str, args: &CommandArgs, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        let cursor = buffer.cursor_position();
+        let result = match editing.execute(name, buffer) {
+            Some(result) => result.map(|quit| CommandOutcome { quit, message: editing.take_message(), ..CommandOutcome::default() }),
+            None => self.commands.execute(name, args, buffer, editing.editor_mode()),
+        };
+        let quit = self.apply_command_outcome(result, buffer, editing)?;
+        if FAILABLE_MOTIONS.contains(&name) && buffer.cursor_position() == cursor {
+            self.key_failed = true;
+        }
+        if JUMP_COMMANDS.contains(&name) {
+            self.record_jump(cursor, buffer);
+        }
+        Ok(quit)
+    }
+    
+    /// Run the command lines and show the message the editing mode asked for, returning whether to quit
+    fn run_mode_requests(&mut self, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        if let Some(message) = editing.take_message() {
+            self.command_message = Some(message);
+        }
+        for line in editing.take_commands() {
+            // Command lines run as they would from the ex command line
+            let result = self.commands.execute_line(&line, buffer, EditorMode::Normal);
+            if self.apply_command_outcome(result, buffer, editing)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+    
+    /// Run the commands and keys resolved by the keybindings, returning whether to quit
+    fn run_key_actions(&mut self, actions: Vec<KeyAction>, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        for action in actions {
+            let quit = match action {
+                KeyAction::Command(name) => self.execute_counted(&name, buffer, editing)?,
+                KeyAction::Key(key) => {
+                    // Unbound keys are the editing mode's own, so it gets the count's digits too
+                    if let Some(count) = self.count.take() {
+                        for digit in count.to_string().chars() {
+                            editing.handle_key(KeyEvent::new(KeyCode::Char(digit), KeyModifiers::NONE), buffer)?;
+                        }
+                    }
+                    editing.handle_key(key, buffer)?
+                }
+            };
+            if quit {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+    
+    /// Run a bound command as many times as the count typed before it, or
+    /// go to the counted line for `gg` and `G`; returns whether to quit
+    fn execute_counted(&mut self, name: &str, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        // Operators take the count themselves
+        let count = if Operator::from_command(name).is_none() { self.count.take() } else { None };
+        let Some(count) = count else {
+            return self.execute_command(name, &CommandArgs::new(), buffer, editing);
+        };
+        if matches!(name, "move_file_start" | "move_file_end") {
+            let args = CommandArgs::new().with("line", ArgValue::Integer(count as i64));
+            return self.execute_command("goto_line", &args, buffer, editing);
+        }
+        // A command that leaves the mode, like deleting a selection, runs once
+        let mode = editing.editor_mode();
+        for _ in 0..count {
+            if self.execute_command(name, &CommandArgs::new(), buffer, editing)? {
+                return Ok(true);
+            }
+            if editing.editor_mode() != mode {
+                break;
+            }
+        }
+        Ok(false)
+    }
+    
+    /// Show a command's message or error and hand its keys to the editing mode, returning whether to quit
+    fn apply_command_outcome(&mut self, result: Result<CommandOutcome>, buffer: &mut Buffer, editing: &mut dyn EditingMode) -> Result<bool> {
+        let outcome = match result {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                self.command_message = Some(e.to_string());
+                self.key_failed = true;
+                return Ok(false);
+            }
+        };
+        
+        self.command_message = outcome.message;
+        if !outcome.keys.is_empty() && !editing.uses_keybindings() {
+            // The keys are vim keys, meaningless to modes with their own key maps
+            self.command_message = Some(format!("Not available in {} mode", editing.name()));
+            return Ok(outcome.quit);
+        }
+        for key in outcome.keys {
+            if editing.handle_key(key, buffer)? {
+                return Ok(true);
+            }
+        }
+        for request in outcome.requests {
+            match request {
+                EditorRequest::FindFile => {
+                    self.completion.cancel();
+                    self.finder.open(&Self::working_dir()?);
+                }
+                EditorRequest::OpenPalette => {
+                    if self.open_palette(buffer, editing)? {
+                        return Ok(true);
+                    }
+                }
+                EditorRequest::OpenFile(path) => self.open_request = Some(path),
+                EditorRequest::CloseBuffer => self.close_request = true,
+                EditorRequest::ToggleExplorer => {
+                    if self.explorer.is_focused() {
+                        self.explorer.close();
+                    } else if self.explorer.is_open() {
+                        self.explorer.set_focused(true);
+                    } else {
+                        self.explorer.open(&Self::working_dir()?);
+                    }
+                }
+                EditorRequest::ShowInfo { title, lines } => self.info = Some((title, lines)),
+                EditorRequest::SetOptions { options, local } => self.option_requests.push(OptionRequest::Set { args: options, local }),
+                EditorRequest::ShowOptionHelp { name } => self.option_requests.push(OptionRequest::Help(name)),
+                EditorRequest::ScrollCursor(place) => self.scroll_cursor(place, buffer),
+                EditorRequest::Lsp(action) => self.lsp.run(action, buffer),
+                EditorRequest::ToggleDiagnosticsList => self.show_diagnostics_list = !self.show_diagnostics_list,
+                EditorRequest::SelectSyntax(step) => self.structural.select(step, buffer, editing)?,
+                EditorRequest::GotoSyntaxObject { kind, forward } => {
+                    if !self.structural.goto_object(kind, forward, buffer) {
+                        self.key_failed = true;
+                    }
+                }
+                EditorRequest::StartOperator(operator) => {
+                    if !editing.start_operator(operator, self.count.take()) {
+                        self.command_message = Some(format!("Not available in {} mode", editing.name()));
+                    }
+                }
+                EditorRequest::StartVisualBlock => {
+                    if !editing.toggle_block(buffer)? {
+                        self.command_message = Some(format!("Not available in {} mode", editing.name()));
+                    }
+                }
+                EditorRequest::Normal { lines, keys } => {
+                    if self.run_normal(lines, keys, buffer, editing)? {
+                        return Ok(true);
+                    }
+                }
+                EditorRequest::Jump { path, position } => {
+                    self.open_request = Some(Self::relative_to_cwd(path));
+                    self.pending_cursor = Some(position);
+                }
+                EditorRequest::RevealInExplorer => {
+                    self.explorer.open(&Self::working_dir()?);
+                    if let Some(path) = buffer.file_path()
+                        && !self.explorer.reveal(Path::new(path))
+                    {
+                        self.command_message = Some(format!("{} is outside {}", path, self.explorer.root().display()));
+                    }
+                }
+            }
+        }
+        Ok(outcome.quit)
+    }
+    
+    /// Pass a key to the focused file explorer, opening chosen files and reporting renames
+    fn handle_explorer_key(&mut self, key: KeyEvent) {
+        match self.explorer.handle_key(key) {
+            Ok(Some(ExplorerEvent::Open(path))) => self.open_request = Some(Self::relative_to_cwd(path)),
+            Ok(Some(ExplorerEvent::Renamed { from, to })) => {
+                self.command_message = Some(format!("Moved {} to {}", from.display(), to.display()));
+                self.rename_request = Some((from, to));
+            }
+            Ok(Some(ExplorerEvent::Message(message))) => self.command_message = Some(message),
+            Ok(None) => {}
+            Err(e) => self.command_message = Some(e.to_string()),
+        }
+    }
+    
+    fn working_dir() -> Result<PathBuf> {
+        std::env::current_dir().map_err(|e| RuskError::io_string(format!("Failed to get working directory: {}", e)))
+    }
+    
+    /// Shorten a path under the working directory to a relative one
+    fn relative_to_cwd(path: PathBuf) -> PathBuf {
+        std::env::current_dir().ok()
+            .and_then(|cwd| path.strip_prefix(cwd).ok().map(PathBuf::from))
+            .unwrap_or(path)
+    }
+    
+    /// Show a message in the status line
+    pub fn show_message(&mut self, message: impl Into<String>) {
+        self.status_message = message.into();
+    }
+    
     /// Update status message
-    fn update_status_message(&mut self, buffer: &Buffer, _vim: &VimPlugin) {
+    fn update_status_message(&mut self, buffer: &Buffer, _editing: &dyn EditingMode) {
         // Clear status message after some time or update with current info
         self.status_message.clear();
         
         // Add any relevant status information
-        if buffer.is_modified() {
+        let (line, col) = buffer.cursor_position();
+        if let Some(diagnostic) = buffer.diagnostics().at(Position::new(line, col)) {
+            self.status_message = diagnostic.message.clone();
+        } else if let Some(session) = self.snippets.session() {
+            self.status_message = format!("Snippet: tabstop {} of {}", session.current() + 1, session.stops().len() - 1);
+        } else if buffer.is_modified() {
             self.status_message = "Modified".to_string();
         }
+        
+        if let Some(name) = self.macros.recording() {
+            self.status_message = match self.status_message.as_str() {
+                "" => format!("recording @{}", name),
+                message => format!("recording @{} | {}", name, message),
+            };
+        }
     }
     
     /// Set a temporary status message
@@ -482,7 +2311,78 @@ impl Plugin for TuiPlugin {
     
     fn shutdown(&mut self) -> Result<()> {
         println!("Shutting down TUI plugin");
+        self.lsp.shutdown();
         self.restore_terminal()?;
         Ok(())
     }
-}
\ No newline at end of file
+    
+    fn dependencies(&self) -> Vec<String> {
+        self.editing_mode.iter().cloned().collect()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::KeyBindings;
+    use crate::plugins::implementations::editing::mode::VimEditing;
+    
+    fn press(tui: &mut TuiPlugin, keys: &str, buffer: &mut Buffer, editing: &mut dyn EditingMode) {
+        for ch in keys.chars() {
+            tui.dispatch_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE), buffer, editing).unwrap();
+        }
+    }
+    
+    #[test]
+    fn test_operators_through_dispatch() {
+        let mut tui = TuiPlugin::new();
+        tui.keybindings.load(&KeyBindings::default()).unwrap();
+        let mut editing = VimEditing::new();
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::new(0, 0), "one two\nthree");
+        buffer.set_cursor_position(0, 0);
+        
+        press(&mut tui, "dw", &mut buffer, &mut editing);
+        assert_eq!(buffer.lines(), ["two", "three"]);
+        assert_eq!(tui.commands.registers().get(None, &buffer).unwrap().unwrap().text, "one ");
+        
+        press(&mut tui, "yy", &mut buffer, &mut editing);
+        let yanked = tui.commands.registers().get(Some('0'), &buffer).unwrap().unwrap();
+        assert_eq!((yanked.text.as_str(), yanked.linewise), ("two", true));
+        
+        buffer.set_cursor_position(1, 0);
+        press(&mut tui, "dd", &mut buffer, &mut editing);
+        assert_eq!(buffer.lines(), ["two"]);
+        assert!(editing.pending_keys().is_none());
+    }
+    
+    #[test]
+    fn test_wrapped_lines_take_rows_of_their_own() {
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::new(0, 0), "abcdefghij\nxy\n0123456789abcd");
+        let view = ViewOptions { line_numbers: false, wrap: true, whitespace: false, cursor_line: false };
+        
+        assert_eq!(TuiPlugin::line_rows(&buffer, 0, 4, view), 3);
+        assert_eq!(TuiPlugin::line_rows(&buffer, 1, 4, view), 1);
+        assert_eq!(TuiPlugin::screen_position(&buffer, 0, Position::new(2, 9), 4, view), (6, 1));
+        assert_eq!(TuiPlugin::screen_position(&buffer, 1, Position::new(2, 9), 4, view), (3, 1));
+        
+        let rows = TuiPlugin::wrap_spans(vec![Span::raw("ab"), Span::raw("cdefg")], 4);
+        let rows: Vec<Vec<&str>> = rows.iter().map(|row| row.iter().map(|span| span.content.as_ref()).collect()).collect();
+        assert_eq!(rows, [vec!["ab", "cd"], vec!["efg"]]);
+        
+        // Without wrapping every line is one row, however long
+        let view = ViewOptions { wrap: false, ..view };
+        assert_eq!(TuiPlugin::screen_position(&buffer, 0, Position::new(2, 9), 4, view), (2, 9));
+    }
+    
+    #[test]
+    fn test_palette_opens_from_its_binding() {
+        let mut tui = TuiPlugin::new();
+        tui.keybindings.load(&KeyBindings::default()).unwrap();
+        let mut editing = VimEditing::new();
+        let mut buffer = Buffer::new();
+        
+        press(&mut tui, "\\p", &mut buffer, &mut editing);
+        assert!(tui.palette.is_open());
+    }
+}
@@ -5,7 +5,6 @@
 //! and other functionality.
 
 pub mod registry;
-pub mod events;
 pub mod implementations;
 
 // Re-export plugin traits
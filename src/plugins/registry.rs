@@ -1,4 +1,9 @@
-use crate::Result;
+use crate::{Config, Result, RuskError};
+use crate::core::state::PropertySpec;
+use crate::plugins::implementations::editing::emacs::EmacsPlugin;
+use crate::plugins::implementations::editing::helix::HelixPlugin;
+use crate::plugins::implementations::editing::mode::{EditingMode, VimEditing};
+use crate::plugins::implementations::ui::tui::TuiPlugin;
 
 /// Trait that all plugins must implement
 pub trait Plugin {
@@ -13,38 +18,478 @@ pub trait Plugin {
     
     /// Shutdown the plugin
     fn shutdown(&mut self) -> Result<()>;
+    
+    /// Properties the plugin reads, registered when the editor starts so `:set` can change them
+    fn properties(&self) -> Vec<PropertySpec> {
+        Vec::new()
+    }
+    
+    /// Names of the plugins that have to be initialized before this one
+    fn dependencies(&self) -> Vec<String> {
+        Vec::new()
+    }
+    
+    /// Apply the plugin's `[plugins.plugin_configs.<name>]` table, before it is initialized
+    fn configure(&mut self, _settings: &toml::Value) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Kind of plugin, which decides how the editor drives it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginKind {
+    /// Draws the editor and reads keys; one has to be enabled
+    Ui,
+    /// Turns keys into edits; the one `editor.editing_mode` names runs
+    EditingMode,
+    Extension,
+}
+
+/// A created plugin, typed by what the editor does with it
+pub enum PluginInstance {
+    Ui(Box<TuiPlugin>),
+    EditingMode(Box<dyn EditingMode>),
+    Extension(Box<dyn Plugin>),
+}
+
+impl PluginInstance {
+    fn plugin(&self) -> &dyn Plugin {
+        match self {
+            Self::Ui(ui) => ui.as_ref(),
+            Self::EditingMode(mode) => mode.as_ref(),
+            Self::Extension(plugin) => plugin.as_ref(),
+        }
+    }
+    
+    fn plugin_mut(&mut self) -> &mut dyn Plugin {
+        match self {
+            Self::Ui(ui) => ui.as_mut(),
+            Self::EditingMode(mode) => mode.as_mut(),
+            Self::Extension(plugin) => plugin.as_mut(),
+        }
+    }
+}
+
+/// Entry of the factory table, creating a plugin by name
+pub struct PluginFactory {
+    pub name: &'static str,
+    pub kind: PluginKind,
+    pub description: &'static str,
+    create: fn(&Config) -> PluginInstance,
+}
+
+impl PluginFactory {
+    pub fn new(name: &'static str, kind: PluginKind, description: &'static str, create: fn(&Config) -> PluginInstance) -> Self {
+        Self { name, kind, description, create }
+    }
+}
+
+/// Names older configurations enable for parts of the editor that are no longer plugins
+const BUILT_INTO_EDITOR: &[&str] = &["syntax", "file_io"];
+
+/// The plugins that come with the editor
+fn builtin_factories() -> Vec<PluginFactory> {
+    vec![
+        PluginFactory::new("tui", PluginKind::Ui, "Terminal user interface", |config| {
+            let mut tui = TuiPlugin::new();
+            tui.set_config(config.clone());
+            PluginInstance::Ui(Box::new(tui))
+        }),
+        PluginFactory::new("vim", PluginKind::EditingMode, "Vim style modal editing", |config| editing_mode(VimEditing::new(), config)),
+        PluginFactory::new("emacs", PluginKind::EditingMode, "Emacs style editing", |config| editing_mode(EmacsPlugin::new(), config)),
+        PluginFactory::new("helix", PluginKind::EditingMode, "Helix style selection-first editing", |config| editing_mode(HelixPlugin::new(), config)),
+    ]
+}
+
+fn editing_mode(mut mode: impl EditingMode + 'static, config: &Config) -> PluginInstance {
+    mode.set_config(config);
+    PluginInstance::EditingMode(Box::new(mode))
+}
+
+/// Plugins a configuration loads, worked out without creating them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadPlan {
+    /// Editing mode that runs, also the first of `plugins`
+    pub editing_mode: String,
+    /// Names of the plugins to create, in the order they are created
+    pub plugins: Vec<String>,
+    /// Problems to tell the user about that do not stop the editor starting
+    pub notices: Vec<String>,
+}
+
+struct LoadedPlugin {
+    instance: PluginInstance,
+    initialized: bool,
 }
 
 /// Plugin registry that manages all loaded plugins
+///
+/// Plugins are created by name from a factory table, for the names in
+/// `plugins.enabled_plugins`. Of the enabled editing modes only the one
+/// `editor.editing_mode` names is created, and it comes first, so the user
+/// interface handing it keys starts after it and stops before it.
 pub struct PluginRegistry {
-    plugins: Vec<Box<dyn Plugin>>,
+    factories: Vec<PluginFactory>,
+    plugins: Vec<LoadedPlugin>,
+}
+
+impl Default for PluginRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PluginRegistry {
     /// Create a new plugin registry
     pub fn new() -> Self {
+        Self::with_factories(builtin_factories())
+    }
+    
+    /// Create a registry creating plugins from another factory table
+    pub fn with_factories(factories: Vec<PluginFactory>) -> Self {
         Self {
+            factories,
             plugins: Vec::new(),
         }
     }
     
+    /// Get the factory table, the plugins that can be enabled
+    pub fn factories(&self) -> &[PluginFactory] {
+        &self.factories
+    }
+    
+    /// Look up a plugin in the factory table
+    pub fn factory(&self, name: &str) -> Option<&PluginFactory> {
+        self.factories.iter().find(|factory| factory.name == name)
+    }
+    
+    /// Work out which plugins a configuration loads
+    ///
+    /// Unknown names and a disabled editing mode, replaced by the first
+    /// enabled one, are notices; names of what is now part of the editor
+    /// are skipped. Fails when no user interface or no editing mode is
+    /// enabled.
+    pub fn plan(&self, config: &Config) -> Result<LoadPlan> {
+        let mut notices = Vec::new();
+        let mut enabled: Vec<&PluginFactory> = Vec::new();
+        for name in &config.plugins.enabled_plugins {
+            match self.factory(name) {
+                Some(factory) if !enabled.iter().any(|known| known.name == factory.name) => enabled.push(factory),
+                Some(_) => {}
+                None if BUILT_INTO_EDITOR.contains(&name.as_str()) => {}
+                None => notices.push(format!("Unknown plugin: {}", name)),
+            }
+        }
+        
+        let wanted = config.editor.editing_mode().name();
+        let modes: Vec<&str> = enabled.iter()
+            .filter(|factory| factory.kind == PluginKind::EditingMode)
+            .map(|factory| factory.name)
+            .collect();
+        let editing_mode = if modes.contains(&wanted) {
+            wanted
+        } else if let Some(first) = modes.first() {
+            notices.push(format!("Editing mode {} is not enabled; using {}", wanted, first));
+            first
+        } else {
+            return Err(RuskError::Plugin(format!("Editing mode {} is not enabled, nor is any other", wanted)));
+        };
+        if !enabled.iter().any(|factory| factory.kind == PluginKind::Ui) {
+            return Err(RuskError::Plugin("No user interface plugin is enabled; enable tui".to_string()));
+        }
+        
+        let plugins = std::iter::once(editing_mode)
+            .chain(enabled.iter().filter(|factory| factory.kind != PluginKind::EditingMode).map(|factory| factory.name))
+            .map(str::to_string)
+            .collect();
+        Ok(LoadPlan { editing_mode: editing_mode.to_string(), plugins, notices })
+    }
+    
+    /// Create and configure the plugins a configuration enables, returning the notices of its plan
+    pub fn load(&mut self, config: &Config) -> Result<Vec<String>> {
+        let plan = self.plan(config)?;
+        for name in &plan.plugins {
+            let mut instance = self.create(name, config)?;
+            if let PluginInstance::Ui(ui) = &mut instance {
+                ui.set_editing_mode(&plan.editing_mode);
+            }
+            self.plugins.push(LoadedPlugin { instance, initialized: false });
+        }
+        Ok(plan.notices)
+    }
+    
+    /// Create a plugin from the factory table and apply its `plugin_configs` table
+    fn create(&self, name: &str, config: &Config) -> Result<PluginInstance> {
+        let factory = self.factory(name)
+            .ok_or_else(|| RuskError::Plugin(format!("Unknown plugin: {}", name)))?;
+        let mut instance = (factory.create)(config);
+        if let Some(settings) = config.plugins.plugin_configs.get(name) {
+            if !settings.is_table() {
+                return Err(RuskError::Config(format!("plugins.plugin_configs.{} must be a table", name)));
+            }
+            instance.plugin_mut().configure(settings)?;
+        }
+        Ok(instance)
+    }
+    
     /// Register a new plugin
     pub fn register(&mut self, plugin: Box<dyn Plugin>) -> Result<()> {
         println!("Registering plugin: {}", plugin.name());
-        self.plugins.push(plugin);
+        self.plugins.push(LoadedPlugin { instance: PluginInstance::Extension(plugin), initialized: false });
         Ok(())
     }
     
-    /// Initialize all registered plugins
+    /// Initialize all registered plugins, each after the plugins it depends on
+    ///
+    /// Plugins without dependencies between them start in the order they
+    /// were loaded. Fails on a missing dependency or a cycle before starting
+    /// any plugin.
     pub fn initialize_all(&mut self) -> Result<()> {
+        self.sort_by_dependencies()?;
         for plugin in &mut self.plugins {
-            plugin.initialize()?;
+            if !plugin.initialized {
+                plugin.instance.plugin_mut().initialize()?;
+                plugin.initialized = true;
+            }
         }
         Ok(())
     }
     
+    /// Shut the initialized plugins down in the reverse of the order they started
+    ///
+    /// Every plugin is shut down even when one fails; the first failure is returned.
+    pub fn shutdown_all(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for plugin in self.plugins.iter_mut().rev().filter(|plugin| plugin.initialized) {
+            plugin.initialized = false;
+            if let Err(e) = plugin.instance.plugin_mut().shutdown()
+                && result.is_ok()
+            {
+                result = Err(e);
+            }
+        }
+        result
+    }
+    
+    fn sort_by_dependencies(&mut self) -> Result<()> {
+        let names: Vec<String> = self.plugins.iter().map(|plugin| plugin.instance.plugin().name().to_string()).collect();
+        let dependencies: Vec<Vec<String>> = self.plugins.iter().map(|plugin| plugin.instance.plugin().dependencies()).collect();
+        let mut sort = DependencySort { names: &names, dependencies: &dependencies, done: vec![false; names.len()], path: Vec::new(), order: Vec::new() };
+        for index in 0..names.len() {
+            sort.visit(index)?;
+        }
+        
+        let mut rank = vec![0; names.len()];
+        for (position, &index) in sort.order.iter().enumerate() {
+            rank[index] = position;
+        }
+        let mut plugins: Vec<(usize, LoadedPlugin)> = std::mem::take(&mut self.plugins).into_iter().enumerate().collect();
+        plugins.sort_by_key(|(index, _)| rank[*index]);
+        self.plugins = plugins.into_iter().map(|(_, plugin)| plugin).collect();
+        Ok(())
+    }
+    
+    /// Switch to the editing mode a reloaded configuration chooses, returning whether it changed
+    ///
+    /// The running mode gets the new configuration when it stays. Other
+    /// changes to the enabled plugins apply when the editor next starts.
+    pub fn reload_editing_mode(&mut self, config: &Config) -> Result<bool> {
+        let plan = self.plan(config)?;
+        let index = self.plugins.iter()
+            .position(|plugin| matches!(plugin.instance, PluginInstance::EditingMode(_)))
+            .ok_or_else(|| RuskError::Plugin("No editing mode is loaded".to_string()))?;
+        if let PluginInstance::EditingMode(mode) = &mut self.plugins[index].instance
+            && mode.name() == plan.editing_mode
+        {
+            mode.set_config(config);
+            return Ok(false);
+        }
+        
+        let instance = self.create(&plan.editing_mode, config)?;
+        let plugin = &mut self.plugins[index];
+        let running = plugin.initialized;
+        if running {
+            let _ = plugin.instance.plugin_mut().shutdown();
+        }
+        plugin.instance = instance;
+        plugin.initialized = false;
+        if running {
+            plugin.instance.plugin_mut().initialize()?;
+            plugin.initialized = true;
+        }
+        self.ui_mut()?.set_editing_mode(&plan.editing_mode);
+        Ok(true)
+    }
+    
+    /// Get the user interface and the editing mode it hands keys to
+    pub fn ui_and_editing_mode_mut(&mut self) -> Result<(&mut TuiPlugin, &mut dyn EditingMode)> {
+        let mut ui = None;
+        let mut editing: Option<&mut dyn EditingMode> = None;
+        for plugin in &mut self.plugins {
+            match &mut plugin.instance {
+                PluginInstance::Ui(tui) if ui.is_none() => ui = Some(tui.as_mut()),
+                PluginInstance::EditingMode(mode) if editing.is_none() => editing = Some(mode.as_mut()),
+                _ => {}
+            }
+        }
+        ui.zip(editing).ok_or_else(|| RuskError::Plugin("No user interface or editing mode is loaded".to_string()))
+    }
+    
+    /// Get the user interface
+    pub fn ui_mut(&mut self) -> Result<&mut TuiPlugin> {
+        self.ui_and_editing_mode_mut().map(|(ui, _)| ui)
+    }
+    
+    /// Get the editing mode
+    pub fn editing_mode_mut(&mut self) -> Result<&mut dyn EditingMode> {
+        self.ui_and_editing_mode_mut().map(|(_, editing)| editing)
+    }
+    
+    /// Properties every loaded plugin reads
+    pub fn properties(&self) -> Vec<PropertySpec> {
+        self.plugins.iter().flat_map(|plugin| plugin.instance.plugin().properties()).collect()
+    }
+    
+    /// Names of the loaded plugins, in the order they start
+    pub fn names(&self) -> Vec<&str> {
+        self.plugins.iter().map(|plugin| plugin.instance.plugin().name()).collect()
+    }
+    
     /// Get the number of registered plugins
     pub fn plugin_count(&self) -> usize {
         self.plugins.len()
     }
-}
\ No newline at end of file
+}
+
+/// Depth-first walk putting each plugin after its dependencies
+struct DependencySort<'a> {
+    names: &'a [String],
+    dependencies: &'a [Vec<String>],
+    done: Vec<bool>,
+    /// Plugins whose dependencies are being visited, to notice cycles
+    path: Vec<usize>,
+    order: Vec<usize>,
+}
+
+impl DependencySort<'_> {
+    fn visit(&mut self, index: usize) -> Result<()> {
+        if self.done[index] {
+            return Ok(());
+        }
+        if let Some(start) = self.path.iter().position(|&visiting| visiting == index) {
+            let cycle: Vec<&str> = self.path[start..].iter().chain([&index]).map(|&i| self.names[i].as_str()).collect();
+            return Err(RuskError::Plugin(format!("Plugins depend on each other in a cycle: {}", cycle.join(" -> "))));
+        }
+        self.path.push(index);
+        for dependency in &self.dependencies[index] {
+            let Some(needed) = self.names.iter().position(|name| name == dependency) else {
+                return Err(RuskError::Plugin(format!("Plugin {} needs {}, which is not enabled", self.names[index], dependency)));
+            };
+            self.visit(needed)?;
+        }
+        self.path.pop();
+        self.done[index] = true;
+        self.order.push(index);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::EditingModeKind;
+    use std::sync::{Arc, Mutex};
+    
+    struct Recorder {
+        name: &'static str,
+        needs: Vec<&'static str>,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+    
+    impl Plugin for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+        
+        fn version(&self) -> &str {
+            "0.1.0"
+        }
+        
+        fn initialize(&mut self) -> Result<()> {
+            self.log.lock().unwrap().push(format!("start {}", self.name));
+            Ok(())
+        }
+        
+        fn shutdown(&mut self) -> Result<()> {
+            self.log.lock().unwrap().push(format!("stop {}", self.name));
+            Ok(())
+        }
+        
+        fn dependencies(&self) -> Vec<String> {
+            self.needs.iter().map(|name| name.to_string()).collect()
+        }
+    }
+    
+    #[test]
+    fn test_dependency_order_and_plans() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name, needs: &[&'static str]| Box::new(Recorder { name, needs: needs.to_vec(), log: log.clone() });
+        let mut registry = PluginRegistry::with_factories(Vec::new());
+        registry.register(recorder("outline", &["lsp", "syntax"])).unwrap();
+        registry.register(recorder("lsp", &["syntax"])).unwrap();
+        registry.register(recorder("syntax", &[])).unwrap();
+        registry.initialize_all().unwrap();
+        registry.shutdown_all().unwrap();
+        assert_eq!(*log.lock().unwrap(), vec![
+            "start syntax", "start lsp", "start outline", "stop outline", "stop lsp", "stop syntax",
+        ]);
+        
+        registry.register(recorder("git", &["blame"])).unwrap();
+        registry.register(recorder("blame", &["git"])).unwrap();
+        let error = registry.initialize_all().unwrap_err();
+        assert!(error.to_string().contains("cycle: git -> blame -> git"));
+        
+        // The configured editing mode runs only when enabled
+        let registry = PluginRegistry::new();
+        let mut config = Config::default();
+        config.editor.editing_mode = Some(EditingModeKind::Helix);
+        config.plugins.enabled_plugins = ["minimap", "tui", "emacs", "vim"].map(String::from).to_vec();
+        let plan = registry.plan(&config).unwrap();
+        assert_eq!(plan.plugins, vec!["emacs", "tui"]);
+        assert_eq!(plan.notices, vec!["Unknown plugin: minimap", "Editing mode helix is not enabled; using emacs"]);
+        config.plugins.enabled_plugins.retain(|name| name != "tui");
+        assert!(registry.plan(&config).is_err());
+    }
+    
+    #[test]
+    fn test_legacy_plugin_names_are_skipped() {
+        // The defaults of older versions, before syntax and file handling became part of the editor
+        let mut config = Config::default();
+        config.editor.editing_mode = Some(EditingModeKind::Vim);
+        config.plugins.enabled_plugins = ["vim", "tui", "syntax", "file_io"].map(String::from).to_vec();
+        let plan = PluginRegistry::new().plan(&config).unwrap();
+        assert_eq!(plan.plugins, vec!["vim", "tui"]);
+        assert!(plan.notices.is_empty());
+    }
+    
+    #[test]
+    fn test_builtins_take_their_config_table() {
+        let mut config = Config::default();
+        config.editor.editing_mode = Some(EditingModeKind::Emacs);
+        let settings = |text: &str| toml::from_str::<toml::Value>(text).unwrap();
+        config.plugins.plugin_configs.insert("emacs".to_string(), settings("kill_ring_max = 2"));
+        let mut registry = PluginRegistry::new();
+        assert!(registry.load(&config).unwrap().is_empty());
+        assert_eq!(registry.names(), vec!["emacs", "tui"]);
+        
+        // The user interface starts after the mode it hands keys to
+        assert_eq!(registry.ui_mut().unwrap().dependencies(), vec!["emacs"]);
+        
+        config.plugins.plugin_configs.insert("emacs".to_string(), settings("kill_ring_max = 0"));
+        let error = PluginRegistry::new().load(&config).unwrap_err();
+        assert!(error.to_string().contains("kill_ring_max must be a positive integer"));
+        config.plugins.plugin_configs.insert("emacs".to_string(), settings("kill_ring = 2"));
+        let error = PluginRegistry::new().load(&config).unwrap_err();
+        assert!(error.to_string().contains("Unknown emacs setting: kill_ring"));
+    }
+}
@@ -0,0 +1,161 @@
+//! System clipboard access
+//!
+//! Text is copied with the OSC 52 escape sequence, which terminals hand to
+//! the system clipboard even over ssh, or with external commands such as
+//! `wl-copy` and `xclip -selection clipboard` when they are configured.
+
+use crate::{Config, Result, RuskError};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// How the clipboard is reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardProvider {
+    /// Copy through the terminal; pasting returns the last copied text
+    Osc52,
+    /// Run commands that take the text on stdin and print it on stdout
+    Command { copy: Vec<String>, paste: Option<Vec<String>> },
+}
+
+/// The system clipboard behind the `"+` and `"*` registers
+#[derive(Debug, Clone)]
+pub struct Clipboard {
+    provider: ClipboardProvider,
+    last_copied: Option<String>,
+}
+
+impl Clipboard {
+    /// Create a clipboard using a provider
+    pub fn new(provider: ClipboardProvider) -> Self {
+        Self { provider, last_copied: None }
+    }
+
+    /// Read the provider from `[plugins.plugin_configs.clipboard]`, using OSC 52 by default
+    ///
+    /// ```toml
+    /// [plugins.plugin_configs.clipboard]
+    /// provider = "command"
+    /// copy = ["wl-copy"]
+    /// paste = ["wl-paste", "--no-newline"]
+    /// ```
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let Some(table) = config.plugins.plugin_configs.get("clipboard").and_then(|value| value.as_table()) else {
+            return Ok(Self::new(ClipboardProvider::Osc52));
+        };
+
+        let command = |key: &str| -> Result<Option<Vec<String>>> {
+            let Some(value) = table.get(key) else {
+                return Ok(None);
+            };
+            let words = value.as_array()
+                .and_then(|words| words.iter().map(|word| word.as_str().map(str::to_string)).collect::<Option<Vec<_>>>())
+                .filter(|words| !words.is_empty())
+                .ok_or_else(|| RuskError::Config(format!("clipboard {} must be a non-empty list of strings", key)))?;
+            Ok(Some(words))
+        };
+
+        let provider = match table.get("provider").and_then(|value| value.as_str()).unwrap_or("osc52") {
+            "osc52" => ClipboardProvider::Osc52,
+            "command" => ClipboardProvider::Command {
+                copy: command("copy")?.ok_or_else(|| RuskError::Config("clipboard provider \"command\" needs a copy command".to_string()))?,
+                paste: command("paste")?,
+            },
+            other => return Err(RuskError::Config(format!("Unknown clipboard provider '{}', expected osc52 or command", other))),
+        };
+        Ok(Self::new(provider))
+    }
+
+    /// Get the provider
+    pub fn provider(&self) -> &ClipboardProvider {
+        &self.provider
+    }
+
+    /// Put text on the system clipboard
+    pub fn copy(&mut self, text: &str) -> Result<()> {
+        match &self.provider {
+            ClipboardProvider::Osc52 => {
+                let mut stdout = std::io::stdout();
+                stdout.write_all(osc52_sequence(text).as_bytes())?;
+                stdout.flush()?;
+            }
+            ClipboardProvider::Command { copy, .. } => {
+                let mut child = Command::new(&copy[0])
+                    .args(&copy[1..])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map_err(|e| RuskError::Command(format!("Failed to run {}: {}", copy[0], e)))?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(text.as_bytes())?;
+                }
+                let status = child.wait()?;
+                if !status.success() {
+                    return Err(RuskError::Command(format!("{} failed with {}", copy[0], status)));
+                }
+            }
+        }
+        self.last_copied = Some(text.to_string());
+        Ok(())
+    }
+
+    /// Get the text on the system clipboard
+    ///
+    /// Terminals rarely answer OSC 52 queries, so without a paste command
+    /// this is the text last copied from the editor.
+    pub fn paste(&self) -> Result<Option<String>> {
+        match &self.provider {
+            ClipboardProvider::Command { paste: Some(paste), .. } => {
+                let output = Command::new(&paste[0])
+                    .args(&paste[1..])
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::null())
+                    .output()
+                    .map_err(|e| RuskError::Command(format!("Failed to run {}: {}", paste[0], e)))?;
+                if !output.status.success() {
+                    return Err(RuskError::Command(format!("{} failed with {}", paste[0], output.status)));
+                }
+                Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+            }
+            _ => Ok(self.last_copied.clone()),
+        }
+    }
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self::new(ClipboardProvider::Osc52)
+    }
+}
+
+/// Escape sequence asking the terminal to put text on the clipboard
+pub fn osc52_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| group | (u32::from(*byte) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(osc52_sequence("hi!"), "\x1b]52;c;aGkh\x07");
+        assert_eq!(osc52_sequence("rusk"), "\x1b]52;c;cnVzaw==\x07");
+    }
+}
@@ -1,8 +1,1680 @@
 /// Command system for executing editor commands
-pub struct CommandSystem;
+///
+/// Commands are registered by name with typed arguments, a description and
+/// a condition saying where they apply. Keybindings and the ex command line
+/// both resolve to command names and run through `CommandSystem::execute`;
+/// command lines may start with a range of lines for the commands that take one.
+use crate::config::KeyBindings;
+use crate::core::buffer::{Buffer, FoldState};
+use crate::core::cursor::movement::ScreenPlace;
+use crate::core::cursor::{Position, TextRange};
+use crate::plugins::implementations::editing::lsp_commands::LspAction;
+use crate::plugins::implementations::language::syntax::SupportedLanguage;
+use crate::plugins::implementations::language::textobjects::{SyntaxSelection, TextObjectKind};
+use crate::services::event_bus::EventBus;
+use crate::services::ex::{self, SortOptions};
+use crate::services::marks::{Location, Marks};
+use crate::services::operators::Operator;
+use crate::services::registers::{RegisterContent, Registers, WriteKind};
+use crate::services::visual_block;
+use crate::{Result, RuskError};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Editing mode a command or keybinding applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EditorMode {
+    Normal,
+    Insert,
+    Visual,
+    Command,
+}
+
+impl EditorMode {
+    /// Lowercase name used in messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Insert => "insert",
+            Self::Visual => "visual",
+            Self::Command => "command",
+        }
+    }
+
+    /// Get the keybinding map of this mode
+    pub fn bindings<'a>(&self, keybindings: &'a KeyBindings) -> &'a std::collections::HashMap<String, String> {
+        match self {
+            Self::Normal => &keybindings.normal_mode,
+            Self::Insert => &keybindings.insert_mode,
+            Self::Visual => &keybindings.visual_mode,
+            Self::Command => &keybindings.command_mode,
+        }
+    }
+}
+
+/// Type of a command argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    String,
+    Integer,
+    Boolean,
+    Path,
+}
+
+impl ArgKind {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::String => "text",
+            Self::Integer => "number",
+            Self::Boolean => "true/false",
+            Self::Path => "path",
+        }
+    }
+}
+
+/// What Tab completes an argument from on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgCompletion {
+    None,
+    Commands,
+    Paths,
+    Settings,
+    Buffers,
+}
+
+/// Declaration of a command argument
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgSpec {
+    pub name: String,
+    pub kind: ArgKind,
+    pub required: bool,
+    pub description: String,
+    pub completion: ArgCompletion,
+}
+
+impl ArgSpec {
+    /// Declare a required argument
+    pub fn required(name: &str, kind: ArgKind, description: &str) -> Self {
+        let completion = if kind == ArgKind::Path { ArgCompletion::Paths } else { ArgCompletion::None };
+        Self { name: name.to_string(), kind, required: true, description: description.to_string(), completion }
+    }
+
+    /// Declare an optional argument
+    pub fn optional(name: &str, kind: ArgKind, description: &str) -> Self {
+        Self { required: false, ..Self::required(name, kind, description) }
+    }
+
+    /// Set what the argument completes from
+    pub fn complete(mut self, completion: ArgCompletion) -> Self {
+        self.completion = completion;
+        self
+    }
+}
+
+/// A parsed argument value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgValue {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Path(PathBuf),
+}
+
+/// Parsed arguments of a command invocation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandArgs {
+    values: BTreeMap<String, ArgValue>,
+}
+
+impl CommandArgs {
+    /// Create an empty argument list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set an argument value
+    pub fn with(mut self, name: &str, value: ArgValue) -> Self {
+        self.values.insert(name.to_string(), value);
+        self
+    }
+
+    /// Get an argument value
+    pub fn get(&self, name: &str) -> Option<&ArgValue> {
+        self.values.get(name)
+    }
+
+    /// Get a text argument
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.values.get(name)? {
+            ArgValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get a number argument
+    pub fn integer(&self, name: &str) -> Option<i64> {
+        match self.values.get(name)? {
+            ArgValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Get a true/false argument
+    pub fn boolean(&self, name: &str) -> Option<bool> {
+        match self.values.get(name)? {
+            ArgValue::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Get a path argument
+    pub fn path(&self, name: &str) -> Option<&PathBuf> {
+        match self.values.get(name)? {
+            ArgValue::Path(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Where a command may run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandCondition {
+    /// Modes the command applies in, all when empty
+    pub modes: Vec<EditorMode>,
+    /// Languages the command applies to, all when empty
+    pub languages: Vec<SupportedLanguage>,
+    /// Whether the command modifies the buffer and so needs it writable
+    pub needs_writable: bool,
+}
+
+impl CommandCondition {
+    /// A condition that always holds
+    pub fn always() -> Self {
+        Self::default()
+    }
+
+    /// Restrict to some modes
+    pub fn in_modes(mut self, modes: &[EditorMode]) -> Self {
+        self.modes = modes.to_vec();
+        self
+    }
+
+    /// Restrict to some languages
+    pub fn for_languages(mut self, languages: &[SupportedLanguage]) -> Self {
+        self.languages = languages.to_vec();
+        self
+    }
+
+    /// Require a writable buffer
+    pub fn writable(mut self) -> Self {
+        self.needs_writable = true;
+        self
+    }
+
+    /// Explain why the condition fails, if it does
+    pub fn check(&self, buffer: &Buffer, mode: EditorMode) -> std::result::Result<(), String> {
+        if !self.modes.is_empty() && !self.modes.contains(&mode) {
+            return Err(format!("not available in {} mode", mode.name()));
+        }
+        if !self.languages.is_empty() && !self.languages.contains(buffer.language()) {
+            return Err(format!("not available for {} files", buffer.language().language_id()));
+        }
+        if self.needs_writable && buffer.is_read_only() {
+            return Err("buffer is read-only".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Something a command asks the user interface to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditorRequest {
+    /// Show the fuzzy file finder
+    FindFile,
+    /// Show the command palette
+    OpenPalette,
+    /// Open a file, or switch to its buffer if it is open
+    OpenFile(PathBuf),
+    /// Close the current buffer, as `:bd` does
+    CloseBuffer,
+    /// Show or hide the file explorer
+    ToggleExplorer,
+    /// Show the current buffer's file in the file explorer
+    RevealInExplorer,
+    /// Show lines of text in a popup until the next key, like the `:registers` listing
+    ShowInfo { title: String, lines: Vec<String> },
+    /// Open a file, or switch to its buffer, and move the cursor to a position in it
+    Jump { path: PathBuf, position: Position },
+    /// Wait for the motion or text object an operator applies to
+    StartOperator(Operator),
+    /// Start selecting a block of columns
+    StartVisualBlock,
+    /// Type keys in normal mode, as `:normal` does, at the start of each line or where the cursor is
+    Normal { lines: Option<Vec<usize>>, keys: Vec<KeyEvent> },
+    /// Change or show options, as `:set` does, or only for the buffer or window with `local`
+    SetOptions { options: String, local: bool },
+    /// Describe an option, or every option without a name
+    ShowOptionHelp { name: Option<String> },
+    /// Scroll so the cursor line is at the top, middle or bottom of the screen
+    ScrollCursor(ScreenPlace),
+    /// Grow, shrink or move the selection over the syntax tree
+    SelectSyntax(SyntaxSelection),
+    /// Move to the start of the next or previous function or class
+    GotoSyntaxObject { kind: TextObjectKind, forward: bool },
+    /// Show or hide the list of the buffer's diagnostics
+    ToggleDiagnosticsList,
+    /// Ask the buffer's language server for something at the cursor
+    Lsp(LspAction),
+}
+
+/// What a command asks of the editor after it ran
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutcome {
+    /// Keys to hand to the editing mode, for commands it implements natively
+    pub keys: Vec<KeyEvent>,
+    /// Whether the editor should quit
+    pub quit: bool,
+    /// Message for the status line
+    pub message: Option<String>,
+    /// Requests for the user interface, in order
+    pub requests: Vec<EditorRequest>,
+}
+
+/// What a running command can see and change
+pub struct CommandContext<'a> {
+    pub buffer: &'a mut Buffer,
+    pub mode: EditorMode,
+    pub registers: &'a mut Registers,
+    pub marks: &'a mut Marks,
+    /// Display columns of a tab, for commands that line text up in columns
+    pub tab_size: usize,
+    /// First and last line of the range typed before the command, counting from 0
+    pub range: Option<(usize, usize)>,
+    pub outcome: CommandOutcome,
+    /// Bus that saves go through, so subscribers can veto them
+    pub events: &'a EventBus,
+    commands: &'a BTreeMap<String, CommandSpec>,
+}
+
+impl CommandContext<'_> {
+    /// Run a command line, like the command a `:g` runs on each line
+    ///
+    /// A line that is only a range moves to its last line, `:!cmd` runs a
+    /// shell command or filters the range through it, and `:w !cmd` writes
+    /// the range to one.
+    pub fn run_line(&mut self, line: &str) -> Result<()> {
+        let line = line.trim().trim_start_matches(':');
+        let (range, rest) = ex::parse_range(line)?;
+        let range = range.map(|range| range.resolve(self.buffer)).transpose()?;
+        let rest = rest.trim_start();
+
+        let (name, rest) = match rest.strip_prefix('!') {
+            Some(command) if range.is_some() => ("filter_lines", command),
+            Some(command) => ("run_shell", command),
+            None => ex::split_command(rest),
+        };
+        if name.is_empty() {
+            return match (range, rest.trim()) {
+                (Some((_, last)), "") => {
+                    self.buffer.set_cursor_position(last, first_non_blank(self.buffer, last));
+                    self.buffer.reveal_cursor_line();
+                    Ok(())
+                }
+                (None, "") => Ok(()),
+                (_, rest) => Err(RuskError::Command(format!("Not an editor command: {}", rest))),
+            };
+        }
+        let (name, rest) = match rest.strip_prefix('!') {
+            // `q!` is a command of its own
+            Some(remaining) if CommandSystem::lookup(self.commands, &format!("{}!", name)).is_some() => (format!("{}!", name), remaining),
+            _ => match rest.trim_start().strip_prefix('!') {
+                Some(command) if matches!(name, "w" | "write") && rest.starts_with(char::is_whitespace) => ("pipe_lines".to_string(), command),
+                _ => (name.to_string(), rest),
+            },
+        };
+
+        let command = CommandSystem::lookup(self.commands, &name)
+            .ok_or_else(|| RuskError::Command(format!("Unknown command '{}'", name)))?;
+        let args = command.parse_args(rest)?;
+        self.run(&command.name, &args, range)
+    }
+
+    /// Run a command with parsed arguments over a range of lines
+    fn run(&mut self, name: &str, args: &CommandArgs, range: Option<(usize, usize)>) -> Result<()> {
+        let commands = self.commands;
+        let command = CommandSystem::lookup(commands, name)
+            .ok_or_else(|| RuskError::Command(format!("Unknown command '{}'", name)))?;
+        command.condition.check(self.buffer, self.mode)
+            .map_err(|reason| RuskError::Command(format!("{}: {}", command.name, reason)))?;
+        if range.is_some() && !command.ranged {
+            return Err(RuskError::Command(format!("{}: no range allowed", command.name)));
+        }
+
+        let outer = std::mem::replace(&mut self.range, range);
+        let result = (command.handler)(self, args);
+        self.range = outer;
+        result
+    }
+
+    /// Get the lines of the range, or the cursor line or every line when none was typed
+    pub fn lines(&self, whole_by_default: bool) -> (usize, usize) {
+        match self.range {
+            Some(range) => range,
+            None if whole_by_default => (0, self.buffer.line_count() - 1),
+            None => {
+                let line = self.buffer.cursor_position().0;
+                (line, line)
+            }
+        }
+    }
+
+    /// Hand keys to the editing mode once the command returns
+    pub fn send_keys(&mut self, keys: &[KeyEvent]) {
+        self.outcome.keys.extend_from_slice(keys);
+    }
+
+    /// Ask the editor to quit
+    pub fn quit(&mut self) {
+        self.outcome.quit = true;
+    }
+
+    /// Show a message in the status line
+    pub fn message(&mut self, message: impl Into<String>) {
+        self.outcome.message = Some(message.into());
+    }
+
+    /// Ask the user interface to do something once the command returns
+    pub fn request(&mut self, request: EditorRequest) {
+        self.outcome.requests.push(request);
+    }
+}
+
+/// Function run by a command
+pub type CommandHandler = Box<dyn Fn(&mut CommandContext, &CommandArgs) -> Result<()>>;
+
+/// A named editor command
+pub struct CommandSpec {
+    pub name: String,
+    pub description: String,
+    /// Other names accepted on the command line, such as `w` for `save_file`
+    pub aliases: Vec<String>,
+    pub args: Vec<ArgSpec>,
+    pub condition: CommandCondition,
+    /// Whether a range of lines may be typed before the command
+    pub ranged: bool,
+    handler: CommandHandler,
+}
+
+impl CommandSpec {
+    /// Create a command without arguments that runs anywhere
+    pub fn new(name: &str, description: &str, handler: impl Fn(&mut CommandContext, &CommandArgs) -> Result<()> + 'static) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            aliases: Vec::new(),
+            args: Vec::new(),
+            condition: CommandCondition::always(),
+            ranged: false,
+            handler: Box::new(handler),
+        }
+    }
+
+    /// Create a command that hands keys to the editing mode, for behaviour it implements natively
+    pub fn keys(name: &str, description: &str, keys: Vec<KeyEvent>) -> Self {
+        Self::new(name, description, move |ctx, _| {
+            ctx.send_keys(&keys);
+            Ok(())
+        })
+    }
+
+    /// Add a command line alias
+    pub fn alias(mut self, alias: &str) -> Self {
+        self.aliases.push(alias.to_string());
+        self
+    }
+
+    /// Add an argument
+    pub fn arg(mut self, arg: ArgSpec) -> Self {
+        self.args.push(arg);
+        self
+    }
+
+    /// Set where the command applies
+    pub fn when(mut self, condition: CommandCondition) -> Self {
+        self.condition = condition;
+        self
+    }
+
+    /// Accept a range of lines before the command
+    pub fn ranged(mut self) -> Self {
+        self.ranged = true;
+        self
+    }
+
+    /// One-line usage, like `save_as <path>` or `[range]sort [flags]`
+    pub fn usage(&self) -> String {
+        let mut usage = if self.ranged { format!("[range]{}", self.name) } else { self.name.clone() };
+        for arg in &self.args {
+            let arg = if arg.required { format!(" <{}>", arg.name) } else { format!(" [{}]", arg.name) };
+            usage.push_str(&arg);
+        }
+        usage
+    }
+
+    /// Parse a command line argument string against the declared arguments
+    ///
+    /// Arguments are separated by whitespace and may be double-quoted; the
+    /// last text or path argument takes the rest of the line.
+    pub fn parse_args(&self, input: &str) -> Result<CommandArgs> {
+        let mut args = CommandArgs::new();
+        let mut rest = input.trim();
+
+        for (n, spec) in self.args.iter().enumerate() {
+            if rest.is_empty() {
+                if spec.required {
+                    return Err(RuskError::Command(format!("{}: missing argument <{}>", self.name, spec.name)));
+                }
+                continue;
+            }
+
+            let is_last = n + 1 == self.args.len();
+            let token = if is_last && matches!(spec.kind, ArgKind::String | ArgKind::Path) && !rest.starts_with('"') {
+                std::mem::take(&mut rest).to_string()
+            } else {
+                let (token, remaining) = split_token(rest);
+                rest = remaining;
+                token
+            };
+
+            let value = match spec.kind {
+                ArgKind::String => ArgValue::String(token),
+                ArgKind::Path => ArgValue::Path(expand_home(&token)),
+                ArgKind::Integer => ArgValue::Integer(token.parse().map_err(|_| {
+                    RuskError::Command(format!("{}: <{}> must be a {}, got '{}'", self.name, spec.name, spec.kind.name(), token))
+                })?),
+                ArgKind::Boolean => ArgValue::Boolean(match token.as_str() {
+                    "true" | "on" | "yes" | "1" => true,
+                    "false" | "off" | "no" | "0" => false,
+                    _ => return Err(RuskError::Command(format!("{}: <{}> must be {}, got '{}'", self.name, spec.name, spec.kind.name(), token))),
+                }),
+            };
+            args = args.with(&spec.name, value);
+        }
+
+        if !rest.trim().is_empty() {
+            return Err(RuskError::Command(format!("{}: unexpected argument '{}'", self.name, rest.trim())));
+        }
+        Ok(args)
+    }
+}
+
+/// Split off the first whitespace-separated, possibly double-quoted, token
+fn split_token(input: &str) -> (String, &str) {
+    if let Some(quoted) = input.strip_prefix('"') {
+        let mut token = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '\\' => token.extend(chars.next().map(|(_, escaped)| escaped)),
+                '"' => return (token, quoted[i + 1..].trim_start()),
+                _ => token.push(ch),
+            }
+        }
+        return (token, "");
+    }
+
+    let end = input.find(char::is_whitespace).unwrap_or(input.len());
+    (input[..end].to_string(), input[end..].trim_start())
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/").zip(dirs::home_dir()) {
+        Some((rest, home)) => home.join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Registry of named commands, along with the registers they yank to and put from and the marks shared by buffers
+pub struct CommandSystem {
+    commands: BTreeMap<String, CommandSpec>,
+    registers: Registers,
+    marks: Marks,
+    tab_size: usize,
+    events: EventBus,
+}
+
+impl Default for CommandSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl CommandSystem {
+    /// Create an empty registry
     pub fn new() -> Self {
-        Self
+        Self { commands: BTreeMap::new(), registers: Registers::new(), marks: Marks::new(), tab_size: 4, events: EventBus::new() }
+    }
+
+    /// Create a registry with the built-in editor commands
+    pub fn with_builtins() -> Self {
+        let mut system = Self::new();
+        register_builtins(&mut system);
+        system
+    }
+
+    /// Register a command, replacing any command with the same name
+    pub fn register(&mut self, command: CommandSpec) {
+        self.commands.insert(command.name.clone(), command);
+    }
+
+    /// Check if a command or alias exists
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Look up a command by name or alias
+    pub fn get(&self, name: &str) -> Option<&CommandSpec> {
+        Self::lookup(&self.commands, name)
+    }
+
+    fn lookup<'a>(commands: &'a BTreeMap<String, CommandSpec>, name: &str) -> Option<&'a CommandSpec> {
+        commands.get(name)
+            .or_else(|| commands.values().find(|command| command.aliases.iter().any(|alias| alias == name)))
+    }
+
+    /// Get every command, sorted by name
+    pub fn commands(&self) -> impl Iterator<Item = &CommandSpec> {
+        self.commands.values()
+    }
+
+    /// Get the registers
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    /// Get the registers mutably, to choose one or change the clipboard
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    /// Get the file marks and jump list
+    pub fn marks(&self) -> &Marks {
+        &self.marks
+    }
+
+    /// Get the file marks and jump list mutably, to record jumps or load saved marks
+    pub fn marks_mut(&mut self) -> &mut Marks {
+        &mut self.marks
+    }
+
+    /// Set the display width of a tab
+    pub fn set_tab_size(&mut self, tab_size: usize) {
+        self.tab_size = tab_size;
+    }
+
+    /// Set the event bus commands announce saves on
+    pub fn set_events(&mut self, events: EventBus) {
+        self.events = events;
+    }
+
+    /// Run a command with parsed arguments
+    pub fn execute(&mut self, name: &str, args: &CommandArgs, buffer: &mut Buffer, mode: EditorMode) -> Result<CommandOutcome> {
+        let mut ctx = self.context(buffer, mode);
+        ctx.run(name, args, None)?;
+        Ok(ctx.outcome)
+    }
+
+    /// Run a command line such as `save_as notes.txt`, `w` or `10,20d`
+    pub fn execute_line(&mut self, line: &str, buffer: &mut Buffer, mode: EditorMode) -> Result<CommandOutcome> {
+        let mut ctx = self.context(buffer, mode);
+        ctx.run_line(line)?;
+        Ok(ctx.outcome)
+    }
+
+    /// Check whether a command line names a registered command, or is only a range or a shell command
+    pub fn is_command_line(&self, line: &str) -> bool {
+        let line = line.trim().trim_start_matches(':');
+        let Ok((_, rest)) = ex::parse_range(line) else {
+            // Let the bad range be reported
+            return true;
+        };
+        let rest = rest.trim_start();
+        let (name, _) = ex::split_command(rest);
+        rest.is_empty() || rest.starts_with('!') || self.contains(name)
+    }
+
+    fn context<'a>(&'a mut self, buffer: &'a mut Buffer, mode: EditorMode) -> CommandContext<'a> {
+        CommandContext {
+            buffer,
+            mode,
+            registers: &mut self.registers,
+            marks: &mut self.marks,
+            tab_size: self.tab_size,
+            range: None,
+            outcome: CommandOutcome::default(),
+            events: &self.events,
+            commands: &self.commands,
+        }
+    }
+
+    /// Check that every keybinding names a registered command
+    pub fn validate_keybindings(&self, keybindings: &KeyBindings) -> Result<()> {
+        let mut unknown: Vec<String> = [EditorMode::Normal, EditorMode::Insert, EditorMode::Visual, EditorMode::Command]
+            .iter()
+            .flat_map(|mode| {
+                mode.bindings(keybindings).iter()
+                    .filter(|(_, command)| !self.contains(command))
+                    .map(move |(keys, command)| format!("'{}' in {} mode is bound to unknown command '{}'", keys, mode.name(), command))
+            })
+            .collect();
+
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        unknown.sort();
+        Err(RuskError::Config(unknown.join("; ")))
+    }
+}
+
+/// Build key events from vim-style text: plain characters and key names such as `<Esc>`, `<CR>`, `<Tab>` or `<C-x>`
+///
+/// A `<` that does not start a known key name is typed as itself, so `Ivec<u8>` inserts `vec<u8>`
+fn vim_keys(text: &str) -> Vec<KeyEvent> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+
+    while let Some(ch) = rest.chars().next() {
+        if ch == '<'
+            && let Some(end) = rest.find('>')
+            && let Some(key) = vim_key_name(&rest[1..end])
+        {
+            keys.push(key);
+            rest = &rest[end + 1..];
+            continue;
+        }
+
+        let modifiers = if ch.is_uppercase() { KeyModifiers::SHIFT } else { KeyModifiers::NONE };
+        keys.push(KeyEvent::new(KeyCode::Char(ch), modifiers));
+        rest = &rest[ch.len_utf8()..];
+    }
+
+    keys
+}
+
+/// The key a vim key name between `<` and `>` stands for, if it is one
+fn vim_key_name(name: &str) -> Option<KeyEvent> {
+    let code = match name.to_ascii_lowercase().as_str() {
+        "esc" => KeyCode::Esc,
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "bs" => KeyCode::Backspace,
+        "del" => KeyCode::Delete,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        _ => {
+            let mut letter = name.strip_prefix("C-").or_else(|| name.strip_prefix("c-"))?.chars();
+            return match (letter.next(), letter.next()) {
+                (Some(letter), None) => Some(KeyEvent::new(KeyCode::Char(letter.to_ascii_lowercase()), KeyModifiers::CONTROL)),
+                _ => None,
+            };
+        }
+    };
+    Some(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+fn register_builtins(system: &mut CommandSystem) {
+    use EditorMode::{Command, Normal, Visual};
+
+    let motion = CommandCondition::always().in_modes(&[Normal, Visual]);
+    let edit = CommandCondition::always().in_modes(&[Normal]).writable();
+    let selection_edit = CommandCondition::always().in_modes(&[Visual]).writable();
+
+    // Behaviour the vim plugin implements natively, run by handing it its own keys
+    let native: [(&str, &str, &str, &CommandCondition); 24] = [
+        ("move_left", "Move the cursor left", "h", &motion),
+        ("move_down", "Move the cursor down", "j", &motion),
+        ("move_up", "Move the cursor up", "k", &motion),
+        ("move_right", "Move the cursor right", "l", &motion),
+        ("move_word_forward", "Move to the start of the next word", "w", &motion),
+        ("move_word_backward", "Move to the start of the previous word", "b", &motion),
+        ("move_line_start", "Move to the start of the line", "0", &motion),
+        ("move_line_end", "Move to the end of the line", "$", &motion),
+        ("move_file_start", "Move to the first line", "gg", &motion),
+        ("move_file_end", "Move to the last line", "G", &motion),
+        ("search_next", "Go to the next match of the last search", "n", &motion),
+        ("search_previous", "Go to the previous match of the last search", "N", &motion),
+        ("search_word_forward", "Search forward for the word under the cursor", "*", &motion),
+        ("search_word_backward", "Search backward for the word under the cursor", "#", &motion),
+        ("enter_insert_mode", "Insert before the cursor", "i", &edit),
+        ("append", "Insert after the cursor", "a", &edit),
+        ("open_line_below", "Open a new line below and insert", "o", &edit),
+        ("open_line_above", "Open a new line above and insert", "O", &edit),
+        ("undo", "Undo the last change", "u", &edit),
+        ("redo", "Redo the last undone change", "<C-r>", &edit),
+        ("enter_visual_mode", "Start selecting characters", "v", &motion),
+        ("enter_visual_line_mode", "Start selecting lines", "V", &motion),
+        ("enter_normal_mode", "Return to normal mode", "<Esc>", &CommandCondition::always()),
+        ("execute_command", "Run the typed command line", "<CR>", &CommandCondition::always().in_modes(&[Command])),
+    ];
+    for (name, description, keys, condition) in native {
+        system.register(CommandSpec::keys(name, description, vim_keys(keys)).when(condition.clone()));
+    }
+
+    register_register_commands(system, &edit, &motion, &selection_edit);
+    register_mark_commands(system, &motion);
+    register_operator_commands(system, &edit);
+    register_view_commands(system);
+    register_syntax_commands(system);
+    register_diagnostic_commands(system, &motion);
+    register_lsp_commands(system);
+    register_ex_commands(system);
+
+    system.register(CommandSpec::new("enter_visual_block_mode", "Start selecting a block of columns", |ctx, _| {
+        ctx.request(EditorRequest::StartVisualBlock);
+        Ok(())
+    }).when(motion.clone()));
+
+    let not_insert = CommandCondition::always().in_modes(&[Normal, Visual, Command]);
+
+    system.register(CommandSpec::new("save_file", "Write the buffer to its file", |ctx, _| {
+        if ctx.buffer.file_path().is_none() {
+            return Err(RuskError::Command("No file name; use save_as <path>".to_string()));
+        }
+        ctx.events.save_buffer(ctx.buffer, None)?;
+        ctx.message(format!("Written {}", ctx.buffer.file_path().unwrap_or_default()));
+        Ok(())
+    }).alias("w").alias("write").when(not_insert.clone().writable()));
+
+    system.register(CommandSpec::new("save_as", "Write the buffer to another file", |ctx, args| {
+        let path = args.path("path").cloned().unwrap_or_default();
+        ctx.events.save_buffer(ctx.buffer, Some(&path))?;
+        ctx.message(format!("Written {}", path.display()));
+        Ok(())
+    }).alias("saveas").arg(ArgSpec::required("path", ArgKind::Path, "File to write")).when(not_insert.clone()));
+
+    system.register(CommandSpec::new("quit", "Quit, refusing if there are unsaved changes", |ctx, _| {
+        if ctx.buffer.is_modified() {
+            return Err(RuskError::Command("No write since last change (use quit_force to discard)".to_string()));
+        }
+        ctx.quit();
+        Ok(())
+    }).alias("q").alias("quit").when(not_insert.clone()));
+
+    system.register(CommandSpec::new("quit_force", "Quit, discarding unsaved changes", |ctx, _| {
+        ctx.quit();
+        Ok(())
+    }).alias("q!").when(not_insert.clone()));
+
+    system.register(CommandSpec::new("save_and_quit", "Write the buffer and quit", |ctx, _| {
+        if ctx.buffer.is_modified() {
+            ctx.events.save_buffer(ctx.buffer, None)?;
+        }
+        ctx.quit();
+        Ok(())
+    }).alias("wq").alias("x").when(not_insert.clone()));
+
+    system.register(CommandSpec::new("edit", "Open a file, or switch to it if it is open", |ctx, args| {
+        let path = args.path("path").cloned().unwrap_or_default();
+        ctx.request(EditorRequest::OpenFile(path));
+        Ok(())
+    }).alias("e").arg(ArgSpec::required("path", ArgKind::Path, "File to open")).when(not_insert.clone()));
+
+    system.register(CommandSpec::new("buffer_delete", "Close the buffer, refusing if it has unsaved changes", |ctx, _| {
+        if ctx.buffer.is_modified() {
+            return Err(RuskError::Command("No write since last change (use quit_force to discard)".to_string()));
+        }
+        ctx.request(EditorRequest::CloseBuffer);
+        Ok(())
+    }).alias("bd").alias("bdelete").when(not_insert.clone()));
+
+    system.register(CommandSpec::new("find_file", "Search the files of the working directory", |ctx, _| {
+        ctx.request(EditorRequest::FindFile);
+        Ok(())
+    }).alias("files"));
+
+    system.register(CommandSpec::new("command_palette", "Search and run commands", |ctx, _| {
+        ctx.request(EditorRequest::OpenPalette);
+        Ok(())
+    }).alias("palette"));
+
+    system.register(CommandSpec::new("toggle_explorer", "Show or hide the file explorer", |ctx, _| {
+        ctx.request(EditorRequest::ToggleExplorer);
+        Ok(())
+    }).alias("explorer"));
+
+    system.register(CommandSpec::new("reveal_in_explorer", "Show the buffer's file in the file explorer", |ctx, _| {
+        if ctx.buffer.file_path().is_none() {
+            return Err(RuskError::Command("Buffer has no file".to_string()));
+        }
+        ctx.request(EditorRequest::RevealInExplorer);
+        Ok(())
+    }).alias("reveal"));
+
+    system.register(CommandSpec::new("goto_line", "Move the cursor to a line", |ctx, args| {
+        let line = args.integer("line").unwrap_or(1).max(1) as usize - 1;
+        let line = line.min(ctx.buffer.line_count() - 1);
+        ctx.buffer.set_cursor_position(line, 0);
+        ctx.buffer.reveal_cursor_line();
+        Ok(())
+    }).alias("goto").arg(ArgSpec::required("line", ArgKind::Integer, "Line number, starting at 1")).when(not_insert.clone()));
+
+    system.register(CommandSpec::new("set_read_only", "Mark the buffer read-only or editable", |ctx, args| {
+        let read_only = args.boolean("read_only").unwrap_or(true);
+        ctx.buffer.set_read_only(read_only);
+        ctx.message(if read_only { "Buffer is read-only" } else { "Buffer is editable" });
+        Ok(())
+    }).arg(ArgSpec::optional("read_only", ArgKind::Boolean, "Whether to forbid edits, true by default")).when(not_insert));
+}
+
+/// Fold and scroll commands, which change what is shown but not the text
+fn register_view_commands(system: &mut CommandSystem) {
+    let normal = CommandCondition::always().in_modes(&[EditorMode::Normal]);
+
+    // Changes to the folds, given the cursor line
+    type FoldChange = fn(&mut FoldState, usize);
+    let folds: [(&str, &str, FoldChange); 5] = [
+        ("fold_toggle", "Open or close the fold under the cursor", |folds, line| { folds.toggle(line); }),
+        ("fold_close", "Close the fold under the cursor", |folds, line| { folds.close(line); }),
+        ("fold_open", "Open the fold under the cursor", |folds, line| { folds.open(line); }),
+        ("fold_open_all", "Open every fold", |folds, _| folds.open_all()),
+        ("fold_close_all", "Close every fold", |folds, _| folds.close_all()),
+    ];
+    for (name, description, apply) in folds {
+        system.register(CommandSpec::new(name, description, move |ctx, _| {
+            let line = ctx.buffer.cursor_position().0;
+            apply(ctx.buffer.folds_mut(), line);
+            ctx.buffer.reveal_cursor_line();
+            Ok(())
+        }).when(normal.clone()));
+    }
+
+    let scrolls = [
+        ("scroll_cursor_top", "Scroll the cursor line to the top of the screen", ScreenPlace::Top),
+        ("scroll_cursor_center", "Scroll the cursor line to the middle of the screen", ScreenPlace::Middle),
+        ("scroll_cursor_bottom", "Scroll the cursor line to the bottom of the screen", ScreenPlace::Bottom),
+    ];
+    for (name, description, place) in scrolls {
+        system.register(CommandSpec::new(name, description, move |ctx, _| {
+            ctx.request(EditorRequest::ScrollCursor(place));
+            Ok(())
+        }).when(normal.clone()));
+    }
+}
+
+/// Commands going to the diagnostics of the buffer and listing them
+fn register_diagnostic_commands(system: &mut CommandSystem, motion: &CommandCondition) {
+    for (name, description, forward) in [
+        ("next_diagnostic", "Move to the next diagnostic", true),
+        ("previous_diagnostic", "Move to the previous diagnostic", false),
+    ] {
+        system.register(CommandSpec::new(name, description, move |ctx, _| {
+            let cursor = cursor_position(ctx.buffer);
+            let diagnostics = ctx.buffer.diagnostics();
+            let target = if forward { diagnostics.next_after(cursor) } else { diagnostics.previous_before(cursor) };
+            let Some(pos) = target.map(|diagnostic| diagnostic.range.start) else {
+                return Err(RuskError::Command("No diagnostics".to_string()));
+            };
+            ctx.buffer.set_cursor_position(pos.line, pos.column);
+            ctx.buffer.folds_mut().open(pos.line);
+            ctx.buffer.reveal_cursor_line();
+            Ok(())
+        }).when(motion.clone()));
+    }
+
+    system.register(CommandSpec::new("toggle_diagnostics_list", "Show or hide the list of diagnostics", |ctx, _| {
+        ctx.request(EditorRequest::ToggleDiagnosticsList);
+        Ok(())
+    }).alias("diagnostics"));
+}
+
+/// Language server features at the cursor
+fn register_lsp_commands(system: &mut CommandSystem) {
+    let normal = CommandCondition::always().in_modes(&[EditorMode::Normal]);
+
+    let actions = [
+        ("lsp_hover", "Show the language server's description of the symbol under the cursor", LspAction::Hover),
+        ("lsp_definition", "Go to the definition of the symbol under the cursor", LspAction::Definition),
+        ("lsp_references", "Go to the next reference to the symbol under the cursor", LspAction::References),
+        ("lsp_code_actions", "Choose a code action for the cursor", LspAction::CodeActions),
+    ];
+    for (name, description, action) in actions {
+        system.register(CommandSpec::new(name, description, move |ctx, _| {
+            ctx.request(EditorRequest::Lsp(action.clone()));
+            Ok(())
+        }).when(normal.clone()));
+    }
+
+    system.register(CommandSpec::new("lsp_format", "Format the buffer with its language server", |ctx, _| {
+        ctx.request(EditorRequest::Lsp(LspAction::Format));
+        Ok(())
+    }).when(normal.clone().writable()));
+
+    system.register(CommandSpec::new("lsp_rename", "Rename the symbol under the cursor everywhere", |ctx, args| {
+        ctx.request(EditorRequest::Lsp(LspAction::Rename(args.string("name").map(str::to_string))));
+        Ok(())
+    }).alias("rename").arg(ArgSpec::optional("name", ArgKind::String, "New name, prompted for when left out")).when(normal.writable()));
+}
+
+/// Structural selection and navigation, for languages with text object queries
+fn register_syntax_commands(system: &mut CommandSystem) {
+    use EditorMode::{Normal, Visual};
+    let languages = [SupportedLanguage::Rust, SupportedLanguage::Python];
+    let in_modes = |modes: &[EditorMode]| CommandCondition::always().in_modes(modes).for_languages(&languages);
+
+    let selections = [
+        ("expand_selection", "Select the syntax node around the selection", SyntaxSelection::Expand, in_modes(&[Normal, Visual])),
+        ("shrink_selection", "Go back to the selection before expanding it", SyntaxSelection::Shrink, in_modes(&[Visual])),
+        ("select_next_sibling", "Select the next syntax node at the same level", SyntaxSelection::NextSibling, in_modes(&[Visual])),
+        ("select_previous_sibling", "Select the previous syntax node at the same level", SyntaxSelection::PreviousSibling, in_modes(&[Visual])),
+    ];
+    for (name, description, step, condition) in selections {
+        system.register(CommandSpec::new(name, description, move |ctx, _| {
+            ctx.request(EditorRequest::SelectSyntax(step));
+            Ok(())
+        }).when(condition));
+    }
+
+    let objects = [
+        ("next_function", "Move to the start of the next function", TextObjectKind::Function, true),
+        ("previous_function", "Move to the start of the previous function", TextObjectKind::Function, false),
+        ("next_class", "Move to the start of the next class", TextObjectKind::Class, true),
+        ("previous_class", "Move to the start of the previous class", TextObjectKind::Class, false),
+    ];
+    for (name, description, kind, forward) in objects {
+        system.register(CommandSpec::new(name, description, move |ctx, _| {
+            ctx.request(EditorRequest::GotoSyntaxObject { kind, forward });
+            Ok(())
+        }).when(in_modes(&[Normal, Visual])));
+    }
+}
+
+/// Yank, delete and put commands, which go through the registers
+fn register_register_commands(system: &mut CommandSystem, edit: &CommandCondition, motion: &CommandCondition, selection_edit: &CommandCondition) {
+    use EditorMode::{Command, Normal, Visual};
+
+    system.register(CommandSpec::new("delete_char", "Delete the character under the cursor", |ctx, _| {
+        let (line, column) = ctx.buffer.cursor_position();
+        let cursor = Position::new(line, ctx.buffer.char_boundary(line, column));
+        let Some(ch) = ctx.buffer.lines()[cursor.line][cursor.column..].chars().next() else {
+            return Ok(());
+        };
+        let range = TextRange::new(cursor, Position::new(cursor.line, cursor.column + ch.len_utf8()));
+        let register = ctx.registers.take_selected();
+        ctx.registers.write(register, RegisterContent::chars(ctx.buffer.text_in_range(&range)), WriteKind::Delete)?;
+        ctx.buffer.delete_range(&range);
+        Ok(())
+    }).when(edit.clone()));
+
+    system.register(CommandSpec::new("delete_line", "Delete the current line", |ctx, _| {
+        let line = ctx.buffer.cursor_position().0;
+        let register = ctx.registers.take_selected();
+        ctx.registers.write(register, RegisterContent::lines(ctx.buffer.lines()[line].clone()), WriteKind::Delete)?;
+        ctx.buffer.delete_range(&whole_line_range(ctx.buffer, line));
+        let line = line.min(ctx.buffer.line_count() - 1);
+        ctx.buffer.set_cursor_position(line, first_non_blank(ctx.buffer, line));
+        Ok(())
+    }).when(edit.clone()));
+
+    system.register(CommandSpec::new("yank_line", "Copy the current line", |ctx, _| {
+        let line = ctx.buffer.cursor_position().0;
+        let register = ctx.registers.take_selected();
+        ctx.registers.write(register, RegisterContent::lines(ctx.buffer.lines()[line].clone()), WriteKind::Yank)
+    }).when(motion.clone()));
+
+    system.register(CommandSpec::new("paste", "Paste after the cursor", |ctx, _| put(ctx, true)).when(edit.clone()));
+    system.register(CommandSpec::new("paste_before", "Paste before the cursor", |ctx, _| put(ctx, false)).when(edit.clone()));
+
+    system.register(CommandSpec::new("delete_selection", "Delete the selection", |ctx, _| {
+        delete_selection(ctx)?;
+        ctx.send_keys(&vim_keys("<Esc>"));
+        Ok(())
+    }).when(selection_edit.clone()));
+
+    system.register(CommandSpec::new("change_selection", "Replace the selection", |ctx, _| {
+        delete_selection(ctx)?;
+        ctx.send_keys(&vim_keys("<Esc>i"));
+        Ok(())
+    }).when(selection_edit.clone()));
+
+    system.register(CommandSpec::new("yank_selection", "Copy the selection", |ctx, _| {
+        let (range, content) = selection_content(ctx.buffer)?;
+        let register = ctx.registers.take_selected();
+        ctx.registers.write(register, content, WriteKind::Yank)?;
+        ctx.buffer.clear_selection();
+        ctx.buffer.set_cursor_position(range.start.line, range.start.column);
+        ctx.send_keys(&vim_keys("<Esc>"));
+        Ok(())
+    }).when(CommandCondition::always().in_modes(&[Visual])));
+
+    system.register(CommandSpec::new("show_registers", "List the registers and their text", |ctx, _| {
+        let lines = ctx.registers.list(ctx.buffer).into_iter()
+            .map(|(name, content)| {
+                let kind = if content.linewise { 'l' } else if content.blockwise { 'b' } else { 'c' };
+                format!("{}  \"{}   {}", kind, name, content.text.replace('\n', "^J").replace('\t', "^I"))
+            })
+            .collect();
+        ctx.request(EditorRequest::ShowInfo { title: "Registers".to_string(), lines });
+        Ok(())
+    }).alias("registers").alias("reg").when(CommandCondition::always().in_modes(&[Normal, Visual, Command])));
+}
+
+/// Commands for the ex command line, most of which take a range of lines
+fn register_ex_commands(system: &mut CommandSystem) {
+    use EditorMode::{Command, Normal, Visual};
+    let ex = CommandCondition::always().in_modes(&[Normal, Visual, Command]);
+    let ex_edit = ex.clone().writable();
+    let register_arg = || ArgSpec::optional("register", ArgKind::String, "Register to use instead of the unnamed one");
+    let shell_arg = || ArgSpec::required("command", ArgKind::String, "Shell command");
+
+    system.register(CommandSpec::new("delete_lines", "Delete lines", |ctx, args| {
+        let (first, last) = ctx.lines(false);
+        let register = ex_register(args)?;
+        let text = ctx.buffer.lines()[first..=last].join("\n");
+        ctx.registers.write(register, RegisterContent::lines(text), WriteKind::Delete)?;
+        ctx.buffer.delete_range(&lines_range(ctx.buffer, first, last));
+        let line = first.min(ctx.buffer.line_count() - 1);
+        ctx.buffer.set_cursor_position(line, first_non_blank(ctx.buffer, line));
+        Ok(())
+    }).alias("d").alias("delete").arg(register_arg()).ranged().when(ex_edit.clone()));
+
+    system.register(CommandSpec::new("yank_lines", "Copy lines", |ctx, args| {
+        let (first, last) = ctx.lines(false);
+        let register = ex_register(args)?;
+        let text = ctx.buffer.lines()[first..=last].join("\n");
+        ctx.registers.write(register, RegisterContent::lines(text), WriteKind::Yank)
+    }).alias("y").alias("yank").arg(register_arg()).ranged().when(ex.clone()));
+
+    system.register(CommandSpec::new("global", "Run a command on every line matching a pattern, as in g/pattern/command", |ctx, args| {
+        let text = args.string("command").unwrap_or("");
+        match text.strip_prefix('!') {
+            Some(text) => global(ctx, text, true),
+            None => global(ctx, text, false),
+        }
+    }).alias("g").arg(ArgSpec::required("command", ArgKind::String, "Pattern between delimiters, then the command")).ranged().when(ex.clone()));
+
+    system.register(CommandSpec::new("vglobal", "Run a command on every line not matching a pattern", |ctx, args| {
+        global(ctx, args.string("command").unwrap_or(""), true)
+    }).alias("v").arg(ArgSpec::required("command", ArgKind::String, "Pattern between delimiters, then the command")).ranged().when(ex.clone()));
+
+    system.register(CommandSpec::new("normal", "Type keys in normal mode on each line", |ctx, args| {
+        let keys = vim_keys(args.string("keys").unwrap_or(""));
+        let lines = ctx.range.map(|(first, last)| (first..=last).collect());
+        ctx.request(EditorRequest::Normal { lines, keys });
+        Ok(())
+    }).alias("norm").arg(ArgSpec::required("keys", ArgKind::String, "Keys, with <Esc>, <CR> and <C-x> for special ones")).ranged().when(ex.clone()));
+
+    system.register(CommandSpec::new("sort", "Sort lines, with flags ! (reverse), i (ignore case), n (numeric) and u (unique)", |ctx, args| {
+        let options = SortOptions::parse(args.string("flags").unwrap_or(""))?;
+        let (first, last) = ctx.lines(true);
+        let mut lines = ctx.buffer.lines()[first..=last].to_vec();
+        ex::sort_lines(&mut lines, options);
+        replace_lines(ctx.buffer, first, last, &lines.join("\n"));
+        ctx.buffer.set_cursor_position(first, 0);
+        Ok(())
+    }).arg(ArgSpec::optional("flags", ArgKind::String, "Sort flags")).ranged().when(ex_edit.clone()));
+
+    system.register(CommandSpec::new("read", "Insert a file, or the output of !command, below the line", |ctx, args| {
+        let source = args.string("source").unwrap_or("");
+        let text = match source.strip_prefix('!') {
+            Some(command) => shell_output(command, None)?,
+            None => std::fs::read_to_string(expand_home(source))
+                .map_err(|e| RuskError::io_string(format!("Failed to read {}: {}", source, e)))?,
+        };
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        let (_, line) = ctx.lines(false);
+        let end = Position::new(line, ctx.buffer.lines()[line].len());
+        ctx.buffer.insert_text(end, &format!("\n{}", text));
+        ctx.buffer.set_cursor_position(line + 1, first_non_blank(ctx.buffer, line + 1));
+        Ok(())
+    }).alias("r").arg(ArgSpec::required("source", ArgKind::String, "File, or !command").complete(ArgCompletion::Paths)).ranged().when(ex_edit.clone()));
+
+    system.register(CommandSpec::new("filter_lines", "Replace lines with what a shell command prints given them, as :{range}!command does", |ctx, args| {
+        let (first, last) = ctx.lines(false);
+        let input = ctx.buffer.lines()[first..=last].join("\n") + "\n";
+        let output = shell_output(args.string("command").unwrap_or(""), Some(&input))?;
+        replace_lines(ctx.buffer, first, last, output.strip_suffix('\n').unwrap_or(&output));
+        ctx.buffer.set_cursor_position(first, first_non_blank(ctx.buffer, first));
+        Ok(())
+    }).arg(shell_arg()).ranged().when(ex_edit));
+
+    system.register(CommandSpec::new("pipe_lines", "Write lines to a shell command and show what it prints, as :w !command does", |ctx, args| {
+        let command = args.string("command").unwrap_or("");
+        let (first, last) = ctx.lines(true);
+        let input = ctx.buffer.lines()[first..=last].join("\n") + "\n";
+        show_shell_output(ctx, command, Some(&input))
+    }).arg(shell_arg()).ranged().when(ex.clone()));
+
+    system.register(CommandSpec::new("run_shell", "Run a shell command and show what it prints, as :!command does", |ctx, args| {
+        show_shell_output(ctx, args.string("command").unwrap_or(""), None)
+    }).arg(shell_arg()).when(ex.clone()));
+
+    for (name, alias, local, description) in [
+        ("set", "se", false, "Change or show options, as in set tabstop=2 nowrap"),
+        ("setlocal", "setl", true, "Change or show options for this buffer or window only"),
+    ] {
+        system.register(CommandSpec::new(name, description, move |ctx, args| {
+            let options = args.string("options").unwrap_or("").to_string();
+            ctx.request(EditorRequest::SetOptions { options, local });
+            Ok(())
+        }).alias(alias).arg(ArgSpec::optional("options", ArgKind::String, "Options, like ts=2, nowrap, wrap! or ts?").complete(ArgCompletion::Settings)).when(ex.clone()));
+    }
+
+    system.register(CommandSpec::new("help", "Describe a command or an option, or list the options with help option", |ctx, args| {
+        let topic = args.string("topic").unwrap_or("");
+        if let Some(spec) = CommandSystem::lookup(ctx.commands, topic) {
+            let mut lines = vec![spec.usage(), format!("    {}", spec.description)];
+            if !spec.aliases.is_empty() {
+                lines.push(format!("Also named {}", spec.aliases.join(", ")));
+            }
+            lines.extend(spec.args.iter().map(|arg| format!("<{}>  {}", arg.name, arg.description)));
+            ctx.request(EditorRequest::ShowInfo { title: format!(":help {}", topic), lines });
+            return Ok(());
+        }
+        let name = (!matches!(topic, "" | "option" | "options")).then(|| topic.to_string());
+        ctx.request(EditorRequest::ShowOptionHelp { name });
+        Ok(())
+    }).alias("h").arg(ArgSpec::optional("topic", ArgKind::String, "Command or option name, or option").complete(ArgCompletion::Commands)).when(ex.clone()));
+
+    system.register(CommandSpec::new("switch_buffer", "Show an open buffer", |ctx, args| {
+        ctx.request(EditorRequest::OpenFile(PathBuf::from(args.string("name").unwrap_or(""))));
+        Ok(())
+    }).alias("b").alias("buffer").arg(ArgSpec::required("name", ArgKind::String, "File of the buffer").complete(ArgCompletion::Buffers)).when(ex));
+}
+
+/// Get the register named by an ex command's argument
+fn ex_register(args: &CommandArgs) -> Result<Option<char>> {
+    let Some(name) = args.string("register") else {
+        return Ok(None);
+    };
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(name), None) => Registers::validate(name).map(|_| Some(name)),
+        _ => Err(RuskError::Command(format!("Invalid register name: '{}'", name))),
+    }
+}
+
+/// Run a command on the lines of the range matching, or with `invert` not matching, a pattern
+///
+/// The lines are found first and followed through the edits the command
+/// makes, so a line deleted by the command on an earlier one is skipped.
+fn global(ctx: &mut CommandContext, text: &str, invert: bool) -> Result<()> {
+    if ctx.buffer.marks().is_tracking() {
+        return Err(RuskError::Command("Cannot run :global from inside another".to_string()));
+    }
+    let delimiter = text.chars().next()
+        .filter(|ch| !ch.is_alphanumeric() && *ch != '\\' && *ch != '"')
+        .ok_or_else(|| RuskError::Command("global: expected a pattern like /pattern/".to_string()))?;
+    let (pattern, command) = ex::split_delimited(&text[delimiter.len_utf8()..], delimiter);
+    let regex = ex::compile(&pattern)?;
+    let (first, last) = ctx.lines(true);
+    let lines: Vec<usize> = (first..=last).filter(|line| regex.is_match(&ctx.buffer.lines()[*line]) != invert).collect();
+    if lines.is_empty() {
+        return Err(RuskError::Command(format!("Pattern not found: {}", pattern)));
+    }
+
+    let command = command.trim();
+    if command.is_empty() {
+        // Without a command the lines are listed
+        let listed = lines.iter().map(|line| format!("{:>6}  {}", line + 1, ctx.buffer.lines()[*line])).collect();
+        ctx.request(EditorRequest::ShowInfo { title: format!("g/{}/", pattern), lines: listed });
+        return Ok(());
+    }
+    let (name, keys) = ex::split_command(command);
+    if CommandSystem::lookup(ctx.commands, name).is_some_and(|spec| spec.name == "normal") {
+        // Keys are typed by the editor, which follows the lines itself
+        ctx.request(EditorRequest::Normal { lines: Some(lines), keys: vim_keys(keys.trim_start()) });
+        return Ok(());
+    }
+
+    ctx.buffer.marks_mut().track_lines(&lines);
+    let mut result = Ok(());
+    for index in 0..lines.len() {
+        let Some(line) = ctx.buffer.marks().tracked_line(index) else {
+            continue;
+        };
+        ctx.buffer.set_cursor_position(line, 0);
+        result = ctx.run_line(command);
+        if result.is_err() {
+            break;
+        }
+    }
+    ctx.buffer.marks_mut().untrack_lines();
+    result
+}
+
+/// Run a shell command and get what it printed, failing with its error output if it failed
+fn shell_output(command: &str, input: Option<&str>) -> Result<String> {
+    let output = ex::run_shell(command, input)?;
+    if !output.success {
+        let reason = output.stderr.lines().next().map_or_else(
+            || format!("exit status {}", output.code.map_or("unknown".to_string(), |code| code.to_string())),
+            str::to_string,
+        );
+        return Err(RuskError::Command(format!("{}: {}", command, reason)));
+    }
+    Ok(output.stdout)
+}
+
+/// Run a shell command and show what it printed in a popup
+fn show_shell_output(ctx: &mut CommandContext, command: &str, input: Option<&str>) -> Result<()> {
+    let output = ex::run_shell(command, input)?;
+    let mut lines: Vec<String> = output.stdout.lines().chain(output.stderr.lines()).map(str::to_string).collect();
+    if !output.success {
+        lines.push(format!("shell returned {}", output.code.map_or("unknown".to_string(), |code| code.to_string())));
+    }
+    if lines.is_empty() {
+        ctx.message(format!("!{} printed nothing", command));
+        return Ok(());
+    }
+    ctx.request(EditorRequest::ShowInfo { title: format!("!{}", command), lines });
+    Ok(())
+}
+
+/// Replace whole lines with text
+fn replace_lines(buffer: &mut Buffer, first: usize, last: usize, text: &str) {
+    let range = TextRange::new(Position::new(first, 0), Position::new(last, buffer.lines()[last].len()));
+    buffer.replace_range(&range, text);
+}
+
+/// Operators, which apply to the motion typed after them
+fn register_operator_commands(system: &mut CommandSystem, edit: &CommandCondition) {
+    for operator in Operator::ALL {
+        let condition = match operator {
+            Operator::Yank => CommandCondition::always().in_modes(&[EditorMode::Normal]),
+            _ => edit.clone(),
+        };
+        system.register(CommandSpec::new(operator.command(), operator.description(), move |ctx, _| {
+            ctx.request(EditorRequest::StartOperator(operator));
+            Ok(())
+        }).when(condition));
+    }
+}
+
+/// Mark, jump list and change list commands
+fn register_mark_commands(system: &mut CommandSystem, motion: &CommandCondition) {
+    use EditorMode::{Command, Normal, Visual};
+    let name_arg = || ArgSpec::required("name", ArgKind::String, "Mark name, a-z in this buffer or A-Z for a file");
+
+    system.register(CommandSpec::new("set_mark", "Mark the cursor position", |ctx, args| {
+        let name = mark_name(args)?;
+        let position = cursor_position(ctx.buffer);
+        if name.is_ascii_uppercase() {
+            let path = ctx.buffer.file_path()
+                .ok_or_else(|| RuskError::Command(format!("Cannot set mark {} in a buffer without a file", name)))?
+                .to_string();
+            ctx.marks.set_file_mark(name, path, position)
+        } else {
+            ctx.buffer.marks_mut().set(name, position)
+        }
+    }).alias("mark").alias("k").arg(name_arg()).when(motion.clone()));
+
+    system.register(CommandSpec::new("goto_mark", "Move to a mark", |ctx, args| {
+        jump_to_mark(ctx, mark_name(args)?, false)
+    }).arg(name_arg()).when(motion.clone()));
+
+    system.register(CommandSpec::new("goto_mark_line", "Move to the line of a mark", |ctx, args| {
+        jump_to_mark(ctx, mark_name(args)?, true)
+    }).arg(name_arg()).when(motion.clone()));
+
+    system.register(CommandSpec::new("delete_marks", "Delete marks", |ctx, args| {
+        for name in args.string("names").unwrap_or("").chars().filter(|name| !name.is_whitespace()) {
+            let removed = if name.is_ascii_uppercase() { ctx.marks.remove_file_mark(name)? } else { ctx.buffer.marks_mut().remove(name) };
+            if !removed {
+                return Err(RuskError::Command(format!("Mark not set: {}", name)));
+            }
+        }
+        Ok(())
+    }).alias("delmarks").arg(ArgSpec::required("names", ArgKind::String, "Names of the marks, like \"ab\"")).when(motion.clone()));
+
+    system.register(CommandSpec::new("jump_older", "Go back to where the cursor was before the last jump", |ctx, _| {
+        let location = ctx.marks.jump_back(Location::cursor(ctx.buffer), 1)
+            .ok_or_else(|| RuskError::Command("At start of jump list".to_string()))?;
+        go_to(ctx, location, false)
+    }).when(motion.clone()));
+
+    system.register(CommandSpec::new("jump_newer", "Go forward again in the jump list", |ctx, _| {
+        let location = ctx.marks.jump_forward(Location::cursor(ctx.buffer), 1)
+            .ok_or_else(|| RuskError::Command("At end of jump list".to_string()))?;
+        go_to(ctx, location, false)
+    }).when(motion.clone()));
+
+    system.register(CommandSpec::new("older_change", "Go to the position of an older change", |ctx, _| {
+        let position = ctx.buffer.marks_mut().older_change(1)?;
+        ctx.buffer.set_cursor_position(position.line, position.column);
+        ctx.buffer.reveal_cursor_line();
+        Ok(())
+    }).when(motion.clone()));
+
+    system.register(CommandSpec::new("newer_change", "Go to the position of a newer change", |ctx, _| {
+        let position = ctx.buffer.marks_mut().newer_change(1)?;
+        ctx.buffer.set_cursor_position(position.line, position.column);
+        ctx.buffer.reveal_cursor_line();
+        Ok(())
+    }).when(motion.clone()));
+
+    system.register(CommandSpec::new("show_marks", "List the marks", |ctx, _| {
+        let row = |name: char, position: Position, place: &str| format!("{:>2} {:>6} {:>4}  {}", name, position.line + 1, position.column, place);
+        let text = |buffer: &Buffer, position: Position| buffer.lines().get(position.line).map_or(String::new(), |line| line.trim().to_string());
+
+        let mut lines = vec!["mark   line  col  file/text".to_string()];
+        if let Some(jump) = ctx.marks.previous().filter(|jump| jump.is_in(ctx.buffer)) {
+            lines.push(row('\'', jump.position, &text(ctx.buffer, jump.position)));
+        }
+        lines.extend(ctx.buffer.marks().iter().map(|(name, position)| row(name, position, &text(ctx.buffer, position))));
+        lines.extend(ctx.marks.file_marks().map(|(name, location)| {
+            let place = location.path.as_deref().map_or(String::new(), |path| path.display().to_string());
+            row(name, location.position, &place)
+        }));
+        if let Some(position) = ctx.buffer.marks().get('.') {
+            lines.push(row('.', position, &text(ctx.buffer, position)));
+        }
+        ctx.request(EditorRequest::ShowInfo { title: "Marks".to_string(), lines });
+        Ok(())
+    }).alias("marks").when(CommandCondition::always().in_modes(&[Normal, Visual, Command])));
+
+    system.register(CommandSpec::new("show_jumps", "List the jump list", |ctx, _| {
+        let (jumps, current) = ctx.marks.jumps();
+        let mut lines = vec![" jump  line  col  file".to_string()];
+        lines.extend(jumps.iter().enumerate().map(|(i, jump)| {
+            let place = jump.path.as_deref().map_or(String::new(), |path| path.display().to_string());
+            let marker = if i == current { '>' } else { ' ' };
+            format!("{}{:>4} {:>5} {:>4}  {}", marker, current.abs_diff(i), jump.position.line + 1, jump.position.column, place)
+        }));
+        if current == jumps.len() {
+            lines.push(">".to_string());
+        }
+        ctx.request(EditorRequest::ShowInfo { title: "Jumps".to_string(), lines });
+        Ok(())
+    }).alias("jumps").when(CommandCondition::always().in_modes(&[Normal, Visual, Command])));
+}
+
+/// Get the single-character mark name argument
+fn mark_name(args: &CommandArgs) -> Result<char> {
+    let name = args.string("name").unwrap_or("");
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(ch),
+        _ => Err(RuskError::Command(format!("Invalid mark name: '{}'", name))),
+    }
+}
+
+/// Move to a mark, remembering where the cursor was in the jump list
+///
+/// Besides `a`-`z` and `A`-`Z`, `.` is the last change and `'` or `` ` ``
+/// where the cursor was before the last jump.
+fn jump_to_mark(ctx: &mut CommandContext, name: char, linewise: bool) -> Result<()> {
+    let here = Location::cursor(ctx.buffer);
+    let target = match name {
+        'a'..='z' | '.' | '<' | '>' => ctx.buffer.marks().get(name).map(|position| Location { position, ..here.clone() }),
+        '\'' | '`' => ctx.marks.previous().cloned(),
+        'A'..='Z' => ctx.marks.file_mark(name),
+        _ => return Err(RuskError::Command(format!("Invalid mark name: '{}'", name))),
+    };
+    let target = target.ok_or_else(|| RuskError::Command(format!("Mark not set: {}", name)))?;
+    ctx.marks.push_jump(here);
+    go_to(ctx, target, linewise)
+}
+
+/// Move the cursor to a location, asking the editor to open its file when it is in another buffer
+fn go_to(ctx: &mut CommandContext, location: Location, linewise: bool) -> Result<()> {
+    if location.is_in(ctx.buffer) {
+        let line = location.position.line.min(ctx.buffer.line_count() - 1);
+        let column = if linewise { first_non_blank(ctx.buffer, line) } else { location.position.column };
+        ctx.buffer.set_cursor_position(line, column);
+        ctx.buffer.reveal_cursor_line();
+        return Ok(());
+    }
+    let path = location.path.ok_or_else(|| RuskError::Command("The position is in a buffer without a file".to_string()))?;
+    ctx.request(EditorRequest::Jump { path, position: location.position });
+    Ok(())
+}
+
+fn cursor_position(buffer: &Buffer) -> Position {
+    let (line, column) = buffer.cursor_position();
+    Position::new(line, column)
+}
+
+fn first_non_blank(buffer: &Buffer, line: usize) -> usize {
+    let text = &buffer.lines()[line];
+    text.len() - text.trim_start().len()
+}
+
+/// Range of a line with a line break, the one before it for the last line
+fn whole_line_range(buffer: &Buffer, line: usize) -> TextRange {
+    lines_range(buffer, line, line)
+}
+
+/// Range of lines with a line break, the one before them when they end the buffer
+fn lines_range(buffer: &Buffer, first: usize, last: usize) -> TextRange {
+    if last + 1 < buffer.line_count() {
+        TextRange::new(Position::new(first, 0), Position::new(last + 1, 0))
+    } else if first > 0 {
+        TextRange::new(Position::new(first - 1, buffer.lines()[first - 1].len()), Position::new(last, buffer.lines()[last].len()))
+    } else {
+        TextRange::new(Position::new(first, 0), Position::new(last, buffer.lines()[last].len()))
+    }
+}
+
+/// Range and text of the selection, which is whole lines when it starts and ends at line boundaries
+fn selection_content(buffer: &Buffer) -> Result<(TextRange, RegisterContent)> {
+    let range = buffer.selection_range()
+        .filter(|range| !range.is_empty())
+        .ok_or_else(|| RuskError::Command("Nothing selected".to_string()))?;
+    let ends_line = range.end.column == buffer.lines()[range.end.line].len();
+    if range.start.column == 0 && ends_line {
+        let lines = buffer.lines()[range.start.line..=range.end.line].join("\n");
+        let end = whole_line_range(buffer, range.end.line).end;
+        let start = whole_line_range(buffer, range.start.line).start;
+        return Ok((TextRange::new(start, end), RegisterContent::lines(lines)));
+    }
+    Ok((range, RegisterContent::chars(buffer.text_in_range(&range))))
+}
+
+fn delete_selection(ctx: &mut CommandContext) -> Result<()> {
+    let (range, content) = selection_content(ctx.buffer)?;
+    let register = ctx.registers.take_selected();
+    ctx.registers.write(register, content, WriteKind::Delete)?;
+    ctx.buffer.clear_selection();
+    ctx.buffer.delete_range(&range);
+    Ok(())
+}
+
+/// Put a register's text after or before the cursor, on lines of their own for whole lines
+fn put(ctx: &mut CommandContext, after: bool) -> Result<()> {
+    let register = ctx.registers.take_selected();
+    let content = ctx.registers.get(register, ctx.buffer)?
+        .ok_or_else(|| RuskError::Command(format!("Register {} is empty", register.unwrap_or('"'))))?;
+    let cursor = cursor_position(ctx.buffer);
+
+    if content.blockwise {
+        // Blocks go in the display column after or at the cursor on each line
+        let line = &ctx.buffer.lines()[cursor.line];
+        let mut column = visual_block::display_column(line, cursor.column, ctx.tab_size);
+        if after && let Some(ch) = line[cursor.column..].chars().next() {
+            column += visual_block::char_width(ch, column, ctx.tab_size);
+        }
+        visual_block::put(ctx.buffer, cursor.line, column, &content.text, ctx.tab_size);
+        return Ok(());
+    }
+
+    if content.linewise {
+        let line = if after {
+            let end = Position::new(cursor.line, ctx.buffer.lines()[cursor.line].len());
+            ctx.buffer.insert_text(end, &format!("\n{}", content.text));
+            cursor.line + 1
+        } else {
+            ctx.buffer.insert_text(Position::new(cursor.line, 0), &format!("{}\n", content.text));
+            cursor.line
+        };
+        ctx.buffer.set_cursor_position(line, first_non_blank(ctx.buffer, line));
+        return Ok(());
+    }
+
+    let at = match ctx.buffer.lines()[cursor.line][cursor.column..].chars().next() {
+        Some(ch) if after => Position::new(cursor.line, cursor.column + ch.len_utf8()),
+        _ => cursor,
+    };
+    let end = ctx.buffer.insert_text(at, &content.text);
+    // The cursor ends on the last character put
+    let last = ctx.buffer.lines()[end.line][..end.column].chars().next_back().map_or(0, char::len_utf8);
+    ctx.buffer.set_cursor_position(end.line, end.column - last);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_keybindings_resolve() {
+        let system = CommandSystem::with_builtins();
+        assert!(system.validate_keybindings(&KeyBindings::default()).is_ok());
+
+        let mut keybindings = KeyBindings::default();
+        keybindings.normal_mode.insert("Q".to_string(), "format_everything".to_string());
+        let error = system.validate_keybindings(&keybindings).unwrap_err().to_string();
+        assert!(error.contains("format_everything"));
+    }
+
+    #[test]
+    fn test_arguments_and_conditions() {
+        let mut system = CommandSystem::with_builtins();
+        let mut buffer = Buffer::new();
+        buffer.insert_str("one");
+
+        assert!(system.execute_line("goto_line ten", &mut buffer, EditorMode::Normal).is_err());
+        assert!(system.execute_line("goto_line", &mut buffer, EditorMode::Normal).is_err());
+        assert!(system.execute_line("q", &mut buffer, EditorMode::Normal).is_err());
+        assert!(system.execute_line("q!", &mut buffer, EditorMode::Normal).unwrap().quit);
+        assert!(system.execute_line("goto_line 1", &mut buffer, EditorMode::Insert).is_err());
+
+        let outcome = system.execute("move_file_start", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(outcome.keys.len(), 2);
+
+        buffer.set_read_only(true);
+        let error = system.execute("delete_char", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap_err();
+        assert!(error.to_string().contains("read-only"));
+
+        let save_as = system.get("saveas").unwrap();
+        assert_eq!(save_as.usage(), "save_as <path>");
+        let args = save_as.parse_args("\"my notes.txt\"").unwrap();
+        assert_eq!(args.path("path"), Some(&PathBuf::from("my notes.txt")));
+    }
+
+    #[test]
+    fn test_yank_delete_and_put_use_registers() {
+        let mut system = CommandSystem::with_builtins();
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::new(0, 0), "one\ntwo\nthree");
+        buffer.set_cursor_position(0, 0);
+
+        system.registers_mut().select('a').unwrap();
+        system.execute("yank_line", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap();
+        system.execute("delete_line", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.text(), "two\nthree");
+
+        system.execute("delete_char", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap();
+        system.execute("paste", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.text(), "wto\nthree");
+
+        // A cursor inside a multi-byte character deletes the whole character
+        let mut accented = Buffer::new();
+        accented.insert_text(Position::new(0, 0), "café");
+        accented.set_cursor_position(0, 4);
+        system.execute("delete_char", &CommandArgs::new(), &mut accented, EditorMode::Normal).unwrap();
+        assert_eq!(accented.text(), "caf");
+
+        system.registers_mut().select('a').unwrap();
+        system.execute("paste_before", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.text(), "one\nwto\nthree");
+        assert_eq!(system.registers().get(Some('1'), &buffer).unwrap(), Some(RegisterContent::lines("one")));
+
+        // A block goes in the same columns of the lines below
+        system.registers_mut().set('b', RegisterContent::block("x\ny")).unwrap();
+        buffer.set_cursor_position(0, 0);
+        system.registers_mut().select('b').unwrap();
+        system.execute("paste", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.text(), "oxne
+wyto
+three");
+    }
+
+    #[test]
+    fn test_marks_follow_edits_and_jumps_go_back() {
+        let mut system = CommandSystem::with_builtins();
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::new(0, 0), "one\n  two\nthree");
+        buffer.set_cursor_position(1, 4);
+        let mark = |name: &str| CommandArgs::new().with("name", ArgValue::String(name.to_string()));
+
+        system.execute("set_mark", &mark("a"), &mut buffer, EditorMode::Normal).unwrap();
+        assert!(system.execute("set_mark", &mark("B"), &mut buffer, EditorMode::Normal).is_err());
+        buffer.insert_text(Position::new(0, 0), "zero\n");
+        buffer.set_cursor_position(0, 0);
+
+        system.execute("goto_mark_line", &mark("a"), &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.cursor_position(), (2, 2));
+        system.execute("jump_older", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.cursor_position(), (0, 0));
+        system.execute("jump_newer", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.cursor_position(), (2, 2));
+        system.execute("goto_mark", &mark("'"), &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.cursor_position(), (0, 0));
+
+        system.execute("older_change", &CommandArgs::new(), &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.cursor_position(), (0, 0));
+        assert!(system.execute("goto_mark", &mark("z"), &mut buffer, EditorMode::Normal).is_err());
+    }
+
+    #[test]
+    fn test_ex_ranges_global_and_sort() {
+        let mut system = CommandSystem::with_builtins();
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::new(0, 0), "pear
+fig x
+apple
+kiwi x
+plum");
+        buffer.set_cursor_position(0, 0);
+
+        system.execute_line("4", &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.cursor_position(), (3, 0));
+        system.execute_line("g/x/d", &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.text(), "pear\napple\nplum");
+        system.execute_line("2,$sort", &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.text(), "pear\napple\nplum");
+        system.execute_line("sort!", &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.text(), "plum\npear\napple");
+        system.execute_line(".,.+1d", &mut buffer, EditorMode::Normal).unwrap();
+        assert_eq!(buffer.text(), "apple");
+        assert!(system.execute_line("3,4d", &mut buffer, EditorMode::Normal).is_err());
+
+        buffer.insert_text(Position::new(0, 5), "\nbanana");
+        let outcome = system.execute_line("v/apple/normal Ax", &mut buffer, EditorMode::Normal).unwrap();
+        assert!(matches!(&outcome.requests[..], [EditorRequest::Normal { lines: Some(lines), keys }] if lines == &[1] && keys.len() == 2));
+    }
+
+    #[test]
+    fn test_vim_keys_parse_only_key_names() {
+        let plain = |ch| KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE);
+        let keys = vim_keys("Ivec<u8><Esc>");
+        assert_eq!(keys.len(), 9);
+        assert_eq!(keys[4], plain('<'));
+        assert_eq!(keys[8], KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+
+        assert_eq!(vim_keys("<Tab><BS><Space><lt>"), [
+            KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE),
+            plain(' '),
+            plain('<'),
+        ]);
+        assert_eq!(vim_keys("<C-r>"), [KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL)]);
+        assert_eq!(vim_keys("a<b").len(), 3);
+        assert_eq!(vim_keys("<C-rx>").len(), 6);
     }
-}
\ No newline at end of file
+}
@@ -1,8 +1,349 @@
+//! Event bus service for inter-component communication
+//!
+//! Plugins subscribe to the events they care about and the editor emits
+//! them as things happen. Synchronous subscribers run in priority order,
+//! highest first, and can veto pre-events like `BeforeSave`; async
+//! subscribers get the events that went ahead through a tokio channel.
+//! The bus is a shared handle, so every component can hold a clone.
+
+use crate::{Result, RuskError};
+use crate::core::buffer::{Buffer, BufferId};
+use crate::core::cursor::{Position, TextChange};
+use crate::services::command_system::EditorMode;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Something that happened in the editor, or is about to
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditorEvent {
+    BufferOpened { buffer: BufferId, path: Option<PathBuf> },
+    BufferClosed { buffer: BufferId, path: Option<PathBuf> },
+    /// A buffer is about to be written; subscribers can veto it
+    BeforeSave { buffer: BufferId, path: PathBuf },
+    BufferSaved { buffer: BufferId, path: PathBuf },
+    /// Text was edited, with the edits in the order they were made
+    TextChanged { buffer: BufferId, changes: Vec<TextChange> },
+    CursorMoved { buffer: BufferId, from: Position, to: Position },
+    ModeChanged { from: EditorMode, to: EditorMode },
+    ConfigReloaded,
+    /// The editor is about to quit; subscribers can veto it
+    BeforeShutdown,
+    Shutdown,
+}
+
+/// Kind of an event, for choosing what to subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    BufferOpened,
+    BufferClosed,
+    BeforeSave,
+    BufferSaved,
+    TextChanged,
+    CursorMoved,
+    ModeChanged,
+    ConfigReloaded,
+    BeforeShutdown,
+    Shutdown,
+}
+
+impl EventKind {
+    /// Check whether subscribers can veto events of this kind
+    pub fn is_vetoable(self) -> bool {
+        matches!(self, Self::BeforeSave | Self::BeforeShutdown)
+    }
+}
+
+impl EditorEvent {
+    /// Get the event's kind
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::BufferOpened { .. } => EventKind::BufferOpened,
+            Self::BufferClosed { .. } => EventKind::BufferClosed,
+            Self::BeforeSave { .. } => EventKind::BeforeSave,
+            Self::BufferSaved { .. } => EventKind::BufferSaved,
+            Self::TextChanged { .. } => EventKind::TextChanged,
+            Self::CursorMoved { .. } => EventKind::CursorMoved,
+            Self::ModeChanged { .. } => EventKind::ModeChanged,
+            Self::ConfigReloaded => EventKind::ConfigReloaded,
+            Self::BeforeShutdown => EventKind::BeforeShutdown,
+            Self::Shutdown => EventKind::Shutdown,
+        }
+    }
+}
+
+/// What a synchronous subscriber says about an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Continue,
+    /// Stop a pre-event from going ahead, with the reason shown to the user
+    Veto(String),
+}
+
+/// Identifies a subscription, for removing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+type Handler = Arc<Mutex<dyn FnMut(&EditorEvent) -> Verdict + Send>>;
+
+struct Subscriber {
+    id: SubscriptionId,
+    name: String,
+    /// Kinds of event wanted, or every kind when empty
+    kinds: Vec<EventKind>,
+    priority: i32,
+    handler: Handler,
+}
+
+struct AsyncSubscriber {
+    kinds: Vec<EventKind>,
+    sender: UnboundedSender<EditorEvent>,
+}
+
+#[derive(Default)]
+struct Subscribers {
+    sync: Vec<Subscriber>,
+    async_subscribers: Vec<AsyncSubscriber>,
+    next_id: u64,
+    /// Whether an event is being handed to the subscribers
+    dispatching: bool,
+    /// Events emitted while another was being handed out, sent after it
+    queue: VecDeque<EditorEvent>,
+}
+
+impl Subscribers {
+    fn next_id(&mut self) -> SubscriptionId {
+        self.next_id += 1;
+        SubscriptionId(self.next_id)
+    }
+}
+
+fn wants(kinds: &[EventKind], kind: EventKind) -> bool {
+    kinds.is_empty() || kinds.contains(&kind)
+}
+
 /// Event bus service for inter-component communication
-pub struct EventBus;
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Subscribers>>,
+}
 
 impl EventBus {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Call a function with events of some kinds, or of every kind for an empty list
+    ///
+    /// Subscribers with a higher priority run first, those with the same
+    /// priority in the order they subscribed. The name says who vetoed an
+    /// event.
+    pub fn subscribe(
+        &self,
+        name: &str,
+        kinds: &[EventKind],
+        priority: i32,
+        handler: impl FnMut(&EditorEvent) -> Verdict + Send + 'static,
+    ) -> SubscriptionId {
+        let mut subscribers = self.lock();
+        let id = subscribers.next_id();
+        let index = subscribers.sync.partition_point(|subscriber| subscriber.priority >= priority);
+        subscribers.sync.insert(index, Subscriber {
+            id,
+            name: name.to_string(),
+            kinds: kinds.to_vec(),
+            priority,
+            handler: Arc::new(Mutex::new(handler)),
+        });
+        id
+    }
+
+    /// Receive events of some kinds, or of every kind for an empty list, on a channel for a tokio task
+    ///
+    /// Async subscribers get events after the synchronous ones, and only
+    /// pre-events nobody vetoed. Dropping the receiver ends the subscription.
+    pub fn subscribe_async(&self, kinds: &[EventKind]) -> UnboundedReceiver<EditorEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.lock().async_subscribers.push(AsyncSubscriber { kinds: kinds.to_vec(), sender });
+        receiver
+    }
+
+    /// Stop a subscription, returning whether it was subscribed
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut subscribers = self.lock();
+        let count = subscribers.sync.len();
+        subscribers.sync.retain(|subscriber| subscriber.id != id);
+        subscribers.sync.len() != count
+    }
+
+    /// Tell the subscribers about an event
+    ///
+    /// Fails for a pre-event a subscriber vetoed, which the subscribers
+    /// after it and async subscribers do not see. Events emitted by a
+    /// subscriber are queued and sent once the current one has reached
+    /// every subscriber, so everyone sees events in the same order. A
+    /// pre-event cannot wait for its verdict that way, so emitting one
+    /// from a subscriber fails.
+    pub fn emit(&self, event: EditorEvent) -> Result<()> {
+        {
+            let mut subscribers = self.lock();
+            if subscribers.dispatching {
+                if event.kind().is_vetoable() {
+                    return Err(RuskError::Plugin(format!(
+                        "{} cannot be checked while another event is being handled",
+                        event_name(event.kind())
+                    )));
+                }
+                subscribers.queue.push_back(event);
+                return Ok(());
+            }
+            subscribers.dispatching = true;
+        }
+        let _dispatch = Dispatch(self);
+
+        let result = self.dispatch(&event);
+        loop {
+            let queued = self.lock().queue.pop_front();
+            let Some(queued) = queued else {
+                break;
+            };
+            // Queued events are never pre-events, so nothing can stop them
+            let _ = self.dispatch(&queued);
+        }
+        result
+    }
+
+    /// Hand an event to the synchronous subscribers, then the async ones
+    fn dispatch(&self, event: &EditorEvent) -> Result<()> {
+        let kind = event.kind();
+        let handlers: Vec<(String, Handler)> = self.lock().sync.iter()
+            .filter(|subscriber| wants(&subscriber.kinds, kind))
+            .map(|subscriber| (subscriber.name.clone(), subscriber.handler.clone()))
+            .collect();
+
+        // The list is not locked while handlers run, so they can emit and subscribe
+        for (name, handler) in handlers {
+            let mut handler = handler.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Verdict::Veto(reason) = handler(event)
+                && kind.is_vetoable()
+            {
+                return Err(RuskError::Plugin(format!("{} stopped by {}: {}", event_name(kind), name, reason)));
+            }
+        }
+
+        self.lock().async_subscribers.retain(|subscriber| {
+            !wants(&subscriber.kinds, kind) || subscriber.sender.send(event.clone()).is_ok()
+        });
+        Ok(())
+    }
+
+    /// Write a buffer to its file, or to another path, unless a subscriber vetoes `BeforeSave`
+    pub fn save_buffer(&self, buffer: &mut Buffer, path: Option<&Path>) -> Result<()> {
+        let target = path.map(Path::to_path_buf)
+            .or_else(|| buffer.file_path().map(PathBuf::from))
+            .ok_or_else(|| RuskError::Buffer("No file path specified".to_string()))?;
+        self.emit(EditorEvent::BeforeSave { buffer: buffer.id(), path: target.clone() })?;
+        match path {
+            Some(path) => buffer.save_as(path)?,
+            None => buffer.save()?,
+        }
+        self.emit(EditorEvent::BufferSaved { buffer: buffer.id(), path: target })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Subscribers> {
+        // A subscriber that panicked leaves the list itself intact
+        self.subscribers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Ends a dispatch even if a subscriber panics, so later events are not queued forever
+struct Dispatch<'a>(&'a EventBus);
+
+impl Drop for Dispatch<'_> {
+    fn drop(&mut self) {
+        let mut subscribers = self.0.lock();
+        subscribers.dispatching = false;
+        subscribers.queue.clear();
+    }
+}
+
+/// Name an event kind in messages
+fn event_name(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::BeforeSave => "Saving",
+        EventKind::BeforeShutdown => "Quitting",
+        _ => "Event",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_veto_and_async_subscribers() {
+        let bus = EventBus::new();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for (name, priority) in [("late", -5), ("early", 10), ("middle", 0)] {
+            let order = order.clone();
+            bus.subscribe(name, &[EventKind::BeforeSave, EventKind::BufferSaved], priority, move |event| {
+                order.lock().unwrap().push(name);
+                match event {
+                    EditorEvent::BeforeSave { path, .. } if name == "middle" && path.ends_with("locked.txt") => Verdict::Veto("file is locked".to_string()),
+                    _ => Verdict::Continue,
+                }
+            });
+        }
+        let mut receiver = bus.subscribe_async(&[EventKind::BeforeSave]);
+        let buffer = Buffer::new().id();
+
+        let error = bus.emit(EditorEvent::BeforeSave { buffer, path: PathBuf::from("locked.txt") }).unwrap_err();
+        assert!(error.to_string().contains("stopped by middle: file is locked"));
+        assert_eq!(*order.lock().unwrap(), vec!["early", "middle"]);
+        assert!(receiver.try_recv().is_err());
+
+        // Only pre-events can be vetoed
+        order.lock().unwrap().clear();
+        bus.emit(EditorEvent::BufferSaved { buffer, path: PathBuf::from("locked.txt") }).unwrap();
+        assert_eq!(*order.lock().unwrap(), vec!["early", "middle", "late"]);
+
+        bus.emit(EditorEvent::BeforeSave { buffer, path: PathBuf::from("notes.txt") }).unwrap();
+        assert_eq!(receiver.try_recv().unwrap().kind(), EventKind::BeforeSave);
+        bus.emit(EditorEvent::Shutdown).unwrap();
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_events_emitted_by_subscribers_are_queued() {
+        let bus = EventBus::new();
+        let buffer = Buffer::new().id();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let nested = Arc::new(Mutex::new(None));
+
+        let (inner_bus, inner_order, inner_nested) = (bus.clone(), order.clone(), nested.clone());
+        bus.subscribe("saver", &[], 10, move |event| {
+            inner_order.lock().unwrap().push(format!("saver {:?}", event.kind()));
+            if let EditorEvent::BufferOpened { buffer, .. } = event {
+                inner_bus.emit(EditorEvent::BufferSaved { buffer: *buffer, path: PathBuf::from("a.txt") }).unwrap();
+                *inner_nested.lock().unwrap() = Some(inner_bus.emit(EditorEvent::BeforeSave { buffer: *buffer, path: PathBuf::from("a.txt") }));
+            }
+            Verdict::Continue
+        });
+        let inner_order = order.clone();
+        bus.subscribe("logger", &[], 0, move |event| {
+            inner_order.lock().unwrap().push(format!("logger {:?}", event.kind()));
+            Verdict::Continue
+        });
+
+        // Everyone sees the opened buffer before the save the first subscriber made
+        bus.emit(EditorEvent::BufferOpened { buffer, path: None }).unwrap();
+        assert_eq!(*order.lock().unwrap(), vec!["saver BufferOpened", "logger BufferOpened", "saver BufferSaved", "logger BufferSaved"]);
+        assert!(nested.lock().unwrap().take().unwrap().is_err());
+
+        // The bus takes events directly again afterwards
+        order.lock().unwrap().clear();
+        bus.emit(EditorEvent::Shutdown).unwrap();
+        assert_eq!(order.lock().unwrap().len(), 2);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,341 @@
+//! Ex command line grammar
+//!
+//! An ex command line is an optional range of lines followed by a command,
+//! like `:10,20d`, `:'<,'>sort` or `:.,$!fmt`. Addresses are line numbers,
+//! `.` for the cursor line, `$` for the last, marks and searches, each
+//! followed by any number of `+n`/`-n` offsets. This module parses ranges
+//! and holds the line work behind `:sort` and the shell filters.
+
+use crate::core::buffer::Buffer;
+use crate::{Result, RuskError};
+use regex::Regex;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Line an address starts from, before its offsets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressBase {
+    /// The cursor line, `.`, and what a bare offset counts from
+    Current,
+    /// A line number, starting at 1
+    Number(usize),
+    /// The last line, `$`
+    Last,
+    /// The line of a mark, like `'a` or `'<`
+    Mark(char),
+    /// The next line matching a pattern, `/pat/`, or the previous one, `?pat?`
+    Search { pattern: String, backward: bool },
+}
+
+/// A line address with its offsets summed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub base: AddressBase,
+    pub offset: i64,
+}
+
+/// Lines a command applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineRange {
+    /// Every line, `%`
+    Whole,
+    /// One address, or two where `;` makes the first the current line for the second
+    Lines { first: Address, second: Option<(Address, bool)> },
+}
+
+impl LineRange {
+    /// Get the first and last line of the range, counting from 0
+    ///
+    /// A range given backwards is turned around.
+    pub fn resolve(&self, buffer: &Buffer) -> Result<(usize, usize)> {
+        let last = buffer.line_count() - 1;
+        let (first, second) = match self {
+            Self::Whole => return Ok((0, last)),
+            Self::Lines { first, second } => (first, second),
+        };
+        let cursor = buffer.cursor_position().0;
+        let start = first.resolve(buffer, cursor)?;
+        let end = match second {
+            Some((address, from_first)) => address.resolve(buffer, if *from_first { start } else { cursor })?,
+            None => start,
+        };
+        Ok((start.min(end), start.max(end)))
+    }
+}
+
+impl Address {
+    /// Get the line the address names, counting from 0, with `.` being `current`
+    pub fn resolve(&self, buffer: &Buffer, current: usize) -> Result<usize> {
+        let last = buffer.line_count() - 1;
+        let base = match &self.base {
+            AddressBase::Current => current,
+            // Line 0 is allowed where vim allows it and means the first line here
+            AddressBase::Number(number) => number.saturating_sub(1),
+            AddressBase::Last => last,
+            AddressBase::Mark(name) => buffer.marks().get(*name)
+                .map(|position| position.line)
+                .ok_or_else(|| RuskError::Command(format!("Mark not set: {}", name)))?,
+            AddressBase::Search { pattern, backward } => search_line(buffer, pattern, current, *backward)?,
+        };
+        let line = base as i64 + self.offset;
+        if line < 0 || line > last as i64 {
+            return Err(RuskError::Command("Invalid range".to_string()));
+        }
+        Ok(line as usize)
+    }
+}
+
+/// Find the next or previous line matching a pattern, wrapping around the buffer
+fn search_line(buffer: &Buffer, pattern: &str, from: usize, backward: bool) -> Result<usize> {
+    let regex = compile(pattern)?;
+    let count = buffer.line_count();
+    (1..=count)
+        .map(|step| if backward { (from + count - step % count) % count } else { (from + step) % count })
+        .find(|line| regex.is_match(&buffer.lines()[*line]))
+        .ok_or_else(|| RuskError::Command(format!("Pattern not found: {}", pattern)))
+}
+
+/// Compile a pattern typed on the command line
+pub fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|e| RuskError::Command(format!("Invalid pattern '{}': {}", pattern, e)))
+}
+
+/// Split the range off the front of a command line, returning it and the rest
+pub fn parse_range(line: &str) -> Result<(Option<LineRange>, &str)> {
+    if let Some(rest) = line.strip_prefix('%') {
+        return Ok((Some(LineRange::Whole), rest));
+    }
+    let (first, mut rest) = parse_address(line)?;
+    let second = match rest.chars().next() {
+        Some(separator @ (',' | ';')) => {
+            let (address, remaining) = parse_address(&rest[1..])?;
+            rest = remaining;
+            // `1,` leaves the second address out, meaning the cursor line
+            Some((address.unwrap_or(Address { base: AddressBase::Current, offset: 0 }), separator == ';'))
+        }
+        _ => None,
+    };
+    let range = match (first, second) {
+        (Some(first), second) => Some(LineRange::Lines { first, second }),
+        (None, Some(second)) => Some(LineRange::Lines { first: Address { base: AddressBase::Current, offset: 0 }, second: Some(second) }),
+        (None, None) => None,
+    };
+    Ok((range, rest))
+}
+
+/// Parse one address, returning nothing if the line does not start with one
+fn parse_address(input: &str) -> Result<(Option<Address>, &str)> {
+    let digits = |text: &str| text.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(text.len());
+    let mut rest = input.trim_start();
+    let base = match rest.chars().next() {
+        Some('.') => {
+            rest = &rest[1..];
+            Some(AddressBase::Current)
+        }
+        Some('$') => {
+            rest = &rest[1..];
+            Some(AddressBase::Last)
+        }
+        Some('\'') => {
+            let name = rest[1..].chars().next().ok_or_else(|| RuskError::Command("Missing mark name after '".to_string()))?;
+            rest = &rest[1 + name.len_utf8()..];
+            Some(AddressBase::Mark(name))
+        }
+        Some(delimiter @ ('/' | '?')) => {
+            let (pattern, remaining) = split_delimited(&rest[1..], delimiter);
+            rest = remaining;
+            Some(AddressBase::Search { pattern, backward: delimiter == '?' })
+        }
+        Some(ch) if ch.is_ascii_digit() => {
+            let end = digits(rest);
+            let number = rest[..end].parse().map_err(|_| RuskError::Command(format!("Line number too large: {}", &rest[..end])))?;
+            rest = &rest[end..];
+            Some(AddressBase::Number(number))
+        }
+        _ => None,
+    };
+
+    let mut offset = 0i64;
+    let mut has_offset = false;
+    while let Some(sign @ ('+' | '-')) = rest.chars().next() {
+        let end = 1 + digits(&rest[1..]);
+        // A sign without a number counts one
+        let amount: i64 = if end == 1 { 1 } else { rest[1..end].parse().map_err(|_| RuskError::Command("Offset too large".to_string()))? };
+        offset += if sign == '+' { amount } else { -amount };
+        rest = &rest[end..];
+        has_offset = true;
+    }
+
+    if base.is_none() && !has_offset {
+        return Ok((None, input));
+    }
+    Ok((Some(Address { base: base.unwrap_or(AddressBase::Current), offset }), rest))
+}
+
+/// Split text up to an unescaped delimiter, returning the text, with `\delimiter` unescaped, and what follows the delimiter
+pub fn split_delimited(input: &str, delimiter: char) -> (String, &str) {
+    let mut text = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some((_, escaped)) if escaped == delimiter => text.push(escaped),
+                Some((_, escaped)) => {
+                    text.push('\\');
+                    text.push(escaped);
+                }
+                None => text.push('\\'),
+            },
+            ch if ch == delimiter => return (text, &input[i + ch.len_utf8()..]),
+            ch => text.push(ch),
+        }
+    }
+    (text, "")
+}
+
+/// Split the command name off a command line with its range removed
+///
+/// Names are letters, digits and underscores, so `d` in `d x` or `g` in
+/// `g/pat/` stands alone. A `!` after the name is left on the rest.
+pub fn split_command(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    let end = line.find(|ch: char| !ch.is_alphanumeric() && ch != '_').unwrap_or(line.len());
+    (&line[..end], &line[end..])
+}
+
+/// How `:sort` orders lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SortOptions {
+    /// `!`: the opposite order
+    pub reverse: bool,
+    /// `i`: ignore case
+    pub ignore_case: bool,
+    /// `n`: by the first number in each line, lines without one first
+    pub numeric: bool,
+    /// `u`: keep only the first of lines that compare equal
+    pub unique: bool,
+}
+
+impl SortOptions {
+    /// Parse the flags typed after `:sort`, like `! n u`
+    pub fn parse(flags: &str) -> Result<Self> {
+        let mut options = Self::default();
+        for flag in flags.chars().filter(|ch| !ch.is_whitespace()) {
+            match flag {
+                '!' => options.reverse = true,
+                'i' => options.ignore_case = true,
+                'n' => options.numeric = true,
+                'u' => options.unique = true,
+                other => return Err(RuskError::Command(format!("sort: unknown flag '{}'", other))),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Sort lines the way `:sort` does, keeping lines that compare equal in their order
+pub fn sort_lines(lines: &mut Vec<String>, options: SortOptions) {
+    let number = |line: &str| -> Option<i64> {
+        let start = line.find(|ch: char| ch.is_ascii_digit())?;
+        let negative = line[..start].ends_with('-');
+        let end = line[start..].find(|ch: char| !ch.is_ascii_digit()).map_or(line.len(), |end| start + end);
+        let value: i64 = line[start..end].parse().ok()?;
+        Some(if negative { -value } else { value })
+    };
+    let compare = |a: &String, b: &String| {
+        if options.numeric {
+            number(a).cmp(&number(b))
+        } else if options.ignore_case {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        } else {
+            a.cmp(b)
+        }
+    };
+    lines.sort_by(|a, b| if options.reverse { compare(b, a) } else { compare(a, b) });
+    if options.unique {
+        lines.dedup_by(|later, earlier| compare(earlier, later).is_eq());
+    }
+}
+
+/// What a shell command printed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellOutput {
+    pub success: bool,
+    /// Exit code, if the command was not killed by a signal
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Run a command line through the shell, writing `input` to it
+pub fn run_shell(command: &str, input: Option<&str>) -> Result<ShellOutput> {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut child = Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| RuskError::Command(format!("Failed to run {}: {}", command, e)))?;
+    // Write from another thread so a command printing as it reads cannot block on a full pipe
+    let writer = input.zip(child.stdin.take()).map(|(input, mut stdin)| {
+        let input = input.to_string();
+        std::thread::spawn(move || stdin.write_all(input.as_bytes()))
+    });
+    let output = child.wait_with_output()
+        .map_err(|e| RuskError::Command(format!("Failed to run {}: {}", command, e)))?;
+    if let Some(writer) = writer {
+        // A command that exits without reading all its input is not an error
+        let _ = writer.join();
+    }
+    Ok(ShellOutput {
+        success: output.status.success(),
+        code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cursor::Position;
+
+    #[test]
+    fn test_ranges_resolve_against_the_buffer() {
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::new(0, 0), "one\ntwo\nthree\nfour\nfive");
+        buffer.set_cursor_position(1, 0);
+        buffer.marks_mut().set('a', Position::new(3, 1)).unwrap();
+        let resolve = |line: &str| {
+            let (range, rest) = parse_range(line).unwrap();
+            (range.unwrap().resolve(&buffer).unwrap(), rest.to_string())
+        };
+
+        assert_eq!(resolve("2,4d"), ((1, 3), "d".to_string()));
+        assert_eq!(resolve(".,$sort"), ((1, 4), "sort".to_string()));
+        assert_eq!(resolve("%normal x"), ((0, 4), "normal x".to_string()));
+        assert_eq!(resolve("'a,.+1y"), ((2, 3), "y".to_string()));
+        assert_eq!(resolve("/f/;+1"), ((3, 4), String::new()));
+        assert_eq!(resolve("?one?"), ((0, 0), String::new()));
+        assert_eq!(resolve("-"), ((0, 0), String::new()));
+        assert_eq!(parse_range("sort").unwrap(), (None, "sort"));
+        assert!(parse_range("9").unwrap().0.unwrap().resolve(&buffer).is_err());
+        assert!(parse_range("'b").unwrap().0.unwrap().resolve(&buffer).is_err());
+        assert_eq!(split_command("g/a\\/b/d"), ("g", "/a\\/b/d"));
+        assert_eq!(split_delimited("a\\/b/d", '/'), ("a/b".to_string(), "d"));
+    }
+
+    #[test]
+    fn test_sort_options() {
+        let mut lines: Vec<String> = ["b10", "a2", "B10", "x", "a2"].iter().map(|line| line.to_string()).collect();
+        sort_lines(&mut lines, SortOptions::default());
+        assert_eq!(lines, ["B10", "a2", "a2", "b10", "x"]);
+        sort_lines(&mut lines, SortOptions::parse("n u").unwrap());
+        assert_eq!(lines, ["x", "a2", "B10"]);
+        sort_lines(&mut lines, SortOptions::parse("!i").unwrap());
+        assert_eq!(lines, ["x", "B10", "a2"]);
+        assert!(SortOptions::parse("z").is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! Keyboard macros recorded into registers
+//!
+//! `qa` records the keys typed until the next `q` into `"a` and `@a` plays
+//! them back. Macros are kept as key notation such as `0dw<Escape>j`, so a
+//! register can be edited like any other text and then played.
+
+use crate::core::buffer::Buffer;
+use crate::services::registers::{RegisterContent, Registers};
+use crate::utils::keybindings::{format_key_notation, parse_key_notation, KeyPress, DEFAULT_LEADER};
+use crate::{Result, RuskError};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Records keys into a register and turns registers back into keys
+#[derive(Debug, Clone, Default)]
+pub struct MacroRecorder {
+    recording: Option<(char, Vec<KeyPress>)>,
+    last_played: Option<char>,
+}
+
+impl MacroRecorder {
+    /// Create a recorder that is not recording
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the register being recorded into
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(name, _)| *name)
+    }
+
+    /// Start recording into a register; uppercase names append to the macro already there
+    pub fn start(&mut self, name: char) -> Result<()> {
+        if !name.is_ascii_alphanumeric() && name != '"' {
+            return Err(RuskError::Command(format!("Cannot record into register {}", name)));
+        }
+        self.recording = Some((name, Vec::new()));
+        Ok(())
+    }
+
+    /// Add a typed key to the macro being recorded
+    pub fn record(&mut self, key: KeyEvent) {
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(KeyPress::from_event(key));
+        }
+    }
+
+    /// Stop recording and store the macro in its register
+    pub fn stop(&mut self, registers: &mut Registers) -> Result<Option<char>> {
+        let Some((name, keys)) = self.recording.take() else {
+            return Ok(None);
+        };
+        registers.set(name, RegisterContent::chars(format_key_notation(&keys)))?;
+        Ok(Some(name))
+    }
+
+    /// Get the keys of the macro in a register, with `@` meaning the macro played last
+    pub fn keys(&mut self, name: char, registers: &Registers, buffer: &Buffer) -> Result<Vec<KeyEvent>> {
+        let name = match name {
+            '@' => self.last_played.ok_or_else(|| RuskError::Command("No previous macro".to_string()))?,
+            name => name,
+        };
+        let content = registers.get(Some(name), buffer)?
+            .ok_or_else(|| RuskError::Command(format!("Register {} is empty", name)))?;
+        self.last_played = Some(name);
+
+        let leader = KeyPress::new(KeyCode::Char(DEFAULT_LEADER), KeyModifiers::NONE);
+        let mut keys: Vec<KeyEvent> = parse_key_notation(&content.text, leader)?.iter()
+            .map(|key| match key.code {
+                // Line breaks in edited register text are Enter, as are the ends of whole lines
+                KeyCode::Char('\n') => KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+                _ => key.to_event(),
+            })
+            .collect();
+        if content.linewise {
+            keys.push(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        }
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_edit_and_replay() {
+        let mut recorder = MacroRecorder::new();
+        let mut registers = Registers::new();
+        let buffer = Buffer::new();
+
+        recorder.start('a').unwrap();
+        for key in [KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE), KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)] {
+            recorder.record(key);
+        }
+        assert_eq!(recorder.stop(&mut registers).unwrap(), Some('a'));
+        assert_eq!(registers.get(Some('a'), &buffer).unwrap(), Some(RegisterContent::chars("x<Escape>")));
+        assert_eq!(registers.get(None, &buffer).unwrap(), None);
+
+        // Register text edited by hand plays back as keys, and `@@` plays it again
+        registers.set('a', RegisterContent::chars("A!<Esc>")).unwrap();
+        assert_eq!(recorder.keys('a', &registers, &buffer).unwrap().len(), 3);
+        assert_eq!(recorder.keys('@', &registers, &buffer).unwrap()[2].code, KeyCode::Esc);
+        assert!(recorder.keys('b', &registers, &buffer).is_err());
+        assert!(recorder.start('%').is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! File marks and the jump list
+//!
+//! Marks `A`-`Z` remember a file as well as a position, so `'A` opens the
+//! file again, and are saved to disk to outlast the session. The jump list
+//! remembers where the cursor was before jumps such as `G`, searches,
+//! going to a mark or switching files, for `Ctrl-o` and `Ctrl-i`. Both
+//! follow the edits of an open file the way its buffer marks do.
+
+use crate::core::buffer::{Buffer, MarkSet};
+use crate::core::cursor::{Position, TextChange};
+use crate::{Result, RuskError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Most jumps remembered
+const MAX_JUMPS: usize = 100;
+
+/// A position in a file, or in the buffer without one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub path: Option<PathBuf>,
+    pub position: Position,
+}
+
+impl Location {
+    /// Get the cursor location in a buffer
+    pub fn cursor(buffer: &Buffer) -> Self {
+        let (line, column) = buffer.cursor_position();
+        Self { path: buffer.file_path().map(absolute), position: Position::new(line, column) }
+    }
+
+    /// Whether the location is in a buffer
+    pub fn is_in(&self, buffer: &Buffer) -> bool {
+        self.path == buffer.file_path().map(absolute)
+    }
+}
+
+/// Make a path absolute so the same file compares equal however it was opened
+fn absolute(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    fs::canonicalize(path).unwrap_or_else(|_| std::env::current_dir().map_or_else(|_| path.to_path_buf(), |cwd| cwd.join(path)))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedMark {
+    path: PathBuf,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SavedMarks {
+    #[serde(default)]
+    marks: BTreeMap<String, SavedMark>,
+}
+
+/// File marks and jump list shared by every buffer
+#[derive(Debug, Clone, Default)]
+pub struct Marks {
+    files: BTreeMap<char, (PathBuf, Position)>,
+    /// File the file marks are saved to
+    storage: Option<PathBuf>,
+    jumps: Vec<Location>,
+    /// Entry of the jump list last moved to, the length when at the newest end
+    jump_index: usize,
+    /// Where the cursor was before the latest jump, the `'` mark
+    previous: Option<Location>,
+}
+
+impl Marks {
+    /// Create marks that are not saved
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get where file marks are kept between sessions
+    pub fn default_storage() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("rusk").join("marks.toml"))
+    }
+
+    /// Load file marks saved to a file, saving later changes there too
+    pub fn load(storage: impl Into<PathBuf>) -> Result<Self> {
+        let storage = storage.into();
+        let saved = match fs::read_to_string(&storage) {
+            Ok(content) => toml::from_str::<SavedMarks>(&content)
+                .map_err(|e| RuskError::Config(format!("Failed to parse marks file '{}': {}", storage.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SavedMarks::default(),
+            Err(e) => return Err(RuskError::io_string(format!("Failed to read marks file '{}': {}", storage.display(), e))),
+        };
+
+        let mut marks = Self { storage: Some(storage), ..Self::default() };
+        for (name, mark) in saved.marks {
+            if let Some(name) = name.chars().next().filter(|name| name.is_ascii_uppercase()) {
+                marks.files.insert(name, (mark.path, Position::new(mark.line, mark.column)));
+            }
+        }
+        Ok(marks)
+    }
+
+    /// Set a file mark `A`-`Z` and save the file marks
+    pub fn set_file_mark(&mut self, name: char, path: impl AsRef<Path>, position: Position) -> Result<()> {
+        if !name.is_ascii_uppercase() {
+            return Err(RuskError::Command(format!("Invalid file mark: '{}'", name)));
+        }
+        self.files.insert(name, (absolute(path), position));
+        self.save()
+    }
+
+    /// Remove a file mark, returning whether it was set
+    pub fn remove_file_mark(&mut self, name: char) -> Result<bool> {
+        let removed = self.files.remove(&name).is_some();
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// Get a file mark
+    pub fn file_mark(&self, name: char) -> Option<Location> {
+        self.files.get(&name).map(|(path, position)| Location { path: Some(path.clone()), position: *position })
+    }
+
+    /// Iterate over the file marks in name order
+    pub fn file_marks(&self) -> impl Iterator<Item = (char, Location)> + '_ {
+        self.files.keys().filter_map(|name| Some((*name, self.file_mark(*name)?)))
+    }
+
+    /// Move the file marks and jumps in a buffer's file with its edits, saving file marks that moved
+    ///
+    /// A file mark on a deleted line goes with it, like a buffer mark;
+    /// jumps there move to where the deletion was.
+    pub fn apply_changes(&mut self, buffer: &Buffer, changes: &[TextChange]) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let path = buffer.file_path().map(absolute);
+
+        let mut moved = false;
+        if let Some(path) = &path {
+            self.files.retain(|_, (file, position)| {
+                if file != path {
+                    return true;
+                }
+                let mapped = changes.iter().try_fold(*position, |pos, change| MarkSet::map(change, pos));
+                moved |= mapped != Some(*position);
+                mapped.inspect(|mapped| *position = *mapped).is_some()
+            });
+        }
+
+        let locations = self.jumps.iter_mut().chain(&mut self.previous);
+        for location in locations.filter(|location| location.path == path) {
+            location.position = changes.iter().fold(location.position, |pos, change| change.map_position(pos));
+        }
+
+        if moved {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Write the file marks to their storage
+    fn save(&self) -> Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        let saved = SavedMarks {
+            marks: self.files.iter()
+                .map(|(name, (path, position))| {
+                    (name.to_string(), SavedMark { path: path.clone(), line: position.line, column: position.column })
+                })
+                .collect(),
+        };
+        let content = toml::to_string_pretty(&saved)
+            .map_err(|e| RuskError::Config(format!("Failed to serialize marks: {}", e)))?;
+        if let Some(dir) = storage.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(storage, content)
+            .map_err(|e| RuskError::io_string(format!("Failed to write marks file '{}': {}", storage.display(), e)))
+    }
+
+    /// Remember where a jump started, replacing an older entry for the same line
+    pub fn push_jump(&mut self, location: Location) {
+        self.jumps.retain(|jump| jump.path != location.path || jump.position.line != location.position.line);
+        self.previous = Some(location.clone());
+        self.jumps.push(location);
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.jump_index = self.jumps.len();
+    }
+
+    /// Get where the cursor was before the latest jump, where `''` goes back to
+    pub fn previous(&self) -> Option<&Location> {
+        self.previous.as_ref()
+    }
+
+    /// Get the jump list, oldest first, and the entry last moved to
+    pub fn jumps(&self) -> (&[Location], usize) {
+        (&self.jumps, self.jump_index)
+    }
+
+    /// Move `count` entries back in the jump list, as `Ctrl-o` does
+    pub fn jump_back(&mut self, current: Location, count: usize) -> Option<Location> {
+        if self.jump_index == self.jumps.len() {
+            // Remember where going back started, so `Ctrl-i` can return there
+            self.push_jump(current.clone());
+            self.jump_index = self.jumps.len() - 1;
+        }
+        self.jump_index = self.jump_index.checked_sub(count.max(1))?;
+        self.previous = Some(current);
+        Some(self.jumps[self.jump_index].clone())
+    }
+
+    /// Move `count` entries forward in the jump list, as `Ctrl-i` does
+    pub fn jump_forward(&mut self, current: Location, count: usize) -> Option<Location> {
+        let index = self.jump_index + count.max(1);
+        let jump = self.jumps.get(index)?.clone();
+        self.jump_index = index;
+        self.previous = Some(current);
+        Some(jump)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::cursor::TextRange;
+
+    fn at(path: &str, line: usize) -> Location {
+        Location { path: Some(PathBuf::from(path)), position: Position::new(line, 0) }
+    }
+
+    #[test]
+    fn test_jump_list_across_files() {
+        let mut marks = Marks::new();
+        marks.push_jump(at("/a.rs", 1));
+        marks.push_jump(at("/b.rs", 40));
+        marks.push_jump(at("/a.rs", 1));
+        assert_eq!(marks.jumps().0, &[at("/b.rs", 40), at("/a.rs", 1)]);
+
+        assert_eq!(marks.jump_back(at("/c.rs", 7), 1), Some(at("/a.rs", 1)));
+        assert_eq!(marks.jump_back(at("/a.rs", 1), 1), Some(at("/b.rs", 40)));
+        assert_eq!(marks.jump_back(at("/b.rs", 40), 1), None);
+        assert_eq!(marks.jump_forward(at("/b.rs", 40), 2), Some(at("/c.rs", 7)));
+        assert_eq!(marks.jump_forward(at("/c.rs", 7), 1), None);
+        assert_eq!(marks.previous(), Some(&at("/b.rs", 40)));
+    }
+
+    #[test]
+    fn test_file_marks_are_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = dir.path().join("marks.toml");
+        let file = dir.path().join("notes.txt");
+        fs::write(&file, "text").unwrap();
+
+        let mut marks = Marks::load(&storage).unwrap();
+        marks.set_file_mark('N', &file, Position::new(3, 2)).unwrap();
+        assert!(marks.set_file_mark('n', &file, Position::new(0, 0)).is_err());
+
+        let reloaded = Marks::load(&storage).unwrap();
+        assert_eq!(reloaded.file_mark('N'), Some(Location { path: Some(absolute(&file)), position: Position::new(3, 2) }));
+    }
+
+    #[test]
+    fn test_file_marks_and_jumps_follow_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = dir.path().join("marks.toml");
+        let file = dir.path().join("notes.txt");
+        fs::write(&file, "one\ntwo\nthree").unwrap();
+        let mut buffer = Buffer::from_file(&file).unwrap();
+        let elsewhere = Location { path: Some(absolute(dir.path().join("other.txt"))), position: Position::new(1, 0) };
+
+        let mut marks = Marks::load(&storage).unwrap();
+        marks.set_file_mark('T', &file, Position::new(2, 1)).unwrap();
+        marks.set_file_mark('W', &file, Position::new(1, 0)).unwrap();
+        marks.push_jump(Location { path: Some(absolute(&file)), position: Position::new(1, 2) });
+        marks.push_jump(elsewhere.clone());
+
+        // A line opened above moves everything in the file down
+        buffer.insert_text(Position::new(0, 0), "zero\n");
+        let changes = buffer.take_changes();
+        marks.apply_changes(&buffer, &changes).unwrap();
+        assert_eq!(marks.file_mark('T').unwrap().position, Position::new(3, 1));
+        assert_eq!(marks.file_mark('W').unwrap().position, Position::new(2, 0));
+        assert_eq!(marks.jumps().0, &[Location { path: Some(absolute(&file)), position: Position::new(2, 2) }, elsewhere]);
+
+        // Deleting a marked line removes its file mark, saved that way, and moves the jump there to the deletion
+        buffer.delete_range(&TextRange::new(Position::new(2, 0), Position::new(3, 0)));
+        let changes = buffer.take_changes();
+        marks.apply_changes(&buffer, &changes).unwrap();
+        let reloaded = Marks::load(&storage).unwrap();
+        assert_eq!(reloaded.file_mark('W'), None);
+        assert_eq!(reloaded.file_mark('T').unwrap().position, Position::new(2, 1));
+        assert_eq!(marks.jumps().0[0].position, Position::new(2, 0));
+    }
+}
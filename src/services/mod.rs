@@ -5,4 +5,11 @@
 
 pub mod event_bus;
 pub mod command_system;
+pub mod registers;
+pub mod clipboard;
+pub mod macros;
+pub mod marks;
+pub mod operators;
+pub mod visual_block;
+pub mod ex;
 pub mod logger;
\ No newline at end of file
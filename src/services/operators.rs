@@ -0,0 +1,689 @@
+//! Operators and the operator-pending grammar
+//!
+//! Normal mode commands follow `[count][operator][count][motion]`: `d3w`
+//! deletes three words, `2y}` yanks two paragraphs and `gUiw` uppercases the
+//! word under the cursor. A doubled operator, such as `dd` or `gUU`, works
+//! on whole lines. The parser turns keys into an [`Operation`] or a bare
+//! motion, and the last change is kept so `.` can repeat it. Syntax text
+//! objects such as `af` are parsed here too, but need the syntax tree to
+//! find what they cover, so [`apply_to`] takes the range found for them.
+//!
+//! Motions are exclusive, inclusive or linewise as in vim, including its
+//! rule that an exclusive motion ending at the start of a later line stops
+//! at the end of the line before, becoming linewise when it started at or
+//! before the first non-blank character.
+
+use crate::core::buffer::Buffer;
+use crate::core::cursor::movement::{Movement, ScreenPlace};
+use crate::core::cursor::{textobject, Position, TextObject, TextRange};
+use crate::plugins::implementations::language::textobjects::TextObjectKind;
+use crate::services::registers::{RegisterContent, Registers, WriteKind};
+use crate::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// What an operator does to the text a motion covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Uppercase,
+    Lowercase,
+    ToggleCase,
+    IndentRight,
+    IndentLeft,
+}
+
+impl Operator {
+    /// Every operator, in the order their commands are registered
+    pub const ALL: [Operator; 8] = [
+        Operator::Delete, Operator::Change, Operator::Yank, Operator::Uppercase,
+        Operator::Lowercase, Operator::ToggleCase, Operator::IndentRight, Operator::IndentLeft,
+    ];
+
+    /// Get the command that starts the operator
+    pub fn command(self) -> &'static str {
+        match self {
+            Self::Delete => "operator_delete",
+            Self::Change => "operator_change",
+            Self::Yank => "operator_yank",
+            Self::Uppercase => "operator_uppercase",
+            Self::Lowercase => "operator_lowercase",
+            Self::ToggleCase => "operator_toggle_case",
+            Self::IndentRight => "operator_indent",
+            Self::IndentLeft => "operator_dedent",
+        }
+    }
+
+    /// Get the operator a command starts
+    pub fn from_command(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|operator| operator.command() == name)
+    }
+
+    /// Get the description shown for the operator's command
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Delete => "Delete the text a motion covers",
+            Self::Change => "Replace the text a motion covers",
+            Self::Yank => "Copy the text a motion covers",
+            Self::Uppercase => "Make the text a motion covers uppercase",
+            Self::Lowercase => "Make the text a motion covers lowercase",
+            Self::ToggleCase => "Switch the case of the text a motion covers",
+            Self::IndentRight => "Indent the lines a motion covers",
+            Self::IndentLeft => "Remove an indent from the lines a motion covers",
+        }
+    }
+
+    /// Keys that type the operator in vim
+    pub fn keys(self) -> &'static str {
+        match self {
+            Self::Delete => "d",
+            Self::Change => "c",
+            Self::Yank => "y",
+            Self::Uppercase => "gU",
+            Self::Lowercase => "gu",
+            Self::ToggleCase => "g~",
+            Self::IndentRight => ">",
+            Self::IndentLeft => "<",
+        }
+    }
+
+    /// Last key of the operator, which typed again works on whole lines
+    fn line_key(self) -> char {
+        self.keys().chars().next_back().unwrap_or_default()
+    }
+
+    /// Whether the operator edits the text, which `.` can repeat
+    pub fn is_change(self) -> bool {
+        self != Self::Yank
+    }
+}
+
+/// Where the cursor moves, or what an operator covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward { big: bool },
+    WordEnd { big: bool },
+    WordBackward { big: bool },
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    Find { ch: char, forward: bool, till: bool },
+    MatchBracket,
+    SentenceForward,
+    SentenceBackward,
+    ParagraphForward,
+    ParagraphBackward,
+    Screen(ScreenPlace),
+    FileStart,
+    FileEnd,
+    /// Whole lines from the cursor's, as a doubled operator covers
+    Lines,
+    Object { object: TextObject, around: bool },
+    /// A function, class, parameter or list item found in the syntax tree
+    Syntax { kind: TextObjectKind, around: bool },
+}
+
+/// How much of the text between the cursor and a motion's end an operator covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MotionKind {
+    Exclusive,
+    Inclusive,
+    Linewise,
+}
+
+/// An operator with the motion it applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub operator: Operator,
+    pub motion: Motion,
+    /// Counts typed before the operator and the motion, multiplied
+    pub count: Option<usize>,
+    pub register: Option<char>,
+}
+
+/// What a key fed to the parser completed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Parsed {
+    /// More keys are needed
+    Pending,
+    Operation(Operation),
+    Motion(Motion, Option<usize>),
+    /// `.` with its count
+    Repeat(Option<usize>),
+    /// Escape dropped what was typed
+    Cancelled,
+    /// The key does not fit the grammar, dropping what was typed
+    Invalid,
+}
+
+/// Character keys still to come before a motion is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Awaiting {
+    G,
+    Find { forward: bool, till: bool },
+    Object { around: bool },
+}
+
+/// Parser of counts, operators and motions typed in normal mode
+#[derive(Debug, Clone, Default)]
+pub struct OperatorParser {
+    count: Option<usize>,
+    operator: Option<(Operator, Option<usize>)>,
+    motion_count: Option<usize>,
+    awaiting: Option<Awaiting>,
+    /// Last `f`, `t`, `F` or `T`, repeated by `;` and `,`
+    last_find: Option<(char, bool, bool)>,
+    typed: String,
+}
+
+impl OperatorParser {
+    /// Create a parser with nothing typed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether an operator or a motion is partly typed, so the next key belongs to the parser
+    pub fn is_active(&self) -> bool {
+        self.operator.is_some() || self.awaiting.is_some()
+    }
+
+    /// Whether a count is typed
+    pub fn has_count(&self) -> bool {
+        self.count.is_some()
+    }
+
+    /// Get the keys typed so far, shown while they wait for more
+    pub fn typed(&self) -> &str {
+        &self.typed
+    }
+
+    /// Take the count typed before a key the parser does not handle
+    pub fn take_count(&mut self) -> Option<usize> {
+        let count = self.count.take();
+        self.reset();
+        count
+    }
+
+    /// Start an operator with the count typed before it
+    pub fn start(&mut self, operator: Operator, count: Option<usize>) {
+        self.reset();
+        self.operator = Some((operator, count));
+        if let Some(count) = count {
+            self.typed = count.to_string();
+        }
+        self.typed.push_str(operator.keys());
+    }
+
+    /// Whether a key not bound in normal mode is one the parser knows as a motion or `.`
+    pub fn is_motion_key(key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char(ch) if key.modifiers.difference(KeyModifiers::SHIFT).is_empty() => {
+                "hjklwWbBeE0^$%(){}HMLGfFtT;,.".contains(ch)
+            }
+            _ => false,
+        }
+    }
+
+    /// Feed a key typed while the parser is active, or a digit or motion key when it is not
+    pub fn feed(&mut self, key: KeyEvent) -> Parsed {
+        let ch = match key.code {
+            KeyCode::Char(ch) if key.modifiers.difference(KeyModifiers::SHIFT).is_empty() => ch,
+            KeyCode::Esc => {
+                self.reset();
+                return Parsed::Cancelled;
+            }
+            _ => return self.invalid(),
+        };
+
+        if let Some(awaiting) = self.awaiting.take() {
+            return match awaiting {
+                Awaiting::Find { forward, till } => {
+                    self.last_find = Some((ch, forward, till));
+                    self.finish(Motion::Find { ch, forward, till })
+                }
+                Awaiting::Object { around } => match (TextObject::from_char(ch), TextObjectKind::from_char(ch)) {
+                    (Some(object), _) => self.finish(Motion::Object { object, around }),
+                    (None, Some(kind)) => self.finish(Motion::Syntax { kind, around }),
+                    (None, None) => self.invalid(),
+                },
+                Awaiting::G => match ch {
+                    'g' => self.finish(Motion::FileStart),
+                    // `gUgU` is `gUU`
+                    'U' | 'u' | '~' if self.operator.is_some_and(|(operator, _)| operator.line_key() == ch) => self.finish(Motion::Lines),
+                    _ => self.invalid(),
+                },
+            };
+        }
+
+        let slot = if self.operator.is_some() { &mut self.motion_count } else { &mut self.count };
+        if let Some(digit) = ch.to_digit(10)
+            && (digit != 0 || slot.is_some())
+        {
+            *slot = Some(slot.unwrap_or(0).saturating_mul(10).saturating_add(digit as usize));
+            self.typed.push(ch);
+            return Parsed::Pending;
+        }
+
+        if let Some((operator, _)) = self.operator {
+            match ch {
+                ch if ch == operator.line_key() => return self.finish(Motion::Lines),
+                'i' | 'a' => {
+                    self.awaiting = Some(Awaiting::Object { around: ch == 'a' });
+                    self.typed.push(ch);
+                    return Parsed::Pending;
+                }
+                _ => {}
+            }
+        } else if ch == '.' {
+            let count = self.count.take();
+            self.reset();
+            return Parsed::Repeat(count);
+        }
+
+        let motion = match ch {
+            'h' => Motion::Left,
+            'l' | ' ' => Motion::Right,
+            'j' => Motion::Down,
+            'k' => Motion::Up,
+            'w' | 'W' => Motion::WordForward { big: ch == 'W' },
+            'e' | 'E' => Motion::WordEnd { big: ch == 'E' },
+            'b' | 'B' => Motion::WordBackward { big: ch == 'B' },
+            '0' => Motion::LineStart,
+            '^' => Motion::FirstNonBlank,
+            '$' => Motion::LineEnd,
+            '%' => Motion::MatchBracket,
+            '(' => Motion::SentenceBackward,
+            ')' => Motion::SentenceForward,
+            '{' => Motion::ParagraphBackward,
+            '}' => Motion::ParagraphForward,
+            'H' => Motion::Screen(ScreenPlace::Top),
+            'M' => Motion::Screen(ScreenPlace::Middle),
+            'L' => Motion::Screen(ScreenPlace::Bottom),
+            'G' => Motion::FileEnd,
+            ';' | ',' => match self.last_find {
+                Some((ch_found, forward, till)) => Motion::Find { ch: ch_found, forward: forward == (ch == ';'), till },
+                None => return self.invalid(),
+            },
+            'f' | 'F' | 't' | 'T' => {
+                self.awaiting = Some(Awaiting::Find { forward: ch.is_lowercase(), till: ch.eq_ignore_ascii_case(&'t') });
+                self.typed.push(ch);
+                return Parsed::Pending;
+            }
+            'g' => {
+                self.awaiting = Some(Awaiting::G);
+                self.typed.push(ch);
+                return Parsed::Pending;
+            }
+            _ => return self.invalid(),
+        };
+        self.finish(motion)
+    }
+
+    fn finish(&mut self, motion: Motion) -> Parsed {
+        let parsed = match self.operator.take() {
+            Some((operator, count)) => {
+                let count = match (count, self.motion_count) {
+                    (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+                    (a, b) => a.or(b),
+                };
+                Parsed::Operation(Operation { operator, motion, count, register: None })
+            }
+            None => Parsed::Motion(motion, self.count),
+        };
+        self.reset();
+        parsed
+    }
+
+    fn invalid(&mut self) -> Parsed {
+        self.reset();
+        Parsed::Invalid
+    }
+
+    fn reset(&mut self) {
+        let last_find = self.last_find;
+        *self = Self { last_find, ..Self::default() };
+    }
+}
+
+/// What motions and operators need to know beyond the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionEnv {
+    /// First line on screen and the number shown, for `H`, `M` and `L`
+    pub top: usize,
+    pub height: usize,
+    pub tab_size: usize,
+    pub insert_spaces: bool,
+}
+
+impl MotionEnv {
+    fn indent_unit(&self) -> String {
+        if self.insert_spaces { " ".repeat(self.tab_size.max(1)) } else { "\t".to_string() }
+    }
+}
+
+/// Where a motion takes the cursor and how much an operator using it covers
+fn target(motion: Motion, count: Option<usize>, lines: &[String], cursor: Position, env: &MotionEnv) -> Option<(Position, MotionKind)> {
+    use MotionKind::{Exclusive, Inclusive, Linewise};
+    let n = count.unwrap_or(1).max(1);
+    let last_line = lines.len() - 1;
+    let line_start = |line: usize| Position::new(line, Movement::first_non_blank(lines, line));
+    // Apply a motion `n` times, stopping once it no longer moves
+    let repeat = |step: &dyn Fn(Position) -> Position| {
+        let mut p = cursor;
+        for _ in 0..n {
+            let next = step(p);
+            if next == p {
+                break;
+            }
+            p = next;
+        }
+        (p != cursor).then_some(p)
+    };
+
+    Some(match motion {
+        Motion::Left => {
+            let line = &lines[cursor.line];
+            let column = line[..cursor.column].char_indices().rev().take(n).last()?.0;
+            (Position::new(cursor.line, column), Exclusive)
+        }
+        Motion::Right => {
+            let line = &lines[cursor.line];
+            let mut chars = line[cursor.column..].char_indices().skip(n);
+            let column = chars.next().map_or(line.len(), |(i, _)| cursor.column + i);
+            if column == cursor.column {
+                return None;
+            }
+            (Position::new(cursor.line, column), Exclusive)
+        }
+        Motion::Up => (Position::new(cursor.line.checked_sub(n)?, cursor.column), Linewise),
+        Motion::Down => {
+            let line = cursor.line + n;
+            if line > last_line {
+                return None;
+            }
+            (Position::new(line, cursor.column), Linewise)
+        }
+        Motion::WordForward { big } => (repeat(&|p| Movement::word_forward(lines, p, big))?, Exclusive),
+        Motion::WordEnd { big } => (repeat(&|p| Movement::word_end(lines, p, big))?, Inclusive),
+        Motion::WordBackward { big } => (repeat(&|p| Movement::word_backward(lines, p, big))?, Exclusive),
+        Motion::LineStart => (Position::new(cursor.line, 0), Exclusive),
+        Motion::FirstNonBlank => (line_start(cursor.line), Exclusive),
+        Motion::LineEnd => {
+            let line = cursor.line + n - 1;
+            if line > last_line {
+                return None;
+            }
+            (Position::new(line, Movement::last_char(lines, line)), Inclusive)
+        }
+        Motion::Find { ch, forward, till } => {
+            let found = Movement::find_char(lines, cursor, ch, forward, till, n)?;
+            (found, if forward { Inclusive } else { Exclusive })
+        }
+        Motion::MatchBracket => match count {
+            // `50%` goes to the line half way through
+            Some(percent) => (line_start(((percent * lines.len()).div_ceil(100)).clamp(1, lines.len()) - 1), Linewise),
+            None => (Movement::matching_bracket(lines, cursor)?, Inclusive),
+        },
+        Motion::SentenceForward => (repeat(&|p| Movement::sentence_forward(lines, p))?, Exclusive),
+        Motion::SentenceBackward => (repeat(&|p| Movement::sentence_backward(lines, p))?, Exclusive),
+        Motion::ParagraphForward => (repeat(&|p| Movement::paragraph_forward(lines, p))?, Exclusive),
+        Motion::ParagraphBackward => (repeat(&|p| Movement::paragraph_backward(lines, p))?, Exclusive),
+        Motion::Screen(place) => (line_start(Movement::screen_line(env.top, env.height, lines.len(), place, n - 1)), Linewise),
+        Motion::FileStart => (line_start(count.map_or(0, |line| line - 1).min(last_line)), Linewise),
+        Motion::FileEnd => (line_start(count.map_or(last_line, |line| line - 1).min(last_line)), Linewise),
+        Motion::Lines => (Position::new((cursor.line + n - 1).min(last_line), 0), Linewise),
+        Motion::Object { .. } | Motion::Syntax { .. } => return None,
+    })
+}
+
+/// Move the cursor by a motion, returning whether it could move
+///
+/// Normal mode keeps the cursor on a character, never past the end of the line.
+pub fn move_cursor(motion: Motion, count: Option<usize>, buffer: &mut Buffer, env: &MotionEnv) -> bool {
+    let (line, column) = buffer.cursor_position();
+    let cursor = Position::new(line, column);
+    let Some((target, _)) = target(motion, count, buffer.lines(), cursor, env) else {
+        return false;
+    };
+    let column = target.column.min(Movement::last_char(buffer.lines(), target.line));
+    buffer.set_cursor_position(target.line, column);
+    buffer.reveal_cursor_line();
+    buffer.update_selection();
+    true
+}
+
+/// Text an operation covers, and whether it is whole lines
+fn operation_range(operation: &Operation, lines: &[String], cursor: Position, env: &MotionEnv) -> Option<(TextRange, bool)> {
+    let linewise = |first: usize, last: usize| {
+        (TextRange::new(Position::new(first, 0), Position::new(last, lines[last].len())), true)
+    };
+
+    if let Motion::Object { object, around } = operation.motion {
+        let selected = textobject::select(lines, cursor, object, around, operation.count.unwrap_or(1))?;
+        return Some((selected.range, selected.linewise));
+    }
+
+    let motion = operation.motion;
+    if operation.operator == Operator::Change
+        && let Motion::WordForward { big } = motion
+        && !Movement::char_at(lines, cursor).is_none_or(char::is_whitespace)
+    {
+        // `cw` on a word changes to its end, like `ce`, leaving the blanks after it
+        let mut end = Movement::end_of_word(lines, cursor, big);
+        for _ in 1..operation.count.unwrap_or(1).max(1) {
+            end = Movement::word_end(lines, end, big);
+        }
+        return Some((TextRange::new(cursor, inclusive_end(lines, end)), false));
+    }
+
+    let (target, kind) = target(motion, operation.count, lines, cursor, env)?;
+    let range = TextRange::new(cursor, target);
+    match kind {
+        MotionKind::Linewise => Some(linewise(range.start.line, range.end.line)),
+        MotionKind::Inclusive => Some((TextRange::new(range.start, inclusive_end(lines, range.end)), false)),
+        MotionKind::Exclusive => {
+            let (start, mut end) = (range.start, range.end);
+            if end.column == 0 && end.line > start.line && !matches!(motion, Motion::Lines) {
+                end = Position::new(end.line - 1, lines[end.line - 1].len());
+                if start.column <= Movement::first_non_blank(lines, start.line) {
+                    return Some(linewise(start.line, end.line));
+                }
+            }
+            let range = TextRange::new(start, end);
+            (!range.is_empty()).then_some((range, false))
+        }
+    }
+}
+
+/// Position just after the character at `pos`, not past the end of its line
+fn inclusive_end(lines: &[String], pos: Position) -> Position {
+    let line = &lines[pos.line];
+    let width = line[pos.column.min(line.len())..].chars().next().map_or(0, char::len_utf8);
+    Position::new(pos.line, pos.column + width)
+}
+
+/// Range of whole lines with a line break, the one before them when they end the buffer
+fn whole_lines_range(buffer: &Buffer, first: usize, last: usize) -> TextRange {
+    let lines = buffer.lines();
+    if last + 1 < lines.len() {
+        TextRange::new(Position::new(first, 0), Position::new(last + 1, 0))
+    } else if first > 0 {
+        TextRange::new(Position::new(first - 1, lines[first - 1].len()), Position::new(last, lines[last].len()))
+    } else {
+        TextRange::new(Position::new(first, 0), Position::new(last, lines[last].len()))
+    }
+}
+
+/// Apply an operation at the cursor, returning whether its motion could move
+///
+/// Deleted and changed text goes to the operation's register as a delete,
+/// yanked text as a yank. A change leaves the cursor where insert mode
+/// should start. Syntax text objects need the syntax tree, so they fail
+/// here; find their range and use [`apply_to`].
+pub fn apply(operation: &Operation, buffer: &mut Buffer, registers: &mut Registers, env: &MotionEnv) -> Result<bool> {
+    let (line, column) = buffer.cursor_position();
+    let Some((range, linewise)) = operation_range(operation, buffer.lines(), Position::new(line, column), env) else {
+        return Ok(false);
+    };
+    apply_to(operation, range, linewise, buffer, registers, env)?;
+    Ok(true)
+}
+
+/// Apply an operation to the text it covers, found by the caller
+pub fn apply_to(operation: &Operation, range: TextRange, linewise: bool, buffer: &mut Buffer, registers: &mut Registers, env: &MotionEnv) -> Result<()> {
+    let (line, column) = buffer.cursor_position();
+    let cursor = Position::new(line, column);
+    let (first, last) = (range.start.line, range.end.line);
+    let content = if linewise {
+        RegisterContent::lines(buffer.lines()[first..=last].join("\n"))
+    } else {
+        RegisterContent::chars(buffer.text_in_range(&range))
+    };
+
+    match operation.operator {
+        Operator::Yank => {
+            registers.write(operation.register, content, WriteKind::Yank)?;
+            if !linewise {
+                buffer.set_cursor_position(range.start.line, range.start.column);
+            } else if first < cursor.line {
+                buffer.set_cursor_position(first, cursor.column);
+            }
+        }
+        Operator::Delete => {
+            registers.write(operation.register, content, WriteKind::Delete)?;
+            if linewise {
+                buffer.delete_range(&whole_lines_range(buffer, first, last));
+                let line = first.min(buffer.line_count() - 1);
+                buffer.set_cursor_position(line, Movement::first_non_blank(buffer.lines(), line));
+            } else {
+                buffer.delete_range(&range);
+                let column = range.start.column.min(Movement::last_char(buffer.lines(), first));
+                buffer.set_cursor_position(first, column);
+            }
+        }
+        Operator::Change => {
+            registers.write(operation.register, content, WriteKind::Delete)?;
+            if linewise {
+                // The changed lines become one, keeping the first line's indent
+                let indent_width = Movement::first_non_blank(buffer.lines(), first);
+                let indent = buffer.lines()[first][..indent_width].to_string();
+                let end = buffer.replace_range(&range, &indent);
+                buffer.set_cursor_position(end.line, end.column);
+            } else {
+                buffer.delete_range(&range);
+            }
+        }
+        Operator::Uppercase | Operator::Lowercase | Operator::ToggleCase => {
+            let converted: String = content.text.chars().map(|ch| match operation.operator {
+                Operator::Uppercase => ch.to_uppercase().collect::<String>(),
+                Operator::Lowercase => ch.to_lowercase().collect(),
+                _ if ch.is_uppercase() => ch.to_lowercase().collect(),
+                _ => ch.to_uppercase().collect(),
+            }).collect();
+            if converted != content.text {
+                buffer.replace_range(&range, &converted);
+            }
+            let start = if linewise && first == cursor.line { cursor } else { range.start };
+            buffer.set_cursor_position(start.line, start.column);
+        }
+        Operator::IndentRight | Operator::IndentLeft => {
+            let unit = env.indent_unit();
+            for line in first..=last {
+                let text = &buffer.lines()[line];
+                if operation.operator == Operator::IndentRight {
+                    if !text.is_empty() {
+                        buffer.insert_text(Position::new(line, 0), &unit);
+                    }
+                    continue;
+                }
+                let width = if text.starts_with('\t') {
+                    1
+                } else {
+                    text.len() - text.trim_start_matches(' ').len()
+                }.min(unit.len().max(env.tab_size));
+                if width > 0 {
+                    buffer.delete_range(&TextRange::new(Position::new(line, 0), Position::new(line, width)));
+                }
+            }
+            buffer.set_cursor_position(first, Movement::first_non_blank(buffer.lines(), first));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(ch: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE)
+    }
+
+    fn parse(parser: &mut OperatorParser, operator: Operator, keys: &str) -> Parsed {
+        let count = parser.take_count();
+        parser.start(operator, count);
+        keys.chars().fold(Parsed::Pending, |_, ch| parser.feed(key(ch)))
+    }
+
+    fn run(text: &str, cursor: (usize, usize), operator: Operator, keys: &str) -> (String, (usize, usize), Registers) {
+        let env = MotionEnv { top: 0, height: 20, tab_size: 4, insert_spaces: true };
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::new(0, 0), text);
+        buffer.set_cursor_position(cursor.0, cursor.1);
+        let mut registers = Registers::new();
+        let Parsed::Operation(operation) = parse(&mut OperatorParser::new(), operator, keys) else {
+            panic!("{} is not an operation", keys);
+        };
+        assert!(apply(&operation, &mut buffer, &mut registers, &env).unwrap());
+        (buffer.text(), buffer.cursor_position(), registers)
+    }
+
+    #[test]
+    fn test_parse_counts_and_doubled_operators() {
+        let mut parser = OperatorParser::new();
+        assert_eq!(parser.feed(key('2')), Parsed::Pending);
+        let Parsed::Operation(operation) = parse(&mut parser, Operator::Delete, "3w") else { panic!() };
+        assert_eq!((operation.motion, operation.count), (Motion::WordForward { big: false }, Some(6)));
+
+        assert!(matches!(parse(&mut parser, Operator::Uppercase, "gU"), Parsed::Operation(Operation { motion: Motion::Lines, .. })));
+        assert!(matches!(parse(&mut parser, Operator::Yank, "i("), Parsed::Operation(Operation { motion: Motion::Object { around: false, .. }, .. })));
+        assert!(matches!(parse(&mut parser, Operator::Delete, "af"), Parsed::Operation(Operation { motion: Motion::Syntax { kind: TextObjectKind::Function, around: true }, .. })));
+        assert_eq!(parse(&mut parser, Operator::Delete, "q"), Parsed::Invalid);
+        assert!(!parser.is_active());
+
+        // `;` repeats the last find and `,` reverses it
+        assert_eq!(parser.feed(key('t')), Parsed::Pending);
+        assert_eq!(parser.feed(key('x')), Parsed::Motion(Motion::Find { ch: 'x', forward: true, till: true }, None));
+        assert_eq!(parser.feed(key(',')), Parsed::Motion(Motion::Find { ch: 'x', forward: false, till: true }, None));
+        assert_eq!(parser.feed(key('3')), Parsed::Pending);
+        assert_eq!(parser.feed(key('.')), Parsed::Repeat(Some(3)));
+    }
+
+    #[test]
+    fn test_operators_over_motions() {
+        assert_eq!(run("one two three", (0, 0), Operator::Delete, "2w").0, "three");
+        assert_eq!(run("one two three", (0, 4), Operator::Change, "w").0, "one  three");
+        assert_eq!(run("one two\nthree", (0, 4), Operator::Delete, "w").0, "one \nthree");
+        assert_eq!(run("one two\nthree", (0, 4), Operator::Delete, "$").0, "one \nthree");
+        let (text, cursor, registers) = run("a\nb\nc\nd", (1, 0), Operator::Delete, "j");
+        assert_eq!((text.as_str(), cursor), ("a\nd", (1, 0)));
+        assert_eq!(registers.get(Some('1'), &Buffer::new()).unwrap(), Some(RegisterContent::lines("b\nc")));
+        assert_eq!(run("f(a, b) x", (0, 3), Operator::Delete, "f)").0, "f(a x");
+        assert_eq!(run("f(a, b) x", (0, 1), Operator::Delete, "%").0, "f x");
+        assert_eq!(run("hello world", (0, 2), Operator::Uppercase, "iw").0, "HELLO world");
+        assert_eq!(run("Hello", (0, 0), Operator::ToggleCase, "~").0, "hELLO");
+        assert_eq!(run("a\n  b\n\nc", (0, 0), Operator::IndentRight, "ip").0, "    a\n      b\n\nc");
+        assert_eq!(run("    a\n\tb", (0, 0), Operator::IndentLeft, "j").0, "a\nb");
+        let (text, cursor, _) = run("  a\n  b\nc", (0, 2), Operator::Change, "j");
+        assert_eq!((text.as_str(), cursor), ("  \nc", (0, 2)));
+
+        let (text, cursor, registers) = run("one\ntwo", (1, 1), Operator::Yank, "k");
+        assert_eq!((text.as_str(), cursor), ("one\ntwo", (0, 1)));
+        assert_eq!(registers.get(Some('0'), &Buffer::new()).unwrap(), Some(RegisterContent::lines("one\ntwo")));
+    }
+}
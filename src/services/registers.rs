@@ -0,0 +1,255 @@
+//! Registers holding yanked and deleted text
+//!
+//! Registers follow vim. Yanks go to `"0`, deletes of a line or more shift
+//! through `"1`-`"9` and smaller deletes go to `"-`, while the unnamed
+//! register `""` always holds the text last written anywhere. `"a`-`"z`
+//! are written when named, `"A`-`"Z` append to them and `"_` discards.
+//! `".`, `"%` and `":` are read-only and `"+`/`"*` are the system clipboard.
+
+use crate::core::buffer::Buffer;
+use crate::services::clipboard::Clipboard;
+use crate::{Result, RuskError};
+use std::collections::BTreeMap;
+
+/// Text held by a register
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterContent {
+    /// The text, without a final newline for whole lines
+    pub text: String,
+    /// Whether the text is whole lines, put on lines of their own
+    pub linewise: bool,
+    /// Whether the text is a block of columns, one line per row, put in the same column on each line
+    pub blockwise: bool,
+}
+
+impl RegisterContent {
+    /// Create characterwise content
+    pub fn chars(text: impl Into<String>) -> Self {
+        Self { text: text.into(), linewise: false, blockwise: false }
+    }
+
+    /// Create content of whole lines
+    pub fn lines(text: impl Into<String>) -> Self {
+        Self { text: text.into(), linewise: true, blockwise: false }
+    }
+
+    /// Create a block of columns, its rows separated by line breaks
+    pub fn block(text: impl Into<String>) -> Self {
+        Self { text: text.into(), linewise: false, blockwise: true }
+    }
+}
+
+/// Why text is written to a register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteKind {
+    Yank,
+    Delete,
+}
+
+/// Register names in the order `:registers` lists them
+const LISTED: &str = "\"0123456789abcdefghijklmnopqrstuvwxyz-.:%+";
+
+/// Store of every register
+#[derive(Debug, Clone, Default)]
+pub struct Registers {
+    unnamed: Option<RegisterContent>,
+    /// Numbered, small delete and named registers
+    stored: BTreeMap<char, RegisterContent>,
+    /// Register chosen with `"x` for the next command
+    selected: Option<char>,
+    last_inserted: String,
+    last_command: String,
+    clipboard: Clipboard,
+}
+
+impl Registers {
+    /// Create empty registers using the OSC 52 clipboard
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the clipboard behind `"+` and `"*`
+    pub fn set_clipboard(&mut self, clipboard: Clipboard) {
+        self.clipboard = clipboard;
+    }
+
+    /// Get the clipboard behind `"+` and `"*`
+    pub fn clipboard(&self) -> &Clipboard {
+        &self.clipboard
+    }
+
+    /// Check that a register name exists
+    pub fn validate(name: char) -> Result<()> {
+        if name.is_ascii_alphanumeric() || "\"-_.:%+*".contains(name) {
+            Ok(())
+        } else {
+            Err(RuskError::Command(format!("Invalid register name: '{}'", name)))
+        }
+    }
+
+    /// Choose the register the next command uses, as `"x` does
+    pub fn select(&mut self, name: char) -> Result<()> {
+        Self::validate(name)?;
+        self.selected = Some(name);
+        Ok(())
+    }
+
+    /// Get the chosen register
+    pub fn selected(&self) -> Option<char> {
+        self.selected
+    }
+
+    /// Take the chosen register, leaving the default for later commands
+    pub fn take_selected(&mut self) -> Option<char> {
+        self.selected.take()
+    }
+
+    /// Remember the text typed in the last insert, for `".`
+    pub fn set_last_inserted(&mut self, text: impl Into<String>) {
+        self.last_inserted = text.into();
+    }
+
+    /// Remember the last command line, for `":`
+    pub fn set_last_command(&mut self, line: impl Into<String>) {
+        self.last_command = line.into();
+    }
+
+    /// Write yanked or deleted text to a register, or to the default registers without one
+    pub fn write(&mut self, register: Option<char>, content: RegisterContent, kind: WriteKind) -> Result<()> {
+        let written = match register.unwrap_or('"') {
+            '"' => {
+                match kind {
+                    WriteKind::Yank => {
+                        self.stored.insert('0', content.clone());
+                    }
+                    WriteKind::Delete if content.linewise || content.text.contains('\n') => self.shift_deletes(content.clone()),
+                    WriteKind::Delete => {
+                        self.stored.insert('-', content.clone());
+                    }
+                }
+                content
+            }
+            name => match self.store(name, content)? {
+                Some(stored) => stored,
+                None => return Ok(()),
+            },
+        };
+        self.unnamed = Some(written);
+        Ok(())
+    }
+
+    /// Put text in a register without touching the others, as recording a macro does
+    pub fn set(&mut self, name: char, content: RegisterContent) -> Result<()> {
+        self.store(name, content).map(|_| ())
+    }
+
+    /// Store text in one register, returning what it holds afterwards, or nothing for `"_`
+    fn store(&mut self, name: char, content: RegisterContent) -> Result<Option<RegisterContent>> {
+        let stored = match name {
+            '_' => return Ok(None),
+            '.' | '%' | ':' => return Err(RuskError::Command(format!("Register {} is read-only", name))),
+            '"' => {
+                self.unnamed = Some(content.clone());
+                content
+            }
+            '+' | '*' => {
+                let mut text = content.text.clone();
+                if content.linewise {
+                    text.push('\n');
+                }
+                self.clipboard.copy(&text)?;
+                content
+            }
+            'A'..='Z' => {
+                let name = name.to_ascii_lowercase();
+                let appended = match self.stored.remove(&name) {
+                    Some(existing) if existing.linewise || content.linewise => {
+                        RegisterContent::lines(format!("{}\n{}", existing.text, content.text))
+                    }
+                    Some(existing) => RegisterContent::chars(existing.text + &content.text),
+                    None => content,
+                };
+                self.stored.insert(name, appended.clone());
+                appended
+            }
+            'a'..='z' | '0'..='9' | '-' => {
+                self.stored.insert(name, content.clone());
+                content
+            }
+            name => return Err(RuskError::Command(format!("Invalid register name: '{}'", name))),
+        };
+        Ok(Some(stored))
+    }
+
+    /// Move `"1`-`"8` down to `"2`-`"9` and put a delete in `"1`
+    fn shift_deletes(&mut self, content: RegisterContent) {
+        for number in (1..9).rev() {
+            let from = char::from(b'0' + number);
+            if let Some(older) = self.stored.remove(&from) {
+                self.stored.insert(char::from(b'1' + number), older);
+            }
+        }
+        self.stored.insert('1', content);
+    }
+
+    /// Read a register, or the unnamed register without one
+    pub fn get(&self, register: Option<char>, buffer: &Buffer) -> Result<Option<RegisterContent>> {
+        let content = match register.unwrap_or('"') {
+            '"' => self.unnamed.clone(),
+            '_' => None,
+            '.' => Some(RegisterContent::chars(self.last_inserted.clone())),
+            ':' => Some(RegisterContent::chars(self.last_command.clone())),
+            '%' => buffer.file_path().map(RegisterContent::chars),
+            '+' | '*' => self.clipboard.paste()?.map(|text| match text.strip_suffix('\n') {
+                Some(lines) => RegisterContent::lines(lines),
+                None => RegisterContent::chars(text),
+            }),
+            name if name.is_ascii_alphanumeric() || name == '-' => self.stored.get(&name.to_ascii_lowercase()).cloned(),
+            name => return Err(RuskError::Command(format!("Invalid register name: '{}'", name))),
+        };
+        Ok(content.filter(|content| !content.text.is_empty() || content.linewise))
+    }
+
+    /// Get every register with text, in the order `:registers` lists them
+    pub fn list(&self, buffer: &Buffer) -> Vec<(char, RegisterContent)> {
+        LISTED.chars()
+            .filter_map(|name| Some((name, self.get(Some(name), buffer).ok()??)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_named_and_special_registers() {
+        let mut registers = Registers::new();
+        let buffer = Buffer::new();
+
+        registers.write(None, RegisterContent::lines("first"), WriteKind::Delete).unwrap();
+        registers.write(None, RegisterContent::lines("second"), WriteKind::Delete).unwrap();
+        registers.write(None, RegisterContent::chars("word"), WriteKind::Delete).unwrap();
+        registers.write(None, RegisterContent::chars("yanked"), WriteKind::Yank).unwrap();
+        assert_eq!(registers.get(Some('1'), &buffer).unwrap(), Some(RegisterContent::lines("second")));
+        assert_eq!(registers.get(Some('2'), &buffer).unwrap(), Some(RegisterContent::lines("first")));
+        assert_eq!(registers.get(Some('-'), &buffer).unwrap(), Some(RegisterContent::chars("word")));
+        assert_eq!(registers.get(Some('0'), &buffer).unwrap(), Some(RegisterContent::chars("yanked")));
+        assert_eq!(registers.get(None, &buffer).unwrap(), Some(RegisterContent::chars("yanked")));
+
+        // Uppercase appends, the black hole keeps the unnamed register as it was
+        registers.write(Some('a'), RegisterContent::chars("one"), WriteKind::Yank).unwrap();
+        registers.write(Some('A'), RegisterContent::chars(" two"), WriteKind::Yank).unwrap();
+        registers.write(Some('_'), RegisterContent::chars("gone"), WriteKind::Delete).unwrap();
+        assert_eq!(registers.get(Some('a'), &buffer).unwrap(), Some(RegisterContent::chars("one two")));
+        assert_eq!(registers.get(None, &buffer).unwrap(), Some(RegisterContent::chars("one two")));
+
+        registers.set_last_command("w notes.txt");
+        assert!(registers.write(Some(':'), RegisterContent::chars("x"), WriteKind::Yank).is_err());
+        assert_eq!(registers.get(Some(':'), &buffer).unwrap(), Some(RegisterContent::chars("w notes.txt")));
+        assert!(registers.select('!').is_err());
+
+        let listed: String = registers.list(&buffer).iter().map(|(name, _)| *name).collect();
+        assert_eq!(listed, "\"012a-:");
+    }
+}
@@ -0,0 +1,256 @@
+//! Visual block selection
+//!
+//! A block is the rectangle between the cursor and the corner where
+//! `Ctrl-v` started. Its columns are display columns, so tabs and wide
+//! characters line up the way they are drawn rather than by bytes. After
+//! `$` the block reaches the end of every line, however long each is.
+
+use crate::core::buffer::Buffer;
+use crate::core::cursor::{Position, TextRange};
+use ratatui::text::Span;
+
+/// Width of a character drawn at a display column, tabs reaching the next tab stop
+pub fn char_width(ch: char, column: usize, tab_size: usize) -> usize {
+    if ch == '\t' {
+        let tab = tab_size.max(1);
+        tab - column % tab
+    } else {
+        Span::raw(ch.to_string()).width()
+    }
+}
+
+/// Display column of a byte column
+pub fn display_column(line: &str, byte: usize, tab_size: usize) -> usize {
+    line[..byte.min(line.len())].chars().fold(0, |column, ch| column + char_width(ch, column, tab_size))
+}
+
+/// Byte column of the character drawn over a display column, or the end of a shorter line
+pub fn byte_column(line: &str, column: usize, tab_size: usize) -> usize {
+    let mut at = 0;
+    for (i, ch) in line.char_indices() {
+        at += char_width(ch, at, tab_size);
+        if at > column {
+            return i;
+        }
+    }
+    line.len()
+}
+
+/// Rows and display columns a block covers, `right` included and `None` after `$`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockColumns {
+    pub first: usize,
+    pub last: usize,
+    pub left: usize,
+    pub right: Option<usize>,
+}
+
+impl BlockColumns {
+    /// Byte range of a line's characters drawn within the columns
+    pub fn line_range(&self, line: &str, tab_size: usize) -> (usize, usize) {
+        let start = byte_column(line, self.left, tab_size);
+        let Some(right) = self.right else {
+            return (start, line.len());
+        };
+        let mut at = display_column(line, start, tab_size);
+        let mut end = start;
+        for ch in line[start..].chars() {
+            if at > right {
+                break;
+            }
+            at += char_width(ch, at, tab_size);
+            end += ch.len_utf8();
+        }
+        (start, end)
+    }
+
+    /// Byte ranges of every row
+    pub fn ranges(&self, lines: &[String], tab_size: usize) -> Vec<TextRange> {
+        (self.first..=self.last)
+            .map(|line| {
+                let (start, end) = self.line_range(&lines[line], tab_size);
+                TextRange::new(Position::new(line, start), Position::new(line, end))
+            })
+            .collect()
+    }
+
+    /// Text of the block, one row per line
+    pub fn text(&self, lines: &[String], tab_size: usize) -> String {
+        self.ranges(lines, tab_size).iter()
+            .map(|range| &lines[range.start.line][range.start.column..range.end.column])
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// A block selection, the cursor being its other corner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VisualBlock {
+    anchor: Position,
+    to_end: bool,
+}
+
+impl VisualBlock {
+    /// Start a block at a corner
+    pub fn new(anchor: Position) -> Self {
+        Self { anchor, to_end: false }
+    }
+
+    /// Get the corner the block started at
+    pub fn anchor(&self) -> Position {
+        self.anchor
+    }
+
+    /// Whether the block reaches the end of every line, as after `$`
+    pub fn to_end(&self) -> bool {
+        self.to_end
+    }
+
+    /// Make the block reach the end of every line, or stop at the cursor again
+    pub fn set_to_end(&mut self, to_end: bool) {
+        self.to_end = to_end;
+    }
+
+    /// Move the cursor to the opposite corner, as `o` does, returning where it goes
+    pub fn swap_corners(&mut self, cursor: Position) -> Position {
+        std::mem::replace(&mut self.anchor, cursor)
+    }
+
+    /// Move the cursor to the other end of its row, as `O` does, returning where it goes
+    pub fn swap_columns(&mut self, lines: &[String], cursor: Position, tab_size: usize) -> Position {
+        let anchor_column = display_column(&lines[self.anchor.line], self.anchor.column, tab_size);
+        let cursor_column = display_column(&lines[cursor.line], cursor.column, tab_size);
+        self.anchor.column = byte_column(&lines[self.anchor.line], cursor_column, tab_size);
+        Position::new(cursor.line, byte_column(&lines[cursor.line], anchor_column, tab_size))
+    }
+
+    /// Get the rows and display columns the block covers
+    pub fn columns(&self, lines: &[String], cursor: Position, tab_size: usize) -> BlockColumns {
+        // A corner covers the whole width of its character, at least one column past the end of a line
+        let span = |pos: Position| {
+            let line = &lines[pos.line];
+            let start = display_column(line, pos.column, tab_size);
+            let width = line[pos.column.min(line.len())..].chars().next().map_or(1, |ch| char_width(ch, start, tab_size).max(1));
+            (start, start + width - 1)
+        };
+        let (anchor, corner) = (span(self.anchor), span(cursor));
+        BlockColumns {
+            first: self.anchor.line.min(cursor.line),
+            last: self.anchor.line.max(cursor.line),
+            left: anchor.0.min(corner.0),
+            right: (!self.to_end).then_some(anchor.1.max(corner.1)),
+        }
+    }
+}
+
+/// Delete a block's text, leaving the cursor at its top left
+pub fn delete(buffer: &mut Buffer, columns: &BlockColumns, tab_size: usize) {
+    for range in columns.ranges(buffer.lines(), tab_size) {
+        buffer.delete_range(&range);
+    }
+    let start = byte_column(&buffer.lines()[columns.first], columns.left, tab_size);
+    buffer.set_cursor_position(columns.first, start);
+}
+
+/// Replace every character in a block with another, as `r` does
+pub fn replace(buffer: &mut Buffer, columns: &BlockColumns, ch: char, tab_size: usize) {
+    for range in columns.ranges(buffer.lines(), tab_size) {
+        let count = buffer.text_in_range(&range).chars().count();
+        if count > 0 {
+            buffer.replace_range(&range, &ch.to_string().repeat(count));
+        }
+    }
+    let start = byte_column(&buffer.lines()[columns.first], columns.left, tab_size);
+    buffer.set_cursor_position(columns.first, start);
+}
+
+/// Insert text at a display column of each line, or at the end of each line without one
+///
+/// Lines too short to reach the column are padded with spaces when `pad`
+/// is set, as for `A`, and left alone otherwise, as for `I`, which only
+/// changes lines that reach into the block.
+pub fn insert_at_column(buffer: &mut Buffer, lines: std::ops::RangeInclusive<usize>, column: Option<usize>, text: &str, pad: bool, tab_size: usize) {
+    for line in lines {
+        let Some(column) = column else {
+            let end = buffer.lines()[line].len();
+            buffer.insert_text(Position::new(line, end), text);
+            continue;
+        };
+        let width = display_column(&buffer.lines()[line], usize::MAX, tab_size);
+        if !pad && width <= column {
+            continue;
+        }
+        if width < column {
+            let end = buffer.lines()[line].len();
+            buffer.insert_text(Position::new(line, end), &" ".repeat(column - width));
+        }
+        let at = byte_column(&buffer.lines()[line], column, tab_size);
+        buffer.insert_text(Position::new(line, at), text);
+    }
+}
+
+/// Put a block's rows at a display column of successive lines, adding lines past the end of the buffer
+pub fn put(buffer: &mut Buffer, line: usize, column: usize, text: &str, tab_size: usize) {
+    for (i, row) in text.split('\n').enumerate() {
+        let target = line + i;
+        if target >= buffer.line_count() {
+            let last = buffer.line_count() - 1;
+            let end = buffer.lines()[last].len();
+            buffer.insert_text(Position::new(last, end), "\n");
+        }
+        insert_at_column(buffer, target..=target, Some(column), row, true, tab_size);
+    }
+    let start = byte_column(&buffer.lines()[line], column, tab_size);
+    buffer.set_cursor_position(line, start);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.insert_text(Position::new(0, 0), text);
+        buffer
+    }
+
+    #[test]
+    fn test_block_columns_follow_display_width() {
+        // A tab and a wide character push the rest of their lines right
+        let lines: Vec<String> = ["abcdef", "\tx", "日本語"].iter().map(|line| line.to_string()).collect();
+        assert_eq!(display_column(&lines[1], 1, 4), 4);
+        assert_eq!(display_column(&lines[2], 3, 4), 2);
+        assert_eq!(byte_column(&lines[2], 3, 4), 3);
+        assert_eq!(byte_column(&lines[1], 2, 4), 0);
+
+        let block = VisualBlock::new(Position::new(0, 1));
+        let columns = block.columns(&lines, Position::new(2, 3), 4);
+        assert_eq!(columns, BlockColumns { first: 0, last: 2, left: 1, right: Some(3) });
+        assert_eq!(columns.text(&lines, 4), "bcd\n\t\n日本");
+
+        let mut to_end = block;
+        to_end.set_to_end(true);
+        assert_eq!(to_end.columns(&lines, Position::new(1, 1), 4).text(&lines, 4), "bcdef\n\tx");
+    }
+
+    #[test]
+    fn test_block_edits() {
+        let mut text = buffer("one\ntwo\nx\nfour");
+        let columns = BlockColumns { first: 0, last: 3, left: 1, right: Some(1) };
+        assert_eq!(columns.text(text.lines(), 4), "n\nw\n\no");
+        delete(&mut text, &columns, 4);
+        assert_eq!(text.text(), "oe\nto\nx\nfur");
+
+        insert_at_column(&mut text, 1..=3, Some(2), "|", false, 4);
+        assert_eq!(text.text(), "oe\nto\nx\nfu|r");
+        insert_at_column(&mut text, 0..=2, Some(4), "<", true, 4);
+        assert_eq!(text.text(), "oe  <\nto  <\nx   <\nfu|r");
+
+        replace(&mut text, &BlockColumns { first: 0, last: 1, left: 0, right: Some(0) }, '#', 4);
+        assert_eq!(text.text(), "#e  <\n#o  <\nx   <\nfu|r");
+
+        let mut text = buffer("ab\ncd");
+        put(&mut text, 1, 1, "12\n34", 4);
+        assert_eq!(text.text(), "ab\nc12d\n 34");
+    }
+}
@@ -0,0 +1,16 @@
+; Fold queries for Python
+
+[
+  (function_definition)
+  (class_definition)
+  (decorated_definition)
+  (if_statement)
+  (for_statement)
+  (while_statement)
+  (try_statement)
+  (with_statement)
+  (match_statement)
+  (dictionary)
+  (list)
+  (import_from_statement)
+] @fold
@@ -0,0 +1,20 @@
+; Fold queries for Rust
+
+[
+  (function_item)
+  (impl_item)
+  (trait_item)
+  (struct_item)
+  (enum_item)
+  (union_item)
+  (mod_item)
+  (macro_definition)
+  (match_expression)
+  (if_expression)
+  (for_expression)
+  (while_expression)
+  (loop_expression)
+  (closure_expression)
+  (use_declaration)
+  (block_comment)
+] @fold
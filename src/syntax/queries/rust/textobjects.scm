@@ -0,0 +1,32 @@
+; Text object queries for Rust
+
+(function_item
+  body: (block) @function.inner) @function.outer
+
+(closure_expression
+  body: (_) @function.inner) @function.outer
+
+(impl_item
+  body: (declaration_list) @class.inner) @class.outer
+
+(trait_item
+  body: (declaration_list) @class.inner) @class.outer
+
+(struct_item
+  body: (field_declaration_list) @class.inner) @class.outer
+
+(enum_item
+  body: (enum_variant_list) @class.inner) @class.outer
+
+(mod_item
+  body: (declaration_list) @class.inner) @class.outer
+
+(parameters (_) @parameter.inner)
+
+(closure_parameters (_) @parameter.inner)
+
+(arguments (_) @parameter.inner)
+
+(type_arguments (_) @parameter.inner)
+
+(type_parameters (_) @parameter.inner)
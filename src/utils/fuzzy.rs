@@ -0,0 +1,130 @@
+//! Fuzzy matching used to rank completion candidates, palette entries and file names
+
+/// Result of matching a pattern against a candidate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Char indices of the candidate that matched the pattern
+    pub indices: Vec<usize>,
+}
+
+const MATCH_SCORE: i64 = 16;
+const CONSECUTIVE_BONUS: i64 = 12;
+const BOUNDARY_BONUS: i64 = 10;
+const FIRST_CHAR_BONUS: i64 = 8;
+const GAP_PENALTY: i64 = 2;
+const LEADING_GAP_PENALTY: i64 = 1;
+
+/// Match a pattern as a subsequence of a candidate
+///
+/// Matching ignores case unless the pattern contains an uppercase letter.
+/// Consecutive matches and matches at word boundaries (after `_`, `-`, `/`,
+/// `.`, spaces, or at a lower-to-upper case change) score higher; gaps score
+/// lower. An empty pattern matches everything with a score of zero.
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<FuzzyMatch> {
+    if pattern.is_empty() {
+        return Some(FuzzyMatch { score: 0, indices: Vec::new() });
+    }
+
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let normalize = |ch: char| if case_sensitive { ch } else { ch.to_ascii_lowercase() };
+    let pattern: Vec<char> = pattern.chars().map(normalize).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+
+    // Whether pattern[p..] still matches somewhere in chars[from..]
+    let rest_matches = |p: usize, from: usize| {
+        let mut rest = pattern[p..].iter().peekable();
+        for &ch in &chars[from.min(chars.len())..] {
+            if rest.peek().is_some_and(|&&wanted| normalize(ch) == wanted) {
+                rest.next();
+            }
+        }
+        rest.peek().is_none()
+    };
+    if !rest_matches(0, 0) {
+        return None;
+    }
+
+    // For each pattern char prefer a consecutive match, then a word boundary, then the first match
+    let mut indices: Vec<usize> = Vec::with_capacity(pattern.len());
+    let mut from = 0;
+    for (p, &wanted) in pattern.iter().enumerate() {
+        let viable: Vec<usize> = (from..chars.len())
+            .filter(|&j| normalize(chars[j]) == wanted && rest_matches(p + 1, j + 1))
+            .collect();
+
+        let consecutive = indices.last().map(|&last| last + 1).filter(|next| viable.contains(next));
+        let boundary = viable.iter().copied().find(|&j| is_boundary(&chars, j));
+        let found = consecutive.or(boundary).or(viable.first().copied())?;
+
+        indices.push(found);
+        from = found + 1;
+    }
+
+    Some(FuzzyMatch { score: score(&chars, &indices), indices })
+}
+
+/// Check if a char starts a word
+fn is_boundary(chars: &[char], i: usize) -> bool {
+    if i == 0 {
+        return true;
+    }
+    let (prev, ch) = (chars[i - 1], chars[i]);
+    matches!(prev, '_' | '-' | '/' | '\\' | '.' | ' ' | ':')
+        || (prev.is_lowercase() && ch.is_uppercase())
+        || (!prev.is_alphanumeric() && ch.is_alphanumeric())
+}
+
+fn score(chars: &[char], indices: &[usize]) -> i64 {
+    let mut score = 0;
+
+    for (n, &i) in indices.iter().enumerate() {
+        score += MATCH_SCORE;
+        if is_boundary(chars, i) {
+            score += BOUNDARY_BONUS;
+        }
+        match n.checked_sub(1).map(|prev| indices[prev]) {
+            Some(prev) if prev + 1 == i => score += CONSECUTIVE_BONUS,
+            Some(prev) => score -= GAP_PENALTY * (i - prev - 1).min(8) as i64,
+            None => {
+                if i == 0 {
+                    score += FIRST_CHAR_BONUS;
+                }
+                score -= LEADING_GAP_PENALTY * i.min(8) as i64;
+            }
+        }
+    }
+
+    // Prefer shorter candidates among otherwise equal matches
+    score - (chars.len() as i64 - indices.len() as i64).min(32) / 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score_of(pattern: &str, candidate: &str) -> i64 {
+        fuzzy_match(pattern, candidate).map(|m| m.score).unwrap_or(i64::MIN)
+    }
+
+    #[test]
+    fn test_subsequence_matching() {
+        assert!(fuzzy_match("abc", "a_b_c").is_some());
+        assert!(fuzzy_match("abc", "acb").is_none());
+        assert_eq!(fuzzy_match("fb", "foo_bar").unwrap().indices, vec![0, 4]);
+        assert!(fuzzy_match("FB", "foo_bar").is_none());
+        assert!(fuzzy_match("fB", "fooBar").is_some());
+    }
+
+    #[test]
+    fn test_ranking() {
+        // Prefix and consecutive matches beat scattered ones
+        assert!(score_of("buf", "buffer") > score_of("buf", "bluff"));
+        // Word-boundary matches beat matches inside words
+        assert!(score_of("cs", "color_scheme") > score_of("cs", "tactics"));
+        // Shorter candidates win ties
+        assert!(score_of("map", "map") > score_of("map", "map_range"));
+        // Matches prefer the boundary occurrence over the first one
+        assert_eq!(fuzzy_match("ms", "mark_set").unwrap().indices, vec![0, 5]);
+    }
+}
@@ -1,6 +1,682 @@
-/// Keybinding utilities
-pub struct Keybindings;
+//! Keybinding parsing and per-mode key tries
+//!
+//! Bindings are written as `x`, `gg`, `Ctrl+r`, `<C-r>`, `<leader>ff` or
+//! `Ctrl+w h`. Entries starting with `:` (`:wq`) name command lines rather
+//! than keys and run when that command line is entered.
+
+use crate::config::KeyBindings;
+use crate::services::command_system::{CommandSystem, EditorMode};
+use crate::{Result, RuskError};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Leader key used when the configuration does not set one
+pub const DEFAULT_LEADER: char = '\\';
+/// How long a held key sequence waits for more keys
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+const MODES: [EditorMode; 4] = [EditorMode::Normal, EditorMode::Insert, EditorMode::Visual, EditorMode::Command];
+
+/// A key with its modifiers, normalized so that bindings and terminal events compare equal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyPress {
+    /// Create a normalized key press
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            // Characters already carry their case; Ctrl+R and Ctrl+r are the same key to terminals
+            KeyCode::Char(ch) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                if modifiers.contains(KeyModifiers::CONTROL) { KeyCode::Char(ch.to_ascii_lowercase()) } else { KeyCode::Char(ch) }
+            }
+            KeyCode::BackTab => {
+                modifiers.insert(KeyModifiers::SHIFT);
+                KeyCode::Tab
+            }
+            other => other,
+        };
+        Self { code, modifiers }
+    }
+
+    /// Normalize a terminal key event
+    pub fn from_event(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+
+    /// Turn back into a key event that can be replayed
+    pub fn to_event(&self) -> KeyEvent {
+        match (self.code, self.modifiers.contains(KeyModifiers::SHIFT)) {
+            (KeyCode::Tab, true) => KeyEvent::new(KeyCode::BackTab, self.modifiers),
+            (KeyCode::Char(ch), false) if ch.is_uppercase() => KeyEvent::new(self.code, self.modifiers | KeyModifiers::SHIFT),
+            _ => KeyEvent::new(self.code, self.modifiers),
+        }
+    }
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(ch) => ch.to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            code => NAMED_KEYS.iter().find(|(_, named)| *named == code).map_or("?", |(name, _)| name).to_string(),
+        };
+        for (modifier, prefix) in [(KeyModifiers::CONTROL, "Ctrl+"), (KeyModifiers::ALT, "Alt+"), (KeyModifiers::SHIFT, "Shift+")] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(prefix)?;
+            }
+        }
+        f.write_str(&name)
+    }
+}
+
+/// Key names accepted in bindings; the first spelling of each key is used for display
+const NAMED_KEYS: [(&str, KeyCode); 20] = [
+    ("Escape", KeyCode::Esc),
+    ("Esc", KeyCode::Esc),
+    ("Enter", KeyCode::Enter),
+    ("Return", KeyCode::Enter),
+    ("CR", KeyCode::Enter),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("BS", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Del", KeyCode::Delete),
+    ("Space", KeyCode::Char(' ')),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Insert", KeyCode::Insert),
+];
+
+/// Look up a named key, case-insensitively, including `F1`-`F12` and `lt` for `<`
+fn named_key(name: &str) -> Option<KeyCode> {
+    if let Some(n) = name.strip_prefix(['F', 'f']).and_then(|n| n.parse::<u8>().ok()).filter(|n| (1..=12).contains(n)) {
+        return Some(KeyCode::F(n));
+    }
+    if name.eq_ignore_ascii_case("lt") {
+        return Some(KeyCode::Char('<'));
+    }
+    NAMED_KEYS.iter().find(|(key_name, _)| key_name.eq_ignore_ascii_case(name)).map(|(_, code)| *code)
+}
+
+/// Parse a single key with optional modifiers: `x`, `Ctrl+r`, `C-r`, `Shift+Tab`, `Esc`
+fn parse_key(token: &str, leader: KeyPress) -> Option<KeyPress> {
+    if token.eq_ignore_ascii_case("leader") {
+        return Some(leader);
+    }
+
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = token;
+    while let Some((prefix, after)) = rest.split_once(['+', '-']).filter(|(_, after)| !after.is_empty()) {
+        let modifier = match prefix.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" | "c" => KeyModifiers::CONTROL,
+            "alt" | "meta" | "a" | "m" => KeyModifiers::ALT,
+            "shift" | "s" => KeyModifiers::SHIFT,
+            _ => break,
+        };
+        modifiers |= modifier;
+        rest = after;
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(ch), None) => KeyCode::Char(ch),
+        _ => named_key(rest)?,
+    };
+    Some(KeyPress::new(code, modifiers))
+}
+
+/// Parse key notation into a key sequence
+///
+/// Space-separated tokens are keys of their own (`Ctrl+w h`); otherwise
+/// `<...>` groups are single keys and every other character is a key.
+pub fn parse_key_sequence(notation: &str, leader: KeyPress) -> Result<Vec<KeyPress>> {
+    let invalid = || RuskError::Config(format!("Invalid key notation '{}'", notation));
+    let notation = notation.trim();
+    if notation.is_empty() {
+        return Err(invalid());
+    }
+
+    // A whole-token key like `Ctrl+r`, `Escape` or `F5`
+    if !notation.contains(char::is_whitespace) && !notation.contains('<') && notation.chars().count() > 1
+        && let Some(key) = parse_key(notation, leader).filter(|_| notation.contains(['+', '-']) || named_key(notation).is_some())
+    {
+        return Ok(vec![key]);
+    }
+
+    if notation.contains(char::is_whitespace) {
+        return notation.split_whitespace()
+            .map(|token| parse_key_sequence(token, leader))
+            .collect::<Result<Vec<_>>>()
+            .map(|keys| keys.concat());
+    }
+
+    parse_key_notation(notation, leader)
+}
+
+/// Parse keys written as typed, where `<...>` groups are single keys and
+/// every other character, spaces included, is a key of its own
+pub fn parse_key_notation(notation: &str, leader: KeyPress) -> Result<Vec<KeyPress>> {
+    let invalid = || RuskError::Config(format!("Invalid key notation '{}'", notation));
+    let mut keys = Vec::new();
+    let mut rest = notation;
+    while let Some(ch) = rest.chars().next() {
+        if ch == '<' && let Some(end) = rest.find('>').filter(|&end| end > 1) {
+            keys.push(parse_key(&rest[1..end], leader).ok_or_else(invalid)?);
+            rest = &rest[end + 1..];
+        } else {
+            keys.push(KeyPress::new(KeyCode::Char(ch), KeyModifiers::NONE));
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+    Ok(keys)
+}
+
+/// Format a key sequence for display, like `g g` or `Ctrl+w h`
+pub fn format_key_sequence(keys: &[KeyPress]) -> String {
+    keys.iter().map(KeyPress::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// Format keys as `parse_key_notation` reads them, like `dd<Escape>` or `<Ctrl+r>`
+pub fn format_key_notation(keys: &[KeyPress]) -> String {
+    keys.iter()
+        .map(|key| match key.code {
+            KeyCode::Char('<') if key.modifiers.is_empty() => "<lt>".to_string(),
+            KeyCode::Char(ch) if key.modifiers.is_empty() => ch.to_string(),
+            _ => format!("<{}>", key),
+        })
+        .collect()
+}
+
+/// How a key sequence relates to the bindings of a mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyMatch {
+    /// No binding starts with the sequence
+    None,
+    /// Longer bindings start with the sequence
+    Prefix,
+    /// The sequence is a binding and nothing longer starts with it
+    Exact(String),
+    /// The sequence is a binding, and longer bindings start with it too
+    ExactAndPrefix(String),
+}
+
+/// A key that can follow a pending sequence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Continuation {
+    pub key: KeyPress,
+    /// Command the sequence runs when it ends with this key
+    pub command: Option<String>,
+    /// Number of longer bindings continuing through this key
+    pub more: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    command: Option<String>,
+    children: HashMap<KeyPress, TrieNode>,
+}
+
+/// Key sequences of one mode, stored as a trie
+#[derive(Debug, Clone, Default)]
+pub struct KeyTrie {
+    root: TrieNode,
+}
+
+impl KeyTrie {
+    /// Create an empty trie
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind a sequence to a command, returning the command it was bound to before
+    pub fn insert(&mut self, keys: &[KeyPress], command: &str) -> Option<String> {
+        let node = keys.iter().fold(&mut self.root, |node, key| node.children.entry(*key).or_default());
+        node.command.replace(command.to_string())
+    }
+
+    /// Look up a sequence
+    pub fn lookup(&self, keys: &[KeyPress]) -> KeyMatch {
+        let Some(node) = keys.iter().try_fold(&self.root, |node, key| node.children.get(key)) else {
+            return KeyMatch::None;
+        };
+        match (&node.command, node.children.is_empty()) {
+            (Some(command), true) => KeyMatch::Exact(command.clone()),
+            (Some(command), false) => KeyMatch::ExactAndPrefix(command.clone()),
+            (None, false) if !keys.is_empty() => KeyMatch::Prefix,
+            _ => KeyMatch::None,
+        }
+    }
+
+    /// Get the keys that can follow a sequence, sorted for display
+    pub fn continuations(&self, keys: &[KeyPress]) -> Vec<Continuation> {
+        let Some(node) = keys.iter().try_fold(&self.root, |node, key| node.children.get(key)) else {
+            return Vec::new();
+        };
+        let mut found: Vec<Continuation> = node.children.iter()
+            .map(|(key, child)| {
+                let mut bindings = Vec::new();
+                collect_bindings(child, &mut Vec::new(), &mut bindings);
+                Continuation {
+                    key: *key,
+                    command: child.command.clone(),
+                    more: bindings.len() - usize::from(child.command.is_some()),
+                }
+            })
+            .collect();
+        found.sort_by_key(|continuation| (continuation.key.to_string().to_lowercase(), continuation.key.to_string()));
+        found
+    }
+
+    /// Get every binding as (keys, command) pairs
+    pub fn bindings(&self) -> Vec<(Vec<KeyPress>, String)> {
+        let mut found = Vec::new();
+        collect_bindings(&self.root, &mut Vec::new(), &mut found);
+        found
+    }
+}
+
+fn collect_bindings(node: &TrieNode, path: &mut Vec<KeyPress>, found: &mut Vec<(Vec<KeyPress>, String)>) {
+    if let Some(command) = &node.command {
+        found.push((path.clone(), command.clone()));
+    }
+    for (key, child) in &node.children {
+        path.push(*key);
+        collect_bindings(child, path, found);
+        path.pop();
+    }
+}
+
+/// What to do with keys fed to `Keybindings`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyAction {
+    /// Run a bound command
+    Command(String),
+    /// Hand an unbound key to the editing mode
+    Key(KeyEvent),
+}
+
+/// Parsed keybindings of every mode, with the keys typed so far
+///
+/// Keys are fed one at a time. A key that continues a binding is held
+/// back; a complete binding runs its command; when a held sequence stops
+/// matching, its first key goes to the editing mode unchanged and the rest
+/// are matched again, as vim does with mappings. A sequence that is a
+/// binding but also the start of a longer one waits for the timeout.
+#[derive(Debug, Clone)]
+pub struct Keybindings {
+    modes: HashMap<EditorMode, KeyTrie>,
+    command_lines: BTreeMap<String, String>,
+    leader: KeyPress,
+    timeout: Duration,
+    conflicts: Vec<String>,
+    pending: Vec<KeyEvent>,
+    pending_mode: EditorMode,
+    pending_since: Option<Instant>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self {
+            modes: HashMap::new(),
+            command_lines: BTreeMap::new(),
+            leader: KeyPress::new(KeyCode::Char(DEFAULT_LEADER), KeyModifiers::NONE),
+            timeout: DEFAULT_TIMEOUT,
+            conflicts: Vec::new(),
+            pending: Vec::new(),
+            pending_mode: EditorMode::Normal,
+            pending_since: None,
+        }
+    }
+}
 
 impl Keybindings {
-    // TODO: Implement keybinding management
-}
\ No newline at end of file
+    /// Create empty keybindings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the keybindings of a configuration
+    pub fn from_config(config: &KeyBindings) -> Result<Self> {
+        let mut keybindings = Self::new();
+        keybindings.load(config)?;
+        Ok(keybindings)
+    }
+
+    /// Replace all bindings with those of a configuration
+    pub fn load(&mut self, config: &KeyBindings) -> Result<()> {
+        self.modes.clear();
+        self.command_lines.clear();
+        self.conflicts.clear();
+        self.leader = match parse_key_sequence(&config.leader, self.leader)?.as_slice() {
+            [leader] => *leader,
+            _ => return Err(RuskError::Config(format!("Leader '{}' must be a single key", config.leader))),
+        };
+        self.timeout = Duration::from_millis(config.timeout_ms);
+
+        for mode in MODES {
+            // Sorted so conflicts are reported the same way every time
+            let sorted: BTreeMap<&String, &String> = mode.bindings(config).iter().collect();
+            for (notation, command) in sorted {
+                if mode == EditorMode::Normal && let Some(line) = notation.strip_prefix(':').filter(|line| !line.is_empty()) {
+                    self.command_lines.insert(line.to_string(), command.clone());
+                    continue;
+                }
+                let keys = parse_key_sequence(notation, self.leader)?;
+                self.bind(mode, &keys, command);
+            }
+        }
+
+        self.find_shadowed_bindings();
+        Ok(())
+    }
+
+    /// Bind a key sequence in a mode, recording a conflict if it was bound to another command
+    pub fn bind(&mut self, mode: EditorMode, keys: &[KeyPress], command: &str) {
+        let trie = self.modes.entry(mode).or_default();
+        if let Some(previous) = trie.insert(keys, command).filter(|previous| previous != command) {
+            self.conflicts.push(format!(
+                "{} mode: '{}' is bound to both '{}' and '{}'",
+                mode.name(), format_key_sequence(keys), previous, command,
+            ));
+        }
+    }
+
+    /// Note bindings that are also prefixes of longer ones, which only run after the timeout
+    fn find_shadowed_bindings(&mut self) {
+        for mode in MODES {
+            let Some(trie) = self.modes.get(&mode) else { continue };
+            let mut shadowed: Vec<String> = trie.bindings().into_iter()
+                .filter(|(keys, _)| matches!(trie.lookup(keys), KeyMatch::ExactAndPrefix(_)))
+                .map(|(keys, command)| format!(
+                    "{} mode: '{}' ({}) is a prefix of longer bindings and waits {} ms for more keys",
+                    mode.name(), format_key_sequence(&keys), command, self.timeout.as_millis(),
+                ))
+                .collect();
+            shadowed.sort();
+            self.conflicts.extend(shadowed);
+        }
+    }
+
+    /// Get the problems found while loading: sequences bound twice and bindings shadowed by longer ones
+    pub fn conflicts(&self) -> &[String] {
+        &self.conflicts
+    }
+
+    /// List bindings to commands the command system does not know
+    pub fn unknown_commands(&self, commands: &CommandSystem) -> Vec<String> {
+        let mut unknown: Vec<String> = MODES.iter()
+            .filter_map(|mode| Some((mode, self.modes.get(mode)?)))
+            .flat_map(|(mode, trie)| {
+                trie.bindings().into_iter()
+                    .filter(|(_, command)| !commands.contains(command))
+                    .map(move |(keys, command)| format!("{} mode: '{}' is bound to unknown command '{}'", mode.name(), format_key_sequence(&keys), command))
+            })
+            .chain(self.command_lines.iter()
+                .filter(|(_, command)| !commands.contains(command))
+                .map(|(line, command)| format!("command line ':{}' is bound to unknown command '{}'", line, command)))
+            .collect();
+        unknown.sort();
+        unknown
+    }
+
+    /// Get the trie of a mode
+    pub fn trie(&self, mode: EditorMode) -> Option<&KeyTrie> {
+        self.modes.get(&mode)
+    }
+
+    /// Get the command a typed command line is bound to, like `save_and_quit` for `wq`
+    pub fn command_line(&self, line: &str) -> Option<&str> {
+        self.command_lines.get(line.trim()).map(String::as_str)
+    }
+
+    /// Get the shortest binding of a command for display, preferring normal mode
+    pub fn keys_for(&self, command: &str) -> Option<String> {
+        let keys = MODES.iter()
+            .filter_map(|mode| self.modes.get(mode))
+            .filter_map(|trie| {
+                trie.bindings().into_iter()
+                    .filter(|(_, bound)| bound == command)
+                    .map(|(keys, _)| keys)
+                    .min_by_key(|keys| (keys.len(), format_key_sequence(keys)))
+            })
+            .next()
+            .map(|keys| format_key_sequence(&keys));
+        keys.or_else(|| {
+            self.command_lines.iter()
+                .filter(|(_, bound)| *bound == command)
+                .map(|(line, _)| format!(":{}", line))
+                .min_by_key(|line| line.len())
+        })
+    }
+
+    /// Get the leader key
+    pub fn leader(&self) -> KeyPress {
+        self.leader
+    }
+
+    /// Get how long held keys wait for the next key
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Check whether keys are held back waiting for the rest of a sequence
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Get how the held keys match the bindings of their mode
+    pub fn pending_match(&self) -> KeyMatch {
+        match self.modes.get(&self.pending_mode) {
+            Some(trie) if self.is_pending() => trie.lookup(&self.pending()),
+            _ => KeyMatch::None,
+        }
+    }
+
+    /// Get the keys that can follow the held keys
+    pub fn pending_continuations(&self) -> Vec<Continuation> {
+        match self.modes.get(&self.pending_mode) {
+            Some(trie) if self.is_pending() => trie.continuations(&self.pending()),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get the keys held back so far
+    pub fn pending(&self) -> Vec<KeyPress> {
+        self.pending.iter().map(|event| KeyPress::from_event(*event)).collect()
+    }
+
+    /// Get the mode the held keys were typed in
+    pub fn pending_mode(&self) -> EditorMode {
+        self.pending_mode
+    }
+
+    /// Get when the last held key was typed
+    pub fn pending_since(&self) -> Option<Instant> {
+        self.pending_since
+    }
+
+    /// Feed a typed key, returning what to do now
+    pub fn feed(&mut self, mode: EditorMode, key: KeyEvent, now: Instant) -> Vec<KeyAction> {
+        if !self.pending.is_empty() && mode != self.pending_mode {
+            // The mode changed under the held keys; they are no longer a sequence
+            let mut actions: Vec<KeyAction> = self.pending.drain(..).map(KeyAction::Key).collect();
+            self.pending_since = None;
+            actions.extend(self.feed(mode, key, now));
+            return actions;
+        }
+
+        let mut actions = Vec::new();
+        let mut queue = std::mem::take(&mut self.pending);
+        queue.push(key);
+        self.pending_mode = mode;
+        self.pending_since = Some(now);
+
+        let Some(trie) = self.modes.get(&mode) else {
+            self.pending_since = None;
+            return queue.into_iter().map(KeyAction::Key).collect();
+        };
+
+        while !queue.is_empty() {
+            let keys: Vec<KeyPress> = queue.iter().map(|event| KeyPress::from_event(*event)).collect();
+            match trie.lookup(&keys) {
+                KeyMatch::Exact(command) => {
+                    actions.push(KeyAction::Command(command));
+                    queue.clear();
+                }
+                KeyMatch::Prefix | KeyMatch::ExactAndPrefix(_) => {
+                    self.pending = queue;
+                    return actions;
+                }
+                KeyMatch::None => {
+                    // The longest binding that the held keys complete runs; otherwise the first key goes through
+                    let complete = (1..queue.len()).rev().find_map(|len| match trie.lookup(&keys[..len]) {
+                        KeyMatch::Exact(command) | KeyMatch::ExactAndPrefix(command) => Some((len, command)),
+                        _ => None,
+                    });
+                    match complete {
+                        Some((len, command)) => {
+                            actions.push(KeyAction::Command(command));
+                            queue.drain(..len);
+                        }
+                        None => actions.push(KeyAction::Key(queue.remove(0))),
+                    }
+                }
+            }
+        }
+
+        self.pending_since = None;
+        actions
+    }
+
+    /// Resolve held keys once the timeout has passed: a complete binding runs, other keys go through
+    pub fn check_timeout(&mut self, now: Instant) -> Vec<KeyAction> {
+        let expired = self.pending_since.is_some_and(|since| now.duration_since(since) >= self.timeout);
+        if !expired {
+            return Vec::new();
+        }
+        self.flush()
+    }
+
+    /// Resolve held keys right away
+    pub fn flush(&mut self) -> Vec<KeyAction> {
+        self.pending_since = None;
+        let queue = std::mem::take(&mut self.pending);
+        if queue.is_empty() {
+            return Vec::new();
+        }
+
+        let keys: Vec<KeyPress> = queue.iter().map(|event| KeyPress::from_event(*event)).collect();
+        match self.modes.get(&self.pending_mode).map(|trie| trie.lookup(&keys)) {
+            Some(KeyMatch::ExactAndPrefix(command)) => vec![KeyAction::Command(command)],
+            _ => queue.into_iter().map(KeyAction::Key).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(ch: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_parse_notation() {
+        let leader = KeyPress::new(KeyCode::Char(' '), KeyModifiers::NONE);
+        let ctrl_r = KeyPress::new(KeyCode::Char('r'), KeyModifiers::CONTROL);
+
+        assert_eq!(parse_key_sequence("Ctrl+r", leader).unwrap(), vec![ctrl_r]);
+        assert_eq!(parse_key_sequence("<C-r>", leader).unwrap(), vec![ctrl_r]);
+        assert_eq!(parse_key_sequence("gg", leader).unwrap().len(), 2);
+        assert_eq!(parse_key_sequence("Escape", leader).unwrap(), vec![KeyPress::new(KeyCode::Esc, KeyModifiers::NONE)]);
+        assert_eq!(format_key_sequence(&parse_key_sequence("<leader>ff", leader).unwrap()), "Space f f");
+        assert_eq!(format_key_sequence(&parse_key_sequence("Ctrl+w h", leader).unwrap()), "Ctrl+w h");
+        assert_eq!(parse_key_sequence("+", leader).unwrap(), vec![KeyPress::new(KeyCode::Char('+'), KeyModifiers::NONE)]);
+        assert!(parse_key_sequence("<C-Nope>", leader).is_err());
+
+        // Typed keys keep spaces and round-trip through their notation
+        let typed = parse_key_notation("ia <lt>b<Esc><C-r>", leader).unwrap();
+        assert_eq!(typed.len(), 7);
+        assert_eq!(format_key_notation(&typed), "ia <lt>b<Escape><Ctrl+r>");
+        assert_eq!(parse_key_notation(&format_key_notation(&typed), leader).unwrap(), typed);
+
+        // Terminal events match bindings regardless of how shift is reported
+        let shifted = KeyPress::from_event(KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT));
+        assert_eq!(parse_key_sequence("G", leader).unwrap(), vec![shifted]);
+    }
+
+    #[test]
+    fn test_trie_dispatch_and_timeout() {
+        let mut config = KeyBindings::default();
+        config.normal_mode.insert("g".to_string(), "move_file_end".to_string());
+        config.normal_mode.insert("<C-r>".to_string(), "undo".to_string());
+        config.normal_mode.insert("zf".to_string(), "undo".to_string());
+        let mut keybindings = Keybindings::from_config(&config).unwrap();
+
+        // Ctrl+r and <C-r> are the same key; `g` is shadowed by `gg`
+        assert_eq!(keybindings.conflicts().len(), 2);
+        assert!(keybindings.unknown_commands(&CommandSystem::with_builtins()).is_empty());
+        assert_eq!(keybindings.command_line("wq"), Some("save_and_quit"));
+
+        let start = Instant::now();
+        let normal = EditorMode::Normal;
+        assert!(keybindings.feed(normal, key('g'), start).is_empty());
+        assert_eq!(keybindings.feed(normal, key('g'), start), vec![KeyAction::Command("move_file_start".to_string())]);
+
+        // An ambiguous prefix runs its own binding when the next key does not continue it
+        keybindings.feed(normal, key('g'), start);
+        assert_eq!(keybindings.feed(normal, key('j'), start), vec![
+            KeyAction::Command("move_file_end".to_string()),
+            KeyAction::Command("move_down".to_string()),
+        ]);
+
+        // ... or after the timeout
+        keybindings.feed(normal, key('g'), start);
+        assert!(keybindings.check_timeout(start).is_empty());
+        assert_eq!(keybindings.check_timeout(start + DEFAULT_TIMEOUT), vec![KeyAction::Command("move_file_end".to_string())]);
+
+        // Unbound keys go straight through, held ones in order
+        assert_eq!(keybindings.feed(normal, key('z'), start), vec![]);
+        assert_eq!(keybindings.feed(normal, key('q'), start), vec![KeyAction::Key(key('z')), KeyAction::Key(key('q'))]);
+        assert_eq!(keybindings.feed(EditorMode::Insert, key('q'), start), vec![KeyAction::Key(key('q'))]);
+    }
+
+    #[test]
+    fn test_pending_continuations() {
+        let mut config = KeyBindings { leader: "Space".to_string(), normal_mode: HashMap::new(), ..KeyBindings::default() };
+        config.normal_mode.insert("<leader>fs".to_string(), "save_file".to_string());
+        config.normal_mode.insert("<leader>fq".to_string(), "quit".to_string());
+        config.normal_mode.insert("<leader>w".to_string(), "save_file".to_string());
+        let mut keybindings = Keybindings::from_config(&config).unwrap();
+
+        let start = Instant::now();
+        keybindings.feed(EditorMode::Normal, key(' '), start);
+        assert_eq!(keybindings.pending_match(), KeyMatch::Prefix);
+        let continuations = keybindings.pending_continuations();
+        assert_eq!(continuations.len(), 2);
+        assert_eq!((continuations[0].key.to_string(), continuations[0].command.as_deref(), continuations[0].more), ("f".to_string(), None, 2));
+        assert_eq!(continuations[1].command.as_deref(), Some("save_file"));
+
+        keybindings.feed(EditorMode::Normal, key('f'), start);
+        let commands: Vec<_> = keybindings.pending_continuations().into_iter().filter_map(|c| c.command).collect();
+        assert_eq!(commands, vec!["quit", "save_file"]);
+
+        config.leader = "ab".to_string();
+        assert!(Keybindings::from_config(&config).is_err());
+    }
+}
@@ -5,4 +5,5 @@
 
 pub mod text;
 pub mod keybindings;
-pub mod macros;
\ No newline at end of file
+pub mod macros;
+pub mod fuzzy;
\ No newline at end of file
@@ -20,6 +20,29 @@ fn test_editor_with_file() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_closing_a_buffer_emits_buffer_closed() -> Result<()> {
+    use rusk::services::event_bus::{EditorEvent, EventKind, Verdict};
+    use std::sync::{Arc, Mutex};
+
+    let mut editor = Editor::new()?;
+    editor.new_buffer()?;
+    let id = editor.current_buffer().map(|buffer| buffer.id());
+
+    let closed = Arc::new(Mutex::new(Vec::new()));
+    let seen = closed.clone();
+    editor.events().subscribe("test", &[EventKind::BufferClosed], 0, move |event| {
+        if let EditorEvent::BufferClosed { buffer, .. } = event {
+            seen.lock().unwrap().push(*buffer);
+        }
+        Verdict::Continue
+    });
+
+    assert!(editor.close_current_buffer()?);
+    assert_eq!(closed.lock().unwrap().first().copied(), id);
+    Ok(())
+}
+
 #[test]
 fn test_configuration_loading() -> Result<()> {
     let config = ConfigLoader::load()?;
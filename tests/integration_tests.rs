@@ -55,4 +55,221 @@ fn test_cursor_movement() {
     let right = Movement::right(pos);
     assert_eq!(right.line, 5);
     assert_eq!(right.column, 11);
-}
\ No newline at end of file
+}
+#[test]
+fn test_buffer_range_operations() {
+    use rusk::core::buffer::Buffer;
+    use rusk::core::cursor::{Position, TextRange};
+    
+    let mut buffer = Buffer::new();
+    buffer.insert_str("fn main() {");
+    buffer.insert_newline();
+    buffer.insert_str("    call(a, b);");
+    buffer.insert_newline();
+    buffer.insert_str("}");
+    
+    let range = TextRange::new(Position::new(1, 9), Position::new(1, 12));
+    assert_eq!(buffer.text_in_range(&range), "a, ");
+    
+    let version = buffer.version();
+    assert_eq!(buffer.delete_range(&range), "a, ");
+    assert_eq!(buffer.get_line(1), Some("    call(b);"));
+    assert_eq!(buffer.cursor_position(), (1, 9));
+    assert!(buffer.version() > version);
+    
+    let range = TextRange::new(Position::new(2, 0), Position::new(0, 11));
+    assert_eq!(buffer.delete_range(&range), "\n    call(b);\n");
+    assert_eq!(buffer.lines(), &["fn main() {}".to_string()]);
+}
+
+#[test]
+fn test_read_only_buffer_refuses_edits() {
+    use rusk::core::buffer::Buffer;
+    use rusk::core::cursor::{Position, TextRange};
+    
+    let mut buffer = Buffer::new();
+    buffer.insert_str("keep");
+    buffer.set_read_only(true);
+    let version = buffer.version();
+    
+    buffer.insert_char('x');
+    buffer.insert_newline();
+    buffer.backspace();
+    assert_eq!(buffer.delete_range(&TextRange::new(Position::new(0, 0), Position::new(0, 2))), "");
+    buffer.insert_text(Position::new(0, 0), "new\n");
+    assert!(!buffer.undo());
+    assert_eq!(buffer.lines(), ["keep"]);
+    assert_eq!(buffer.version(), version);
+    assert!(buffer.take_refused_edit());
+    assert!(!buffer.take_refused_edit());
+    
+    buffer.set_read_only(false);
+    buffer.insert_char('s');
+    assert_eq!(buffer.lines(), ["keeps"]);
+    assert!(!buffer.take_refused_edit());
+}
+
+#[test]
+fn test_completion_engine_sources() -> Result<()> {
+    use rusk::core::buffer::Buffer;
+    use rusk::plugins::implementations::completion::{CompletionEngine, CompletionKind, CompletionSettings};
+    use rusk::plugins::implementations::editing::emacs::EmacsPlugin;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    
+    let dir = tempfile::tempdir()?;
+    std::fs::create_dir(dir.path().join("src"))?;
+    std::fs::write(dir.path().join("src").join("main.rs"), "")?;
+    
+    let mut other = Buffer::from_file(dir.path().join("notes.txt"))?;
+    other.insert_str("remember the frobnicator");
+    
+    let mut buffer = Buffer::from_file(dir.path().join("main.txt"))?;
+    buffer.insert_str("let buffer_count = 1; buf");
+    
+    let mut engine = CompletionEngine::with_default_sources(CompletionSettings::default());
+    engine.index_buffers(&[other]);
+    
+    // Fuzzy-ranked words from the current buffer
+    assert!(engine.trigger(&buffer, None, None));
+    assert_eq!(engine.menu().unwrap().selected_item().unwrap().label, "buffer_count");
+    assert!(engine.accept(&mut buffer));
+    assert_eq!(buffer.get_line(0), Some("let buffer_count = 1; buffer_count"));
+    
+    // Words from other open buffers
+    buffer.insert_str(" frob");
+    assert!(engine.trigger(&buffer, None, None));
+    let item = engine.menu().unwrap().selected_item().unwrap().clone();
+    assert_eq!(item.label, "frobnicator");
+    assert_eq!(item.detail.as_deref(), Some("notes.txt"));
+    
+    // Closed buffers stop offering their words
+    engine.index_buffers(&[]);
+    assert!(!engine.trigger(&buffer, None, None));
+    
+    // Typing narrows the open popup until nothing matches
+    buffer.insert_str("x");
+    engine.refresh(&buffer);
+    assert!(!engine.is_active());
+    
+    // Paths relative to the buffer's file
+    buffer.insert_newline();
+    buffer.insert_str("\"./src/m");
+    assert!(engine.trigger(&buffer, None, None));
+    let item = engine.menu().unwrap().selected_item().unwrap().clone();
+    assert_eq!((item.label.as_str(), item.kind), ("main.rs", CompletionKind::Path));
+    engine.accept(&mut buffer);
+    assert_eq!(buffer.get_line(1), Some("\"./src/main.rs"));
+    
+    // Emacs keeps C-n, C-p and C-SPC for its own key map
+    let emacs = EmacsPlugin::new();
+    for ch in ['n', 'p', ' '] {
+        assert!(!engine.handle_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL), &mut buffer, &emacs, None));
+    }
+    assert!(!engine.is_active());
+    
+    Ok(())
+}
+
+#[test]
+fn test_snippet_expansion() -> Result<()> {
+    use rusk::core::buffer::Buffer;
+    use rusk::core::cursor::{Position, TextRange};
+    use rusk::plugins::implementations::language::syntax::SupportedLanguage;
+    use rusk::plugins::implementations::snippets::{SnippetLibrary, SnippetManager};
+    use rusk::plugins::implementations::completion::{CompletionEngine, CompletionSettings};
+    use rusk::plugins::implementations::editing::emacs::EmacsPlugin;
+    use rusk::plugins::implementations::editing::mode::{EditingMode, GrammarContext, VimEditing};
+    use rusk::services::operators::MotionEnv;
+    use rusk::services::registers::Registers;
+    use rusk::services::clipboard::{Clipboard, ClipboardProvider};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("rust.toml"), r#"
+[function]
+prefix = "fn"
+body = ["fn ${1:name}() -> ${2|i32,u8|} {", "\t$1$0", "}"]
+"#)?;
+    std::fs::write(dir.path().join("global.json"), r#"{
+    // VS Code files may have comments and trailing commas
+    "header": { "prefix": ["hdr"], "body": "// $TM_FILENAME ${1:title} ", },
+}"#)?;
+    
+    let mut snippets = SnippetManager::new();
+    snippets.set_library(SnippetLibrary::load_dir(dir.path())?);
+    let clipboard = Clipboard::new(ClipboardProvider::Command {
+        copy: vec!["true".to_string()],
+        paste: Some(vec!["printf".to_string(), "pasted".to_string()]),
+    });
+    
+    // The typed prefix expands with its placeholder selected, except on an emacs Tab
+    let mut buffer = Buffer::from_file(dir.path().join("main.rs"))?;
+    buffer.insert_str("fn");
+    let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+    assert!(!snippets.handle_key(tab, &mut buffer, &mut EmacsPlugin::new(), &mut CompletionEngine::new(CompletionSettings::default()), &clipboard));
+    assert!(snippets.expand_prefix(&mut buffer, &mut EmacsPlugin::new(), &clipboard));
+    assert_eq!(buffer.lines(), ["fn name() -> i32 {", "    name", "}"]);
+    let placeholder = buffer.selection_range().unwrap();
+    assert_eq!(placeholder, TextRange::new(Position::new(0, 3), Position::new(0, 7)));
+    
+    // Typing over the placeholder updates its mirror
+    buffer.delete_range(&placeholder);
+    buffer.insert_str("add");
+    snippets.after_key(&mut buffer);
+    assert_eq!(buffer.lines(), ["fn add() -> i32 {", "    add", "}"]);
+    assert_eq!(buffer.cursor_position(), (0, 6));
+    
+    // Tab visits the choice, then the final stop ends the session
+    snippets.next_stop(&mut buffer);
+    assert_eq!(snippets.session().unwrap().current_stop().choices, ["i32", "u8"]);
+    snippets.next_stop(&mut buffer);
+    assert!(!snippets.is_active());
+    assert_eq!(buffer.cursor_position(), (1, 7));
+    
+    // A global snippet expanded at two cursors, filled in at once
+    let body = snippets.library().find_by_prefix(&SupportedLanguage::Text, "hdr").unwrap().body.clone();
+    let mut buffer = Buffer::from_file(dir.path().join("notes.txt"))?;
+    buffer.insert_text(Position::new(0, 0), "one\ntwo");
+    buffer.checkpoint();
+    let cursors = [Position::new(1, 0), Position::new(0, 0)].map(|pos| TextRange::new(pos, pos));
+    assert!(snippets.expand(&mut buffer, &body, &cursors, &clipboard));
+    assert_eq!(buffer.lines(), ["// notes.txt title one", "// notes.txt title two"]);
+    
+    // Every copy goes away with a single undo
+    assert!(buffer.undo());
+    assert_eq!(buffer.lines(), ["one", "two"]);
+    assert!(buffer.redo());
+    assert_eq!(buffer.lines(), ["// notes.txt title one", "// notes.txt title two"]);
+    
+    let placeholder = buffer.selection_range().unwrap();
+    buffer.delete_range(&placeholder);
+    buffer.insert_str("todo");
+    snippets.after_key(&mut buffer);
+    assert_eq!(buffer.lines(), ["// notes.txt todo one", "// notes.txt todo two"]);
+    
+    // A prefix typed in an insert on a block expands on each of its rows
+    let mut buffer = Buffer::from_file(dir.path().join("notes.txt"))?;
+    buffer.insert_text(Position::new(0, 0), "one\ntwo\n");
+    buffer.set_cursor_position(0, 0);
+    let mut vim = VimEditing::new();
+    let mut registers = Registers::new();
+    let env = MotionEnv { top: 0, height: 20, tab_size: 4, insert_spaces: true };
+    vim.toggle_block(&mut buffer)?;
+    for ch in ['j', 'I'] {
+        let context = GrammarContext { registers: &mut registers, env, keys_pending: false, bound: false, recording: false, text_objects: None };
+        vim.handle_grammar_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE), &mut buffer, context)?;
+    }
+    assert_eq!(vim.block_insert_cursors(&buffer), [Position::new(0, 0), Position::new(1, 0)]);
+    buffer.insert_str("hdr");
+    assert!(snippets.expand_prefix(&mut buffer, &mut vim, &clipboard));
+    assert_eq!(buffer.lines(), ["// notes.txt title one", "// notes.txt title two", ""]);
+    assert!(vim.block_insert_cursors(&buffer).is_empty());
+    
+    // `$CLIPBOARD` comes from the configured clipboard
+    let mut buffer = Buffer::new();
+    let cursor = TextRange::new(Position::new(0, 0), Position::new(0, 0));
+    snippets.expand(&mut buffer, "let x = $CLIPBOARD;", &[cursor], &clipboard);
+    assert_eq!(buffer.lines(), ["let x = pasted;"]);
+    
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! Language server client tests against the scripted server in `tests/support`
+//!
+//! Run with `cargo test --features fake-lsp`, which builds the server.
+
+use rusk::Result;
+
+#[test]
+fn test_lsp_client_with_fake_server() -> Result<()> {
+    use rusk::core::buffer::Buffer;
+    use rusk::core::cursor::Position;
+    use rusk::plugins::implementations::completion::{CompletionEngine, CompletionSettings, LspSource};
+    use rusk::plugins::implementations::language::lsp::manager::apply_text_edits;
+    use rusk::plugins::implementations::language::lsp::{LspManager, LspServerConfig, LspSeverity};
+    use rusk::plugins::implementations::language::syntax::SupportedLanguage;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("main.rs");
+    std::fs::write(&path, "fn answer() {}\nfn main() { answer(); }   ")?;
+    
+    let runtime = tokio::runtime::Runtime::new()?;
+    let servers = HashMap::from([
+        (SupportedLanguage::Rust, LspServerConfig::new(env!("CARGO_BIN_EXE_rusk-fake-lsp"), Vec::new())),
+    ]);
+    let mut manager = LspManager::new(runtime.handle().clone(), servers);
+    manager.set_root(dir.path());
+    
+    let mut buffer = Buffer::from_file(&path)?;
+    let file = buffer.file_path().unwrap().to_string();
+    manager.open_buffer(&buffer)?;
+    assert!(manager.wait_for_diagnostics(Duration::from_secs(5)));
+    assert!(manager.diagnostics(&file).is_empty());
+    
+    // Incremental sync: the server only sees the typed text and reports on it
+    buffer.set_cursor_position(0, 13);
+    buffer.insert_str(" TODO");
+    manager.sync_buffer(&buffer)?;
+    assert!(manager.wait_for_diagnostics(Duration::from_secs(5)));
+    let diagnostics = manager.diagnostics(&file);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].range.start.line, 0);
+    assert_eq!(diagnostics[0].range.start.character, 14);
+    assert_eq!(diagnostics[0].severity(), LspSeverity::Warning);
+    
+    // Buffer diagnostics follow later edits until the server reports again
+    let diagnostics = manager.buffer_diagnostics(&buffer);
+    buffer.diagnostics_mut().set("lsp", diagnostics);
+    buffer.set_cursor_position(0, 0);
+    buffer.insert_str("  ");
+    let diagnostic = buffer.diagnostics().next_after(Position::new(0, 0)).unwrap();
+    assert_eq!(diagnostic.range.start, Position::new(0, 16));
+    buffer.set_cursor_position(0, 0);
+    buffer.delete_char();
+    buffer.delete_char();
+    
+    buffer.set_cursor_position(1, 13);
+    // Requests return straight away and are answered by polling
+    let mut hover = manager.hover(&buffer)?;
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    let text = loop {
+        if let Some(result) = hover.poll() {
+            break result?;
+        }
+        assert!(std::time::Instant::now() < deadline, "hover was never answered");
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(text.as_deref(), Some("symbol answer"));
+    
+    let definition = manager.definition(&buffer)?.wait()?;
+    assert_eq!(definition.len(), 1);
+    assert_eq!(definition[0].range.start.line, 0);
+    assert_eq!(definition[0].range.start.character, 3);
+    assert_eq!(manager.references(&buffer)?.wait()?.len(), 2);
+    
+    let edit = manager.rename(&buffer, "reply")?.wait()?;
+    manager.apply_workspace_edit(std::slice::from_mut(&mut buffer), &edit)?;
+    assert_eq!(buffer.get_line(0), Some("fn reply() { TODO}"));
+    assert_eq!(buffer.get_line(1), Some("fn main() { reply(); }   "));
+    
+    let edits = manager.formatting(&buffer, 4, true)?.wait()?;
+    apply_text_edits(&mut buffer, &edits);
+    assert_eq!(buffer.get_line(1), Some("fn main() { reply(); }"));
+    
+    assert_eq!(manager.code_actions(&buffer)?.wait()?.len(), 2);
+    
+    let completions = manager.completion(&buffer, None)?.wait()?;
+    assert_eq!(completions.len(), 2);
+    assert_eq!(completions[0].documentation_text().as_deref(), Some("Returns the answer"));
+    assert!(completions[1].is_snippet());
+    
+    // The completion engine sends its request and takes the answer once it arrives
+    let mut engine = CompletionEngine::new(CompletionSettings::default());
+    engine.add_source(Box::new(LspSource::new()));
+    buffer.set_cursor_position(1, 12);
+    buffer.insert_str("an");
+    manager.sync_buffer(&buffer)?;
+    assert!(!engine.trigger(&buffer, Some(&mut manager), None));
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while !engine.poll(&buffer) {
+        assert!(std::time::Instant::now() < deadline, "completion was never answered");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(engine.menu().unwrap().selected_item().unwrap().label, "answer");
+    
+    manager.shutdown_all();
+    Ok(())
+}
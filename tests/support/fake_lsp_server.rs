@@ -0,0 +1,218 @@
+//! Minimal language server used by the LSP integration tests
+//!
+//! Keeps documents in memory and answers with predictable results:
+//! lines containing `TODO` get a warning diagnostic, hover reports the
+//! word under the cursor, definition and references point at every
+//! occurrence of that word, rename replaces them, completion offers a
+//! function and a snippet, and formatting strips trailing whitespace.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok()?;
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn send(message: Value) {
+    let body = message.to_string();
+    let mut stdout = io::stdout().lock();
+    let _ = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = stdout.flush();
+}
+
+/// Apply an incremental change; the tests only use ASCII so characters are bytes
+fn apply_change(text: &mut String, change: &Value) {
+    let Some(range) = change.get("range") else {
+        *text = change["text"].as_str().unwrap_or_default().to_string();
+        return;
+    };
+
+    let offset = |pos: &Value| {
+        let line = pos["line"].as_u64().unwrap_or(0) as usize;
+        let character = pos["character"].as_u64().unwrap_or(0) as usize;
+        text.split('\n').take(line).map(|l| l.len() + 1).sum::<usize>() + character
+    };
+    let (start, end) = (offset(&range["start"]), offset(&range["end"]));
+    text.replace_range(start..end, change["text"].as_str().unwrap_or_default());
+}
+
+fn publish_diagnostics(uri: &str, text: &str) {
+    let diagnostics: Vec<Value> = text.split('\n')
+        .enumerate()
+        .filter_map(|(line, content)| {
+            let column = content.find("TODO")?;
+            Some(json!({
+                "range": {
+                    "start": { "line": line, "character": column },
+                    "end": { "line": line, "character": column + 4 },
+                },
+                "severity": 2,
+                "source": "fake",
+                "message": "unfinished work",
+            }))
+        })
+        .collect();
+
+    send(json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    }));
+}
+
+fn word_at(text: &str, pos: &Value) -> Option<String> {
+    let line = text.split('\n').nth(pos["line"].as_u64()? as usize)?;
+    let column = (pos["character"].as_u64()? as usize).min(line.len());
+    let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+
+    let start = line[..column].rfind(|ch: char| !is_word(ch)).map_or(0, |i| i + 1);
+    let end = line[start..].find(|ch: char| !is_word(ch)).map_or(line.len(), |i| start + i);
+    (start < end).then(|| line[start..end].to_string())
+}
+
+fn occurrences(uri: &str, text: &str, word: &str) -> Vec<Value> {
+    let mut locations = Vec::new();
+    for (line, content) in text.split('\n').enumerate() {
+        for (column, _) in content.match_indices(word) {
+            locations.push(json!({
+                "uri": uri,
+                "range": {
+                    "start": { "line": line, "character": column },
+                    "end": { "line": line, "character": column + word.len() },
+                },
+            }));
+        }
+    }
+    locations
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let mut documents: HashMap<String, String> = HashMap::new();
+
+    while let Some(message) = read_message(&mut reader) {
+        let method = message["method"].as_str().unwrap_or_default().to_string();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+
+        let result = match method.as_str() {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 2,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": ["."] },
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "renameProvider": true,
+                    "codeActionProvider": true,
+                    "documentFormattingProvider": true,
+                }
+            }),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default().to_string();
+                publish_diagnostics(&uri, &text);
+                documents.insert(uri, text);
+                continue;
+            }
+            "textDocument/didChange" => {
+                let text = documents.entry(uri.clone()).or_default();
+                for change in params["contentChanges"].as_array().into_iter().flatten() {
+                    apply_change(text, change);
+                }
+                publish_diagnostics(&uri, text);
+                continue;
+            }
+            "textDocument/hover" => {
+                let text = documents.get(&uri).cloned().unwrap_or_default();
+                match word_at(&text, &params["position"]) {
+                    Some(word) => json!({ "contents": { "kind": "plaintext", "value": format!("symbol {}", word) } }),
+                    None => Value::Null,
+                }
+            }
+            "textDocument/definition" | "textDocument/references" => {
+                let text = documents.get(&uri).cloned().unwrap_or_default();
+                let locations = word_at(&text, &params["position"])
+                    .map(|word| occurrences(&uri, &text, &word))
+                    .unwrap_or_default();
+                if method == "textDocument/definition" {
+                    locations.into_iter().next().unwrap_or(Value::Null)
+                } else {
+                    Value::Array(locations)
+                }
+            }
+            "textDocument/rename" => {
+                let text = documents.get(&uri).cloned().unwrap_or_default();
+                let new_name = params["newName"].as_str().unwrap_or_default();
+                let edits: Vec<Value> = word_at(&text, &params["position"])
+                    .map(|word| occurrences(&uri, &text, &word))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|location| json!({ "range": location["range"], "newText": new_name }))
+                    .collect();
+                json!({ "changes": { uri: edits } })
+            }
+            "textDocument/completion" => json!({
+                "isIncomplete": false,
+                "items": [
+                    {
+                        "label": "answer",
+                        "kind": 3,
+                        "detail": "fn answer()",
+                        "documentation": { "kind": "plaintext", "value": "Returns the answer" },
+                    },
+                    {
+                        "label": "println!",
+                        "kind": 15,
+                        "insertTextFormat": 2,
+                        "insertText": "println!(\"${1:text}\")$0",
+                    },
+                ],
+            }),
+            "textDocument/codeAction" => json!([
+                { "title": "Remove TODO", "kind": "quickfix" },
+                { "title": "Run fake command", "command": "fake.run" },
+            ]),
+            "textDocument/formatting" => {
+                let text = documents.get(&uri).cloned().unwrap_or_default();
+                let edits: Vec<Value> = text.split('\n')
+                    .enumerate()
+                    .filter(|(_, line)| line.ends_with(' '))
+                    .map(|(line, content)| json!({
+                        "range": {
+                            "start": { "line": line, "character": content.trim_end().len() },
+                            "end": { "line": line, "character": content.len() },
+                        },
+                        "newText": "",
+                    }))
+                    .collect();
+                Value::Array(edits)
+            }
+            "exit" => break,
+            _ => Value::Null,
+        };
+
+        // Only requests carry an id and get a response
+        if let Some(id) = message.get("id") {
+            send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+        }
+    }
+}